@@ -0,0 +1,322 @@
+//! The state behind the exports, the drive of the fixture and the handles issued for it
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Mutex, MutexGuard, OnceLock, PoisonError},
+};
+
+use proton_sdk_sys::{
+    data::ByteArray,
+    prost::Message,
+    protobufs::{
+        account::{Error, ErrorDomain},
+        drive::{
+            node_type, FileNode, FolderNode, LinkId, NodeIdentity, NodeState, NodeType, NodeTypeList, Revision,
+            RevisionId, RevisionState, Share, ShareId, VolumeId, VolumeMetadata, VolumeState, VolumesResponse,
+        },
+    },
+};
+
+use crate::fixture::{Fixture, Node};
+
+/// What a handle was issued for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Object {
+    Session { username: String },
+    Client { session: isize },
+    Downloader { client: isize },
+    Uploader { client: isize },
+    Token,
+    Logger,
+    Observability,
+}
+
+#[derive(Debug)]
+pub struct Drive {
+    pub fixture: Fixture,
+    objects: HashMap<isize, Object>,
+    next_handle: isize,
+    cancelled: HashSet<isize>,
+    /// The revision number of files whose content was replaced
+    revisions: HashMap<String, u32>,
+    uploads: u32,
+    /// Bytes handed out as a `ByteArray`, kept alive until the caller frees them with `byte_array_free`
+    returned: Vec<Box<[u8]>>,
+}
+
+static DRIVE: OnceLock<Mutex<Drive>> = OnceLock::new();
+
+/// The drive of the process, loaded from the fixture on first use
+///
+/// A fixture that can't be loaded panics, which aborts the process calling into the mock.
+pub fn drive() -> MutexGuard<'static, Drive> {
+    DRIVE
+        .get_or_init(|| Mutex::new(Drive::new(Fixture::load().unwrap_or_else(|e| panic!("{}", e)))))
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+/// An `Error` as the SDK reports it to the failure callbacks
+pub fn error(domain: ErrorDomain, primary_code: Option<i64>, message: &str) -> Error {
+    Error {
+        r#type: "MockProtonSdkException".to_string(),
+        message: message.to_string(),
+        domain: domain as i32,
+        primary_code,
+        ..Default::default()
+    }
+}
+
+fn not_found() -> Error {
+    error(ErrorDomain::Api, Some(2501), "File or folder not found")
+}
+
+impl Drive {
+    pub fn new(fixture: Fixture) -> Self {
+        Self {
+            fixture,
+            objects: HashMap::new(),
+            next_handle: 1,
+            cancelled: HashSet::new(),
+            revisions: HashMap::new(),
+            uploads: 0,
+            returned: Vec::new(),
+        }
+    }
+
+    pub fn issue(&mut self, object: Object) -> isize {
+        let handle = self.next_handle;
+        self.next_handle += 1;
+        self.objects.insert(handle, object);
+        handle
+    }
+
+    pub fn object(&self, handle: isize) -> Option<&Object> {
+        self.objects.get(&handle)
+    }
+
+    pub fn free(&mut self, handle: isize) {
+        self.cancelled.remove(&handle);
+        self.objects.remove(&handle);
+    }
+
+    pub fn cancel(&mut self, token: isize) {
+        if self.object(token) == Some(&Object::Token) {
+            self.cancelled.insert(token);
+        }
+    }
+
+    pub fn is_cancelled(&self, token: isize) -> bool {
+        self.cancelled.contains(&token)
+    }
+
+    /// The user of a session handle
+    pub fn username(&self, session: isize) -> Option<&str> {
+        match self.object(session)? {
+            Object::Session { username } => Some(username),
+            _ => None,
+        }
+    }
+
+    pub fn is_client(&self, handle: isize) -> bool {
+        matches!(self.object(handle), Some(Object::Client { .. }))
+    }
+
+    /// Keeps `bytes` alive for the caller of an export returning them
+    pub fn hand_out(&mut self, bytes: Vec<u8>) -> ByteArray {
+        let bytes = bytes.into_boxed_slice();
+        let array = ByteArray::from_slice(&bytes);
+        self.returned.push(bytes);
+        array
+    }
+
+    /// Drops the bytes handed out at `pointer`, false when none were
+    pub fn release(&mut self, pointer: *const u8) -> bool {
+        let count = self.returned.len();
+        self.returned.retain(|bytes| bytes.as_ptr() != pointer);
+        self.returned.len() != count
+    }
+
+    pub fn volumes(&self) -> VolumesResponse {
+        let volumes = self.fixture.volumes.iter().map(|volume| VolumeMetadata {
+            volume_id: Some(VolumeId { value: volume.id.clone() }),
+            state: VolumeState::Active as i32,
+            max_space: volume.max_space,
+            root_share_id: Some(ShareId { value: volume.root_share_id.clone() }),
+        });
+        VolumesResponse { volumes: volumes.collect() }
+    }
+
+    /// The shares of a volume, length delimited like the SDK sends several
+    pub fn shares(&self, volume_id: &str) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for share in self.fixture.shares.iter().filter(|share| share.volume_id == volume_id) {
+            let share = Share {
+                share_id: Some(ShareId { value: share.id.clone() }),
+                membership_address_id: None,
+                membership_email_address: share.email.clone(),
+                volume_id: Some(VolumeId { value: share.volume_id.clone() }),
+                root_node_id: Some(LinkId { value: share.root_node_id.clone() }),
+            };
+            share.encode_length_delimited(&mut bytes).expect("a Vec grows as needed");
+        }
+        bytes
+    }
+
+    /// The children of a folder, `None` when there's no such folder
+    pub fn children(&self, folder_id: &str) -> Option<NodeTypeList> {
+        self.fixture.node(folder_id).filter(|folder| folder.content.is_none())?;
+        let nodes = self.fixture.children(folder_id).map(|node| self.node_type(node));
+        Some(NodeTypeList { nodes: nodes.collect() })
+    }
+
+    pub fn file(&self, id: &str) -> Result<(&Node, &[u8]), Error> {
+        let node = self.fixture.node(id).ok_or_else(not_found)?;
+        let content = node.content.as_deref().ok_or_else(not_found)?;
+        Ok((node, content))
+    }
+
+    /// Stores a file in a folder, as a new revision when one has its name
+    pub fn upload(&mut self, parent_id: &str, name: &str, content: Vec<u8>) -> Result<FileNode, Error> {
+        self.fixture.node(parent_id).filter(|parent| parent.content.is_none()).ok_or_else(not_found)?;
+        let existing = self.fixture.children(parent_id).find(|node| node.name == name);
+        let id = match existing {
+            Some(node) if node.content.is_none() => {
+                return Err(error(ErrorDomain::Api, Some(2500), "A folder with that name already exists"));
+            }
+            Some(node) => {
+                let id = node.id.clone();
+                self.replace(&id, content)?;
+                id
+            }
+            None => {
+                self.uploads += 1;
+                let id = format!("upload-{}", self.uploads);
+                self.fixture.nodes.push(Node {
+                    id: id.clone(),
+                    parent: Some(parent_id.to_string()),
+                    name: name.to_string(),
+                    content: Some(content),
+                    failure: None,
+                });
+                id
+            }
+        };
+
+        match self.node_type(self.fixture.node(&id).expect("just stored")).node_type {
+            Some(node_type::NodeType::FileNode(file)) => Ok(file),
+            _ => unreachable!("uploads are files"),
+        }
+    }
+
+    /// Replaces the content of a file, returning its new revision
+    pub fn replace(&mut self, id: &str, content: Vec<u8>) -> Result<Revision, Error> {
+        let node = self.fixture.nodes.iter_mut().find(|node| node.id == id).ok_or_else(not_found)?;
+        node.content.as_ref().ok_or_else(not_found)?;
+        node.content = Some(content);
+        *self.revisions.entry(id.to_string()).or_insert(1) += 1;
+
+        let (node, content) = self.file(id)?;
+        Ok(self.revision(node, content))
+    }
+
+    fn identity(&self, id: &str) -> NodeIdentity {
+        let share = self.fixture.share_of(id);
+        NodeIdentity {
+            node_id: Some(LinkId { value: id.to_string() }),
+            share_id: share.map(|share| ShareId { value: share.id.clone() }),
+            volume_id: share.map(|share| VolumeId { value: share.volume_id.clone() }),
+        }
+    }
+
+    fn revision(&self, node: &Node, content: &[u8]) -> Revision {
+        let number = self.revisions.get(&node.id).copied().unwrap_or(1);
+        Revision {
+            revision_id: Some(RevisionId { value: format!("{}-revision-{}", node.id, number) }),
+            state: RevisionState::Active as i32,
+            volume_id: self.identity(&node.id).volume_id,
+            file_id: Some(LinkId { value: node.id.clone() }),
+            size: Some(content.len() as i64),
+            quota_consumption: content.len() as i64,
+            ..Default::default()
+        }
+    }
+
+    fn node_type(&self, node: &Node) -> NodeType {
+        let node_identity = Some(self.identity(&node.id));
+        let parent_id = node.parent.clone().map(|value| LinkId { value });
+        let node_type = match &node.content {
+            None => node_type::NodeType::FolderNode(FolderNode {
+                node_identity,
+                parent_id,
+                name: node.name.clone(),
+                state: NodeState::Active as i32,
+                ..Default::default()
+            }),
+            Some(content) => node_type::NodeType::FileNode(FileNode {
+                node_identity,
+                parent_id,
+                name: node.name.clone(),
+                state: NodeState::Active as i32,
+                active_revision: Some(self.revision(node, content)),
+                ..Default::default()
+            }),
+        };
+        NodeType { node_type: Some(node_type) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn drive() -> Drive {
+        let fixture = Fixture::parse(
+            r#"{
+                "accounts": [],
+                "volumes": [{ "id": "volume", "root_share_id": "share" }],
+                "shares": [{ "id": "share", "volume_id": "volume", "root_node_id": "root" }],
+                "nodes": [
+                    { "id": "root", "name": "root" },
+                    { "id": "notes", "parent": "root", "name": "notes.txt", "content": "v1" }
+                ]
+            }"#,
+        )
+        .unwrap();
+        Drive::new(fixture)
+    }
+
+    #[test]
+    fn uploads_add_files_or_revisions() {
+        let mut drive = drive();
+        let added = drive.upload("root", "todo.txt", b"milk".to_vec()).unwrap();
+        let replaced = drive.upload("root", "notes.txt", b"v2".to_vec()).unwrap();
+
+        assert_eq!(added.node_identity.unwrap().share_id.unwrap().value, "share");
+        assert_eq!(replaced.active_revision.unwrap().revision_id.unwrap().value, "notes-revision-2");
+        assert_eq!(drive.file("notes").unwrap().1, b"v2");
+        assert_eq!(drive.children("root").unwrap().nodes.len(), 2);
+        assert_eq!(drive.upload("notes", "x", Vec::new()).unwrap_err().primary_code, Some(2501));
+    }
+
+    #[test]
+    fn released_bytes_are_forgotten() {
+        let mut drive = drive();
+        let array = drive.hand_out(b"volumes".to_vec());
+        assert!(drive.release(array.pointer));
+        assert!(!drive.release(array.pointer));
+    }
+
+    #[test]
+    fn freed_handles_are_forgotten() {
+        let mut drive = drive();
+        let session = drive.issue(Object::Session { username: "user".to_string() });
+        let token = drive.issue(Object::Token);
+        drive.cancel(token);
+        assert_eq!((drive.username(session), drive.is_cancelled(token)), (Some("user"), true));
+
+        drive.free(session);
+        drive.free(token);
+        assert_eq!((drive.username(session), drive.is_cancelled(token)), (None, false));
+    }
+}
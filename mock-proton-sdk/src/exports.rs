@@ -0,0 +1,504 @@
+//! The C exports of the native SDK, answered from the [`Drive`](crate::drive::Drive)
+//!
+//! Calls on a handle the mock didn't issue, or already freed, fail with [`FAILED`],
+//! an empty `ByteArray` or the failure callback. The optional node exports
+//! (`node_rename`, ...) are left out, like in an older SDK.
+
+use std::{ffi::c_char, fs, thread};
+
+use proton_sdk_sys::{
+    data::{AsyncCallback, AsyncCallbackWithProgress, BooleanCallback, ByteArray, Callback, TwoFactorRequestedCallback},
+    prost::Message,
+    protobufs::{
+        account::{
+            Error, ErrorDomain, IntResponse, SessionBeginRequest, SessionId, SessionInfo, SessionResumeRequest,
+            StringResponse,
+        },
+        drive::{
+            FileDownloadRequest, FileUploadRequest, NodeIdentity, NodeNameDecryptionRequest, ProgressUpdate,
+            RevisionUploadRequest, VolumeMetadata,
+        },
+    },
+};
+
+use crate::{
+    drive::{drive, error, Object},
+    fixture::Failure,
+    GARBAGE_HANDLE,
+};
+
+/// Returned by the calls the mock rejects
+const FAILED: i32 = 1;
+
+/// Callbacks moved to the thread calling them
+struct Pending {
+    callback: AsyncCallback,
+    progress: Callback,
+}
+
+// the caller keeps the callback state alive until a callback ran, from whatever thread
+unsafe impl Send for Pending {}
+
+impl Pending {
+    fn run(self, updates: &[ProgressUpdate], outcome: Result<Vec<u8>, Error>, failure: Option<Failure>) {
+        let Pending { callback, progress } = self;
+        let outcome = if drive().is_cancelled(callback.cancellation_token_source_handle) {
+            Err(error(ErrorDomain::SuccessfulCancellation, None, "The operation was cancelled"))
+        } else {
+            outcome
+        };
+
+        if let (Ok(_), Some(on_progress)) = (&outcome, progress.callback) {
+            for update in updates {
+                on_progress(progress.state, ByteArray::from_slice(&update.encode_to_vec()));
+            }
+        }
+        let succeeded = outcome.is_ok();
+        deliver(&callback, outcome);
+        if failure == Some(Failure::CallsBothCallbacks) {
+            let other = if succeeded {
+                Err(error(ErrorDomain::Undefined, None, "Called back a second time"))
+            } else {
+                Ok(Vec::new())
+            };
+            deliver(&callback, other);
+        }
+    }
+}
+
+fn deliver(callback: &AsyncCallback, outcome: Result<Vec<u8>, Error>) {
+    let (on_done, bytes) = match outcome {
+        Ok(bytes) => (callback.on_success, bytes),
+        Err(error) => (callback.on_failure, error.encode_to_vec()),
+    };
+    if let Some(on_done) = on_done {
+        on_done(callback.state, ByteArray::from_slice(&bytes));
+    }
+}
+
+/// Answers an async call from a new thread, the way the SDK calls back from its own
+fn call_back(
+    callback: AsyncCallback,
+    progress: Callback,
+    updates: Vec<ProgressUpdate>,
+    outcome: Result<Vec<u8>, Error>,
+    failure: Option<Failure>,
+) -> i32 {
+    if failure == Some(Failure::NeverCallsBack) {
+        return 0;
+    }
+    let pending = Pending { callback, progress };
+    thread::spawn(move || pending.run(&updates, outcome, failure));
+    0
+}
+
+fn respond(callback: AsyncCallback, outcome: Result<Vec<u8>, Error>) -> i32 {
+    call_back(callback, Callback::empty(), Vec::new(), outcome, None)
+}
+
+fn decode<M: Message + Default>(request: ByteArray) -> Result<M, Error> {
+    M::decode(&*request.view())
+        .map_err(|e| error(ErrorDomain::Serialization, None, &format!("Malformed request: {}", e)))
+}
+
+fn unknown_handle() -> Error {
+    error(ErrorDomain::Undefined, None, "Unknown handle")
+}
+
+/// Updates halfway and at the end of a transfer of `length` bytes
+fn transfer(length: usize) -> Vec<ProgressUpdate> {
+    let bytes_in_total = length as i64;
+    vec![
+        ProgressUpdate { bytes_completed: bytes_in_total / 2, bytes_in_total },
+        ProgressUpdate { bytes_completed: bytes_in_total, bytes_in_total },
+    ]
+}
+
+fn read_source(path: &str) -> Result<Vec<u8>, Error> {
+    fs::read(path).map_err(|e| error(ErrorDomain::Undefined, None, &format!("Failed to read {}: {}", path, e)))
+}
+
+fn node_id(identity: Option<&NodeIdentity>) -> &str {
+    identity.and_then(|identity| identity.node_id.as_ref()).map_or("", |id| id.value.as_str())
+}
+
+/// Issues a handle through an out parameter, or fails when `object` is `None`
+unsafe fn issue(object: Option<Object>, handle: *mut isize) -> i32 {
+    match object {
+        Some(object) if !handle.is_null() => {
+            unsafe { *handle = drive().issue(object) };
+            0
+        }
+        _ => FAILED,
+    }
+}
+
+fn status(valid: bool) -> i32 {
+    if valid { 0 } else { FAILED }
+}
+
+// Sessions
+
+#[no_mangle]
+pub extern "C" fn session_begin(
+    _unused_handle: isize,
+    request: ByteArray,
+    _request_response_callback: Callback,
+    _secret_requested_callback: BooleanCallback,
+    _two_factor_requested_callback: TwoFactorRequestedCallback,
+    _tokens_refreshed_callback: Callback,
+    callback: AsyncCallback,
+) -> i32 {
+    let request: SessionBeginRequest = match decode(request) {
+        Ok(request) => request,
+        Err(e) => return respond(callback, Err(e)),
+    };
+    let mut drive = drive();
+    let account = drive
+        .fixture
+        .account(&request.username)
+        .filter(|account| account.password == request.password)
+        .cloned();
+    let Some(account) = account else {
+        drop(drive);
+        let incorrect = error(ErrorDomain::Api, Some(8002), "Incorrect login credentials. Please try again.");
+        return respond(callback, Err(incorrect));
+    };
+
+    let handle = match account.failure {
+        Some(Failure::GarbageHandles) => GARBAGE_HANDLE,
+        _ => drive.issue(Object::Session { username: account.username }),
+    };
+    drop(drive);
+    let response = IntResponse { value: handle as i64 }.encode_to_vec();
+    call_back(callback, Callback::empty(), Vec::new(), Ok(response), account.failure)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn session_resume(
+    request: ByteArray,
+    _request_response_callback: Callback,
+    _secret_requested_callback: BooleanCallback,
+    _tokens_refreshed_callback: Callback,
+    session_handle: *mut isize,
+) -> i32 {
+    let Ok(request) = decode::<SessionResumeRequest>(request) else {
+        return FAILED;
+    };
+    let known = drive().fixture.account(&request.username).is_some();
+    let session = known.then_some(Object::Session { username: request.username });
+    unsafe { issue(session, session_handle) }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn session_renew(
+    old_session_handle: isize,
+    _request: ByteArray,
+    _tokens_refreshed_callback: Callback,
+    new_session_handle: *mut isize,
+) -> i32 {
+    let username = drive().username(old_session_handle).map(str::to_string);
+    let session = username.map(|username| Object::Session { username });
+    unsafe { issue(session, new_session_handle) }
+}
+
+#[no_mangle]
+pub extern "C" fn session_end(session_handle: isize, callback: AsyncCallback) -> i32 {
+    let known = drive().username(session_handle).is_some();
+    respond(callback, if known { Ok(Vec::new()) } else { Err(unknown_handle()) })
+}
+
+#[no_mangle]
+pub extern "C" fn session_free(session_handle: isize) {
+    drive().free(session_handle);
+}
+
+#[no_mangle]
+pub extern "C" fn session_register_armored_locked_user_key(session_handle: isize, _armored_user_key: ByteArray) -> i32 {
+    status(drive().username(session_handle).is_some())
+}
+
+#[no_mangle]
+pub extern "C" fn session_register_address_keys(session_handle: isize, _request: ByteArray) -> i32 {
+    status(drive().username(session_handle).is_some())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn session_get_info(session_handle: isize, _cancellation_token: isize, info: *mut ByteArray) -> i32 {
+    let mut drive = drive();
+    let Some(username) = drive.username(session_handle).map(str::to_string) else {
+        return FAILED;
+    };
+    if info.is_null() {
+        return FAILED;
+    }
+    let session_info = SessionInfo {
+        session_id: Some(SessionId { value: format!("session-{}", session_handle) }),
+        username,
+        access_token: "access-token".to_string(),
+        refresh_token: "refresh-token".to_string(),
+        scopes: vec!["full".to_string(), "drive".to_string()],
+        ..Default::default()
+    };
+    unsafe { *info = drive.hand_out(session_info.encode_to_vec()) };
+    0
+}
+
+#[no_mangle]
+pub extern "C" fn session_apply_data_password(session_handle: isize, _password: ByteArray) -> i32 {
+    status(drive().username(session_handle).is_some())
+}
+
+// Cancellation tokens
+
+#[no_mangle]
+pub extern "C" fn cancellation_token_source_create() -> isize {
+    drive().issue(Object::Token)
+}
+
+#[no_mangle]
+pub extern "C" fn cancellation_token_source_cancel(handle: isize) {
+    drive().cancel(handle);
+}
+
+#[no_mangle]
+pub extern "C" fn cancellation_token_source_free(handle: isize) {
+    drive().free(handle);
+}
+
+// Logging and observability
+
+#[no_mangle]
+pub unsafe extern "C" fn logger_provider_create(_log_callback: Callback, logger_provider_handle: *mut isize) -> i32 {
+    unsafe { issue(Some(Object::Logger), logger_provider_handle) }
+}
+
+#[no_mangle]
+pub extern "C" fn byte_array_free(array: ByteArray) {
+    drive().release(array.pointer);
+}
+
+#[no_mangle]
+pub extern "C" fn sdk_get_version() -> *const c_char {
+    concat!(env!("CARGO_PKG_VERSION"), "-mock\0").as_ptr().cast()
+}
+
+#[no_mangle]
+pub extern "C" fn logger_provider_free(logger_provider_handle: isize) {
+    drive().free(logger_provider_handle);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn observability_service_start_new(session_handle: isize, observability_handle: *mut isize) -> i32 {
+    let known = drive().username(session_handle).is_some();
+    unsafe { issue(known.then_some(Object::Observability), observability_handle) }
+}
+
+#[no_mangle]
+pub extern "C" fn observability_service_flush(observability_handle: isize, callback: AsyncCallback) -> i32 {
+    let known = drive().object(observability_handle) == Some(&Object::Observability);
+    respond(callback, if known { Ok(Vec::new()) } else { Err(unknown_handle()) })
+}
+
+#[no_mangle]
+pub extern "C" fn observability_service_free(observability_handle: isize) {
+    drive().free(observability_handle);
+}
+
+// Drive client
+
+#[no_mangle]
+pub unsafe extern "C" fn drive_client_create(
+    session_handle: isize,
+    _observability_handle: isize,
+    _request: ByteArray,
+    client_handle: *mut isize,
+) -> i32 {
+    let known = drive().username(session_handle).is_some();
+    unsafe { issue(known.then_some(Object::Client { session: session_handle }), client_handle) }
+}
+
+#[no_mangle]
+pub extern "C" fn drive_client_register_node_keys(client_handle: isize, _request: ByteArray) -> i32 {
+    status(drive().is_client(client_handle))
+}
+
+#[no_mangle]
+pub extern "C" fn drive_client_register_share_key(client_handle: isize, _request: ByteArray) -> i32 {
+    status(drive().is_client(client_handle))
+}
+
+#[no_mangle]
+pub extern "C" fn drive_client_free(client_handle: isize) {
+    drive().free(client_handle);
+}
+
+#[no_mangle]
+pub extern "C" fn drive_client_get_volumes(client_handle: isize, _cancellation_token: isize) -> ByteArray {
+    let mut drive = drive();
+    if !drive.is_client(client_handle) {
+        return ByteArray::empty();
+    }
+    let volumes = drive.volumes().encode_to_vec();
+    drive.hand_out(volumes)
+}
+
+#[no_mangle]
+pub extern "C" fn drive_client_get_shares(
+    client_handle: isize,
+    volume_metadata: ByteArray,
+    _cancellation_token: isize,
+) -> ByteArray {
+    let mut drive = drive();
+    let Ok(volume) = decode::<VolumeMetadata>(volume_metadata) else {
+        return ByteArray::empty();
+    };
+    if !drive.is_client(client_handle) {
+        return ByteArray::empty();
+    }
+    let shares = drive.shares(volume.volume_id.as_ref().map_or("", |id| id.value.as_str()));
+    drive.hand_out(shares)
+}
+
+#[no_mangle]
+pub extern "C" fn drive_client_get_folder_children(
+    client_handle: isize,
+    node_identity: ByteArray,
+    _cancellation_token: isize,
+) -> ByteArray {
+    let mut drive = drive();
+    let Ok(identity) = decode::<NodeIdentity>(node_identity) else {
+        return ByteArray::empty();
+    };
+    match drive.children(node_id(Some(&identity))) {
+        Some(children) if drive.is_client(client_handle) => drive.hand_out(children.encode_to_vec()),
+        _ => ByteArray::empty(),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn node_decrypt_armored_name(client_handle: isize, request: ByteArray, callback: AsyncCallback) -> i32 {
+    let outcome = decode::<NodeNameDecryptionRequest>(request).and_then(|request| {
+        if !drive().is_client(client_handle) {
+            return Err(unknown_handle());
+        }
+        // the mock's names are stored in the clear
+        Ok(StringResponse { value: request.armored_encrypted_name }.encode_to_vec())
+    });
+    respond(callback, outcome)
+}
+
+// Downloads
+
+#[no_mangle]
+pub extern "C" fn downloader_create(client_handle: isize, _request: ByteArray, callback: AsyncCallback) -> i32 {
+    let mut drive = drive();
+    let outcome = if drive.is_client(client_handle) {
+        let handle = drive.issue(Object::Downloader { client: client_handle });
+        Ok(IntResponse { value: handle as i64 }.encode_to_vec())
+    } else {
+        Err(unknown_handle())
+    };
+    drop(drive);
+    respond(callback, outcome)
+}
+
+#[no_mangle]
+pub extern "C" fn downloader_download_file(
+    downloader_handle: isize,
+    request: ByteArray,
+    callback: AsyncCallbackWithProgress,
+) -> i32 {
+    let AsyncCallbackWithProgress { async_callback, progress_callback } = callback;
+    let drive = drive();
+    let download = decode::<FileDownloadRequest>(request).and_then(|request| {
+        if !matches!(drive.object(downloader_handle), Some(Object::Downloader { .. })) {
+            return Err(unknown_handle());
+        }
+        let (node, content) = drive.file(node_id(request.file_identity.as_ref()))?;
+        Ok((request.target_file_path, node.failure, content.to_vec()))
+    });
+    drop(drive);
+
+    let (target, failure, content) = match download {
+        Ok(download) => download,
+        Err(e) => return respond(async_callback, Err(e)),
+    };
+    let outcome = fs::write(&target, &content)
+        .map(|()| content.clone())
+        .map_err(|e| error(ErrorDomain::Undefined, None, &format!("Failed to write {}: {}", target, e)));
+    call_back(async_callback, progress_callback, transfer(content.len()), outcome, failure)
+}
+
+#[no_mangle]
+pub extern "C" fn downloader_free(downloader_handle: isize) {
+    drive().free(downloader_handle);
+}
+
+// Uploads
+
+#[no_mangle]
+pub extern "C" fn uploader_create(client_handle: isize, _request: ByteArray, callback: AsyncCallback) -> i32 {
+    let mut drive = drive();
+    let outcome = if drive.is_client(client_handle) {
+        let handle = drive.issue(Object::Uploader { client: client_handle });
+        Ok(IntResponse { value: handle as i64 }.encode_to_vec())
+    } else {
+        Err(unknown_handle())
+    };
+    drop(drive);
+    respond(callback, outcome)
+}
+
+fn is_uploader(handle: isize) -> Result<(), Error> {
+    match drive().object(handle) {
+        Some(Object::Uploader { .. }) => Ok(()),
+        _ => Err(unknown_handle()),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn uploader_upload_file_or_revision(
+    uploader_handle: isize,
+    request: ByteArray,
+    callback: AsyncCallbackWithProgress,
+) -> i32 {
+    let upload = is_uploader(uploader_handle).and_then(|()| {
+        let request: FileUploadRequest = decode(request)?;
+        let content = read_source(&request.source_file_path)?;
+        let length = content.len();
+        let parent_id = node_id(request.parent_folder_identity.as_ref());
+        let file = drive().upload(parent_id, &request.name, content)?;
+        Ok((length, file.encode_to_vec()))
+    });
+    let (length, outcome) = match upload {
+        Ok((length, file)) => (length, Ok(file)),
+        Err(e) => (0, Err(e)),
+    };
+    call_back(callback.async_callback, callback.progress_callback, transfer(length), outcome, None)
+}
+
+#[no_mangle]
+pub extern "C" fn uploader_upload_revision(
+    uploader_handle: isize,
+    request: ByteArray,
+    callback: AsyncCallbackWithProgress,
+) -> i32 {
+    let upload = is_uploader(uploader_handle).and_then(|()| {
+        let request: RevisionUploadRequest = decode(request)?;
+        let content = read_source(&request.source_file_path)?;
+        let length = content.len();
+        let revision = drive().replace(node_id(request.file_identity.as_ref()), content)?;
+        Ok((length, revision.encode_to_vec()))
+    });
+    let (length, outcome) = match upload {
+        Ok((length, revision)) => (length, Ok(revision)),
+        Err(e) => (0, Err(e)),
+    };
+    call_back(callback.async_callback, callback.progress_callback, transfer(length), outcome, None)
+}
+
+#[no_mangle]
+pub extern "C" fn uploader_free(uploader_handle: isize) {
+    drive().free(uploader_handle);
+}
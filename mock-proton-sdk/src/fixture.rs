@@ -0,0 +1,160 @@
+//! The drive the mock serves, read from the JSON file [`FIXTURE_ENV`] names
+
+use std::{env, fs};
+
+use serde::{Deserialize, Deserializer};
+
+use crate::FIXTURE_ENV;
+
+/// The fixture used when [`FIXTURE_ENV`] isn't set
+const DEFAULT_FIXTURE: &str = include_str!("../fixtures/default.json");
+
+/// How a call goes wrong, set on the account or file it's made for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Failure {
+    /// Accepts the call but never calls back
+    NeverCallsBack,
+    /// Calls the success callback, then the failure callback
+    ///
+    /// Only for calls whose callback state outlives the first callback, like `session_begin`.
+    CallsBothCallbacks,
+    /// Succeeds with a handle the mock never issued
+    GarbageHandles,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Fixture {
+    pub accounts: Vec<Account>,
+    #[serde(default)]
+    pub volumes: Vec<Volume>,
+    #[serde(default)]
+    pub shares: Vec<Share>,
+    #[serde(default)]
+    pub nodes: Vec<Node>,
+}
+
+/// An account `session_begin` accepts, its failure applies to logging in
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Account {
+    pub username: String,
+    pub password: String,
+    #[serde(default)]
+    pub failure: Option<Failure>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Volume {
+    pub id: String,
+    pub root_share_id: String,
+    #[serde(default)]
+    pub max_space: i64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Share {
+    pub id: String,
+    pub volume_id: String,
+    pub root_node_id: String,
+    #[serde(default)]
+    pub email: String,
+}
+
+/// A folder, or a file when it has content, its failure applies to downloading it
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Node {
+    pub id: String,
+    #[serde(default)]
+    pub parent: Option<String>,
+    pub name: String,
+    #[serde(default, deserialize_with = "text")]
+    pub content: Option<Vec<u8>>,
+    #[serde(default)]
+    pub failure: Option<Failure>,
+}
+
+fn text<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Vec<u8>>, D::Error> {
+    Ok(Option::<String>::deserialize(deserializer)?.map(String::into_bytes))
+}
+
+impl Fixture {
+    /// Reads the file [`FIXTURE_ENV`] names, or the default fixture of a single empty drive
+    pub fn load() -> Result<Self, String> {
+        let Some(path) = env::var_os(FIXTURE_ENV) else {
+            return Self::parse(DEFAULT_FIXTURE);
+        };
+        let text = fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read the fixture {}: {}", path.to_string_lossy(), e))?;
+        Self::parse(&text).map_err(|e| format!("{} in {}", e, path.to_string_lossy()))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        serde_json::from_str(text).map_err(|e| format!("Invalid fixture: {}", e))
+    }
+
+    pub fn account(&self, username: &str) -> Option<&Account> {
+        self.accounts.iter().find(|account| account.username == username)
+    }
+
+    pub fn node(&self, id: &str) -> Option<&Node> {
+        self.nodes.iter().find(|node| node.id == id)
+    }
+
+    pub fn children<'a>(&'a self, id: &'a str) -> impl Iterator<Item = &'a Node> {
+        self.nodes.iter().filter(move |node| node.parent.as_deref() == Some(id))
+    }
+
+    /// The share whose root the node is under
+    pub fn share_of(&self, id: &str) -> Option<&Share> {
+        let mut node = self.node(id)?;
+        // a parent cycle in a broken fixture ends the walk
+        for _ in 0..=self.nodes.len() {
+            match &node.parent {
+                Some(parent) => node = self.node(parent)?,
+                None => return self.shares.iter().find(|share| share.root_node_id == node.id),
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_default_fixture_is_a_single_empty_drive() {
+        let fixture = Fixture::parse(DEFAULT_FIXTURE).unwrap();
+        assert_eq!(fixture.accounts.len(), 1);
+        assert_eq!(fixture.share_of("root").map(|share| share.id.as_str()), Some("share"));
+        assert_eq!(fixture.children("root").count(), 0);
+    }
+
+    #[test]
+    fn nodes_find_their_share_through_their_parents() {
+        let fixture = Fixture::parse(
+            r#"{
+                "accounts": [],
+                "shares": [{ "id": "share", "volume_id": "volume", "root_node_id": "root" }],
+                "nodes": [
+                    { "id": "root", "name": "root" },
+                    { "id": "photos", "parent": "root", "name": "Photos" },
+                    { "id": "beach", "parent": "photos", "name": "beach.jpg", "content": "jpeg", "failure": "never-calls-back" },
+                    { "id": "loop", "parent": "loop", "name": "loop" }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let beach = fixture.node("beach").unwrap();
+        assert_eq!((beach.content.as_deref(), beach.failure), (Some(&b"jpeg"[..]), Some(Failure::NeverCallsBack)));
+        assert_eq!(fixture.share_of("beach").map(|share| share.id.as_str()), Some("share"));
+        assert!(fixture.share_of("loop").is_none());
+        assert!(Fixture::parse(r#"{ "accounts": [], "users": [] }"#).is_err());
+    }
+}
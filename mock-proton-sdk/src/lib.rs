@@ -0,0 +1,18 @@
+//! A stand-in for the native Proton Drive SDK library
+//!
+//! Built as a cdylib exporting the C symbols of the SDK, it serves a drive held
+//! in memory, read from a JSON fixture (see [`Fixture`]), and calls back from
+//! its own threads like the SDK does. Point [`proton_sdk_sys::LIB_PATH_ENV`] at
+//! the built library to load it instead of the native one.
+
+mod drive;
+mod exports;
+pub mod fixture;
+
+pub use fixture::{Failure, Fixture};
+
+/// Names the JSON fixture to serve, the default is a single empty drive
+pub const FIXTURE_ENV: &str = "MOCK_PROTON_SDK_FIXTURE";
+
+/// The handle an account with [`Failure::GarbageHandles`] gets, the mock never issues it
+pub const GARBAGE_HANDLE: isize = 0x0bad_f00d;
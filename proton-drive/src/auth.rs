@@ -1,80 +1,306 @@
 use std::{env, io};
-use std::fs::{File, OpenOptions};
-use std::io::{Read, Write};
-use std::path::Path;
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use log::{debug, error, info, trace, warn};
-use proton_sdk_rs::sessions::{Session, SessionBuilder, SessionCallbacks, SessionPlatform};
-use proton_sdk_rs::{FromByteArray, ProtonClientOptions, SessionInfo, SessionResumeRequest};
+use proton_sdk_rs::errors::SdkErrorDetails;
+use proton_sdk_rs::sessions::{FileSessionStore, Session, SessionBuilder, SessionCallbacks, SessionError, SessionPlatform};
+use proton_sdk_rs::{FromByteArray, ProtonClientOptions, SdkErrorKind, SessionResumeRequest, SessionTokens};
+use proton_sdk_sys::protobufs::account::{PasswordMode, StringResponse};
+use proton_sdk_sys::secret::SecretBytes;
 use rpassword::prompt_password;
+use totp_rs::{Algorithm, TOTP};
 
-pub async fn create_new_session() -> (Session, bool, String) {
-    let first_run = match std::fs::read_to_string(".cfg") {
-        Ok(cfg) => !cfg.lines().any(|line| line.trim() == "INITIAL_INDEX=true"),
-        Err(_) => true,
-    };
+use crate::config;
+use crate::credentials::{self, CredentialStore, Secret};
+
+/// Exit code when credentials or a second factor are needed but can't be prompted for
+pub const EXIT_CREDENTIALS_MISSING: i32 = 3;
+/// Exit code when Proton rejects the credentials or the session
+pub const EXIT_AUTH_REJECTED: i32 = 4;
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuthError {
+    #[error("No {what} available in non-interactive mode, set {source_hint}")]
+    CredentialsMissing {
+        what: &'static str,
+        source_hint: &'static str,
+    },
+
+    #[error("The account asked for a 2FA code in non-interactive mode, set PROTON_TOTP_SECRET to answer it")]
+    SecondFactorRequired,
+
+    #[error("Authentication failed: {0}")]
+    Rejected(#[from] SessionError),
+}
+
+impl AuthError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            AuthError::CredentialsMissing { .. } | AuthError::SecondFactorRequired => {
+                EXIT_CREDENTIALS_MISSING
+            }
+            AuthError::Rejected(_) => EXIT_AUTH_REJECTED,
+        }
+    }
+}
+
+/// Where credentials may come from
+#[derive(Debug, Clone)]
+pub struct AuthOptions {
+    pub credentials: CredentialStore,
+    /// Prompts for anything missing when set, fails instead otherwise
+    pub interactive: bool,
+    /// From `--username`, `PROTON_USERNAME` or the profile's settings, in that order
+    pub username: Option<String>,
+    /// Read from stdin with `--data-password-stdin`
+    pub data_password: Option<String>,
+    pub profile: Option<String>,
+    /// Settings file the username is remembered in
+    pub config: PathBuf,
+    /// Settings file of older builds, whose secrets are moved to the keyring
+    pub legacy_cfg: PathBuf,
+    /// Where the session is saved for resuming
+    pub session_file: PathBuf,
+    /// Sends usage telemetry to Proton
+    pub telemetry: bool,
+}
 
-    if first_run {
-        debug!("First run!");
+/// Generates the current code of a base32 TOTP secret
+fn totp_code(secret: &str) -> anyhow::Result<String> {
+    let secret: String = secret
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '=')
+        .collect::<String>()
+        .to_uppercase();
+    let bytes = totp_rs::Secret::Encoded(secret).to_bytes()?;
+    let totp = TOTP::new(Algorithm::SHA1, 6, 1, 30, bytes)?;
+    Ok(totp.generate_current()?)
+}
+
+/// Gets a 2FA code from the TOTP secret, or a prompt when interactive
+fn second_factor_code(options: &AuthOptions, username: &str) -> Option<String> {
+    let secret = env::var("PROTON_TOTP_SECRET")
+        .ok()
+        .or_else(|| options.credentials.get(username, Secret::Totp));
+    if let Some(secret) = secret {
+        match totp_code(&secret) {
+            Ok(code) => {
+                debug!("Answering 2FA with the TOTP secret");
+                return Some(code);
+            }
+            Err(e) => error!("Unable to generate a 2FA code from the TOTP secret: {}", e),
+        }
     }
 
-    if let Err(_) = dotenv::dotenv() {
-        let workspace_root = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
-            .parent()
-            .unwrap();
-        let env_path = workspace_root.join(".cfg");
-        dotenv::from_path(env_path).ok();
+    if !options.interactive {
+        return None;
     }
 
-    if let Ok(log_level) = env::var("RUST_LOG") {
-        env_logger::init_from_env(env_logger::Env::default().default_filter_or(&log_level));
-    } else {
-        env_logger::init();
-        warn!("No RUST_LOG environment variable found. Setting default log level.");
+    print!("Enter 2FA code: ");
+    io::stdout().flush().ok();
+    let mut code = String::new();
+    io::stdin().read_line(&mut code).ok()?;
+    let code = code.trim();
+    (!code.is_empty()).then(|| code.to_string())
+}
+
+/// Answers the SDK's 2FA request, flagging `unanswered` when no code was available
+fn two_factor_answer(
+    options: &AuthOptions,
+    username: &str,
+    password: &SecretBytes,
+    password_mode: PasswordMode,
+    unanswered: &AtomicBool,
+) -> (Option<StringResponse>, Option<StringResponse>) {
+    let code = second_factor_code(options, username);
+    if code.is_none() {
+        unanswered.store(true, Ordering::SeqCst);
     }
 
-    let username = env::var("PROTON_USERNAME").unwrap_or_else(|_| {
-        print!("Enter your email: ");
-        io::stdout().flush().unwrap();
-        let mut input = String::new();
-        io::stdin().read_line(&mut input).unwrap();
-        let username = input.trim().to_string();
-
-        let mut file = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(".cfg")
-            .unwrap();
-        writeln!(file, "PROTON_USERNAME={}", username).unwrap();
-
-        username
-    });
+    (
+        code.map(|value| StringResponse { value }),
+        Some(StringResponse {
+            value: data_password(options, username, password, password_mode).into_inner(),
+        }),
+    )
+}
 
-    let password = env::var("PROTON_PASSWORD").unwrap_or_else(|_| {
-        io::stdout().flush().unwrap();
-        let password = prompt_password("Password: ").unwrap();
+/// Reads the data password given with `--data-password-stdin`, the first line of `input`
+pub fn read_data_password(mut input: impl BufRead) -> anyhow::Result<String> {
+    let mut line = String::new();
+    input.read_line(&mut line)?;
+    let data_password = line.trim_end_matches(['\r', '\n']);
+    if data_password.is_empty() {
+        anyhow::bail!("--data-password-stdin was passed but stdin has no password");
+    }
+    Ok(data_password.to_string())
+}
 
-        let mut file = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(".cfg")
-            .unwrap();
-        writeln!(file, "PROTON_PASSWORD={}", password).unwrap();
+/// Gets the data password from `--data-password-stdin`, `PROTON_DATA_PASSWORD`, the keyring
+/// or a prompt, in that order
+///
+/// Single-password accounts have none, their account password is used without
+/// asking, as it is when `NO_DATA_PASS=true`. A blank answer falls back to the
+/// account password and isn't stored, as does non-interactive mode when
+/// nothing else has it.
+fn data_password(options: &AuthOptions, username: &str, password: &SecretBytes, password_mode: PasswordMode) -> SecretBytes {
+    if password_mode == PasswordMode::Single {
+        debug!("Single-password account, using the account password to unlock the data");
+        return password.clone();
+    }
 
-        password
-    });
+    if let Some(data_password) = &options.data_password {
+        return SecretBytes::from(data_password.as_str());
+    }
+
+    if let Ok(data_password) = env::var("PROTON_DATA_PASSWORD") {
+        return SecretBytes::new(data_password);
+    }
+
+    if let Some(data_password) = options.credentials.get(username, Secret::DataPassword) {
+        debug!("Using the data password from the keyring");
+        return SecretBytes::new(data_password);
+    }
+
+    if let Ok("true") = env::var("NO_DATA_PASS").as_deref() {
+        debug!("NO_DATA_PASS is set, using the account password to unlock the data");
+        return password.clone();
+    }
+
+    if !options.interactive {
+        warn!("No data password available, using the account password");
+        return password.clone();
+    }
+
+    println!("Accounts in two-password mode unlock their data with a second password.");
+    println!("Leave it blank to use your account password instead.");
+    io::stdout().flush().ok();
+    let answer = SecretBytes::new(rpassword::prompt_password("Data password: ").unwrap());
+    let data_pass = answer.expose_str().trim();
+    if data_pass.is_empty() {
+        return password.clone();
+    }
+
+    options.credentials.set(username, Secret::DataPassword, data_pass);
+    SecretBytes::from(data_pass)
+}
+
+/// Saves the session so the next start can resume it
+pub fn persist_session(session: &Session, store: &FileSessionStore) {
+    let saved = session
+        .info()
+        .and_then(|info| store.save(&info).map_err(anyhow::Error::from));
+    match saved {
+        Ok(()) => debug!("Session saved to {}", store.path().display()),
+        Err(e) => warn!("Unable to save the session, it won't be resumed: {}", e),
+    }
+}
+
+/// Keeps the saved session's tokens current as the SDK rotates them
+fn persist_refreshed_tokens(store: FileSessionStore) -> impl Fn(&[u8]) + Send + Sync + 'static {
+    move |data| match SessionTokens::from_bytes(data) {
+        Ok(tokens) => {
+            if let Err(e) = store.update_tokens(&tokens) {
+                warn!("Unable to save the refreshed tokens: {}", e);
+            }
+        }
+        Err(e) => warn!("Unable to decode the refreshed tokens: {}", e),
+    }
+}
+
+/// Password the local index is encrypted with, `None` when there is none to use
+///
+/// The data password is preferred, from `--data-password-stdin`,
+/// `PROTON_DATA_PASSWORD` or the keyring, then the account password for
+/// accounts without one. It is looked up for the
+/// account of the saved session, so commands that don't log in can open the
+/// index too.
+pub fn index_key(options: &AuthOptions) -> Option<String> {
+    let username = options.username.clone().or_else(|| {
+        let saved = FileSessionStore::new(&options.session_file).load().ok().flatten();
+        saved.map(|info| info.username)
+    })?;
+
+    options
+        .data_password
+        .clone()
+        .or_else(|| env::var("PROTON_DATA_PASSWORD").ok())
+        .or_else(|| options.credentials.get(&username, Secret::DataPassword))
+        .or_else(|| env::var("PROTON_PASSWORD").ok())
+        .or_else(|| options.credentials.get(&username, Secret::Password))
+        .filter(|key| !key.is_empty())
+}
+
+/// Resumes the saved session, or logs in with the configured credentials
+pub async fn create_new_session(options: AuthOptions) -> Result<Session, AuthError> {
+    let credentials = options.credentials.clone();
+
+    if let Err(e) = credentials::migrate_cfg(&options.legacy_cfg, &credentials) {
+        warn!("Unable to move the .cfg secrets to the keyring: {}", e);
+    }
+
+    if !options.interactive {
+        info!("Running non-interactively, credentials won't be prompted for");
+    }
+
+    let username = match options.username.clone() {
+        Some(username) => username,
+        None if !options.interactive => {
+            return Err(AuthError::CredentialsMissing {
+                what: "username",
+                source_hint: "PROTON_USERNAME, the username setting or pass --username",
+            });
+        }
+        None => {
+            print!("Enter your email: ");
+            io::stdout().flush().unwrap();
+            let mut input = String::new();
+            io::stdin().read_line(&mut input).unwrap();
+            let username = input.trim().to_string();
+
+            if let Err(e) = config::remember_username(&options.config, options.profile.as_deref(), &username) {
+                warn!("Unable to remember the username: {}", e);
+            }
+            username
+        }
+    };
+
+    let stored_password = env::var("PROTON_PASSWORD")
+        .ok()
+        .or_else(|| credentials.get(&username, Secret::Password))
+        .map(SecretBytes::new);
+    let password = match stored_password {
+        Some(password) => password,
+        None if !options.interactive => {
+            return Err(AuthError::CredentialsMissing {
+                what: "password",
+                source_hint: "PROTON_PASSWORD or store it in the keyring from an interactive login",
+            });
+        }
+        None => {
+            io::stdout().flush().unwrap();
+            let password = SecretBytes::new(prompt_password("Password: ").unwrap());
+            if !credentials.set(&username, Secret::Password, password.expose_str()) {
+                info!("Password not stored, you will be asked for it on the next start");
+            }
+            password
+        }
+    };
+    let second_factor_unanswered = Arc::new(AtomicBool::new(false));
     let password_clone = password.clone();
     let password_clone2 = password.clone();
 
-    let session_info = File::open("session_info.bin")
-        .ok()
-        .and_then(|mut f| {
-            let mut info_bytes = Vec::new();
-            f.read_to_end(&mut info_bytes).ok()?;
-            SessionInfo::from_bytes(&info_bytes).ok()
-        });
+    let store = FileSessionStore::new(&options.session_file);
+    let session_info = store.load().unwrap_or_else(|e| {
+        warn!("Ignoring unreadable {}: {}", store.path().display(), e);
+        None
+    });
 
     if let Some(info) = session_info {
         let username_for_2fa = info.username.clone();
+        let password_mode = info.password_mode();
 
         info!("Attempting to resume session...");
         let resume_result = SessionBuilder::resume_session(
@@ -97,138 +323,68 @@ pub async fn create_new_session() -> (Session, bool, String) {
                 })),
                 secret_requested: None,
                 two_factor_requested: Some(Box::new({
+                    let options = options.clone();
                     let username_for_2fa = username_for_2fa.clone();
+                    let unanswered = second_factor_unanswered.clone();
                     move |_context| {
-                        print!("Enter 2FA code: ");
-                        io::stdout().flush().ok();
-                        let mut code = String::new();
-                        let code_opt = if io::stdin().read_line(&mut code).is_ok() {
-                            let code = code.trim();
-                            if !code.is_empty() {
-                                Some(proton_sdk_sys::protobufs::StringResponse {
-                                    value: code.to_string(),
-                                })
-                            } else {
-                                None
-                            }
-                        } else {
-                            None
-                        };
-
-                        let data_pass_opt = match env::var("NO_DATA_PASS").as_deref() {
-                            Ok("true") => {
-                                warn!("Data password not provided, setting as users password");
-                                Some(proton_sdk_sys::protobufs::StringResponse {
-                                    value: password.clone(),
-                                })
-                            }
-                            _ => {
-                                println!("Your data password is the password used to unlock your data. \nIf you do not know what that is or don't have one, just leave it blank and we won't prompt you.");
-                                io::stdout().flush().ok();
-                                let data_pass = rpassword::prompt_password("Data password: ").unwrap();
-                                if !data_pass.trim().is_empty() {
-                                    Some(proton_sdk_sys::protobufs::StringResponse {
-                                        value: data_pass.trim().to_string(),
-                                    })
-                                } else {
-                                    Some(proton_sdk_sys::protobufs::StringResponse {
-                                        value: password.clone(),
-                                    })
-                                }
-                            }
-                        };
-
-                        (code_opt, data_pass_opt)
+                        two_factor_answer(&options, &username_for_2fa, &password, password_mode, &unanswered)
                     }
                 })),
-                tokens_refreshed: None,
+                tokens_refreshed: Some(Box::new(persist_refreshed_tokens(store.clone()))),
             },
         SessionPlatform::Linux, "proton-drive-rs", "0.1.0");
         match resume_result.await {
             Ok(session) => {
-                let data_password = match env::var("NO_DATA_PASS").as_deref() {
-                    Ok("true") => {
-                        warn!("Data password not provided, setting as password");
-                        password_clone2.clone()
-                    }
-                    _ => {
-                        println!("Enter your data password to unlock your data (leave blank to use username): ");
-                        io::stdout().flush().ok();
-                        let data_pass = rpassword::prompt_password("Data password: ").unwrap();
-                        if !data_pass.trim().is_empty() {
-                            data_pass.trim().to_string()
-                        } else {
-                            password_clone2.clone()
-                        }
-                    }
-                };
+                let data_password = data_password(&options, &info.username, &password_clone2, password_mode);
 
                 // Apply the data password to the session
-                session.apply_data_password(&data_password)
+                session.apply_data_password(data_password)
                     .map_err(|e| {
                         error!("Failed to apply data password: {}", e);
                         e
                     }).ok();
 
                 info!("Session resumed successfully!");
-                return (session, first_run, info.username.clone());
+                persist_session(&session, &store);
+                return Ok(session);
             },
+            Err(_) if second_factor_unanswered.load(Ordering::SeqCst) => {
+                return Err(AuthError::SecondFactorRequired);
+            }
             Err(e) => {
                 warn!("Session resume failed [{}], will try creating new session.", e);
+                if e.kind() == SdkErrorKind::Authentication {
+                    debug!("Removing the rejected session");
+                    if let Err(e) = store.clear() {
+                        warn!("Unable to remove {}: {}", store.path().display(), e);
+                    }
+                }
             }
         }
     }
 
     let password_for_2fa = password_clone.clone();
-    let session_result = SessionBuilder::new(username.clone(), password_clone.clone())
+    let username_for_2fa = username.clone();
+    let options_for_2fa = options.clone();
+    let unanswered = second_factor_unanswered.clone();
+    let session_result = SessionBuilder::new(username.clone(), password_clone)
         .with_app_version(SessionPlatform::Linux, "proton-drive-rs", "0.1.0")
         .with_request_response_callback(|data| {
             let data_str = String::from_utf8_lossy(data);
             trace!("HTTP: {} bytes", data.len());
             trace!("Content: {}", data_str);
         })
+        // the password mode isn't known before the session exists
         .with_two_factor_requested_callback(move |_context| {
-            print!("Enter 2FA code: ");
-            io::stdout().flush().ok();
-            let mut code = String::new();
-            let code_opt = if io::stdin().read_line(&mut code).is_ok() {
-                let code = code.trim();
-                if !code.is_empty() {
-                    Some(proton_sdk_sys::protobufs::StringResponse {
-                        value: code.to_string(),
-                    })
-                } else {
-                    None
-                }
-            } else {
-                None
-            };
-
-            let data_pass_opt = match env::var("NO_DATA_PASS").as_deref() {
-                Ok("true") => {
-                    warn!("Data password not provided, setting as users password");
-                    Some(proton_sdk_sys::protobufs::StringResponse {
-                        value: password_for_2fa.clone(),
-                    })
-                }
-                _ => {
-                    println!("Your data password is the password used to unlock your data. \n If you do not know what that is or don't have one, just leave it blank and we won't prompt you. ");
-                    io::stdout().flush().ok();
-                    let data_pass = rpassword::prompt_password("Data password: ").unwrap();
-                    if !data_pass.trim().is_empty() {
-                        Some(proton_sdk_sys::protobufs::StringResponse {
-                            value: data_pass.trim().to_string(),
-                        })
-                    } else {
-                        Some(proton_sdk_sys::protobufs::StringResponse {
-                            value: password_for_2fa.clone(),
-                        })
-                    }
-                }
-            };
-
-            (code_opt, data_pass_opt)
+            two_factor_answer(
+                &options_for_2fa,
+                &username_for_2fa,
+                &password_for_2fa,
+                PasswordMode::UnknownPasswordMode,
+                &unanswered,
+            )
         })
+        .with_tokens_refreshed_callback(persist_refreshed_tokens(store.clone()))
         .begin()
         .await;
 
@@ -236,26 +392,31 @@ pub async fn create_new_session() -> (Session, bool, String) {
         Ok(session) => {
             println!("Session created successfully!");
             debug!("Session handle: {:?}", session.handle());
+            persist_session(&session, &store);
             session
         }
+        Err(_) if second_factor_unanswered.load(Ordering::SeqCst) => {
+            return Err(AuthError::SecondFactorRequired);
+        }
         Err(e) => {
             println!("Failed to create session: {}", e);
 
-            match e {
+            match &e {
                 proton_sdk_rs::sessions::SessionError::SdkError(sdk_err) => {
                     error!("SDK Error Details: {}", sdk_err);
                 }
-                proton_sdk_rs::sessions::SessionError::OperationFailed(code) => {
-                    error!("SDK operation failed with code: {}", code);
-                    match code {
-                        -1 => error!(
+                proton_sdk_rs::sessions::SessionError::OperationFailed(details) => {
+                    error!("SDK operation failed: {}", details);
+                    match details {
+                        SdkErrorDetails::Unauthorized { .. } => println!("   Authentication failed - check username/password"),
+                        SdkErrorDetails::Forbidden { .. } => println!("   Access forbidden - account may be locked or suspended"),
+                        SdkErrorDetails::Unprocessable { .. } => println!("   Invalid request format"),
+                        SdkErrorDetails::RateLimited { .. } => println!("   Too many attempts - try again later"),
+                        SdkErrorDetails::IncorrectPassword { .. } => println!("   Two factor code failed"),
+                        SdkErrorDetails::Other { code: Some(-1), .. } => error!(
                             "   Possible causes: Invalid credentials, network issues, or SDK not initialized"
                         ),
-                        401 => println!("   Authentication failed - check username/password"),
-                        403 => println!("   Access forbidden - account may be locked or suspended"),
-                        422 => println!("   Invalid request format"),
-                        8002 => println!("   Two factor code failed"),
-                        _ => println!("   Unknown error code: {}", code),
+                        SdkErrorDetails::Other { .. } => println!("   Unknown error code: {:?}", details.code()),
                     }
                 }
                 proton_sdk_rs::sessions::SessionError::ProtobufError(proto_err) => {
@@ -265,8 +426,41 @@ pub async fn create_new_session() -> (Session, bool, String) {
                     error!("Other error: {}", e);
                 }
             }
-            panic!("Failed to create session");
+            return Err(AuthError::Rejected(e));
         }
     };
-    (session, first_run, password_clone)
-}
\ No newline at end of file
+    Ok(session)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(data_password: Option<&str>) -> AuthOptions {
+        AuthOptions {
+            credentials: CredentialStore::new(false, None),
+            interactive: false,
+            username: Some("user@proton.me".to_string()),
+            data_password: data_password.map(str::to_string),
+            profile: None,
+            config: PathBuf::new(),
+            legacy_cfg: PathBuf::new(),
+            session_file: PathBuf::new(),
+            telemetry: false,
+        }
+    }
+
+    #[test]
+    fn single_password_accounts_are_never_asked_for_a_data_password() {
+        let options = options(Some("data"));
+        let account = SecretBytes::from("account");
+        assert_eq!(data_password(&options, "user@proton.me", &account, PasswordMode::Single).expose_str(), "account");
+        assert_eq!(data_password(&options, "user@proton.me", &account, PasswordMode::Dual).expose_str(), "data");
+    }
+
+    #[test]
+    fn the_data_password_is_the_first_line_of_stdin() {
+        assert_eq!(read_data_password("s3cret pass\r\nignored\n".as_bytes()).unwrap(), "s3cret pass");
+        assert!(read_data_password("\n".as_bytes()).is_err());
+        assert!(read_data_password("".as_bytes()).is_err());
+    }
+}
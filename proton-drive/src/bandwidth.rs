@@ -0,0 +1,211 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use chrono::{Local, NaiveTime, Utc};
+use log::debug;
+use proton_sdk_sys::protobufs::human_bytes;
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+use crate::config::Bandwidth;
+use crate::dry_run::Direction;
+
+/// Transfer rates, in bytes per second, `None` for no limit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Limits {
+    pub down: Option<u64>,
+    pub up: Option<u64>,
+}
+
+impl Limits {
+    fn of(&self, direction: Direction) -> Option<u64> {
+        match direction {
+            Direction::Down => self.down,
+            Direction::Up => self.up,
+        }
+    }
+}
+
+impl fmt::Display for Limits {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let rate = |rate: Option<u64>| match rate {
+            Some(rate) => format!("{}/s", human_bytes(rate as i64)),
+            None => "unlimited".to_string(),
+        };
+        write!(f, "down {}, up {}", rate(self.down), rate(self.up))
+    }
+}
+
+/// Parses a time of day like `22:00`
+pub fn parse_time(value: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(value, "%H:%M").map_err(|_| format!("`{}` isn't a time like 22:00", value))
+}
+
+/// What one direction has moved, and when its next transfer may start
+#[derive(Debug, Default)]
+struct Pacer {
+    /// When the bytes transferred so far are done at the limit
+    allowed_at: Option<Instant>,
+    bytes: u64,
+    first_started: Option<Instant>,
+    last_finished: Option<Instant>,
+}
+
+/// Limits of the process and what went through them
+#[derive(Debug, Default)]
+struct State {
+    settings: Bandwidth,
+    /// `--limit-down` and `--limit-up`
+    flags: Limits,
+    down: Pacer,
+    up: Pacer,
+}
+
+impl State {
+    fn pacer(&mut self, direction: Direction) -> &mut Pacer {
+        match direction {
+            Direction::Down => &mut self.down,
+            Direction::Up => &mut self.up,
+        }
+    }
+}
+
+/// Allowance an idle spell leaves for the next transfers, so a pause doesn't buy a long burst
+const MAX_BURST: Duration = Duration::from_secs(1);
+
+static STATE: OnceLock<Mutex<State>> = OnceLock::new();
+
+fn state() -> &'static Mutex<State> {
+    STATE.get_or_init(Default::default)
+}
+
+/// Limits the transfers from now on by the settings and the flags, which take precedence
+///
+/// The schedule of the settings is followed as the day goes by.
+pub fn configure(settings: Bandwidth, flags: Limits) {
+    let mut state = state().lock().unwrap();
+    state.settings = settings;
+    state.flags = flags;
+}
+
+/// Limits transfers run with at the moment
+pub fn limits() -> Limits {
+    let state = state().lock().unwrap();
+    state.settings.effective(state.flags, Local::now().time())
+}
+
+/// Records a finished transfer of `bytes` and waits until the average rate is back under the limit
+///
+/// The native SDK reads and writes the files itself, so a single file goes
+/// at full speed. Holding the next transfers back keeps the average rate of
+/// all of them under the limit instead.
+pub async fn pace(direction: Direction, bytes: u64, started: Instant) {
+    let wait = {
+        let rate = limits().of(direction);
+        let mut state = state().lock().unwrap();
+        let pacer = state.pacer(direction);
+        let now = Instant::now();
+        pacer.bytes += bytes;
+        pacer.first_started = Some(pacer.first_started.map_or(started, |first| first.min(started)));
+
+        let wait = match rate {
+            Some(rate) => {
+                let earliest = now.checked_sub(MAX_BURST).unwrap_or(now);
+                let from = pacer.allowed_at.map_or(started, |allowed| allowed.max(earliest));
+                let allowed_at = from + Duration::from_secs_f64(bytes as f64 / rate as f64);
+                pacer.allowed_at = Some(allowed_at);
+                allowed_at.saturating_duration_since(now)
+            }
+            None => {
+                pacer.allowed_at = None;
+                Duration::ZERO
+            }
+        };
+        // the wait counts, the throughput is what the transfers average out to
+        pacer.last_finished = Some(now + wait);
+        wait
+    };
+    if !wait.is_zero() {
+        debug!("Holding {:?} transfers back for {:?}", direction, wait);
+        tokio::time::sleep(wait).await;
+    }
+}
+
+/// Bytes moved one way and over how long, from the start of the first transfer to the end of the last
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Measured {
+    pub bytes: u64,
+    pub seconds: f64,
+    /// When the last transfer finished, in seconds since the epoch
+    pub at: i64,
+}
+
+impl Measured {
+    /// Average rate in bytes per second
+    pub fn rate(&self) -> u64 {
+        if self.seconds > 0.0 { (self.bytes as f64 / self.seconds) as u64 } else { 0 }
+    }
+}
+
+/// Throughput of the last runs that transferred anything, as `status` shows it
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Throughput {
+    pub down: Option<Measured>,
+    pub up: Option<Measured>,
+}
+
+fn measured(direction: Direction) -> Option<Measured> {
+    let mut state = state().lock().unwrap();
+    let pacer = state.pacer(direction);
+    let (Some(first), Some(last)) = (pacer.first_started, pacer.last_finished) else {
+        return None;
+    };
+    Some(Measured {
+        bytes: pacer.bytes,
+        seconds: (last - first).as_secs_f64(),
+        at: Utc::now().timestamp() - Instant::now().saturating_duration_since(last).as_secs() as i64,
+    })
+}
+
+/// Saves what this process transferred to `path`, keeping the previous figure of a direction it didn't use
+pub fn save(path: &Path) -> anyhow::Result<()> {
+    let (down, up) = (measured(Direction::Down), measured(Direction::Up));
+    if down.is_none() && up.is_none() {
+        return Ok(());
+    }
+    let previous = load(path).unwrap_or_default();
+    let throughput = Throughput { down: down.or(previous.down), up: up.or(previous.up) };
+    fs::write(path, serde_json::to_vec_pretty(&throughput)?)?;
+    Ok(())
+}
+
+/// Reads the throughput [`save`] left, nothing when no transfer was made yet
+pub fn load(path: &Path) -> anyhow::Result<Throughput> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Throughput::default()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn transfers_are_held_back_to_the_average_rate() {
+        configure(Bandwidth::default(), Limits { down: None, up: Some(10_000) });
+        let started = Instant::now();
+        pace(Direction::Up, 500, started).await;
+        pace(Direction::Up, 1500, Instant::now()).await;
+        assert!(started.elapsed() >= Duration::from_millis(200), "{:?}", started.elapsed());
+
+        assert!(measured(Direction::Down).is_none());
+        let up = measured(Direction::Up).unwrap();
+        assert_eq!(up.bytes, 2000);
+        assert!((9_900..=10_000).contains(&up.rate()), "{}", up.rate());
+    }
+}
@@ -0,0 +1,713 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use clap::{ArgAction, Args, Parser, Subcommand};
+use log::{warn, LevelFilter};
+use proton_sdk_rs::downloads::DownloadError;
+use proton_sdk_rs::drive::DriveError;
+use proton_sdk_rs::nodes::{NodeError, RemotePath};
+use proton_sdk_rs::sessions::SessionError;
+use proton_sdk_rs::uploads::UploadError;
+use proton_sdk_rs::SdkErrorKind;
+use proton_sdk_sys::{LoadDiagnostics, LoadError};
+
+use crate::auth::{AuthError, EXIT_AUTH_REJECTED};
+use crate::bandwidth::Limits;
+use crate::config::{self, ConfigError};
+use crate::index::{ExportFormat, IndexError};
+use crate::upload::OnConflict;
+
+/// Exit code for failures without a more specific code
+pub const EXIT_FAILURE: i32 = 1;
+/// Exit code when a remote or local path doesn't exist
+pub const EXIT_NOT_FOUND: i32 = 5;
+/// Exit code when Proton can't be reached
+pub const EXIT_NETWORK: i32 = 6;
+/// Exit code when `quota --warn-at` finds more space used than the threshold
+pub const EXIT_QUOTA_WARNING: i32 = 7;
+/// Exit code when the operation was cancelled
+pub const EXIT_CANCELLED: i32 = 130;
+
+#[derive(Debug, Parser)]
+#[command(name = "proton-drive", version, about = "Proton Drive from the command line")]
+pub struct Cli {
+    /// Settings file, defaults to `config.toml` in the platform config directory
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Keeps the account, session, keyring entries, index and jobs of this profile apart from the others
+    #[arg(
+        long,
+        global = true,
+        value_name = "NAME",
+        default_value = config::DEFAULT_PROFILE,
+        value_parser = parse_profile
+    )]
+    pub profile: String,
+
+    /// Logs more, repeat for debug (-vv) and trace (-vvv) output
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Logs at this level, whatever `RUST_LOG` says
+    #[arg(long, global = true, value_name = "LEVEL", conflicts_with = "verbose")]
+    pub log_level: Option<LevelFilter>,
+
+    /// Also writes the log to a file, instead of the `log.file` setting
+    #[arg(long, global = true, value_name = "PATH")]
+    pub log_file: Option<PathBuf>,
+
+    /// Never reads nor stores secrets in the OS keyring
+    #[arg(long, global = true)]
+    pub no_keyring: bool,
+
+    /// Fails instead of prompting, implied when stdin isn't a terminal
+    #[arg(long, global = true)]
+    pub non_interactive: bool,
+
+    /// Account to log in with, instead of `PROTON_USERNAME` or the `username` setting
+    #[arg(long, global = true, value_name = "EMAIL")]
+    pub username: Option<String>,
+
+    /// Reads the data password from the first line of stdin, instead of `PROTON_DATA_PASSWORD`
+    #[arg(long, global = true)]
+    pub data_password_stdin: bool,
+
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Logs in and saves the session for the other commands
+    Login,
+
+    /// Forgets the saved session and the secrets stored in the keyring
+    Logout,
+
+    /// Lists a remote folder
+    Ls {
+        #[arg(default_value = "/")]
+        remote_path: RemotePath,
+
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Shows the ids and metadata of a remote file or folder, found through the index when there is one
+    Stat {
+        /// Remote path, or a node identity as printed by `stat`
+        target: String,
+
+        #[arg(long)]
+        json: bool,
+
+        /// Looks the node and a folder's children up on the drive, even with an index
+        #[arg(long)]
+        live: bool,
+    },
+
+    /// Downloads a remote file or folder, found through the index when there is one
+    Download {
+        remote: RemotePath,
+
+        #[arg(default_value = ".")]
+        local: PathBuf,
+
+        #[command(flatten)]
+        limits: RateLimits,
+    },
+
+    /// Uploads a local file or directory into a remote folder
+    Upload {
+        local: PathBuf,
+        remote: RemotePath,
+
+        /// Creates the remote folder and its missing parents
+        #[arg(long)]
+        create_parents: bool,
+
+        /// What to do with files that already exist remotely
+        #[arg(long, value_enum, default_value_t = OnConflict::Revision)]
+        on_conflict: OnConflict,
+
+        #[command(flatten)]
+        limits: RateLimits,
+    },
+
+    /// Uploads the changes made under a local folder until interrupted
+    Watch {
+        local: PathBuf,
+
+        #[arg(default_value = "/")]
+        remote: RemotePath,
+
+        /// Trashes remote files deleted locally
+        #[arg(long)]
+        delete_remote: bool,
+
+        /// Skips paths or names matching a glob, on top of the usual editor and OS leftovers
+        #[arg(long, value_name = "GLOB")]
+        ignore: Vec<glob::Pattern>,
+
+        /// Seconds to wait for a file to settle before uploading it
+        #[arg(long, value_name = "SECONDS", default_value_t = 2)]
+        debounce: u64,
+
+        #[command(flatten)]
+        limits: RateLimits,
+
+        /// Lists what would be uploaded and moved since the last sync, then exits
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Prints the dry run plan as JSON
+        #[arg(long, requires = "dry_run")]
+        json: bool,
+    },
+
+    /// Makes a local folder match an indexed remote folder, downloading what changed
+    Mirror {
+        remote: RemotePath,
+        local: PathBuf,
+
+        /// Deletes local files that aren't in the remote folder
+        #[arg(long)]
+        delete_local: bool,
+
+        /// Lists what would be moved, downloaded and deleted without doing it
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Prints the dry run plan as JSON
+        #[arg(long, requires = "dry_run")]
+        json: bool,
+
+        /// Files downloaded in parallel, instead of the `concurrency.transfers` setting
+        #[arg(long)]
+        workers: Option<usize>,
+
+        #[command(flatten)]
+        limits: RateLimits,
+    },
+
+    /// Refreshes the index and runs the `[[jobs]]` of the settings periodically
+    Daemon {
+        /// Time between iterations, in seconds or with an `s`, `m` or `h` suffix
+        #[arg(long, value_parser = parse_interval, default_value = "5m")]
+        interval: Duration,
+
+        /// Runs a single iteration, for cron
+        #[arg(long)]
+        once: bool,
+
+        #[command(flatten)]
+        limits: RateLimits,
+
+        /// Refreshes the index, then lists what the jobs would do without doing it
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Prints the dry run plan as JSON
+        #[arg(long, requires = "dry_run")]
+        json: bool,
+    },
+
+    /// Builds the local index of the drive, resuming an interrupted run, then refreshes it
+    #[command(args_conflicts_with_subcommands = true)]
+    Index {
+        #[command(subcommand)]
+        command: Option<IndexCommand>,
+
+        /// Keeps refreshing the index until interrupted
+        #[arg(long)]
+        watch: bool,
+
+        /// Folders listed in parallel, instead of the `concurrency.index_workers` setting
+        #[arg(long, alias = "index-workers")]
+        workers: Option<usize>,
+
+        /// Lists every folder again instead of resuming the previous indexing
+        #[arg(long)]
+        force_reindex: bool,
+
+        /// Lists only the folders that failed in earlier runs, and what is unlisted under them
+        #[arg(long, conflicts_with_all = ["force_reindex", "watch"])]
+        retry_failed: bool,
+    },
+
+    /// Searches the local index for paths containing a pattern
+    Search {
+        pattern: String,
+
+        /// Matches the whole path against a glob instead, `*` also matches `/`
+        #[arg(long, conflicts_with = "regex")]
+        glob: bool,
+
+        /// Matches a regular expression anywhere in the path instead
+        #[arg(long)]
+        regex: bool,
+
+        #[arg(long)]
+        case_sensitive: bool,
+
+        #[arg(long, conflicts_with = "folders_only")]
+        files_only: bool,
+
+        #[arg(long)]
+        folders_only: bool,
+
+        /// Also shows what was deleted from the drive since it was indexed
+        #[arg(long)]
+        include_deleted: bool,
+
+        /// Stops after this many results
+        #[arg(long, value_name = "N")]
+        limit: Option<usize>,
+
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Shows the saved session and the state of the index
+    Status {
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Shows the space of the account's volumes, the space used and the largest indexed paths
+    Quota {
+        /// Number of largest files and folders listed
+        #[arg(long, value_name = "N", default_value_t = 10)]
+        top: usize,
+
+        /// Exits with status 7 when more than this share of the space is used, e.g. `90%`
+        #[arg(long, value_name = "PERCENT", value_parser = parse_percent)]
+        warn_at: Option<f64>,
+
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Checks that a local folder matches an indexed remote folder, by size and content digest
+    Verify {
+        local: PathBuf,
+        remote: RemotePath,
+
+        /// Downloads the missing and mismatching files again
+        #[arg(long)]
+        repair: bool,
+
+        /// Files downloaded in parallel when repairing, instead of the `concurrency.transfers` setting
+        #[arg(long)]
+        workers: Option<usize>,
+    },
+
+    /// Manages the profiles, each with its own account, session and index
+    Profile {
+        #[command(subcommand)]
+        command: ProfileCommand,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum IndexCommand {
+    /// Writes every indexed folder and file with its ids, size, mtime and deletion time
+    Export {
+        #[arg(long, value_enum, default_value_t = ExportFormat::Json)]
+        format: ExportFormat,
+
+        /// File to write, stdout without one
+        #[arg(long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+
+    /// Restores a JSON export into a new index
+    Import { input: PathBuf },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ProfileCommand {
+    /// Lists the profiles with their account and whether they are logged in
+    List,
+
+    /// Adds a profile to the settings, to select with `--profile`
+    Add {
+        #[arg(value_parser = parse_profile)]
+        name: String,
+
+        /// Account the profile logs in with, asked for on its first login otherwise
+        #[arg(long, value_name = "EMAIL")]
+        username: Option<String>,
+    },
+
+    /// Removes a profile's settings, session, index and keyring entries
+    Remove {
+        #[arg(value_parser = parse_profile)]
+        name: String,
+    },
+}
+
+/// Transfer rate flags, taking precedence over the `[bandwidth]` settings
+#[derive(Debug, Clone, Copy, Args)]
+pub struct RateLimits {
+    /// Limits downloads to this many bytes per second, with a `k`, `M` or `G` suffix
+    #[arg(long, value_name = "RATE", value_parser = parse_rate)]
+    pub limit_down: Option<u64>,
+
+    /// Limits uploads to this many bytes per second, with a `k`, `M` or `G` suffix
+    #[arg(long, value_name = "RATE", value_parser = parse_rate)]
+    pub limit_up: Option<u64>,
+}
+
+impl From<RateLimits> for Limits {
+    fn from(flags: RateLimits) -> Self {
+        Limits { down: flags.limit_down, up: flags.limit_up }
+    }
+}
+
+/// Files a profile keeps
+#[derive(Debug, Clone)]
+pub struct Paths {
+    /// `config.toml`, shared by every profile
+    pub config: PathBuf,
+    /// Settings file of older builds, imported into `config` and kept for the secrets it may hold
+    pub legacy_cfg: PathBuf,
+    pub session: PathBuf,
+    pub index: PathBuf,
+    /// Transfer rates measured by the last runs, shown by `status`
+    pub throughput: PathBuf,
+}
+
+impl Paths {
+    /// Resolves the files of a profile, `None` being the default one
+    ///
+    /// The index moves to the profile's `index_db` setting when there is one.
+    pub fn for_profile(config: PathBuf, profile: Option<&str>) -> Self {
+        let dir = config::profile_dir(profile);
+        Paths {
+            config,
+            legacy_cfg: legacy_dir(profile).join(".cfg"),
+            session: dir.join("session_info.bin"),
+            index: dir.join("index.db"),
+            throughput: dir.join("throughput.json"),
+        }
+    }
+
+    /// Moves the session and index older builds kept under the working directory
+    /// to the profile's directory, returning what was moved
+    ///
+    /// Nothing is moved over an existing file.
+    pub fn adopt_legacy_files(&self) -> Vec<(PathBuf, PathBuf)> {
+        let Some(legacy) = self.legacy_cfg.parent() else {
+            return Vec::new();
+        };
+
+        let mut files = vec![(legacy.join("session_info.bin"), self.session.clone())];
+        for suffix in ["", "-wal", "-shm"] {
+            let mut index = self.index.as_os_str().to_owned();
+            index.push(suffix);
+            files.push((legacy.join(format!("index.db{}", suffix)), PathBuf::from(index)));
+        }
+
+        let mut moved = Vec::new();
+        for (from, to) in files {
+            if !from.is_file() || to.exists() || same_file(&from, &to) {
+                continue;
+            }
+            let renamed = to
+                .parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|()| fs::rename(&from, &to));
+            match renamed {
+                Ok(()) => moved.push((from, to)),
+                Err(e) => warn!("Unable to move {} to {}: {}", from.display(), to.display(), e),
+            }
+        }
+        moved
+    }
+}
+
+/// Where older builds kept the files of a profile, relative to the working directory
+fn legacy_dir(profile: Option<&str>) -> PathBuf {
+    match profile {
+        Some(profile) => PathBuf::from("profiles").join(profile),
+        None => PathBuf::new(),
+    }
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    std::path::absolute(a).ok() == std::path::absolute(b).ok()
+}
+
+fn parse_profile(name: &str) -> Result<String, String> {
+    config::validate_profile_name(name)?;
+    Ok(name.to_string())
+}
+
+impl Cli {
+    /// The selected profile, `None` for the default one whose settings are at the top level
+    pub fn profile(&self) -> Option<&str> {
+        Some(self.profile.as_str()).filter(|profile| *profile != config::DEFAULT_PROFILE)
+    }
+
+    /// Resolves the files of the selected profile
+    pub fn paths(&self) -> Paths {
+        let config = self.config.clone().unwrap_or_else(config::default_path);
+        Paths::for_profile(config, self.profile())
+    }
+
+    /// Log level of `--log-level` or the verbosity flags, `None` defers to `RUST_LOG`
+    pub fn log_level(&self) -> Option<LevelFilter> {
+        self.log_level.or(match self.verbose {
+            0 => None,
+            1 => Some(LevelFilter::Info),
+            2 => Some(LevelFilter::Debug),
+            _ => Some(LevelFilter::Trace),
+        })
+    }
+}
+
+impl Command {
+    /// Rates of `--limit-down` and `--limit-up`, for the commands transferring files
+    pub fn rate_limits(&self) -> Limits {
+        match self {
+            Command::Download { limits, .. }
+            | Command::Upload { limits, .. }
+            | Command::Watch { limits, .. }
+            | Command::Mirror { limits, .. }
+            | Command::Daemon { limits, .. } => (*limits).into(),
+            _ => Limits::default(),
+        }
+    }
+
+    /// Checks if the command calls the native SDK, which the others run without
+    pub fn uses_sdk(&self) -> bool {
+        !matches!(
+            self,
+            Command::Logout
+                | Command::Index { command: Some(IndexCommand::Export { .. } | IndexCommand::Import { .. }), .. }
+                | Command::Search { .. }
+                | Command::Status { .. }
+                | Command::Profile { .. }
+        )
+    }
+}
+
+/// Parses a duration like `90`, `30s`, `5m` or `1h`
+fn parse_interval(value: &str) -> Result<Duration, String> {
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(at) => value.split_at(at),
+        None => (value, "s"),
+    };
+    let number: u64 = number.parse().map_err(|_| format!("`{}` isn't a duration", value))?;
+    let seconds = match unit {
+        "s" => number,
+        "m" => number * 60,
+        "h" => number * 60 * 60,
+        _ => return Err(format!("unknown unit `{}`, use s, m or h", unit)),
+    };
+    if seconds == 0 {
+        return Err("the interval can't be zero".to_string());
+    }
+    Ok(Duration::from_secs(seconds))
+}
+
+/// Parses a share of the space like `90%` or `90`
+fn parse_percent(value: &str) -> Result<f64, String> {
+    let number = value.strip_suffix('%').unwrap_or(value).trim();
+    let percent: f64 = number.parse().map_err(|_| format!("`{}` isn't a percentage", value))?;
+    if !(percent > 0.0 && percent <= 100.0) {
+        return Err("the percentage must be above 0 and at most 100".to_string());
+    }
+    Ok(percent)
+}
+
+/// Parses a rate like `500k`, `10M` or `1.5G` into bytes per second, units being powers of 1024
+fn parse_rate(value: &str) -> Result<u64, String> {
+    let (number, unit) = match value.find(|c: char| !c.is_ascii_digit() && c != '.') {
+        Some(at) => value.split_at(at),
+        None => (value, ""),
+    };
+    let number: f64 = number.parse().map_err(|_| format!("`{}` isn't a rate", value))?;
+    let multiplier = match unit.to_ascii_lowercase().as_str() {
+        "" | "b" => 1.0,
+        "k" | "kb" => 1024.0,
+        "m" | "mb" => 1024.0 * 1024.0,
+        "g" | "gb" => 1024.0 * 1024.0 * 1024.0,
+        _ => return Err(format!("unknown unit `{}`, use k, M or G", unit)),
+    };
+    let rate = (number * multiplier) as u64;
+    if rate == 0 {
+        return Err("the rate can't be zero".to_string());
+    }
+    Ok(rate)
+}
+
+/// A remote path that doesn't exist or has the wrong type
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub struct NotFound(pub String);
+
+/// More of the space is used than `quota --warn-at` allows
+#[derive(Debug, thiserror::Error)]
+#[error("{used:.1}% of the space is used, above the {threshold}% warning threshold")]
+pub struct QuotaWarning {
+    pub used: f64,
+    pub threshold: f64,
+}
+
+/// The operation was stopped by the user
+#[derive(Debug, thiserror::Error)]
+#[error("Interrupted")]
+pub struct Interrupted;
+
+/// Maps an error to the process exit code, by its library error kind where it has one
+pub fn exit_code(error: &anyhow::Error) -> i32 {
+    if let Some(auth) = error.downcast_ref::<AuthError>() {
+        return auth.exit_code();
+    }
+    if error.downcast_ref::<Interrupted>().is_some() {
+        return EXIT_CANCELLED;
+    }
+    if error.downcast_ref::<QuotaWarning>().is_some() {
+        return EXIT_QUOTA_WARNING;
+    }
+    if matches!(error.downcast_ref::<IndexError>(), Some(IndexError::WrongPassword(_))) {
+        return EXIT_AUTH_REJECTED;
+    }
+    if error.downcast_ref::<NotFound>().is_some()
+        || matches!(error.downcast_ref::<ConfigError>(), Some(ConfigError::NoSuchProfile(_)))
+    {
+        return EXIT_NOT_FOUND;
+    }
+    if let Some(io) = error.downcast_ref::<std::io::Error>()
+        && io.kind() == std::io::ErrorKind::NotFound
+    {
+        return EXIT_NOT_FOUND;
+    }
+
+    let kind = error
+        .downcast_ref::<NodeError>()
+        .map(NodeError::kind)
+        .or_else(|| error.downcast_ref::<SessionError>().map(SessionError::kind));
+
+    match kind {
+        Some(SdkErrorKind::Authentication | SdkErrorKind::PermissionDenied) => EXIT_AUTH_REJECTED,
+        Some(SdkErrorKind::NotFound) => EXIT_NOT_FOUND,
+        Some(SdkErrorKind::Network) => EXIT_NETWORK,
+        Some(SdkErrorKind::Cancelled) => EXIT_CANCELLED,
+        _ => EXIT_FAILURE,
+    }
+}
+
+/// Checks if the error came from a call to the native SDK, so its version belongs in the report
+pub fn from_sdk(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        cause.is::<SessionError>()
+            || cause.is::<DriveError>()
+            || cause.is::<NodeError>()
+            || cause.is::<DownloadError>()
+            || cause.is::<UploadError>()
+    })
+}
+
+/// Where the native SDK was looked for, when the error is that it couldn't be loaded
+pub fn load_diagnostics(error: &anyhow::Error) -> Option<&LoadDiagnostics> {
+    error
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<LoadError>())
+        .and_then(LoadError::diagnostics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy_sessions_and_indexes_move_to_the_profile_directory() {
+        let dir = std::env::temp_dir().join(format!("proton-drive-paths-{}", std::process::id()));
+        let paths = Paths {
+            config: dir.join("config.toml"),
+            legacy_cfg: dir.join("legacy").join(".cfg"),
+            session: dir.join("profile").join("session_info.bin"),
+            index: dir.join("profile").join("index.db"),
+            throughput: dir.join("profile").join("throughput.json"),
+        };
+        fs::create_dir_all(dir.join("legacy")).unwrap();
+        fs::write(dir.join("legacy").join("session_info.bin"), "session").unwrap();
+        fs::write(dir.join("legacy").join("index.db"), "index").unwrap();
+        fs::write(dir.join("legacy").join("index.db-wal"), "wal").unwrap();
+
+        let moved: Vec<PathBuf> = paths.adopt_legacy_files().into_iter().map(|(_, to)| to).collect();
+        assert_eq!(moved, [paths.session.clone(), paths.index.clone(), dir.join("profile").join("index.db-wal")]);
+        assert_eq!(fs::read_to_string(&paths.index).unwrap(), "index");
+
+        fs::write(dir.join("legacy").join("session_info.bin"), "older session").unwrap();
+        assert!(paths.adopt_legacy_files().is_empty());
+        assert_eq!(fs::read_to_string(&paths.session).unwrap(), "session");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn intervals_take_an_optional_unit() {
+        assert_eq!(parse_interval("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_interval("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_interval("5m"), Ok(Duration::from_secs(300)));
+        assert_eq!(parse_interval("1h"), Ok(Duration::from_secs(3600)));
+        assert!(parse_interval("0").is_err());
+        assert!(parse_interval("5d").is_err());
+        assert!(parse_interval("m").is_err());
+    }
+
+    #[test]
+    fn percentages_take_an_optional_sign() {
+        assert_eq!(parse_percent("90%"), Ok(90.0));
+        assert_eq!(parse_percent("99.5"), Ok(99.5));
+        assert!(parse_percent("0%").is_err());
+        assert!(parse_percent("120%").is_err());
+        assert!(parse_percent("most").is_err());
+    }
+
+    #[test]
+    fn sdk_errors_are_found_behind_context() {
+        let error = anyhow::Error::from(SessionError::OperationFailed(proton_sdk_rs::errors::SdkErrorDetails::from_code(8002))).context("Logging in failed");
+        assert!(from_sdk(&error));
+        assert!(!from_sdk(&anyhow::anyhow!("The index is locked")));
+    }
+
+    #[test]
+    fn load_diagnostics_are_found_behind_sdk_errors() {
+        let diagnostics = LoadDiagnostics {
+            attempts: vec![("./libproton_drive_sdk.so".into(), "not found".to_string())],
+            ..Default::default()
+        };
+        let load_error = LoadError::Failed { library: "libproton_drive_sdk.so".to_string(), diagnostics: diagnostics.clone() };
+        let error = anyhow::Error::from(SessionError::SdkError(load_error.into())).context("Logging in failed");
+        assert_eq!(load_diagnostics(&error), Some(&diagnostics));
+        assert!(load_diagnostics(&anyhow::anyhow!("The index is locked")).is_none());
+    }
+
+    #[test]
+    fn local_commands_run_without_the_sdk() {
+        let uses_sdk = |args: &[&str]| Cli::try_parse_from([&["proton-drive"], args].concat()).unwrap().command.uses_sdk();
+        assert!(!uses_sdk(&["search", "beach"]));
+        assert!(!uses_sdk(&["index", "export"]));
+        assert!(!uses_sdk(&["profile", "list"]));
+        assert!(uses_sdk(&["index"]));
+        assert!(uses_sdk(&["ls", "/"]));
+    }
+
+    #[test]
+    fn rates_take_binary_units() {
+        assert_eq!(parse_rate("500k"), Ok(500 * 1024));
+        assert_eq!(parse_rate("10M"), Ok(10 * 1024 * 1024));
+        assert_eq!(parse_rate("1.5m"), Ok(3 * 512 * 1024));
+        assert_eq!(parse_rate("2048"), Ok(2048));
+        assert!(parse_rate("0k").is_err());
+        assert!(parse_rate("10x").is_err());
+        assert!(parse_rate("fast").is_err());
+    }
+}
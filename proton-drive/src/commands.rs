@@ -0,0 +1,1021 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::UNIX_EPOCH;
+
+use log::{debug, info, trace, warn};
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use proton_sdk_rs::{
+    downloads::FileDownloadRequestBuilder,
+    drive::{DriveClient, DriveClientBuilder},
+    nodes::{NodeIdentityExt, NodeTypeExt, RemotePath},
+    observability::OptionalObservability,
+    sessions::FileSessionStore,
+    ClientId, FileNode, FileUploadRequest,
+    NodeIdentity, NodeType, OperationIdentifier, ProtonDriveClientCreateRequest, Share,
+};
+use proton_sdk_sys::protobufs::human_bytes;
+use serde::Serialize;
+use tokio::time::Instant;
+
+use crate::auth::{self, AuthOptions};
+use crate::bandwidth;
+use crate::cli::{Interrupted, NotFound, Paths, QuotaWarning};
+use crate::config::{self, Config};
+use crate::credentials::CredentialStore;
+use crate::daemon;
+use crate::dry_run;
+use crate::index::{self, IndexError, IndexedNode, Subtree};
+use crate::mirror;
+use crate::quota::QuotaReport;
+use crate::remote::{self, DriveOps};
+use crate::shutdown;
+use crate::stat::{ChildCounts, Source, Stat};
+use crate::upload;
+use crate::verify;
+use crate::watch;
+
+/// An authenticated Drive client and the root of the main share
+///
+/// The commands run against the SDK through [`DriveClient`], the tests against
+/// an in-memory drive.
+pub struct Context<D = DriveClient> {
+    client: Arc<D>,
+    // outlives the client, which was created with its handle
+    observability: OptionalObservability,
+    share: Share,
+    root: NodeIdentity,
+}
+
+impl Context {
+    pub async fn new(options: AuthOptions) -> anyhow::Result<Self> {
+        let telemetry = options.telemetry;
+        let session_file = options.session_file.clone();
+        let session = auth::create_new_session(options).await?;
+
+        let obs = if telemetry {
+            info!("Creating observability");
+            OptionalObservability::enabled_with_api(session.api().clone(), session.handle())?
+        } else {
+            debug!("Telemetry is turned off");
+            OptionalObservability::disabled()
+        };
+        trace!("Observability handle: {:?}", obs.handle());
+
+        info!("Creating Drive client");
+        let create_request = ProtonDriveClientCreateRequest {
+            client_id: Some(ClientId {
+                value: "proton-sdk-rs".to_string(),
+            }),
+        };
+        trace!("Request: {:?}", create_request);
+
+        let client = DriveClientBuilder::new(session)
+            .with_observability(obs.handle())
+            .with_request(create_request)
+            .build()?;
+        debug!("Drive client created {:?}", client.handle());
+
+        let (share, root) = remote::main_share(&client).await?;
+
+        let client = Arc::new(client);
+        shutdown::register_client(&client, session_file);
+        Ok(Self {
+            client,
+            observability: obs,
+            share,
+            root,
+        })
+    }
+
+    /// Sends the pending telemetry
+    pub async fn flush_observability(&self) -> anyhow::Result<()> {
+        self.observability.flush_if_enabled().await?;
+        Ok(())
+    }
+}
+
+impl<D: DriveOps> Context<D> {
+    /// A context over any drive, without telemetry
+    #[cfg(test)]
+    pub async fn with_drive(client: D) -> anyhow::Result<Self> {
+        let (share, root) = remote::main_share(&client).await?;
+        Ok(Self {
+            client: Arc::new(client),
+            observability: OptionalObservability::disabled(),
+            share,
+            root,
+        })
+    }
+
+    pub fn client(&self) -> &D {
+        &self.client
+    }
+
+    /// Identity of the root folder of the share
+    pub fn root(&self) -> &NodeIdentity {
+        &self.root
+    }
+
+    /// Lists a folder
+    pub async fn children(&self, folder: NodeIdentity) -> anyhow::Result<Vec<NodeType>> {
+        Ok(self.client.get_folder_children(folder).await?)
+    }
+
+    /// Finds the node at `path`, `None` for the root of the share
+    async fn resolve(&self, path: &RemotePath) -> anyhow::Result<(Option<NodeType>, NodeIdentity)> {
+        let mut current = (None, self.root.clone());
+
+        for (depth, name) in path.segments().iter().enumerate() {
+            if current.0.as_ref().is_some_and(|node: &NodeType| node.is_file()) {
+                let parent = RemotePath::from_segments(&path.segments()[..depth]);
+                return Err(NotFound(format!("{} is not a folder", parent)).into());
+            }
+
+            let children = self.client.get_folder_children(current.1.clone()).await?;
+            let Some(child) = children.into_iter().find(|child| node_name(child) == Some(name)) else {
+                return Err(NotFound(format!("{} doesn't exist", path)).into());
+            };
+
+            let identity = child.full_identity(&current.1)?;
+            current = (Some(child), identity);
+        }
+
+        Ok(current)
+    }
+
+    /// Finds the folder at `path`
+    pub async fn folder(&self, path: &RemotePath) -> anyhow::Result<NodeIdentity> {
+        match self.resolve(path).await? {
+            (Some(node), _) if !node.is_folder() => {
+                Err(NotFound(format!("{} is not a folder", path)).into())
+            }
+            (_, identity) => Ok(identity),
+        }
+    }
+
+    /// Looks up the file or folder at `path` on the drive, listing a folder's whole tree
+    async fn lookup(&self, path: &RemotePath) -> anyhow::Result<IndexedNode> {
+        let (node, identity) = self.resolve(path).await?;
+        if let Some(file) = node.as_ref().and_then(|node| node.as_file()) {
+            return Ok(IndexedNode::File(Box::new(file.clone())));
+        }
+
+        let mut subtree = Subtree::default();
+        let mut queue = VecDeque::from([(String::new(), identity)]);
+        while let Some((path, identity)) = queue.pop_front() {
+            for child in self.client.get_folder_children(identity.clone()).await? {
+                let Some(name) = node_name(&child) else {
+                    continue;
+                };
+                let relative = if path.is_empty() { name.clone() } else { format!("{}/{}", path, name) };
+                if let Some(file) = child.as_file() {
+                    subtree.files.push((relative, file.clone()));
+                } else {
+                    queue.push_back((relative.clone(), child.full_identity(&identity)?));
+                    subtree.folders.push(relative);
+                }
+            }
+        }
+        Ok(IndexedNode::Folder(subtree))
+    }
+
+    /// Uploads a local file into a folder, as a new revision if the name is taken
+    ///
+    /// Returns once the upload is within the bandwidth limit.
+    pub async fn upload_file<F>(
+        &self,
+        local: &Path,
+        metadata: &fs::Metadata,
+        parent: NodeIdentity,
+        progress_callback: Option<F>,
+    ) -> anyhow::Result<FileNode>
+    where
+        F: Fn(f32) + Send + Sync + 'static,
+    {
+        let Some(name) = local.file_name().and_then(|name| name.to_str()) else {
+            anyhow::bail!("{} has no usable file name", local.display());
+        };
+
+        let source = std::path::absolute(local)?;
+        let started = Instant::now();
+        let request = FileUploadRequest {
+            share_metadata: Some(self.share.metadata()),
+            parent_folder_identity: Some(parent),
+            name: name.to_string(),
+            mime_type: mime_guess::from_path(local).first_or_octet_stream().to_string(),
+            source_file_path: source.to_string_lossy().into_owned(),
+            thumbnail: None,
+            last_modification_date: metadata.modified()?.duration_since(UNIX_EPOCH)?.as_secs() as i64,
+            operation_id: Some(OperationIdentifier::upload()),
+        };
+
+        let uploaded = self.client.upload(request, metadata.len() as i64, progress_callback).await?;
+        bandwidth::pace(dry_run::Direction::Up, metadata.len(), started).await;
+        Ok(uploaded)
+    }
+
+    /// Downloads the active revision of a file of the share to `target`, overwriting it
+    ///
+    /// Like uploads, returns once the download is within the bandwidth limit, see [`bandwidth::pace`].
+    pub async fn download_file<F>(
+        &self,
+        file: &FileNode,
+        target: &Path,
+        progress_callback: Option<F>,
+    ) -> anyhow::Result<()>
+    where
+        F: Fn(f32) + Send + Sync + 'static,
+    {
+        let started = Instant::now();
+        let identity = self.client.complete_identity(file, &self.root)?;
+        let request = FileDownloadRequestBuilder::for_file(file, identity, target).build();
+
+        self.client.download(request, progress_callback).await?;
+        let size = fs::metadata(target).map(|metadata| metadata.len()).unwrap_or_default();
+        bandwidth::pace(dry_run::Direction::Down, size, started).await;
+        Ok(())
+    }
+}
+
+fn node_name(node: &NodeType) -> Option<&String> {
+    node.as_folder()
+        .map(|folder| &folder.name)
+        .or_else(|| node.as_file().map(|file| &file.name))
+}
+
+fn print_json<T: Serialize>(value: &T) -> anyhow::Result<()> {
+    let mut stdout = io::stdout().lock();
+    serde_json::to_writer_pretty(&mut stdout, value)?;
+    writeln!(stdout)?;
+    Ok(())
+}
+
+pub fn print_progress(action: &'static str) -> impl Fn(f32) + Send + Sync + 'static {
+    move |progress| {
+        eprint!("\r{} {:.1}%", action, progress * 100.0);
+        if progress >= 1.0 {
+            eprintln!();
+        }
+    }
+}
+
+pub async fn login(options: AuthOptions) -> anyhow::Result<()> {
+    let session = auth::create_new_session(options).await?;
+    println!("Logged in as {}", session.info()?.username);
+    Ok(())
+}
+
+pub fn logout(paths: &Paths, credentials: &CredentialStore, username: Option<String>) -> anyhow::Result<()> {
+    let store = FileSessionStore::new(&paths.session);
+    let username = username.or_else(|| store.load().ok().flatten().map(|info| info.username));
+
+    store.clear()?;
+    if let Some(username) = &username {
+        credentials.forget(username);
+    }
+
+    match username {
+        Some(username) => println!("Logged out {}", username),
+        None => println!("Logged out"),
+    }
+    Ok(())
+}
+
+/// A listed node, as printed by `ls --json`
+#[derive(Debug, Serialize)]
+struct Entry<'a> {
+    name: &'a str,
+    kind: &'static str,
+    size: Option<i64>,
+    /// Creation time of the active revision, in seconds since the epoch
+    modified: Option<i64>,
+}
+
+impl<'a> Entry<'a> {
+    fn new(node: &'a NodeType) -> Option<Self> {
+        if let Some(folder) = node.as_folder() {
+            return Some(Self { name: &folder.name, kind: "folder", size: None, modified: None });
+        }
+
+        let file = node.as_file()?;
+        let revision = file.active_revision.as_ref();
+        Some(Self {
+            name: &file.name,
+            kind: "file",
+            size: revision.and_then(|revision| revision.size),
+            modified: revision.map(|revision| revision.creation_time),
+        })
+    }
+}
+
+pub async fn ls(options: AuthOptions, path: &RemotePath, json: bool) -> anyhow::Result<()> {
+    let context = Context::new(options).await?;
+    let folder = context.folder(path).await?;
+    let children = context.client.get_folder_children(folder).await?;
+
+    let mut entries: Vec<Entry> = children.iter().filter_map(Entry::new).collect();
+    entries.sort_by(|a, b| (a.kind, a.name).cmp(&(b.kind, b.name)));
+
+    if json {
+        return print_json(&entries);
+    }
+
+    for entry in entries {
+        match entry.kind {
+            "folder" => println!("{:>12}  {}/", "", entry.name),
+            _ => println!("{:>12}  {}", entry.size.unwrap_or_default(), entry.name),
+        }
+    }
+    Ok(())
+}
+
+/// Opens the index, `None` when there is no complete index to trust
+fn complete_index(options: &AuthOptions, paths: &Paths) -> anyhow::Result<Option<Pool<SqliteConnectionManager>>> {
+    if !paths.index.exists() {
+        return Ok(None);
+    }
+    let pool = index::open(&paths.index, auth::index_key(options))?;
+    Ok(index::is_complete(&pool)?.then_some(pool))
+}
+
+fn not_indexed(remote: &RemotePath) -> anyhow::Error {
+    NotFound(format!("{} isn't in the index, run `proton-drive index` to refresh it", remote)).into()
+}
+
+/// Looks a remote path up in the index, `None` when there is no complete index to trust
+fn lookup_indexed(options: &AuthOptions, paths: &Paths, remote: &RemotePath) -> anyhow::Result<Option<IndexedNode>> {
+    let Some(pool) = complete_index(options, paths)? else {
+        return Ok(None);
+    };
+    match index::lookup(&pool, &remote.segments().join("/"))? {
+        Some(node) => Ok(Some(node)),
+        None => Err(not_indexed(remote)),
+    }
+}
+
+/// Describes the node at `remote` from the index
+fn indexed_stat(context: &Context, pool: &Pool<SqliteConnectionManager>, remote: &RemotePath) -> anyhow::Result<Stat> {
+    let path = remote.segments().join("/");
+    match index::lookup(pool, &path)?.ok_or_else(|| not_indexed(remote))? {
+        IndexedNode::File(file) => {
+            Ok(Stat::file(remote, &file, &file.full_identity(context.root())?, Source::Index))
+        }
+        IndexedNode::Folder(subtree) => {
+            let folder = index::folder_node(pool, &path)?;
+            let identity = match &folder {
+                Some(folder) => folder.full_identity(context.root())?,
+                None => context.root().clone(),
+            };
+            let children = ChildCounts::indexed(&subtree);
+            Ok(Stat::folder(remote, folder.as_ref(), &identity, children, Source::Index))
+        }
+    }
+}
+
+/// Describes the node at `remote`, listing a folder's children
+async fn live_stat(context: &Context, remote: &RemotePath) -> anyhow::Result<Stat> {
+    let (node, identity) = context.resolve(remote).await?;
+    if let Some(file) = node.as_ref().and_then(|node| node.as_file()) {
+        return Ok(Stat::file(remote, file, &identity, Source::Live));
+    }
+
+    let children = ChildCounts::listed(&context.children(identity.clone()).await?);
+    let folder = node.as_ref().and_then(|node| node.as_folder());
+    Ok(Stat::folder(remote, folder, &identity, children, Source::Live))
+}
+
+pub async fn stat(options: AuthOptions, paths: &Paths, target: &str, json: bool, live: bool) -> anyhow::Result<()> {
+    let index = if live { None } else { complete_index(&options, paths)? };
+
+    // a compact identity is looked up by its node id, anything else is a path
+    let node_id = target.parse::<NodeIdentity>().ok().and_then(|identity| identity.node_id);
+    let remote = match node_id {
+        Some(node_id) => {
+            if !paths.index.exists() {
+                return Err(NotFound("Node identities are looked up in the index, run `proton-drive index` first".to_string()).into());
+            }
+            let pool = match &index {
+                Some(pool) => pool.clone(),
+                None => index::open(&paths.index, auth::index_key(&options))?,
+            };
+            let Some(path) = index::path_of(&pool, &node_id.value)? else {
+                return Err(NotFound(format!("{} isn't in the index, run `proton-drive index` to refresh it", target)).into());
+            };
+            RemotePath::from_segments(path.split('/'))
+        }
+        None => target.parse()?,
+    };
+
+    let context = Context::new(options).await?;
+    let stat = match &index {
+        Some(pool) => indexed_stat(&context, pool, &remote)?,
+        None => live_stat(&context, &remote).await?,
+    };
+
+    if json {
+        return print_json(&stat);
+    }
+    Ok(stat.print()?)
+}
+
+pub async fn download(
+    options: AuthOptions,
+    paths: &Paths,
+    remote: &RemotePath,
+    local: &Path,
+    workers: usize,
+) -> anyhow::Result<()> {
+    let indexed = lookup_indexed(&options, paths, remote)?;
+    let context = Context::new(options).await?;
+    let node = match indexed {
+        Some(node) => node,
+        None => {
+            warn!("No complete index, looking {} up on the drive instead", remote);
+            context.lookup(remote).await?
+        }
+    };
+
+    let subtree = match node {
+        IndexedNode::File(file) => {
+            let target = if local.is_dir() { local.join(&file.name) } else { local.to_path_buf() };
+            let target = std::path::absolute(target)?;
+            context
+                .download_file(&file, &target, Some(print_progress("Downloading")))
+                .await?;
+
+            println!("Downloaded {} to {}", remote, target.display());
+            return Ok(());
+        }
+        IndexedNode::Folder(subtree) => subtree,
+    };
+
+    let target = match remote.segments().last() {
+        Some(name) if local.is_dir() => local.join(name),
+        _ => local.to_path_buf(),
+    };
+    let mirror_options = mirror::MirrorOptions {
+        remote: remote.clone(),
+        local_root: target,
+        delete_local: false,
+        dry_run: false,
+        workers,
+    };
+    let plan = mirror::plan(subtree, &mirror_options)?;
+    let shutdown = shutdown::flag("Stopping after the files being downloaded...");
+    let report = mirror::apply(&context, plan, &mirror_options, &shutdown).await;
+
+    for (path, e) in &report.failed {
+        eprintln!("Failed to download {}: {:#}", path, e);
+    }
+    println!(
+        "Downloaded {} to {}: {} downloaded, {} already there, {} failed",
+        remote,
+        mirror_options.local_root.display(),
+        report.downloaded,
+        report.skipped,
+        report.failed.len()
+    );
+
+    if report.interrupted {
+        return Err(Interrupted.into());
+    }
+    if !report.failed.is_empty() {
+        anyhow::bail!("{} paths failed to download", report.failed.len());
+    }
+    Ok(())
+}
+
+pub async fn upload(options: AuthOptions, paths: &Paths, upload_options: upload::UploadOptions) -> anyhow::Result<()> {
+    let metadata = fs::metadata(&upload_options.local)?;
+    if !metadata.is_file() && !metadata.is_dir() {
+        return Err(NotFound(format!("{} is not a file nor a folder", upload_options.local.display())).into());
+    }
+
+    // uploads are added to an existing index, none is created for them
+    let pool = if paths.index.exists() {
+        Some(index::open(&paths.index, auth::index_key(&options))?)
+    } else {
+        None
+    };
+    let context = Context::new(options).await?;
+    let shutdown = shutdown::flag("Stopping after the file being uploaded...");
+    let report = upload::run(&context, pool.as_ref(), &upload_options, &shutdown).await?;
+
+    for (path, e) in &report.failed {
+        eprintln!("Failed to upload {}: {:#}", path, e);
+    }
+    if metadata.is_dir() {
+        println!(
+            "{} uploaded, {} skipped, {} failed",
+            report.uploaded,
+            report.skipped,
+            report.failed.len()
+        );
+    }
+    if report.interrupted {
+        return Err(Interrupted.into());
+    }
+    if !report.failed.is_empty() {
+        anyhow::bail!("{} paths failed to upload", report.failed.len());
+    }
+    Ok(())
+}
+
+pub async fn watch(
+    options: AuthOptions,
+    paths: &Paths,
+    watch_options: watch::WatchOptions,
+    json: bool,
+) -> anyhow::Result<()> {
+    if !watch_options.local_root.is_dir() {
+        return Err(NotFound(format!("{} is not a folder", watch_options.local_root.display())).into());
+    }
+
+    let pool = index::open(&paths.index, auth::index_key(&options))?;
+    if watch_options.dry_run {
+        let entries = watch::plan(&pool, watch_options)?;
+        dry_run::print(&entries, json)?;
+        if !json {
+            println!("{} changes to sync", entries.len());
+        }
+        return Ok(());
+    }
+    let context = Context::new(options).await?;
+    context.folder(&watch_options.remote).await?;
+
+    watch::run(&context, &pool, watch_options).await
+}
+
+pub async fn mirror(
+    options: AuthOptions,
+    paths: &Paths,
+    mirror_options: mirror::MirrorOptions,
+    json: bool,
+) -> anyhow::Result<()> {
+    if !paths.index.exists() {
+        return Err(NotFound("No index yet, run `proton-drive index` first".to_string()).into());
+    }
+
+    let pool = index::open(&paths.index, auth::index_key(&options))?;
+    let Some(subtree) = index::subtree(&pool, &mirror_options.remote.segments().join("/"))? else {
+        return Err(NotFound(format!(
+            "{} isn't an indexed folder, run `proton-drive index` to refresh the index",
+            mirror_options.remote
+        ))
+        .into());
+    };
+    let plan = mirror::plan(subtree, &mirror_options)?;
+
+    if mirror_options.dry_run {
+        dry_run::print(&plan.entries(), json)?;
+        if json {
+            return Ok(());
+        }
+        println!(
+            "{} to move, {} to download, {} up to date, {} to delete",
+            plan.renames.len(),
+            plan.downloads.len(),
+            plan.skipped,
+            plan.deletions.len()
+        );
+        return Ok(());
+    }
+
+    let context = Context::new(options).await?;
+    let shutdown = shutdown::flag("Stopping after the files being downloaded...");
+    let report = mirror::apply(&context, plan, &mirror_options, &shutdown).await;
+
+    for (path, e) in &report.failed {
+        eprintln!("Failed to mirror {}: {:#}", path, e);
+    }
+    println!(
+        "{} moved, {} downloaded, {} skipped, {} deleted, {} failed",
+        report.moved,
+        report.downloaded,
+        report.skipped,
+        report.deleted,
+        report.failed.len()
+    );
+
+    if report.interrupted {
+        eprintln!("Mirror interrupted, run it again to resume");
+        return Err(Interrupted.into());
+    }
+    if !report.failed.is_empty() {
+        anyhow::bail!("{} paths failed to mirror", report.failed.len());
+    }
+    Ok(())
+}
+
+pub async fn verify(options: AuthOptions, paths: &Paths, verify_options: verify::VerifyOptions) -> anyhow::Result<()> {
+    if !verify_options.local_root.is_dir() {
+        return Err(NotFound(format!("{} is not a folder", verify_options.local_root.display())).into());
+    }
+    if !paths.index.exists() {
+        return Err(NotFound("No index yet, run `proton-drive index` first".to_string()).into());
+    }
+
+    let pool = index::open(&paths.index, auth::index_key(&options))?;
+    let Some(subtree) = index::subtree(&pool, &verify_options.remote.segments().join("/"))? else {
+        return Err(NotFound(format!(
+            "{} isn't an indexed folder, run `proton-drive index` to refresh the index",
+            verify_options.remote
+        ))
+        .into());
+    };
+
+    let shutdown = shutdown::flag("Stopping the verification...");
+    let report = tokio::task::spawn_blocking({
+        let local_root = verify_options.local_root.clone();
+        let shutdown = shutdown.clone();
+        move || verify::run(subtree, &local_root, &shutdown, print_progress("Hashing"))
+    })
+    .await??;
+
+    for mismatch in &report.mismatches {
+        let status = match mismatch.status {
+            verify::Status::Missing => "Missing",
+            verify::Status::SizeMismatch => "Size mismatch",
+            verify::Status::HashMismatch => "Hash mismatch",
+        };
+        println!("{}: {}", status, mismatch.relative);
+    }
+    println!(
+        "{} OK, {} missing, {} size mismatch, {} hash mismatch",
+        report.ok,
+        report.count(verify::Status::Missing),
+        report.count(verify::Status::SizeMismatch),
+        report.count(verify::Status::HashMismatch)
+    );
+    if report.unhashed > 0 {
+        println!("{} of the OK files were only checked by size, their revision has no digests", report.unhashed);
+    }
+
+    if report.interrupted {
+        return Err(Interrupted.into());
+    }
+    if report.mismatches.is_empty() {
+        return Ok(());
+    }
+    if !verify_options.repair {
+        anyhow::bail!("{} files don't match, run with --repair to download them again", report.mismatches.len());
+    }
+
+    let context = Context::new(options).await?;
+    let mirror_options = mirror::MirrorOptions {
+        remote: verify_options.remote,
+        local_root: verify_options.local_root,
+        delete_local: false,
+        dry_run: false,
+        workers: verify_options.workers,
+    };
+    let bad = report.mismatches.into_iter().map(|mismatch| (mismatch.relative, mismatch.file)).collect();
+    let plan = mirror::redownloads(bad, &mirror_options);
+    shutdown::flag("Stopping after the files being downloaded...");
+    let repaired = mirror::apply(&context, plan, &mirror_options, &shutdown).await;
+
+    for (path, e) in &repaired.failed {
+        eprintln!("Failed to download {}: {:#}", path, e);
+    }
+    println!("Downloaded {} files again", repaired.downloaded);
+    if repaired.interrupted {
+        return Err(Interrupted.into());
+    }
+    if !repaired.failed.is_empty() {
+        anyhow::bail!("{} files couldn't be repaired", repaired.failed.len());
+    }
+    Ok(())
+}
+
+pub async fn daemon(
+    options: AuthOptions,
+    paths: &Paths,
+    daemon_options: daemon::DaemonOptions,
+    json: bool,
+) -> anyhow::Result<()> {
+    let pool = index::open(&paths.index, auth::index_key(&options))?;
+    if daemon_options.dry_run {
+        let context = Context::new(options).await?;
+        let entries = daemon::plan(&context, &pool, &daemon_options).await?;
+        dry_run::print(&entries, json)?;
+        if !json {
+            println!("{} changes to make", entries.len());
+        }
+        return Ok(());
+    }
+    daemon::run(options, &pool, daemon_options).await
+}
+
+pub async fn index(
+    options: AuthOptions,
+    paths: &Paths,
+    watch: bool,
+    workers: usize,
+    force_reindex: bool,
+) -> anyhow::Result<()> {
+    let pool = index::open(&paths.index, auth::index_key(&options))?;
+    let context = Context::new(options).await?;
+
+    if force_reindex {
+        index::reset(&pool)?;
+    }
+    if index::take_legacy_marker(&paths.legacy_cfg)? && !force_reindex {
+        index::mark_complete(&pool)?;
+    }
+
+    let shutdown = shutdown::flag("Stopping after the folders being listed...");
+
+    if !index::is_complete(&pool)? {
+        let report = index::index(context.client(), &context.root, &pool, workers, &shutdown, print_index_progress).await;
+        eprintln!();
+        if report?.interrupted {
+            eprintln!("Indexing interrupted, run `proton-drive index` again to resume");
+            return Err(Interrupted.into());
+        }
+        check_failed_folders(&pool)?;
+        println!("Ding! Initial indexing is done");
+    }
+
+    loop {
+        let report = index::refresh(context.client(), &context.root, &pool, workers, &shutdown, |progress| {
+            eprint!(
+                "\rScanned {} folders, {} to go, {} new folders, {} new files, {} deleted",
+                progress.folders_scanned,
+                progress.folders_pending,
+                progress.new_folders,
+                progress.new_files,
+                progress.deleted
+            );
+        })
+        .await?;
+        eprintln!();
+
+        for (path, e) in &report.failures {
+            eprintln!("Failed to refresh /{}: {:#}", path, e);
+        }
+        if report.interrupted || shutdown.load(Ordering::Relaxed) {
+            return Err(Interrupted.into());
+        }
+        if !watch {
+            return check_failed_folders(&pool);
+        }
+    }
+}
+
+fn print_index_progress(progress: &index::RefreshProgress) {
+    eprint!(
+        "\rListed {} folders, {} to go, indexed {} new folders and {} new files, {} failed",
+        progress.folders_scanned, progress.folders_pending, progress.new_folders, progress.new_files, progress.failures
+    );
+}
+
+/// Lists the folders that still fail, as an error when there are any
+fn check_failed_folders(pool: &Pool<SqliteConnectionManager>) -> anyhow::Result<()> {
+    let failed = index::failed_folders(pool)?;
+    if failed.is_empty() {
+        return Ok(());
+    }
+    eprintln!("{} folders couldn't be listed:", failed.len());
+    for folder in &failed {
+        eprintln!("  /{}: {}", folder.path, folder.error);
+    }
+    Err(IndexError::FailedFolders(failed.len()).into())
+}
+
+pub async fn index_retry_failed(options: AuthOptions, paths: &Paths, workers: usize) -> anyhow::Result<()> {
+    if !paths.index.exists() {
+        return Err(NotFound("No index yet, run `proton-drive index` first".to_string()).into());
+    }
+    let pool = index::open(&paths.index, auth::index_key(&options))?;
+    if index::failed_folders(&pool)?.is_empty() {
+        println!("No failed folders to retry");
+        return Ok(());
+    }
+
+    let context = Context::new(options).await?;
+    let shutdown = shutdown::flag("Stopping after the folders being listed...");
+    let report = index::retry_failed(context.client(), &pool, workers, &shutdown, print_index_progress).await;
+    eprintln!();
+    if report?.interrupted {
+        eprintln!("Retry interrupted, run `proton-drive index --retry-failed` again to resume");
+        return Err(Interrupted.into());
+    }
+    check_failed_folders(&pool)?;
+    if index::is_complete(&pool)? {
+        println!("Every failed folder is indexed now");
+    } else {
+        println!("The failed folders are indexed, run `proton-drive index` to finish the rest");
+    }
+    Ok(())
+}
+
+pub fn index_export(
+    options: &AuthOptions,
+    paths: &Paths,
+    format: index::ExportFormat,
+    output: Option<&Path>,
+) -> anyhow::Result<()> {
+    if !paths.index.exists() {
+        return Err(NotFound("No index yet, run `proton-drive index` first".to_string()).into());
+    }
+    let pool = index::open(&paths.index, auth::index_key(options))?;
+
+    let count = match output {
+        Some(output) => index::export(&pool, format, io::BufWriter::new(fs::File::create(output)?))?,
+        None => index::export(&pool, format, io::stdout().lock())?,
+    };
+    info!("Exported {} rows", count);
+    Ok(())
+}
+
+pub fn index_import(options: &AuthOptions, paths: &Paths, input: &Path) -> anyhow::Result<()> {
+    let file = io::BufReader::new(fs::File::open(input)?);
+    let pool = index::open(&paths.index, auth::index_key(options))?;
+    let count = index::import(&pool, file)?;
+    println!("Imported {} rows into {}", count, paths.index.display());
+    Ok(())
+}
+
+pub fn search(options: &AuthOptions, paths: &Paths, query: &index::SearchQuery, json: bool) -> anyhow::Result<()> {
+    if !paths.index.exists() {
+        return Err(NotFound("No index yet, run `proton-drive index` first".to_string()).into());
+    }
+
+    let pool = index::open(&paths.index, auth::index_key(options))?;
+    let hits = index::search(&pool, query)?;
+
+    if json {
+        return print_json(&hits);
+    }
+    for hit in hits {
+        let deleted = if hit.deleted_at.is_some() { "  (deleted)" } else { "" };
+        match hit.kind {
+            "folder" => println!("/{}/{}", hit.path, deleted),
+            _ => println!("/{}{}", hit.path, deleted),
+        }
+    }
+    Ok(())
+}
+
+/// The state printed by `status --json`
+#[derive(Debug, Serialize)]
+struct Status {
+    logged_in: bool,
+    username: Option<String>,
+    session_file: String,
+    index_file: String,
+    indexed: bool,
+    index: Option<index::IndexStats>,
+    /// Limits in force now, from the `[bandwidth]` settings and their schedule
+    limits: bandwidth::Limits,
+    /// Rates the last transfers averaged out to
+    throughput: bandwidth::Throughput,
+}
+
+pub fn status(options: &AuthOptions, paths: &Paths, json: bool) -> anyhow::Result<()> {
+    let session = FileSessionStore::new(&paths.session).load()?;
+    let (indexed, stats) = if paths.index.exists() {
+        let pool = index::open(&paths.index, auth::index_key(options))?;
+        (index::is_complete(&pool)?, Some(index::stats(&pool)?))
+    } else {
+        (false, None)
+    };
+
+    let status = Status {
+        logged_in: session.is_some(),
+        username: session.map(|info| info.username),
+        session_file: paths.session.display().to_string(),
+        index_file: paths.index.display().to_string(),
+        indexed,
+        index: stats,
+        limits: bandwidth::limits(),
+        throughput: bandwidth::load(&paths.throughput)?,
+    };
+
+    if json {
+        return print_json(&status);
+    }
+
+    match &status.username {
+        Some(username) => println!("Logged in as {}", username),
+        None => println!("Not logged in"),
+    }
+    match &status.index {
+        Some(stats) if status.indexed => {
+            println!("Index: {} folders, {} files in {}", stats.folders, stats.files, status.index_file)
+        }
+        Some(stats) => println!(
+            "Index: incomplete, {} folders, {} files in {}",
+            stats.folders, stats.files, status.index_file
+        ),
+        None => println!("Index: none yet"),
+    }
+    println!("Limits: {}", status.limits);
+    for (direction, measured) in [("down", &status.throughput.down), ("up", &status.throughput.up)] {
+        if let Some(measured) = measured {
+            let at = chrono::DateTime::from_timestamp(measured.at, 0).unwrap_or_default();
+            println!(
+                "Throughput {}: {}/s over {}, last at {}",
+                direction,
+                human_bytes(measured.rate() as i64),
+                human_bytes(measured.bytes as i64),
+                at.format("%Y-%m-%d %H:%M UTC")
+            );
+        }
+    }
+    Ok(())
+}
+
+pub async fn quota(options: AuthOptions, paths: &Paths, top: usize, warn_at: Option<f64>, json: bool) -> anyhow::Result<()> {
+    let (usage, complete) = if paths.index.exists() {
+        let pool = index::open(&paths.index, auth::index_key(&options))?;
+        (Some(index::usage(&pool, top)?), index::is_complete(&pool)?)
+    } else {
+        (None, false)
+    };
+
+    let context = Context::new(options).await?;
+    let volumes = context.client().get_volumes().await?;
+    let report = QuotaReport::new(&volumes, context.root().volume_id.as_ref(), usage, complete);
+
+    if json {
+        print_json(&report)?;
+    } else {
+        report.print()?;
+    }
+
+    let Some(threshold) = warn_at else {
+        return Ok(());
+    };
+    match report.percent {
+        Some(used) if used > threshold => Err(QuotaWarning { used, threshold }.into()),
+        Some(_) => Ok(()),
+        None => anyhow::bail!("The used space isn't known without an index, --warn-at can't be checked"),
+    }
+}
+
+/// Profile name as the rest of the code takes it, `None` for the default one
+fn named_profile(name: &str) -> Option<&str> {
+    (name != config::DEFAULT_PROFILE).then_some(name)
+}
+
+pub fn profile_list(config_path: &Path, selected: Option<&str>) -> anyhow::Result<()> {
+    let config = Config::load(config_path)?;
+    let width = config.profile_names().iter().map(|name| name.len()).max().unwrap_or(0);
+    for name in config.profile_names() {
+        let profile = named_profile(name);
+        let paths = Paths::for_profile(config_path.to_path_buf(), profile);
+        let session = FileSessionStore::new(&paths.session).load().ok().flatten();
+        let account = match (session, config.profile(profile).username) {
+            (Some(info), _) => format!("logged in as {}", info.username),
+            (None, Some(username)) => format!("{}, not logged in", username),
+            (None, None) => "not logged in".to_string(),
+        };
+        let marker = if profile == selected { '*' } else { ' ' };
+        println!("{} {:<width$}  {}", marker, name, account, width = width);
+    }
+    Ok(())
+}
+
+pub fn profile_add(config_path: &Path, name: &str, username: Option<String>) -> anyhow::Result<()> {
+    let mut config = Config::load(config_path)?;
+    config.add_profile(name, username)?;
+    config.save(config_path)?;
+    println!("Added profile {}, select it with --profile {}", name, name);
+    Ok(())
+}
+
+/// Removes a profile's settings, then its session, index and keyring entries
+///
+/// An index kept elsewhere through the `index_db` setting is left alone.
+pub fn profile_remove(config_path: &Path, name: &str, keyring: bool) -> anyhow::Result<()> {
+    if name == config::DEFAULT_PROFILE {
+        anyhow::bail!("The default profile can't be removed, `logout` clears its session and secrets");
+    }
+
+    let mut config = Config::load(config_path)?;
+    let profile = config.remove_profile(name)?;
+    config.save(config_path)?;
+
+    let paths = Paths::for_profile(config_path.to_path_buf(), Some(name));
+    let session = FileSessionStore::new(&paths.session).load().ok().flatten();
+    if let Some(username) = session.map(|info| info.username).or(profile.username) {
+        CredentialStore::new(keyring, Some(name)).forget(&username);
+    }
+    match fs::remove_dir_all(config::profile_dir(Some(name))) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    println!("Removed profile {}", name);
+    if let Some(index_db) = profile.index_db {
+        println!("Its index is kept at {}", index_db.display());
+    }
+    Ok(())
+}
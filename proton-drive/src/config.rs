@@ -0,0 +1,539 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use chrono::NaiveTime;
+use directories::ProjectDirs;
+use log::LevelFilter;
+use proton_sdk_rs::nodes::RemotePath;
+use serde::{Deserialize, Serialize};
+
+use crate::bandwidth::{parse_time, Limits};
+use crate::daemon::Job;
+
+/// Name of the settings file in the platform config directory
+pub const CONFIG_FILE: &str = "config.toml";
+/// Name of the profile whose settings are at the top level of `config.toml`
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// Legacy settings key of the account
+const LEGACY_USERNAME_KEY: &str = "PROTON_USERNAME";
+/// Legacy settings key of a mirror job, `<remote-path> -> <local-dir>`
+const LEGACY_MIRROR_KEY: &str = "PROTON_MIRROR";
+/// Legacy settings key of a sync job, `<local-dir> -> <remote-path>`
+const LEGACY_SYNC_KEY: &str = "PROTON_SYNC";
+/// Separates the two sides of a legacy job
+const LEGACY_JOB_ARROW: &str = "->";
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("Unable to read {}: {source}", path.display())]
+    Read { path: PathBuf, source: io::Error },
+
+    #[error("Invalid settings in {}: {message}", path.display())]
+    Invalid { path: PathBuf, message: String },
+
+    #[error("Unable to write {}: {source}", path.display())]
+    Write { path: PathBuf, source: io::Error },
+
+    #[error("Profile `{0}` already exists")]
+    ProfileExists(String),
+
+    #[error("No profile named `{0}`")]
+    NoSuchProfile(String),
+}
+
+/// Settings of `config.toml`, every key is optional
+///
+/// The account, index and jobs at the top level are the default profile's,
+/// `[profiles.<name>]` tables hold the same keys for the other profiles.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Account logged in with when neither `--username` nor `PROTON_USERNAME` is given
+    pub username: Option<String>,
+    /// Index database, defaults to `index.db` in the profile's data directory
+    pub index_db: Option<PathBuf>,
+    /// Sends usage telemetry to Proton
+    pub telemetry: bool,
+    /// Seconds the work in flight gets to finish after Ctrl-C or SIGTERM, before it is cancelled
+    pub shutdown_grace: u64,
+    pub concurrency: Concurrency,
+    pub bandwidth: Bandwidth,
+    pub log: LogSettings,
+    /// Work done by `daemon` on every iteration
+    pub jobs: Vec<JobSettings>,
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            username: None,
+            index_db: None,
+            telemetry: true,
+            shutdown_grace: 30,
+            concurrency: Concurrency::default(),
+            bandwidth: Bandwidth::default(),
+            log: LogSettings::default(),
+            jobs: Vec::new(),
+            profiles: BTreeMap::new(),
+        }
+    }
+}
+
+/// Account, index and jobs of a profile
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    pub username: Option<String>,
+    pub index_db: Option<PathBuf>,
+    pub jobs: Vec<JobSettings>,
+}
+
+/// How much runs in parallel
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Concurrency {
+    /// Folders listed in parallel while indexing, unless `--workers` is given
+    pub index_workers: usize,
+    /// Files downloaded in parallel, unless `--workers` is given
+    pub transfers: usize,
+}
+
+impl Default for Concurrency {
+    fn default() -> Self {
+        Self { index_workers: 8, transfers: 4 }
+    }
+}
+
+/// Transfer rate limits, in KiB per second, `--limit-up` and `--limit-down` take precedence
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Bandwidth {
+    pub upload: Option<u64>,
+    pub download: Option<u64>,
+    /// Times of day with other limits, the first window holding the local time applies
+    pub schedule: Vec<BandwidthWindow>,
+}
+
+/// A `[[bandwidth.schedule]]` entry, a direction it leaves out is unlimited in the window
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BandwidthWindow {
+    /// Local time the window starts at, as `HH:MM`
+    pub from: String,
+    /// Local time the window ends at, before `from` for windows running past midnight
+    pub to: String,
+    pub upload: Option<u64>,
+    pub download: Option<u64>,
+}
+
+impl BandwidthWindow {
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        let (Ok(from), Ok(to)) = (parse_time(&self.from), parse_time(&self.to)) else {
+            return false;
+        };
+        if from <= to { from <= time && time < to } else { from <= time || time < to }
+    }
+}
+
+impl Bandwidth {
+    /// Limits in bytes per second at local time `time`, `flags` taking precedence in their direction
+    pub fn effective(&self, flags: Limits, time: NaiveTime) -> Limits {
+        let (upload, download) = match self.schedule.iter().find(|window| window.contains(time)) {
+            Some(window) => (window.upload, window.download),
+            None => (self.upload, self.download),
+        };
+        Limits {
+            down: flags.down.or(download.map(|kib| kib * 1024)),
+            up: flags.up.or(upload.map(|kib| kib * 1024)),
+        }
+    }
+}
+
+/// Logging defaults, the command line flags and `RUST_LOG` take precedence
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogSettings {
+    /// `error`, `warn`, `info`, `debug` or `trace`
+    pub level: Option<String>,
+    /// Where the log is also written, as `--log-file` does
+    pub file: Option<PathBuf>,
+}
+
+/// A `[[jobs]]` entry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase", deny_unknown_fields)]
+pub enum JobSettings {
+    /// Downloads a remote folder into a local one, as `mirror` does
+    Mirror { remote: String, local: PathBuf },
+    /// Uploads the changes made under a local folder, as `watch` does
+    Sync { local: PathBuf, remote: String },
+}
+
+impl JobSettings {
+    fn local(&self) -> &Path {
+        match self {
+            JobSettings::Mirror { local, .. } | JobSettings::Sync { local, .. } => local,
+        }
+    }
+
+    fn to_job(&self) -> Job {
+        match self {
+            JobSettings::Mirror { remote, local } => {
+                let Ok(remote) = remote.parse::<RemotePath>();
+                Job::Mirror { remote, local: local.clone() }
+            }
+            JobSettings::Sync { local, remote } => {
+                let Ok(remote) = remote.parse::<RemotePath>();
+                Job::Sync { local: local.clone(), remote }
+            }
+        }
+    }
+}
+
+/// Default location of `config.toml`, in the platform config directory when there is one
+pub fn default_path() -> PathBuf {
+    match ProjectDirs::from("", "", "proton-drive") {
+        Some(dirs) => dirs.config_dir().join(CONFIG_FILE),
+        None => PathBuf::from(CONFIG_FILE),
+    }
+}
+
+/// Directory the session and index of a profile are kept in, under the platform data directory
+///
+/// Every profile has its own, so profiles can be used at the same time.
+pub fn profile_dir(profile: Option<&str>) -> PathBuf {
+    let data_dir = match ProjectDirs::from("", "", "proton-drive") {
+        Some(dirs) => dirs.data_dir().to_path_buf(),
+        None => PathBuf::new(),
+    };
+    data_dir.join("profiles").join(profile.unwrap_or(DEFAULT_PROFILE))
+}
+
+/// Checks that a profile name can name its directory and keyring entries
+pub fn validate_profile_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err(format!("profile names are letters, digits, `-` and `_`, not `{}`", name));
+    }
+    Ok(())
+}
+
+impl Config {
+    /// Reads and checks the settings, a missing file gives the defaults
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(source) => return Err(ConfigError::Read { path: path.to_path_buf(), source }),
+        };
+        Self::parse(&text).map_err(|message| ConfigError::Invalid { path: path.to_path_buf(), message })
+    }
+
+    fn parse(text: &str) -> Result<Self, String> {
+        let config: Self = toml::from_str(text).map_err(|e| e.to_string())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.concurrency.index_workers == 0 || self.concurrency.transfers == 0 {
+            return Err("concurrency settings must be at least 1".to_string());
+        }
+        if let Some(level) = &self.log.level
+            && LevelFilter::from_str(level).is_err()
+        {
+            return Err(format!(
+                "log.level `{}` isn't one of off, error, warn, info, debug or trace",
+                level
+            ));
+        }
+
+        for (n, window) in self.bandwidth.schedule.iter().enumerate() {
+            for time in [&window.from, &window.to] {
+                parse_time(time).map_err(|e| format!("bandwidth.schedule[{}]: {}", n, e))?;
+            }
+        }
+
+        if self.profiles.contains_key(DEFAULT_PROFILE) {
+            return Err(format!(
+                "the {} profile's settings go at the top level, not in [profiles.{}]",
+                DEFAULT_PROFILE, DEFAULT_PROFILE
+            ));
+        }
+        for name in self.profiles.keys() {
+            validate_profile_name(name)?;
+        }
+
+        let profiles = self.profiles.iter().map(|(name, profile)| (format!("profiles.{}.", name), &profile.jobs));
+        for (prefix, jobs) in [(String::new(), &self.jobs)].into_iter().chain(profiles) {
+            if let Some(n) = jobs.iter().position(|job| job.local().as_os_str().is_empty()) {
+                return Err(format!("{}jobs[{}] has an empty local path", prefix, n));
+            }
+        }
+        Ok(())
+    }
+
+    /// Settings of the profile `name`, the top level ones for the default profile
+    pub fn profile(&self, name: Option<&str>) -> Profile {
+        match name {
+            None => Profile {
+                username: self.username.clone(),
+                index_db: self.index_db.clone(),
+                jobs: self.jobs.clone(),
+            },
+            Some(name) => self.profiles.get(name).cloned().unwrap_or_default(),
+        }
+    }
+
+    /// Names of every profile, the default one first
+    pub fn profile_names(&self) -> Vec<&str> {
+        let named = self.profiles.keys().map(String::as_str);
+        [DEFAULT_PROFILE].into_iter().chain(named).collect()
+    }
+
+    /// Adds an empty `[profiles.<name>]` table, with the account when given
+    pub fn add_profile(&mut self, name: &str, username: Option<String>) -> Result<(), ConfigError> {
+        if name == DEFAULT_PROFILE || self.profiles.contains_key(name) {
+            return Err(ConfigError::ProfileExists(name.to_string()));
+        }
+        self.profiles.insert(name.to_string(), Profile { username, ..Default::default() });
+        Ok(())
+    }
+
+    /// Removes a named profile's table, returning its settings
+    pub fn remove_profile(&mut self, name: &str) -> Result<Profile, ConfigError> {
+        self.profiles
+            .remove(name)
+            .ok_or_else(|| ConfigError::NoSuchProfile(name.to_string()))
+    }
+
+    fn profile_mut(&mut self, name: Option<&str>) -> (&mut Option<String>, &mut Vec<JobSettings>) {
+        match name {
+            None => (&mut self.username, &mut self.jobs),
+            Some(name) => {
+                let profile = self.profiles.entry(name.to_string()).or_default();
+                (&mut profile.username, &mut profile.jobs)
+            }
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ConfigError> {
+        let write = |path: &Path| -> io::Result<()> {
+            if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                fs::create_dir_all(dir)?;
+            }
+            let text = toml::to_string_pretty(self).map_err(io::Error::other)?;
+            fs::write(path, text)
+        };
+        write(path).map_err(|source| ConfigError::Write { path: path.to_path_buf(), source })
+    }
+}
+
+impl Profile {
+    pub fn jobs(&self) -> Vec<Job> {
+        self.jobs.iter().map(JobSettings::to_job).collect()
+    }
+}
+
+/// Remembers the username of a profile for the next starts
+pub fn remember_username(path: &Path, profile: Option<&str>, username: &str) -> Result<(), ConfigError> {
+    let mut config = Config::load(path)?;
+    *config.profile_mut(profile).0 = Some(username.to_string());
+    config.save(path)
+}
+
+fn parse_legacy_job(key: &str, value: &str) -> anyhow::Result<JobSettings> {
+    let Some((from, to)) = value.split_once(LEGACY_JOB_ARROW) else {
+        anyhow::bail!("{} should look like `<from> {} <to>`, not `{}`", key, LEGACY_JOB_ARROW, value);
+    };
+    let (from, to) = (from.trim(), to.trim());
+
+    Ok(match key {
+        LEGACY_MIRROR_KEY => JobSettings::Mirror { remote: from.to_string(), local: PathBuf::from(to) },
+        _ => JobSettings::Sync { local: PathBuf::from(from), remote: to.to_string() },
+    })
+}
+
+/// Moves the username and jobs of a legacy `.cfg` into the profile's settings, returning what was moved
+///
+/// The imported lines are removed from the `.cfg`, which only keeps what
+/// the keyring couldn't take, and is removed once empty.
+pub fn migrate_legacy(legacy: &Path, path: &Path, profile: Option<&str>) -> anyhow::Result<Vec<String>> {
+    let cfg = match fs::read_to_string(legacy) {
+        Ok(cfg) => cfg,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut config = Config::load(path)?;
+    let (username, jobs) = config.profile_mut(profile);
+    let mut imported = Vec::new();
+    let mut kept = String::new();
+    for line in cfg.lines() {
+        let Some((key, value)) = line.split_once('=') else {
+            kept.push_str(line);
+            kept.push('\n');
+            continue;
+        };
+        let (key, value) = (key.trim(), value.trim().trim_matches('"'));
+        match key {
+            LEGACY_USERNAME_KEY => {
+                if username.is_none() && !value.is_empty() {
+                    *username = Some(value.to_string());
+                }
+            }
+            LEGACY_MIRROR_KEY | LEGACY_SYNC_KEY => {
+                let job = parse_legacy_job(key, value)?;
+                if !jobs.contains(&job) {
+                    jobs.push(job);
+                }
+            }
+            _ => {
+                kept.push_str(line);
+                kept.push('\n');
+                continue;
+            }
+        }
+        if !imported.iter().any(|imported| imported == key) {
+            imported.push(key.to_string());
+        }
+    }
+
+    if imported.is_empty() {
+        return Ok(imported);
+    }
+    config.save(path)?;
+    if kept.trim().is_empty() {
+        fs::remove_file(legacy)?;
+    } else {
+        fs::write(legacy, kept)?;
+    }
+    Ok(imported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settings_are_typed_and_unknown_keys_rejected() {
+        let config = Config::parse(
+            r#"
+            username = "user@proton.me"
+            telemetry = false
+
+            [concurrency]
+            transfers = 2
+
+            [[jobs]]
+            kind = "mirror"
+            remote = "/Photos"
+            local = "/home/user/Photos"
+
+            [profiles.work]
+            username = "work@proton.me"
+            index_db = "/var/lib/proton/work.db"
+            "#,
+        )
+        .unwrap();
+
+        assert!(!config.telemetry);
+        assert_eq!(config.concurrency, Concurrency { index_workers: 8, transfers: 2 });
+        assert_eq!(
+            config.profile(None).jobs(),
+            [Job::Mirror { remote: "/Photos".parse().unwrap(), local: PathBuf::from("/home/user/Photos") }]
+        );
+        assert_eq!(config.profile(Some("work")).username.as_deref(), Some("work@proton.me"));
+        assert_eq!(config.profile(Some("home")), Profile::default());
+
+        assert_eq!(config.profile_names(), ["default", "work"]);
+
+        let typo = Config::parse("[concurrency]\ntransfer = 2").unwrap_err();
+        assert!(typo.contains("unknown field `transfer`"), "{}", typo);
+        let job = Config::parse("[[jobs]]\nkind = \"sync\"\nlocal = \"\"\nremote = \"/\"").unwrap_err();
+        assert_eq!(job, "jobs[0] has an empty local path");
+        assert!(Config::parse("[log]\nlevel = \"loud\"").is_err());
+        assert!(Config::parse("[profiles.default]\nusername = \"a@proton.me\"").is_err());
+        assert!(Config::parse("[profiles.\"../work\"]").is_err());
+    }
+
+    #[test]
+    fn bandwidth_schedules_apply_by_time_of_day() {
+        let config = Config::parse(
+            r#"
+            [bandwidth]
+            download = 100
+            upload = 10
+
+            [[bandwidth.schedule]]
+            from = "22:00"
+            to = "07:00"
+            upload = 50
+            "#,
+        )
+        .unwrap();
+        let bandwidth = &config.bandwidth;
+        let time = |value| parse_time(value).unwrap();
+
+        assert_eq!(bandwidth.effective(Limits::default(), time("12:00")), Limits { down: Some(102_400), up: Some(10_240) });
+        assert_eq!(bandwidth.effective(Limits::default(), time("23:30")), Limits { down: None, up: Some(51_200) });
+        assert_eq!(bandwidth.effective(Limits::default(), time("06:59")), Limits { down: None, up: Some(51_200) });
+        assert_eq!(
+            bandwidth.effective(Limits { down: Some(1), up: None }, time("07:00")),
+            Limits { down: Some(1), up: Some(10_240) }
+        );
+
+        let typo = Config::parse("[[bandwidth.schedule]]\nfrom = \"25:00\"\nto = \"07:00\"").unwrap_err();
+        assert!(typo.starts_with("bandwidth.schedule[0]"), "{}", typo);
+    }
+
+    #[test]
+    fn profiles_are_added_and_removed_by_name() {
+        let mut config = Config::default();
+        config.add_profile("work", Some("work@proton.me".to_string())).unwrap();
+        assert!(matches!(config.add_profile("work", None), Err(ConfigError::ProfileExists(_))));
+        assert!(matches!(config.add_profile(DEFAULT_PROFILE, None), Err(ConfigError::ProfileExists(_))));
+        assert_eq!(Config::parse(&toml::to_string_pretty(&config).unwrap()).unwrap(), config);
+
+        assert_eq!(config.remove_profile("work").unwrap().username.as_deref(), Some("work@proton.me"));
+        assert!(matches!(config.remove_profile("work"), Err(ConfigError::NoSuchProfile(_))));
+        assert_eq!(profile_dir(None).file_name().unwrap(), DEFAULT_PROFILE);
+    }
+
+    #[test]
+    fn legacy_settings_are_imported_once() {
+        let dir = std::env::temp_dir().join(format!("proton-drive-config-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (legacy, path) = (dir.join(".cfg"), dir.join(CONFIG_FILE));
+        fs::write(
+            &legacy,
+            "PROTON_USERNAME=user@proton.me\n\
+            PROTON_MIRROR=/Photos -> /home/user/Photos\n\
+            PROTON_SYNC=\"/home/user/Documents -> /Backup/Documents\"\n\
+            PROTON_PASSWORD=hunter2\n",
+        )
+        .unwrap();
+
+        let imported = migrate_legacy(&legacy, &path, Some("work")).unwrap();
+        assert_eq!(imported, [LEGACY_USERNAME_KEY, LEGACY_MIRROR_KEY, LEGACY_SYNC_KEY]);
+        assert_eq!(fs::read_to_string(&legacy).unwrap(), "PROTON_PASSWORD=hunter2\n");
+
+        let profile = Config::load(&path).unwrap().profile(Some("work"));
+        assert_eq!(profile.username.as_deref(), Some("user@proton.me"));
+        assert_eq!(
+            profile.jobs(),
+            [
+                Job::Mirror { remote: "/Photos".parse().unwrap(), local: PathBuf::from("/home/user/Photos") },
+                Job::Sync { local: PathBuf::from("/home/user/Documents"), remote: "/Backup/Documents".parse().unwrap() },
+            ]
+        );
+        assert!(migrate_legacy(&legacy, &path, Some("work")).unwrap().is_empty());
+        assert!(parse_legacy_job(LEGACY_MIRROR_KEY, "/Photos").is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
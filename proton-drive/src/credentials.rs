@@ -0,0 +1,176 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use log::{debug, info, warn};
+
+/// Service name the secrets of the default profile are filed under in the OS keyring,
+/// other profiles append `:<profile>`
+const KEYRING_SERVICE: &str = "proton-drive-rs";
+
+/// `.cfg` keys that hold secrets and are moved to the keyring
+const SECRET_KEYS: [(&str, Secret); 3] = [
+    ("PROTON_PASSWORD", Secret::Password),
+    ("PROTON_DATA_PASSWORD", Secret::DataPassword),
+    ("PROTON_TOTP_SECRET", Secret::Totp),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Secret {
+    Password,
+    DataPassword,
+    /// Base32 TOTP secret answering 2FA without a prompt
+    Totp,
+}
+
+impl Secret {
+    /// Keyring user for this secret, all secrets are keyed by the account's username
+    fn entry_user(self, username: &str) -> String {
+        match self {
+            Secret::Password => username.to_string(),
+            Secret::DataPassword => format!("{}:data", username),
+            Secret::Totp => format!("{}:totp", username),
+        }
+    }
+}
+
+/// Reads and writes account secrets in the OS keyring
+///
+/// A disabled store (`--no-keyring`) never finds nor keeps anything, so the
+/// secrets come from the environment or a prompt on every start. Each profile
+/// has its own keyring service, so the same account can be in two profiles.
+#[derive(Debug, Clone)]
+pub struct CredentialStore {
+    enabled: bool,
+    service: String,
+}
+
+impl CredentialStore {
+    pub fn new(enabled: bool, profile: Option<&str>) -> Self {
+        let service = match profile {
+            Some(profile) => format!("{}:{}", KEYRING_SERVICE, profile),
+            None => KEYRING_SERVICE.to_string(),
+        };
+        Self { enabled, service }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn entry(&self, username: &str, secret: Secret) -> Option<keyring::Entry> {
+        if !self.enabled || username.is_empty() {
+            return None;
+        }
+
+        match keyring::Entry::new(&self.service, &secret.entry_user(username)) {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                warn!("Unable to open the keyring entry for {:?}: {}", secret, e);
+                None
+            }
+        }
+    }
+
+    /// Looks up a secret, `None` when it isn't stored or the keyring is unavailable
+    pub fn get(&self, username: &str, secret: Secret) -> Option<String> {
+        match self.entry(username, secret)?.get_password() {
+            Ok(value) => Some(value),
+            Err(keyring::Error::NoEntry) => None,
+            Err(e) => {
+                warn!("Unable to read {:?} from the keyring: {}", secret, e);
+                None
+            }
+        }
+    }
+
+    /// Stores a secret, returning whether it ended up in the keyring
+    pub fn set(&self, username: &str, secret: Secret, value: &str) -> bool {
+        let Some(entry) = self.entry(username, secret) else {
+            return false;
+        };
+
+        match entry.set_password(value) {
+            Ok(()) => {
+                debug!("Stored {:?} in the keyring", secret);
+                true
+            }
+            Err(e) => {
+                warn!("Unable to store {:?} in the keyring: {}", secret, e);
+                false
+            }
+        }
+    }
+
+    /// Deletes every secret stored for `username`
+    pub fn forget(&self, username: &str) {
+        for (_, secret) in SECRET_KEYS {
+            let Some(entry) = self.entry(username, secret) else {
+                return;
+            };
+
+            match entry.delete_credential() {
+                Ok(()) => debug!("Removed {:?} from the keyring", secret),
+                Err(keyring::Error::NoEntry) => {}
+                Err(e) => warn!("Unable to remove {:?} from the keyring: {}", secret, e),
+            }
+        }
+    }
+}
+
+/// Moves the secrets of an existing `.cfg` into the keyring
+///
+/// The file is rewritten without the secret lines once they are stored, so
+/// it only keeps non-secret settings. Nothing changes if the keyring is
+/// disabled or refuses a secret.
+pub fn migrate_cfg(path: &Path, store: &CredentialStore) -> io::Result<()> {
+    if !store.is_enabled() {
+        return Ok(());
+    }
+
+    let cfg = match fs::read_to_string(path) {
+        Ok(cfg) => cfg,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+
+    let Some(username) = cfg_value(&cfg, "PROTON_USERNAME") else {
+        return Ok(());
+    };
+
+    let mut migrated = Vec::new();
+    for (key, secret) in SECRET_KEYS {
+        if let Some(value) = cfg_value(&cfg, key) {
+            if !store.set(username, secret, value) {
+                return Ok(());
+            }
+            migrated.push(key);
+        }
+    }
+
+    if migrated.is_empty() {
+        return Ok(());
+    }
+
+    let kept: String = cfg
+        .lines()
+        .filter(|line| !migrated.iter().any(|key| cfg_key(line) == Some(*key)))
+        .map(|line| format!("{}\n", line))
+        .collect();
+    fs::write(path, kept)?;
+
+    info!("Moved {} from {} to the keyring", migrated.join(", "), path.display());
+    Ok(())
+}
+
+fn cfg_key(line: &str) -> Option<&str> {
+    line.split_once('=').map(|(key, _)| key.trim())
+}
+
+fn cfg_value<'a>(cfg: &'a str, key: &str) -> Option<&'a str> {
+    cfg.lines()
+        .filter_map(|line| line.split_once('='))
+        .find(|(k, _)| k.trim() == key)
+        .map(|(_, value)| value.trim())
+        .filter(|value| !value.is_empty())
+}
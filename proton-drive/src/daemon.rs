@@ -0,0 +1,375 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use glob::Pattern;
+use log::{debug, info, warn};
+use proton_sdk_rs::nodes::RemotePath;
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+
+use crate::auth::AuthOptions;
+use crate::bandwidth::{self, Limits};
+use crate::cli::Interrupted;
+use crate::commands::Context;
+use crate::config::Config;
+use crate::dry_run::Entry;
+use crate::index;
+use crate::mirror;
+use crate::shutdown;
+use crate::watch;
+
+/// Longest wait between iterations after repeated failures
+const MAX_BACKOFF: Duration = Duration::from_secs(60 * 60);
+
+/// How the daemon runs
+#[derive(Debug, Clone)]
+pub struct DaemonOptions {
+    pub interval: Duration,
+    /// Runs a single iteration and returns
+    pub once: bool,
+    /// Settings file the jobs are read from, again on SIGHUP
+    pub config: PathBuf,
+    /// Profile whose jobs are run
+    pub profile: Option<String>,
+    /// Folders listed in parallel while refreshing the index
+    pub index_workers: usize,
+    /// Files downloaded in parallel by a mirror job
+    pub transfers: usize,
+    /// `--limit-down` and `--limit-up`, over the `[bandwidth]` settings read again on SIGHUP
+    pub limits: Limits,
+    /// Plans what the jobs would do once instead of running them, see [`plan`]
+    pub dry_run: bool,
+}
+
+/// Work done on every iteration after refreshing the index
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Job {
+    /// Downloads a remote folder into a local one, as `mirror` does
+    Mirror { remote: RemotePath, local: PathBuf },
+    /// Uploads the changes made under a local folder, as `watch` does
+    Sync { local: PathBuf, remote: RemotePath },
+}
+
+/// Reads the `[[jobs]]` of the profile from the settings file
+pub fn read_jobs(config: &Path, profile: Option<&str>) -> anyhow::Result<Vec<Job>> {
+    Ok(Config::load(config)?.profile(profile).jobs())
+}
+
+/// Wait before the next iteration, doubling with every failure in a row
+fn backoff(interval: Duration, failures: u32) -> Duration {
+    if failures == 0 {
+        return interval;
+    }
+    interval
+        .saturating_mul(1 << failures.min(16))
+        .min(MAX_BACKOFF.max(interval))
+}
+
+/// Brings the index up to date, or builds it when it never completed
+async fn refresh_index(
+    context: &Context,
+    pool: &Pool<SqliteConnectionManager>,
+    options: &DaemonOptions,
+    shutdown: &AtomicBool,
+) -> anyhow::Result<()> {
+    let workers = options.index_workers;
+    if index::is_complete(pool)? {
+        let report = index::refresh(context.client(), context.root(), pool, workers, shutdown, |_| {}).await?;
+        info!(
+            "Refreshed the index, {} new folders and {} new files",
+            report.progress.new_folders, report.progress.new_files
+        );
+        for (path, e) in &report.failures {
+            warn!("Failed to refresh /{}: {:#}", path, e);
+        }
+    } else {
+        let report = index::index(context.client(), context.root(), pool, workers, shutdown, |_| {}).await?;
+        if report.interrupted {
+            return Err(Interrupted.into());
+        }
+        // the jobs need the whole index, the failed folders are retried by the next iteration
+        let failed = report.failures.len();
+        if let Some((path, e)) = report.failures.into_iter().next() {
+            return Err(e.context(format!("{} folders couldn't be indexed, starting with /{}", failed, path)));
+        }
+    }
+    Ok(())
+}
+
+/// Refreshes the index, then runs the jobs, returning how many of them failed
+async fn iteration(
+    context: &Context,
+    pool: &Pool<SqliteConnectionManager>,
+    jobs: &[Job],
+    options: &DaemonOptions,
+    shutdown: &AtomicBool,
+) -> anyhow::Result<usize> {
+    refresh_index(context, pool, options, shutdown).await?;
+
+    let mut failed = 0;
+    for job in jobs {
+        if shutdown.load(Ordering::Relaxed) {
+            return Err(Interrupted.into());
+        }
+        if let Err(e) = run_job(context, pool, job, options, shutdown).await {
+            warn!("Job {:?} failed: {:#}", job, e);
+            failed += 1;
+        }
+    }
+
+    context.flush_observability().await?;
+    Ok(failed)
+}
+
+fn mirror_options(remote: &RemotePath, local: &Path, options: &DaemonOptions) -> mirror::MirrorOptions {
+    mirror::MirrorOptions {
+        remote: remote.clone(),
+        local_root: local.to_path_buf(),
+        delete_local: false,
+        dry_run: options.dry_run,
+        workers: options.transfers,
+    }
+}
+
+fn sync_options(local: &Path, remote: &RemotePath, options: &DaemonOptions) -> watch::WatchOptions {
+    watch::WatchOptions {
+        local_root: local.to_path_buf(),
+        remote: remote.clone(),
+        delete_remote: false,
+        ignore: watch::DEFAULT_IGNORES.iter().map(|pattern| Pattern::new(pattern).unwrap()).collect(),
+        debounce: Duration::ZERO,
+        dry_run: options.dry_run,
+    }
+}
+
+/// Works out what a mirror job has to do from the index
+fn mirror_plan(pool: &Pool<SqliteConnectionManager>, options: &mirror::MirrorOptions) -> anyhow::Result<mirror::Plan> {
+    let Some(subtree) = index::subtree(pool, &options.remote.segments().join("/"))? else {
+        anyhow::bail!("{} isn't an indexed folder", options.remote);
+    };
+    mirror::plan(subtree, options)
+}
+
+async fn run_job(
+    context: &Context,
+    pool: &Pool<SqliteConnectionManager>,
+    job: &Job,
+    options: &DaemonOptions,
+    shutdown: &AtomicBool,
+) -> anyhow::Result<()> {
+    match job {
+        Job::Mirror { remote, local } => {
+            let options = mirror_options(remote, local, options);
+            let plan = mirror_plan(pool, &options)?;
+            let report = mirror::apply(context, plan, &options, shutdown).await;
+            info!(
+                "Mirrored {} to {}, {} moved and {} downloaded",
+                remote,
+                local.display(),
+                report.moved,
+                report.downloaded
+            );
+            if let Some((path, e)) = report.failed.into_iter().next() {
+                return Err(e.context(format!("Failed to mirror {}", path)));
+            }
+        }
+        Job::Sync { local, remote } => {
+            watch::sync_once(context, pool, sync_options(local, remote, options)).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Refreshes the index like an iteration does, then lists what the jobs would change
+///
+/// The plans come from the checks the jobs run before transferring anything,
+/// so they match what the next iteration would do.
+pub async fn plan(
+    context: &Context,
+    pool: &Pool<SqliteConnectionManager>,
+    options: &DaemonOptions,
+) -> anyhow::Result<Vec<Entry>> {
+    let jobs = read_jobs(&options.config, options.profile.as_deref())?;
+    let shutdown = shutdown::flag("Stopping after the folders being listed...");
+    refresh_index(context, pool, options, &shutdown).await?;
+
+    let mut entries = Vec::new();
+    for job in &jobs {
+        match job {
+            Job::Mirror { remote, local } => {
+                entries.extend(mirror_plan(pool, &mirror_options(remote, local, options))?.entries());
+            }
+            Job::Sync { local, remote } => {
+                entries.extend(watch::plan(pool, sync_options(local, remote, options))?);
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// Turns SIGHUP into a reload request, stop requests are handled by [`shutdown`]
+fn listen_for_reloads(reload: Arc<AtomicBool>) {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        tokio::spawn(async move {
+            let Ok(mut hangup) = signal(SignalKind::hangup()) else {
+                warn!("Unable to listen for SIGHUP");
+                return;
+            };
+            while hangup.recv().await.is_some() {
+                info!("SIGHUP received, reloading the jobs and bandwidth limits before the next iteration");
+                reload.store(true, Ordering::Relaxed);
+            }
+        });
+    }
+    #[cfg(not(unix))]
+    let _ = reload;
+}
+
+/// Refreshes the index and runs the jobs every `interval` until stopped
+///
+/// The client is created again after a failed iteration, so an expired session
+/// is resumed from the saved one. Once stopped, the iteration running finishes
+/// its transfers in flight, or has them cancelled after the grace period.
+pub async fn run(
+    auth: AuthOptions,
+    pool: &Pool<SqliteConnectionManager>,
+    options: DaemonOptions,
+) -> anyhow::Result<()> {
+    let reload = Arc::new(AtomicBool::new(false));
+    let stop = shutdown::flag("Stopping after the transfers in flight...");
+    listen_for_reloads(reload.clone());
+
+    let mut jobs = read_jobs(&options.config, options.profile.as_deref())?;
+    info!("Running {} jobs every {:?}", jobs.len(), options.interval);
+
+    let mut context = None;
+    let mut failures = 0;
+    let mut limits = None;
+    loop {
+        if reload.swap(false, Ordering::Relaxed) {
+            match Config::load(&options.config) {
+                Ok(config) => {
+                    jobs = config.profile(options.profile.as_deref()).jobs();
+                    info!("Reloaded {} jobs from {}", jobs.len(), options.config.display());
+                    bandwidth::configure(config.bandwidth, options.limits);
+                }
+                Err(e) => warn!("Keeping the previous jobs, the settings are invalid: {:#}", e),
+            }
+        }
+
+        // the schedule may have moved on to other limits since the last iteration
+        let effective = bandwidth::limits();
+        if limits != Some(effective) {
+            info!("Transfer limits: {}", effective);
+            limits = Some(effective);
+        }
+
+        if context.is_none() {
+            match Context::new(auth.clone()).await {
+                Ok(created) => context = Some(created),
+                Err(e) if options.once => return Err(e),
+                Err(e) => warn!("Unable to connect: {:#}", e),
+            }
+        }
+
+        let result = match &context {
+            Some(context) => iteration(context, pool, &jobs, &options, &stop).await,
+            None => Err(anyhow::anyhow!("Not connected")),
+        };
+        if stop.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        match result {
+            Ok(0) => failures = 0,
+            Ok(failed) => {
+                warn!("{} jobs failed", failed);
+                failures += 1;
+            }
+            Err(e) => {
+                warn!("Iteration failed: {:#}", e);
+                failures += 1;
+                // reconnects on the next iteration, resuming the saved session
+                context = None;
+            }
+        }
+
+        if options.once {
+            return match failures {
+                0 => Ok(()),
+                _ => Err(anyhow::anyhow!("The iteration failed, see the log for details")),
+            };
+        }
+
+        let delay = backoff(options.interval, failures);
+        debug!("Sleeping for {:?}", delay);
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = shutdown::requested() => return Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn jobs_are_read_from_the_settings() {
+        let config = std::env::temp_dir().join(format!("proton-drive-daemon-{}.toml", std::process::id()));
+        std::fs::write(
+            &config,
+            r#"
+            username = "user@proton.me"
+
+            [[jobs]]
+            kind = "mirror"
+            remote = "/Photos"
+            local = "/home/user/Photos"
+
+            [[jobs]]
+            kind = "sync"
+            local = "/home/user/Documents"
+            remote = "/Backup/Documents"
+
+            [[profiles.work.jobs]]
+            kind = "mirror"
+            remote = "/Work"
+            local = "/home/user/Work"
+            "#,
+        )
+        .unwrap();
+
+        let jobs = read_jobs(&config, None).unwrap();
+        let work = read_jobs(&config, Some("work")).unwrap();
+        std::fs::remove_file(&config).unwrap();
+        assert_eq!(
+            jobs,
+            [
+                Job::Mirror {
+                    remote: "/Photos".parse().unwrap(),
+                    local: PathBuf::from("/home/user/Photos"),
+                },
+                Job::Sync {
+                    local: PathBuf::from("/home/user/Documents"),
+                    remote: "/Backup/Documents".parse().unwrap(),
+                },
+            ]
+        );
+        assert_eq!(work.len(), 1);
+    }
+
+    #[test]
+    fn failures_back_off_up_to_an_hour() {
+        let interval = Duration::from_secs(300);
+        assert_eq!(backoff(interval, 0), interval);
+        assert_eq!(backoff(interval, 1), Duration::from_secs(600));
+        assert_eq!(backoff(interval, 30), MAX_BACKOFF);
+        assert_eq!(backoff(Duration::from_secs(7200), 3), Duration::from_secs(7200));
+    }
+}
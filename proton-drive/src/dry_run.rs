@@ -0,0 +1,64 @@
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+/// What a run would do to a path
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Download,
+    Upload,
+    /// Renames a local copy along with its remote file
+    Move,
+    Delete,
+    /// Drops a path from the sync journal, leaving the remote copy alone
+    Forget,
+}
+
+/// Which side a change is applied to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// Remote to local, the local folder changes
+    Down,
+    /// Local to remote, the drive changes
+    Up,
+}
+
+/// A change a dry run reports instead of making, as printed by `--dry-run --json`
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct Entry {
+    pub action: Action,
+    /// Local path the change is about
+    pub path: PathBuf,
+    pub direction: Direction,
+    /// Size of the file transferred or deleted, none for moves and forgotten paths
+    pub bytes: Option<u64>,
+    pub reason: String,
+}
+
+/// Prints a plan, as a JSON array for `json`
+pub fn print(entries: &[Entry], json: bool) -> anyhow::Result<()> {
+    let mut stdout = io::stdout().lock();
+    if json {
+        serde_json::to_writer_pretty(&mut stdout, entries)?;
+        writeln!(stdout)?;
+        return Ok(());
+    }
+
+    for entry in entries {
+        let verb = match entry.action {
+            Action::Download => "download",
+            Action::Upload => "upload",
+            Action::Move => "move",
+            Action::Delete => "delete",
+            Action::Forget => "forget",
+        };
+        match entry.bytes {
+            Some(bytes) => writeln!(stdout, "Would {} {} ({} bytes): {}", verb, entry.path.display(), bytes, entry.reason)?,
+            None => writeln!(stdout, "Would {} {}: {}", verb, entry.path.display(), entry.reason)?,
+        }
+    }
+    Ok(())
+}
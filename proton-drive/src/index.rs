@@ -1,135 +1,1705 @@
-use async_recursion::async_recursion;
-use r2d2::Pool;
+mod export;
+mod schema;
+
+use std::fs;
+use std::io;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use chrono::Utc;
+use futures::stream::{FuturesUnordered, StreamExt};
+use r2d2::{CustomizeConnection, Pool};
 use r2d2_sqlite::SqliteConnectionManager;
-use r2d2_sqlite::rusqlite::params;
-use proton_sdk_rs::drive::DriveClient;
-use proton_sdk_rs::utils;
-use proton_sdk_sys::protobufs::{NodeIdentity, NodeType, ToByteArray};
-
-pub async fn index(
-    client: &DriveClient,
-    identity: &NodeIdentity,
-    password: String,
+use r2d2_sqlite::rusqlite::{self, params, Connection, ErrorCode, OptionalExtension};
+use proton_sdk_rs::drive::DriveError;
+use proton_sdk_rs::nodes::{NodeIdentityExt, NodeTypeExt};
+use proton_sdk_rs::retry::RetryPolicy;
+use proton_sdk_sys::prost::Message;
+use proton_sdk_sys::protobufs::{drive::{node_type, FileNode, FolderNode, LinkId, NodeIdentity, NodeType}, ToByteArray};
+use regex::RegexBuilder;
+use serde::Serialize;
+
+use crate::remote::DriveOps;
+use crate::shutdown;
+
+pub use export::{export, import, ExportFormat};
+
+/// Line older builds added to the settings file once the initial indexing was done
+const LEGACY_INDEXED_MARKER: &str = "INITIAL_INDEX=true";
+
+/// `index_state` key set when the initial indexing starts
+const STATE_STARTED_AT: &str = "started_at";
+/// `index_state` key set once every folder was listed by the initial indexing
+const STATE_COMPLETED_AT: &str = "completed_at";
+
+/// Errors opening the index
+#[derive(Debug, thiserror::Error)]
+pub enum IndexError {
+    #[error("Wrong index password for {0}, or the index is encrypted and no password is available")]
+    WrongPassword(PathBuf),
+
+    #[error("{0} folders couldn't be indexed, run `proton-drive index --retry-failed` to try them again")]
+    FailedFolders(usize),
+
+    #[error("An index password is set, but this build's SQLite has no SQLCipher to encrypt {0} with")]
+    NoCipher(PathBuf),
+}
+
+/// Keys every connection of the pool as it is opened
+struct IndexKey(String);
+
+impl fmt::Debug for IndexKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("IndexKey(..)")
+    }
+}
+
+impl CustomizeConnection<Connection, rusqlite::Error> for IndexKey {
+    fn on_acquire(&self, conn: &mut Connection) -> Result<(), rusqlite::Error> {
+        apply_key(conn, &self.0)
+    }
+}
+
+/// Keys a SQLCipher connection
+fn apply_key(conn: &Connection, key: &str) -> rusqlite::Result<()> {
+    conn.execute_batch(&format!(
+        "PRAGMA key = '{}'; PRAGMA cipher_memory_security = ON;",
+        key.replace('\'', "''")
+    ))
+}
+
+/// Checks the key opens the index before a pool is built around it
+///
+/// A wrong key only shows up as "file is not a database" on the first query,
+/// and failing connections would make the pool retry until it times out.
+///
+/// Plain SQLite ignores `PRAGMA key`, so a key is refused unless SQLCipher
+/// answers `PRAGMA cipher_version`, rather than writing the index in the clear.
+fn verify_key(path: &Path, key: Option<&str>) -> anyhow::Result<()> {
+    let conn = Connection::open(path)?;
+    if let Some(key) = key {
+        let cipher: Option<String> = conn.query_row("PRAGMA cipher_version", [], |row| row.get(0)).optional()?;
+        if cipher.is_none() {
+            return Err(IndexError::NoCipher(path.to_path_buf()).into());
+        }
+        // SQLCipher logs failed decryptions to stderr, a wrong key is reported below instead
+        conn.execute_batch("PRAGMA cipher_log_level = NONE;")?;
+        if encrypt_plain_index(&conn, path, key)? {
+            drop(conn);
+            return verify_key(path, Some(key));
+        }
+        apply_key(&conn, key)?;
+    }
+
+    match conn.query_row("SELECT count(*) FROM sqlite_master", [], |row| row.get::<_, i64>(0)) {
+        Ok(_) => Ok(()),
+        Err(rusqlite::Error::SqliteFailure(e, _)) if e.code == ErrorCode::NotADatabase => {
+            Err(IndexError::WrongPassword(path.to_path_buf()).into())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Encrypts an index written in the clear, returning whether it did
+///
+/// Builds without SQLCipher ignored the key, so their indexes are plain SQLite
+/// files that the key would otherwise be reported wrong for.
+fn encrypt_plain_index(conn: &Connection, path: &Path, key: &str) -> anyhow::Result<bool> {
+    let tables = conn.query_row("SELECT count(*) FROM sqlite_master", [], |row| row.get::<_, i64>(0));
+    if !matches!(tables, Ok(count) if count > 0) {
+        return Ok(false);
+    }
+
+    log::info!("Encrypting the index {}", path.display());
+    let mut encrypted = path.as_os_str().to_owned();
+    encrypted.push(".encrypting");
+    let encrypted = PathBuf::from(encrypted);
+    // left over by an interrupted run, if any
+    let _ = fs::remove_file(&encrypted);
+    conn.execute("ATTACH DATABASE ?1 AS encrypted KEY ?2", params![encrypted.to_string_lossy(), key])?;
+    conn.query_row("SELECT sqlcipher_export('encrypted')", [], |_| Ok(()))?;
+    conn.execute_batch("DETACH DATABASE encrypted; PRAGMA wal_checkpoint(TRUNCATE);")?;
+    fs::rename(&encrypted, path)?;
+    Ok(true)
+}
+
+/// Opens (or creates) the index database and migrates it to the current schema
+///
+/// With a `key`, the index is encrypted with SQLCipher. Every pooled
+/// connection is keyed once when it is opened, so connections handed out later
+/// are ready to use.
+pub fn open(path: &Path, key: Option<String>) -> anyhow::Result<Pool<SqliteConnectionManager>> {
+    verify_key(path, key.as_deref())?;
+
+    let mut builder = Pool::builder();
+    if let Some(key) = key {
+        builder = builder.connection_customizer(Box::new(IndexKey(key)));
+    }
+    let pool = builder.build(SqliteConnectionManager::file(path))?;
+    schema::migrate(&mut *pool.get()?)?;
+    shutdown::register_pool(&pool);
+    Ok(pool)
+}
+
+/// Writes the write-ahead log back into the database file, if the index has one
+pub fn checkpoint(pool: &Pool<SqliteConnectionManager>) -> anyhow::Result<()> {
+    pool.get()?.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+    Ok(())
+}
+
+/// Removes the marker older builds wrote to the settings file after the initial
+/// indexing, returning whether it was there
+pub fn take_legacy_marker(config: &Path) -> io::Result<bool> {
+    let cfg = match fs::read_to_string(config) {
+        Ok(cfg) => cfg,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e),
+    };
+    if !cfg.lines().any(|line| line.trim() == LEGACY_INDEXED_MARKER) {
+        return Ok(false);
+    }
+
+    let kept: String = cfg
+        .lines()
+        .filter(|line| line.trim() != LEGACY_INDEXED_MARKER)
+        .map(|line| format!("{}\n", line))
+        .collect();
+    fs::write(config, kept)?;
+    Ok(true)
+}
+
+fn state(conn: &Connection, key: &str) -> rusqlite::Result<Option<String>> {
+    conn.query_row("SELECT value FROM index_state WHERE key = ?1", params![key], |row| row.get(0))
+        .optional()
+}
+
+fn set_state(conn: &Connection, key: &str, value: &str) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO index_state (key, value) VALUES (?1, ?2)
+        ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+        params![key, value],
+    )?;
+    Ok(())
+}
+
+/// Checks if the initial indexing ran to completion
+pub fn is_complete(pool: &Pool<SqliteConnectionManager>) -> anyhow::Result<bool> {
+    Ok(state(&*pool.get()?, STATE_COMPLETED_AT)?.is_some())
+}
+
+/// Records that the initial indexing is done, for indexes built before checkpoints
+pub fn mark_complete(pool: &Pool<SqliteConnectionManager>) -> anyhow::Result<()> {
+    set_state(&*pool.get()?, STATE_COMPLETED_AT, &Utc::now().to_rfc3339())?;
+    Ok(())
+}
+
+/// Clears the checkpoints so the next indexing lists every folder again
+pub fn reset(pool: &Pool<SqliteConnectionManager>) -> anyhow::Result<()> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+    tx.execute("UPDATE folders SET checked = 0", [])?;
+    tx.execute("DELETE FROM index_state", [])?;
+    tx.execute("DELETE FROM failed_folders", [])?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Node columns of an index row, taken from the listed node
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NodeColumns {
+    pub node_id: Option<String>,
+    pub parent_node_id: Option<String>,
+    pub size: Option<i64>,
+    /// Creation time of the active revision, in seconds since the epoch
+    pub modified_at: Option<i64>,
+    pub revision_id: Option<String>,
+    /// Not provided by the SDK listings yet, kept for when it is
+    pub content_hash: Option<String>,
+}
+
+impl From<&FileNode> for NodeColumns {
+    fn from(file: &FileNode) -> Self {
+        let revision = file.active_revision.as_ref();
+        Self {
+            node_id: link_id(file.node_identity.as_ref().and_then(|identity| identity.node_id.as_ref())),
+            parent_node_id: link_id(file.parent_id.as_ref()),
+            size: revision.and_then(|revision| revision.size),
+            modified_at: revision.map(|revision| revision.creation_time),
+            revision_id: revision
+                .and_then(|revision| revision.revision_id.as_ref())
+                .map(|id| id.value.clone()),
+            content_hash: None,
+        }
+    }
+}
+
+impl From<&FolderNode> for NodeColumns {
+    fn from(folder: &FolderNode) -> Self {
+        Self {
+            node_id: link_id(folder.node_identity.as_ref().and_then(|identity| identity.node_id.as_ref())),
+            parent_node_id: link_id(folder.parent_id.as_ref()),
+            ..Default::default()
+        }
+    }
+}
+
+fn link_id(id: Option<&LinkId>) -> Option<String> {
+    id.map(|id| id.value.clone()).filter(|value| !value.is_empty())
+}
+
+/// A listed folder or file, ready to be written to the index
+struct NodeRow {
+    name: String,
+    is_folder: bool,
+    node: Vec<u8>,
+    columns: NodeColumns,
+    /// Complete identity of a folder, to list it in turn
+    identity: Option<NodeIdentity>,
+}
+
+impl NodeRow {
+    /// Builds the row of a child of the folder `parent`
+    fn new(child: &NodeType, parent: &NodeIdentity) -> anyhow::Result<Option<Self>> {
+        let mut row = if let Some(folder) = child.as_folder() {
+            Self {
+                name: folder.name.clone(),
+                is_folder: true,
+                node: folder.to_bytes()?,
+                columns: NodeColumns::from(folder),
+                identity: Some(folder.full_identity(parent)?),
+            }
+        } else if let Some(file) = child.as_file() {
+            Self {
+                name: file.name.clone(),
+                is_folder: false,
+                node: file.to_bytes()?,
+                columns: NodeColumns::from(file),
+                identity: None,
+            }
+        } else {
+            return Ok(None);
+        };
+
+        if row.columns.node_id.is_none() {
+            anyhow::bail!("{} was listed without a node id", row.name);
+        }
+        if row.columns.parent_node_id.is_none() {
+            row.columns.parent_node_id = link_id(parent.node_id.as_ref());
+        }
+        Ok(Some(row))
+    }
+}
+
+/// What writing a row changed in the index
+#[derive(Debug, PartialEq)]
+enum Upserted {
+    /// The node wasn't indexed yet
+    New,
+    /// The node was indexed under another path, which it and its children left
+    Moved { from: String },
+    /// The node was indexed under this path already
+    Existing,
+}
+
+fn child_path(parent_path: &str, name: &str) -> String {
+    if parent_path.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{}", parent_path, name)
+    }
+}
+
+/// Writes a row keyed by its node id, so renamed and moved nodes keep a single row
+///
+/// A row of another node still holding the path is dropped, its node is gone.
+/// Moves are logged in `moves`, a folder's entry stands for everything under it.
+fn upsert(conn: &Connection, parent_path: &str, row: &NodeRow) -> rusqlite::Result<Upserted> {
+    // a folder stays listed until its node changes, a file stays checked until its revision does
+    let (table, name_column, unchanged) = if row.is_folder {
+        ("folders", "folder_name", "node = excluded.node")
+    } else {
+        ("files", "file_name", "revision_id IS excluded.revision_id")
+    };
+    let full_path = child_path(parent_path, &row.name);
+    let columns = &row.columns;
+
+    let previous: Option<String> = conn
+        .query_row(
+            &format!("SELECT full_path FROM {} WHERE node_id = ?1", table),
+            params![columns.node_id],
+            |r| r.get(0),
+        )
+        .optional()?;
+
+    conn.execute(
+        &format!("DELETE FROM {} WHERE full_path = ?1 AND node_id IS NOT ?2", table),
+        params![full_path, columns.node_id],
+    )?;
+    conn.execute(
+        &format!(
+            "INSERT INTO {0} (full_path, {1}, checked, node, node_id, parent_node_id, size, modified_at, revision_id, content_hash)
+                VALUES (?1, ?2, 0, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+            ON CONFLICT(node_id) DO UPDATE SET
+                full_path = excluded.full_path,
+                {1} = excluded.{1},
+                node = excluded.node,
+                parent_node_id = excluded.parent_node_id,
+                size = excluded.size,
+                modified_at = excluded.modified_at,
+                checked = CASE WHEN {2} THEN checked ELSE 0 END,
+                revision_id = excluded.revision_id,
+                content_hash = excluded.content_hash,
+                deleted_at = NULL",
+            table, name_column, unchanged
+        ),
+        params![
+            full_path,
+            row.name,
+            row.node,
+            columns.node_id,
+            columns.parent_node_id,
+            columns.size,
+            columns.modified_at,
+            columns.revision_id,
+            columns.content_hash,
+        ],
+    )?;
+
+    match previous {
+        None => Ok(Upserted::New),
+        Some(from) if from == full_path => Ok(Upserted::Existing),
+        Some(from) => {
+            if row.is_folder {
+                move_children(conn, &from, &full_path)?;
+            }
+            conn.execute(
+                "INSERT INTO moves (node_id, is_folder, from_path, to_path, moved_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![columns.node_id, row.is_folder, from, full_path, Utc::now().timestamp()],
+            )?;
+            Ok(Upserted::Moved { from })
+        }
+    }
+}
+
+/// Rewrites the paths under a moved folder
+///
+/// A folder moved out of a listed folder was tombstoned with its children,
+/// they are alive again until the folder is listed at its new path.
+fn move_children(conn: &Connection, from: &str, to: &str) -> rusqlite::Result<()> {
+    for table in ["files", "folders"] {
+        conn.execute(
+            &format!(
+                "UPDATE {} SET full_path = ?2 || substr(full_path, length(?1) + 1), deleted_at = NULL
+                WHERE substr(full_path, 1, length(?1) + 1) = ?1 || '/'",
+                table
+            ),
+            params![from, to],
+        )?;
+    }
+    Ok(())
+}
+
+/// Indexes every folder and file of the drive, picking up where an interrupted run stopped
+///
+/// Folders are listed from a queue, at most `workers` at once, and each listing
+/// is recorded in a single transaction that also marks the folder as listed.
+/// Listed folders are skipped by later runs as long as their node is unchanged,
+/// so a resumed run starts from the folders that weren't listed yet. A folder
+/// whose listing still fails after its retries is recorded in `failed_folders`
+/// and stays unlisted, the others carry on. The indexing is only complete
+/// once every folder was listed.
+pub async fn index<F>(
+    client: &impl DriveOps,
+    root: &NodeIdentity,
     pool: &Pool<SqliteConnectionManager>,
-) -> anyhow::Result<()> {
+    workers: usize,
+    shutdown: &AtomicBool,
+    progress_callback: F,
+) -> anyhow::Result<RefreshReport>
+where
+    F: Fn(&RefreshProgress),
+{
     {
         let conn = pool.get()?;
-        conn.execute_batch(&format!("PRAGMA key = '{}';", password))?;
-        conn.execute_batch(
-            "CREATE TABLE IF NOT EXISTS files (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                full_path TEXT NOT NULL UNIQUE,
-                file_name TEXT NOT NULL,
-                checked BOOLEAN NOT NULL DEFAULT 0,
-                node BLOB NOT NULL
-            );
-            CREATE TABLE IF NOT EXISTS folders (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                full_path TEXT NOT NULL UNIQUE,
-                folder_name TEXT NOT NULL,
-                checked BOOLEAN NOT NULL DEFAULT 0,
-                node BLOB NOT NULL
-            );",
+        if state(&conn, STATE_STARTED_AT)?.is_some() {
+            log::info!("Resuming the initial indexing");
+        } else {
+            set_state(&conn, STATE_STARTED_AT, &Utc::now().to_rfc3339())?;
+        }
+    }
+
+    let queue = pending_folders(pool, root, Queue::Unlisted).await?;
+    let report = crawl(client, pool, queue, Queue::Unlisted, workers, shutdown, progress_callback).await;
+
+    if report.failures.is_empty() && !report.interrupted {
+        set_state(&*pool.get()?, STATE_COMPLETED_AT, &Utc::now().to_rfc3339())?;
+    }
+    Ok(report)
+}
+
+/// Lists the folders of `failed_folders` again, along with the unlisted folders found under them
+///
+/// An incomplete index whose other folders are all listed is complete once
+/// none fails anymore.
+pub async fn retry_failed<F>(
+    client: &impl DriveOps,
+    pool: &Pool<SqliteConnectionManager>,
+    workers: usize,
+    shutdown: &AtomicBool,
+    progress_callback: F,
+) -> anyhow::Result<RefreshReport>
+where
+    F: Fn(&RefreshProgress),
+{
+    let queue = failed_queue(pool)?;
+    log::info!("Retrying {} folders", queue.len());
+    let report = crawl(client, pool, queue, Queue::Unlisted, workers, shutdown, progress_callback).await;
+
+    let conn = pool.get()?;
+    if report.failures.is_empty() && !report.interrupted && state(&conn, STATE_COMPLETED_AT)?.is_none() {
+        let unlisted: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM folders WHERE checked = 0 AND deleted_at IS NULL",
+            [],
+            |row| row.get(0),
         )?;
+        if unlisted == 0 {
+            set_state(&conn, STATE_COMPLETED_AT, &Utc::now().to_rfc3339())?;
+        }
+    }
+    Ok(report)
+}
+
+/// A folder whose last listing failed after its retries
+#[derive(Debug, Clone, Serialize)]
+pub struct FailedFolder {
+    pub path: String,
+    pub error: String,
+    /// Seconds since the epoch
+    pub failed_at: i64,
+}
+
+/// Folders whose listing failed, in path order
+pub fn failed_folders(pool: &Pool<SqliteConnectionManager>) -> anyhow::Result<Vec<FailedFolder>> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare("SELECT full_path, error, failed_at FROM failed_folders ORDER BY full_path")?;
+    let failed = stmt
+        .query_map([], |row| Ok(FailedFolder { path: row.get(0)?, error: row.get(1)?, failed_at: row.get(2)? }))?
+        .collect::<Result<_, _>>()?;
+    Ok(failed)
+}
+
+/// Queues the folders of `failed_folders`
+fn failed_queue(pool: &Pool<SqliteConnectionManager>) -> anyhow::Result<VecDeque<PendingFolder>> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare("SELECT full_path, identity FROM failed_folders ORDER BY full_path")?;
+    let rows: Vec<(String, Vec<u8>)> = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_, _>>()?;
+
+    let mut folders = VecDeque::with_capacity(rows.len());
+    for (path, identity) in rows {
+        match NodeIdentity::decode(identity.as_slice()) {
+            Ok(identity) if identity.is_complete() => folders.push_back(PendingFolder { path, identity }),
+            Ok(identity) => log::error!("Skipping failed folder /{} with the incomplete identity {}", path, identity),
+            Err(e) => log::error!("Skipping unreadable failed folder /{}: {}", path, e),
+        }
     }
+    Ok(folders)
+}
 
-    let mut file_count = 0;
-    recursive_list_file_root(
-        client,
-        identity,
-        "".to_string(),
-        &mut file_count,
-        &|count| println!("Indexed {} files...", count),
-        pool,
-    )
-        .await?;
+/// Records a folder whose listing failed for good, replacing an earlier failure
+async fn record_failure(pool: &Pool<SqliteConnectionManager>, folder: &PendingFolder, error: &anyhow::Error) -> anyhow::Result<()> {
+    let pool = pool.clone();
+    let node_id = link_id(folder.identity.node_id.as_ref()).unwrap_or_default();
+    let (path, identity, error) = (folder.path.clone(), folder.identity.encode_to_vec(), format!("{:#}", error));
+    tokio::task::spawn_blocking(move || {
+        pool.get()?.execute(
+            "INSERT INTO failed_folders (node_id, full_path, identity, error, failed_at) VALUES (?1, ?2, ?3, ?4, ?5)
+            ON CONFLICT(node_id) DO UPDATE SET
+                full_path = excluded.full_path, error = excluded.error, failed_at = excluded.failed_at",
+            params![node_id, path, identity, error, Utc::now().timestamp()],
+        )?;
+        Ok(())
+    })
+    .await?
+}
 
-    Ok(())
+/// Progress of an indexing or refresh, reported after every folder
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RefreshProgress {
+    /// Folders listed so far
+    pub folders_scanned: usize,
+    /// Folders left to list, including the ones found during this run
+    pub folders_pending: usize,
+    pub new_folders: usize,
+    pub new_files: usize,
+    /// Folders and files no longer on the drive, tombstoned in the index
+    pub deleted: usize,
+    /// Folders that couldn't be listed or recorded
+    pub failures: usize,
 }
 
-#[async_recursion]
-pub async fn recursive_list_file_root<F>(
-    client: &DriveClient,
-    identity: &NodeIdentity,
-    parent_folder: String,
-    file_count: &mut usize,
-    progress_callback: &F,
+/// Outcome of a refresh
+#[derive(Debug, Default)]
+pub struct RefreshReport {
+    pub progress: RefreshProgress,
+    /// Path and error of every folder that failed
+    pub failures: Vec<(String, anyhow::Error)>,
+    /// Set when the refresh stopped early because of `shutdown`
+    pub interrupted: bool,
+}
+
+/// A folder waiting to be listed
+struct PendingFolder {
+    path: String,
+    identity: NodeIdentity,
+}
+
+/// Which folders a listing queues in turn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Queue {
+    /// Folders that weren't listed yet, or changed since, for the initial indexing
+    Unlisted,
+    /// Folders that weren't indexed before, for a refresh that lists every known folder anyway
+    New,
+}
+
+/// Lists the root and every indexed folder again, recording the folders and files that appeared
+///
+/// At most `workers` listings run at once. A folder that fails is reported and
+/// skipped, the others carry on. Setting `shutdown` stops handing out folders,
+/// the listings in flight are still recorded.
+pub async fn refresh<F>(
+    client: &impl DriveOps,
+    root: &NodeIdentity,
     pool: &Pool<SqliteConnectionManager>,
-) -> anyhow::Result<()>
+    workers: usize,
+    shutdown: &AtomicBool,
+    progress_callback: F,
+) -> anyhow::Result<RefreshReport>
 where
-    F: Fn(usize) + Send + Sync,
+    F: Fn(&RefreshProgress),
 {
-    let children = client.get_folder_children(identity.clone()).await?;
-
-    for child in children {
-        let (is_folder, folder) = utils::node_is_folder(child.clone());
-        if is_folder {
-            if let Some(folder) = folder {
-                let folder_name = if parent_folder.is_empty() {
-                    folder.name.clone()
-                } else {
-                    format!("{}/{}", parent_folder, folder.name)
-                };
-                let new_identity = {
-                    let node_id = folder.node_identity.as_ref()
-                        .and_then(|ni| ni.node_id.clone())
-                        .or_else(|| identity.node_id.clone());
-                    let share_id = folder.node_identity.as_ref()
-                        .and_then(|ni| ni.share_id.clone())
-                        .or_else(|| identity.share_id.clone());
-                    let volume_id = folder.node_identity.as_ref()
-                        .and_then(|ni| ni.volume_id.clone())
-                        .or_else(|| identity.volume_id.clone());
-                    NodeIdentity { node_id, share_id, volume_id }
-                };
-
-                let folder_name_clone = folder.name.clone();
-                let full_path_clone = folder_name.clone();
-                let node_bytes = folder.to_bytes()?;
-                let pool_for_blocking = pool.clone();
-                tokio::task::spawn_blocking(move || {
-                    let conn = pool_for_blocking.get()?;
-                    conn.execute(
-                        "INSERT INTO folders (full_path, folder_name, checked, node) VALUES (?1, ?2, 0, ?3)
-                            ON CONFLICT(full_path) DO UPDATE SET node = excluded.node, folder_name = excluded.folder_name, checked = 0",
-                        params![full_path_clone, folder_name_clone, node_bytes],
-                    )?;
-                    Ok::<_, anyhow::Error>(())
-                })
-                .await??;
+    let queue = pending_folders(pool, root, Queue::New).await?;
+    Ok(crawl(client, pool, queue, Queue::New, workers, shutdown, progress_callback).await)
+}
+
+/// Lists the queued folders with at most `workers` listings in flight, queuing folders as `mode` says
+///
+/// A folder is queued once per run, even when its parent is queued too. Failed
+/// listings are retried as [`RetryPolicy::default`] says, a stop request cuts
+/// the waits short.
+async fn crawl<F>(
+    client: &impl DriveOps,
+    pool: &Pool<SqliteConnectionManager>,
+    mut queue: VecDeque<PendingFolder>,
+    mode: Queue,
+    workers: usize,
+    shutdown: &AtomicBool,
+    progress_callback: F,
+) -> RefreshReport
+where
+    F: Fn(&RefreshProgress),
+{
+    let mut queued: HashSet<String> = queue
+        .iter()
+        .filter_map(|folder| link_id(folder.identity.node_id.as_ref()))
+        .collect();
+    let mut report = RefreshReport::default();
+    let mut listings = FuturesUnordered::new();
 
-                recursive_list_file_root(client, &new_identity, folder_name, file_count, progress_callback, pool).await?;
+    loop {
+        while listings.len() < workers.max(1) && !shutdown.load(Ordering::Relaxed) {
+            let Some(folder) = queue.pop_front() else {
+                break;
+            };
+            listings.push(async move {
+                let children = RetryPolicy::default()
+                    .run(
+                        || client.get_folder_children(folder.identity.clone()),
+                        DriveError::is_retryable,
+                        shutdown::requested(),
+                    )
+                    .await;
+                (folder, children)
+            });
+        }
+
+        let Some((folder, children)) = listings.next().await else {
+            break;
+        };
+        report.progress.folders_scanned += 1;
+
+        let recorded = match children {
+            Ok(children) => record_children(pool, &folder, children, mode).await,
+            Err(e) => Err(e.into()),
+        };
+        match recorded {
+            Ok(listed) => {
+                report.progress.new_files += listed.new_files;
+                report.progress.new_folders += listed.new_folders;
+                report.progress.deleted += listed.deleted;
+                queue.extend(listed.folders.into_iter().filter(|folder| {
+                    link_id(folder.identity.node_id.as_ref()).is_none_or(|id| queued.insert(id))
+                }));
             }
-        } else {
-            let (is_file, file) = utils::node_is_file(child.clone());
-            if is_file {
-                *file_count += 1;
-                progress_callback(*file_count);
-                if let Some(file) = file {
-                    let file_name = file.name.clone();
-                    let full_path = if parent_folder.is_empty() {
-                        file_name.clone()
-                    } else {
-                        format!("{}/{}", parent_folder, file_name)
-                    };
-                    let node_bytes = file.to_bytes()?;
-                    let pool = pool.clone();
-                    let file_name_clone = file_name.clone();
-                    let full_path_clone = full_path.clone();
-                    tokio::task::spawn_blocking(move || {
-                        let conn = pool.get()?;
-                        conn.execute(
-                            "INSERT INTO files (full_path, file_name, checked, node) VALUES (?1, ?2, 0, ?3)
-        ON CONFLICT(full_path) DO UPDATE SET node = excluded.node, file_name = excluded.file_name, checked = 0",
-                            params![full_path_clone, file_name_clone, node_bytes],
-                        )?;
-                        Ok::<_, anyhow::Error>(())
-                    })
-                        .await??;
-                    println!("{}", full_path);
+            // cut short by the stop request, the folder is listed again by the next run
+            Err(_) if shutdown.load(Ordering::Relaxed) => queue.push_front(folder),
+            Err(e) => {
+                log::error!("Failed to list /{}: {:#}", folder.path, e);
+                if let Err(recording) = record_failure(pool, &folder, &e).await {
+                    log::warn!("Unable to record the failure of /{}: {:#}", folder.path, recording);
                 }
+                report.progress.failures += 1;
+                report.failures.push((folder.path, e));
             }
         }
+
+        report.progress.folders_pending = queue.len() + listings.len();
+        progress_callback(&report.progress);
+    }
+
+    report.interrupted = !queue.is_empty();
+    report
+}
+
+/// Loads the folders `mode` starts from, after the root, with identities completed from `root`
+async fn pending_folders(
+    pool: &Pool<SqliteConnectionManager>,
+    root: &NodeIdentity,
+    mode: Queue,
+) -> anyhow::Result<VecDeque<PendingFolder>> {
+    let sql = match mode {
+        Queue::Unlisted => "SELECT full_path, node FROM folders WHERE checked = 0 AND deleted_at IS NULL",
+        Queue::New => "SELECT full_path, node FROM folders WHERE deleted_at IS NULL",
+    };
+    let pool = pool.clone();
+    let rows: Vec<(String, Vec<u8>)> = tokio::task::spawn_blocking(move || {
+        let conn = pool.get()?;
+        let mut stmt = conn.prepare(sql)?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<_, _>>()?;
+        Ok::<_, anyhow::Error>(rows)
+    })
+    .await??;
+
+    let mut folders = VecDeque::with_capacity(rows.len() + 1);
+    folders.push_back(PendingFolder {
+        path: String::new(),
+        identity: root.clone(),
+    });
+    for (path, node) in rows {
+        let identity = FolderNode::decode(node.as_slice())
+            .map_err(anyhow::Error::from)
+            .and_then(|folder| Ok(folder.full_identity(root)?));
+        match identity {
+            Ok(identity) => folders.push_back(PendingFolder { path, identity }),
+            Err(e) => log::error!("Skipping unreadable folder /{}: {:#}", path, e),
+        }
+    }
+    Ok(folders)
+}
+
+/// What recording a listing changed
+struct Listed {
+    /// Subfolders to list in turn
+    folders: Vec<PendingFolder>,
+    new_folders: usize,
+    new_files: usize,
+    /// Children and descendants tombstoned
+    deleted: usize,
+}
+
+/// Records the children of a listed folder by node id, and marks the folder as listed
+///
+/// The whole listing is written in one transaction. Paths are taken from the
+/// folder's current row rather than the queued path, as a rename recorded
+/// since it was queued may have moved it.
+async fn record_children(
+    pool: &Pool<SqliteConnectionManager>,
+    parent: &PendingFolder,
+    children: Vec<NodeType>,
+    mode: Queue,
+) -> anyhow::Result<Listed> {
+    let rows = children
+        .iter()
+        .filter_map(|child| NodeRow::new(child, &parent.identity).transpose())
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let listed_ids: HashSet<String> = rows.iter().filter_map(|row| row.columns.node_id.clone()).collect();
+
+    let pool = pool.clone();
+    let parent_id = link_id(parent.identity.node_id.as_ref());
+    let queued_path = parent.path.clone();
+    tokio::task::spawn_blocking(move || {
+        let mut conn = pool.get()?;
+        let tx = conn.transaction()?;
+        let parent_path: String = tx
+            .query_row("SELECT full_path FROM folders WHERE node_id = ?1", params![parent_id], |row| row.get(0))
+            .optional()?
+            .unwrap_or(queued_path);
+        let mut listed = Listed { folders: Vec::new(), new_folders: 0, new_files: 0, deleted: 0 };
+
+        for row in rows {
+            let full_path = child_path(&parent_path, &row.name);
+            let upserted = upsert(&tx, &parent_path, &row)?;
+            match &upserted {
+                Upserted::New if row.is_folder => {
+                    log::info!("New folder detected: {}", full_path);
+                    listed.new_folders += 1;
+                }
+                Upserted::New => {
+                    log::debug!("New file detected: {}", full_path);
+                    listed.new_files += 1;
+                }
+                Upserted::Moved { from } => log::info!("Moved: {} -> {}", from, full_path),
+                Upserted::Existing => {}
+            }
+
+            let Some(identity) = row.identity.clone() else {
+                continue;
+            };
+            let queue = match mode {
+                Queue::Unlisted => !is_listed(&tx, &row)?,
+                Queue::New => upserted == Upserted::New,
+            };
+            if queue {
+                listed.folders.push(PendingFolder { path: full_path, identity });
+            } else {
+                log::debug!("Skipping listed folder {}", full_path);
+            }
+        }
+
+        listed.deleted = tombstone_missing(&tx, parent_id.as_deref(), &listed_ids)?;
+
+        // the root has no row, its completion is the indexing's
+        tx.execute("UPDATE folders SET checked = 1 WHERE node_id = ?1", params![parent_id])?;
+        tx.execute("DELETE FROM failed_folders WHERE node_id = ?1", params![parent_id.as_deref().unwrap_or_default()])?;
+        tx.commit()?;
+        Ok(listed)
+    })
+    .await?
+}
+
+/// Tombstones the children of a folder its listing no longer has, along with everything under them
+///
+/// Rows are kept with their `deleted_at` set, so the deletion can be passed
+/// on. Returns the number of rows tombstoned.
+fn tombstone_missing(conn: &Connection, parent_id: Option<&str>, listed: &HashSet<String>) -> rusqlite::Result<usize> {
+    let now = Utc::now().timestamp();
+    let mut deleted = 0;
+
+    for table in ["files", "folders"] {
+        let children: Vec<(String, String)> = conn
+            .prepare(&format!(
+                "SELECT node_id, full_path FROM {} WHERE parent_node_id = ?1 AND node_id IS NOT NULL AND deleted_at IS NULL",
+                table
+            ))?
+            .query_map(params![parent_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<_, _>>()?;
+
+        for (node_id, path) in children.into_iter().filter(|(node_id, _)| !listed.contains(node_id)) {
+            log::info!("Deleted: {}", path);
+            deleted += conn.execute(
+                &format!("UPDATE {} SET deleted_at = ?2 WHERE node_id = ?1", table),
+                params![node_id, now],
+            )?;
+            if table == "folders" {
+                conn.execute(
+                    "DELETE FROM failed_folders
+                    WHERE node_id = ?1 OR substr(full_path, 1, length(?2) + 1) = ?2 || '/'",
+                    params![node_id, path],
+                )?;
+                for under in ["files", "folders"] {
+                    deleted += conn.execute(
+                        &format!(
+                            "UPDATE {} SET deleted_at = ?2
+                            WHERE deleted_at IS NULL AND substr(full_path, 1, length(?1) + 1) = ?1 || '/'",
+                            under
+                        ),
+                        params![path, now],
+                    )?;
+                }
+            }
+        }
+    }
+    Ok(deleted)
+}
+
+fn is_listed(conn: &Connection, row: &NodeRow) -> rusqlite::Result<bool> {
+    let checked: Option<bool> = conn
+        .query_row(
+            "SELECT checked FROM folders WHERE node_id = ?1",
+            params![row.columns.node_id],
+            |r| r.get(0),
+        )
+        .optional()?;
+    Ok(checked.unwrap_or(false))
+}
+
+/// How a search pattern is matched against full paths
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchMode {
+    /// The path contains the pattern
+    Substring,
+    /// The whole path matches a glob, `*` also matches `/`
+    Glob,
+    /// The path contains a match of the regular expression
+    Regex,
+}
+
+/// A search of the index
+#[derive(Debug, Clone)]
+pub struct SearchQuery {
+    pub pattern: String,
+    pub mode: MatchMode,
+    pub case_sensitive: bool,
+    pub files: bool,
+    pub folders: bool,
+    /// Also matches nodes deleted from the drive since they were indexed
+    pub include_deleted: bool,
+    pub limit: Option<usize>,
+}
+
+/// A path of the index matching a search
+#[derive(Debug, Serialize)]
+pub struct SearchHit {
+    pub path: String,
+    pub kind: &'static str,
+    pub node_id: Option<String>,
+    pub size: Option<i64>,
+    /// Creation time of the active revision, in seconds since the epoch
+    pub modified_at: Option<i64>,
+    /// When the node was found deleted, in seconds since the epoch
+    pub deleted_at: Option<i64>,
+}
+
+/// Escapes `%`, `_` and the escape character itself for `LIKE ... ESCAPE '\'`
+fn like_literal(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+/// Finds the indexed folders and files whose path matches `query`, sorted by path
+///
+/// Substring and glob searches are filtered by SQLite. Regular expressions
+/// aren't supported by SQLite, so those rows are streamed and matched here.
+pub fn search(pool: &Pool<SqliteConnectionManager>, query: &SearchQuery) -> anyhow::Result<Vec<SearchHit>> {
+    let regex = match query.mode {
+        MatchMode::Regex => Some(
+            RegexBuilder::new(&query.pattern)
+                .case_insensitive(!query.case_sensitive)
+                .build()?,
+        ),
+        _ => None,
+    };
+
+    let (condition, pattern) = match (query.mode, query.case_sensitive) {
+        (MatchMode::Substring, false) => {
+            ("full_path LIKE ?1 ESCAPE '\\'", format!("%{}%", like_literal(&query.pattern)))
+        }
+        (MatchMode::Substring, true) => ("instr(full_path, ?1) > 0", query.pattern.clone()),
+        (MatchMode::Glob, false) => ("lower(full_path) GLOB lower(?1)", query.pattern.clone()),
+        (MatchMode::Glob, true) => ("full_path GLOB ?1", query.pattern.clone()),
+        (MatchMode::Regex, _) => ("?1 IS NOT NULL", String::new()),
+    };
+
+    let condition = if query.include_deleted {
+        condition.to_string()
+    } else {
+        format!("deleted_at IS NULL AND {}", condition)
+    };
+    let mut selects = Vec::new();
+    if query.folders {
+        selects.push(format!(
+            "SELECT full_path, 1, node_id, size, modified_at, deleted_at FROM folders WHERE {}",
+            condition
+        ));
+    }
+    if query.files {
+        selects.push(format!(
+            "SELECT full_path, 0, node_id, size, modified_at, deleted_at FROM files WHERE {}",
+            condition
+        ));
+    }
+    if selects.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    // the limit can only be pushed down when SQLite does all the matching
+    let limit = match (&regex, query.limit) {
+        (None, Some(limit)) => format!(" LIMIT {}", limit),
+        _ => String::new(),
+    };
+    let sql = format!("{} ORDER BY 1{}", selects.join(" UNION ALL "), limit);
+
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(&sql)?;
+    let mut rows = stmt.query(params![pattern])?;
+
+    let mut hits = Vec::new();
+    while let Some(row) = rows.next()? {
+        let path: String = row.get(0)?;
+        if regex.as_ref().is_some_and(|regex| !regex.is_match(&path)) {
+            continue;
+        }
+
+        let is_folder: bool = row.get(1)?;
+        hits.push(SearchHit {
+            path,
+            kind: if is_folder { "folder" } else { "file" },
+            node_id: row.get(2)?,
+            size: row.get(3)?,
+            modified_at: row.get(4)?,
+            deleted_at: row.get(5)?,
+        });
+        if query.limit.is_some_and(|limit| hits.len() >= limit) {
+            break;
+        }
+    }
+    Ok(hits)
+}
+
+/// Number of folders and files in the index
+#[derive(Debug, Default, Serialize)]
+pub struct IndexStats {
+    pub folders: i64,
+    pub files: i64,
+}
+
+/// Counts the indexed folders and files
+pub fn stats(pool: &Pool<SqliteConnectionManager>) -> anyhow::Result<IndexStats> {
+    let conn = pool.get()?;
+    Ok(IndexStats {
+        folders: conn.query_row("SELECT COUNT(*) FROM folders WHERE deleted_at IS NULL", [], |row| row.get(0))?,
+        files: conn.query_row("SELECT COUNT(*) FROM files WHERE deleted_at IS NULL", [], |row| row.get(0))?,
+    })
+}
+
+/// Bytes of the files indexed at or under a path
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PathUsage {
+    pub path: String,
+    pub bytes: i64,
+}
+
+/// Space taken by the indexed files, as summed by `usage`
+#[derive(Debug, Default, Serialize)]
+pub struct Usage {
+    pub bytes: i64,
+    /// Largest files, biggest first
+    pub files: Vec<PathUsage>,
+    /// Folders holding the most bytes, subfolders included, biggest first
+    pub folders: Vec<PathUsage>,
+}
+
+/// Sums the sizes of the indexed files, keeping the `top` largest files and folders
+///
+/// Only the active revisions are counted, older revisions and the trash
+/// take quota the index doesn't know about.
+pub fn usage(pool: &Pool<SqliteConnectionManager>, top: usize) -> anyhow::Result<Usage> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare("SELECT full_path, COALESCE(size, 0) FROM files WHERE deleted_at IS NULL")?;
+    let mut rows = stmt.query([])?;
+
+    let mut usage = Usage::default();
+    let mut files = Vec::new();
+    let mut folders: HashMap<String, i64> = HashMap::new();
+    while let Some(row) = rows.next()? {
+        let path: String = row.get(0)?;
+        let bytes: i64 = row.get(1)?;
+        usage.bytes += bytes;
+        for (at, _) in path.match_indices('/') {
+            *folders.entry(path[..at].to_string()).or_default() += bytes;
+        }
+        files.push(PathUsage { path, bytes });
+    }
+
+    let largest = |mut paths: Vec<PathUsage>| {
+        paths.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.path.cmp(&b.path)));
+        paths.truncate(top);
+        paths
+    };
+    usage.files = largest(files);
+    usage.folders = largest(folders.into_iter().map(|(path, bytes)| PathUsage { path, bytes }).collect());
+    Ok(usage)
+}
+
+/// Reads the indexed node of the folder at `path`
+pub fn folder_node(pool: &Pool<SqliteConnectionManager>, path: &str) -> anyhow::Result<Option<FolderNode>> {
+    let node: Option<Vec<u8>> = pool
+        .get()?
+        .query_row(
+            "SELECT node FROM folders WHERE full_path = ?1 AND deleted_at IS NULL",
+            params![path],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(node.map(|node| FolderNode::decode(node.as_slice())).transpose()?)
+}
+
+/// Finds the path of the file or folder with the link id `node_id`, `None` when it isn't indexed
+pub fn path_of(pool: &Pool<SqliteConnectionManager>, node_id: &str) -> anyhow::Result<Option<String>> {
+    let conn = pool.get()?;
+    for table in ["files", "folders"] {
+        let path = conn
+            .query_row(
+                &format!("SELECT full_path FROM {} WHERE node_id = ?1 AND deleted_at IS NULL", table),
+                params![node_id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if path.is_some() {
+            return Ok(path);
+        }
+    }
+    Ok(None)
+}
+
+/// Records a file uploaded into the folder `parent` at `parent_path`, so it is found before the next refresh
+pub fn record_file(
+    pool: &Pool<SqliteConnectionManager>,
+    parent: &NodeIdentity,
+    parent_path: &str,
+    file: &FileNode,
+) -> anyhow::Result<()> {
+    let child = NodeType {
+        node_type: Some(node_type::NodeType::FileNode(file.clone())),
+    };
+    if let Some(row) = NodeRow::new(&child, parent)? {
+        upsert(&*pool.get()?, parent_path, &row)?;
     }
     Ok(())
-}
\ No newline at end of file
+}
+
+/// A rename or move of an indexed node, from one path to another
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Move {
+    pub from: String,
+    pub to: String,
+    pub is_folder: bool,
+}
+
+/// Folders and files indexed under a folder, with paths relative to it
+#[derive(Debug, Default)]
+pub struct Subtree {
+    pub folders: Vec<String>,
+    pub files: Vec<(String, FileNode)>,
+    /// Moves within the folder, most recent first
+    pub moves: Vec<Move>,
+}
+
+/// A remote file, or the contents of a remote folder
+#[derive(Debug)]
+pub enum IndexedNode {
+    File(Box<FileNode>),
+    Folder(Subtree),
+}
+
+/// Looks up the file or folder at `path`, `None` when it isn't indexed
+pub fn lookup(pool: &Pool<SqliteConnectionManager>, path: &str) -> anyhow::Result<Option<IndexedNode>> {
+    let node: Option<Vec<u8>> = pool
+        .get()?
+        .query_row(
+            "SELECT node FROM files WHERE full_path = ?1 AND deleted_at IS NULL",
+            params![path],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if let Some(node) = node {
+        return Ok(Some(IndexedNode::File(Box::new(FileNode::decode(node.as_slice())?))));
+    }
+    Ok(subtree(pool, path)?.map(IndexedNode::Folder))
+}
+
+/// Reads what the index holds under the folder at `path`, `None` when it isn't an indexed folder
+///
+/// `path` has no leading `/`, the root of the share is the empty path. Files
+/// whose stored node can't be decoded are skipped.
+pub fn subtree(pool: &Pool<SqliteConnectionManager>, path: &str) -> anyhow::Result<Option<Subtree>> {
+    let conn = pool.get()?;
+    if !path.is_empty() {
+        let indexed = conn
+            .query_row(
+                "SELECT 1 FROM folders WHERE full_path = ?1 AND deleted_at IS NULL",
+                params![path],
+                |_| Ok(()),
+            )
+            .optional()?;
+        if indexed.is_none() {
+            return Ok(None);
+        }
+    }
+
+    let under = "WHERE deleted_at IS NULL AND (?1 = '' OR substr(full_path, 1, length(?1) + 1) = ?1 || '/')
+        ORDER BY full_path";
+    let relative = |full_path: String| match path {
+        "" => full_path,
+        _ => full_path[path.len() + 1..].to_string(),
+    };
+
+    let mut subtree = Subtree::default();
+    let mut stmt = conn.prepare(&format!("SELECT full_path FROM folders {}", under))?;
+    for full_path in stmt.query_map(params![path], |row| row.get::<_, String>(0))? {
+        subtree.folders.push(relative(full_path?));
+    }
+
+    let mut stmt = conn.prepare(&format!("SELECT full_path, node FROM files {}", under))?;
+    let mut rows = stmt.query(params![path])?;
+    while let Some(row) = rows.next()? {
+        let full_path: String = row.get(0)?;
+        let node: Vec<u8> = row.get(1)?;
+        match FileNode::decode(node.as_slice()) {
+            Ok(file) => subtree.files.push((relative(full_path), file)),
+            Err(e) => log::warn!("Skipping /{}, its indexed node can't be decoded: {}", full_path, e),
+        }
+    }
+
+    subtree.moves = moves(&conn, path)?;
+    Ok(Some(subtree))
+}
+
+/// Moves logged within the folder at `path`, with paths relative to it, most recent first
+pub fn moves_within(pool: &Pool<SqliteConnectionManager>, path: &str) -> anyhow::Result<Vec<Move>> {
+    Ok(moves(&*pool.get()?, path)?)
+}
+
+/// Reads the moves logged from a path under the folder at `path` to another one, most recent first
+///
+/// Moves into or out of the folder are left out, their other end isn't part of it.
+fn moves(conn: &Connection, path: &str) -> rusqlite::Result<Vec<Move>> {
+    let relative = |full_path: &str| match path {
+        "" => Some(full_path.to_string()),
+        _ => full_path.strip_prefix(path)?.strip_prefix('/').map(str::to_string),
+    };
+
+    let mut stmt = conn.prepare(
+        "SELECT from_path, to_path, is_folder FROM moves
+        WHERE ?1 = '' OR (substr(from_path, 1, length(?1) + 1) = ?1 || '/' AND substr(to_path, 1, length(?1) + 1) = ?1 || '/')
+        ORDER BY id DESC",
+    )?;
+    let rows = stmt.query_map(params![path], |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get(2)?))
+    })?;
+
+    let mut moves = Vec::new();
+    for row in rows {
+        let (from, to, is_folder) = row?;
+        if let (Some(from), Some(to)) = (relative(&from), relative(&to)) {
+            moves.push(Move { from, to, is_folder });
+        }
+    }
+    Ok(moves)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::remote::fake::FakeDrive;
+    use crate::remote::main_share;
+
+    fn row(node_id: &str, name: &str, is_folder: bool) -> NodeRow {
+        NodeRow {
+            name: name.to_string(),
+            is_folder,
+            node: Vec::new(),
+            columns: NodeColumns {
+                node_id: Some(node_id.to_string()),
+                ..Default::default()
+            },
+            identity: None,
+        }
+    }
+
+    fn paths(conn: &Connection, table: &str) -> Vec<String> {
+        conn.prepare(&format!("SELECT full_path FROM {} ORDER BY full_path", table))
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn renamed_folders_keep_one_row_and_move_their_children() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        schema::migrate(&mut conn).unwrap();
+
+        assert_eq!(upsert(&conn, "", &row("folder", "Old", true)).unwrap(), Upserted::New);
+        assert_eq!(upsert(&conn, "Old", &row("file", "a.txt", false)).unwrap(), Upserted::New);
+        assert_eq!(upsert(&conn, "Old", &row("file", "a.txt", false)).unwrap(), Upserted::Existing);
+
+        assert_eq!(
+            upsert(&conn, "", &row("folder", "New", true)).unwrap(),
+            Upserted::Moved { from: "Old".to_string() }
+        );
+        assert_eq!(paths(&conn, "folders"), ["New"]);
+        assert_eq!(paths(&conn, "files"), ["New/a.txt"]);
+    }
+
+    #[test]
+    fn folder_renames_rewrite_every_path_under_them_and_log_one_move() {
+        let pool = Pool::builder().max_size(1).build(SqliteConnectionManager::memory()).unwrap();
+        let mut conn = pool.get().unwrap();
+        schema::migrate(&mut conn).unwrap();
+
+        upsert(&conn, "", &row("archive", "Archive", true)).unwrap();
+        upsert(&conn, "Archive", &row("old", "Old", true)).unwrap();
+        upsert(&conn, "Archive/Old", &row("sub", "sub", true)).unwrap();
+        for n in 0..2000 {
+            let parent = if n % 2 == 0 { "Archive/Old" } else { "Archive/Old/sub" };
+            upsert(&conn, parent, &row(&format!("file-{}", n), &format!("{}.txt", n), false)).unwrap();
+        }
+
+        upsert(&conn, "Archive", &row("old", "New", true)).unwrap();
+        let files = paths(&conn, "files");
+        assert_eq!(files.len(), 2000);
+        assert!(files.iter().all(|path| path.starts_with("Archive/New/")));
+        assert!(files.contains(&"Archive/New/sub/1999.txt".to_string()));
+        assert_eq!(paths(&conn, "folders"), ["Archive", "Archive/New", "Archive/New/sub"]);
+
+        let moved = Move { from: "Old".to_string(), to: "New".to_string(), is_folder: true };
+        assert_eq!(moves(&conn, "Archive").unwrap(), [moved]);
+        assert!(moves(&conn, "Archive/New").unwrap().is_empty());
+    }
+
+    #[test]
+    fn a_new_node_replaces_the_row_of_the_node_that_held_its_path() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        schema::migrate(&mut conn).unwrap();
+
+        upsert(&conn, "", &row("deleted", "a.txt", false)).unwrap();
+        assert_eq!(upsert(&conn, "", &row("uploaded", "a.txt", false)).unwrap(), Upserted::New);
+
+        let node_id: String = conn
+            .query_row("SELECT node_id FROM files WHERE full_path = 'a.txt'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(node_id, "uploaded");
+    }
+
+    pub(super) fn indexed_pool() -> Pool<SqliteConnectionManager> {
+        let pool = Pool::builder().max_size(1).build(SqliteConnectionManager::memory()).unwrap();
+        let mut conn = pool.get().unwrap();
+        schema::migrate(&mut conn).unwrap();
+        upsert(&conn, "", &row("photos", "Photos", true)).unwrap();
+        upsert(&conn, "Photos", &row("beach", "Beach_2024.JPG", false)).unwrap();
+        upsert(&conn, "Photos", &row("notes", "notes.txt", false)).unwrap();
+        upsert(&conn, "", &row("budget", "Budget 2024.ods", false)).unwrap();
+        drop(conn);
+        pool
+    }
+
+    fn search_paths(pool: &Pool<SqliteConnectionManager>, mode: MatchMode, pattern: &str) -> Vec<String> {
+        let query = SearchQuery {
+            pattern: pattern.to_string(),
+            mode,
+            case_sensitive: false,
+            files: true,
+            folders: true,
+            include_deleted: false,
+            limit: None,
+        };
+        search(pool, &query).unwrap().into_iter().map(|hit| hit.path).collect()
+    }
+
+    #[test]
+    fn substring_searches_ignore_case_and_take_wildcards_literally() {
+        let pool = indexed_pool();
+        assert_eq!(search_paths(&pool, MatchMode::Substring, "2024"), ["Budget 2024.ods", "Photos/Beach_2024.JPG"]);
+        assert_eq!(search_paths(&pool, MatchMode::Substring, "h_2"), ["Photos/Beach_2024.JPG"]);
+        assert!(search_paths(&pool, MatchMode::Substring, "h%2").is_empty());
+        assert_eq!(search_paths(&pool, MatchMode::Substring, "PHOTOS"), ["Photos", "Photos/Beach_2024.JPG", "Photos/notes.txt"]);
+    }
+
+    #[test]
+    fn glob_and_regex_searches() {
+        let pool = indexed_pool();
+        assert_eq!(search_paths(&pool, MatchMode::Glob, "*.jpg"), ["Photos/Beach_2024.JPG"]);
+        assert_eq!(search_paths(&pool, MatchMode::Glob, "photos/*"), ["Photos/Beach_2024.JPG", "Photos/notes.txt"]);
+        assert_eq!(search_paths(&pool, MatchMode::Regex, r"\d{4}\.ods$"), ["Budget 2024.ods"]);
+    }
+
+    #[test]
+    fn searches_honour_case_kinds_and_limits() {
+        let pool = indexed_pool();
+        let mut query = SearchQuery {
+            pattern: "photos".to_string(),
+            mode: MatchMode::Substring,
+            case_sensitive: true,
+            files: true,
+            folders: true,
+            include_deleted: false,
+            limit: None,
+        };
+        assert!(search(&pool, &query).unwrap().is_empty());
+
+        query.case_sensitive = false;
+        query.folders = false;
+        query.limit = Some(1);
+        let hits = search(&pool, &query).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!((hits[0].path.as_str(), hits[0].kind, hits[0].node_id.as_deref()), ("Photos/Beach_2024.JPG", "file", Some("beach")));
+    }
+
+    #[test]
+    fn usage_sums_file_sizes_into_every_parent_folder() {
+        let pool = indexed_pool();
+        let conn = pool.get().unwrap();
+        for (path, size) in [("Photos/Beach_2024.JPG", 300), ("Photos/notes.txt", 20), ("Budget 2024.ods", 100)] {
+            conn.execute("UPDATE files SET size = ?1 WHERE full_path = ?2", params![size, path]).unwrap();
+        }
+        upsert(&conn, "Photos", &row("old", "Old", true)).unwrap();
+        upsert(&conn, "Photos/Old", &row("scan", "scan.pdf", false)).unwrap();
+        conn.execute("UPDATE files SET size = 50 WHERE node_id = 'scan'", []).unwrap();
+        drop(conn);
+
+        let usage = usage(&pool, 2).unwrap();
+        let sizes = |paths: &[PathUsage]| paths.iter().map(|usage| (usage.path.clone(), usage.bytes)).collect::<Vec<_>>();
+        assert_eq!(usage.bytes, 470);
+        assert_eq!(
+            sizes(&usage.files),
+            [("Photos/Beach_2024.JPG".to_string(), 300), ("Budget 2024.ods".to_string(), 100)]
+        );
+        assert_eq!(sizes(&usage.folders), [("Photos".to_string(), 370), ("Photos/Old".to_string(), 50)]);
+    }
+
+    #[test]
+    fn subtrees_hold_the_paths_under_a_folder() {
+        let pool = indexed_pool();
+
+        let photos = subtree(&pool, "Photos").unwrap().unwrap();
+        assert!(photos.folders.is_empty());
+        let files: Vec<&str> = photos.files.iter().map(|(path, _)| path.as_str()).collect();
+        assert_eq!(files, ["Beach_2024.JPG", "notes.txt"]);
+
+        let root = subtree(&pool, "").unwrap().unwrap();
+        assert_eq!(root.folders, ["Photos"]);
+        assert_eq!(root.files.len(), 3);
+
+        assert!(subtree(&pool, "Budget 2024.ods").unwrap().is_none());
+        assert!(subtree(&pool, "Phot").unwrap().is_none());
+
+        assert!(matches!(lookup(&pool, "Budget 2024.ods").unwrap(), Some(IndexedNode::File(_))));
+        assert!(matches!(lookup(&pool, "Photos").unwrap(), Some(IndexedNode::Folder(_))));
+        assert!(lookup(&pool, "Photos/missing.txt").unwrap().is_none());
+
+        assert_eq!(path_of(&pool, "beach").unwrap().as_deref(), Some("Photos/Beach_2024.JPG"));
+        assert_eq!(path_of(&pool, "photos").unwrap().as_deref(), Some("Photos"));
+        assert!(path_of(&pool, "missing").unwrap().is_none());
+    }
+
+    #[test]
+    fn listed_folders_stay_listed_until_their_node_changes() {
+        let pool = indexed_pool();
+        let conn = pool.get().unwrap();
+        conn.execute("UPDATE folders SET checked = 1", []).unwrap();
+        let photos = row("photos", "Photos", true);
+
+        upsert(&conn, "", &photos).unwrap();
+        assert!(is_listed(&conn, &photos).unwrap());
+
+        let changed = NodeRow { node: vec![1], ..row("photos", "Photos", true) };
+        upsert(&conn, "", &changed).unwrap();
+        assert!(!is_listed(&conn, &changed).unwrap());
+
+        conn.execute("UPDATE folders SET checked = 1", []).unwrap();
+        set_state(&conn, STATE_COMPLETED_AT, "now").unwrap();
+        drop(conn);
+        assert!(is_complete(&pool).unwrap());
+
+        reset(&pool).unwrap();
+        assert!(!is_complete(&pool).unwrap());
+        assert!(!is_listed(&pool.get().unwrap(), &changed).unwrap());
+    }
+
+    fn folder(node_id: &str, name: &str) -> NodeType {
+        NodeType {
+            node_type: Some(node_type::NodeType::FolderNode(FolderNode {
+                node_identity: Some(NodeIdentity {
+                    node_id: Some(LinkId { value: node_id.to_string() }),
+                    ..Default::default()
+                }),
+                name: name.to_string(),
+                ..Default::default()
+            })),
+        }
+    }
+
+    fn pending(node_id: &str, path: &str) -> PendingFolder {
+        use proton_sdk_sys::protobufs::drive::{ShareId, VolumeId};
+
+        PendingFolder {
+            path: path.to_string(),
+            identity: NodeIdentity {
+                node_id: Some(LinkId { value: node_id.to_string() }),
+                share_id: Some(ShareId { value: "share".to_string() }),
+                volume_id: Some(VolumeId { value: "volume".to_string() }),
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn listings_mark_their_folder_listed_and_queue_the_unlisted_subfolders() {
+        let pool = indexed_pool();
+        let children = || vec![folder("photos", "Photos"), folder("music", "Music")];
+
+        let listed = record_children(&pool, &pending("root", ""), children(), Queue::Unlisted).await.unwrap();
+        let queued: Vec<&str> = listed.folders.iter().map(|folder| folder.path.as_str()).collect();
+        assert_eq!(queued, ["Photos", "Music"]);
+        assert_eq!(listed.new_folders, 1);
+
+        let photos = record_children(&pool, &pending("photos", "Photos"), Vec::new(), Queue::Unlisted).await.unwrap();
+        assert!(photos.folders.is_empty());
+
+        let listed = record_children(&pool, &pending("root", ""), children(), Queue::Unlisted).await.unwrap();
+        let queued: Vec<&str> = listed.folders.iter().map(|folder| folder.path.as_str()).collect();
+        assert_eq!(queued, ["Music"]);
+
+        let refreshed = record_children(&pool, &pending("root", ""), children(), Queue::New).await.unwrap();
+        assert!(refreshed.folders.is_empty());
+    }
+
+    #[tokio::test]
+    async fn failed_folders_are_kept_for_a_retry_until_listed() {
+        let pool = indexed_pool();
+        let photos = pending("photos", "Photos");
+        record_failure(&pool, &photos, &anyhow::anyhow!("timed out")).await.unwrap();
+        record_failure(&pool, &photos, &anyhow::anyhow!("rate limited")).await.unwrap();
+
+        let failed = failed_folders(&pool).unwrap();
+        assert_eq!(failed.len(), 1);
+        assert_eq!((failed[0].path.as_str(), failed[0].error.as_str()), ("Photos", "rate limited"));
+        let queue = failed_queue(&pool).unwrap();
+        assert_eq!(queue[0].identity, photos.identity);
+
+        // a folder can't be listed again without its share and volume ids
+        let partial = PendingFolder {
+            path: "Music".to_string(),
+            identity: NodeIdentity::builder().node_id("music").build(),
+        };
+        record_failure(&pool, &partial, &anyhow::anyhow!("timed out")).await.unwrap();
+        assert_eq!(failed_folders(&pool).unwrap().len(), 2);
+        let queue = failed_queue(&pool).unwrap();
+        let queued: Vec<&str> = queue.iter().map(|folder| folder.path.as_str()).collect();
+        assert_eq!(queued, ["Photos"]);
+
+        record_children(&pool, &photos, Vec::new(), Queue::Unlisted).await.unwrap();
+        let failed = failed_folders(&pool).unwrap();
+        assert_eq!(failed.iter().map(|folder| folder.path.as_str()).collect::<Vec<_>>(), ["Music"]);
+    }
+
+    #[tokio::test]
+    async fn listings_use_the_current_path_of_their_folder() {
+        let pool = indexed_pool();
+        upsert(&pool.get().unwrap(), "", &row("photos", "Pictures", true)).unwrap();
+
+        let listed = record_children(&pool, &pending("photos", "Photos"), vec![folder("trips", "Trips")], Queue::New)
+            .await
+            .unwrap();
+        assert_eq!(listed.folders[0].path, "Pictures/Trips");
+    }
+
+    fn file(node_id: &str, name: &str) -> NodeType {
+        NodeType {
+            node_type: Some(node_type::NodeType::FileNode(FileNode {
+                node_identity: Some(NodeIdentity {
+                    node_id: Some(LinkId { value: node_id.to_string() }),
+                    ..Default::default()
+                }),
+                name: name.to_string(),
+                ..Default::default()
+            })),
+        }
+    }
+
+    #[tokio::test]
+    async fn children_missing_from_a_listing_are_tombstoned() {
+        let pool = indexed_pool();
+        let root = || pending("root", "");
+        let photos = || pending("photos", "Photos");
+        record_children(&pool, &root(), vec![folder("photos", "Photos"), file("budget", "Budget 2024.ods")], Queue::New)
+            .await
+            .unwrap();
+        record_children(&pool, &photos(), vec![file("beach", "Beach_2024.JPG"), file("notes", "notes.txt")], Queue::New)
+            .await
+            .unwrap();
+
+        let listed = record_children(&pool, &photos(), vec![file("beach", "Beach_2024.JPG")], Queue::New).await.unwrap();
+        assert_eq!(listed.deleted, 1);
+        assert_eq!(search_paths(&pool, MatchMode::Substring, "notes"), Vec::<String>::new());
+
+        let query = SearchQuery {
+            pattern: "notes".to_string(),
+            mode: MatchMode::Substring,
+            case_sensitive: false,
+            files: true,
+            folders: true,
+            include_deleted: true,
+            limit: None,
+        };
+        let hits = search(&pool, &query).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert!(hits[0].deleted_at.is_some());
+
+        let listed = record_children(&pool, &root(), vec![file("budget", "Budget 2024.ods")], Queue::New).await.unwrap();
+        assert_eq!(listed.deleted, 2);
+        assert!(subtree(&pool, "Photos").unwrap().is_none());
+        assert_eq!(stats(&pool).unwrap().files, 1);
+
+        record_children(&pool, &root(), vec![folder("photos", "Photos"), file("budget", "Budget 2024.ods")], Queue::New)
+            .await
+            .unwrap();
+        assert!(lookup(&pool, "Photos").unwrap().is_some());
+    }
+
+    #[test]
+    fn uploaded_files_are_recorded_under_their_folder() {
+        let pool = indexed_pool();
+        assert!(folder_node(&pool, "Photos").unwrap().is_some());
+        assert!(folder_node(&pool, "Photos/notes.txt").unwrap().is_none());
+
+        let file = FileNode {
+            node_identity: Some(NodeIdentity {
+                node_id: Some(LinkId { value: "uploaded".to_string() }),
+                ..Default::default()
+            }),
+            name: "upload.txt".to_string(),
+            ..Default::default()
+        };
+        record_file(&pool, &pending("photos", "Photos").identity, "Photos", &file).unwrap();
+
+        let hits = search_paths(&pool, MatchMode::Substring, "upload");
+        assert_eq!(hits, ["Photos/upload.txt"]);
+    }
+
+    fn empty_pool() -> Pool<SqliteConnectionManager> {
+        let pool = Pool::builder().max_size(1).build(SqliteConnectionManager::memory()).unwrap();
+        schema::migrate(&mut pool.get().unwrap()).unwrap();
+        pool
+    }
+
+    fn file_paths(subtree: &Subtree) -> Vec<&str> {
+        subtree.files.iter().map(|(path, _)| path.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn indexing_records_the_drive_and_refreshes_follow_its_changes() {
+        let drive = FakeDrive::new();
+        drive.add_file("Photos/beach.jpg", b"jpeg");
+        drive.add_file("Documents/Old/a.txt", b"a");
+        drive.add_file("notes.txt", b"notes");
+        let (_, root) = main_share(&drive).await.unwrap();
+        let pool = empty_pool();
+        let shutdown = AtomicBool::new(false);
+
+        let report = index(&drive, &root, &pool, 2, &shutdown, |_| {}).await.unwrap();
+        assert!(report.failures.is_empty() && !report.interrupted);
+        assert_eq!((report.progress.folders_scanned, report.progress.new_files), (4, 3));
+        assert!(is_complete(&pool).unwrap());
+        let indexed = subtree(&pool, "").unwrap().unwrap();
+        assert_eq!(indexed.folders, ["Documents", "Documents/Old", "Photos"]);
+        assert_eq!(file_paths(&indexed), ["Documents/Old/a.txt", "Photos/beach.jpg", "notes.txt"]);
+
+        drive.rename("Documents/Old", "Archive/Old");
+        drive.remove("Photos/beach.jpg");
+        drive.add_file("Photos/sunset.jpg", b"jpeg");
+        drive.add_file("notes.txt", b"more notes");
+        let report = refresh(&drive, &root, &pool, 2, &shutdown, |_| {}).await.unwrap();
+        assert!(report.failures.is_empty());
+        assert_eq!((report.progress.new_folders, report.progress.new_files), (1, 1));
+
+        let indexed = subtree(&pool, "").unwrap().unwrap();
+        assert_eq!(indexed.folders, ["Archive", "Archive/Old", "Documents", "Photos"]);
+        assert_eq!(file_paths(&indexed), ["Archive/Old/a.txt", "Photos/sunset.jpg", "notes.txt"]);
+        let moved = Move { from: "Documents/Old".to_string(), to: "Archive/Old".to_string(), is_folder: true };
+        assert_eq!(indexed.moves, [moved]);
+        let notes = &indexed.files[2].1;
+        assert_eq!(notes.active_revision.as_ref().and_then(|revision| revision.size), Some(10));
+    }
+
+    #[tokio::test]
+    async fn folders_failing_to_list_leave_the_index_incomplete_until_retried() {
+        let drive = FakeDrive::new();
+        drive.add_file("Photos/beach.jpg", b"jpeg");
+        drive.add_file("Documents/a.txt", b"a");
+        drive.fail_listing("Photos", true);
+        let (_, root) = main_share(&drive).await.unwrap();
+        let pool = empty_pool();
+        let shutdown = AtomicBool::new(false);
+
+        let report = index(&drive, &root, &pool, 1, &shutdown, |_| {}).await.unwrap();
+        let failed: Vec<&str> = report.failures.iter().map(|(path, _)| path.as_str()).collect();
+        assert_eq!(failed, ["Photos"]);
+        assert!(!is_complete(&pool).unwrap());
+        assert_eq!(failed_folders(&pool).unwrap()[0].path, "Photos");
+        assert!(lookup(&pool, "Documents/a.txt").unwrap().is_some());
+
+        drive.fail_listing("Photos", false);
+        let report = retry_failed(&drive, &pool, 1, &shutdown, |_| {}).await.unwrap();
+        assert!(report.failures.is_empty());
+        assert!(is_complete(&pool).unwrap());
+        assert!(failed_folders(&pool).unwrap().is_empty());
+        assert!(lookup(&pool, "Photos/beach.jpg").unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn an_interrupted_indexing_resumes_where_it_stopped() {
+        let drive = FakeDrive::new();
+        drive.add_file("Photos/beach.jpg", b"jpeg");
+        let (_, root) = main_share(&drive).await.unwrap();
+        let pool = empty_pool();
+
+        let report = index(&drive, &root, &pool, 1, &AtomicBool::new(true), |_| {}).await.unwrap();
+        assert!(report.interrupted);
+        assert!(!is_complete(&pool).unwrap());
+
+        let report = index(&drive, &root, &pool, 1, &AtomicBool::new(false), |_| {}).await.unwrap();
+        assert!(!report.interrupted);
+        assert!(is_complete(&pool).unwrap());
+        assert!(lookup(&pool, "Photos/beach.jpg").unwrap().is_some());
+    }
+
+    #[test]
+    fn unreadable_indexes_report_a_wrong_password() {
+        let path = std::env::temp_dir().join(format!("proton-drive-index-{}.db", std::process::id()));
+        fs::write(&path, vec![0x5a_u8; 4096]).unwrap();
+
+        let error = open(&path, Some("key".to_string())).unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(error.downcast_ref::<IndexError>(), Some(IndexError::WrongPassword(_))));
+    }
+
+    #[test]
+    fn plain_indexes_are_encrypted_once_a_key_is_given() {
+        let path = std::env::temp_dir().join(format!("proton-drive-plain-index-{}.db", std::process::id()));
+        let pool = open(&path, None).unwrap();
+        set_state(&pool.get().unwrap(), STATE_STARTED_AT, "now").unwrap();
+        drop(pool);
+
+        let reopened = open(&path, Some("key".to_string())).map(|pool| state(&pool.get().unwrap(), STATE_STARTED_AT).unwrap());
+        let plain = Connection::open(&path).unwrap().query_row("SELECT count(*) FROM sqlite_master", [], |row| row.get::<_, i64>(0));
+        for leftover in ["", "-wal", "-shm"] {
+            let _ = fs::remove_file(format!("{}{}", path.display(), leftover));
+        }
+        assert_eq!(reopened.unwrap().as_deref(), Some("now"));
+        assert!(plain.is_err());
+    }
+
+    #[test]
+    fn keyed_indexes_are_encrypted() {
+        let path = std::env::temp_dir().join(format!("proton-drive-keyed-index-{}.db", std::process::id()));
+        let pool = open(&path, Some("key".to_string())).unwrap();
+        set_state(&pool.get().unwrap(), STATE_STARTED_AT, "now").unwrap();
+        drop(pool);
+
+        let plain = Connection::open(&path).unwrap().query_row("SELECT count(*) FROM sqlite_master", [], |row| row.get::<_, i64>(0));
+        let reopened = open(&path, Some("key".to_string())).map(|pool| state(&pool.get().unwrap(), STATE_STARTED_AT).unwrap());
+        let wrong = open(&path, Some("other".to_string())).unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert!(plain.is_err());
+        assert_eq!(reopened.unwrap().as_deref(), Some("now"));
+        assert!(matches!(wrong.downcast_ref::<IndexError>(), Some(IndexError::WrongPassword(_))));
+    }
+}
@@ -0,0 +1,282 @@
+use std::fmt;
+use std::io::{Read, Write};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use proton_sdk_sys::prost::Message;
+use proton_sdk_sys::protobufs::drive::{FileNode, FolderNode};
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use r2d2_sqlite::rusqlite::{params, Connection};
+use serde::de::{self, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use super::{set_state, NodeColumns, STATE_COMPLETED_AT};
+
+/// File formats of `index export`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ExportFormat {
+    /// A JSON array, one row per line, with the node of each row so it can be imported
+    Json,
+    /// A header and one line per row, without the nodes
+    Csv,
+}
+
+/// An indexed folder or file, as written by `index export`
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportRow {
+    /// `folder` or `file`
+    pub kind: String,
+    pub path: String,
+    pub node_id: Option<String>,
+    pub size: Option<i64>,
+    /// Creation time of the active revision, in seconds since the epoch
+    pub mtime: Option<i64>,
+    pub revision_id: Option<String>,
+    pub hash: Option<String>,
+    pub deleted_at: Option<i64>,
+    /// The stored node in base64, only in JSON exports
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub node: Option<String>,
+}
+
+/// Folders first so an import creates parents before their children, each in path order
+const TABLES: [(&str, &str); 2] = [("folder", "folders"), ("file", "files")];
+
+/// Calls `row` for every indexed folder and file, reading one row at a time
+fn each_row(
+    conn: &Connection,
+    with_nodes: bool,
+    mut row: impl FnMut(ExportRow) -> anyhow::Result<()>,
+) -> anyhow::Result<u64> {
+    let mut count = 0;
+    for (kind, table) in TABLES {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT full_path, node_id, size, modified_at, revision_id, content_hash, deleted_at, node
+            FROM {} ORDER BY full_path",
+            table
+        ))?;
+        let mut rows = stmt.query([])?;
+        while let Some(stored) = rows.next()? {
+            let node: Vec<u8> = stored.get(7)?;
+            row(ExportRow {
+                kind: kind.to_string(),
+                path: stored.get(0)?,
+                node_id: stored.get(1)?,
+                size: stored.get(2)?,
+                mtime: stored.get(3)?,
+                revision_id: stored.get(4)?,
+                hash: stored.get(5)?,
+                deleted_at: stored.get(6)?,
+                node: with_nodes.then(|| STANDARD.encode(node)),
+            })?;
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+/// Writes every indexed folder and file to `output`, returning how many
+///
+/// Rows are streamed from the database, the table is never held in memory.
+/// Deleted rows are included with their `deleted_at`.
+pub fn export(pool: &Pool<SqliteConnectionManager>, format: ExportFormat, output: impl Write) -> anyhow::Result<u64> {
+    let conn = pool.get()?;
+    match format {
+        ExportFormat::Json => {
+            let mut output = output;
+            let mut first = true;
+            output.write_all(b"[")?;
+            let count = each_row(&conn, true, |row| {
+                output.write_all(if first { b"\n" } else { b",\n" })?;
+                first = false;
+                serde_json::to_writer(&mut output, &row)?;
+                Ok(())
+            })?;
+            output.write_all(if first { b"]\n" } else { b"\n]\n" })?;
+            output.flush()?;
+            Ok(count)
+        }
+        ExportFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(output);
+            // the header comes from the first row, an empty index still gets one
+            let count = each_row(&conn, false, |row| Ok(writer.serialize(row)?))?;
+            if count == 0 {
+                writer.write_record(["kind", "path", "node_id", "size", "mtime", "revision_id", "hash", "deleted_at"])?;
+            }
+            writer.flush()?;
+            Ok(count)
+        }
+    }
+}
+
+/// Errors importing an export
+#[derive(Debug, thiserror::Error)]
+pub enum ImportError {
+    #[error("The index already has {0} rows, import into a new index")]
+    NotEmpty(i64),
+
+    #[error("Row {row} ({path}) can't be imported: {reason}")]
+    BadRow { row: u64, path: String, reason: String },
+}
+
+/// Feeds the elements of a JSON array to a closure as they are parsed
+struct EachRow<F>(F);
+
+impl<'de, F: FnMut(ExportRow) -> anyhow::Result<()>> Visitor<'de> for EachRow<F> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an array of index rows")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(mut self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(row) = seq.next_element()? {
+            (self.0)(row).map_err(|e| de::Error::custom(format!("{:#}", e)))?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes a row of a JSON export back, deriving what the export leaves out from its node
+fn import_row(conn: &Connection, number: u64, row: ExportRow) -> anyhow::Result<()> {
+    let bad = |reason: String| ImportError::BadRow { row: number, path: row.path.clone(), reason };
+    let Some(encoded) = &row.node else {
+        return Err(bad("it has no node, only JSON exports can be imported".to_string()).into());
+    };
+    let node = STANDARD.decode(encoded).map_err(|e| bad(format!("its node isn't base64: {}", e)))?;
+
+    let (table, name_column, columns) = match row.kind.as_str() {
+        "folder" => ("folders", "folder_name", FolderNode::decode(node.as_slice()).map(|folder| NodeColumns::from(&folder))),
+        "file" => ("files", "file_name", FileNode::decode(node.as_slice()).map(|file| NodeColumns::from(&file))),
+        kind => return Err(bad(format!("unknown kind `{}`", kind)).into()),
+    };
+    let columns = columns.map_err(|e| bad(format!("its node can't be decoded: {}", e)))?;
+    let name = row.path.rsplit('/').next().unwrap_or_default();
+
+    conn.execute(
+        &format!(
+            "INSERT INTO {} (full_path, {}, checked, node, node_id, parent_node_id, size, modified_at,
+                revision_id, content_hash, deleted_at)
+            VALUES (?1, ?2, 1, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            table, name_column
+        ),
+        params![
+            row.path,
+            name,
+            node,
+            row.node_id,
+            columns.parent_node_id,
+            row.size,
+            row.mtime,
+            row.revision_id,
+            row.hash,
+            row.deleted_at,
+        ],
+    )?;
+    Ok(())
+}
+
+/// Restores a JSON export into an empty index, returning how many rows it held
+///
+/// Rows are inserted as they are parsed, in one transaction. The index is
+/// marked complete, the next `index` run refreshes it from the drive.
+pub fn import(pool: &Pool<SqliteConnectionManager>, input: impl Read) -> anyhow::Result<u64> {
+    let mut conn = pool.get()?;
+    let existing: i64 = conn.query_row("SELECT (SELECT COUNT(*) FROM files) + (SELECT COUNT(*) FROM folders)", [], |row| row.get(0))?;
+    if existing > 0 {
+        return Err(ImportError::NotEmpty(existing).into());
+    }
+
+    let tx = conn.transaction()?;
+    let mut count = 0;
+    serde_json::Deserializer::from_reader(input).deserialize_seq(EachRow(|row| {
+        count += 1;
+        import_row(&tx, count, row)
+    }))?;
+    set_state(&tx, STATE_COMPLETED_AT, &chrono::Utc::now().to_rfc3339())?;
+    tx.commit()?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::schema;
+    use crate::index::tests::indexed_pool;
+    use proton_sdk_sys::protobufs::drive::{LinkId, NodeIdentity, Revision, RevisionId};
+
+    fn empty_pool() -> Pool<SqliteConnectionManager> {
+        let pool = Pool::builder().max_size(1).build(SqliteConnectionManager::memory()).unwrap();
+        schema::migrate(&mut pool.get().unwrap()).unwrap();
+        pool
+    }
+
+    fn with_nodes(pool: &Pool<SqliteConnectionManager>) {
+        let conn = pool.get().unwrap();
+        let file = FileNode {
+            node_identity: Some(NodeIdentity { node_id: Some(LinkId { value: "beach".to_string() }), ..Default::default() }),
+            parent_id: Some(LinkId { value: "photos".to_string() }),
+            name: "Beach_2024.JPG".to_string(),
+            active_revision: Some(Revision {
+                revision_id: Some(RevisionId { value: "rev".to_string() }),
+                size: Some(300),
+                creation_time: 1_700_000_000,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        conn.execute(
+            "UPDATE files SET node = ?1, size = 300, modified_at = 1700000000, revision_id = 'rev', deleted_at = 5
+            WHERE node_id = 'beach'",
+            params![file.encode_to_vec()],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn json_exports_import_back_byte_for_byte() {
+        let pool = indexed_pool();
+        with_nodes(&pool);
+        let mut exported = Vec::new();
+        assert_eq!(export(&pool, ExportFormat::Json, &mut exported).unwrap(), 4);
+
+        let restored = empty_pool();
+        assert_eq!(import(&restored, exported.as_slice()).unwrap(), 4);
+        let mut again = Vec::new();
+        export(&restored, ExportFormat::Json, &mut again).unwrap();
+        assert_eq!(String::from_utf8(again).unwrap(), String::from_utf8(exported.clone()).unwrap());
+
+        let parent: Option<String> = restored
+            .get()
+            .unwrap()
+            .query_row("SELECT parent_node_id FROM files WHERE node_id = 'beach'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(parent.as_deref(), Some("photos"));
+        assert!(super::super::is_complete(&restored).unwrap());
+
+        assert!(matches!(
+            import(&restored, exported.as_slice()).unwrap_err().downcast_ref(),
+            Some(ImportError::NotEmpty(4))
+        ));
+    }
+
+    #[test]
+    fn csv_exports_have_a_header_and_no_nodes() {
+        let pool = indexed_pool();
+        with_nodes(&pool);
+        let mut exported = Vec::new();
+        export(&pool, ExportFormat::Csv, &mut exported).unwrap();
+        let exported = String::from_utf8(exported).unwrap();
+        let lines: Vec<&str> = exported.lines().collect();
+        assert_eq!(lines[0], "kind,path,node_id,size,mtime,revision_id,hash,deleted_at");
+        assert_eq!(lines[1], "folder,Photos,photos,,,,,");
+        assert!(lines.contains(&"file,Photos/Beach_2024.JPG,beach,300,1700000000,rev,,5"));
+
+        let mut empty = Vec::new();
+        export(&empty_pool(), ExportFormat::Csv, &mut empty).unwrap();
+        assert_eq!(String::from_utf8(empty).unwrap(), "kind,path,node_id,size,mtime,revision_id,hash,deleted_at\n");
+
+        let csv_import = import(&empty_pool(), exported.as_bytes());
+        assert!(csv_import.is_err());
+    }
+}
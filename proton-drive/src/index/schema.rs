@@ -0,0 +1,284 @@
+use log::{debug, info};
+use proton_sdk_sys::prost::Message;
+use proton_sdk_sys::protobufs::drive::{FileNode, FolderNode};
+use r2d2_sqlite::rusqlite::{self, params, Connection, OptionalExtension, Transaction};
+
+use super::NodeColumns;
+
+/// A forward migration, applied inside the transaction that bumps the version
+type Migration = fn(&Transaction) -> rusqlite::Result<()>;
+
+/// Migrations in order, the schema version is the number applied
+const MIGRATIONS: &[Migration] = &[
+    create_tables,
+    add_node_columns,
+    add_index_state,
+    add_watch_journal,
+    add_tombstones,
+    add_moves_log,
+    add_failed_folders,
+];
+
+/// Version of the schema once every migration is applied
+pub const SCHEMA_VERSION: u32 = MIGRATIONS.len() as u32;
+
+/// Brings the index up to [`SCHEMA_VERSION`], returning the version it was at
+///
+/// Each migration runs in its own transaction, so an index is never left
+/// between two versions. Indexes created before versioning are at version 0
+/// and keep their rows.
+pub fn migrate(conn: &mut Connection) -> rusqlite::Result<u32> {
+    conn.execute_batch("CREATE TABLE IF NOT EXISTS schema_version (version INTEGER NOT NULL)")?;
+    let current = version(conn)?;
+    if current > SCHEMA_VERSION {
+        return Err(rusqlite::Error::InvalidParameterName(format!(
+            "index schema version {} is newer than this build supports ({})",
+            current, SCHEMA_VERSION
+        )));
+    }
+
+    for (applied, migration) in MIGRATIONS.iter().enumerate().skip(current as usize) {
+        let version = applied as u32 + 1;
+        let tx = conn.transaction()?;
+        migration(&tx)?;
+        tx.execute("DELETE FROM schema_version", [])?;
+        tx.execute("INSERT INTO schema_version (version) VALUES (?1)", params![version])?;
+        tx.commit()?;
+        info!("Migrated the index to schema version {}", version);
+    }
+
+    Ok(current)
+}
+
+fn version(conn: &Connection) -> rusqlite::Result<u32> {
+    let version: Option<u32> = conn
+        .query_row("SELECT version FROM schema_version", [], |row| row.get(0))
+        .optional()?;
+    Ok(version.unwrap_or(0))
+}
+
+/// Version 1, the path keyed tables of the first indexer
+fn create_tables(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS files (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            full_path TEXT NOT NULL UNIQUE,
+            file_name TEXT NOT NULL,
+            checked BOOLEAN NOT NULL DEFAULT 0,
+            node BLOB NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS folders (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            full_path TEXT NOT NULL UNIQUE,
+            folder_name TEXT NOT NULL,
+            checked BOOLEAN NOT NULL DEFAULT 0,
+            node BLOB NOT NULL
+        );",
+    )
+}
+
+/// Version 2, node ids and file metadata as columns, filled from the stored nodes
+fn add_node_columns(tx: &Transaction) -> rusqlite::Result<()> {
+    for table in ["files", "folders"] {
+        for column in [
+            "node_id TEXT",
+            "parent_node_id TEXT",
+            "size INTEGER",
+            "modified_at INTEGER",
+            "revision_id TEXT",
+            "content_hash TEXT",
+        ] {
+            tx.execute_batch(&format!("ALTER TABLE {} ADD COLUMN {}", table, column))?;
+        }
+    }
+
+    backfill(tx, "files", |node| FileNode::decode(node).ok().map(|file| NodeColumns::from(&file)))?;
+    backfill(tx, "folders", |node| FolderNode::decode(node).ok().map(|folder| NodeColumns::from(&folder)))?;
+
+    tx.execute_batch(
+        "CREATE UNIQUE INDEX files_node_id ON files (node_id);
+        CREATE INDEX files_parent_node_id ON files (parent_node_id);
+        CREATE INDEX files_modified_at ON files (modified_at);
+        CREATE UNIQUE INDEX folders_node_id ON folders (node_id);
+        CREATE INDEX folders_parent_node_id ON folders (parent_node_id);",
+    )
+}
+
+/// Version 3, checkpoints of the initial indexing
+///
+/// `checked` on a folder now means all its children were recorded.
+fn add_index_state(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE index_state (
+            key TEXT PRIMARY KEY,
+            value TEXT NOT NULL
+        );
+        UPDATE folders SET checked = 0;",
+    )
+}
+
+/// Version 4, local files `watch` last synced, to catch up on changes made while it was down
+fn add_watch_journal(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE watch_journal (
+            local_root TEXT NOT NULL,
+            relative_path TEXT NOT NULL,
+            size INTEGER NOT NULL,
+            modified_at INTEGER NOT NULL,
+            synced_at INTEGER NOT NULL,
+            PRIMARY KEY (local_root, relative_path)
+        );",
+    )
+}
+
+/// Version 5, tombstones of nodes no longer listed by their parent folder
+fn add_tombstones(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "ALTER TABLE files ADD COLUMN deleted_at INTEGER;
+        ALTER TABLE folders ADD COLUMN deleted_at INTEGER;",
+    )
+}
+
+/// Version 6, renames and moves seen by the indexer, to be replayed on local copies
+fn add_moves_log(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE moves (
+            id INTEGER PRIMARY KEY,
+            node_id TEXT NOT NULL,
+            is_folder INTEGER NOT NULL,
+            from_path TEXT NOT NULL,
+            to_path TEXT NOT NULL,
+            moved_at INTEGER NOT NULL
+        );
+        CREATE INDEX moves_to_path ON moves (to_path);",
+    )
+}
+
+/// Version 7, folders whose listing kept failing, for `index --retry-failed`
+///
+/// The identity is kept whole, the root has no row to take it from.
+fn add_failed_folders(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE failed_folders (
+            node_id TEXT PRIMARY KEY,
+            full_path TEXT NOT NULL,
+            identity BLOB NOT NULL,
+            error TEXT NOT NULL,
+            failed_at INTEGER NOT NULL
+        );",
+    )
+}
+
+/// Fills the node columns of `table` from the node blobs
+///
+/// Path keyed indexes can hold the same node under several paths after a
+/// rename, only the most recently inserted row is kept.
+fn backfill(
+    tx: &Transaction,
+    table: &str,
+    decode: impl Fn(&[u8]) -> Option<NodeColumns>,
+) -> rusqlite::Result<()> {
+    let rows: Vec<(i64, Vec<u8>)> = tx
+        .prepare(&format!("SELECT id, node FROM {}", table))?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_, _>>()?;
+
+    let mut update = tx.prepare(&format!(
+        "UPDATE {} SET node_id = ?2, parent_node_id = ?3, size = ?4, modified_at = ?5,
+            revision_id = ?6, content_hash = ?7
+        WHERE id = ?1",
+        table
+    ))?;
+    for (id, node) in rows {
+        let Some(columns) = decode(&node) else {
+            debug!("Leaving undecodable {} row {} without node columns", table, id);
+            continue;
+        };
+        update.execute(params![
+            id,
+            columns.node_id,
+            columns.parent_node_id,
+            columns.size,
+            columns.modified_at,
+            columns.revision_id,
+            columns.content_hash,
+        ])?;
+    }
+
+    let removed = tx.execute(
+        &format!(
+            "DELETE FROM {0} WHERE node_id IS NOT NULL
+                AND id NOT IN (SELECT MAX(id) FROM {0} WHERE node_id IS NOT NULL GROUP BY node_id)",
+            table
+        ),
+        [],
+    )?;
+    if removed > 0 {
+        info!("Removed {} duplicate {} rows left by renames", removed, table);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proton_sdk_sys::protobufs::drive::{LinkId, NodeIdentity, Revision, RevisionId};
+
+    fn file(node_id: &str, size: i64) -> FileNode {
+        FileNode {
+            node_identity: Some(NodeIdentity {
+                node_id: Some(LinkId { value: node_id.to_string() }),
+                ..Default::default()
+            }),
+            parent_id: Some(LinkId { value: "parent".to_string() }),
+            name: "file.txt".to_string(),
+            active_revision: Some(Revision {
+                revision_id: Some(RevisionId { value: "revision".to_string() }),
+                size: Some(size),
+                creation_time: 1_700_000_000,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn new_indexes_get_the_latest_schema() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        assert_eq!(migrate(&mut conn).unwrap(), 0);
+        assert_eq!(version(&conn).unwrap(), SCHEMA_VERSION);
+
+        assert_eq!(migrate(&mut conn).unwrap(), SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn unversioned_indexes_keep_their_rows_and_get_node_columns() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        let tx = conn.transaction().unwrap();
+        create_tables(&tx).unwrap();
+        tx.commit().unwrap();
+
+        let insert = "INSERT INTO files (full_path, file_name, node) VALUES (?1, 'file.txt', ?2)";
+        conn.execute(insert, params!["old/file.txt", file("node", 1).encode_to_vec()]).unwrap();
+        conn.execute(insert, params!["new/file.txt", file("node", 2).encode_to_vec()]).unwrap();
+        conn.execute(insert, params!["broken.txt", vec![0xff_u8]]).unwrap();
+
+        migrate(&mut conn).unwrap();
+
+        let (path, node_id, parent, size, modified, revision): (String, String, String, i64, i64, String) = conn
+            .query_row(
+                "SELECT full_path, node_id, parent_node_id, size, modified_at, revision_id
+                FROM files WHERE node_id IS NOT NULL",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?)),
+            )
+            .unwrap();
+        assert_eq!(
+            (path.as_str(), node_id.as_str(), parent.as_str(), size, modified, revision.as_str()),
+            ("new/file.txt", "node", "parent", 2, 1_700_000_000, "revision")
+        );
+
+        let total: i64 = conn.query_row("SELECT COUNT(*) FROM files", [], |row| row.get(0)).unwrap();
+        assert_eq!(total, 2);
+    }
+}
@@ -0,0 +1,112 @@
+use std::backtrace::Backtrace;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::panic;
+use std::path::{Path, PathBuf};
+
+use log::{error, LevelFilter};
+use proton_sdk_rs::logging::SdkLogger;
+
+/// Size past which `--log-file` is moved to `<path>.1` on startup
+const LOG_FILE_MAX_SIZE: u64 = 10 * 1024 * 1024;
+/// Native SDK events appended to the log of a panic
+const PANIC_SDK_EVENTS: usize = 20;
+
+/// Where the log goes besides stderr
+pub struct LogSettings<'a> {
+    /// Overrides `RUST_LOG`
+    pub level: Option<LevelFilter>,
+    /// Applies when neither `level` nor `RUST_LOG` is set
+    pub default_filter: Option<&'a str>,
+    pub file: Option<&'a Path>,
+}
+
+/// Writes every formatted record to stderr and to the log file
+struct Tee(File);
+
+impl Write for Tee {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write_all(buf)?;
+        io::stderr().write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()?;
+        io::stderr().flush()
+    }
+}
+
+/// Opens the log file for appending, after moving it aside if it grew past `max_size`
+fn open_log_file(path: &Path, max_size: u64) -> io::Result<File> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
+    if fs::metadata(path).is_ok_and(|metadata| metadata.len() > max_size) {
+        let mut rotated = path.as_os_str().to_owned();
+        rotated.push(".1");
+        fs::rename(path, PathBuf::from(rotated))?;
+    }
+    File::options().create(true).append(true).open(path)
+}
+
+/// Installs the logger, the native SDK records forwarded into `log` included
+///
+/// Called first thing in `main`, so nothing logged later is lost.
+pub fn init(settings: LogSettings) -> io::Result<()> {
+    let mut logger = env_logger::Builder::from_default_env();
+    if let Some(level) = settings.level {
+        logger.filter_level(level);
+    } else if let Some(filter) = settings.default_filter
+        && std::env::var_os("RUST_LOG").is_none()
+    {
+        logger.parse_filters(filter);
+    }
+
+    let file = settings.file.map(|path| open_log_file(path, LOG_FILE_MAX_SIZE)).transpose()?;
+    if let Some(file) = file {
+        logger.target(env_logger::Target::Pipe(Box::new(Tee(file))));
+    }
+    logger.init();
+    Ok(())
+}
+
+/// Logs panics with a backtrace and the last native SDK events before the usual report
+pub fn install_panic_hook() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        error!("{}\n{}", info, Backtrace::force_capture());
+        let events = SdkLogger::recent(PANIC_SDK_EVENTS);
+        if !events.is_empty() {
+            let lines: Vec<String> = events
+                .iter()
+                .map(|event| format!("  [{}] {}", event.category_name, event.message))
+                .collect();
+            error!("Last native SDK events:\n{}", lines.join("\n"));
+        }
+        log::logger().flush();
+        default_hook(info);
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn large_log_files_are_moved_aside() {
+        let dir = std::env::temp_dir().join(format!("proton-drive-logging-{}", std::process::id()));
+        let path = dir.join("proton-drive.log");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&path, "old run\n").unwrap();
+
+        writeln!(open_log_file(&path, 1024).unwrap(), "second run").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old run\nsecond run\n");
+
+        writeln!(open_log_file(&path, 8).unwrap(), "third run").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "third run\n");
+        assert_eq!(fs::read_to_string(dir.join("proton-drive.log.1")).unwrap(), "old run\nsecond run\n");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
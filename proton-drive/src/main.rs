@@ -1,283 +1,280 @@
 mod auth;
+mod bandwidth;
+mod cli;
+mod commands;
+mod config;
+mod credentials;
+mod daemon;
+mod dry_run;
 mod index;
+mod logging;
+mod mirror;
+mod quota;
+mod remote;
+mod shutdown;
+mod stat;
+mod upload;
+mod verify;
+mod watch;
 
-use r2d2::Pool;
-use proton_sdk_sys::{data::Callback, prost::Message};
-use std::{fs::{File, OpenOptions}, sync::{Arc, Mutex}};
-use async_recursion::async_recursion;
-use chrono::Utc;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::time::Duration;
+
+use clap::Parser;
 use log::*;
-use proton_sdk_rs::{
-    downloads::DownloaderBuilder, drive::{DriveClient, DriveClientBuilder}, observability::OptionalObservability, sessions::{SessionBuilder, SessionPlatform}, utils, AddressKeyRegistrationRequest, ClientId, FileDownloadRequest, NodeIdentity, OperationIdentifier, OperationType, ProtonDriveClientCreateRequest, RevisionMetadata, ToByteArray, VolumeMetadata
-};
-use proton_sdk_sys::logger;
-use tokio::time::timeout;
-use uuid::Uuid;
-use std::{env, fs, io::{self, Write}, thread, time::Duration};
-use std::os::windows::prelude::MetadataExt;
-use r2d2_sqlite::{rusqlite::params, SqliteConnectionManager};
-use proton_sdk_sys::protobufs::{FileUploadRequest, FileUploaderCreationRequest, ShareMetadata};
-use proton_sdk_rs::uploads::UploaderBuilder;
+use proton_sdk_rs::logging::SdkLogger;
+use proton_sdk_sys::{LoadError, ProtonSDKLib};
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    println!("================== Proton Drive (primitive) ==================");
-    let (session, is_first_run, password) = auth::create_new_session().await;
+use crate::cli::{Cli, Command, IndexCommand, ProfileCommand};
+use crate::config::Config;
 
-    session.save_session(None)?;
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
+    let mut paths = cli.paths();
 
-    info!("Creating observability");
-    let obs = OptionalObservability::enabled(session.handle())?;
-    trace!("Observability handle: {:?}", obs.handle());
+    // RUST_LOG and the log settings may come from these, neither logs anything; secrets
+    // the keyring couldn't take stay in the legacy settings file
+    if dotenv::from_path(&paths.legacy_cfg).is_err() {
+        dotenv::dotenv().ok();
+    }
+    let config = Config::load(&paths.config);
 
-    info!("Creating Drive client");
-    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
-    let create_request = ProtonDriveClientCreateRequest {
-        client_id: Some(ClientId {
-            value: "proton-sdk-rs".to_string(),
-        }),
+    let defaults = config.as_ref().ok().map(|config| &config.log);
+    let log_settings = logging::LogSettings {
+        level: cli.log_level(),
+        default_filter: defaults.and_then(|log| log.level.as_deref()),
+        file: cli.log_file.as_deref().or(defaults.and_then(|log| log.file.as_deref())),
     };
-    trace!("Request: {:?}", create_request);
+    if let Err(e) = logging::init(log_settings) {
+        eprintln!("error: Unable to open the log file: {}", e);
+        std::process::exit(cli::EXIT_FAILURE);
+    }
+    logging::install_panic_hook();
 
-    let client = match DriveClientBuilder::new(session)
-        .with_observability(obs.handle())
-        .with_request(create_request)
-        .build()
-    {
-        Ok(cli) => {
-            println!("Drive client successfully created!");
-            debug!("Drive client created {:?}", cli.handle());
-            Arc::new(cli)
+    let config = match config {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            std::process::exit(cli::EXIT_FAILURE);
         }
-        Err(e) => anyhow::bail!(e),
     };
 
-    let volumes = client.get_volumes().await?;
-
-    let main_volume = &volumes[0];
-
-    let share = client.get_shares(main_volume).await?;
+    // an SDK lacking exports or a platform without one is reported now rather than by the call
+    // that needs it, a missing SDK only by the commands calling it
+    let _sdk_logger = if cli.command.uses_sdk() {
+        match ProtonSDKLib::instance() {
+            Ok(sdk) => info!("Using the {}", describe_sdk(sdk)),
+            Err(LoadError::Unsupported(e)) => {
+                eprintln!("error: proton-drive can't run on this system. {}", e);
+                std::process::exit(cli::EXIT_FAILURE);
+            }
+            Err(e @ LoadError::SymbolsMissing { .. }) => {
+                eprintln!("error: {}", e);
+                std::process::exit(cli::EXIT_FAILURE);
+            }
+            Err(LoadError::Failed { .. }) => {}
+        }
 
-    let identity = NodeIdentity { 
-        node_id: share.root_node_id.clone(), 
-        share_id: share.share_id.clone(), 
-        volume_id: main_volume.volume_id.clone()
+        // keeps the native SDK logs flowing into `log` under the `proton_sdk` target
+        match SdkLogger::install() {
+            Ok(guard) => Some(guard),
+            Err(e) => {
+                warn!("Unable to forward native SDK logs: {}", e);
+                None
+            }
+        }
+    } else {
+        None
     };
 
-    let manager = SqliteConnectionManager::file("index.db");
-    let pool = Arc::new(Pool::new(manager)?);
-
-    if is_first_run {
-        index::index(&client, &identity, password, &pool).await?;
-        println!("Ding! Initial indexing is done");
-        let mut file = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(".cfg")
-            .unwrap();
-        writeln!(file, "INITIAL_INDEX=true").unwrap();
-    } else {
-        println!("No big indexing");
+    let config = match config::migrate_legacy(&paths.legacy_cfg, &paths.config, cli.profile()) {
+        Ok(imported) if imported.is_empty() => config,
+        Ok(imported) => {
+            info!(
+                "Moved {} from {} to {}",
+                imported.join(", "),
+                paths.legacy_cfg.display(),
+                paths.config.display()
+            );
+            Config::load(&paths.config).unwrap_or(config)
+        }
+        Err(e) => {
+            warn!("Unable to import the settings of {}: {:#}", paths.legacy_cfg.display(), e);
+            config
+        }
+    };
+    if let Some(index_db) = config.profile(cli.profile()).index_db {
+        paths.index = index_db;
     }
+    for (from, to) in paths.adopt_legacy_files() {
+        info!("Moved {} to {}", from.display(), to.display());
+    }
+    bandwidth::configure(config.bandwidth.clone(), cli.command.rate_limits());
+    let throughput = paths.throughput.clone();
 
-    loop {
-        update(client.clone(), pool.clone(), 8).await;
+    shutdown::install(Duration::from_secs(config.shutdown_grace));
+    let result = run(cli, paths, config).await;
+    shutdown::finish();
+    if let Err(e) = bandwidth::save(&throughput) {
+        warn!("Unable to save the transfer rates to {}: {:#}", throughput.display(), e);
     }
 
-    Ok(())
+    if let Err(e) = result {
+        eprintln!("error: {:#}", e);
+        if let Some(diagnostics) = cli::load_diagnostics(&e) {
+            eprintln!("note: {}", diagnostics);
+        }
+        if let Some(sdk) = ProtonSDKLib::instance().ok().filter(|_| cli::from_sdk(&e)) {
+            eprintln!("note: {}", describe_sdk(sdk));
+        }
+        // whatever failed once the operations were cancelled, the user stopped it
+        let code = if shutdown::is_requested() { cli::EXIT_CANCELLED } else { cli::exit_code(&e) };
+        std::process::exit(code);
+    }
 }
 
-async fn update(client: Arc<DriveClient>, pool: Arc<Pool<SqliteConnectionManager>>, number_of_workers: usize) {
-    let folders: Vec<(String, Vec<u8>)> = {
-        let conn = pool.get().unwrap();
-        let mut stmt = conn.prepare("SELECT full_path, node FROM folders").unwrap();
-        stmt.query_map([], |row| {
-            let path: String = row.get(0)?;
-            let node: Vec<u8> = row.get(1)?;
-            Ok((path, node))
-        })
-        .unwrap()
-        .map(|r| r.unwrap())
-        .collect()
-    };
-
-    let folder_queue = Arc::new(Mutex::new(folders));
-    let mut handles = vec![];
-
-    for _ in 0..number_of_workers {
-        let queue = Arc::clone(&folder_queue);
-        let client = Arc::clone(&client);
-        let pool = Arc::clone(&pool);
-
-        handles.push(thread::spawn(move || {
-            loop {
-                let (folder_path, node_bytes) = {
-                    let mut q = queue.lock().unwrap();
-                    if q.is_empty() {
-                        break;
-                    }
-                    q.pop().unwrap()
-                };
-
-                let node_identity: NodeIdentity = match NodeIdentity::decode(node_bytes.as_slice()) {
-                    Ok(n) => n,
-                    Err(e) => {
-                        log::error!("Failed to decode node for {}: {:?}", folder_path, e);
-                        continue;
-                    }
-                };
+/// Names the loaded SDK for logs and bug reports
+fn describe_sdk(sdk: &ProtonSDKLib) -> String {
+    match sdk.version() {
+        Some(version) => format!("native SDK {} at {}", version, sdk.location.display()),
+        None => format!("native SDK of unknown version at {}", sdk.location.display()),
+    }
+}
 
-                // Call get_folder_children (sync version)
-                let children = match client.get_folder_children_blocking(node_identity) {
-                    Ok(c) => c,
-                    Err(e) => {
-                        log::error!("Failed to get children for {}: {:?}", folder_path, e);
-                        continue;
-                    }
-                };
+async fn run(cli: Cli, paths: cli::Paths, config: Config) -> anyhow::Result<()> {
+    if let Some(dir) = paths.session.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)?;
+    }
 
-                let conn = pool.get().unwrap();
-                for child in children {
-                    let (is_folder, folder) = utils::node_is_folder(child.clone());
-                    let (is_file, file) = utils::node_is_file(child.clone());
+    let data_password = if cli.data_password_stdin {
+        Some(auth::read_data_password(io::stdin().lock())?)
+    } else {
+        None
+    };
+    let selected = cli.profile().map(str::to_string);
+    let profile = config.profile(selected.as_deref());
+    let credentials = credentials::CredentialStore::new(!cli.no_keyring, selected.as_deref());
+    let auth_options = auth::AuthOptions {
+        credentials,
+        interactive: !cli.non_interactive && io::stdin().is_terminal(),
+        username: cli
+            .username
+            .clone()
+            .or_else(|| std::env::var("PROTON_USERNAME").ok())
+            .or_else(|| profile.username.clone()),
+        data_password,
+        profile: selected.clone(),
+        config: paths.config.clone(),
+        legacy_cfg: paths.legacy_cfg.clone(),
+        session_file: paths.session.clone(),
+        telemetry: config.telemetry,
+    };
+    let transfers = config.concurrency.transfers;
 
-                    if is_folder {
-                        let folder_name = folder.as_ref().map(|f| f.name.clone()).unwrap_or_default();
-                        let full_path = format!("{}/{}", folder_path, folder_name);
-                        let mut stmt = conn.prepare("SELECT COUNT(*) FROM folders WHERE full_path = ?1").unwrap();
-                        let exists: i64 = stmt.query_row(params![full_path], |row| row.get(0)).unwrap();
-                        if exists == 0 {
-                            log::info!("New folder detected: {}", full_path);
-                            let node_bytes = folder.unwrap().to_bytes().unwrap();
-                            conn.execute(
-                                "INSERT INTO folders (full_path, folder_name, checked, node) VALUES (?1, ?2, 0, ?3)",
-                                params![full_path, folder_name, node_bytes],
-                            ).unwrap();
-                        }
-                    } else if is_file {
-                        let file_name = file.as_ref().map(|f| f.name.clone()).unwrap_or_default();
-                        let full_path = format!("{}/{}", folder_path, file_name);
-                        let mut stmt = conn.prepare("SELECT COUNT(*) FROM files WHERE full_path = ?1").unwrap();
-                        let exists: i64 = stmt.query_row(params![full_path], |row| row.get(0)).unwrap();
-                        if exists == 0 {
-                            log::info!("New file detected: {}", full_path);
-                            let node_bytes = file.unwrap().to_bytes().unwrap();
-                            conn.execute(
-                                "INSERT INTO files (full_path, file_name, checked, node) VALUES (?1, ?2, 0, ?3)",
-                                params![full_path, file_name, node_bytes],
-                            ).unwrap();
-                        }
-                    }
-                }
+    match cli.command {
+        Command::Login => commands::login(auth_options).await,
+        Command::Logout => commands::logout(&paths, &auth_options.credentials, auth_options.username.clone()),
+        Command::Ls { remote_path, json } => commands::ls(auth_options, &remote_path, json).await,
+        Command::Stat { target, json, live } => commands::stat(auth_options, &paths, &target, json, live).await,
+        Command::Download { remote, local, .. } => {
+            commands::download(auth_options, &paths, &remote, &local, transfers).await
+        }
+        Command::Upload { local, remote, create_parents, on_conflict, .. } => {
+            let upload_options = upload::UploadOptions { local, remote, create_parents, on_conflict };
+            commands::upload(auth_options, &paths, upload_options).await
+        }
+        Command::Watch { local, remote, delete_remote, mut ignore, debounce, dry_run, json, .. } => {
+            ignore.extend(watch::DEFAULT_IGNORES.iter().map(|pattern| glob::Pattern::new(pattern).unwrap()));
+            let watch_options = watch::WatchOptions {
+                local_root: local,
+                remote,
+                delete_remote,
+                ignore,
+                debounce: Duration::from_secs(debounce),
+                dry_run,
+            };
+            commands::watch(auth_options, &paths, watch_options, json).await
+        }
+        Command::Mirror { remote, local, delete_local, dry_run, json, workers, .. } => {
+            let mirror_options = mirror::MirrorOptions {
+                remote,
+                local_root: local,
+                delete_local,
+                dry_run,
+                workers: workers.unwrap_or(transfers),
+            };
+            commands::mirror(auth_options, &paths, mirror_options, json).await
+        }
+        Command::Daemon { interval, once, limits, dry_run, json } => {
+            let daemon_options = daemon::DaemonOptions {
+                interval,
+                once,
+                config: paths.config.clone(),
+                profile: selected,
+                index_workers: config.concurrency.index_workers,
+                transfers,
+                limits: limits.into(),
+                dry_run,
+            };
+            commands::daemon(auth_options, &paths, daemon_options, json).await
+        }
+        Command::Index { command: Some(IndexCommand::Export { format, output }), .. } => {
+            commands::index_export(&auth_options, &paths, format, output.as_deref())
+        }
+        Command::Index { command: Some(IndexCommand::Import { input }), .. } => {
+            commands::index_import(&auth_options, &paths, &input)
+        }
+        Command::Index { command: None, watch, workers, force_reindex, retry_failed } => {
+            let workers = workers.unwrap_or(config.concurrency.index_workers);
+            if retry_failed {
+                commands::index_retry_failed(auth_options, &paths, workers).await
+            } else {
+                commands::index(auth_options, &paths, watch, workers, force_reindex).await
             }
-        }));
-    }
-
-    for h in handles {
-        h.join().unwrap();
+        }
+        Command::Search {
+            pattern,
+            glob,
+            regex,
+            case_sensitive,
+            files_only,
+            folders_only,
+            include_deleted,
+            limit,
+            json,
+        } => {
+            let query = index::SearchQuery {
+                pattern,
+                mode: match (glob, regex) {
+                    (true, _) => index::MatchMode::Glob,
+                    (_, true) => index::MatchMode::Regex,
+                    _ => index::MatchMode::Substring,
+                },
+                case_sensitive,
+                files: !folders_only,
+                folders: !files_only,
+                include_deleted,
+                limit,
+            };
+            commands::search(&auth_options, &paths, &query, json)
+        }
+        Command::Status { json } => commands::status(&auth_options, &paths, json),
+        Command::Quota { top, warn_at, json } => commands::quota(auth_options, &paths, top, warn_at, json).await,
+        Command::Verify { local, remote, repair, workers } => {
+            let verify_options = verify::VerifyOptions {
+                local_root: local,
+                remote,
+                repair,
+                workers: workers.unwrap_or(transfers),
+            };
+            commands::verify(auth_options, &paths, verify_options).await
+        }
+        Command::Profile { command } => match command {
+            ProfileCommand::List => commands::profile_list(&paths.config, selected.as_deref()),
+            ProfileCommand::Add { name, username } => commands::profile_add(&paths.config, &name, username),
+            ProfileCommand::Remove { name } => commands::profile_remove(&paths.config, &name, !cli.no_keyring),
+        },
     }
 }
-
-// let downloader = DownloaderBuilder::new(&client).build().await?;
-    //
-    // let children = client.get_folder_children(identity.clone()).await?;
-    // for child in children {
-    //     let (is_file, file_node) = utils::node_is_file(child);
-    //     if is_file && file_node.as_ref().unwrap().name == "BadApple.mp4" {
-    //         let file = file_node.as_ref().unwrap();
-    //         let mut file_identity = file.node_identity.clone();
-    //         if let Some(ref mut fi) = file_identity {
-    //             if fi.share_id.is_none() {
-    //                 log::warn!("Missing share id, adding...");
-    //                 fi.share_id = identity.share_id.clone();
-    //             }
-    //             if fi.volume_id.is_none() {
-    //                 log::warn!("Missing volume id, adding...");
-    //                 fi.volume_id = identity.volume_id.clone();
-    //             }
-    //         };
-    //         let revision_info = file.active_revision.as_ref().unwrap();
-    //         let revision = RevisionMetadata {
-    //             revision_id: revision_info.revision_id.clone(),
-    //             state: revision_info.state,
-    //             manifest_signature: revision_info.manifest_signature.clone(),
-    //             signature_email_address: revision_info.signature_email_address.clone(),
-    //             samples_sha256_digests: revision_info.samples_sha256_digests.clone()
-    //         };
-    //         let operation = OperationIdentifier {
-    //             r#type: OperationType::Download.into(),
-    //             identifier: Uuid::new_v4().to_string(),
-    //             timestamp: Utc::now().to_rfc3339()
-    //         };
-    //         trace!("share id: {:?}", file.node_identity.as_ref().unwrap().share_id);
-    //         trace!("volume id: {:?}", file.node_identity.as_ref().unwrap().volume_id);
-    //         trace!("node id: {:?}", file.node_identity.as_ref().unwrap().node_id);
-    //         trace!("global share id: {:?}", share.share_id);
-    //
-    //         let request = FileDownloadRequest {
-    //             file_identity,
-    //             revision_metadata: Some(revision),
-    //             target_file_path: String::from("C:/Users/thrib/Downloads/BadApple.mp4"),
-    //             operation_id: Some(operation)
-    //         };
-    //
-    //         let status = downloader.download_file(
-    //             request,
-    //              Some(|progress| println!("Progress: {:.1}%", progress * 100.0)),
-    //             &client.session().cancellation_token()
-    //             )
-    //             .await?;
-    //         trace!("downloader status: {:?}", status);
-    //     }
-    // }
-
-    // // uploading an example file
-    // const FILE: &'static str = "C:/Users/thrib/Downloads/protobuf-31.1.zip";
-    // let metadata = fs::metadata(FILE)?;
-    // let file_size = metadata.file_size();
-    //
-    // let request = FileUploaderCreationRequest {
-    //     file_size: file_size as i64,
-    //     number_of_samples: 0,
-    // };
-    //
-    // let uploader = UploaderBuilder::new(&client)
-    //     .with_request(request)
-    //     .build()
-    //     .await?;
-    //
-    // let metadata = fs::metadata(FILE)?;
-    // let file_name = std::path::Path::new(FILE)
-    //     .file_name()
-    //     .and_then(|n| n.to_str())
-    //     .unwrap_or("protobuf-31.1.zip")
-    //     .to_string();
-    //
-    // let operation = OperationIdentifier {
-    //     r#type: OperationType::Download.into(),
-    //     identifier: Uuid::new_v4().to_string(),
-    //     timestamp: Utc::now().to_rfc3339()
-    // };
-    //
-    // let share_metadata = ShareMetadata {
-    //     share_id: share.share_id.clone(),
-    //     membership_address_id: share.membership_address_id.clone(),
-    //     membership_email_address: share.membership_email_address.clone(),
-    // };
-    // let modified = metadata.modified()?;
-    // let last_modification_date = modified.duration_since(std::time::UNIX_EPOCH)?.as_secs() as i64;
-    //
-    // let request = FileUploadRequest {
-    //     share_metadata: Some(share_metadata),
-    //     parent_folder_identity: Some(identity),
-    //     name: file_name.clone(),
-    //     mime_type: mime_guess::from_path(FILE).first_or_octet_stream().to_string(),
-    //     source_file_path: FILE.to_string(),
-    //     thumbnail: None,
-    //     last_modification_date,
-    //     operation_id: Some(operation),
-    // };
-    //
-    // uploader.upload_file_or_revision(request, Some(move |progress| {
-    //     info!("Uploading file [{}] at progress: {}", file_name, progress * 100.0);
-    // })).await?;
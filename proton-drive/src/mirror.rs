@@ -0,0 +1,531 @@
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, UNIX_EPOCH};
+
+use futures::future;
+use futures::stream::{self, StreamExt};
+use log::{debug, warn};
+use proton_sdk_rs::nodes::{compare_local, ChangeState, ComparePolicy, RemotePath};
+use proton_sdk_rs::FileNode;
+
+use crate::commands::Context;
+use crate::dry_run::{Action, Direction, Entry};
+use crate::index::{Move, Subtree};
+use crate::remote::DriveOps;
+
+/// Suffix of files being downloaded, renamed to their real name once complete
+const PARTIAL_SUFFIX: &str = ".proton-part";
+
+/// What `mirror` brings down and where
+#[derive(Debug, Clone)]
+pub struct MirrorOptions {
+    pub remote: RemotePath,
+    pub local_root: PathBuf,
+    /// Deletes local files that aren't in the remote folder
+    pub delete_local: bool,
+    /// Plans the changes without making them
+    pub dry_run: bool,
+    /// Files downloaded in parallel
+    pub workers: usize,
+}
+
+/// A remote file missing or outdated locally
+#[derive(Debug)]
+pub struct Download {
+    pub relative: String,
+    file: FileNode,
+    target: PathBuf,
+    /// How the local copy compared, [`ChangeState::LocalMissing`] when there is none
+    pub state: ChangeState,
+}
+
+/// A local copy of a remote file that was renamed or moved, to follow it instead of downloading it again
+#[derive(Debug, PartialEq, Eq)]
+pub struct Rename {
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+/// What mirroring has to do, worked out from the index and the local files
+#[derive(Debug, Default)]
+pub struct Plan {
+    pub folders: Vec<PathBuf>,
+    pub renames: Vec<Rename>,
+    pub downloads: Vec<Download>,
+    /// Files already matching the remote
+    pub skipped: usize,
+    pub deletions: Vec<PathBuf>,
+}
+
+impl Plan {
+    /// The changes [`apply`] would make, for a dry run
+    pub fn entries(&self) -> Vec<Entry> {
+        let renames = self.renames.iter().map(|rename| Entry {
+            action: Action::Move,
+            path: rename.to.clone(),
+            direction: Direction::Down,
+            bytes: None,
+            reason: format!("moved remotely, the local copy is at {}", rename.from.display()),
+        });
+        let downloads = self.downloads.iter().map(|download| Entry {
+            action: Action::Download,
+            path: download.target.clone(),
+            direction: Direction::Down,
+            bytes: remote_size(&download.file),
+            reason: match download.state {
+                ChangeState::LocalMissing => "missing locally",
+                ChangeState::RemoteNewer => "the remote revision is newer",
+                ChangeState::LocalNewer => "the local copy is newer, the remote one wins",
+                _ => "both copies changed, the remote one wins",
+            }
+            .to_string(),
+        });
+        let deletions = self.deletions.iter().map(|path| Entry {
+            action: Action::Delete,
+            path: path.clone(),
+            direction: Direction::Down,
+            bytes: fs::metadata(path).ok().map(|metadata| metadata.len()),
+            reason: "not in the remote folder".to_string(),
+        });
+        renames.chain(downloads).chain(deletions).collect()
+    }
+}
+
+/// Outcome of a mirror
+#[derive(Debug, Default)]
+pub struct MirrorReport {
+    pub moved: usize,
+    pub downloaded: usize,
+    pub skipped: usize,
+    pub deleted: usize,
+    pub failed: Vec<(String, anyhow::Error)>,
+    /// Set when Ctrl-C stopped the downloads early, nothing is deleted then
+    pub interrupted: bool,
+}
+
+/// Local path of a `/` separated path relative to `root`
+pub fn local_path(root: &Path, relative: &str) -> PathBuf {
+    relative.split('/').fold(root.to_path_buf(), |path, name| path.join(name))
+}
+
+fn partial_path(target: &Path) -> PathBuf {
+    let mut partial = target.as_os_str().to_owned();
+    partial.push(PARTIAL_SUFFIX);
+    PathBuf::from(partial)
+}
+
+/// Creation time of the file's active revision, in seconds since the epoch
+fn remote_mtime(file: &FileNode) -> Option<i64> {
+    file.active_revision
+        .as_ref()
+        .map(|revision| revision.creation_time)
+        .filter(|time| *time > 0)
+}
+
+/// Size of the file's active revision
+pub fn remote_size(file: &FileNode) -> Option<u64> {
+    file.active_revision
+        .as_ref()
+        .and_then(|revision| revision.size)
+        .map(|size| size.max(0) as u64)
+}
+
+/// Paths the file at `relative` was at before the logged moves, most recent first
+fn previous_paths<'a>(relative: &'a str, moves: &'a [Move]) -> impl Iterator<Item = String> + 'a {
+    moves.iter().filter_map(move |moved| {
+        if moved.to == relative {
+            return Some(moved.from.clone());
+        }
+        let rest = relative.strip_prefix(&moved.to)?.strip_prefix('/')?;
+        moved.is_folder.then(|| format!("{}/{}", moved.from, rest))
+    })
+}
+
+/// Lists the local files under `dir` by their `/` separated path relative to the root
+fn scan(dir: &Path, prefix: &str, files: &mut Vec<(String, PathBuf)>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+            warn!("Leaving {} alone, its name isn't valid UTF-8", path.display());
+            continue;
+        };
+        let relative = match prefix {
+            "" => name,
+            _ => format!("{}/{}", prefix, name),
+        };
+
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            scan(&path, &relative, files)?;
+        } else {
+            files.push((relative, path));
+        }
+    }
+    Ok(())
+}
+
+/// Compares the indexed remote folder against the local one
+///
+/// Local files with the size and modification time of the remote revision are
+/// up to date. Downloaded files get the remote modification time, so an
+/// interrupted mirror picks up where it stopped. A missing file whose local
+/// copy is still at a path it was moved from is renamed rather than downloaded.
+pub fn plan(subtree: Subtree, options: &MirrorOptions) -> anyhow::Result<Plan> {
+    let root = &options.local_root;
+    let mut plan = Plan {
+        folders: subtree.folders.iter().map(|relative| local_path(root, relative)).collect(),
+        ..Default::default()
+    };
+
+    let remote: HashSet<String> = subtree.files.iter().map(|(relative, _)| relative.clone()).collect();
+    let mut renamed = HashSet::new();
+    'files: for (relative, file) in subtree.files {
+        let target = local_path(root, &relative);
+        match compare_local(&target, &file, ComparePolicy::default())? {
+            ChangeState::Unchanged => plan.skipped += 1,
+            state @ ChangeState::LocalMissing => {
+                for previous in previous_paths(&relative, &subtree.moves) {
+                    if remote.contains(&previous) || renamed.contains(&previous) {
+                        continue;
+                    }
+                    let from = local_path(root, &previous);
+                    if compare_local(&from, &file, ComparePolicy::default())? == ChangeState::Unchanged {
+                        debug!("{} was moved from {}", relative, previous);
+                        renamed.insert(previous);
+                        plan.renames.push(Rename { from, to: target });
+                        continue 'files;
+                    }
+                }
+                plan.downloads.push(Download { relative, file, target, state });
+            }
+            state => {
+                debug!("{} is {:?}", relative, state);
+                plan.downloads.push(Download { relative, file, target, state });
+            }
+        }
+    }
+
+    if options.delete_local && root.is_dir() {
+        let mut local = Vec::new();
+        scan(root, "", &mut local)?;
+        plan.deletions = local
+            .into_iter()
+            .filter(|(relative, _)| !remote.contains(relative) && !renamed.contains(relative))
+            .map(|(_, path)| path)
+            .collect();
+    }
+    Ok(plan)
+}
+
+/// Plans downloading `files` again whatever their local copies look like, as `verify --repair` does
+pub fn redownloads(files: Vec<(String, FileNode)>, options: &MirrorOptions) -> Plan {
+    let downloads = files
+        .into_iter()
+        .map(|(relative, file)| Download {
+            target: local_path(&options.local_root, &relative),
+            relative,
+            file,
+            // the local copy doesn't match and isn't known to be older
+            state: ChangeState::Conflict,
+        })
+        .collect();
+    Plan { downloads, ..Default::default() }
+}
+
+/// Moves a local file along with its remote one, removing the folders it leaves empty
+fn rename(root: &Path, rename: &Rename) -> io::Result<()> {
+    if let Some(parent) = rename.to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::rename(&rename.from, &rename.to)?;
+
+    let mut dir = rename.from.parent();
+    while let Some(parent) = dir.filter(|parent| parent.starts_with(root) && *parent != root) {
+        if fs::remove_dir(parent).is_err() {
+            break;
+        }
+        dir = parent.parent();
+    }
+    Ok(())
+}
+
+/// Downloads next to the target and renames over it, so a partial file never looks complete
+async fn download(context: &Context<impl DriveOps>, download: &Download) -> anyhow::Result<()> {
+    if let Some(parent) = download.target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let partial = partial_path(&download.target);
+    match fs::remove_file(&partial) {
+        Ok(()) => debug!("Removed the leftover {}", partial.display()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    context.download_file(&download.file, &partial, None::<fn(f32)>).await?;
+    fs::rename(&partial, &download.target)?;
+
+    if let Some(mtime) = remote_mtime(&download.file) {
+        File::options()
+            .write(true)
+            .open(&download.target)?
+            .set_modified(UNIX_EPOCH + Duration::from_secs(mtime as u64))?;
+    }
+    Ok(())
+}
+
+/// Carries out a plan, stopping new downloads once `shutdown` is set
+pub async fn apply(context: &Context<impl DriveOps>, plan: Plan, options: &MirrorOptions, shutdown: &AtomicBool) -> MirrorReport {
+    let mut report = MirrorReport {
+        skipped: plan.skipped,
+        ..Default::default()
+    };
+
+    for moved in &plan.renames {
+        match rename(&options.local_root, moved) {
+            Ok(()) => {
+                println!("Moved {} to {}", moved.from.display(), moved.to.display());
+                report.moved += 1;
+            }
+            Err(e) => report.failed.push((moved.to.display().to_string(), e.into())),
+        }
+    }
+
+    for folder in &plan.folders {
+        if let Err(e) = fs::create_dir_all(folder) {
+            report.failed.push((folder.display().to_string(), e.into()));
+        }
+    }
+
+    let mut downloads = stream::iter(plan.downloads)
+        .take_while(|_| future::ready(!shutdown.load(Ordering::Relaxed)))
+        .map(|job| async move {
+            let result = download(context, &job).await;
+            (job.relative, result)
+        })
+        .buffer_unordered(options.workers.max(1));
+
+    while let Some((relative, result)) = downloads.next().await {
+        match result {
+            Ok(()) => {
+                println!("Downloaded {}", relative);
+                report.downloaded += 1;
+            }
+            Err(e) => report.failed.push((relative, e)),
+        }
+    }
+
+    if shutdown.load(Ordering::Relaxed) {
+        report.interrupted = true;
+        return report;
+    }
+
+    for path in &plan.deletions {
+        match fs::remove_file(path) {
+            Ok(()) => {
+                println!("Deleted {}", path.display());
+                report.deleted += 1;
+            }
+            Err(e) => report.failed.push((path.display().to_string(), e.into())),
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proton_sdk_sys::protobufs::drive::Revision;
+
+    use crate::index;
+    use crate::remote::fake::FakeDrive;
+
+    const REMOTE_MTIME: i64 = 1_700_000_000;
+
+    fn remote(size: i64) -> FileNode {
+        FileNode {
+            active_revision: Some(Revision {
+                size: Some(size),
+                creation_time: REMOTE_MTIME,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn local_root(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("proton-drive-mirror-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("docs")).unwrap();
+        root
+    }
+
+    fn write(path: &Path, contents: &[u8], mtime: i64) {
+        fs::write(path, contents).unwrap();
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(UNIX_EPOCH + Duration::from_secs(mtime as u64))
+            .unwrap();
+    }
+
+    fn options(root: &Path, delete_local: bool) -> MirrorOptions {
+        MirrorOptions {
+            remote: RemotePath::root(),
+            local_root: root.to_path_buf(),
+            delete_local,
+            dry_run: false,
+            workers: 1,
+        }
+    }
+
+    fn subtree() -> Subtree {
+        Subtree {
+            folders: vec!["docs".to_string()],
+            files: vec![
+                ("docs/done.txt".to_string(), remote(5)),
+                ("docs/changed.txt".to_string(), remote(5)),
+                ("missing.txt".to_string(), remote(5)),
+            ],
+            moves: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn only_missing_and_changed_files_are_downloaded() {
+        let root = local_root("plan");
+        write(&root.join("docs").join("done.txt"), b"hello", REMOTE_MTIME);
+        write(&root.join("docs").join("changed.txt"), b"hello!", REMOTE_MTIME - 60);
+        write(&root.join("extra.txt"), b"local", REMOTE_MTIME);
+
+        let plan = plan(subtree(), &options(&root, false)).unwrap();
+        let downloads: Vec<&str> = plan.downloads.iter().map(|download| download.relative.as_str()).collect();
+        assert_eq!(downloads, ["docs/changed.txt", "missing.txt"]);
+        assert_eq!(plan.downloads[1].target, root.join("missing.txt"));
+        assert_eq!(plan.skipped, 1);
+        assert!(plan.deletions.is_empty());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn dry_run_entries_say_why_each_path_changes() {
+        let root = local_root("dry-run");
+        write(&root.join("docs").join("done.txt"), b"hello", REMOTE_MTIME);
+        write(&root.join("docs").join("changed.txt"), b"hello!", REMOTE_MTIME - 60);
+        write(&root.join("extra.txt"), b"local", REMOTE_MTIME);
+
+        let entries = plan(subtree(), &options(&root, true)).unwrap().entries();
+        let entries: Vec<_> = entries
+            .iter()
+            .map(|entry| (entry.action, entry.path.strip_prefix(&root).unwrap(), entry.bytes, entry.reason.as_str()))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                (Action::Download, Path::new("docs/changed.txt"), Some(5), "the remote revision is newer"),
+                (Action::Download, Path::new("missing.txt"), Some(5), "missing locally"),
+                (Action::Delete, Path::new("extra.txt"), Some(5), "not in the remote folder"),
+            ]
+        );
+        assert!(root.join("extra.txt").exists());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn local_only_files_are_deleted_when_asked() {
+        let root = local_root("delete");
+        write(&root.join("docs").join("done.txt"), b"hello", REMOTE_MTIME);
+        write(&root.join("docs").join("stale.txt"), b"old", REMOTE_MTIME);
+        write(&partial_path(&root.join("missing.txt")), b"he", REMOTE_MTIME);
+
+        let mut deletions = plan(subtree(), &options(&root, true)).unwrap().deletions;
+        deletions.sort();
+        assert_eq!(deletions, [root.join("docs").join("stale.txt"), partial_path(&root.join("missing.txt"))]);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn local_copies_of_moved_files_are_renamed_instead_of_downloaded() {
+        let root = local_root("moves");
+        write(&root.join("docs").join("a.txt"), b"hello", REMOTE_MTIME);
+        write(&root.join("b.txt"), b"hello", REMOTE_MTIME);
+        write(&root.join("stale.txt"), b"hello", REMOTE_MTIME - 60);
+
+        let subtree = Subtree {
+            folders: vec!["papers".to_string()],
+            files: vec![
+                ("papers/a.txt".to_string(), remote(5)),
+                ("c.txt".to_string(), remote(5)),
+                ("fresh.txt".to_string(), remote(5)),
+            ],
+            moves: vec![
+                Move { from: "stale.txt".to_string(), to: "fresh.txt".to_string(), is_folder: false },
+                Move { from: "b.txt".to_string(), to: "c.txt".to_string(), is_folder: false },
+                Move { from: "docs".to_string(), to: "papers".to_string(), is_folder: true },
+            ],
+        };
+
+        let plan = plan(subtree, &options(&root, true)).unwrap();
+        assert_eq!(
+            plan.renames,
+            [
+                Rename { from: root.join("docs").join("a.txt"), to: root.join("papers").join("a.txt") },
+                Rename { from: root.join("b.txt"), to: root.join("c.txt") },
+            ]
+        );
+        // the copy left at the old path is outdated, so it's downloaded and the copy deleted
+        let downloads: Vec<&str> = plan.downloads.iter().map(|download| download.relative.as_str()).collect();
+        assert_eq!(downloads, ["fresh.txt"]);
+        assert_eq!(plan.deletions, [root.join("stale.txt")]);
+
+        for moved in &plan.renames {
+            rename(&root, moved).unwrap();
+        }
+        assert!(root.join("papers").join("a.txt").is_file());
+        assert!(!root.join("docs").exists());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn mirrors_download_the_drive_and_follow_its_moves() {
+        let root = local_root("apply");
+        let drive = FakeDrive::new();
+        drive.add_file("Photos/beach.jpg", b"jpeg");
+        drive.add_file("notes.txt", b"notes");
+        let context = Context::with_drive(drive).await.unwrap();
+        let drive = context.client();
+        let pool = index::open(&root.join("index.db"), None).unwrap();
+        let shutdown = AtomicBool::new(false);
+        let options = MirrorOptions { local_root: root.join("mirror"), ..options(&root, false) };
+        let mirror = || async {
+            index::refresh(drive, context.root(), &pool, 1, &shutdown, |_| {}).await.unwrap();
+            let plan = plan(index::subtree(&pool, "").unwrap().unwrap(), &options).unwrap();
+            apply(&context, plan, &options, &shutdown).await
+        };
+
+        let report = mirror().await;
+        assert!(report.failed.is_empty());
+        assert_eq!(report.downloaded, 2);
+        assert_eq!(fs::read(options.local_root.join("Photos").join("beach.jpg")).unwrap(), b"jpeg");
+
+        drive.rename("Photos/beach.jpg", "Trips/beach.jpg");
+        drive.add_file("notes.txt", b"more notes");
+        let report = mirror().await;
+        assert_eq!((report.moved, report.downloaded, report.skipped), (1, 1, 0));
+        assert!(options.local_root.join("Trips").join("beach.jpg").is_file());
+        assert!(!options.local_root.join("Photos").join("beach.jpg").exists());
+        assert_eq!(fs::read(options.local_root.join("notes.txt")).unwrap(), b"more notes");
+
+        assert_eq!(mirror().await.skipped, 2);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,164 @@
+use std::io::{self, Write};
+
+use proton_sdk_rs::{VolumeId, VolumeMetadata};
+use proton_sdk_sys::protobufs::drive::VolumeState;
+use proton_sdk_sys::protobufs::human_bytes;
+use serde::Serialize;
+
+use crate::index::{PathUsage, Usage};
+
+/// Size of one volume of the account
+#[derive(Debug, Serialize)]
+pub struct VolumeQuota {
+    pub volume_id: String,
+    pub state: &'static str,
+    pub max_space: i64,
+    /// Indexed bytes, only known for the volume of the indexed share
+    pub used: Option<i64>,
+}
+
+/// Storage used against the account's space, as printed by `quota --json`
+#[derive(Debug, Serialize)]
+pub struct QuotaReport {
+    /// Space of every volume together
+    pub total: i64,
+    pub used: Option<i64>,
+    pub percent: Option<f64>,
+    pub volumes: Vec<VolumeQuota>,
+    pub largest_files: Vec<PathUsage>,
+    pub largest_folders: Vec<PathUsage>,
+    /// Caveats about where the numbers came from
+    pub notes: Vec<String>,
+}
+
+fn state_name(state: VolumeState) -> &'static str {
+    match state {
+        VolumeState::None => "none",
+        VolumeState::Active => "active",
+        VolumeState::Deleted => "deleted",
+        VolumeState::Locked => "locked",
+        VolumeState::Restored => "restored",
+    }
+}
+
+impl QuotaReport {
+    /// Puts the volumes' space together with the usage of the index of `indexed_volume`
+    ///
+    /// The SDK bindings have no quota call, so the used space is always the
+    /// index's sum, `None` without an index.
+    pub fn new(volumes: &[VolumeMetadata], indexed_volume: Option<&VolumeId>, usage: Option<Usage>, complete: bool) -> Self {
+        let used = usage.as_ref().map(|usage| usage.bytes);
+        let total = volumes.iter().map(|volume| volume.max_space).sum();
+
+        let mut notes = vec![
+            "The SDK bindings can't query the quota, the used space is summed from the index and leaves out older revisions and the trash"
+                .to_string(),
+        ];
+        if usage.is_none() {
+            notes.push("No index yet, run `proton-drive index` to see the used space and the largest paths".to_string());
+        } else if !complete {
+            notes.push("The index is incomplete, more space may be used than shown".to_string());
+        }
+
+        let volumes = volumes
+            .iter()
+            .map(|volume| {
+                let indexed = volume.volume_id.is_some() && volume.volume_id.as_ref() == indexed_volume;
+                VolumeQuota {
+                    volume_id: volume.volume_id.as_ref().map(|id| id.value.clone()).unwrap_or_default(),
+                    state: state_name(volume.state()),
+                    max_space: volume.max_space,
+                    used: used.filter(|_| indexed),
+                }
+            })
+            .collect();
+
+        let (largest_files, largest_folders) = usage.map(|usage| (usage.files, usage.folders)).unwrap_or_default();
+        Self {
+            total,
+            used,
+            percent: used.filter(|_| total > 0).map(|used| used as f64 * 100.0 / total as f64),
+            volumes,
+            largest_files,
+            largest_folders,
+            notes,
+        }
+    }
+
+    pub fn print(&self) -> io::Result<()> {
+        let mut stdout = io::stdout().lock();
+        match (self.used, self.percent) {
+            (Some(used), Some(percent)) => writeln!(
+                stdout,
+                "Used {} of {} ({:.1}%)",
+                human_bytes(used),
+                human_bytes(self.total),
+                percent
+            )?,
+            (Some(used), None) => writeln!(stdout, "Used {}", human_bytes(used))?,
+            (None, _) => writeln!(stdout, "Space {}, used unknown", human_bytes(self.total))?,
+        }
+
+        for volume in &self.volumes {
+            let used = volume.used.map(|used| format!(", {} used", human_bytes(used))).unwrap_or_default();
+            writeln!(
+                stdout,
+                "  Volume {} ({}): {}{}",
+                volume.volume_id,
+                volume.state,
+                human_bytes(volume.max_space),
+                used
+            )?;
+        }
+
+        for (title, paths, suffix) in [("files", &self.largest_files, ""), ("folders", &self.largest_folders, "/")] {
+            if paths.is_empty() {
+                continue;
+            }
+            writeln!(stdout, "Largest {}:", title)?;
+            for path in paths {
+                writeln!(stdout, "{:>12}  /{}{}", human_bytes(path.bytes), path.path, suffix)?;
+            }
+        }
+
+        for note in &self.notes {
+            writeln!(stdout, "Note: {}", note)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn volume(id: &str, max_space: i64) -> VolumeMetadata {
+        VolumeMetadata {
+            volume_id: Some(VolumeId { value: id.to_string() }),
+            state: VolumeState::Active as i32,
+            max_space,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn indexed_usage_counts_against_every_volume_with_a_note() {
+        let volumes = [volume("main", 3000), volume("photos", 1000)];
+        let usage = Usage {
+            bytes: 1000,
+            files: vec![PathUsage { path: "big.iso".to_string(), bytes: 900 }],
+            folders: Vec::new(),
+        };
+
+        let report = QuotaReport::new(&volumes, volumes[0].volume_id.as_ref(), Some(usage), false);
+        assert_eq!((report.total, report.used, report.percent), (4000, Some(1000), Some(25.0)));
+        assert_eq!(report.volumes[0].used, Some(1000));
+        assert_eq!(report.volumes[1].used, None);
+        assert_eq!(report.largest_files[0].path, "big.iso");
+        assert_eq!(report.notes.len(), 2);
+
+        let unindexed = QuotaReport::new(&volumes, volumes[0].volume_id.as_ref(), None, false);
+        assert_eq!((unindexed.used, unindexed.percent), (None, None));
+        assert!(unindexed.notes[1].starts_with("No index yet"));
+    }
+}
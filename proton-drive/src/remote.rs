@@ -0,0 +1,120 @@
+#[cfg(test)]
+pub mod fake;
+
+use proton_sdk_rs::downloads::DownloaderBuilder;
+use proton_sdk_rs::progress;
+use proton_sdk_rs::drive::{DriveClient, DriveError};
+use proton_sdk_rs::nodes::{NodeError, NodeIdentityExt};
+use proton_sdk_rs::uploads::UploaderBuilder;
+use proton_sdk_rs::{
+    FileDownloadRequest, FileNode, FileUploadRequest, FileUploaderCreationRequest, NodeIdentity, NodeType, Share,
+    VolumeMetadata,
+};
+
+/// The calls the indexer and the sync commands make to the drive
+///
+/// [`DriveClient`] makes them through the SDK, the tests against an in-memory
+/// drive that they change between runs.
+pub trait DriveOps {
+    async fn get_volumes(&self) -> Result<Vec<VolumeMetadata>, DriveError>;
+
+    async fn get_shares(&self, volume: &VolumeMetadata) -> Result<Vec<Share>, DriveError>;
+
+    /// Lists a folder, the identity of the children may lack what they share with it
+    async fn get_folder_children(&self, folder: NodeIdentity) -> Result<Vec<NodeType>, DriveError>;
+
+    /// Returns the identity of a listed `node`, completed from `root` as the listings leave ids out
+    fn complete_identity(&self, node: &impl NodeIdentityExt, root: &NodeIdentity) -> Result<NodeIdentity, NodeError> {
+        node.full_identity(root)
+    }
+
+    /// Writes the revision `request` names to its target path, overwriting it
+    async fn download<F>(&self, request: FileDownloadRequest, progress_callback: Option<F>) -> anyhow::Result<()>
+    where
+        F: Fn(f32) + Send + Sync + 'static;
+
+    /// Uploads the `file_size` bytes of the source file, as a new revision if the name is taken
+    async fn upload<F>(
+        &self,
+        request: FileUploadRequest,
+        file_size: i64,
+        progress_callback: Option<F>,
+    ) -> anyhow::Result<FileNode>
+    where
+        F: Fn(f32) + Send + Sync + 'static;
+
+    /// Moves a file or folder to the trash
+    async fn trash(&self, node: NodeIdentity) -> anyhow::Result<()>;
+}
+
+impl DriveOps for DriveClient {
+    async fn get_volumes(&self) -> Result<Vec<VolumeMetadata>, DriveError> {
+        DriveClient::get_volumes(self).await
+    }
+
+    async fn get_shares(&self, volume: &VolumeMetadata) -> Result<Vec<Share>, DriveError> {
+        DriveClient::get_shares(self, volume).await
+    }
+
+    async fn get_folder_children(&self, folder: NodeIdentity) -> Result<Vec<NodeType>, DriveError> {
+        DriveClient::get_folder_children(self, folder).await
+    }
+
+    fn complete_identity(&self, node: &impl NodeIdentityExt, root: &NodeIdentity) -> Result<NodeIdentity, NodeError> {
+        DriveClient::complete_identity(self, node, root)
+    }
+
+    async fn download<F>(&self, request: FileDownloadRequest, progress_callback: Option<F>) -> anyhow::Result<()>
+    where
+        F: Fn(f32) + Send + Sync + 'static,
+    {
+        let downloader = DownloaderBuilder::new(self).build().await?;
+        downloader
+            .download_file(request, progress_callback.map(progress::fractions))
+            .await?;
+        Ok(())
+    }
+
+    async fn upload<F>(
+        &self,
+        request: FileUploadRequest,
+        file_size: i64,
+        progress_callback: Option<F>,
+    ) -> anyhow::Result<FileNode>
+    where
+        F: Fn(f32) + Send + Sync + 'static,
+    {
+        let uploader = UploaderBuilder::new(self)
+            .with_request(FileUploaderCreationRequest {
+                file_size,
+                number_of_samples: 0,
+            })
+            .build()
+            .await?;
+        Ok(uploader.upload_file_or_revision(request, progress_callback.map(progress::fractions)).await?)
+    }
+
+    async fn trash(&self, _node: NodeIdentity) -> anyhow::Result<()> {
+        anyhow::bail!("Trashing needs remote trash, which the SDK bindings don't expose yet")
+    }
+}
+
+/// The main share of the drive, the first share of its first volume, and the identity of its root
+pub async fn main_share(drive: &impl DriveOps) -> anyhow::Result<(Share, NodeIdentity)> {
+    let volumes = drive.get_volumes().await?;
+    let Some(main_volume) = volumes.first() else {
+        anyhow::bail!("The account has no volumes");
+    };
+
+    let shares = drive.get_shares(main_volume).await?;
+    let Some(share) = shares.into_iter().next() else {
+        anyhow::bail!("The main volume has no shares");
+    };
+
+    let root = NodeIdentity {
+        node_id: share.root_node_id.clone(),
+        share_id: share.share_id.clone(),
+        volume_id: main_volume.volume_id.clone(),
+    };
+    Ok((share, root))
+}
@@ -0,0 +1,365 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::sync::{Mutex, MutexGuard};
+
+use chrono::Utc;
+use proton_sdk_rs::drive::DriveError;
+use proton_sdk_rs::nodes::NodeError;
+use proton_sdk_rs::{
+    node_type, FileDownloadRequest, FileNode, FileUploadRequest, FolderNode, LinkId, NodeIdentity, NodeType, Revision,
+    Share, ShareId, VolumeId, VolumeMetadata,
+};
+use proton_sdk_sys::protobufs::drive::{NodeState, RevisionId};
+
+use super::DriveOps;
+
+pub const VOLUME_ID: &str = "volume";
+pub const SHARE_ID: &str = "share";
+pub const ROOT_ID: &str = "root";
+
+#[derive(Debug)]
+struct FakeNode {
+    parent: Option<String>,
+    name: String,
+    /// `None` for folders
+    content: Option<Vec<u8>>,
+    revision: u32,
+    /// Creation time of the revision, in seconds since the epoch
+    modified_at: i64,
+}
+
+impl FakeNode {
+    /// A folder, until content is stored in it
+    fn new(parent: &str, name: &str) -> Self {
+        Self { parent: Some(parent.to_string()), name: name.to_string(), content: None, revision: 0, modified_at: 0 }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Tree {
+    nodes: BTreeMap<String, FakeNode>,
+    next_id: u32,
+    /// Folders whose listings fail
+    failing: HashSet<String>,
+    /// Paths trashed through [`DriveOps::trash`]
+    trashed: Vec<String>,
+}
+
+/// A drive held in memory, with a single volume and share
+///
+/// The tests address files and folders by their `/` separated path under the
+/// root, and change them between runs the way another client of the drive
+/// would. Nodes keep their id when moved, like on the real drive.
+#[derive(Debug)]
+pub struct FakeDrive {
+    tree: Mutex<Tree>,
+}
+
+fn remote_error(primary_code: i64, message: String) -> DriveError {
+    let error = NodeError::Remote { kind: "FakeDriveException".to_string(), message, primary_code: Some(primary_code) };
+    DriveError::NodeError(error.into())
+}
+
+fn not_found(what: &str) -> DriveError {
+    remote_error(2501, format!("{} doesn't exist", what))
+}
+
+impl Tree {
+    fn id(&mut self) -> String {
+        self.next_id += 1;
+        format!("node-{}", self.next_id)
+    }
+
+    fn child(&self, parent: &str, name: &str) -> Option<&str> {
+        self.nodes
+            .iter()
+            .find(|(_, node)| node.parent.as_deref() == Some(parent) && node.name == name)
+            .map(|(id, _)| id.as_str())
+    }
+
+    fn find(&self, path: &str) -> Option<&str> {
+        path.split('/')
+            .filter(|name| !name.is_empty())
+            .try_fold(ROOT_ID, |parent, name| self.child(parent, name))
+    }
+
+    fn path(&self, id: &str) -> String {
+        let mut names = Vec::new();
+        let mut current = self.nodes.get(id);
+        while let Some(node) = current.filter(|node| node.parent.is_some()) {
+            names.push(node.name.as_str());
+            current = node.parent.as_deref().and_then(|parent| self.nodes.get(parent));
+        }
+        names.reverse();
+        names.join("/")
+    }
+
+    /// The folder at `path`, created along with its missing parents
+    fn folder(&mut self, path: &str) -> String {
+        let mut parent = ROOT_ID.to_string();
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            parent = match self.child(&parent, name) {
+                Some(id) => id.to_string(),
+                None => {
+                    let id = self.id();
+                    self.nodes.insert(id.clone(), FakeNode::new(&parent, name));
+                    id
+                }
+            };
+        }
+        parent
+    }
+
+    /// Stores a file in a folder, as a new revision when one has its name
+    fn store(&mut self, parent: &str, name: &str, content: Vec<u8>) -> Result<String, DriveError> {
+        let id = match self.child(parent, name) {
+            Some(id) if self.nodes[id].content.is_none() => {
+                return Err(remote_error(2500, format!("A folder called {} already exists", name)));
+            }
+            Some(id) => id.to_string(),
+            None => {
+                let id = self.id();
+                self.nodes.insert(id.clone(), FakeNode::new(parent, name));
+                id
+            }
+        };
+
+        let node = self.nodes.get_mut(&id).expect("just found or added");
+        node.content = Some(content);
+        node.revision += 1;
+        node.modified_at = Utc::now().timestamp();
+        Ok(id)
+    }
+
+    fn remove(&mut self, id: &str) {
+        let children: Vec<String> = self
+            .nodes
+            .iter()
+            .filter(|(_, node)| node.parent.as_deref() == Some(id))
+            .map(|(child, _)| child.clone())
+            .collect();
+        for child in children {
+            self.remove(&child);
+        }
+        self.nodes.remove(id);
+    }
+
+    fn identity(&self, id: &str) -> NodeIdentity {
+        NodeIdentity {
+            node_id: Some(LinkId { value: id.to_string() }),
+            share_id: Some(ShareId { value: SHARE_ID.to_string() }),
+            volume_id: Some(VolumeId { value: VOLUME_ID.to_string() }),
+        }
+    }
+
+    fn file_node(&self, id: &str, node: &FakeNode) -> FileNode {
+        let content = node.content.as_deref().unwrap_or_default();
+        FileNode {
+            node_identity: Some(self.identity(id)),
+            parent_id: node.parent.clone().map(|value| LinkId { value }),
+            name: node.name.clone(),
+            state: NodeState::Active as i32,
+            active_revision: Some(Revision {
+                revision_id: Some(RevisionId { value: format!("{}-revision-{}", id, node.revision) }),
+                file_id: Some(LinkId { value: id.to_string() }),
+                size: Some(content.len() as i64),
+                creation_time: node.modified_at,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn node_type(&self, id: &str, node: &FakeNode) -> NodeType {
+        let node_type = match node.content {
+            Some(_) => node_type::NodeType::FileNode(self.file_node(id, node)),
+            None => node_type::NodeType::FolderNode(FolderNode {
+                node_identity: Some(self.identity(id)),
+                parent_id: node.parent.clone().map(|value| LinkId { value }),
+                name: node.name.clone(),
+                state: NodeState::Active as i32,
+                ..Default::default()
+            }),
+        };
+        NodeType { node_type: Some(node_type) }
+    }
+}
+
+impl FakeDrive {
+    /// An empty drive
+    pub fn new() -> Self {
+        let mut tree = Tree::default();
+        let root = FakeNode { parent: None, ..FakeNode::new("", "") };
+        tree.nodes.insert(ROOT_ID.to_string(), root);
+        Self { tree: Mutex::new(tree) }
+    }
+
+    fn tree(&self) -> MutexGuard<'_, Tree> {
+        self.tree.lock().unwrap()
+    }
+
+    /// Adds a folder along with its missing parents, returning its node id
+    pub fn add_folder(&self, path: &str) -> String {
+        self.tree().folder(path)
+    }
+
+    /// Adds a file, or a new revision of it, along with its missing parents, returning its node id
+    pub fn add_file(&self, path: &str, content: &[u8]) -> String {
+        let (parent, name) = path.rsplit_once('/').unwrap_or(("", path));
+        let mut tree = self.tree();
+        let parent = tree.folder(parent);
+        tree.store(&parent, name, content.to_vec()).unwrap()
+    }
+
+    /// Moves or renames a file or folder, which keeps its node id
+    pub fn rename(&self, from: &str, to: &str) {
+        let (parent, name) = to.rsplit_once('/').unwrap_or(("", to));
+        let mut tree = self.tree();
+        let id = tree.find(from).unwrap_or_else(|| panic!("no {} to move", from)).to_string();
+        let parent = tree.folder(parent);
+        let node = tree.nodes.get_mut(&id).unwrap();
+        node.parent = Some(parent);
+        node.name = name.to_string();
+    }
+
+    /// Deletes a file or folder and everything under it
+    pub fn remove(&self, path: &str) {
+        let mut tree = self.tree();
+        let id = tree.find(path).unwrap_or_else(|| panic!("no {} to remove", path)).to_string();
+        tree.remove(&id);
+    }
+
+    /// Makes the listings of the folder fail, or succeed again
+    pub fn fail_listing(&self, path: &str, failing: bool) {
+        let mut tree = self.tree();
+        let id = tree.find(path).unwrap_or_else(|| panic!("no {} to fail", path)).to_string();
+        if failing {
+            tree.failing.insert(id);
+        } else {
+            tree.failing.remove(&id);
+        }
+    }
+
+    /// Content of the file at `path`
+    pub fn content(&self, path: &str) -> Option<Vec<u8>> {
+        let tree = self.tree();
+        tree.find(path).and_then(|id| tree.nodes[id].content.clone())
+    }
+
+    /// Revisions the file at `path` had, counting the first one
+    pub fn revision(&self, path: &str) -> Option<u32> {
+        let tree = self.tree();
+        tree.find(path).map(|id| tree.nodes[id].revision).filter(|revision| *revision > 0)
+    }
+
+    /// Paths of the files, sorted
+    pub fn files(&self) -> Vec<String> {
+        let tree = self.tree();
+        let mut files: Vec<String> = tree
+            .nodes
+            .iter()
+            .filter(|(_, node)| node.content.is_some())
+            .map(|(id, _)| tree.path(id))
+            .collect();
+        files.sort();
+        files
+    }
+
+    /// Paths trashed through [`DriveOps::trash`], in order
+    pub fn trashed(&self) -> Vec<String> {
+        self.tree().trashed.clone()
+    }
+}
+
+fn node_id(identity: Option<&NodeIdentity>) -> Option<&str> {
+    identity.and_then(|identity| identity.node_id.as_ref()).map(|id| id.value.as_str())
+}
+
+impl DriveOps for FakeDrive {
+    async fn get_volumes(&self) -> Result<Vec<VolumeMetadata>, DriveError> {
+        Ok(vec![VolumeMetadata {
+            volume_id: Some(VolumeId { value: VOLUME_ID.to_string() }),
+            root_share_id: Some(ShareId { value: SHARE_ID.to_string() }),
+            ..Default::default()
+        }])
+    }
+
+    async fn get_shares(&self, volume: &VolumeMetadata) -> Result<Vec<Share>, DriveError> {
+        if volume.volume_id.as_ref().is_none_or(|id| id.value != VOLUME_ID) {
+            return Err(not_found("The volume"));
+        }
+        Ok(vec![Share {
+            share_id: Some(ShareId { value: SHARE_ID.to_string() }),
+            volume_id: Some(VolumeId { value: VOLUME_ID.to_string() }),
+            root_node_id: Some(LinkId { value: ROOT_ID.to_string() }),
+            ..Default::default()
+        }])
+    }
+
+    async fn get_folder_children(&self, folder: NodeIdentity) -> Result<Vec<NodeType>, DriveError> {
+        let tree = self.tree();
+        let id = node_id(Some(&folder)).unwrap_or_default();
+        if tree.failing.contains(id) {
+            return Err(remote_error(2011, format!("Listing {} isn't allowed", tree.path(id))));
+        }
+        if tree.nodes.get(id).is_none_or(|node| node.content.is_some()) {
+            return Err(not_found("The folder"));
+        }
+        Ok(tree
+            .nodes
+            .iter()
+            .filter(|(_, node)| node.parent.as_deref() == Some(id))
+            .map(|(child, node)| tree.node_type(child, node))
+            .collect())
+    }
+
+    async fn download<F>(&self, request: FileDownloadRequest, progress_callback: Option<F>) -> anyhow::Result<()>
+    where
+        F: Fn(f32) + Send + Sync + 'static,
+    {
+        let content = {
+            let tree = self.tree();
+            let id = node_id(request.file_identity.as_ref()).unwrap_or_default();
+            tree.nodes.get(id).and_then(|node| node.content.clone()).ok_or_else(|| not_found("The file"))?
+        };
+        fs::write(&request.target_file_path, content)?;
+        if let Some(progress) = progress_callback {
+            progress(1.0);
+        }
+        Ok(())
+    }
+
+    async fn upload<F>(
+        &self,
+        request: FileUploadRequest,
+        _file_size: i64,
+        progress_callback: Option<F>,
+    ) -> anyhow::Result<FileNode>
+    where
+        F: Fn(f32) + Send + Sync + 'static,
+    {
+        let content = fs::read(&request.source_file_path)?;
+        let mut tree = self.tree();
+        let parent = node_id(request.parent_folder_identity.as_ref()).unwrap_or_default();
+        if tree.nodes.get(parent).is_none_or(|node| node.content.is_some()) {
+            return Err(not_found("The parent folder").into());
+        }
+        let id = tree.store(parent, &request.name, content)?;
+        if let Some(progress) = progress_callback {
+            progress(1.0);
+        }
+        Ok(tree.file_node(&id, &tree.nodes[&id]))
+    }
+
+    async fn trash(&self, node: NodeIdentity) -> anyhow::Result<()> {
+        let mut tree = self.tree();
+        let id = node_id(Some(&node)).unwrap_or_default().to_string();
+        if id == ROOT_ID || !tree.nodes.contains_key(&id) {
+            return Err(not_found("The node").into());
+        }
+        let path = tree.path(&id);
+        tree.remove(&id);
+        tree.trashed.push(path);
+        Ok(())
+    }
+}
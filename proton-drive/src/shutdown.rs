@@ -0,0 +1,158 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+use std::time::Duration;
+
+use log::{debug, info, warn};
+use proton_sdk_rs::drive::DriveClient;
+use proton_sdk_rs::sessions::FileSessionStore;
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use tokio::sync::watch;
+
+use crate::auth;
+use crate::cli::EXIT_CANCELLED;
+use crate::index;
+
+/// What gets wound down when the process is asked to stop
+struct Shutdown {
+    requested: Arc<AtomicBool>,
+    notify: watch::Sender<bool>,
+    /// Shown on the first stop request, by the command running
+    message: Mutex<Option<&'static str>>,
+    /// Clients whose operations are cancelled once the grace period is over
+    clients: Mutex<Vec<(Weak<DriveClient>, PathBuf)>>,
+    /// Index pools checkpointed before exiting
+    pools: Mutex<Vec<Pool<SqliteConnectionManager>>>,
+}
+
+static SHUTDOWN: OnceLock<Shutdown> = OnceLock::new();
+
+fn shutdown() -> &'static Shutdown {
+    SHUTDOWN.get_or_init(|| Shutdown {
+        requested: Arc::new(AtomicBool::new(false)),
+        notify: watch::channel(false).0,
+        message: Mutex::new(None),
+        clients: Mutex::new(Vec::new()),
+        pools: Mutex::new(Vec::new()),
+    })
+}
+
+/// Waits for Ctrl-C, or SIGTERM on Unix
+async fn stop_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        if let Ok(mut terminate) = signal(SignalKind::terminate()) {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = terminate.recv() => {}
+            }
+            return;
+        }
+        warn!("Unable to listen for SIGTERM");
+    }
+    if tokio::signal::ctrl_c().await.is_err() {
+        std::future::pending::<()>().await;
+    }
+}
+
+/// Listens for stop requests for the rest of the process
+///
+/// The first request sets the flag returned by [`flag`], which the indexer and
+/// the transfers check between work items, and saves the sessions. Work still
+/// running after `grace` has its SDK operations cancelled. A second request
+/// exits right away.
+pub fn install(grace: Duration) {
+    let shutdown = shutdown();
+    tokio::spawn(async move {
+        stop_signal().await;
+        let message = shutdown.message.lock().unwrap().take();
+        eprintln!("\n{} Press Ctrl-C again to quit now.", message.unwrap_or("Stopping..."));
+        shutdown.requested.store(true, Ordering::Relaxed);
+        shutdown.notify.send_replace(true);
+        persist_sessions();
+
+        tokio::select! {
+            _ = stop_signal() => force_exit(),
+            _ = tokio::time::sleep(grace) => {
+                warn!("Still running after {:?}, cancelling the operations in flight", grace);
+                cancel_clients();
+            }
+        }
+        stop_signal().await;
+        force_exit();
+    });
+}
+
+fn force_exit() -> ! {
+    eprintln!("\nQuitting now");
+    std::process::exit(EXIT_CANCELLED);
+}
+
+/// Flag set once the process is asked to stop, with the message to show the user then
+pub fn flag(message: &'static str) -> Arc<AtomicBool> {
+    let shutdown = shutdown();
+    *shutdown.message.lock().unwrap() = Some(message);
+    shutdown.requested.clone()
+}
+
+/// Checks if the process was asked to stop
+pub fn is_requested() -> bool {
+    shutdown().requested.load(Ordering::Relaxed)
+}
+
+/// Waits until the process is asked to stop
+pub async fn requested() {
+    let mut requested = shutdown().notify.subscribe();
+    let _ = requested.wait_for(|requested| *requested).await;
+}
+
+/// Cancels the operations of `client` when the grace period runs out, and saves its session on stop
+pub fn register_client(client: &Arc<DriveClient>, session_file: PathBuf) {
+    let mut clients = shutdown().clients.lock().unwrap();
+    clients.retain(|(client, _)| client.strong_count() > 0);
+    clients.push((Arc::downgrade(client), session_file));
+}
+
+/// Checkpoints the index of `pool` before the process exits
+pub fn register_pool(pool: &Pool<SqliteConnectionManager>) {
+    shutdown().pools.lock().unwrap().push(pool.clone());
+}
+
+fn live_clients() -> Vec<(Arc<DriveClient>, PathBuf)> {
+    let clients = shutdown().clients.lock().unwrap();
+    clients
+        .iter()
+        .filter_map(|(client, session_file)| Some((client.upgrade()?, session_file.clone())))
+        .collect()
+}
+
+fn persist_sessions() {
+    for (client, session_file) in live_clients() {
+        auth::persist_session(client.session(), &FileSessionStore::new(session_file));
+    }
+}
+
+fn cancel_clients() {
+    for (client, _) in live_clients() {
+        if let Err(e) = client.session().cancellation_token().cancel() {
+            warn!("Unable to cancel the operations in flight: {}", e);
+        }
+    }
+}
+
+/// Checkpoints and closes the registered indexes, call before exiting
+pub fn finish() {
+    let pools = std::mem::take(&mut *shutdown().pools.lock().unwrap());
+    for pool in pools {
+        match index::checkpoint(&pool) {
+            Ok(()) => debug!("Index checkpointed"),
+            Err(e) => warn!("Unable to checkpoint the index: {:#}", e),
+        }
+    }
+    if is_requested() {
+        info!("Stopped");
+    }
+}
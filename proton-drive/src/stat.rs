@@ -0,0 +1,233 @@
+use std::io::{self, Write};
+
+use chrono::DateTime;
+use proton_sdk_rs::nodes::{NodeTypeExt, RemotePath};
+use proton_sdk_rs::{FileNode, FolderNode, NodeIdentity, NodeType};
+use proton_sdk_sys::protobufs::drive::NodeState;
+use serde::Serialize;
+
+use crate::index::Subtree;
+
+/// Direct children of a folder
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub struct ChildCounts {
+    pub folders: usize,
+    pub files: usize,
+}
+
+impl ChildCounts {
+    /// Counts the children among everything indexed under a folder
+    pub fn indexed(subtree: &Subtree) -> Self {
+        let direct = |relative: &&str| !relative.contains('/');
+        Self {
+            folders: subtree.folders.iter().map(String::as_str).filter(direct).count(),
+            files: subtree.files.iter().map(|(relative, _)| relative.as_str()).filter(direct).count(),
+        }
+    }
+
+    /// Counts a folder's listed children
+    pub fn listed(children: &[NodeType]) -> Self {
+        Self {
+            folders: children.iter().filter(|child| child.is_folder()).count(),
+            files: children.iter().filter(|child| child.is_file()).count(),
+        }
+    }
+}
+
+/// Where the printed metadata was read from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    Index,
+    Live,
+}
+
+/// A remote node's ids and metadata, as printed by `stat --json`
+#[derive(Debug, Serialize)]
+pub struct Stat {
+    pub path: String,
+    pub kind: &'static str,
+    /// Compact identity, `volume:share:node` in URL-safe base64, which `stat` takes back
+    pub identity: String,
+    pub node_id: String,
+    pub share_id: String,
+    pub volume_id: String,
+    pub parent_id: Option<String>,
+    pub state: &'static str,
+    pub revision_id: Option<String>,
+    pub size: Option<i64>,
+    /// Guessed from the name, the drive doesn't return the type given at upload
+    pub mime_type: Option<String>,
+    /// Creation time of the active revision, in seconds since the epoch
+    pub modified: Option<i64>,
+    /// Address that signed the active revision
+    pub signature_email: Option<String>,
+    pub children: Option<ChildCounts>,
+    pub source: Source,
+}
+
+fn state_name(state: NodeState) -> &'static str {
+    match state {
+        NodeState::Draft => "draft",
+        NodeState::Active => "active",
+        NodeState::Trashed => "trashed",
+    }
+}
+
+impl Stat {
+    fn new(path: &RemotePath, kind: &'static str, identity: &NodeIdentity, source: Source) -> Self {
+        let id = |value: Option<&String>| value.cloned().unwrap_or_default();
+        Self {
+            path: path.to_string(),
+            kind,
+            identity: identity.to_compact_string(),
+            node_id: id(identity.node_id.as_ref().map(|id| &id.value)),
+            share_id: id(identity.share_id.as_ref().map(|id| &id.value)),
+            volume_id: id(identity.volume_id.as_ref().map(|id| &id.value)),
+            parent_id: None,
+            state: state_name(NodeState::Active),
+            revision_id: None,
+            size: None,
+            mime_type: None,
+            modified: None,
+            signature_email: None,
+            children: None,
+            source,
+        }
+    }
+
+    /// Describes a file, `identity` being its complete identity
+    pub fn file(path: &RemotePath, file: &FileNode, identity: &NodeIdentity, source: Source) -> Self {
+        let revision = file.active_revision.as_ref();
+        Self {
+            parent_id: file.parent_id.as_ref().map(|id| id.value.clone()),
+            state: state_name(file.state()),
+            revision_id: revision.and_then(|revision| revision.revision_id.as_ref()).map(|id| id.value.clone()),
+            size: revision.and_then(|revision| revision.size),
+            mime_type: Some(mime_guess::from_path(&file.name).first_or_octet_stream().to_string()),
+            modified: revision.map(|revision| revision.creation_time),
+            signature_email: revision.and_then(|revision| revision.signature_email_address.clone()),
+            ..Self::new(path, "file", identity, source)
+        }
+    }
+
+    /// Describes a folder, `None` for the root of the share, which has no node of its own
+    pub fn folder(
+        path: &RemotePath,
+        folder: Option<&FolderNode>,
+        identity: &NodeIdentity,
+        children: ChildCounts,
+        source: Source,
+    ) -> Self {
+        let mut stat = Self::new(path, "folder", identity, source);
+        if let Some(folder) = folder {
+            stat.parent_id = folder.parent_id.as_ref().map(|id| id.value.clone());
+            stat.state = state_name(folder.state());
+        }
+        stat.children = Some(children);
+        stat
+    }
+
+    /// Prints one labelled line per known field
+    pub fn print(&self) -> io::Result<()> {
+        let mut stdout = io::stdout().lock();
+        let mut line = |label: &str, value: &str| writeln!(stdout, "{:<14}{}", label, value);
+
+        line("Path", &self.path)?;
+        line("Kind", self.kind)?;
+        line("Identity", &self.identity)?;
+        line("Node id", &self.node_id)?;
+        line("Share id", &self.share_id)?;
+        line("Volume id", &self.volume_id)?;
+        if let Some(parent_id) = &self.parent_id {
+            line("Parent id", parent_id)?;
+        }
+        line("State", self.state)?;
+        if let Some(revision_id) = &self.revision_id {
+            line("Revision id", revision_id)?;
+        }
+        if let Some(size) = self.size {
+            line("Size", &format!("{} bytes", size))?;
+        }
+        if let Some(mime_type) = &self.mime_type {
+            line("Mime type", &format!("{} (guessed from the name)", mime_type))?;
+        }
+        if let Some(modified) = self.modified {
+            let time = DateTime::from_timestamp(modified, 0)
+                .map(|time| time.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+                .unwrap_or_else(|| modified.to_string());
+            line("Modified", &time)?;
+        }
+        if let Some(email) = &self.signature_email {
+            line("Signed by", email)?;
+        }
+        if let Some(children) = &self.children {
+            line("Children", &format!("{} folders, {} files", children.folders, children.files))?;
+        }
+        line(
+            "Source",
+            match self.source {
+                Source::Index => "index",
+                Source::Live => "live",
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proton_sdk_rs::nodes::NodeIdentityExt;
+    use proton_sdk_rs::{LinkId, Revision, ShareId, VolumeId};
+    use proton_sdk_sys::protobufs::drive::RevisionId;
+
+    fn identity(node: &str) -> NodeIdentity {
+        NodeIdentity {
+            node_id: Some(LinkId { value: node.to_string() }),
+            share_id: Some(ShareId { value: "share==".to_string() }),
+            volume_id: Some(VolumeId { value: "volume-_1".to_string() }),
+        }
+    }
+
+    #[test]
+    fn files_and_folders_show_their_ids_and_revision() {
+        let root = identity("root");
+        let file = FileNode {
+            node_identity: Some(NodeIdentity { node_id: Some(LinkId { value: "file".to_string() }), ..Default::default() }),
+            parent_id: Some(LinkId { value: "docs".to_string() }),
+            name: "notes.txt".to_string(),
+            state: NodeState::Active as i32,
+            active_revision: Some(Revision {
+                revision_id: Some(RevisionId { value: "rev".to_string() }),
+                signature_email_address: Some("me@proton.me".to_string()),
+                size: Some(5),
+                creation_time: 1_700_000_000,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let path = RemotePath::from_segments(["Docs", "notes.txt"]);
+
+        let stat = Stat::file(&path, &file, &file.full_identity(&root).unwrap(), Source::Index);
+        assert_eq!(stat.identity.parse::<NodeIdentity>().unwrap(), identity("file"));
+        assert_eq!(stat.parent_id.as_deref(), Some("docs"));
+        assert_eq!(stat.revision_id.as_deref(), Some("rev"));
+        assert_eq!((stat.size, stat.modified), (Some(5), Some(1_700_000_000)));
+        assert_eq!(stat.mime_type.as_deref(), Some("text/plain"));
+        assert_eq!(stat.signature_email.as_deref(), Some("me@proton.me"));
+        assert!(stat.children.is_none());
+
+        let subtree = Subtree {
+            folders: vec!["Docs".to_string(), "Docs/Old".to_string()],
+            files: vec![
+                ("a.txt".to_string(), FileNode::default()),
+                ("Docs/notes.txt".to_string(), file),
+            ],
+            moves: Vec::new(),
+        };
+        let stat = Stat::folder(&RemotePath::root(), None, &root, ChildCounts::indexed(&subtree), Source::Index);
+        assert_eq!((stat.kind, stat.node_id.as_str()), ("folder", "root"));
+        assert_eq!(stat.children, Some(ChildCounts { folders: 1, files: 1 }));
+        assert!(stat.revision_id.is_none());
+    }
+}
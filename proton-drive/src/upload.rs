@@ -0,0 +1,242 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use log::warn;
+use proton_sdk_rs::nodes::{NodeIdentityExt, NodeTypeExt, RemotePath};
+use proton_sdk_rs::{NodeIdentity, NodeType};
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+
+use crate::cli::NotFound;
+use crate::commands::{self, Context};
+use crate::index;
+
+/// What to do when the remote folder already has a file of the same name
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OnConflict {
+    /// Uploads the file as a new revision of the remote one
+    Revision,
+    /// Leaves the remote file as it is
+    Skip,
+    /// Fails the file
+    Error,
+}
+
+/// What `upload` sends and where
+#[derive(Debug, Clone)]
+pub struct UploadOptions {
+    pub local: PathBuf,
+    /// Folder the file or directory is uploaded into
+    pub remote: RemotePath,
+    pub create_parents: bool,
+    pub on_conflict: OnConflict,
+}
+
+/// Outcome of an upload
+#[derive(Debug, Default)]
+pub struct UploadReport {
+    pub uploaded: usize,
+    pub skipped: usize,
+    pub failed: Vec<(String, anyhow::Error)>,
+    /// Set when Ctrl-C stopped the upload before the last file
+    pub interrupted: bool,
+}
+
+fn child<'a>(children: &'a [NodeType], name: &str) -> Option<&'a NodeType> {
+    children.iter().find(|child| {
+        child.as_file().map(|file| file.name.as_str()) == Some(name)
+            || child.as_folder().map(|folder| folder.name.as_str()) == Some(name)
+    })
+}
+
+/// Uploads local files into remote folders, keeping the index up to date
+struct Uploader<'a> {
+    context: &'a Context,
+    pool: Option<&'a Pool<SqliteConnectionManager>>,
+    on_conflict: OnConflict,
+    shutdown: &'a AtomicBool,
+    report: UploadReport,
+}
+
+impl Uploader<'_> {
+    /// Finds the remote folder at `path`, through the index when it has it
+    async fn folder(&self, path: &RemotePath, create_parents: bool) -> anyhow::Result<NodeIdentity> {
+        if path.is_root() {
+            return Ok(self.context.root().clone());
+        }
+        if let Some(pool) = self.pool
+            && let Some(folder) = index::folder_node(pool, &path.segments().join("/"))?
+        {
+            return Ok(folder.full_identity(self.context.root())?);
+        }
+
+        match self.context.folder(path).await {
+            Err(e) if create_parents && e.downcast_ref::<NotFound>().is_some() => Err(e.context(
+                "--create-parents needs folder creation, which the SDK bindings don't define a request for yet",
+            )),
+            result => result,
+        }
+    }
+
+    /// Uploads a local file into `folder`, whose listing is `children`
+    async fn upload_file(
+        &mut self,
+        local: &Path,
+        folder: &NodeIdentity,
+        remote_folder: &RemotePath,
+        children: &[NodeType],
+        progress: bool,
+    ) -> anyhow::Result<()> {
+        let metadata = fs::metadata(local)?;
+        let Some(name) = local.file_name().and_then(|name| name.to_str()) else {
+            anyhow::bail!("{} has no usable file name", local.display());
+        };
+        let remote = remote_folder.join(name);
+
+        match (child(children, name), self.on_conflict) {
+            (Some(existing), _) if existing.is_folder() => {
+                anyhow::bail!("{} is a remote folder", remote);
+            }
+            (Some(_), OnConflict::Skip) => {
+                println!("Skipped {}, it already exists", remote);
+                self.report.skipped += 1;
+                return Ok(());
+            }
+            (Some(_), OnConflict::Error) => anyhow::bail!("{} already exists", remote),
+            _ => {}
+        }
+
+        let file = if progress {
+            let progress = commands::print_progress("Uploading");
+            self.context.upload_file(local, &metadata, folder.clone(), Some(progress)).await?
+        } else {
+            self.context.upload_file(local, &metadata, folder.clone(), None::<fn(f32)>).await?
+        };
+
+        if let Some(pool) = self.pool
+            && let Err(e) = index::record_file(pool, folder, &remote_folder.segments().join("/"), &file)
+        {
+            warn!("Uploaded {} but couldn't add it to the index: {:#}", remote, e);
+        }
+        println!("Uploaded {} to {}", local.display(), remote);
+        self.report.uploaded += 1;
+        Ok(())
+    }
+
+    /// Uploads the files under a local directory into the matching remote folders
+    async fn upload_dir(&mut self, dir: &Path, folder: NodeIdentity, remote: RemotePath) -> anyhow::Result<()> {
+        let children = self.context.children(folder.clone()).await?;
+
+        let mut entries = fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            if self.shutdown.load(Ordering::Relaxed) {
+                self.report.interrupted = true;
+                return Ok(());
+            }
+            let path = entry.path();
+            let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+                warn!("Skipping {}, its name isn't valid UTF-8", path.display());
+                continue;
+            };
+
+            let uploaded = if entry.file_type()?.is_dir() {
+                match child(&children, &name).and_then(|child| child.as_folder()) {
+                    Some(subfolder) => match subfolder.full_identity(&folder) {
+                        Ok(identity) => Box::pin(self.upload_dir(&path, identity, remote.join(&name))).await,
+                        Err(e) => Err(e.into()),
+                    },
+                    None => Err(NotFound(format!(
+                        "{} doesn't exist, and folders can't be created by this build",
+                        remote.join(&name)
+                    ))
+                    .into()),
+                }
+            } else {
+                self.upload_file(&path, &folder, &remote, &children, false).await
+            };
+
+            if let Err(e) = uploaded {
+                self.report.failed.push((path.display().to_string(), e));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Uploads a file or a directory tree into the remote folder
+///
+/// A directory is uploaded into the remote folder of the same name, which has
+/// to exist along with its subfolders. Files that fail are reported and the
+/// others carry on, until `shutdown` is set.
+pub async fn run(
+    context: &Context,
+    pool: Option<&Pool<SqliteConnectionManager>>,
+    options: &UploadOptions,
+    shutdown: &AtomicBool,
+) -> anyhow::Result<UploadReport> {
+    let metadata = fs::metadata(&options.local)?;
+    let mut uploader = Uploader {
+        context,
+        pool,
+        on_conflict: options.on_conflict,
+        shutdown,
+        report: UploadReport::default(),
+    };
+    let folder = uploader.folder(&options.remote, options.create_parents).await?;
+
+    if metadata.is_dir() {
+        let Some(name) = options.local.file_name().and_then(|name| name.to_str()) else {
+            anyhow::bail!("{} has no usable name", options.local.display());
+        };
+        let remote = options.remote.join(name);
+        let children = context.children(folder.clone()).await?;
+        let Some(subfolder) = child(&children, name).and_then(|child| child.as_folder()) else {
+            return Err(NotFound(format!("{} doesn't exist, and folders can't be created by this build", remote)).into());
+        };
+        uploader
+            .upload_dir(&options.local, subfolder.full_identity(&folder)?, remote)
+            .await?;
+    } else {
+        let children = match options.on_conflict {
+            // the SDK picks between a new file and a revision by itself
+            OnConflict::Revision => Vec::new(),
+            _ => context.children(folder.clone()).await?,
+        };
+        uploader
+            .upload_file(&options.local, &folder, &options.remote, &children, true)
+            .await?;
+    }
+
+    Ok(uploader.report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proton_sdk_rs::{FileNode, FolderNode};
+    use proton_sdk_sys::protobufs::drive::node_type;
+
+    #[test]
+    fn children_are_found_by_name_whatever_their_kind() {
+        let children = [
+            NodeType {
+                node_type: Some(node_type::NodeType::FileNode(FileNode {
+                    name: "notes.txt".to_string(),
+                    ..Default::default()
+                })),
+            },
+            NodeType {
+                node_type: Some(node_type::NodeType::FolderNode(FolderNode {
+                    name: "Photos".to_string(),
+                    ..Default::default()
+                })),
+            },
+        ];
+
+        assert!(child(&children, "notes.txt").is_some_and(|node| node.is_file()));
+        assert!(child(&children, "Photos").is_some_and(|node| node.is_folder()));
+        assert!(child(&children, "photos").is_none());
+    }
+}
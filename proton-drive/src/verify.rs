@@ -0,0 +1,212 @@
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use proton_sdk_rs::nodes::RemotePath;
+use proton_sdk_rs::FileNode;
+use sha2::{Digest, Sha256};
+
+use crate::index::Subtree;
+use crate::mirror::{self, remote_size};
+
+/// Bytes read at a time while hashing, the stop flag is checked between them
+const HASH_CHUNK: usize = 256 * 1024;
+
+/// What `verify` checks and how it repairs it
+#[derive(Debug, Clone)]
+pub struct VerifyOptions {
+    pub local_root: PathBuf,
+    pub remote: RemotePath,
+    /// Downloads the files that don't match again
+    pub repair: bool,
+    /// Files downloaded in parallel when repairing
+    pub workers: usize,
+}
+
+/// How a local file differs from its indexed remote revision
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Missing,
+    SizeMismatch,
+    HashMismatch,
+}
+
+/// A local file that doesn't match the remote one
+#[derive(Debug)]
+pub struct Mismatch {
+    pub relative: String,
+    pub status: Status,
+    pub file: FileNode,
+}
+
+/// Outcome of a verification
+#[derive(Debug, Default)]
+pub struct VerifyReport {
+    /// Files of the same size, and the same content when the revision has digests
+    pub ok: usize,
+    /// Files found OK by their size alone, their revision having no digests
+    pub unhashed: usize,
+    pub mismatches: Vec<Mismatch>,
+    /// Set when Ctrl-C stopped the verification before the last file
+    pub interrupted: bool,
+}
+
+impl VerifyReport {
+    pub fn count(&self, status: Status) -> usize {
+        self.mismatches.iter().filter(|mismatch| mismatch.status == status).count()
+    }
+}
+
+fn digests(file: &FileNode) -> &[proton_sdk_sys::prost::bytes::Bytes] {
+    file.active_revision
+        .as_ref()
+        .map(|revision| revision.samples_sha256_digests.as_slice())
+        .unwrap_or_default()
+}
+
+/// Streams a SHA-256 of the file, `None` once `shutdown` is set
+fn sha256(path: &Path, shutdown: &AtomicBool, mut hashed: impl FnMut(u64)) -> io::Result<Option<[u8; 32]>> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; HASH_CHUNK];
+    loop {
+        if shutdown.load(Ordering::Relaxed) {
+            return Ok(None);
+        }
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(Some(hasher.finalize().into()));
+        }
+        hasher.update(&buffer[..read]);
+        hashed(read as u64);
+    }
+}
+
+/// Checks every indexed file under the remote folder against its copy under `local_root`
+///
+/// Sizes are compared first, then the files whose revision has SHA-256
+/// digests are hashed, reporting the share of those bytes hashed so far to
+/// `progress`. Stops between chunks once `shutdown` is set. Blocks, so it is
+/// run off the async runtime.
+pub fn run(
+    subtree: Subtree,
+    local_root: &Path,
+    shutdown: &AtomicBool,
+    progress: impl Fn(f32),
+) -> io::Result<VerifyReport> {
+    let mut report = VerifyReport::default();
+    let mut to_hash: Vec<(String, PathBuf, FileNode)> = Vec::new();
+
+    for (relative, file) in subtree.files {
+        let path = mirror::local_path(local_root, &relative);
+        let metadata = match fs::metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                report.mismatches.push(Mismatch { relative, status: Status::Missing, file });
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+
+        if remote_size(&file).is_some_and(|size| size != metadata.len()) {
+            report.mismatches.push(Mismatch { relative, status: Status::SizeMismatch, file });
+        } else if digests(&file).is_empty() {
+            report.ok += 1;
+            report.unhashed += 1;
+        } else {
+            to_hash.push((relative, path, file));
+        }
+    }
+
+    let total: u64 = to_hash.iter().filter_map(|(_, _, file)| remote_size(file)).sum();
+    let mut done = 0u64;
+    for (relative, path, file) in to_hash {
+        let digest = sha256(&path, shutdown, |read| {
+            done += read;
+            if total > 0 {
+                progress(done as f32 / total as f32);
+            }
+        })?;
+        let Some(digest) = digest else {
+            report.interrupted = true;
+            return Ok(report);
+        };
+
+        if digests(&file).iter().any(|remote| remote.as_ref() == digest) {
+            report.ok += 1;
+        } else {
+            report.mismatches.push(Mismatch { relative, status: Status::HashMismatch, file });
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proton_sdk_sys::protobufs::drive::Revision;
+
+    fn remote(contents: &[u8], hashed: bool) -> FileNode {
+        let digests = if hashed { vec![Sha256::digest(contents).to_vec().into()] } else { Vec::new() };
+        FileNode {
+            active_revision: Some(Revision {
+                size: Some(contents.len() as i64),
+                samples_sha256_digests: digests,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn files_are_checked_by_size_then_by_digest() {
+        let root = std::env::temp_dir().join(format!("proton-drive-verify-{}", std::process::id()));
+        fs::create_dir_all(root.join("docs")).unwrap();
+        fs::write(root.join("docs").join("same.txt"), "hello").unwrap();
+        fs::write(root.join("docs").join("corrupt.txt"), "hellO").unwrap();
+        fs::write(root.join("short.txt"), "hell").unwrap();
+        fs::write(root.join("unhashed.txt"), "hellO").unwrap();
+
+        let subtree = Subtree {
+            folders: vec!["docs".to_string()],
+            files: vec![
+                ("docs/same.txt".to_string(), remote(b"hello", true)),
+                ("docs/corrupt.txt".to_string(), remote(b"hello", true)),
+                ("short.txt".to_string(), remote(b"hello", true)),
+                ("unhashed.txt".to_string(), remote(b"hello", false)),
+                ("missing.txt".to_string(), remote(b"hello", true)),
+            ],
+            moves: Vec::new(),
+        };
+
+        let report = run(subtree, &root, &AtomicBool::new(false), |_| {}).unwrap();
+        let mut mismatches: Vec<(&str, Status)> = report
+            .mismatches
+            .iter()
+            .map(|mismatch| (mismatch.relative.as_str(), mismatch.status))
+            .collect();
+        mismatches.sort_by_key(|(relative, _)| *relative);
+        assert_eq!(
+            mismatches,
+            [
+                ("docs/corrupt.txt", Status::HashMismatch),
+                ("missing.txt", Status::Missing),
+                ("short.txt", Status::SizeMismatch),
+            ]
+        );
+        assert_eq!((report.ok, report.unhashed), (2, 1));
+        assert!(!report.interrupted);
+
+        let stopped = run(
+            Subtree { files: vec![("docs/same.txt".to_string(), remote(b"hello", true))], ..Default::default() },
+            &root,
+            &AtomicBool::new(true),
+            |_| {},
+        )
+        .unwrap();
+        assert!(stopped.interrupted);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,706 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+use chrono::Utc;
+use glob::Pattern;
+use log::{debug, info, warn};
+use notify::RecursiveMode;
+use notify_debouncer_mini::{new_debouncer, DebounceEventResult};
+use proton_sdk_rs::nodes::{NodeIdentityExt, NodeTypeExt, RemotePath};
+use proton_sdk_rs::NodeIdentity;
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use r2d2_sqlite::rusqlite::{params, OptionalExtension};
+use tokio::sync::mpsc;
+
+use crate::commands::Context;
+use crate::dry_run::{Action, Direction, Entry};
+use crate::index;
+use crate::remote::DriveOps;
+use crate::shutdown;
+
+/// Names that are never uploaded, editor and OS leftovers
+pub const DEFAULT_IGNORES: &[&str] = &[".DS_Store", "Thumbs.db", "desktop.ini", "*.swp", "*~", ".~lock.*"];
+
+/// What `watch` keeps in sync
+#[derive(Debug, Clone)]
+pub struct WatchOptions {
+    pub local_root: PathBuf,
+    pub remote: RemotePath,
+    /// Trashes remote files deleted locally
+    pub delete_remote: bool,
+    /// Matched against the relative path and each of its names
+    pub ignore: Vec<Pattern>,
+    /// How long events of a file are gathered before it is synced
+    pub debounce: Duration,
+    /// Plans the changes without making them
+    pub dry_run: bool,
+}
+
+impl WatchOptions {
+    fn is_ignored(&self, relative: &str) -> bool {
+        self.ignore.iter().any(|pattern| {
+            pattern.matches(relative) || relative.split('/').any(|name| pattern.matches(name))
+        })
+    }
+}
+
+/// Size and modification time of a local file, as the journal records them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FileState {
+    size: i64,
+    /// Seconds since the epoch
+    modified_at: i64,
+}
+
+impl FileState {
+    fn of(metadata: &fs::Metadata) -> Self {
+        let modified_at = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |since| since.as_secs() as i64);
+        Self {
+            size: metadata.len() as i64,
+            modified_at,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Change {
+    Upload,
+    Delete,
+}
+
+/// Path of `path` under `root` with `/` separators, `None` for the root itself and paths outside it
+fn relative_path(root: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(root).ok()?;
+    let names: Option<Vec<&str>> = relative
+        .components()
+        .map(|component| match component {
+            Component::Normal(name) => name.to_str(),
+            _ => None,
+        })
+        .collect();
+    names.filter(|names| !names.is_empty()).map(|names| names.join("/"))
+}
+
+/// Keeps a remote folder in step with a local one
+///
+/// Working out the changes only takes the index, the client is needed to sync them.
+struct Syncer<'a> {
+    pool: &'a Pool<SqliteConnectionManager>,
+    options: WatchOptions,
+    /// Journal key of the local root
+    root_key: String,
+    /// Remote folders resolved so far, by relative path
+    folders: HashMap<String, NodeIdentity>,
+}
+
+impl<'a> Syncer<'a> {
+    fn new(pool: &'a Pool<SqliteConnectionManager>, options: WatchOptions) -> Self {
+        Self {
+            pool,
+            root_key: options.local_root.to_string_lossy().into_owned(),
+            options,
+            folders: HashMap::new(),
+        }
+    }
+
+    fn journal_entry(&self, relative: &str) -> anyhow::Result<Option<FileState>> {
+        let conn = self.pool.get()?;
+        Ok(conn
+            .query_row(
+                "SELECT size, modified_at FROM watch_journal WHERE local_root = ?1 AND relative_path = ?2",
+                params![self.root_key, relative],
+                |row| Ok(FileState { size: row.get(0)?, modified_at: row.get(1)? }),
+            )
+            .optional()?)
+    }
+
+    /// Journal entries at `relative` or under it, every entry for `None`
+    fn journal_paths(&self, relative: Option<&str>) -> anyhow::Result<Vec<String>> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT relative_path FROM watch_journal WHERE local_root = ?1
+                AND (?2 IS NULL OR relative_path = ?2 OR substr(relative_path, 1, length(?2) + 1) = ?2 || '/')",
+        )?;
+        let paths = stmt
+            .query_map(params![self.root_key, relative], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+        Ok(paths)
+    }
+
+    fn record(&self, relative: &str, state: FileState) -> anyhow::Result<()> {
+        self.pool.get()?.execute(
+            "INSERT INTO watch_journal (local_root, relative_path, size, modified_at, synced_at)
+                VALUES (?1, ?2, ?3, ?4, ?5)
+            ON CONFLICT(local_root, relative_path) DO UPDATE SET
+                size = excluded.size, modified_at = excluded.modified_at, synced_at = excluded.synced_at",
+            params![self.root_key, relative, state.size, state.modified_at, Utc::now().timestamp()],
+        )?;
+        Ok(())
+    }
+
+    /// Moves the journal entries at `from` or under it to `to`
+    fn move_entries(&self, from: &str, to: &str) -> anyhow::Result<()> {
+        self.pool.get()?.execute(
+            "UPDATE OR REPLACE watch_journal SET relative_path = ?3 || substr(relative_path, length(?2) + 1)
+            WHERE local_root = ?1
+                AND (relative_path = ?2 OR substr(relative_path, 1, length(?2) + 1) = ?2 || '/')",
+            params![self.root_key, from, to],
+        )?;
+        Ok(())
+    }
+
+    fn forget(&self, relative: &str) -> anyhow::Result<()> {
+        self.pool.get()?.execute(
+            "DELETE FROM watch_journal WHERE local_root = ?1 AND relative_path = ?2",
+            params![self.root_key, relative],
+        )?;
+        Ok(())
+    }
+
+    /// Size and revision time of the remote copy, as last indexed
+    fn remote_state(&self, relative: &str) -> anyhow::Result<Option<FileState>> {
+        let index_path = self
+            .options
+            .remote
+            .segments()
+            .iter()
+            .map(String::as_str)
+            .chain(relative.split('/'))
+            .collect::<Vec<_>>()
+            .join("/");
+
+        let conn = self.pool.get()?;
+        let state: Option<(Option<i64>, Option<i64>)> = conn
+            .query_row(
+                "SELECT size, modified_at FROM files WHERE full_path = ?1 AND deleted_at IS NULL",
+                params![index_path],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        Ok(match state {
+            Some((Some(size), Some(modified_at))) => Some(FileState { size, modified_at }),
+            _ => None,
+        })
+    }
+
+    /// Checks if a local file differs from what was last synced
+    ///
+    /// A file never synced by `watch` but already on the remote with the same
+    /// size and a newer revision is journaled instead of uploaded again, except
+    /// in a dry run.
+    fn needs_upload(&self, relative: &str, state: FileState) -> anyhow::Result<bool> {
+        if self.journal_entry(relative)? == Some(state) {
+            return Ok(false);
+        }
+        if let Some(remote) = self.remote_state(relative)?
+            && remote.size == state.size
+            && remote.modified_at >= state.modified_at
+        {
+            if !self.options.dry_run {
+                self.record(relative, state)?;
+            }
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    /// Lists the files under a local folder that aren't ignored
+    fn scan(&self, dir: &Path, files: &mut Vec<(String, FileState)>) -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            let Some(relative) = relative_path(&self.options.local_root, &path) else {
+                continue;
+            };
+            if self.options.is_ignored(&relative) {
+                continue;
+            }
+
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                self.scan(&path, files)?;
+            } else if file_type.is_file() {
+                files.push((relative, FileState::of(&entry.metadata()?)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Renames the local copies of remote files and folders moved since they were synced
+    ///
+    /// Only files still as they were synced are renamed, along with folders
+    /// holding synced files, so the next scan finds nothing to upload. A
+    /// local copy already moved away isn't in the journal anymore, which keeps
+    /// a move from being replayed twice. Returns the moves followed, or the
+    /// ones that would be in a dry run.
+    fn follow_moves(&self) -> anyhow::Result<Vec<Entry>> {
+        let mut followed = Vec::new();
+        let remote = self.options.remote.segments().join("/");
+        for moved in index::moves_within(self.pool, &remote)?.into_iter().rev() {
+            if self.options.is_ignored(&moved.from) || self.options.is_ignored(&moved.to) {
+                continue;
+            }
+            let from = self.options.local_root.join(&moved.from);
+            let to = self.options.local_root.join(&moved.to);
+            if to.exists() {
+                continue;
+            }
+
+            let synced = match fs::symlink_metadata(&from) {
+                Ok(metadata) if moved.is_folder && metadata.is_dir() => {
+                    !self.journal_paths(Some(&moved.from))?.is_empty()
+                }
+                Ok(metadata) if !moved.is_folder && metadata.is_file() => {
+                    self.journal_entry(&moved.from)? == Some(FileState::of(&metadata))
+                }
+                _ => false,
+            };
+            if !synced {
+                continue;
+            }
+
+            if !self.options.dry_run {
+                if let Some(parent) = to.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::rename(&from, &to)?;
+                self.move_entries(&moved.from, &moved.to)?;
+                println!("Moved {} to {}", moved.from, moved.to);
+            }
+            followed.push(Entry {
+                action: Action::Move,
+                path: to,
+                direction: Direction::Down,
+                bytes: None,
+                reason: format!("moved remotely, the local copy is at {}", from.display()),
+            });
+        }
+        Ok(followed)
+    }
+
+    /// Finds the changes made while `watch` wasn't running, after following the remote moves
+    ///
+    /// Returns the moves followed, see [`Syncer::follow_moves`].
+    fn reconcile(&self, pending: &mut BTreeMap<String, Change>) -> anyhow::Result<Vec<Entry>> {
+        let followed = self.follow_moves()?;
+
+        let mut files = Vec::new();
+        self.scan(&self.options.local_root, &mut files)?;
+
+        let present: HashSet<&str> = files.iter().map(|(relative, _)| relative.as_str()).collect();
+        for relative in self.journal_paths(None)? {
+            if !present.contains(relative.as_str()) {
+                pending.insert(relative, Change::Delete);
+            }
+        }
+
+        for (relative, state) in &files {
+            if self.needs_upload(relative, *state)? {
+                pending.insert(relative.clone(), Change::Upload);
+            }
+        }
+        Ok(followed)
+    }
+
+    /// What [`Syncer::flush`] would do about a pending change
+    fn entry(&self, relative: &str, change: Change) -> anyhow::Result<Entry> {
+        let path = self.options.local_root.join(relative);
+        Ok(match change {
+            Change::Upload => {
+                let bytes = fs::metadata(&path)?.len();
+                let reason = match self.journal_entry(relative)? {
+                    Some(_) => "changed since the last sync",
+                    None => "not synced yet",
+                };
+                Entry {
+                    action: Action::Upload,
+                    path,
+                    direction: Direction::Up,
+                    bytes: Some(bytes),
+                    reason: reason.to_string(),
+                }
+            }
+            Change::Delete if self.options.delete_remote => Entry {
+                action: Action::Delete,
+                path,
+                direction: Direction::Up,
+                bytes: None,
+                reason: "deleted locally, the remote copy is trashed".to_string(),
+            },
+            Change::Delete => Entry {
+                action: Action::Forget,
+                path,
+                direction: Direction::Up,
+                bytes: None,
+                reason: "deleted locally, the remote copy is kept".to_string(),
+            },
+        })
+    }
+
+    /// Maps a changed local path to the changes to sync
+    fn changes(&self, path: &Path) -> anyhow::Result<Vec<(String, Change)>> {
+        let Some(relative) = relative_path(&self.options.local_root, path) else {
+            return Ok(Vec::new());
+        };
+        if self.options.is_ignored(&relative) {
+            return Ok(Vec::new());
+        }
+
+        let metadata = match fs::symlink_metadata(path) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let deleted = self.journal_paths(Some(&relative))?;
+                return Ok(deleted.into_iter().map(|relative| (relative, Change::Delete)).collect());
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut files = Vec::new();
+        if metadata.is_dir() {
+            self.scan(path, &mut files)?;
+        } else if metadata.is_file() {
+            files.push((relative, FileState::of(&metadata)));
+        }
+
+        let mut changes = Vec::new();
+        for (relative, state) in files {
+            if self.needs_upload(&relative, state)? {
+                changes.push((relative, Change::Upload));
+            }
+        }
+        Ok(changes)
+    }
+
+    /// Resolves the remote folder a relative folder path maps to
+    async fn folder(&mut self, context: &Context<impl DriveOps>, relative: &str) -> anyhow::Result<NodeIdentity> {
+        if let Some(identity) = self.folders.get(relative) {
+            return Ok(identity.clone());
+        }
+
+        let mut remote = self.options.remote.clone();
+        for name in relative.split('/').filter(|name| !name.is_empty()) {
+            remote = remote.join(name);
+        }
+        let identity = context.folder(&remote).await?;
+        self.folders.insert(relative.to_string(), identity.clone());
+        Ok(identity)
+    }
+
+    async fn upload(&mut self, context: &Context<impl DriveOps>, relative: &str) -> anyhow::Result<()> {
+        let local = self.options.local_root.join(relative);
+        let metadata = match fs::metadata(&local) {
+            Ok(metadata) if metadata.is_file() => metadata,
+            // gone or replaced since the event, a later event covers it
+            _ => return Ok(()),
+        };
+        let state = FileState::of(&metadata);
+        if !self.needs_upload(relative, state)? {
+            return Ok(());
+        }
+
+        let parent = relative.rsplit_once('/').map_or("", |(parent, _)| parent);
+        let parent = self.folder(context, parent).await?;
+        context
+            .upload_file(&local, &metadata, parent, None::<fn(f32)>)
+            .await?;
+
+        self.record(relative, state)?;
+        println!("Uploaded {}", relative);
+        Ok(())
+    }
+
+    /// Forgets a file deleted locally, after trashing the remote copy when `delete_remote` is set
+    async fn delete(&mut self, context: &Context<impl DriveOps>, relative: &str) -> anyhow::Result<()> {
+        if !self.options.delete_remote {
+            self.forget(relative)?;
+            info!("{} was deleted locally, the remote copy is kept", relative);
+            return Ok(());
+        }
+
+        let (parent, name) = relative.rsplit_once('/').unwrap_or(("", relative));
+        let parent = self.folder(context, parent).await?;
+        let children = context.children(parent.clone()).await?;
+        match children.iter().find(|child| child.as_file().is_some_and(|file| file.name == name)) {
+            Some(file) => {
+                context.client().trash(file.full_identity(&parent)?).await?;
+                println!("Trashed {}", relative);
+            }
+            None => info!("{} was deleted locally and is gone from the remote folder", relative),
+        }
+        self.forget(relative)
+    }
+
+    /// Syncs every pending change, a failed one is retried by the next startup scan
+    async fn flush(&mut self, context: &Context<impl DriveOps>, pending: &mut BTreeMap<String, Change>) {
+        while let Some((relative, change)) = pending.pop_first() {
+            let synced = match change {
+                Change::Upload => self.upload(context, &relative).await,
+                Change::Delete => self.delete(context, &relative).await,
+            };
+            if let Err(e) = synced {
+                warn!("Failed to sync {}: {:#}", relative, e);
+                eprintln!("Failed to sync {}: {:#}", relative, e);
+            }
+        }
+    }
+
+    fn queue_events(&self, result: DebounceEventResult, pending: &mut BTreeMap<String, Change>) {
+        let events = match result {
+            Ok(events) => events,
+            Err(e) => {
+                warn!("Watch error: {}", e);
+                return;
+            }
+        };
+
+        for event in events {
+            match self.changes(&event.path) {
+                Ok(changes) => pending.extend(changes),
+                Err(e) => warn!("Unable to check {}: {:#}", event.path.display(), e),
+            }
+        }
+    }
+}
+
+/// Uploads the changes made under the local folder since the last sync, without watching it
+pub async fn sync_once(
+    context: &Context<impl DriveOps>,
+    pool: &Pool<SqliteConnectionManager>,
+    mut options: WatchOptions,
+) -> anyhow::Result<()> {
+    options.local_root = fs::canonicalize(&options.local_root)?;
+    let mut sync = Syncer::new(pool, options);
+    let mut pending = BTreeMap::new();
+
+    sync.reconcile(&mut pending)?;
+    debug!("{} changes since the last sync", pending.len());
+    sync.flush(context, &mut pending).await;
+    Ok(())
+}
+
+/// Works out what [`sync_once`] would do, through the same checks, without changing anything
+///
+/// The changes found at startup by [`run`] are the same, so this also serves
+/// as the dry run of `watch`.
+pub fn plan(pool: &Pool<SqliteConnectionManager>, mut options: WatchOptions) -> anyhow::Result<Vec<Entry>> {
+    check_options(&options)?;
+    options.local_root = fs::canonicalize(&options.local_root)?;
+    options.dry_run = true;
+    let sync = Syncer::new(pool, options);
+    let mut pending = BTreeMap::new();
+
+    let mut entries = sync.reconcile(&mut pending)?;
+    for (relative, change) in pending {
+        entries.push(sync.entry(&relative, change)?);
+    }
+    Ok(entries)
+}
+
+fn check_options(options: &WatchOptions) -> anyhow::Result<()> {
+    if options.delete_remote {
+        anyhow::bail!("--delete-remote needs remote trash, which the SDK bindings don't expose yet");
+    }
+    Ok(())
+}
+
+/// Uploads the changes made under the local folder until interrupted
+///
+/// Changes made while this wasn't running are found by comparing the local
+/// files against the journal and the index first. On Ctrl-C or SIGTERM, the changes
+/// already seen are synced before returning.
+pub async fn run(
+    context: &Context<impl DriveOps>,
+    pool: &Pool<SqliteConnectionManager>,
+    mut options: WatchOptions,
+) -> anyhow::Result<()> {
+    check_options(&options)?;
+    // events carry canonical paths, which only strip a canonical root
+    options.local_root = fs::canonicalize(&options.local_root)?;
+
+    let (events_tx, mut events_rx) = mpsc::unbounded_channel();
+    let mut debouncer = new_debouncer(options.debounce, move |result: DebounceEventResult| {
+        let _ = events_tx.send(result);
+    })?;
+    // watching starts before the scan so no change falls between the two
+    debouncer.watcher().watch(&options.local_root, RecursiveMode::Recursive)?;
+
+    shutdown::flag("Stopping after the pending changes are synced...");
+
+    let local_root = options.local_root.clone();
+    let remote = options.remote.clone();
+    let mut sync = Syncer::new(pool, options);
+    let mut pending = BTreeMap::new();
+
+    sync.reconcile(&mut pending)?;
+    debug!("{} changes since the last run", pending.len());
+    sync.flush(context, &mut pending).await;
+
+    println!("Watching {} for changes to upload to {}", local_root.display(), remote);
+    loop {
+        tokio::select! {
+            result = events_rx.recv() => match result {
+                Some(result) => sync.queue_events(result, &mut pending),
+                None => break,
+            },
+            _ = shutdown::requested() => break,
+        }
+        sync.flush(context, &mut pending).await;
+    }
+
+    drop(debouncer);
+    while let Ok(result) = events_rx.try_recv() {
+        sync.queue_events(result, &mut pending);
+    }
+    sync.flush(context, &mut pending).await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicBool;
+
+    use super::*;
+    use crate::remote::fake::FakeDrive;
+
+    fn options(ignore: &[&str]) -> WatchOptions {
+        WatchOptions {
+            local_root: PathBuf::from("/backup"),
+            remote: RemotePath::root(),
+            delete_remote: false,
+            ignore: ignore.iter().map(|pattern| Pattern::new(pattern).unwrap()).collect(),
+            debounce: Duration::from_secs(1),
+            dry_run: false,
+        }
+    }
+
+    #[test]
+    fn dry_runs_plan_the_changes_since_the_last_sync_without_journaling() {
+        let dir = std::env::temp_dir().join(format!("proton-drive-watch-plan-{}", std::process::id()));
+        let root = dir.join("local");
+        fs::create_dir_all(root.join("docs")).unwrap();
+        fs::write(root.join("docs").join("synced.txt"), "same").unwrap();
+        fs::write(root.join("docs").join("edited.txt"), "edited").unwrap();
+        fs::write(root.join("new.txt"), "new").unwrap();
+        fs::write(root.join("new.txt.swp"), "ignored").unwrap();
+        let root = fs::canonicalize(&root).unwrap();
+
+        let pool = index::open(&dir.join("index.db"), None).unwrap();
+        let options = WatchOptions { local_root: root.clone(), ..options(&["*.swp"]) };
+        let sync = Syncer::new(&pool, options.clone());
+        let synced = FileState::of(&fs::metadata(root.join("docs").join("synced.txt")).unwrap());
+        sync.record("docs/synced.txt", synced).unwrap();
+        sync.record("docs/edited.txt", FileState { size: 1, modified_at: 0 }).unwrap();
+        sync.record("gone.txt", FileState { size: 1, modified_at: 0 }).unwrap();
+
+        let entries = plan(&pool, options).unwrap();
+        let entries: Vec<_> = entries
+            .iter()
+            .map(|entry| (entry.action, entry.path.strip_prefix(&root).unwrap(), entry.bytes, entry.reason.as_str()))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                (Action::Upload, Path::new("docs/edited.txt"), Some(6), "changed since the last sync"),
+                (Action::Forget, Path::new("gone.txt"), None, "deleted locally, the remote copy is kept"),
+                (Action::Upload, Path::new("new.txt"), Some(3), "not synced yet"),
+            ]
+        );
+        assert_eq!(sync.journal_paths(None).unwrap().len(), 3);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// A local folder synced into `Backup` on an in-memory drive, with its index
+    async fn synced_folder(name: &str) -> (PathBuf, Context<FakeDrive>, Pool<SqliteConnectionManager>, WatchOptions) {
+        let dir = std::env::temp_dir().join(format!("proton-drive-watch-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let root = dir.join("local");
+        fs::create_dir_all(root.join("docs")).unwrap();
+
+        let drive = FakeDrive::new();
+        drive.add_folder("Backup/docs");
+        let context = Context::with_drive(drive).await.unwrap();
+        let pool = index::open(&dir.join("index.db"), None).unwrap();
+        let options = WatchOptions {
+            local_root: root,
+            remote: RemotePath::root().join("Backup"),
+            delete_remote: true,
+            ..options(&[])
+        };
+        (dir, context, pool, options)
+    }
+
+    #[tokio::test]
+    async fn syncs_upload_local_changes_and_trash_local_deletions() {
+        let (dir, context, pool, options) = synced_folder("sync").await;
+        let drive = context.client();
+        fs::write(options.local_root.join("docs").join("a.txt"), "a").unwrap();
+        fs::write(options.local_root.join("b.txt"), "b").unwrap();
+
+        sync_once(&context, &pool, options.clone()).await.unwrap();
+        assert_eq!(drive.files(), ["Backup/b.txt", "Backup/docs/a.txt"]);
+        assert_eq!(drive.content("Backup/docs/a.txt").as_deref(), Some(&b"a"[..]));
+
+        fs::write(options.local_root.join("b.txt"), "edited").unwrap();
+        fs::remove_file(options.local_root.join("docs").join("a.txt")).unwrap();
+        sync_once(&context, &pool, options.clone()).await.unwrap();
+        assert_eq!(drive.files(), ["Backup/b.txt"]);
+        assert_eq!(drive.content("Backup/b.txt").as_deref(), Some(&b"edited"[..]));
+        assert_eq!(drive.trashed(), ["Backup/docs/a.txt"]);
+
+        // nothing changed since, so nothing is uploaded again
+        sync_once(&context, &pool, options).await.unwrap();
+        assert_eq!(drive.revision("Backup/b.txt"), Some(2));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn syncs_follow_remote_moves_and_keep_remote_edits() {
+        let (dir, context, pool, options) = synced_folder("moves").await;
+        let drive = context.client();
+        let shutdown = AtomicBool::new(false);
+        fs::write(options.local_root.join("docs").join("a.txt"), "a").unwrap();
+        sync_once(&context, &pool, options.clone()).await.unwrap();
+        index::index(drive, context.root(), &pool, 1, &shutdown, |_| {}).await.unwrap();
+
+        drive.rename("Backup/docs/a.txt", "Backup/a.txt");
+        drive.add_file("Backup/remote.txt", b"from elsewhere");
+        index::refresh(drive, context.root(), &pool, 1, &shutdown, |_| {}).await.unwrap();
+        sync_once(&context, &pool, options.clone()).await.unwrap();
+
+        assert!(options.local_root.join("a.txt").is_file());
+        assert!(!options.local_root.join("docs").join("a.txt").exists());
+        assert_eq!(drive.files(), ["Backup/a.txt", "Backup/remote.txt"]);
+        assert_eq!(drive.revision("Backup/a.txt"), Some(1));
+        assert!(drive.trashed().is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn relative_paths_use_slashes_and_stay_under_the_root() {
+        let root = Path::new("/backup");
+        assert_eq!(relative_path(root, &root.join("a").join("b.txt")).as_deref(), Some("a/b.txt"));
+        assert_eq!(relative_path(root, root), None);
+        assert_eq!(relative_path(root, Path::new("/elsewhere/b.txt")), None);
+    }
+
+    #[test]
+    fn ignore_patterns_match_paths_and_names() {
+        let options = options(&["*.swp", "node_modules", "build/*.o"]);
+        assert!(options.is_ignored("notes.txt.swp"));
+        assert!(options.is_ignored("app/node_modules/left-pad/index.js"));
+        assert!(options.is_ignored("build/main.o"));
+        assert!(!options.is_ignored("src/main.o"));
+        assert!(!options.is_ignored("notes.txt"));
+    }
+}
@@ -0,0 +1,71 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use proton_sdk_rs::{
+    node_type, nodes::NodeTypeExt, FileNode, FolderNode, LinkId, NodeIdentity, NodeType, Revision,
+};
+use std::hint::black_box;
+
+/// A listing of 50k nodes, every tenth one a folder
+fn synthetic_listing() -> Vec<NodeType> {
+    (0..50_000)
+        .map(|i| {
+            let node_identity = Some(NodeIdentity {
+                node_id: Some(LinkId { value: format!("node-{}", i) }),
+                ..Default::default()
+            });
+            let node_type = if i % 10 == 0 {
+                node_type::NodeType::FolderNode(FolderNode {
+                    node_identity,
+                    name: format!("folder {}", i),
+                    ..Default::default()
+                })
+            } else {
+                node_type::NodeType::FileNode(FileNode {
+                    node_identity,
+                    name: format!("file {}.jpg", i),
+                    active_revision: Some(Revision {
+                        size: Some(4096),
+                        samples_sha256_digests: vec![vec![0u8; 32].into(); 3],
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                })
+            };
+            NodeType { node_type: Some(node_type) }
+        })
+        .collect()
+}
+
+#[allow(deprecated)]
+fn count_with_utils(nodes: &[NodeType]) -> (usize, usize) {
+    let (mut files, mut folders) = (0, 0);
+    for node in nodes {
+        let (is_folder, _) = proton_sdk_rs::utils::node_is_folder(node.clone());
+        let (is_file, _) = proton_sdk_rs::utils::node_is_file(node.clone());
+        folders += is_folder as usize;
+        files += is_file as usize;
+    }
+    (files, folders)
+}
+
+fn count_with_accessors(nodes: &[NodeType]) -> (usize, usize) {
+    let (mut files, mut folders) = (0, 0);
+    for node in nodes {
+        folders += node.as_folder().is_some() as usize;
+        files += node.as_file().is_some() as usize;
+    }
+    (files, folders)
+}
+
+fn node_accessors(c: &mut Criterion) {
+    let nodes = synthetic_listing();
+
+    let mut group = c.benchmark_group("classify_50k_nodes");
+    group.bench_function("utils_by_value", |b| b.iter(|| count_with_utils(black_box(&nodes))));
+    group.bench_function("borrowing_accessors", |b| {
+        b.iter(|| count_with_accessors(black_box(&nodes)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, node_accessors);
+criterion_main!(benches);
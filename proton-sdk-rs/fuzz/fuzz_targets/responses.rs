@@ -0,0 +1,17 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use proton_sdk_rs::errors::SdkErrorDetails;
+use proton_sdk_rs::responses;
+use proton_sdk_sys::data::{parse_progress, ByteArray};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(handle) = responses::session_handle(data) {
+        assert!(!handle.is_null());
+    }
+    let _ = SdkErrorDetails::decode(data);
+    let _ = responses::created_handle(data);
+    if let Ok(update) = parse_progress(ByteArray::from_slice(data)) {
+        assert!((0.0..=1.0).contains(&update.fraction()));
+    }
+});
@@ -1,16 +1,28 @@
-use proton_sdk_sys::cancellation::{raw, CancellationTokenHandle};
+use std::sync::Arc;
+
+use proton_sdk_sys::{
+    api::{LibloadingApi, SdkApi},
+    cancellation::CancellationTokenHandle,
+};
 
 // Todo
 pub struct CancellationToken {
     handle: CancellationTokenHandle,
+    api: Arc<dyn SdkApi>,
 }
 
 impl CancellationToken {
     /// Creates a new cancellation token source
     pub fn new() -> anyhow::Result<Self> {
-        let handle = raw::create()?;
+        Self::with_api(LibloadingApi::shared())
+    }
+
+    /// Creates a new cancellation token source through `api`
+    pub fn with_api(api: Arc<dyn SdkApi>) -> anyhow::Result<Self> {
+        let handle = api.cancellation_token_create()?;
         Ok(Self {
             handle: CancellationTokenHandle(handle),
+            api,
         })
     }
 
@@ -21,13 +33,14 @@ impl CancellationToken {
 
     /// Cancels all operations associated with this token
     pub fn cancel(&self) -> anyhow::Result<()> {
-        raw::cancel(self.handle.raw())
+        self.api.cancellation_token_cancel(self.handle.raw())
     }
 
     /// Free the cancellation token source
-    pub fn free(self) -> anyhow::Result<()> {
-        let result = raw::free(self.handle.raw());
-        std::mem::forget(self);
+    pub fn free(mut self) -> anyhow::Result<()> {
+        let result = self.api.cancellation_token_free(self.handle.raw());
+        // already freed, Drop leaves it alone
+        self.handle = CancellationTokenHandle::null();
         result
     }
 }
@@ -35,7 +48,7 @@ impl CancellationToken {
 impl Drop for CancellationToken {
     fn drop(&mut self) {
         if !self.handle.is_null() {
-            let _ = raw::free(self.handle.raw());
+            let _ = self.api.cancellation_token_free(self.handle.raw());
         }
     }
 }
@@ -49,8 +62,9 @@ impl Default for CancellationToken {
 impl Clone for CancellationToken {
     fn clone(&self) -> Self {
         // not ideal but safe
-        Self::new().unwrap_or_else(|_| Self {
+        Self::with_api(self.api.clone()).unwrap_or_else(|_| Self {
             handle: CancellationTokenHandle::null(),
+            api: self.api.clone(),
         })
     }
 }
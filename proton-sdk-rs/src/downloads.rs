@@ -1,12 +1,20 @@
-use std::{ffi::c_void, fmt};
+mod request;
+
+use std::{fmt, sync::Arc};
 
 use log::{debug, warn};
 use proton_sdk_sys::{
-    cancellation::CancellationTokenHandle, data::{AsyncCallback, AsyncCallbackWithProgress, ByteArray}, downloads::{self, raw, DownloaderHandle}, drive::DriveClientHandle, prost::Message, protobufs::{FileDownloadRequest, IntResponse, ToByteArray}
+    api::{LibloadingApi, SdkApi}, data::{AsyncCallback, AsyncCallbackBuilder, AsyncCallbackWithProgress, ByteArray, Callback, CallbackGuard, CallbackState}, downloads::DownloaderHandle, drive::DriveClientHandle, protobufs::{drive::FileDownloadRequest, validation::Validate, ToByteArray}
+};
+use crate::{
+    cancellation::CancellationToken,
+    drive::DriveClient,
+    errors::SdkErrorDetails,
+    progress::{self, TransferProgress},
+    responses,
 };
-use proton_sdk_sys::protobufs::ProgressUpdate;
-use crate::{cancellation::{self, CancellationToken}, drive::DriveClient};
-use proton_sdk_sys::protobufs::FromByteArray;
+
+pub use self::request::FileDownloadRequestBuilder;
 
 #[derive(Debug, thiserror::Error)]
 pub enum DownloadError {
@@ -17,10 +25,10 @@ pub enum DownloadError {
     ProtobufError(#[from] proton_sdk_sys::protobufs::ProtoError),
 
     #[error("Downloader creation failed: {0}")]
-    CreationFailed(String),
+    CreationFailed(SdkErrorDetails),
 
     #[error("Download operation failed: {0}")]
-    DownloadFailed(String),
+    DownloadFailed(SdkErrorDetails),
 
     #[error("Downloader creation timed out")]
     CreationTimeout,
@@ -33,109 +41,65 @@ pub enum DownloadError {
 
     #[error("Invalid Drive client handle")]
     InvalidClient,
+
+    #[error("Invalid request: {0}")]
+    InvalidRequest(#[from] proton_sdk_sys::protobufs::validation::ValidationError),
 }
 
 pub struct Downloader {
     handle: DownloaderHandle,
     _client: DriveClientHandle,
+    api: Arc<dyn SdkApi>,
 }
 
-struct CombinedDownloadState<F>
-where
-    F: Fn(f32) + Send + 'static,
-{
-    result_sender: tokio::sync::oneshot::Sender<Result<Vec<u8>, DownloadError>>,
-    progress_callback: Option<F>,
-}
+/// State of a download's callbacks, the progress callback besides the result
+type DownloadState<F> = CallbackState<Result<Vec<u8>, DownloadError>, Option<F>>;
 
 impl Downloader {
-    pub async fn new(
-        client: DriveClientHandle,
-        cancellation_token: CancellationTokenHandle,
-    ) -> Result<Self, DownloadError> {
+    pub async fn new(client: DriveClientHandle) -> Result<Self, DownloadError> {
+        Self::with_api(LibloadingApi::shared(), client).await
+    }
+
+    /// Creates a downloader for `client`, making the SDK calls through `api`
+    ///
+    /// The creation has a token of its own, cancelled when it times out or its future
+    /// is dropped unfinished.
+    pub async fn with_api(api: Arc<dyn SdkApi>, client: DriveClientHandle) -> Result<Self, DownloadError> {
         if client.is_null() {
             return Err(DownloadError::InvalidClient);
         }
 
-        let (tx, rx) = tokio::sync::oneshot::channel::<Result<DownloaderHandle, DownloadError>>();
-        let tx = Box::new(tx);
-        let tx_ptr = Box::into_raw(tx)
-            as *mut tokio::sync::oneshot::Sender<Result<DownloaderHandle, DownloadError>>;
-
-        extern "C" fn create_success_callback(state: *const c_void, response: ByteArray) {
-            if !state.is_null() {
-                unsafe {
-                    let tx_ptr = state
-                        as *mut tokio::sync::oneshot::Sender<
-                            Result<DownloaderHandle, DownloadError>,
-                        >;
-                    let tx = Box::from_raw(tx_ptr);
-
-                    let response = response.as_slice().to_vec();
-                    let handle = match IntResponse::decode(&*response) {
-                        Ok(value) => {
-                            DownloaderHandle::from(value.value as isize)
-                        },
-                        Err(e) => DownloaderHandle::null()
-                    };
-
-                    debug!("Downloader created with handle: {:?}", handle);
-                    let _ = tx.send(Ok(handle));
-                }
-            }
-        }
-
-        extern "C" fn create_failure_callback(state: *const c_void, error_data: ByteArray) {
-            if !state.is_null() {
-                unsafe {
-                    let tx_ptr = state
-                        as *mut tokio::sync::oneshot::Sender<
-                            Result<DownloaderHandle, DownloadError>,
-                        >;
-                    let tx = Box::from_raw(tx_ptr);
-
-                    let error_slice = error_data.as_slice();
-                    let error_msg = if error_slice.is_empty() {
-                        "Unknown downloader creation error".to_string()
-                    } else {
-                        String::from_utf8_lossy(error_slice).to_string()
-                    };
-
-                    log::error!("Downloader creation failed: {}", error_msg);
-                    let _ = tx.send(Err(DownloadError::CreationFailed(error_msg)));
-                }
-            }
-        }
-
-        let async_callback = AsyncCallback::new(
-            tx_ptr as *const c_void,
-            Some(create_success_callback),
-            Some(create_failure_callback),
-            cancellation_token.raw(),
-        );
+        // dropped after the guard, which cancels it
+        let cancellation_token = CancellationToken::with_api(api.clone())?;
+        let (async_callback, guard) = AsyncCallbackBuilder::new()
+            .on_success(|response: ByteArray| {
+                let handle = DownloaderHandle::from(responses::created_handle(&response.view())?);
+                debug!("Downloader created with handle: {:?}", handle);
+                Ok(handle)
+            })
+            .on_failure(|error_data: ByteArray| {
+                let details = SdkErrorDetails::decode(&error_data.view());
+                log::error!("Downloader creation failed: {}", details);
+                Err(DownloadError::CreationFailed(details))
+            })
+            .cancellation_token(cancellation_token.handle())
+            .cancel_on_drop(api.clone())
+            .build();
 
         // Empty request as per API specification
         let empty_request = ByteArray::empty();
 
-        let result = downloads::raw::downloader_create(client, empty_request, async_callback)
-            .map_err(|e| DownloadError::SdkError(e))?;
+        let result = guard.call(|| api.downloader_create(client, empty_request, async_callback))
+            .map_err(DownloadError::SdkError)?;
 
         if result != 0 {
-            // Clean up the leaked box if FFI failed immediately
-            unsafe {
-                let _ = Box::from_raw(tx_ptr);
-            }
-            return Err(DownloadError::CreationFailed(format!(
-                "FFI call failed with code: {}",
-                result
-            )));
+            return Err(DownloadError::CreationFailed(SdkErrorDetails::from_code(result)));
         }
 
-        // Wait for async completion with timeout
+        // Wait for async completion with timeout, dropping the guard then cancels the creation
         let downloader_handle =
-            match tokio::time::timeout(std::time::Duration::from_secs(30), rx).await {
-                Ok(Ok(handle)) => handle,
-                Ok(Err(e)) => return Err(DownloadError::CreationFailed(e.to_string())),
+            match tokio::time::timeout(std::time::Duration::from_secs(30), guard.completion()).await {
+                Ok(handle) => handle,
                 Err(_) => return Err(DownloadError::CreationTimeout),
             }?;
 
@@ -148,6 +112,7 @@ impl Downloader {
         Ok(Self {
             handle: downloader_handle,
             _client: client,
+            api,
         })
     }
 
@@ -163,55 +128,62 @@ impl Downloader {
 
     /// Downloads a file with progress tracking
     ///
+    /// The download has a token of its own, cancelled when it times out or its future
+    /// is dropped unfinished.
+    ///
     /// # Arguments
     /// * `request` - The file download request specifying what to download
     /// * `progress_callback` - Optional callback for progress updates
-    /// * `cancellation_token` - Token to cancel the download if needed
     ///
     /// # Returns
     /// The downloaded file data as bytes, or an error if download failed
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            skip_all,
+            fields(
+                operation_id = crate::tracing_support::operation_id(request.operation_id.as_ref()),
+                node_id = crate::tracing_support::node_id(request.file_identity.as_ref()),
+                bytes = tracing::field::Empty,
+            )
+        )
+    )]
     pub async fn download_file<F>(
         &self,
         request: FileDownloadRequest,
         progress_callback: Option<F>,
-        cancellation_token: &CancellationToken,
     ) -> Result<Vec<u8>, DownloadError>
     where
-        F: Fn(f32) + Send + 'static,
+        F: Fn(TransferProgress) + Send + Sync + 'static,
     {
         if self.handle.is_null() {
             return Err(DownloadError::NullHandle);
         }
+        request.validate()?;
 
         let proto_buf = request
             .to_proto_buffer()
-            .map_err(|e| DownloadError::ProtobufError(e))?;
-
-        let (tx, rx) = tokio::sync::oneshot::channel();
+            .map_err(DownloadError::ProtobufError)?;
 
         let has_progress_callback = progress_callback.is_some();
-
-        let combined_state = Box::leak(Box::new(CombinedDownloadState {
-            result_sender: tx,
-            progress_callback,
-        })) as *mut CombinedDownloadState<F>;
+        // dropped after the guard, which cancels it
+        let cancellation_token = CancellationToken::with_api(self.api.clone())?;
+        let guard = CallbackGuard::<Result<Vec<u8>, DownloadError>, _>::new(progress_callback)
+            .cancel_on_drop(self.api.clone(), cancellation_token.handle());
 
         extern "C" fn download_success_callback<F>(
             state: *const std::ffi::c_void,
             response: ByteArray,
         ) where
-            F: Fn(f32) + Send + 'static,
+            F: Fn(TransferProgress) + Send + Sync + 'static,
         {
-            if !state.is_null() {
-                unsafe {
-                    let state_ptr = state as *mut CombinedDownloadState<F>;
-                    let download_state = Box::from_raw(state_ptr);
-
-                    let file_data = response.as_slice().to_vec();
+            unsafe {
+                DownloadState::<F>::finish(state, |state| {
+                    let file_data = response.to_vec();
                     log::debug!("File downloaded successfully: {} bytes", file_data.len());
 
-                    let _ = download_state.result_sender.send(Ok(file_data));
-                }
+                    state.send(Ok(file_data));
+                })
             }
         }
 
@@ -219,61 +191,31 @@ impl Downloader {
             state: *const std::ffi::c_void,
             error_data: ByteArray,
         ) where
-            F: Fn(f32) + Send + 'static,
-        {
-            if !state.is_null() {
-                unsafe {
-                    let state_ptr = state as *mut CombinedDownloadState<F>;
-                    let download_state = Box::from_raw(state_ptr);
-
-                    let error_slice = error_data.as_slice();
-                    let error_msg = if error_slice.is_empty() {
-                        "Unknown download error".to_string()
-                    } else {
-                        String::from_utf8_lossy(error_slice).to_string()
-                    };
-
-                    log::error!("File download failed: {}", error_msg);
-                    let _ = download_state
-                        .result_sender
-                        .send(Err(DownloadError::DownloadFailed(error_msg)));
-                }
-            }
-        }
-
-        extern "C" fn progress_callback_fn<F>(
-            state: *const std::ffi::c_void,
-            progress_data: ByteArray,
-        ) where
-            F: Fn(f32) + Send + 'static,
+            F: Fn(TransferProgress) + Send + Sync + 'static,
         {
-            if !state.is_null() {
-                unsafe {
-                    let state_ptr = state as *const CombinedDownloadState<F>;
-                    let download_state = &*state_ptr;
-                    let bytes = progress_data.as_slice();
-                    let progress = ProgressUpdate::from_bytes(bytes).expect("No progress update data");
-                    if let Some(ref callback) = download_state.progress_callback {
-                        callback((progress.bytes_completed as f32 / progress.bytes_in_total as f32));
-                    }
-                }
+            unsafe {
+                DownloadState::<F>::finish(state, |state| {
+                    let details = SdkErrorDetails::decode(&error_data.view());
+                    log::error!("File download failed: {}", details);
+                    state.send(Err(DownloadError::DownloadFailed(details)));
+                })
             }
         }
 
         let main_async_callback = AsyncCallback::new(
-            combined_state as *const std::ffi::c_void,
+            guard.state(),
             Some(download_success_callback::<F>),
             Some(download_failure_callback::<F>),
             cancellation_token.handle().raw(),
         );
 
         let progress_cb = if has_progress_callback {
-            proton_sdk_sys::data::Callback::new(
-                combined_state as *const std::ffi::c_void,
-                Some(progress_callback_fn::<F>),
+            Callback::checked(
+                guard.thread_safe_state(),
+                progress::report_progress::<Result<Vec<u8>, DownloadError>, F>,
             )
         } else {
-            proton_sdk_sys::data::Callback::new(std::ptr::null(), None)
+            Callback::empty()
         };
 
         let async_callback_with_progress = AsyncCallbackWithProgress {
@@ -281,49 +223,43 @@ impl Downloader {
             progress_callback: progress_cb,
         };
 
-        let result = raw::downloader_download_file(
-            self.handle,
-            proto_buf.as_byte_array(),
-            async_callback_with_progress,
-        )
-        .map_err(|e| DownloadError::SdkError(e))?;
+        let result = guard
+            .call(|| self.api.downloader_download_file(self.handle, proto_buf.as_byte_array(), async_callback_with_progress))
+            .map_err(DownloadError::SdkError)?;
 
         if result != 0 {
-            // clean up leak
-            unsafe {
-                let _ = Box::from_raw(combined_state);
-            }
-            return Err(DownloadError::DownloadFailed(format!(
-                "FFI call failed with code: {}",
-                result
-            )));
+            return Err(DownloadError::DownloadFailed(SdkErrorDetails::from_code(result)));
         }
+        // present, as the request is valid
+        let operation_id = request.operation_id.as_ref().map_or("", |operation| operation.identifier.as_str());
+        debug!("Download {} started", operation_id);
 
-        // 5 min timeout
-        match tokio::time::timeout(std::time::Duration::from_secs(300), rx).await {
-            Ok(result) => match result {
-                Ok(result) => result,
-                Err(e) => Err(DownloadError::DownloadFailed(e.to_string())),
-            },
-            Err(_) => Err(DownloadError::DownloadTimeout),
+        // 5 min timeout, dropping the guard then cancels the download
+        let result = tokio::time::timeout(std::time::Duration::from_secs(300), guard.completion())
+            .await
+            .unwrap_or(Err(DownloadError::DownloadTimeout));
+        match &result {
+            Ok(data) => debug!("Download {} finished with {} bytes", operation_id, data.len()),
+            Err(e) => warn!("Download {} failed: {}", operation_id, e),
+        }
+
+        #[cfg(feature = "tracing")]
+        if let Ok(data) = &result {
+            tracing::Span::current().record("bytes", data.len());
         }
+
+        result
     }
 
     /// Downloads a file without progress tracking (simpler version)
     ///
     /// # Arguments
     /// * `request` - The file download request
-    /// * `cancellation_token` - Token to cancel the download if needed
     ///
     /// # Returns
     /// The downloaded file data as bytes
-    pub async fn download_file_simple(
-        &self,
-        request: FileDownloadRequest,
-        cancellation_token: &CancellationToken,
-    ) -> Result<Vec<u8>, DownloadError> {
-        self.download_file(request, None::<fn(f32)>, cancellation_token)
-            .await
+    pub async fn download_file_simple(&self, request: FileDownloadRequest) -> Result<Vec<u8>, DownloadError> {
+        self.download_file(request, None::<fn(TransferProgress)>).await
     }
 
     /// Explicitly frees the downloader
@@ -332,7 +268,7 @@ impl Downloader {
     /// so you usually don't need to call this manually.
     pub fn free(self) -> Result<(), DownloadError> {
         if !self.handle.is_null() {
-            raw::downloader_free(self.handle).map_err(|e| DownloadError::SdkError(e))?;
+            self.api.downloader_free(self.handle).map_err(DownloadError::SdkError)?;
             log::debug!("Downloader freed successfully");
         }
         Ok(())
@@ -351,7 +287,7 @@ impl fmt::Debug for Downloader {
 impl Drop for Downloader {
     fn drop(&mut self) {
         if !self.handle.is_null() {
-            if let Err(e) = raw::downloader_free(self.handle) {
+            if let Err(e) = self.api.downloader_free(self.handle) {
                 warn!("Failed to free downloader in Drop: {}", e);
             } else {
                 debug!("Downloader cleaned up automatically");
@@ -362,17 +298,103 @@ impl Drop for Downloader {
 
 pub struct DownloaderBuilder {
     client: DriveClientHandle,
-    token: CancellationTokenHandle
+    api: Arc<dyn SdkApi>,
 }
 
 impl DownloaderBuilder {
     pub fn new(client: &DriveClient) -> Self {
-        Self { client: client.handle(), token: client.session().cancellation_token().handle() }
+        Self {
+            client: client.handle(),
+            api: client.api().clone(),
+        }
     }
 
     pub async fn build(
         self
     ) -> Result<Downloader, DownloadError> {
-        Downloader::new(self.client, self.token).await
+        Downloader::with_api(self.api, self.client).await
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    use proton_sdk_sys::{
+        api::{MockApi, Reply},
+        protobufs::{account::OperationIdentifier, drive::{LinkId, NodeIdentity, ProgressUpdate}},
+    };
+
+    use crate::drive::tests::mock_client;
+
+    fn request() -> FileDownloadRequest {
+        FileDownloadRequest {
+            file_identity: Some(NodeIdentity { node_id: Some(LinkId { value: "beach".to_string() }), ..Default::default() }),
+            target_file_path: "/tmp/beach.jpg".to_string(),
+            operation_id: Some(OperationIdentifier::download()),
+            ..Default::default()
+        }
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn downloads_report_progress_then_the_outcome() {
+        let mock = Arc::new(MockApi::new());
+        let client = mock_client(&mock).await;
+        let downloader = DownloaderBuilder::new(&client).build().await.unwrap();
+        let updates = [
+            ProgressUpdate { bytes_completed: 1, bytes_in_total: 4 },
+            ProgressUpdate { bytes_completed: 2, bytes_in_total: 4 },
+        ];
+        mock.progress("downloader_download_file", &updates)
+            .reply("downloader_download_file", Reply::Success(b"jpeg".to_vec()))
+            .reply("downloader_download_file", Reply::Failure(b"Node not found".to_vec()))
+            .reply("downloader_download_file", Reply::Code(4));
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let progress = {
+            let seen = seen.clone();
+            progress::fractions(move |fraction| seen.lock().unwrap().push(fraction))
+        };
+        assert_eq!(downloader.download_file(request(), Some(progress)).await.unwrap(), b"jpeg");
+        assert_eq!(*seen.lock().unwrap(), [0.25, 0.5]);
+
+        let error = downloader.download_file_simple(request()).await.unwrap_err();
+        assert!(matches!(error, DownloadError::DownloadFailed(details) if details.message() == "Node not found"));
+        let error = downloader.download_file_simple(request()).await.unwrap_err();
+        assert!(matches!(error, DownloadError::DownloadFailed(details) if details.code() == Some(4)));
+    }
+
+    #[tokio::test]
+    async fn abandoned_calls_cancel_a_token_of_their_own() {
+        let mock = Arc::new(MockApi::new());
+        let client = mock_client(&mock).await;
+        let patience = std::time::Duration::from_millis(10);
+        mock.reply("downloader_create", Reply::Pending);
+        assert!(tokio::time::timeout(patience, DownloaderBuilder::new(&client).build()).await.is_err());
+        let downloader = DownloaderBuilder::new(&client).build().await.unwrap();
+        mock.reply("downloader_download_file", Reply::Pending);
+        assert!(tokio::time::timeout(patience, downloader.download_file_simple(request())).await.is_err());
+
+        // cancelled then freed, unlike the session's
+        let session = client.session().cancellation_token().handle().raw();
+        let cancelled: Vec<_> = mock.calls_to("cancellation_token_source_cancel").iter().map(|call| call.handle).collect();
+        let freed: Vec<_> = mock.calls_to("cancellation_token_source_free").iter().map(|call| call.handle).collect();
+        assert_eq!(cancelled.len(), 2);
+        assert!(!cancelled.contains(&session));
+        assert!(cancelled.iter().all(|token| freed.contains(token)));
+    }
+
+    #[tokio::test]
+    async fn creation_failures_and_drop_reach_the_sdk() {
+        let mock = Arc::new(MockApi::new());
+        let client = mock_client(&mock).await;
+        mock.reply("downloader_create", Reply::Failure(b"Quota exceeded".to_vec()));
+        assert!(matches!(DownloaderBuilder::new(&client).build().await, Err(DownloadError::CreationFailed(_))));
+
+        let downloader = DownloaderBuilder::new(&client).build().await.unwrap();
+        let handle = downloader.handle().raw();
+        drop(downloader);
+        let freed: Vec<_> = mock.calls_to("downloader_free").iter().map(|call| call.handle).collect();
+        assert_eq!(freed, [handle]);
+    }
+}
@@ -0,0 +1,109 @@
+use std::path::PathBuf;
+
+use proton_sdk_sys::protobufs::{
+    account::OperationIdentifier,
+    drive::{FileDownloadRequest, FileNode, NodeIdentity, RevisionMetadata},
+};
+
+/// Builds the request downloading a file to a local path
+///
+/// The operation is a new [`OperationIdentifier::download`] unless given one, and
+/// a relative target is made absolute, as the SDK wants it.
+///
+/// ```
+/// # use proton_sdk_rs::{downloads::FileDownloadRequestBuilder, LinkId, NodeIdentity, OperationType};
+/// let file = NodeIdentity { node_id: Some(LinkId { value: "beach".to_string() }), ..Default::default() };
+/// let request = FileDownloadRequestBuilder::new(file, "beach.jpg").build();
+/// assert_eq!(request.operation_id.unwrap().r#type(), OperationType::Download);
+/// ```
+#[derive(Debug, Clone)]
+pub struct FileDownloadRequestBuilder {
+    file_identity: NodeIdentity,
+    target: PathBuf,
+    revision_metadata: Option<RevisionMetadata>,
+    operation_id: Option<OperationIdentifier>,
+}
+
+impl FileDownloadRequestBuilder {
+    pub fn new(file_identity: NodeIdentity, target: impl Into<PathBuf>) -> Self {
+        Self { file_identity, target: target.into(), revision_metadata: None, operation_id: None }
+    }
+
+    /// Downloads the active revision of `file`, whose complete identity is `file_identity`
+    pub fn for_file(file: &FileNode, file_identity: NodeIdentity, target: impl Into<PathBuf>) -> Self {
+        Self { revision_metadata: file.active_revision_metadata(), ..Self::new(file_identity, target) }
+    }
+
+    pub fn with_revision_metadata(self, revision_metadata: RevisionMetadata) -> Self {
+        Self { revision_metadata: Some(revision_metadata), ..self }
+    }
+
+    /// Uses an operation created by the caller, to correlate it with their own logs
+    pub fn with_operation_id(self, operation_id: OperationIdentifier) -> Self {
+        Self { operation_id: Some(operation_id), ..self }
+    }
+
+    pub fn build(self) -> FileDownloadRequest {
+        // a target that can't be made absolute is left for the validation to reject
+        let target = std::path::absolute(&self.target).unwrap_or(self.target);
+        FileDownloadRequest {
+            file_identity: Some(self.file_identity),
+            revision_metadata: self.revision_metadata,
+            target_file_path: target.to_string_lossy().into_owned(),
+            operation_id: Some(self.operation_id.unwrap_or_else(OperationIdentifier::download)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::DateTime;
+    use proton_sdk_sys::protobufs::{
+        account::OperationType,
+        drive::{LinkId, Revision, RevisionId, ShareId, VolumeId},
+        validation::Validate,
+    };
+    use std::path::Path;
+
+    fn identity() -> NodeIdentity {
+        NodeIdentity {
+            node_id: Some(LinkId { value: "beach".to_string() }),
+            share_id: Some(ShareId { value: "share".to_string() }),
+            volume_id: Some(VolumeId { value: "volume".to_string() }),
+        }
+    }
+
+    #[test]
+    fn requests_get_a_download_operation_and_an_absolute_target() {
+        let request = FileDownloadRequestBuilder::new(identity(), "photos/beach.jpg").build();
+
+        assert!(Path::new(&request.target_file_path).is_absolute());
+        assert!(request.target_file_path.ends_with("beach.jpg"));
+        assert_eq!(request.file_identity, Some(identity()));
+        assert_eq!(request.revision_metadata, None);
+        let operation = request.operation_id.as_ref().unwrap();
+        assert_eq!(operation.r#type(), OperationType::Download);
+        assert!(DateTime::parse_from_rfc3339(&operation.timestamp).is_ok());
+        assert!(request.validate().is_ok());
+    }
+
+    #[test]
+    fn given_operations_and_revisions_are_kept() {
+        let operation = OperationIdentifier::new(OperationType::Download);
+        let file = FileNode {
+            active_revision: Some(Revision {
+                revision_id: Some(RevisionId { value: "revision".to_string() }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let request = FileDownloadRequestBuilder::for_file(&file, identity(), "/tmp/beach.jpg")
+            .with_operation_id(operation.clone())
+            .build();
+
+        assert_eq!(request.operation_id, Some(operation));
+        assert_eq!(request.revision_metadata, file.active_revision_metadata());
+        assert_eq!(request.target_file_path, "/tmp/beach.jpg");
+    }
+}
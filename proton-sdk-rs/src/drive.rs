@@ -1,9 +1,9 @@
-use std::{ffi::c_void, fmt, future::Future};
+use std::{ffi::c_void, fmt, future::Future, sync::Arc};
 
 use log::{debug, error, trace, warn};
 use proton_sdk_sys::{
-    cancellation, data::ByteArray, drive::{self, DriveClientHandle}, observability::{self, ObservabilityHandle}, protobufs::{
-        NodeIdentity, NodeKeysRegistrationRequest, NodeType, NodeTypeList, ProtonDriveClientCreateRequest, Share, ShareKeyRegistrationRequest, ToByteArray, VolumeEventType, VolumeMetadata, VolumesResponse
+    api::SdkApi, cancellation, drive::DriveClientHandle, logger::LoggerProviderHandle, observability::{self, ObservabilityHandle}, protobufs::{
+        drive::{node_type, NodeIdentity, NodeKeysRegistrationRequest, NodeType, NodeTypeList, ProtonDriveClientCreateRequest, Share, ShareKeyRegistrationRequest, VolumeEventType, VolumeMetadata, VolumesResponse}, helpers, ProtoBufferPool, ToByteArray, validation::Validate
     }, sessions::SessionHandle
 };
 
@@ -11,11 +11,14 @@ use proton_sdk_sys::prost::Message;
 use r2d2::Pool;
 use r2d2_sqlite::SqliteConnectionManager;
 
-use crate::{cancellation::CancellationToken, observability::ObservabilityService, sessions::Session};
+use crate::{cancellation::CancellationToken, logging::LoggerProvider, nodes::{NodeCache, NodeError, NodeIdentityExt, NodeLink, NodeLinks, DEFAULT_LINK_CAPACITY}, observability::ObservabilityService, sessions::Session, SdkErrorKind};
 
 pub struct DriveClient {
     handle: DriveClientHandle,
+    api: Arc<dyn SdkApi>,
     session: Session,
+    links: NodeLinks,
+    cache: Option<NodeCache>,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -52,6 +55,35 @@ pub enum DriveError {
 
     #[error("Invalid session handle")]
     InvalidSession,
+
+    #[error("Invalid request: {0}")]
+    InvalidRequest(#[from] proton_sdk_sys::protobufs::validation::ValidationError),
+}
+
+impl DriveError {
+    /// Whether the call may succeed when tried again, see [`RetryPolicy`](crate::retry::RetryPolicy)
+    ///
+    /// Failures reported by the native SDK are, unless they name a cause that
+    /// won't go away. Errors of the bindings themselves never are.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            DriveError::SdkError(e)
+            | DriveError::VolumeError(e)
+            | DriveError::ShareError(e)
+            | DriveError::NodeError(e) => !matches!(
+                e.downcast_ref::<NodeError>().map(NodeError::kind),
+                Some(
+                    SdkErrorKind::Cancelled
+                        | SdkErrorKind::Authentication
+                        | SdkErrorKind::PermissionDenied
+                        | SdkErrorKind::NotFound
+                        | SdkErrorKind::InvalidRequest
+                )
+            ),
+            DriveError::OperationFailed { .. } | DriveError::OperationFailedWithoutCode { .. } => true,
+            _ => false,
+        }
+    }
 }
 
 impl DriveClient {
@@ -69,6 +101,7 @@ impl DriveClient {
         observability: ObservabilityHandle,
         request: ProtonDriveClientCreateRequest,
     ) -> Result<Self, DriveError> {
+        request.validate()?;
         if session.handle().is_null() {
             return Err(DriveError::InvalidSession);
         }
@@ -77,9 +110,10 @@ impl DriveClient {
             .to_proto_buffer()
             .map_err(|e| DriveError::ProtobufError(e))?;
 
-        let (result, client_handle) =
-            drive::raw::drive_client_create(session.handle(), observability, proto_buf.as_byte_array())
-                .map_err(|e| DriveError::SdkError(e))?;
+        let api = session.api().clone();
+        let (result, client_handle) = api
+            .drive_client_create(session.handle(), observability, proto_buf.as_byte_array())
+            .map_err(|e| DriveError::SdkError(e))?;
 
         if result != 0 {
             return Err(DriveError::CreationFailed(result));
@@ -93,7 +127,10 @@ impl DriveClient {
 
         Ok(Self {
             handle: client_handle,
+            api,
             session,
+            links: NodeLinks::new(DEFAULT_LINK_CAPACITY),
+            cache: None,
         })
     }
 
@@ -107,10 +144,34 @@ impl DriveClient {
         !self.handle.is_null()
     }
 
+    pub(crate) fn links(&self) -> &NodeLinks {
+        &self.links
+    }
+
+    /// Returns the node cache, if the client was built with one
+    pub fn cache(&self) -> Option<&NodeCache> {
+        self.cache.as_ref()
+    }
+
     pub fn session(&self) -> &Session {
         &self.session
     }
 
+    /// Returns the SDK calls the client makes, those of its session
+    pub fn api(&self) -> &Arc<dyn SdkApi> {
+        &self.api
+    }
+
+    /// Returns the identity of `node` to hand to a download or another node call
+    ///
+    /// Listed nodes usually leave the share and volume ids out, those are taken from
+    /// `root`, the identity of the share root. The node id always comes from `node`.
+    pub fn complete_identity(&self, node: &impl NodeIdentityExt, root: &NodeIdentity) -> Result<NodeIdentity, NodeError> {
+        node.full_identity(root).inspect_err(|e| {
+            trace!("Unable to complete the identity of {:?}: {}", node.node_identity(), e);
+        })
+    }
+
     /// Registers node keys with the Drive client
     ///
     /// Node keys are used for encrypting/decrypting file content and metadata
@@ -132,9 +193,10 @@ impl DriveClient {
             .to_proto_buffer()
             .map_err(|e| DriveError::ProtobufError((e)))?;
 
-        let result =
-            drive::raw::drive_client_register_node_keys(self.handle, proto_buf.as_byte_array())
-                .map_err(|e| DriveError::SdkError(e))?;
+        let result = self
+            .api
+            .drive_client_register_node_keys(self.handle, proto_buf.as_byte_array())
+            .map_err(|e| DriveError::SdkError(e))?;
 
         if result != 0 {
             return Err(DriveError::OperationFailed {
@@ -168,9 +230,10 @@ impl DriveClient {
             .to_proto_buffer()
             .map_err(|e| DriveError::ProtobufError(e))?;
 
-        let result =
-            drive::raw::drive_client_register_share_key(self.handle, proto_buf.as_byte_array())
-                .map_err(|e| DriveError::SdkError(e))?;
+        let result = self
+            .api
+            .drive_client_register_share_key(self.handle, proto_buf.as_byte_array())
+            .map_err(|e| DriveError::SdkError(e))?;
 
         if result != 0 {
             return Err(DriveError::OperationFailed {
@@ -185,10 +248,11 @@ impl DriveClient {
 
     pub async fn get_volumes(&self) -> Result<Vec<VolumeMetadata>, DriveError> {
         let handle = self.handle;
+        let api = self.api.clone();
         let cancellation_token = self.session.cancellation_token().handle();
 
         let bytes = tokio::task::spawn_blocking(move || {
-            let result = drive::raw::drive_client_get_volumes(
+            let result = api.drive_client_get_volumes(
                 handle,
                 cancellation_token)
                 .map_err(|e| DriveError::SdkError(e))?;
@@ -197,11 +261,7 @@ impl DriveClient {
                 return Err(DriveError::EmptyByteArray(String::from("VolumesResponse")));
             }
 
-            let bytes = unsafe {
-                result.as_slice().to_vec()
-            };
-
-            Ok(bytes)
+            Ok(result.into_vec())
         }).await.map_err(|e| DriveError::SdkError(anyhow::Error::new(e)))?;
 
         let bytes = bytes?;
@@ -214,16 +274,20 @@ impl DriveClient {
         Ok(response.volumes)
     }
 
-    pub async fn get_shares(&self, volume_metadata: &VolumeMetadata) -> Result<Share, DriveError> {
+    /// Fetches the shares of a volume
+    ///
+    /// The SDK may answer with a single share or a sequence of length-delimited
+    /// ones, both are handled.
+    pub async fn get_shares(&self, volume_metadata: &VolumeMetadata) -> Result<Vec<Share>, DriveError> {
         let handle = self.handle;
+        let api = self.api.clone();
         let token = self.session.cancellation_token().handle();
-        let metadata_vec = volume_metadata.encode_to_vec();
+        let metadata_buf = ProtoBufferPool::encode(volume_metadata)?;
 
         let bytes = tokio::task::spawn_blocking(move || {
-            let metadata = ByteArray::from_slice(&metadata_vec);
-            let result = drive::raw::drive_client_get_shares(
+            let result = api.drive_client_get_shares(
                 handle, 
-                metadata,
+                metadata_buf.as_byte_array(),
                 token
             ).map_err(|e| DriveError::ShareError(e))?;
 
@@ -231,36 +295,43 @@ impl DriveClient {
                 return Err(DriveError::EmptyByteArray(String::from("Share")));
             }
 
-            let bytes = unsafe {
-                result.as_slice().to_vec()
-            };
-
-            Ok(bytes)
+            Ok(result.into_vec())
         }).await.map_err(|e| DriveError::ShareError(anyhow::Error::new(e)))?;
 
         let bytes = bytes?;
-        let response = match Share::decode(&*bytes) {
-            Ok(value) => value,
-            Err(error) => return Err(DriveError::ProtobufError(error.into())),
-        };
+        let shares = helpers::decode_delimited_or_single::<Share>(&bytes)?;
+
+        for root_node_id in shares.iter().filter_map(|share| share.root_node_id.as_ref()) {
+            self.links.record_root(root_node_id.value.clone());
+            if let Some(cache) = &self.cache {
+                cache.insert_root(&root_node_id.value);
+            }
+        }
 
-        Ok(response)
+        Ok(shares)
     }
 
     /// This function fetches the children of a folder using a node identity. 
     /// 
     /// # Parameters
     /// * node_identity: The NodeIdentity (which contains a link id, share id and volume id)
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            skip_all,
+            fields(node_id = crate::tracing_support::node_id(Some(&node_identity)), children = tracing::field::Empty)
+        )
+    )]
     pub async fn get_folder_children(&self, node_identity: NodeIdentity) -> Result<Vec<NodeType>, DriveError> {
         let handle = self.handle;
+        let api = self.api.clone();
         let token = self.session.cancellation_token().handle();
-        let identity_vec = node_identity.encode_to_vec();
+        let identity_buf = ProtoBufferPool::encode(&node_identity)?;
 
         let bytes: Result<Vec<u8>, DriveError> = tokio::task::spawn_blocking(move || {
-            let identity = ByteArray::from_slice(&identity_vec);
-            let result = drive::raw::drive_client_get_folder_children(
+            let result = api.drive_client_get_folder_children(
                 handle, 
-                identity, 
+                identity_buf.as_byte_array(), 
                 token
             ).map_err(|e| DriveError::NodeError(anyhow::anyhow!(e)))?;
 
@@ -268,17 +339,48 @@ impl DriveClient {
             //     return Err(DriveError::EmptyByteArray(String::from("NodeTypeList")));
             // }
 
-            let bytes = unsafe { result.as_slice().to_vec() };
-            Ok(bytes)
+            Ok(result.into_vec())
         }).await.map_err(|e| DriveError::NodeError(anyhow::anyhow!(e)))?;
 
         let bytes = bytes?;
         let node_list = NodeTypeList::decode(&*bytes)
             .map_err(|e| DriveError::ProtobufError(e.into()))?;
 
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("children", node_list.nodes.len());
+
+        self.remember_links(&node_identity, &node_list.nodes);
+        if let (Some(cache), Some(folder_id)) = (&self.cache, &node_identity.node_id) {
+            cache.insert_children(&folder_id.value, &node_list.nodes);
+        }
+
         Ok(node_list.nodes)
     }
 
+    /// Records the names and parents of listed children for path resolution
+    fn remember_links(&self, folder: &NodeIdentity, children: &[NodeType]) {
+        let folder_id = folder.node_id.as_ref().map(|id| id.value.clone());
+
+        for child in children {
+            let (name, parent_id) = match child.node_type.as_ref() {
+                Some(node_type::NodeType::FileNode(file)) => (&file.name, &file.parent_id),
+                Some(node_type::NodeType::FolderNode(folder)) => (&folder.name, &folder.parent_id),
+                None => continue,
+            };
+            let Some(node_id) = child.node_identity().and_then(|identity| identity.node_id.as_ref()) else {
+                continue;
+            };
+
+            self.links.record(
+                node_id.value.clone(),
+                NodeLink {
+                    name: name.clone(),
+                    parent_id: parent_id.as_ref().map(|id| id.value.clone()).or_else(|| folder_id.clone()),
+                },
+            );
+        }
+    }
+
     pub fn get_folder_children_blocking(&self, node_identity: NodeIdentity) -> Result<Vec<NodeType>, DriveError> {
         let rt = tokio::runtime::Runtime::new().map_err(|e| DriveError::NodeError(anyhow::anyhow!(e)))?;
         rt.block_on(self.get_folder_children(node_identity))
@@ -287,7 +389,7 @@ impl DriveClient {
     /// Manually frees up the Proton Drive client handles in memory
     pub fn free(self) -> Result<(), DriveError> {
         Ok(if !self.handle.is_null() {
-            drive::raw::drive_client_free(self.handle).map_err(|e| DriveError::SdkError(e))?;
+            self.api.drive_client_free(self.handle).map_err(|e| DriveError::SdkError(e))?;
             debug!("Drive client freed successfully!")
         })
     }
@@ -305,7 +407,7 @@ impl fmt::Debug for DriveClient {
 impl Drop for DriveClient {
     fn drop(&mut self) {
         if !self.handle.is_null() {
-            if let Err(e) = drive::raw::drive_client_free(self.handle) {
+            if let Err(e) = self.api.drive_client_free(self.handle) {
                 warn!("Failed to free Drive client in Drop: {}", e);
             } else {
                 debug!("Drive client cleaned up automatically");
@@ -318,6 +420,8 @@ pub struct DriveClientBuilder {
     session: Session,
     observability: ObservabilityHandle,
     request: ProtonDriveClientCreateRequest,
+    logger: Option<LoggerProviderHandle>,
+    cache_capacity: Option<usize>,
 }
 
 impl DriveClientBuilder {
@@ -327,6 +431,8 @@ impl DriveClientBuilder {
             session: session,
             observability: ObservabilityHandle::null(),
             request: ProtonDriveClientCreateRequest::default(),
+            logger: None,
+            cache_capacity: None,
         }
     }
 
@@ -342,14 +448,116 @@ impl DriveClientBuilder {
         self
     }
 
+    /// Sets the logger provider the Drive client should log through
+    ///
+    /// The native client has no logger of its own and always logs through the
+    /// session's provider, so this only takes effect when the session was created
+    /// with the same provider (see `SessionBuilder::with_logger`). A mismatch is
+    /// reported when building.
+    pub fn with_logger(mut self, logger: &LoggerProvider) -> Self {
+        self.logger = Some(logger.handle());
+        self
+    }
+
+    /// Gives the client a [`NodeCache`] of up to `capacity` nodes, filled by
+    /// `get_folder_children` and `get_shares`
+    pub fn with_cache(mut self, capacity: usize) -> Self {
+        self.cache_capacity = Some(capacity);
+        self
+    }
+
     /// Builds it
     pub fn build(self) -> Result<DriveClient, DriveError> {
+        if let Some(logger) = self.logger {
+            if self.session.logger_provider() != Some(logger) {
+                warn!(
+                    "Drive client logger {:?} differs from the session's {:?}, the session's provider will be used",
+                    logger,
+                    self.session.logger_provider()
+                );
+            }
+        }
         if self.request.client_id.is_none() {
             error!(
                 "Unable to locate client id. Please add in a client id (just the name of your app)"
             );
             error!("May fail without it, carrying on...");
         }
-        DriveClient::new(self.session, self.observability, self.request)
+        let mut client = DriveClient::new(self.session, self.observability, self.request)?;
+        client.cache = self.cache_capacity.map(NodeCache::new);
+        Ok(client)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use proton_sdk_sys::{
+        api::{MockApi, Reply},
+        protobufs::drive::{FolderNode, LinkId},
+    };
+
+    use crate::sessions::tests::mock_session;
+
+    /// A client of a session begun against `mock`
+    pub(crate) async fn mock_client(mock: &Arc<MockApi>) -> DriveClient {
+        DriveClientBuilder::new(mock_session(mock).await).build().unwrap()
+    }
+
+    fn identity(node_id: &str) -> NodeIdentity {
+        NodeIdentity { node_id: Some(LinkId { value: node_id.to_string() }), ..Default::default() }
+    }
+
+    #[tokio::test]
+    async fn creation_failures_are_reported() {
+        let mock = Arc::new(MockApi::new());
+        mock.reply("drive_client_create", Reply::Code(9));
+        let session = mock_session(&mock).await;
+        assert!(matches!(DriveClientBuilder::new(session).build(), Err(DriveError::CreationFailed(9))));
+    }
+
+    #[tokio::test]
+    async fn folder_children_are_decoded() {
+        let mock = Arc::new(MockApi::new());
+        let client = mock_client(&mock).await;
+        let photos = NodeType {
+            node_type: Some(node_type::NodeType::FolderNode(FolderNode {
+                node_identity: Some(identity("photos")),
+                name: "Photos".to_string(),
+                ..Default::default()
+            })),
+        };
+        let children = NodeTypeList { nodes: vec![photos.clone()] };
+        mock.reply("drive_client_get_folder_children", Reply::Success(children.encode_to_vec()))
+            .reply("drive_client_get_folder_children", Reply::Missing);
+
+        assert_eq!(client.get_folder_children(identity("root")).await.unwrap(), [photos]);
+        let calls = mock.calls_to("drive_client_get_folder_children");
+        assert_eq!((calls[0].handle, calls[0].request.clone()), (client.handle().raw(), identity("root").encode_to_vec()));
+        assert!(matches!(client.get_folder_children(identity("root")).await, Err(DriveError::NodeError(_))));
+    }
+
+    #[tokio::test]
+    async fn empty_shares_are_an_error() {
+        let mock = Arc::new(MockApi::new());
+        let client = mock_client(&mock).await;
+        let shares = client.get_shares(&VolumeMetadata::default()).await;
+        assert!(matches!(shares, Err(DriveError::EmptyByteArray(_))));
+    }
+
+    #[tokio::test]
+    async fn dropping_the_client_frees_it_before_its_session() {
+        let mock = Arc::new(MockApi::new());
+        let client = mock_client(&mock).await;
+        let expected = [("drive_client_free", client.handle().raw()), ("session_free", client.session().handle().raw())];
+
+        drop(client);
+        let freed: Vec<_> = mock
+            .calls()
+            .into_iter()
+            .filter(|call| matches!(call.name, "drive_client_free" | "session_free"))
+            .map(|call| (call.name, call.handle))
+            .collect();
+        assert_eq!(freed, expected);
     }
 }
@@ -0,0 +1,292 @@
+//! Decoding of what the SDK hands the failure callbacks of its async calls
+
+use std::fmt;
+
+use proton_sdk_sys::{
+    data::ByteArray,
+    protobufs::{
+        account::{Error, ErrorDomain},
+        primary_code_name, FromByteArray, SdkErrorKind,
+    },
+};
+
+/// Longest binary error dumped whole in the message
+const MAX_DUMP: usize = 50;
+
+/// An error the SDK reported, with the codes callers act on as their own variants
+///
+/// The SDK usually calls back with an `Error` protobuf. Older builds, and the
+/// Proton API when the SDK passes its answer on, use JSON or plain text, see
+/// [`SdkErrorDetails::decode`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SdkErrorDetails {
+    /// HTTP 401, the session's tokens were rejected
+    Unauthorized { message: String },
+    /// HTTP 403, the account may not do this, or is locked
+    Forbidden { message: String },
+    /// HTTP 422, the API rejected the content of the request
+    Unprocessable { message: String },
+    /// HTTP 429, too many requests were made
+    RateLimited { message: String },
+    /// Proton code 8002, the password or second factor was wrong
+    IncorrectPassword { message: String },
+    /// Any other error, `code` being the primary code when there is one
+    Other {
+        code: Option<i64>,
+        secondary_code: Option<i64>,
+        kind: SdkErrorKind,
+        message: String,
+    },
+}
+
+impl SdkErrorDetails {
+    /// An error the Proton API reported with `code`, if any
+    pub fn new(code: Option<i64>, message: impl Into<String>) -> Self {
+        let message = message.into();
+        match code.and_then(well_known) {
+            Some(variant) => variant(message),
+            None => SdkErrorDetails::Other {
+                code,
+                secondary_code: None,
+                kind: SdkErrorKind::classify(ErrorDomain::Api, code),
+                message,
+            },
+        }
+    }
+
+    /// The error of a call the SDK failed with a non-zero return code, instead of a callback
+    pub fn from_code(code: i32) -> Self {
+        if code > 0 {
+            return Self::new(Some(code.into()), String::new());
+        }
+        SdkErrorDetails::Other {
+            code: Some(code.into()),
+            secondary_code: None,
+            kind: SdkErrorKind::Unknown,
+            message: String::new(),
+        }
+    }
+
+    /// Reads what a failure callback received, which never fails
+    ///
+    /// An `Error` protobuf is read first, then a JSON object with a `Code` and an
+    /// `Error` (or `code` and `message`), then text as it is. Anything else is
+    /// kept as a hex dump.
+    pub fn decode(error_data: &[u8]) -> Self {
+        if error_data.is_empty() {
+            return Self::new(None, "No error details provided");
+        }
+
+        // plain text often decodes as a protobuf too, so it only counts as one when it has a message
+        let text = std::str::from_utf8(error_data).ok();
+        if let Ok(error) = Error::from_bytes_strict(error_data) {
+            if !error.message.is_empty() || text.is_none() {
+                return error.into();
+            }
+        }
+
+        match text {
+            Some(text) => from_json(text).unwrap_or_else(|| Self::new(None, text.trim())),
+            None => {
+                let dump = ByteArray::from_slice(error_data).hex_preview(MAX_DUMP, false);
+                Self::new(None, format!("Binary error data ({} bytes): {}", error_data.len(), dump))
+            }
+        }
+    }
+
+    /// The primary code, the HTTP status for the well-known errors
+    pub fn code(&self) -> Option<i64> {
+        match self {
+            SdkErrorDetails::Unauthorized { .. } => Some(401),
+            SdkErrorDetails::Forbidden { .. } => Some(403),
+            SdkErrorDetails::Unprocessable { .. } => Some(422),
+            SdkErrorDetails::RateLimited { .. } => Some(429),
+            SdkErrorDetails::IncorrectPassword { .. } => Some(8002),
+            SdkErrorDetails::Other { code, .. } => *code,
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            SdkErrorDetails::Unauthorized { message }
+            | SdkErrorDetails::Forbidden { message }
+            | SdkErrorDetails::Unprocessable { message }
+            | SdkErrorDetails::RateLimited { message }
+            | SdkErrorDetails::IncorrectPassword { message }
+            | SdkErrorDetails::Other { message, .. } => message,
+        }
+    }
+
+    /// Classifies the error, a rate limit being an [`SdkErrorKind::Api`] error worth retrying
+    pub fn kind(&self) -> SdkErrorKind {
+        match self {
+            SdkErrorDetails::Unauthorized { .. } | SdkErrorDetails::IncorrectPassword { .. } => {
+                SdkErrorKind::Authentication
+            }
+            SdkErrorDetails::Forbidden { .. } => SdkErrorKind::PermissionDenied,
+            SdkErrorDetails::Unprocessable { .. } => SdkErrorKind::InvalidRequest,
+            SdkErrorDetails::RateLimited { .. } => SdkErrorKind::Api,
+            SdkErrorDetails::Other { kind, .. } => *kind,
+        }
+    }
+}
+
+/// The variant of a well-known code
+fn well_known(code: i64) -> Option<fn(String) -> SdkErrorDetails> {
+    Some(match code {
+        401 => |message| SdkErrorDetails::Unauthorized { message },
+        403 => |message| SdkErrorDetails::Forbidden { message },
+        422 => |message| SdkErrorDetails::Unprocessable { message },
+        429 => |message| SdkErrorDetails::RateLimited { message },
+        8002 => |message| SdkErrorDetails::IncorrectPassword { message },
+        _ => return None,
+    })
+}
+
+/// Reads a JSON error, `None` when `text` isn't an object with a code or message
+fn from_json(text: &str) -> Option<SdkErrorDetails> {
+    let value: serde_json::Value = serde_json::from_str(text).ok()?;
+    let object = value.as_object()?;
+    let field = |names: &[&str]| names.iter().find_map(|name| object.get(*name));
+
+    let code = field(&["Code", "code", "primaryCode"]).and_then(serde_json::Value::as_i64);
+    let message = field(&["Error", "error", "Message", "message"]).and_then(serde_json::Value::as_str);
+    if code.is_none() && message.is_none() {
+        return None;
+    }
+    Some(SdkErrorDetails::new(code, message.unwrap_or_default()))
+}
+
+/// Uses the first well-known code of the primary and secondary codes, in that order
+impl From<Error> for SdkErrorDetails {
+    fn from(error: Error) -> Self {
+        let known = [error.primary_code, error.secondary_code]
+            .into_iter()
+            .flatten()
+            .find_map(well_known);
+        match known {
+            Some(variant) => variant(error.message),
+            None => SdkErrorDetails::Other {
+                kind: error.kind(),
+                code: error.primary_code,
+                secondary_code: error.secondary_code,
+                message: error.message,
+            },
+        }
+    }
+}
+
+impl fmt::Display for SdkErrorDetails {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SdkErrorDetails::Unauthorized { .. } => f.write_str("Unauthorized (401)")?,
+            SdkErrorDetails::Forbidden { .. } => f.write_str("Forbidden (403)")?,
+            SdkErrorDetails::Unprocessable { .. } => f.write_str("Unprocessable request (422)")?,
+            SdkErrorDetails::RateLimited { .. } => f.write_str("Rate limited (429)")?,
+            SdkErrorDetails::IncorrectPassword { .. } => f.write_str("Incorrect password (8002)")?,
+            SdkErrorDetails::Other { code: Some(code), .. } => match primary_code_name(*code) {
+                Some(name) => write!(f, "Error {} ({})", code, name)?,
+                None => write!(f, "Error {}", code)?,
+            },
+            SdkErrorDetails::Other { code: None, .. } => return f.write_str(self.message()),
+        }
+        match self.message() {
+            "" => Ok(()),
+            message => write!(f, ": {}", message),
+        }
+    }
+}
+
+impl std::error::Error for SdkErrorDetails {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proton_sdk_sys::prost::Message;
+
+    /// `Error { message: "Invalid access token", domain: Api, primary_code: 401 }`
+    const UNAUTHORIZED: &[u8] = b"\x12\x14Invalid access token\x18\x02\x20\x91\x03";
+    /// `Error { type: "ProtonApiException", message: "Incorrect login credentials", domain: Api, primary_code: 8002 }`
+    const INCORRECT_PASSWORD: &[u8] =
+        b"\x0a\x12ProtonApiException\x12\x1bIncorrect login credentials\x18\x02\x20\xc2\x3e";
+    /// `Error { message: "Too many requests", domain: Api, primary_code: 2028, secondary_code: 429 }`
+    const RATE_LIMITED: &[u8] = b"\x12\x11Too many requests\x18\x02\x20\xec\x0f\x28\xad\x03";
+    /// `Error { message: "File not found", domain: Api, primary_code: 2501, secondary_code: 404 }`
+    const NOT_FOUND: &[u8] = b"\x12\x0eFile not found\x18\x02\x20\xc5\x13\x28\x94\x03";
+    /// `Error { message: "Session key decryption failed", domain: Cryptography }`
+    const CRYPTOGRAPHY: &[u8] = b"\x12\x1dSession key decryption failed\x18\x06";
+
+    #[test]
+    fn fixtures_are_error_protobufs() {
+        let error = Error {
+            r#type: "ProtonApiException".to_string(),
+            message: "Incorrect login credentials".to_string(),
+            domain: ErrorDomain::Api.into(),
+            primary_code: Some(8002),
+            ..Default::default()
+        };
+        assert_eq!(error.encode_to_vec(), INCORRECT_PASSWORD);
+        assert_eq!(Error::decode(RATE_LIMITED).unwrap().secondary_code, Some(429));
+    }
+
+    #[test]
+    fn well_known_codes_get_their_own_variant() {
+        let message = |text: &str| text.to_string();
+        assert_eq!(
+            SdkErrorDetails::decode(UNAUTHORIZED),
+            SdkErrorDetails::Unauthorized { message: message("Invalid access token") }
+        );
+        assert_eq!(
+            SdkErrorDetails::decode(INCORRECT_PASSWORD),
+            SdkErrorDetails::IncorrectPassword { message: message("Incorrect login credentials") }
+        );
+        // the HTTP status is found among the secondary codes too
+        let rate_limited = SdkErrorDetails::decode(RATE_LIMITED);
+        assert_eq!(rate_limited, SdkErrorDetails::RateLimited { message: message("Too many requests") });
+        assert_eq!((rate_limited.code(), rate_limited.kind()), (Some(429), SdkErrorKind::Api));
+
+        let json = SdkErrorDetails::decode(br#"{"Code":422,"Error":"Invalid input"}"#);
+        assert_eq!(json, SdkErrorDetails::Unprocessable { message: message("Invalid input") });
+        assert_eq!(json.to_string(), "Unprocessable request (422): Invalid input");
+        assert_eq!(SdkErrorDetails::from_code(403).kind(), SdkErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    fn other_errors_keep_their_codes_and_kind() {
+        let not_found = SdkErrorDetails::decode(NOT_FOUND);
+        assert_eq!(
+            not_found,
+            SdkErrorDetails::Other {
+                code: Some(2501),
+                secondary_code: Some(404),
+                kind: SdkErrorKind::NotFound,
+                message: "File not found".to_string(),
+            }
+        );
+        assert_eq!(not_found.to_string(), "Error 2501 (DoesNotExist): File not found");
+
+        let cryptography = SdkErrorDetails::decode(CRYPTOGRAPHY);
+        assert_eq!((cryptography.code(), cryptography.kind()), (None, SdkErrorKind::Cryptography));
+        assert_eq!(cryptography.to_string(), "Session key decryption failed");
+
+        let json = SdkErrorDetails::decode(br#"{"code": 2501, "message": "Link not found"}"#);
+        assert_eq!((json.code(), json.message(), json.kind()), (Some(2501), "Link not found", SdkErrorKind::NotFound));
+
+        let failed = SdkErrorDetails::from_code(-1);
+        assert_eq!((failed.code(), failed.kind()), (Some(-1), SdkErrorKind::Unknown));
+        assert_eq!(failed.to_string(), "Error -1");
+    }
+
+    #[test]
+    fn text_and_binary_errors_are_kept_as_messages() {
+        let text = SdkErrorDetails::decode(b"Node not found\n");
+        assert_eq!((text.code(), text.message()), (None, "Node not found"));
+        // JSON that isn't an error object is text too
+        assert_eq!(SdkErrorDetails::decode(b"[1, 2]").message(), "[1, 2]");
+
+        let binary = SdkErrorDetails::decode(&[0xff, 0x00]);
+        assert_eq!(binary.message(), "Binary error data (2 bytes): [ff, 00]");
+        assert_eq!(binary.kind(), SdkErrorKind::Unknown);
+        assert_eq!(SdkErrorDetails::decode(&[]).message(), "No error details provided");
+    }
+}
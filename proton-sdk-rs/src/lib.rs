@@ -2,8 +2,30 @@ pub mod utils;
 pub mod cancellation;
 pub mod downloads;
 pub mod drive;
+pub mod errors;
+pub mod logging;
+pub mod nodes;
 pub mod observability;
+pub mod progress;
+pub mod responses;
+pub mod retry;
 pub mod sessions;
 pub mod uploads;
 
-pub use proton_sdk_sys::protobufs::*;
+#[cfg(feature = "tracing")]
+mod tracing_support;
+
+pub use proton_sdk_sys::protobufs;
+pub use proton_sdk_sys::protobufs::{
+    FromByteArray, ProtoBuffer, ProtoBufferPool, ProtoError, SdkErrorKind, ToByteArray,
+};
+pub use proton_sdk_sys::protobufs::account::{
+    AddressKeyRegistrationRequest, OperationIdentifier, OperationType, ProtonClientOptions,
+    SessionBeginRequest, SessionInfo, SessionResumeRequest, SessionTokens,
+};
+pub use proton_sdk_sys::protobufs::drive::{
+    node_type, ClientId, FileDownloadRequest, FileNode, FileUploadRequest,
+    FileUploaderCreationRequest, FolderNode, LinkId, NodeIdentity, NodeNameDecryptionRequest,
+    NodeType, ProgressUpdate, ProtonDriveClientCreateRequest, Revision, RevisionMetadata, Share,
+    ShareId, ShareMetadata, VolumeEventType, VolumeId, VolumeMetadata,
+};
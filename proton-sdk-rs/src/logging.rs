@@ -0,0 +1,524 @@
+#[cfg(any(test, feature = "test-support"))]
+mod capture;
+mod file;
+
+use std::{
+    collections::VecDeque,
+    ffi::c_void,
+    fmt, io,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicIsize, Ordering},
+        Mutex,
+    },
+};
+
+use log::{debug, warn, Level, LevelFilter};
+use proton_sdk_sys::{
+    data::{ByteArray, Callback},
+    logger::{self, LoggerProviderHandle},
+    protobufs::{account::LogEvent, FromByteArray},
+};
+
+use self::file::{LogFileConfig, LogFileSink};
+#[cfg(any(test, feature = "test-support"))]
+pub use self::capture::{CapturedEvents, CapturingLogger};
+
+/// The target every forwarded native SDK record is logged under, so
+/// `RUST_LOG=proton_sdk=debug` enables them
+pub const SDK_LOG_TARGET: &str = "proton_sdk";
+
+/// Number of recent events kept for [`SdkLogger::recent`] unless configured otherwise
+pub const DEFAULT_HISTORY_CAPACITY: usize = 256;
+
+/// Raw handle of the currently installed logger provider (0 when none)
+static INSTALLED_PROVIDER: AtomicIsize = AtomicIsize::new(0);
+
+/// The most recently captured events, oldest first
+static HISTORY: Mutex<VecDeque<LogEvent>> = Mutex::new(VecDeque::new());
+
+#[derive(Debug, thiserror::Error)]
+pub enum LoggerError {
+    #[error("SDK error: {0}")]
+    SdkError(#[from] anyhow::Error),
+
+    #[error("Logger provider creation failed with code: {0}")]
+    CreationFailed(i32),
+
+    #[error("Logger provider handle is null")]
+    NullHandle,
+
+    #[error("Failed to open SDK log file: {0}")]
+    LogFile(#[from] io::Error),
+}
+
+/// Owns a native logger provider, freeing it when dropped
+pub struct LoggerProvider {
+    handle: LoggerProviderHandle,
+}
+
+impl LoggerProvider {
+    /// Creates a new logger provider reporting to the given callback
+    ///
+    /// # Arguments
+    /// * `callback` - Callback receiving every SDK log event as a `LogEvent` ByteArray.
+    ///   Its state must stay valid for as long as the provider lives.
+    pub fn new(callback: Callback) -> Result<Self, LoggerError> {
+        let (result, handle) = logger::raw::logger_provider_create(callback)?;
+
+        if result != 0 {
+            return Err(LoggerError::CreationFailed(result));
+        }
+
+        if handle.is_null() {
+            return Err(LoggerError::NullHandle);
+        }
+
+        debug!("Logger provider created with handle: {:?}", handle);
+        Ok(Self { handle })
+    }
+
+    /// Returns the logger provider handle
+    pub fn handle(&self) -> LoggerProviderHandle {
+        self.handle
+    }
+
+    /// Checks if the handle is valid (not null)
+    pub fn is_valid(&self) -> bool {
+        !self.handle.is_null()
+    }
+
+    /// Explicitly frees the logger provider
+    ///
+    /// Note: This is automatically called when the LoggerProvider is dropped,
+    /// so you usually don't need to call this manually.
+    pub fn free(mut self) -> Result<(), LoggerError> {
+        let handle = std::mem::replace(&mut self.handle, LoggerProviderHandle::null());
+        if !handle.is_null() {
+            logger::raw::logger_provider_free(handle)?;
+            debug!("Logger provider freed successfully");
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for LoggerProvider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LoggerProvider")
+            .field("handle", &self.handle)
+            .field("valid", &self.is_valid())
+            .finish()
+    }
+}
+
+impl Drop for LoggerProvider {
+    fn drop(&mut self) {
+        if !self.handle.is_null() {
+            if let Err(e) = logger::raw::logger_provider_free(self.handle) {
+                warn!("Failed to free logger provider in Drop: {}", e);
+            } else {
+                debug!("Logger provider cleaned up automatically");
+            }
+        }
+    }
+}
+
+/// Bridges the native SDK's internal logs into the `log` crate
+pub struct SdkLogger;
+
+impl SdkLogger {
+    /// Creates a logger provider whose output is forwarded through `log::log!`
+    /// with the [`SDK_LOG_TARGET`] target.
+    ///
+    /// Sessions created while the returned guard is alive pick the provider up
+    /// automatically. Keep the guard alive for as long as the SDK is in use.
+    ///
+    /// # Returns
+    /// A guard that frees the logger provider when dropped
+    pub fn install() -> Result<SdkLoggerGuard, LoggerError> {
+        SdkLoggerBuilder::new().install()
+    }
+
+    /// Starts building an SDK logger that never forwards records above `level`,
+    /// whatever the `log` filter allows
+    pub fn with_max_level(level: LevelFilter) -> SdkLoggerBuilder {
+        SdkLoggerBuilder::new().with_max_level(level)
+    }
+
+    /// Starts building an SDK logger that also writes every event to a rolling log file
+    ///
+    /// See [`SdkLoggerBuilder::with_file`].
+    pub fn with_file(
+        path: impl Into<PathBuf>,
+        max_size_bytes: u64,
+        max_files: usize,
+    ) -> SdkLoggerBuilder {
+        SdkLoggerBuilder::new().with_file(path, max_size_bytes, max_files)
+    }
+
+    /// Returns up to `n` of the most recently captured SDK log events, oldest first
+    ///
+    /// Useful for attaching the native side's last words to a crash report.
+    pub fn recent(n: usize) -> Vec<LogEvent> {
+        match HISTORY.lock() {
+            Ok(history) => history.iter().skip(history.len().saturating_sub(n)).cloned().collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Returns the handle of the installed logger provider, if any
+    pub fn installed_handle() -> Option<LoggerProviderHandle> {
+        match INSTALLED_PROVIDER.load(Ordering::SeqCst) {
+            0 => None,
+            handle => Some(LoggerProviderHandle::from(handle)),
+        }
+    }
+}
+
+pub struct SdkLoggerBuilder {
+    max_level: LevelFilter,
+    history_capacity: usize,
+    file: Option<LogFileConfig>,
+}
+
+impl SdkLoggerBuilder {
+    /// Creates a new SdkLogger builder forwarding every level the `log` filter allows
+    pub fn new() -> Self {
+        Self {
+            max_level: LevelFilter::Trace,
+            history_capacity: DEFAULT_HISTORY_CAPACITY,
+            file: None,
+        }
+    }
+
+    /// Caps the forwarded levels independently of the `log` filter
+    pub fn with_max_level(mut self, level: LevelFilter) -> Self {
+        self.max_level = level;
+        self
+    }
+
+    /// Writes every SDK event to `path`, whatever the console filter allows
+    ///
+    /// The file is rotated to `<path>.1` once it would grow past `max_size_bytes`,
+    /// keeping at most `max_files` rotated files. Lines are buffered and flushed
+    /// every couple of seconds and when the guard is dropped.
+    pub fn with_file(
+        mut self,
+        path: impl Into<PathBuf>,
+        max_size_bytes: u64,
+        max_files: usize,
+    ) -> Self {
+        self.file = Some(LogFileConfig {
+            path: path.into(),
+            max_size_bytes,
+            max_files,
+        });
+        self
+    }
+
+    /// Sets how many captured events [`SdkLogger::recent`] can return (0 disables the history)
+    pub fn with_history(mut self, capacity: usize) -> Self {
+        self.history_capacity = capacity;
+        self
+    }
+
+    /// Installs the logger
+    pub fn install(self) -> Result<SdkLoggerGuard, LoggerError> {
+        let file = match self.file {
+            Some(config) => Some(LogFileSink::open(config)?),
+            None => None,
+        };
+
+        let state = Box::new(LoggerState {
+            max_level: self.max_level,
+            history_capacity: self.history_capacity,
+            file,
+        });
+        let state_ptr = state.as_ref() as *const LoggerState as *const c_void;
+
+        let provider = LoggerProvider::new(Callback::new(state_ptr, Some(log_c_callback)))?;
+
+        if INSTALLED_PROVIDER.swap(provider.handle().raw(), Ordering::SeqCst) != 0 {
+            warn!("An SDK logger was already installed, replacing it");
+        }
+
+        Ok(SdkLoggerGuard {
+            provider,
+            _state: state,
+        })
+    }
+}
+
+impl Default for SdkLoggerBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// State shared with the log callback
+struct LoggerState {
+    max_level: LevelFilter,
+    history_capacity: usize,
+    file: Option<LogFileSink>,
+}
+
+impl LoggerState {
+    fn remember(&self, event: LogEvent) {
+        if self.history_capacity == 0 {
+            return;
+        }
+
+        if let Ok(mut history) = HISTORY.lock() {
+            while history.len() >= self.history_capacity {
+                history.pop_front();
+            }
+            history.push_back(event);
+        }
+    }
+}
+
+/// Keeps the SDK logger provider alive, freeing it on drop
+pub struct SdkLoggerGuard {
+    // declared before the state so the provider is freed before its callback state
+    provider: LoggerProvider,
+    _state: Box<LoggerState>,
+}
+
+impl SdkLoggerGuard {
+    /// Returns the logger provider backing this guard
+    pub fn provider(&self) -> &LoggerProvider {
+        &self.provider
+    }
+}
+
+impl fmt::Debug for SdkLoggerGuard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SdkLoggerGuard")
+            .field("provider", &self.provider)
+            .field("max_level", &self._state.max_level)
+            .field("file", &self._state.file.is_some())
+            .finish()
+    }
+}
+
+impl Drop for SdkLoggerGuard {
+    fn drop(&mut self) {
+        let _ = INSTALLED_PROVIDER.compare_exchange(
+            self.provider.handle().raw(),
+            0,
+            Ordering::SeqCst,
+            Ordering::SeqCst,
+        );
+    }
+}
+
+/// Maps the SDK's (.NET style) log level onto `log::Level`
+///
+/// Returns `None` for `LogLevel.None`, which should never be emitted.
+fn level_from_sdk(level: i32) -> Option<Level> {
+    match level {
+        0 => Some(Level::Trace),
+        1 => Some(Level::Debug),
+        2 => Some(Level::Info),
+        3 => Some(Level::Warn),
+        4 | 5 => Some(Level::Error),
+        _ => None,
+    }
+}
+
+/// Reads the level of an encoded `LogEvent` without decoding the rest of it
+///
+/// The SDK writes fields in field number order, so the level (field 1, varint)
+/// is either the very first field or was omitted because it is the default (trace).
+fn peek_level(bytes: &[u8]) -> i32 {
+    const LEVEL_TAG: u8 = 0x08;
+
+    if bytes.first() != Some(&LEVEL_TAG) {
+        return 0;
+    }
+
+    let mut value: u64 = 0;
+    for (i, byte) in bytes[1..].iter().take(10).enumerate() {
+        value |= ((byte & 0x7f) as u64) << (7 * i);
+        if byte & 0x80 == 0 {
+            return value as i32;
+        }
+    }
+
+    // truncated varint, let the full decode report it
+    0
+}
+
+extern "C" fn log_c_callback(state: *const c_void, data: ByteArray) {
+    if state.is_null() {
+        return;
+    }
+
+    let state = unsafe { &*(state as *const LoggerState) };
+    let bytes = data.view().as_slice();
+
+    let level = match level_from_sdk(peek_level(bytes)) {
+        Some(level) => level,
+        None => return,
+    };
+
+    let forwarded = level <= state.max_level && target_enabled(level);
+    if !forwarded && state.file.is_none() {
+        return;
+    }
+
+    let event = match LogEvent::from_bytes(bytes) {
+        Ok(event) => event,
+        Err(e) => {
+            if forwarded {
+                log::log!(
+                    target: SDK_LOG_TARGET,
+                    level,
+                    "Undecodable SDK log event ({}): {}",
+                    e,
+                    describe_raw(bytes)
+                );
+            }
+            return;
+        }
+    };
+
+    if forwarded {
+        forward(level, &event);
+    }
+    if let Some(file) = &state.file {
+        file.write(level, &event);
+    }
+    state.remember(event);
+}
+
+/// Checks whether records of `level` under the [`SDK_LOG_TARGET`] would be emitted
+#[cfg(not(feature = "tracing"))]
+fn target_enabled(level: Level) -> bool {
+    log::log_enabled!(target: SDK_LOG_TARGET, level)
+}
+
+#[cfg(feature = "tracing")]
+fn target_enabled(level: Level) -> bool {
+    tracing::level_filters::LevelFilter::current() >= tracing_level(level)
+}
+
+#[cfg(feature = "tracing")]
+fn tracing_level(level: Level) -> tracing::Level {
+    match level {
+        Level::Error => tracing::Level::ERROR,
+        Level::Warn => tracing::Level::WARN,
+        Level::Info => tracing::Level::INFO,
+        Level::Debug => tracing::Level::DEBUG,
+        Level::Trace => tracing::Level::TRACE,
+    }
+}
+
+/// Emits a decoded event as a `tracing` event with its fields as key-values
+#[cfg(feature = "tracing")]
+fn forward(level: Level, event: &LogEvent) {
+    macro_rules! emit {
+        ($level:expr) => {
+            tracing::event!(
+                target: SDK_LOG_TARGET,
+                $level,
+                category = %event.category_name,
+                exception = event.exception.as_deref(),
+                "{}",
+                event.message
+            )
+        };
+    }
+
+    match level {
+        Level::Error => emit!(tracing::Level::ERROR),
+        Level::Warn => emit!(tracing::Level::WARN),
+        Level::Info => emit!(tracing::Level::INFO),
+        Level::Debug => emit!(tracing::Level::DEBUG),
+        Level::Trace => emit!(tracing::Level::TRACE),
+    }
+}
+
+/// Logs a decoded event, using its category as the target
+#[cfg(not(feature = "tracing"))]
+fn forward(level: Level, event: &LogEvent) {
+    let target = if event.category_name.is_empty() {
+        SDK_LOG_TARGET.to_string()
+    } else {
+        format!("{}::{}", SDK_LOG_TARGET, event.category_name)
+    };
+
+    match event.exception.as_deref() {
+        Some(exception) if !exception.is_empty() => {
+            log::log!(target: &target, level, "{}\n{}", event.message, exception)
+        }
+        _ => log::log!(target: &target, level, "{}", event.message),
+    }
+}
+
+/// Renders a payload that isn't a `LogEvent` as text, or as hex when it isn't UTF-8
+fn describe_raw(bytes: &[u8]) -> String {
+    const MAX_HEX_BYTES: usize = 64;
+
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_string(),
+        Err(_) => {
+            let hex: String = bytes
+                .iter()
+                .take(MAX_HEX_BYTES)
+                .map(|byte| format!("{:02x}", byte))
+                .collect();
+            if bytes.len() > MAX_HEX_BYTES {
+                format!("{}... ({} bytes)", hex, bytes.len())
+            } else {
+                hex
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proton_sdk_sys::prost::Message;
+
+    fn encoded(level: i32) -> Vec<u8> {
+        LogEvent {
+            level,
+            message: "block 3 uploaded".to_string(),
+            category_name: "Proton.Drive.Sdk".to_string(),
+            exception: None,
+        }
+        .encode_to_vec()
+    }
+
+    #[test]
+    fn peek_level_matches_full_decode() {
+        for level in [0, 1, 2, 3, 4, 5, 6, 300] {
+            let bytes = encoded(level);
+            assert_eq!(peek_level(&bytes), LogEvent::decode(&*bytes).unwrap().level);
+        }
+    }
+
+    #[test]
+    fn peek_level_handles_empty_and_truncated_payloads() {
+        assert_eq!(peek_level(&[]), 0);
+        assert_eq!(peek_level(&[0x08]), 0);
+        assert_eq!(peek_level(&[0x08, 0x80]), 0);
+    }
+
+    #[test]
+    fn malformed_payloads_degrade_to_text_or_hex() {
+        assert_eq!(describe_raw(b"plain text"), "plain text");
+        assert_eq!(describe_raw(&[0xff, 0x00, 0x10]), "ff0010");
+        assert!(describe_raw(&[0xff; 100]).ends_with("... (100 bytes)"));
+    }
+
+    #[test]
+    fn sdk_levels_map_onto_log_levels() {
+        assert_eq!(level_from_sdk(0), Some(Level::Trace));
+        assert_eq!(level_from_sdk(2), Some(Level::Info));
+        assert_eq!(level_from_sdk(5), Some(Level::Error));
+        assert_eq!(level_from_sdk(6), None);
+    }
+}
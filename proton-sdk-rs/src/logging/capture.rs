@@ -0,0 +1,202 @@
+use std::{
+    ffi::c_void,
+    fmt,
+    sync::{atomic::Ordering, Arc, Mutex},
+};
+
+use log::Level;
+use proton_sdk_sys::{
+    data::{ByteArray, Callback},
+    logger::LoggerProviderHandle,
+    protobufs::{account::LogEvent, FromByteArray},
+};
+
+use super::{level_from_sdk, LoggerError, LoggerProvider, INSTALLED_PROVIDER};
+
+/// Native SDK log events captured by a [`CapturingLogger`]
+#[derive(Debug, Clone, Default)]
+pub struct CapturedEvents {
+    events: Arc<Mutex<Vec<LogEvent>>>,
+}
+
+impl CapturedEvents {
+    /// Returns a copy of every event captured so far, oldest first
+    pub fn events(&self) -> Vec<LogEvent> {
+        self.events.lock().map(|events| events.clone()).unwrap_or_default()
+    }
+
+    /// Checks if an event of the given level has a message containing `substring`
+    pub fn contains(&self, level: Level, substring: &str) -> bool {
+        self.with_events(|events| {
+            events.iter().any(|event| {
+                level_from_sdk(event.level) == Some(level) && event.message.contains(substring)
+            })
+        })
+    }
+
+    /// Counts the events logged under the given category, e.g. `Proton.Drive.Sdk`
+    pub fn count(&self, target: &str) -> usize {
+        self.with_events(|events| {
+            events
+                .iter()
+                .filter(|event| event.category_name == target)
+                .count()
+        })
+    }
+
+    /// Forgets every captured event
+    pub fn clear(&self) {
+        if let Ok(mut events) = self.events.lock() {
+            events.clear();
+        }
+    }
+
+    fn push(&self, event: LogEvent) {
+        if let Ok(mut events) = self.events.lock() {
+            events.push(event);
+        }
+    }
+
+    fn with_events<T: Default>(&self, f: impl FnOnce(&[LogEvent]) -> T) -> T {
+        self.events.lock().map(|events| f(&events)).unwrap_or_default()
+    }
+}
+
+/// A logger provider storing every native SDK event for test assertions
+///
+/// Either hand [`CapturingLogger::provider`] to `SessionBuilder::with_logger`, or
+/// call [`CapturingLogger::install`] to make it the provider new sessions pick up.
+/// An installed logger restores the previously installed provider when dropped.
+pub struct CapturingLogger {
+    // declared before the events so the provider is freed before its callback state
+    provider: LoggerProvider,
+    events: Box<CapturedEvents>,
+    previous: Option<isize>,
+}
+
+impl CapturingLogger {
+    /// Creates a capturing logger provider without installing it
+    pub fn new() -> Result<Self, LoggerError> {
+        let events = Box::new(CapturedEvents::default());
+        let state_ptr = events.as_ref() as *const CapturedEvents as *const c_void;
+
+        let provider = LoggerProvider::new(Callback::new(state_ptr, Some(capture_c_callback)))?;
+
+        Ok(Self {
+            provider,
+            events,
+            previous: None,
+        })
+    }
+
+    /// Creates a capturing logger and installs it in place of the current SDK logger
+    /// until it is dropped
+    pub fn install() -> Result<Self, LoggerError> {
+        let mut logger = Self::new()?;
+        logger.previous =
+            Some(INSTALLED_PROVIDER.swap(logger.provider.handle().raw(), Ordering::SeqCst));
+        Ok(logger)
+    }
+
+    /// Returns the logger provider backing this logger
+    pub fn provider(&self) -> &LoggerProvider {
+        &self.provider
+    }
+
+    /// Returns the logger provider handle
+    pub fn handle(&self) -> LoggerProviderHandle {
+        self.provider.handle()
+    }
+
+    /// Returns a shareable view of the captured events
+    pub fn events(&self) -> CapturedEvents {
+        self.events.as_ref().clone()
+    }
+
+    /// Checks if an event of the given level has a message containing `substring`
+    pub fn contains(&self, level: Level, substring: &str) -> bool {
+        self.events.contains(level, substring)
+    }
+
+    /// Counts the events logged under the given category
+    pub fn count(&self, target: &str) -> usize {
+        self.events.count(target)
+    }
+}
+
+impl fmt::Debug for CapturingLogger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CapturingLogger")
+            .field("provider", &self.provider)
+            .field("installed", &self.previous.is_some())
+            .finish()
+    }
+}
+
+impl Drop for CapturingLogger {
+    fn drop(&mut self) {
+        if let Some(previous) = self.previous {
+            let _ = INSTALLED_PROVIDER.compare_exchange(
+                self.provider.handle().raw(),
+                previous,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            );
+        }
+    }
+}
+
+extern "C" fn capture_c_callback(state: *const c_void, data: ByteArray) {
+    if state.is_null() {
+        return;
+    }
+
+    let events = unsafe { &*(state as *const CapturedEvents) };
+    let bytes = data.view().as_slice();
+
+    if let Ok(event) = LogEvent::from_bytes(bytes) {
+        events.push(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proton_sdk_sys::prost::Message;
+
+    fn deliver(events: &CapturedEvents, level: i32, category: &str, message: &str) {
+        let bytes = LogEvent {
+            level,
+            message: message.to_string(),
+            category_name: category.to_string(),
+            exception: None,
+        }
+        .encode_to_vec();
+
+        capture_c_callback(
+            events as *const CapturedEvents as *const c_void,
+            ByteArray::from_slice(&bytes),
+        );
+    }
+
+    #[test]
+    fn captured_events_can_be_queried() {
+        let events = CapturedEvents::default();
+        deliver(&events, 2, "Proton.Drive.Sdk.Upload", "draft created");
+        deliver(&events, 1, "Proton.Drive.Sdk.Upload", "block 3 uploaded");
+        deliver(&events, 4, "Proton.Sdk", "request failed");
+        capture_c_callback(
+            &events as *const CapturedEvents as *const c_void,
+            ByteArray::from_slice(&[0xff, 0xff]),
+        );
+
+        assert_eq!(events.events().len(), 3);
+        assert!(events.contains(Level::Info, "draft created"));
+        assert!(events.contains(Level::Debug, "block 3"));
+        assert!(!events.contains(Level::Info, "block 3"));
+        assert_eq!(events.count("Proton.Drive.Sdk.Upload"), 2);
+
+        events.clear();
+        assert!(events.events().is_empty());
+    }
+}
@@ -0,0 +1,209 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use chrono::Utc;
+use log::{warn, Level};
+use proton_sdk_sys::protobufs::account::LogEvent;
+
+/// How often buffered log lines are flushed to disk
+const FLUSH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Configuration for the rolling SDK log file
+#[derive(Debug, Clone)]
+pub(crate) struct LogFileConfig {
+    pub path: PathBuf,
+    pub max_size_bytes: u64,
+    pub max_files: usize,
+}
+
+/// A size-capped log file, rotated to `<path>.1` .. `<path>.<max_files>`
+struct RollingFile {
+    config: LogFileConfig,
+    writer: BufWriter<File>,
+    written: u64,
+}
+
+impl RollingFile {
+    fn open(config: LogFileConfig) -> io::Result<Self> {
+        if let Some(parent) = config.path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&config.path)?;
+        let written = file.metadata()?.len();
+
+        Ok(Self {
+            config,
+            writer: BufWriter::new(file),
+            written,
+        })
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let len = line.len() as u64 + 1;
+        if self.written > 0 && self.written + len > self.config.max_size_bytes {
+            self.rotate()?;
+        }
+
+        writeln!(self.writer, "{}", line)?;
+        self.written += len;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+
+        let path = &self.config.path;
+        if self.config.max_files == 0 {
+            let file = File::create(path)?;
+            self.writer = BufWriter::new(file);
+            self.written = 0;
+            return Ok(());
+        }
+
+        let _ = fs::remove_file(rotated_path(path, self.config.max_files));
+        for index in (1..self.config.max_files).rev() {
+            let from = rotated_path(path, index);
+            if from.exists() {
+                fs::rename(&from, rotated_path(path, index + 1))?;
+            }
+        }
+        fs::rename(path, rotated_path(path, 1))?;
+
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        self.writer = BufWriter::new(file);
+        self.written = 0;
+        Ok(())
+    }
+}
+
+fn rotated_path(path: &Path, index: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", index));
+    PathBuf::from(name)
+}
+
+/// Formats an event as `<RFC 3339 timestamp> <LEVEL> [<category>] <message>`,
+/// with the exception (if any) on the following lines
+fn format_event(level: Level, event: &LogEvent) -> String {
+    let mut line = format!(
+        "{} {:<5} [{}] {}",
+        Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+        level,
+        event.category_name,
+        event.message
+    );
+
+    if let Some(exception) = event.exception.as_deref().filter(|e| !e.is_empty()) {
+        line.push('\n');
+        line.push_str(exception);
+    }
+
+    line
+}
+
+/// Writes SDK log events to a rolling file, flushing periodically and on drop
+pub(crate) struct LogFileSink {
+    file: Arc<Mutex<RollingFile>>,
+    stop: Option<mpsc::Sender<()>>,
+    flusher: Option<thread::JoinHandle<()>>,
+}
+
+impl LogFileSink {
+    pub fn open(config: LogFileConfig) -> io::Result<Self> {
+        let file = Arc::new(Mutex::new(RollingFile::open(config)?));
+        let (stop, stopped) = mpsc::channel::<()>();
+
+        let flushed = Arc::clone(&file);
+        let flusher = thread::Builder::new()
+            .name("proton-sdk-log-flush".to_string())
+            .spawn(move || {
+                while let Err(mpsc::RecvTimeoutError::Timeout) = stopped.recv_timeout(FLUSH_INTERVAL) {
+                    if let Ok(mut file) = flushed.lock() {
+                        let _ = file.writer.flush();
+                    }
+                }
+            })?;
+
+        Ok(Self {
+            file,
+            stop: Some(stop),
+            flusher: Some(flusher),
+        })
+    }
+
+    pub fn write(&self, level: Level, event: &LogEvent) {
+        let line = format_event(level, event);
+        if let Ok(mut file) = self.file.lock() {
+            if let Err(e) = file.write_line(&line) {
+                // not routed to the SDK target, so this can't loop back into the file
+                warn!("Failed to write SDK log file: {}", e);
+            }
+        }
+    }
+}
+
+impl Drop for LogFileSink {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+        if let Some(flusher) = self.flusher.take() {
+            let _ = flusher.join();
+        }
+
+        if let Ok(mut file) = self.file.lock() {
+            let _ = file.writer.flush();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(message: &str) -> LogEvent {
+        LogEvent {
+            level: 2,
+            message: message.to_string(),
+            category_name: "Proton.Drive.Sdk".to_string(),
+            exception: None,
+        }
+    }
+
+    #[test]
+    fn rotates_and_prunes_old_files() {
+        let dir = std::env::temp_dir().join(format!("proton-sdk-log-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("sdk.log");
+
+        let sink = LogFileSink::open(LogFileConfig {
+            path: path.clone(),
+            max_size_bytes: 200,
+            max_files: 2,
+        })
+        .unwrap();
+        for i in 0..20 {
+            sink.write(Level::Info, &event(&format!("message number {}", i)));
+        }
+        drop(sink);
+
+        assert!(path.exists());
+        assert!(rotated_path(&path, 1).exists());
+        assert!(rotated_path(&path, 2).exists());
+        assert!(!rotated_path(&path, 3).exists());
+        assert!(fs::metadata(&path).unwrap().len() <= 200);
+        assert!(fs::read_to_string(&path).unwrap().contains("message number 19"));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,442 @@
+mod cache;
+mod compare;
+mod path;
+mod stats;
+
+use std::{fmt, time::Duration};
+
+use log::{debug, error};
+use proton_sdk_sys::{
+    data::{AsyncCallbackBuilder, ByteArray},
+    prost::Message,
+    protobufs::{
+        account::{Error as SdkError, ErrorDomain, StringResponse},
+        drive::{node_type, FileNode, FolderNode, NodeIdentity, NodeNameDecryptionRequest, NodeType},
+        SdkErrorKind, ToByteArray,
+    },
+};
+use futures::future::join_all;
+use tokio::sync::Semaphore;
+
+use crate::{cancellation::CancellationToken, drive::DriveClient, errors::SdkErrorDetails};
+
+pub use self::cache::NodeCache;
+pub use self::compare::{compare_local, ChangeState, ComparePolicy, DEFAULT_MTIME_TOLERANCE};
+pub use self::path::RemotePath;
+pub use self::stats::{SubtreeOptions, SubtreeProgressCallback, SubtreeStats, DEFAULT_MAX_IN_FLIGHT};
+pub(crate) use self::path::{NodeLink, NodeLinks, DEFAULT_LINK_CAPACITY};
+
+/// How long a node operation may take before it is cancelled
+pub const DEFAULT_NODE_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, thiserror::Error)]
+pub enum NodeError {
+    #[error("SDK error: {0}")]
+    SdkError(#[from] anyhow::Error),
+
+    #[error("Protobuf error: {0}")]
+    ProtobufError(#[from] proton_sdk_sys::protobufs::ProtoError),
+
+    #[error("Drive error: {0}")]
+    DriveError(#[from] crate::drive::DriveError),
+
+    #[error("Node operation failed with code: {0}")]
+    OperationFailed(i32),
+
+    #[error("Node operation failed: {0}")]
+    Failed(SdkErrorDetails),
+
+    #[error("SDK reported {kind}: {message}")]
+    Remote {
+        kind: String,
+        message: String,
+        primary_code: Option<i64>,
+    },
+
+    #[error("Node operation timed out after {0:?}")]
+    Timeout(Duration),
+
+    #[error("Callback channel closed")]
+    CallbackClosed,
+
+    #[error("Drive client handle is null")]
+    NullHandle,
+
+    #[error("Node identity is missing its {0}")]
+    IncompleteIdentity(&'static str),
+
+    #[error("Node {0} hasn't been seen in any folder listing yet")]
+    UnknownNode(String),
+
+    #[error("Parent links of node {0} form a cycle")]
+    PathCycle(String),
+
+    #[error("Local file error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+impl From<SdkError> for NodeError {
+    fn from(error: SdkError) -> Self {
+        NodeError::Remote {
+            kind: error.r#type,
+            message: error.message,
+            primary_code: error.primary_code,
+        }
+    }
+}
+
+impl NodeError {
+    /// Classifies the error, [`SdkErrorKind::Unknown`] for errors raised on the Rust side
+    pub fn kind(&self) -> SdkErrorKind {
+        match self {
+            NodeError::Failed(details) => details.kind(),
+            NodeError::Remote { primary_code, .. } => {
+                SdkErrorKind::classify(ErrorDomain::Undefined, *primary_code)
+            }
+            _ => SdkErrorKind::Unknown,
+        }
+    }
+}
+
+/// Materializes complete node identities from listed nodes
+///
+/// Listings often leave the share and volume ids out of a child's identity, as they
+/// are the same as the parent's. The link id always has to come from the node itself.
+pub trait NodeIdentityExt {
+    /// Returns the node's own identity, as listed
+    fn node_identity(&self) -> Option<&NodeIdentity>;
+
+    /// Returns the node's identity if it has node, share and volume ids
+    fn identity(&self) -> Result<NodeIdentity, NodeError> {
+        let identity = self
+            .node_identity()
+            .ok_or(NodeError::IncompleteIdentity("node id"))?;
+        complete_identity(identity, None)
+    }
+
+    /// Returns the node's identity with missing share and volume ids taken from
+    /// `context`, usually the identity of the parent folder
+    fn full_identity(&self, context: &NodeIdentity) -> Result<NodeIdentity, NodeError> {
+        let identity = self
+            .node_identity()
+            .ok_or(NodeError::IncompleteIdentity("node id"))?;
+        complete_identity(identity, Some(context))
+    }
+}
+
+impl NodeIdentityExt for FileNode {
+    fn node_identity(&self) -> Option<&NodeIdentity> {
+        self.node_identity.as_ref()
+    }
+}
+
+impl NodeIdentityExt for FolderNode {
+    fn node_identity(&self) -> Option<&NodeIdentity> {
+        self.node_identity.as_ref()
+    }
+}
+
+impl NodeIdentityExt for NodeType {
+    fn node_identity(&self) -> Option<&NodeIdentity> {
+        match self.node_type.as_ref()? {
+            node_type::NodeType::FileNode(file) => file.node_identity.as_ref(),
+            node_type::NodeType::FolderNode(folder) => folder.node_identity.as_ref(),
+        }
+    }
+}
+
+/// Borrowing accessors for the variants of a `NodeType`
+pub trait NodeTypeExt {
+    /// Returns the file node, if this is a file
+    fn as_file(&self) -> Option<&FileNode>;
+
+    /// Returns the folder node, if this is a folder
+    fn as_folder(&self) -> Option<&FolderNode>;
+
+    /// Checks if this is a file
+    fn is_file(&self) -> bool {
+        self.as_file().is_some()
+    }
+
+    /// Checks if this is a folder
+    fn is_folder(&self) -> bool {
+        self.as_folder().is_some()
+    }
+}
+
+impl NodeTypeExt for NodeType {
+    fn as_file(&self) -> Option<&FileNode> {
+        match self.node_type.as_ref()? {
+            node_type::NodeType::FileNode(file) => Some(file),
+            _ => None,
+        }
+    }
+
+    fn as_folder(&self) -> Option<&FolderNode> {
+        match self.node_type.as_ref()? {
+            node_type::NodeType::FolderNode(folder) => Some(folder),
+            _ => None,
+        }
+    }
+}
+
+fn complete_identity(
+    identity: &NodeIdentity,
+    context: Option<&NodeIdentity>,
+) -> Result<NodeIdentity, NodeError> {
+    // a node id taken from the context would name the parent
+    if identity.node_id.as_ref().is_none_or(|id| id.value.is_empty()) {
+        return Err(NodeError::IncompleteIdentity("node id"));
+    }
+
+    let identity = match context {
+        Some(context) => identity.with_fallback(context),
+        None => identity.clone(),
+    };
+    if identity.is_complete() {
+        return Ok(identity);
+    }
+    if identity.share_id.as_ref().is_none_or(|id| id.value.is_empty()) {
+        Err(NodeError::IncompleteIdentity("share id"))
+    } else {
+        Err(NodeError::IncompleteIdentity("volume id"))
+    }
+}
+
+/// Node level operations of a Drive client
+pub struct NodeOperations<'a> {
+    client: &'a DriveClient,
+    timeout: Duration,
+}
+
+impl<'a> NodeOperations<'a> {
+    /// Creates node operations for the given Drive client
+    pub fn new(client: &'a DriveClient) -> Self {
+        Self {
+            client,
+            timeout: DEFAULT_NODE_TIMEOUT,
+        }
+    }
+
+    /// Sets how long each operation may take before it is cancelled
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Returns the Drive client the operations run against
+    pub fn client(&self) -> &DriveClient {
+        self.client
+    }
+
+    /// Decrypts the armored name of a node
+    ///
+    /// Each call gets its own cancellation token, which is cancelled if the SDK
+    /// doesn't answer within the timeout.
+    ///
+    /// # Arguments
+    /// * `request` - The node identity and its armored encrypted name
+    ///
+    /// # Returns
+    /// The decrypted name
+    ///
+    /// # Example
+    /// ```no_run
+    /// # use proton_sdk_rs::{drive::DriveClient, nodes::{NodeError, NodeOperations}, NodeIdentity, NodeNameDecryptionRequest};
+    /// # async fn example(client: &DriveClient, node_identity: NodeIdentity, armored_name: String) -> Result<(), NodeError> {
+    /// // `node_identity` and `armored_name` come from a volume event of a renamed node
+    /// let name = NodeOperations::new(client)
+    ///     .decrypt_armored_name(NodeNameDecryptionRequest {
+    ///         node_identity: Some(node_identity),
+    ///         armored_encrypted_name: armored_name,
+    ///         signature_email_address: None,
+    ///     })
+    ///     .await?;
+    /// println!("Node renamed to {}", name);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn decrypt_armored_name(
+        &self,
+        request: NodeNameDecryptionRequest,
+    ) -> Result<String, NodeError> {
+        if self.client.handle().is_null() {
+            return Err(NodeError::NullHandle);
+        }
+
+        let proto_buf = request.to_proto_buffer()?;
+        let api = self.client.api();
+        // dropped after the guard, which cancels it
+        let token = CancellationToken::with_api(api.clone())?;
+
+        let (async_callback, guard) = AsyncCallbackBuilder::new()
+            .on_success(|response: ByteArray| {
+                StringResponse::decode(response.view().as_slice())
+                    .map(|response| response.value)
+                    .map_err(|e| NodeError::ProtobufError(e.into()))
+            })
+            .on_failure(|error_data: ByteArray| {
+                let details = SdkErrorDetails::decode(&error_data.view());
+                error!("Node name decryption failed: {}", details);
+                Err(NodeError::Failed(details))
+            })
+            .cancellation_token(token.handle())
+            .cancel_on_drop(api.clone())
+            .build();
+
+        let code = guard.call(|| api.node_decrypt_armored_name(self.client.handle(), proto_buf.as_byte_array(), async_callback))?;
+        if code != 0 {
+            return Err(NodeError::OperationFailed(code));
+        }
+
+        // dropping the guard on a timeout cancels the call
+        match tokio::time::timeout(self.timeout, guard.completion()).await {
+            Ok(result) => result,
+            Err(_) => {
+                debug!("Node name decryption timed out, cancelling");
+                Err(NodeError::Timeout(self.timeout))
+            }
+        }
+    }
+
+    /// Resolves a node to its path inside its share, e.g. `/Photos/2024/img.jpg`
+    ///
+    /// The native SDK has no export to fetch a single node, so the walk up the
+    /// parent links uses the names and parents the client remembers from
+    /// `get_folder_children` and `get_shares` calls, preferring the client's
+    /// [`NodeCache`] when it has one. Resolving a node whose ancestors were never
+    /// listed fails with [`NodeError::UnknownNode`].
+    pub async fn resolve_path(&self, node: &NodeIdentity) -> Result<RemotePath, NodeError> {
+        let node_id = node
+            .node_id
+            .as_ref()
+            .filter(|id| !id.value.is_empty())
+            .ok_or(NodeError::IncompleteIdentity("node id"))?;
+
+        match self.client.cache() {
+            Some(cache) => cache
+                .path_of(&node_id.value)
+                .or_else(|_| self.client.links().resolve(&node_id.value)),
+            None => self.client.links().resolve(&node_id.value),
+        }
+    }
+
+    /// Decrypts many armored names concurrently, at most `max_in_flight` at a time
+    ///
+    /// The native SDK has no batched export for this, so each name is its own
+    /// FFI call. A failing name doesn't abort the batch, its slot holds the error.
+    ///
+    /// # Returns
+    /// One result per request, in the same order as `requests`
+    pub async fn decrypt_names(
+        &self,
+        requests: Vec<NodeNameDecryptionRequest>,
+        max_in_flight: usize,
+    ) -> Vec<Result<String, NodeError>> {
+        let semaphore = Semaphore::new(max_in_flight.max(1));
+
+        let calls = requests.into_iter().map(|request| {
+            let semaphore = &semaphore;
+            async move {
+                let _permit = semaphore
+                    .acquire()
+                    .await
+                    .map_err(|_| NodeError::CallbackClosed)?;
+                self.decrypt_armored_name(request).await
+            }
+        });
+
+        join_all(calls).await
+    }
+}
+
+impl fmt::Debug for NodeOperations<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NodeOperations")
+            .field("client", &self.client.handle())
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use proton_sdk_sys::{
+        api::{MockApi, Reply},
+        protobufs::drive::{LinkId, ShareId, VolumeId},
+    };
+
+    use crate::drive::tests::mock_client;
+
+    fn identity(node: &str, share: &str, volume: &str) -> NodeIdentity {
+        NodeIdentity {
+            node_id: Some(LinkId { value: node.to_string() }),
+            share_id: Some(ShareId { value: share.to_string() }),
+            volume_id: Some(VolumeId { value: volume.to_string() }),
+        }
+    }
+
+    #[test]
+    fn full_identity_fills_share_and_volume_from_context() {
+        let parent = identity("parent", "share", "volume");
+        let folder = FolderNode {
+            node_identity: Some(NodeIdentity {
+                node_id: Some(LinkId { value: "child".to_string() }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        assert_eq!(folder.full_identity(&parent).unwrap(), identity("child", "share", "volume"));
+        assert!(matches!(folder.identity(), Err(NodeError::IncompleteIdentity("share id"))));
+
+        let node = NodeType {
+            node_type: Some(node_type::NodeType::FolderNode(folder)),
+        };
+        assert_eq!(node.full_identity(&parent).unwrap(), identity("child", "share", "volume"));
+    }
+
+    #[test]
+    fn full_identity_never_borrows_the_node_id_from_context() {
+        let parent = identity("parent", "share", "volume");
+        let file = FileNode::default();
+
+        assert!(matches!(file.full_identity(&parent), Err(NodeError::IncompleteIdentity("node id"))));
+    }
+
+    #[tokio::test]
+    async fn names_are_decrypted_or_the_failure_reported() {
+        let mock = Arc::new(MockApi::new());
+        let client = mock_client(&mock).await;
+        let failure = SdkError {
+            r#type: "CryptographicException".to_string(),
+            message: "Session key decryption failed".to_string(),
+            primary_code: Some(2501),
+            ..Default::default()
+        };
+        let name = StringResponse { value: "beach.jpg".to_string() };
+        mock.reply("node_decrypt_armored_name", Reply::Success(name.encode_to_vec()))
+            .reply("node_decrypt_armored_name", Reply::Failure(failure.encode_to_vec()))
+            .reply("node_decrypt_armored_name", Reply::Pending);
+        let operations = NodeOperations::new(&client).with_timeout(Duration::from_millis(10));
+        let request = || NodeNameDecryptionRequest {
+            node_identity: Some(identity("beach", "share", "volume")),
+            ..Default::default()
+        };
+
+        assert_eq!(operations.decrypt_armored_name(request()).await.unwrap(), "beach.jpg");
+        match operations.decrypt_armored_name(request()).await {
+            Err(error @ NodeError::Failed(_)) => assert_eq!(error.kind(), SdkErrorKind::NotFound),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(matches!(operations.decrypt_armored_name(request()).await, Err(NodeError::Timeout(_))));
+
+        // only the call that timed out was cancelled, with a token of its own
+        let cancelled = mock.calls_to("cancellation_token_source_cancel");
+        assert_eq!(cancelled.len(), 1);
+        assert_ne!(cancelled[0].handle, client.session().cancellation_token().handle().raw());
+    }
+}
@@ -0,0 +1,291 @@
+use std::{
+    collections::{HashMap, HashSet},
+    num::NonZeroUsize,
+    sync::RwLock,
+};
+
+use lru::LruCache;
+use proton_sdk_sys::protobufs::drive::{node_type, NodeType, VolumeEventType};
+
+use super::{path::walk_to_root, NodeError, NodeIdentityExt, NodeLink, RemotePath};
+
+struct CachedNode {
+    node: NodeType,
+    parent_id: Option<String>,
+}
+
+struct CacheInner {
+    nodes: LruCache<String, CachedNode>,
+    // folder id -> ids of its children, present once the folder was listed
+    children: HashMap<String, HashSet<String>>,
+    roots: HashSet<String>,
+}
+
+impl CacheInner {
+    /// Removes a node, everything cached below it and its parent's listing
+    fn remove_subtree(&mut self, node_id: &str) {
+        if let Some(parent_id) = self.nodes.peek(node_id).and_then(|node| node.parent_id.clone()) {
+            self.children.remove(&parent_id);
+        }
+
+        let mut pending = vec![node_id.to_string()];
+        while let Some(id) = pending.pop() {
+            self.nodes.pop(&id);
+            if let Some(children) = self.children.remove(&id) {
+                pending.extend(children);
+            }
+        }
+    }
+}
+
+/// An in-memory cache of listed nodes keyed by node id
+///
+/// Bounded by entry count, evicting the least recently used node. A folder's
+/// listing is only served while none of its children have been evicted.
+pub struct NodeCache {
+    inner: RwLock<CacheInner>,
+}
+
+impl NodeCache {
+    /// Creates a cache holding at most `capacity` nodes
+    pub fn new(capacity: usize) -> Self {
+        let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN);
+        Self {
+            inner: RwLock::new(CacheInner {
+                nodes: LruCache::new(capacity),
+                children: HashMap::new(),
+                roots: HashSet::new(),
+            }),
+        }
+    }
+
+    /// Returns the maximum number of cached nodes
+    pub fn capacity(&self) -> usize {
+        self.inner.read().map(|inner| inner.nodes.cap().get()).unwrap_or(0)
+    }
+
+    /// Returns the number of cached nodes
+    pub fn len(&self) -> usize {
+        self.inner.read().map(|inner| inner.nodes.len()).unwrap_or(0)
+    }
+
+    /// Checks if no nodes are cached
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Caches a node, linking it to `parent_id` when the node doesn't name its parent
+    pub fn insert(&self, node: NodeType, parent_id: Option<&str>) {
+        let Some(node_id) = node_id_of(&node) else {
+            return;
+        };
+        let parent_id = parent_id_of(&node).or_else(|| parent_id.map(str::to_string));
+
+        if let Ok(mut inner) = self.inner.write() {
+            if let Some(parent_id) = &parent_id {
+                if let Some(siblings) = inner.children.get_mut(parent_id) {
+                    siblings.insert(node_id.clone());
+                }
+            }
+            inner.nodes.put(node_id, CachedNode { node, parent_id });
+        }
+    }
+
+    /// Caches the complete listing of a folder
+    pub fn insert_children(&self, folder_id: &str, children: &[NodeType]) {
+        let ids = children.iter().filter_map(node_id_of).collect();
+        for child in children {
+            self.insert(child.clone(), Some(folder_id));
+        }
+
+        if let Ok(mut inner) = self.inner.write() {
+            inner.children.insert(folder_id.to_string(), ids);
+        }
+    }
+
+    /// Marks a node as the root of a share
+    pub fn insert_root(&self, node_id: &str) {
+        if let Ok(mut inner) = self.inner.write() {
+            inner.roots.insert(node_id.to_string());
+        }
+    }
+
+    /// Returns a cached node
+    pub fn get(&self, node_id: &str) -> Option<NodeType> {
+        let mut inner = self.inner.write().ok()?;
+        inner.nodes.get(node_id).map(|cached| cached.node.clone())
+    }
+
+    /// Returns the cached listing of a folder, if it was listed and none of its
+    /// children have been evicted since
+    pub fn children_of(&self, node_id: &str) -> Option<Vec<NodeType>> {
+        let inner = self.inner.read().ok()?;
+        inner
+            .children
+            .get(node_id)?
+            .iter()
+            .map(|id| inner.nodes.peek(id).map(|cached| cached.node.clone()))
+            .collect()
+    }
+
+    /// Resolves a cached node to its path inside its share
+    pub fn path_of(&self, node_id: &str) -> Result<RemotePath, NodeError> {
+        let inner = self
+            .inner
+            .read()
+            .map_err(|_| NodeError::UnknownNode(node_id.to_string()))?;
+
+        walk_to_root(
+            node_id,
+            |id| inner.roots.contains(id),
+            |id| {
+                let cached = inner.nodes.peek(id)?;
+                Some(NodeLink {
+                    name: name_of(&cached.node).to_string(),
+                    parent_id: cached.parent_id.clone(),
+                })
+            },
+        )
+    }
+
+    /// Drops a node and everything cached below it
+    pub fn invalidate(&self, node_id: &str) {
+        if let Ok(mut inner) = self.inner.write() {
+            inner.remove_subtree(node_id);
+        }
+    }
+
+    /// Applies a volume event to the cache
+    ///
+    /// Deletes and metadata updates (which include moves and renames) clear the
+    /// node's subtree and its parent's listing. Content updates only drop the node.
+    /// Creations are left alone, as the event doesn't say which folder gained a child.
+    pub fn apply_event(&self, event_type: VolumeEventType, node_id: &str) {
+        let Ok(mut inner) = self.inner.write() else {
+            return;
+        };
+
+        match event_type {
+            VolumeEventType::Delete | VolumeEventType::UpdateMetadata => inner.remove_subtree(node_id),
+            VolumeEventType::Update => {
+                inner.nodes.pop(node_id);
+            }
+            VolumeEventType::Create => {}
+        }
+    }
+
+    /// Drops every cached node
+    pub fn clear(&self) {
+        if let Ok(mut inner) = self.inner.write() {
+            inner.nodes.clear();
+            inner.children.clear();
+        }
+    }
+}
+
+impl std::fmt::Debug for NodeCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NodeCache")
+            .field("len", &self.len())
+            .field("capacity", &self.capacity())
+            .finish()
+    }
+}
+
+fn node_id_of(node: &NodeType) -> Option<String> {
+    node.node_identity()?
+        .node_id
+        .as_ref()
+        .map(|id| id.value.clone())
+}
+
+fn parent_id_of(node: &NodeType) -> Option<String> {
+    let parent_id = match node.node_type.as_ref()? {
+        node_type::NodeType::FileNode(file) => file.parent_id.as_ref(),
+        node_type::NodeType::FolderNode(folder) => folder.parent_id.as_ref(),
+    };
+    parent_id.map(|id| id.value.clone())
+}
+
+fn name_of(node: &NodeType) -> &str {
+    match node.node_type.as_ref() {
+        Some(node_type::NodeType::FileNode(file)) => &file.name,
+        Some(node_type::NodeType::FolderNode(folder)) => &folder.name,
+        None => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proton_sdk_sys::protobufs::drive::{FileNode, FolderNode, LinkId, NodeIdentity};
+
+    fn identity(id: &str) -> Option<NodeIdentity> {
+        Some(NodeIdentity {
+            node_id: Some(LinkId { value: id.to_string() }),
+            ..Default::default()
+        })
+    }
+
+    fn folder(id: &str, name: &str) -> NodeType {
+        NodeType {
+            node_type: Some(node_type::NodeType::FolderNode(FolderNode {
+                node_identity: identity(id),
+                name: name.to_string(),
+                ..Default::default()
+            })),
+        }
+    }
+
+    fn file(id: &str, name: &str) -> NodeType {
+        NodeType {
+            node_type: Some(node_type::NodeType::FileNode(FileNode {
+                node_identity: identity(id),
+                name: name.to_string(),
+                ..Default::default()
+            })),
+        }
+    }
+
+    #[test]
+    fn evicts_least_recently_used_nodes() {
+        let cache = NodeCache::new(2);
+        cache.insert(file("a", "a.txt"), None);
+        cache.insert(file("b", "b.txt"), None);
+        assert!(cache.get("a").is_some());
+
+        cache.insert(file("c", "c.txt"), None);
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("b").is_none());
+    }
+
+    #[test]
+    fn listings_are_dropped_once_a_child_is_evicted() {
+        let cache = NodeCache::new(2);
+        cache.insert_children("root", &[file("a", "a.txt"), file("b", "b.txt")]);
+        assert_eq!(cache.children_of("root").unwrap().len(), 2);
+
+        cache.insert(file("c", "c.txt"), None);
+        assert!(cache.children_of("root").is_none());
+    }
+
+    #[test]
+    fn volume_events_clear_affected_subtrees() {
+        let cache = NodeCache::new(16);
+        cache.insert_root("root");
+        cache.insert_children("root", &[folder("photos", "Photos"), file("notes", "notes.txt")]);
+        cache.insert_children("photos", &[folder("2024", "2024")]);
+        cache.insert_children("2024", &[file("img", "img.jpg")]);
+        assert_eq!(cache.path_of("img").unwrap().to_string(), "/Photos/2024/img.jpg");
+
+        cache.apply_event(VolumeEventType::UpdateMetadata, "photos");
+        assert!(cache.get("photos").is_none());
+        assert!(cache.get("img").is_none());
+        assert!(cache.children_of("root").is_none());
+        assert!(cache.get("notes").is_some());
+
+        cache.apply_event(VolumeEventType::Update, "notes");
+        assert!(cache.get("notes").is_none());
+    }
+}
@@ -0,0 +1,344 @@
+use std::{
+    fs::File,
+    io::{self, Read},
+    path::Path,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use proton_sdk_sys::{prost::bytes::Bytes, protobufs::drive::FileNode};
+use sha2::{Digest, Sha256};
+
+use super::NodeError;
+
+/// Default slack when comparing modification times, covering FAT's 2 second resolution
+pub const DEFAULT_MTIME_TOLERANCE: Duration = Duration::from_secs(2);
+
+/// Largest UTC offset a timezone-naive filesystem can be off by
+const MAX_TIMEZONE_OFFSET_SECS: i64 = 14 * 60 * 60;
+/// Granularity of real world UTC offsets
+const TIMEZONE_OFFSET_STEP_SECS: i64 = 15 * 60;
+
+/// How a local file is compared against a remote `FileNode`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComparePolicy {
+    /// Only compares sizes, so a change can't be attributed to either side
+    SizeOnly,
+    /// Compares sizes, then modification times to whole seconds within `tolerance`
+    SizeAndMtime { tolerance: Duration },
+    /// Compares a SHA-256 of the local file against the revision's stored digests,
+    /// falling back to sizes and modification times when it has none
+    Hash { tolerance: Duration },
+}
+
+impl Default for ComparePolicy {
+    fn default() -> Self {
+        ComparePolicy::SizeAndMtime {
+            tolerance: DEFAULT_MTIME_TOLERANCE,
+        }
+    }
+}
+
+/// How a local file relates to its remote counterpart
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeState {
+    Unchanged,
+    LocalNewer,
+    RemoteNewer,
+    /// Both differ and neither side is clearly newer
+    Conflict,
+    LocalMissing,
+}
+
+/// What is known about the local file
+struct LocalFile {
+    size: u64,
+    mtime: i64,
+    digest: Option<[u8; 32]>,
+}
+
+/// What is known about the remote file
+struct RemoteFile<'a> {
+    size: Option<u64>,
+    mtime: Option<i64>,
+    digests: &'a [Bytes],
+}
+
+impl<'a> RemoteFile<'a> {
+    fn of(node: &'a FileNode) -> Self {
+        let revision = node.active_revision.as_ref();
+        Self {
+            size: revision
+                .and_then(|revision| revision.size)
+                .map(|size| size.max(0) as u64),
+            mtime: revision
+                .map(|revision| revision.creation_time)
+                .filter(|time| *time > 0),
+            digests: revision
+                .map(|revision| revision.samples_sha256_digests.as_slice())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Works out whether `path` changed relative to the remote `node`
+///
+/// Modification times are compared to whole seconds, as the remote only keeps
+/// seconds. When sizes match, time differences that are a whole number of UTC
+/// offset steps are ignored, so files on timezone-naive filesystems (FAT, exFAT)
+/// don't all look modified.
+pub fn compare_local(
+    path: &Path,
+    node: &FileNode,
+    policy: ComparePolicy,
+) -> Result<ChangeState, NodeError> {
+    let metadata = match std::fs::metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(ChangeState::LocalMissing),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mtime = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|since_epoch| since_epoch.as_secs() as i64)
+        .unwrap_or(0);
+
+    let remote = RemoteFile::of(node);
+    let digest = match policy {
+        ComparePolicy::Hash { .. } if !remote.digests.is_empty() => Some(sha256_file(path)?),
+        _ => None,
+    };
+
+    let local = LocalFile {
+        size: metadata.len(),
+        mtime,
+        digest,
+    };
+    Ok(compare(&local, &remote, policy))
+}
+
+fn compare(local: &LocalFile, remote: &RemoteFile<'_>, policy: ComparePolicy) -> ChangeState {
+    let same_size = remote.size == Some(local.size);
+
+    match policy {
+        ComparePolicy::SizeOnly => {
+            if same_size {
+                ChangeState::Unchanged
+            } else {
+                ChangeState::Conflict
+            }
+        }
+        ComparePolicy::SizeAndMtime { tolerance } => {
+            compare_times(local, remote, same_size, tolerance)
+        }
+        ComparePolicy::Hash { tolerance } => match local.digest {
+            Some(digest)
+                if remote
+                    .digests
+                    .iter()
+                    .any(|remote| remote.as_ref() == digest) =>
+            {
+                ChangeState::Unchanged
+            }
+            Some(_) => match compare_times(local, remote, false, tolerance) {
+                // same time but different content, nobody can tell which one wins
+                ChangeState::Unchanged => ChangeState::Conflict,
+                state => state,
+            },
+            None => compare_times(local, remote, same_size, tolerance),
+        },
+    }
+}
+
+fn compare_times(
+    local: &LocalFile,
+    remote: &RemoteFile<'_>,
+    same_size: bool,
+    tolerance: Duration,
+) -> ChangeState {
+    let Some(remote_mtime) = remote.mtime else {
+        return if same_size {
+            ChangeState::Unchanged
+        } else {
+            ChangeState::Conflict
+        };
+    };
+
+    let tolerance = tolerance.as_secs() as i64;
+    let difference = local.mtime - remote_mtime;
+
+    if difference.abs() <= tolerance {
+        return if same_size {
+            ChangeState::Unchanged
+        } else {
+            ChangeState::Conflict
+        };
+    }
+
+    if same_size && is_timezone_offset(difference, tolerance) {
+        return ChangeState::Unchanged;
+    }
+
+    if difference > 0 {
+        ChangeState::LocalNewer
+    } else {
+        ChangeState::RemoteNewer
+    }
+}
+
+/// Checks if `difference` is a whole UTC offset, give or take `tolerance`
+fn is_timezone_offset(difference: i64, tolerance: i64) -> bool {
+    let difference = difference.abs();
+    if difference > MAX_TIMEZONE_OFFSET_SECS + tolerance {
+        return false;
+    }
+
+    let remainder = difference % TIMEZONE_OFFSET_STEP_SECS;
+    remainder <= tolerance || TIMEZONE_OFFSET_STEP_SECS - remainder <= tolerance
+}
+
+fn sha256_file(path: &Path) -> io::Result<[u8; 32]> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = [0u8; 64 * 1024];
+
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+
+    Ok(hasher.finalize().into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proton_sdk_sys::protobufs::drive::Revision;
+    use std::{fs, time::SystemTime};
+
+    const REMOTE_MTIME: i64 = 1_700_000_000;
+
+    fn remote(size: i64, digests: Vec<Vec<u8>>) -> FileNode {
+        FileNode {
+            active_revision: Some(Revision {
+                size: Some(size),
+                creation_time: REMOTE_MTIME,
+                samples_sha256_digests: digests.into_iter().map(Bytes::from).collect(),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn local_file(name: &str, contents: &[u8], mtime: f64) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("proton-sdk-compare-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        fs::write(&path, contents).unwrap();
+
+        let modified = UNIX_EPOCH + Duration::from_secs_f64(mtime);
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        path
+    }
+
+    #[test]
+    fn sub_second_mtimes_are_truncated() {
+        let path = local_file("truncated.txt", b"hello", REMOTE_MTIME as f64 + 0.999);
+        assert_eq!(
+            compare_local(
+                &path,
+                &remote(5, vec![]),
+                ComparePolicy::SizeAndMtime {
+                    tolerance: Duration::ZERO
+                }
+            )
+            .unwrap(),
+            ChangeState::Unchanged
+        );
+    }
+
+    #[test]
+    fn newer_side_is_detected_outside_the_tolerance() {
+        let path = local_file("newer.txt", b"hello world", REMOTE_MTIME as f64 + 60.0);
+        assert_eq!(
+            compare_local(&path, &remote(5, vec![]), ComparePolicy::default()).unwrap(),
+            ChangeState::LocalNewer
+        );
+
+        let path = local_file("older.txt", b"hello world", REMOTE_MTIME as f64 - 60.0);
+        assert_eq!(
+            compare_local(&path, &remote(5, vec![]), ComparePolicy::default()).unwrap(),
+            ChangeState::RemoteNewer
+        );
+    }
+
+    #[test]
+    fn whole_hour_offsets_are_ignored_for_timezone_naive_filesystems() {
+        let path = local_file(
+            "fat.txt",
+            b"hello",
+            REMOTE_MTIME as f64 - 5.0 * 3600.0 + 1.0,
+        );
+        assert_eq!(
+            compare_local(&path, &remote(5, vec![]), ComparePolicy::default()).unwrap(),
+            ChangeState::Unchanged
+        );
+
+        let path = local_file("fat-changed.txt", b"hello!", REMOTE_MTIME as f64 + 3600.0);
+        assert_eq!(
+            compare_local(&path, &remote(5, vec![]), ComparePolicy::default()).unwrap(),
+            ChangeState::LocalNewer
+        );
+    }
+
+    #[test]
+    fn zero_byte_files_compare_by_hash_and_size() {
+        let empty_digest = Sha256::digest([]).to_vec();
+        let path = local_file("empty.txt", b"", REMOTE_MTIME as f64 + 600.0);
+
+        assert_eq!(
+            compare_local(
+                &path,
+                &remote(0, vec![empty_digest]),
+                ComparePolicy::Hash {
+                    tolerance: DEFAULT_MTIME_TOLERANCE
+                }
+            )
+            .unwrap(),
+            ChangeState::Unchanged
+        );
+        assert_eq!(
+            compare_local(&path, &remote(0, vec![]), ComparePolicy::SizeOnly).unwrap(),
+            ChangeState::Unchanged
+        );
+        assert_eq!(
+            compare_local(
+                &path,
+                &remote(0, vec![vec![0; 32]]),
+                ComparePolicy::Hash {
+                    tolerance: DEFAULT_MTIME_TOLERANCE
+                }
+            )
+            .unwrap(),
+            ChangeState::LocalNewer
+        );
+    }
+
+    #[test]
+    fn missing_local_files_are_reported() {
+        let path = std::env::temp_dir().join(format!("proton-sdk-missing-{:?}", SystemTime::now()));
+        assert_eq!(
+            compare_local(&path, &remote(5, vec![]), ComparePolicy::default()).unwrap(),
+            ChangeState::LocalMissing
+        );
+    }
+}
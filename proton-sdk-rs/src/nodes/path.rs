@@ -0,0 +1,233 @@
+use std::{
+    collections::HashSet,
+    fmt,
+    num::NonZeroUsize,
+    str::FromStr,
+    sync::Mutex,
+};
+
+use lru::LruCache;
+
+use super::NodeError;
+
+/// Number of node links remembered per client for path resolution
+pub(crate) const DEFAULT_LINK_CAPACITY: usize = 4096;
+
+/// Guards against absurdly deep (or corrupted) parent chains
+const MAX_PATH_DEPTH: usize = 1024;
+
+/// An absolute path inside a share, e.g. `/Photos/2024/img.jpg`
+///
+/// Names are stored as-is. When displayed, `%` and `/` inside a name are escaped as
+/// `%25` and `%2F` so every `/` in the text form is a separator.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct RemotePath {
+    segments: Vec<String>,
+}
+
+impl RemotePath {
+    /// The root of the share, `/`
+    pub fn root() -> Self {
+        Self::default()
+    }
+
+    /// Creates a path from unescaped names, outermost first
+    pub fn from_segments<I, S>(segments: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            segments: segments.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Checks if this is the root of the share
+    pub fn is_root(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    /// Returns the unescaped names making up the path, outermost first
+    pub fn segments(&self) -> &[String] {
+        &self.segments
+    }
+
+    /// Returns the last name of the path, `None` for the root
+    pub fn name(&self) -> Option<&str> {
+        self.segments.last().map(String::as_str)
+    }
+
+    /// Returns the parent path, `None` for the root
+    pub fn parent(&self) -> Option<RemotePath> {
+        if self.is_root() {
+            return None;
+        }
+        Some(Self {
+            segments: self.segments[..self.segments.len() - 1].to_vec(),
+        })
+    }
+
+    /// Returns a new path with `name` appended
+    pub fn join(&self, name: impl Into<String>) -> RemotePath {
+        let mut path = self.clone();
+        path.segments.push(name.into());
+        path
+    }
+}
+
+impl fmt::Display for RemotePath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_root() {
+            return f.write_str("/");
+        }
+        for segment in &self.segments {
+            write!(f, "/{}", segment.replace('%', "%25").replace('/', "%2F"))?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for RemotePath {
+    type Err = std::convert::Infallible;
+
+    /// Parses a displayed path, accepting `\` as a separator and ignoring empty
+    /// and `.` segments
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let segments = s
+            .split(['/', '\\'])
+            .filter(|segment| !segment.is_empty() && *segment != ".")
+            .map(|segment| segment.replace("%2F", "/").replace("%2f", "/").replace("%25", "%"))
+            .collect();
+        Ok(Self { segments })
+    }
+}
+
+/// The name and parent of a node, as seen in a listing
+#[derive(Debug, Clone)]
+pub(crate) struct NodeLink {
+    pub name: String,
+    pub parent_id: Option<String>,
+}
+
+/// Remembers node links and share roots seen by a client
+pub(crate) struct NodeLinks {
+    links: Mutex<LruCache<String, NodeLink>>,
+    roots: Mutex<HashSet<String>>,
+}
+
+impl NodeLinks {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN);
+        Self {
+            links: Mutex::new(LruCache::new(capacity)),
+            roots: Mutex::new(HashSet::new()),
+        }
+    }
+
+    pub fn record(&self, node_id: String, link: NodeLink) {
+        if let Ok(mut links) = self.links.lock() {
+            links.put(node_id, link);
+        }
+    }
+
+    pub fn record_root(&self, node_id: String) {
+        if let Ok(mut roots) = self.roots.lock() {
+            roots.insert(node_id);
+        }
+    }
+
+    fn is_root(&self, node_id: &str) -> bool {
+        self.roots
+            .lock()
+            .map(|roots| roots.contains(node_id))
+            .unwrap_or(false)
+    }
+
+    fn get(&self, node_id: &str) -> Option<NodeLink> {
+        self.links.lock().ok()?.get(node_id).cloned()
+    }
+
+    /// Walks the parent links of `node_id` up to a share root
+    pub fn resolve(&self, node_id: &str) -> Result<RemotePath, NodeError> {
+        walk_to_root(node_id, |id| self.is_root(id), |id| self.get(id))
+    }
+}
+
+/// Builds the path of `node_id` by following parent links until `is_root` matches
+/// or a node without a parent is reached
+pub(crate) fn walk_to_root(
+    node_id: &str,
+    is_root: impl Fn(&str) -> bool,
+    lookup: impl Fn(&str) -> Option<NodeLink>,
+) -> Result<RemotePath, NodeError> {
+    let mut names = Vec::new();
+    let mut visited = HashSet::new();
+    let mut current = node_id.to_string();
+
+    loop {
+        if is_root(&current) {
+            break;
+        }
+        if !visited.insert(current.clone()) || visited.len() > MAX_PATH_DEPTH {
+            return Err(NodeError::PathCycle(current));
+        }
+
+        let link = lookup(&current).ok_or_else(|| NodeError::UnknownNode(current.clone()))?;
+
+        match link.parent_id {
+            Some(parent_id) => {
+                names.push(link.name);
+                current = parent_id;
+            }
+            // only a share root has no parent
+            None => break,
+        }
+    }
+
+    names.reverse();
+    Ok(RemotePath::from_segments(names))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(name: &str, parent_id: Option<&str>) -> NodeLink {
+        NodeLink {
+            name: name.to_string(),
+            parent_id: parent_id.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn remote_paths_escape_slashes_in_names() {
+        let path = RemotePath::from_segments(["Photos", "AC/DC 100%"]);
+        assert_eq!(path.to_string(), "/Photos/AC%2FDC 100%25");
+        assert_eq!(path.to_string().parse::<RemotePath>().unwrap(), path);
+        assert_eq!("\\Photos//2024/./".parse::<RemotePath>().unwrap().to_string(), "/Photos/2024");
+        assert_eq!(RemotePath::root().to_string(), "/");
+    }
+
+    #[test]
+    fn resolves_through_parents_up_to_the_root() {
+        let links = NodeLinks::new(16);
+        links.record_root("root".to_string());
+        links.record("photos".to_string(), link("Photos", Some("root")));
+        links.record("2024".to_string(), link("2024", Some("photos")));
+        links.record("img".to_string(), link("img.jpg", Some("2024")));
+
+        assert_eq!(links.resolve("img").unwrap().to_string(), "/Photos/2024/img.jpg");
+        assert!(links.resolve("root").unwrap().is_root());
+    }
+
+    #[test]
+    fn missing_parents_and_cycles_are_errors() {
+        let links = NodeLinks::new(16);
+        links.record("orphan".to_string(), link("orphan", Some("gone")));
+        links.record("a".to_string(), link("a", Some("b")));
+        links.record("b".to_string(), link("b", Some("a")));
+
+        assert!(matches!(links.resolve("orphan"), Err(NodeError::UnknownNode(id)) if id == "gone"));
+        assert!(matches!(links.resolve("a"), Err(NodeError::PathCycle(_))));
+    }
+}
@@ -0,0 +1,174 @@
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use futures::stream::{self, StreamExt};
+use proton_sdk_sys::protobufs::drive::NodeIdentity;
+
+use super::{NodeError, NodeIdentityExt, NodeOperations, NodeTypeExt};
+
+/// Folders listed at the same time when computing subtree stats, unless configured otherwise
+pub const DEFAULT_MAX_IN_FLIGHT: usize = 4;
+
+/// Totals of a remote folder subtree
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SubtreeStats {
+    pub files: u64,
+    pub folders: u64,
+    pub bytes: u64,
+    /// Whether every folder of the subtree was listed, `false` when cancelled
+    /// or cut off by the maximum depth
+    pub complete: bool,
+}
+
+pub type SubtreeProgressCallback = Box<dyn Fn(usize) + Send + Sync>;
+
+/// Options for [`NodeOperations::subtree_stats`]
+pub struct SubtreeOptions {
+    max_depth: Option<usize>,
+    max_in_flight: usize,
+    cancelled: Option<Arc<AtomicBool>>,
+    progress: Option<SubtreeProgressCallback>,
+}
+
+impl SubtreeOptions {
+    /// Creates options walking the whole subtree with the default concurrency
+    pub fn new() -> Self {
+        Self {
+            max_depth: None,
+            max_in_flight: DEFAULT_MAX_IN_FLIGHT,
+            cancelled: None,
+            progress: None,
+        }
+    }
+
+    /// Only lists folders up to `depth` levels below the starting folder
+    pub fn with_max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Sets how many folders are listed at the same time
+    pub fn with_max_in_flight(mut self, max_in_flight: usize) -> Self {
+        self.max_in_flight = max_in_flight.max(1);
+        self
+    }
+
+    /// Stops the walk once `cancelled` is set, returning what was counted so far
+    pub fn with_cancel_flag(mut self, cancelled: Arc<AtomicBool>) -> Self {
+        self.cancelled = Some(cancelled);
+        self
+    }
+
+    /// Sets a callback receiving the number of folders visited so far
+    pub fn with_progress_callback<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(usize) + Send + Sync + 'static,
+    {
+        self.progress = Some(Box::new(callback));
+        self
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancelled
+            .as_ref()
+            .is_some_and(|cancelled| cancelled.load(Ordering::SeqCst))
+    }
+}
+
+impl Default for SubtreeOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for SubtreeOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SubtreeOptions")
+            .field("max_depth", &self.max_depth)
+            .field("max_in_flight", &self.max_in_flight)
+            .field("cancellable", &self.cancelled.is_some())
+            .field("progress_callback", &self.progress.is_some())
+            .finish()
+    }
+}
+
+impl NodeOperations<'_> {
+    /// Counts the files, folders and bytes below a folder
+    ///
+    /// Folders are listed level by level, `max_in_flight` at a time. The bytes are
+    /// the sizes of the files' active revisions. The starting folder itself isn't
+    /// counted.
+    ///
+    /// # Returns
+    /// The totals, with `complete: false` if the walk was cancelled or stopped at
+    /// the maximum depth
+    pub async fn subtree_stats(
+        &self,
+        folder: &NodeIdentity,
+        options: SubtreeOptions,
+    ) -> Result<SubtreeStats, NodeError> {
+        let mut stats = SubtreeStats {
+            complete: true,
+            ..Default::default()
+        };
+        let mut visited = 0;
+        let mut level = vec![folder.clone()];
+        let mut depth = 0;
+
+        while !level.is_empty() {
+            if options.is_cancelled() {
+                stats.complete = false;
+                break;
+            }
+            if options.max_depth.is_some_and(|max_depth| depth > max_depth) {
+                stats.complete = false;
+                break;
+            }
+
+            let mut listings = stream::iter(level)
+                .map(|identity| async move {
+                    let children = self.client.get_folder_children(identity.clone()).await;
+                    (identity, children)
+                })
+                .buffer_unordered(options.max_in_flight);
+
+            let mut next_level = Vec::new();
+            while let Some((identity, children)) = listings.next().await {
+                if options.is_cancelled() {
+                    stats.complete = false;
+                    return Ok(stats);
+                }
+
+                for child in children? {
+                    if let Some(folder) = child.as_folder() {
+                        stats.folders += 1;
+                        next_level.push(folder.full_identity(&identity)?);
+                    } else if let Some(file) = child.as_file() {
+                        stats.files += 1;
+                        stats.bytes += file
+                            .active_revision
+                            .as_ref()
+                            .and_then(|revision| revision.size)
+                            .unwrap_or(0)
+                            .max(0) as u64;
+                    }
+                }
+
+                visited += 1;
+                if let Some(progress) = &options.progress {
+                    progress(visited);
+                }
+            }
+
+            level = next_level;
+            depth += 1;
+        }
+
+        Ok(stats)
+    }
+}
@@ -1,13 +1,14 @@
-use std::{ffi::c_void, fmt};
+use std::{fmt, sync::Arc};
 
 use log::{debug, warn};
 use proton_sdk_sys::{
-    data::{AsyncCallback, ByteArray},
-    observability::{self, ObservabilityHandle},
+    api::{LibloadingApi, SdkApi},
+    data::{AsyncCallbackBuilder, ByteArray},
+    observability::ObservabilityHandle,
     sessions::SessionHandle,
 };
 
-use crate::cancellation::CancellationToken;
+use crate::{cancellation::CancellationToken, errors::SdkErrorDetails};
 
 #[derive(Debug, thiserror::Error)]
 pub enum ObservabilityError {
@@ -18,7 +19,7 @@ pub enum ObservabilityError {
     StartFailed(i32),
 
     #[error("Observability data flush failed: {0}")]
-    FlushFailed(String),
+    FlushFailed(SdkErrorDetails),
 
     #[error("Observability flush operation timed out")]
     FlushTimeout,
@@ -34,6 +35,7 @@ pub enum ObservabilityError {
 pub struct ObservabilityService {
     handle: ObservabilityHandle,
     _session: SessionHandle,
+    api: Arc<dyn SdkApi>,
 }
 
 impl ObservabilityService {
@@ -45,12 +47,16 @@ impl ObservabilityService {
     /// # Returns
     /// A new ObservabilityService instance or an error if creation failed
     pub fn new(session: SessionHandle) -> Result<Self, ObservabilityError> {
+        Self::with_api(LibloadingApi::shared(), session)
+    }
+
+    /// Starts the service for `session`, making the SDK calls through `api`
+    pub fn with_api(api: Arc<dyn SdkApi>, session: SessionHandle) -> Result<Self, ObservabilityError> {
         if session.is_null() {
             return Err(ObservabilityError::InvalidSession);
         }
 
-        let (result, obs_handle) = observability::raw::observability_service_start_new(session)
-            .map_err(|e| ObservabilityError::SdkError(e))?;
+        let (result, obs_handle) = api.observability_service_start_new(session)?;
 
         if result != 0 {
             return Err(ObservabilityError::StartFailed(result));
@@ -68,6 +74,7 @@ impl ObservabilityService {
         Ok(Self {
             handle: obs_handle,
             _session: session,
+            api,
         })
     }
 
@@ -81,78 +88,40 @@ impl ObservabilityService {
 
     /// Flushes observability data asynchronously
     ///
-    /// This sends any pending telemetry data to Proton's servers.
-    ///
-    /// # Arguments
-    /// * `cancellation_token` - Token to cancel the operation if needed
+    /// This sends any pending telemetry data to Proton's servers. The flush has a
+    /// token of its own, cancelled when it times out or its future is dropped unfinished.
     ///
     /// # Returns
     /// Ok(()) on success, or an error if the flush failed
-    pub async fn flush(
-        &self,
-        cancellation_token: &CancellationToken,
-    ) -> Result<(), ObservabilityError> {
+    pub async fn flush(&self) -> Result<(), ObservabilityError> {
         if self.handle.is_null() {
             return Err(ObservabilityError::NullHandle);
         }
 
-        let (tx, rx) = tokio::sync::oneshot::channel::<Result<(), ObservabilityError>>();
-        let tx_ptr = Box::leak(Box::new(tx));
-
-        extern "C" fn flush_success_callback(state: *const c_void, _response: ByteArray) {
-            log::debug!("Flush success callback hit!");
-            if !state.is_null() {
-                unsafe {
-                    let tx_ptr =
-                        state as *mut tokio::sync::oneshot::Sender<Result<(), ObservabilityError>>;
-                    let tx = Box::from_raw(tx_ptr);
-                    let _ = tx.send(Ok(()));
-                }
-            }
-        }
-
-        extern "C" fn flush_failure_callback(state: *const c_void, error_data: ByteArray) {
-            log::debug!("Flush failure callback hit...");
-            if !state.is_null() {
-                unsafe {
-                    let tx_ptr =
-                        state as *mut tokio::sync::oneshot::Sender<Result<(), ObservabilityError>>;
-                    let tx = Box::from_raw(tx_ptr);
-
-                    let error_slice = error_data.as_slice();
-                    let error_msg = if error_slice.is_empty() {
-                        "Unknown flush error".to_string()
-                    } else {
-                        String::from_utf8_lossy(error_slice).to_string()
-                    };
-
-                    let _ = tx.send(Err(ObservabilityError::FlushFailed(error_msg)));
-                }
-            }
-        }
+        // dropped after the guard, which cancels it
+        let cancellation_token = CancellationToken::with_api(self.api.clone())?;
 
-        let async_callback = AsyncCallback::new(
-            tx_ptr as *mut _ as *const std::ffi::c_void,
-            Some(flush_success_callback),
-            Some(flush_failure_callback),
-            cancellation_token.handle().raw(),
-        );
+        let (async_callback, guard) = AsyncCallbackBuilder::new()
+            .on_success(|_response| {
+                log::debug!("Flush success callback hit!");
+                Ok(())
+            })
+            .on_failure(|error_data: ByteArray| {
+                log::debug!("Flush failure callback hit...");
+                Err(ObservabilityError::FlushFailed(SdkErrorDetails::decode(&error_data.view())))
+            })
+            .cancellation_token(cancellation_token.handle())
+            .cancel_on_drop(self.api.clone())
+            .build();
 
-        let result = observability::raw::observability_service_flush(self.handle, async_callback)
-            .map_err(|e| ObservabilityError::SdkError(e))?;
+        let result = guard.call(|| self.api.observability_service_flush(self.handle, async_callback))?;
 
         if result != 0 {
-            unsafe {
-                let _ = Box::from_raw(tx_ptr);
-            }
-            return Err(ObservabilityError::FlushFailed(format!(
-                "FFI call failed with code: {}",
-                result
-            )));
+            return Err(ObservabilityError::FlushFailed(SdkErrorDetails::from_code(result)));
         }
 
-        match tokio::time::timeout(std::time::Duration::from_secs(30), rx).await {
-            Ok(result) => result.map_err(|e| ObservabilityError::FlushFailed(e.to_string()))?,
+        match tokio::time::timeout(std::time::Duration::from_secs(30), guard.completion()).await {
+            Ok(result) => result,
             Err(_) => Err(ObservabilityError::FlushTimeout),
         }
     }
@@ -163,8 +132,7 @@ impl ObservabilityService {
     /// so you usually don't need to call this manually.
     pub fn free(self) -> Result<(), ObservabilityError> {
         if !self.handle.is_null() {
-            observability::raw::observability_service_free(self.handle)
-                .map_err(|e| ObservabilityError::SdkError(e))?;
+            self.api.observability_service_free(self.handle)?;
             log::debug!("Observability service freed successfully");
         }
         Ok(())
@@ -183,7 +151,7 @@ impl fmt::Debug for ObservabilityService {
 impl Drop for ObservabilityService {
     fn drop(&mut self) {
         if !self.handle.is_null() {
-            if let Err(e) = observability::raw::observability_service_free(self.handle) {
+            if let Err(e) = self.api.observability_service_free(self.handle) {
                 warn!("Failed to free observability service in Drop: {}", e);
             } else {
                 debug!("Observability service cleaned up automatically");
@@ -219,6 +187,11 @@ impl OptionalObservability {
         Ok(Self(Some(ObservabilityService::new(session)?)))
     }
 
+    /// Creates an enabled observability service making the SDK calls through `api`
+    pub fn enabled_with_api(api: Arc<dyn SdkApi>, session: SessionHandle) -> Result<Self, ObservabilityError> {
+        Ok(Self(Some(ObservabilityService::with_api(api, session)?)))
+    }
+
     /// Creates a disabled observability service (no-op)
     pub fn disabled() -> Self {
         Self(None)
@@ -238,12 +211,9 @@ impl OptionalObservability {
     }
 
     /// Flushes data if observability is enabled
-    pub async fn flush_if_enabled(
-        &self,
-        cancellation_token: &CancellationToken,
-    ) -> Result<(), ObservabilityError> {
+    pub async fn flush_if_enabled(&self) -> Result<(), ObservabilityError> {
         if let Some(obs) = &self.0 {
-            obs.flush(cancellation_token).await
+            obs.flush().await
         } else {
             Ok(())
         }
@@ -261,3 +231,32 @@ impl fmt::Debug for OptionalObservability {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    use proton_sdk_sys::api::{MockApi, Reply};
+
+    #[tokio::test]
+    async fn abandoned_flushes_cancel_a_token_of_their_own() {
+        let mock = Arc::new(MockApi::new());
+        let service = ObservabilityService::with_api(mock.clone(), SessionHandle::from(5)).unwrap();
+        service.flush().await.unwrap();
+        mock.reply("observability_service_flush", Reply::Pending);
+        assert!(tokio::time::timeout(Duration::from_millis(10), service.flush()).await.is_err());
+
+        // cancelled then freed, which a token borrowed from the session wouldn't be
+        let cancelled: Vec<_> = mock.calls_to("cancellation_token_source_cancel").iter().map(|call| call.handle).collect();
+        let freed: Vec<_> = mock.calls_to("cancellation_token_source_free").iter().map(|call| call.handle).collect();
+        assert_eq!(cancelled.len(), 1);
+        assert_eq!(freed.len(), 2);
+        assert!(freed.contains(&cancelled[0]));
+
+        let handle = service.handle().raw();
+        drop(service);
+        let freed: Vec<_> = mock.calls_to("observability_service_free").iter().map(|call| call.handle).collect();
+        assert_eq!(freed, [handle]);
+    }
+}
@@ -0,0 +1,109 @@
+//! Progress of downloads and uploads, as the SDK reports it
+
+use std::ffi::c_void;
+
+use log::warn;
+use proton_sdk_sys::{
+    data::{self, ByteArray, CallbackState},
+    protobufs::drive::ProgressUpdate,
+};
+
+/// How far a download or upload got
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TransferProgress {
+    pub bytes_completed: i64,
+    /// 0 while the SDK doesn't know the size yet
+    pub bytes_in_total: i64,
+}
+
+impl TransferProgress {
+    /// Returns the completed share of the transfer, between 0 and 1, see [`ProgressUpdate::fraction`]
+    pub fn fraction(&self) -> f32 {
+        ProgressUpdate::from(*self).fraction() as f32
+    }
+}
+
+impl From<ProgressUpdate> for TransferProgress {
+    fn from(update: ProgressUpdate) -> Self {
+        Self { bytes_completed: update.bytes_completed, bytes_in_total: update.bytes_in_total }
+    }
+}
+
+impl From<TransferProgress> for ProgressUpdate {
+    fn from(progress: TransferProgress) -> Self {
+        Self { bytes_completed: progress.bytes_completed, bytes_in_total: progress.bytes_in_total }
+    }
+}
+
+/// Adapts a callback taking the completed fraction, as transfers used to report progress
+///
+/// ```
+/// # use proton_sdk_rs::progress::{fractions, TransferProgress};
+/// let report = fractions(|fraction| println!("{:.0}%", fraction * 100.0));
+/// report(TransferProgress { bytes_completed: 1, bytes_in_total: 4 });
+/// ```
+pub fn fractions<F>(callback: F) -> impl Fn(TransferProgress) + Send + Sync + 'static
+where
+    F: Fn(f32) + Send + Sync + 'static,
+{
+    move |progress| callback(progress.fraction())
+}
+
+/// Progress callback of a transfer whose callback state holds the user's callback
+///
+/// The SDK calls it from its own threads while the transfer's future holds the same
+/// state, so the user's callback has to be `Sync`.
+pub(crate) extern "C" fn report_progress<T, F>(state: *const c_void, progress: ByteArray)
+where
+    F: Fn(TransferProgress) + Send + Sync + 'static,
+{
+    let Some(state) = (unsafe { CallbackState::<T, Option<F>>::from_raw(state) }) else {
+        return;
+    };
+    match data::parse_progress(progress) {
+        Ok(update) => {
+            if let Some(callback) = state.data() {
+                callback(update.into());
+            }
+        }
+        Err(e) => warn!("Ignoring an unreadable progress update of {} bytes: {}", progress.length, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proton_sdk_sys::data::CallbackGuard;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn fractions_follow_the_bytes() {
+        assert_eq!(TransferProgress { bytes_completed: 1, bytes_in_total: 4 }.fraction(), 0.25);
+        assert_eq!(TransferProgress::default().fraction(), 0.0);
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let report = {
+            let seen = seen.clone();
+            fractions(move |fraction| seen.lock().unwrap().push(fraction))
+        };
+        report(TransferProgress { bytes_completed: 3, bytes_in_total: 4 });
+        assert_eq!(*seen.lock().unwrap(), [0.75]);
+    }
+
+    fn deliver<F: Fn(TransferProgress) + Send + Sync + 'static>(guard: &CallbackGuard<(), Option<F>>, payload: &[u8]) {
+        report_progress::<(), F>(guard.state(), ByteArray::from_slice(payload));
+    }
+
+    #[test]
+    fn unreadable_updates_are_not_reported() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let callback = {
+            let seen = seen.clone();
+            move |progress: TransferProgress| seen.lock().unwrap().push(progress)
+        };
+        let guard = CallbackGuard::<(), _>::new(Some(callback));
+        deliver(&guard, &[0x08, 0x80, 0x20, 0x10, 0x80, 0x80, 0x01]);
+        deliver(&guard, &0.5f32.to_le_bytes());
+        assert_eq!(*seen.lock().unwrap(), [TransferProgress { bytes_completed: 4096, bytes_in_total: 16384 }]);
+    }
+}
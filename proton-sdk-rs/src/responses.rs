@@ -0,0 +1,144 @@
+//! Decoding of the buffers the SDK calls back with, which reports malformed
+//! input as an error since a panic would abort inside an `extern "C"` callback
+
+use log::trace;
+use proton_sdk_sys::{
+    data::ByteArray,
+    protobufs::{
+        account::{IntResponse, SessionTokens},
+        FromByteArray, ProtoError,
+    },
+    sessions::SessionHandle,
+};
+
+/// Longest buffer dumped whole in messages and logs
+pub(crate) const MAX_DUMP: usize = 50;
+
+/// Reads the session handle `session_begin` and `session_resume` succeed with
+///
+/// The SDK answers with an `IntResponse`, and the handle as text is read too.
+/// The `SessionTokens` older builds answer with hold no handle, so they are an
+/// error, like a null handle and anything else that can't be read.
+pub fn session_handle(response: &[u8]) -> Result<SessionHandle, String> {
+    if response.is_empty() {
+        return Err("Empty response".to_string());
+    }
+    trace!("Response data: {} bytes", response.len());
+
+    let handle = if let Ok(int_response) = IntResponse::from_bytes_strict(response) {
+        trace!("Parsed as IntResponse: value = {}", int_response.value);
+        int_response.value as isize
+    } else if SessionTokens::from_bytes_strict(response).is_ok() {
+        // older builds answer with the session's tokens
+        return Err("The SDK answered with session tokens instead of a session handle, it is too old".to_string());
+    } else if let Some(handle_value) = std::str::from_utf8(response)
+        .ok()
+        .and_then(|text| text.trim().parse::<isize>().ok())
+    {
+        trace!("Parsed as string number: {}", handle_value);
+        handle_value
+    } else {
+        trace!("Unreadable response: {}", ByteArray::from_slice(response).hex_preview(MAX_DUMP, true));
+        return Err(format!("Could not parse session handle from {} bytes", response.len()));
+    };
+
+    if handle == 0 {
+        return Err("The SDK answered with a null session handle".to_string());
+    }
+    Ok(SessionHandle::from(handle))
+}
+
+/// Reads the handle a downloader or uploader was created with
+pub fn created_handle(response: &[u8]) -> Result<isize, ProtoError> {
+    Ok(IntResponse::from_bytes_strict(response)?.value as isize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use proton_sdk_sys::data::parse_progress;
+    use proton_sdk_sys::prost::Message;
+    use proton_sdk_sys::protobufs::{account::Error, drive::ProgressUpdate};
+
+    use crate::errors::SdkErrorDetails;
+
+    /// Encodings cut short inside their last field
+    fn truncated(bytes: Vec<u8>) -> Vec<u8> {
+        bytes[..bytes.len() - 1].to_vec()
+    }
+
+    proptest! {
+        #[test]
+        fn parsers_never_panic(bytes in prop::collection::vec(any::<u8>(), 0..512)) {
+            let _ = session_handle(&bytes);
+            let _ = SdkErrorDetails::decode(&bytes);
+            let _ = created_handle(&bytes);
+            let _ = parse_progress(ByteArray::from_slice(&bytes));
+        }
+
+        #[test]
+        fn session_handles_are_never_null(bytes in prop::collection::vec(any::<u8>(), 0..64)) {
+            if let Ok(handle) = session_handle(&bytes) {
+                prop_assert!(!handle.is_null());
+            }
+        }
+
+        #[test]
+        fn encoded_handles_round_trip(value in any::<i64>().prop_filter("null", |value| *value != 0)) {
+            let encoded = IntResponse { value }.encode_to_vec();
+            prop_assert_eq!(session_handle(&encoded), Ok(SessionHandle::from(value as isize)));
+            prop_assert_eq!(created_handle(&encoded).ok(), Some(value as isize));
+        }
+
+        #[test]
+        fn truncated_handles_are_rejected(value in (1i64 << 7)..i64::MAX) {
+            let cut = truncated(IntResponse { value }.encode_to_vec());
+            prop_assert!(created_handle(&cut).is_err());
+            prop_assert!(session_handle(&cut).is_err());
+        }
+
+        #[test]
+        fn buffers_of_no_known_shape_are_not_handles(
+            // wire types 6 and 7 don't exist, so no protobuf starts with these
+            first in any::<u8>().prop_filter("valid wire type or digit", |b| b & 7 >= 6 && !b.is_ascii_digit()),
+            rest in prop::collection::vec(any::<u8>(), 0..64),
+        ) {
+            let bytes = [vec![first], rest].concat();
+            prop_assert!(session_handle(&bytes).is_err());
+            prop_assert!(created_handle(&bytes).is_err());
+        }
+
+        #[test]
+        fn error_protobufs_keep_their_code_and_message(code in any::<i32>(), message in "\\PC+") {
+            let error = Error { primary_code: Some(code.into()), message: message.clone(), ..Default::default() };
+            let details = SdkErrorDetails::decode(&error.encode_to_vec());
+            prop_assert_eq!(details.code(), Some(code.into()));
+            prop_assert_eq!(details.message(), message);
+        }
+
+        #[test]
+        fn text_errors_are_kept_as_they_are(text in "[ -z|~]{1,200}") {
+            let details = SdkErrorDetails::decode(text.as_bytes());
+            prop_assert_eq!(details.code(), None);
+            prop_assert_eq!(details.message(), text.trim());
+        }
+
+        #[test]
+        fn progress_is_a_fraction(completed in any::<i64>(), total in any::<i64>()) {
+            let update = ProgressUpdate { bytes_completed: completed, bytes_in_total: total };
+            let fraction = parse_progress(ByteArray::from_slice(&update.encode_to_vec())).unwrap().fraction();
+            prop_assert!((0.0..=1.0).contains(&fraction));
+        }
+    }
+
+    #[test]
+    fn legacy_handle_encodings_are_read() {
+        assert_eq!(session_handle(b" 42\n"), Ok(SessionHandle::from(42)));
+        assert!(session_handle(&42i64.to_le_bytes()).is_err());
+        let tokens = SessionTokens { access_token: "access".to_string(), refresh_token: "refresh".to_string() };
+        assert!(session_handle(&tokens.encode_to_vec()).unwrap_err().contains("session tokens"));
+        assert!(session_handle(b"0").is_err());
+        assert!(session_handle(&[]).is_err());
+    }
+}
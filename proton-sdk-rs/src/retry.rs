@@ -0,0 +1,131 @@
+use std::future::Future;
+use std::time::Duration;
+
+use log::warn;
+
+/// How often a failed call is tried again, and how long to wait in between
+///
+/// The wait doubles after every failure, from `initial_delay` up to `max_delay`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Attempts in total, the first one included
+    pub max_attempts: u32,
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A single attempt, nothing is retried
+    pub fn never() -> Self {
+        Self { max_attempts: 1, ..Self::default() }
+    }
+
+    /// Wait before retry number `retry`, counting from 1
+    pub fn delay(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry.saturating_sub(1));
+        self.initial_delay.saturating_mul(factor).min(self.max_delay)
+    }
+
+    /// Calls `operation` until it succeeds, fails for good or runs out of attempts
+    ///
+    /// Errors `retryable` rejects are returned at once. When `cancelled`
+    /// completes during a wait, the last error is returned without waiting
+    /// for the rest of it.
+    pub async fn run<T, E, Op, Fut>(
+        &self,
+        mut operation: Op,
+        retryable: impl Fn(&E) -> bool,
+        cancelled: impl Future<Output = ()>,
+    ) -> Result<T, E>
+    where
+        Op: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: std::fmt::Display,
+    {
+        let mut cancelled = std::pin::pin!(cancelled);
+        let mut attempt = 1;
+        loop {
+            let error = match operation().await {
+                Ok(value) => return Ok(value),
+                Err(e) => e,
+            };
+            if attempt >= self.max_attempts.max(1) || !retryable(&error) {
+                return Err(error);
+            }
+
+            let delay = self.delay(attempt);
+            warn!("Attempt {} of {} failed, retrying in {:?}: {}", attempt, self.max_attempts, delay, error);
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {}
+                _ = &mut cancelled => return Err(error),
+            }
+            attempt += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    const QUICK: RetryPolicy = RetryPolicy {
+        max_attempts: 3,
+        initial_delay: Duration::from_millis(1),
+        max_delay: Duration::from_millis(1),
+    };
+
+    /// Fails with `error` on the first `failures` calls, counting them in `calls`
+    fn failing<'a>(
+        calls: &'a Cell<u32>,
+        failures: u32,
+        error: &'static str,
+    ) -> impl FnMut() -> std::future::Ready<Result<u32, &'static str>> + 'a {
+        move || {
+            calls.set(calls.get() + 1);
+            std::future::ready(if calls.get() <= failures { Err(error) } else { Ok(calls.get()) })
+        }
+    }
+
+    #[test]
+    fn delays_double_up_to_the_maximum() {
+        let policy = RetryPolicy::default();
+        let delays: Vec<u64> = (1..=7).map(|retry| policy.delay(retry).as_secs()).collect();
+        assert_eq!(delays, [1, 2, 4, 8, 16, 30, 30]);
+    }
+
+    #[tokio::test]
+    async fn failures_are_retried_until_the_attempts_run_out() {
+        let calls = Cell::new(0);
+        let result = QUICK.run(failing(&calls, 10, "timeout"), |_| true, std::future::pending()).await;
+        assert_eq!((result, calls.get()), (Err("timeout"), 3));
+
+        let calls = Cell::new(0);
+        let result = QUICK.run(failing(&calls, 1, "timeout"), |_| true, std::future::pending()).await;
+        assert_eq!(result, Ok(2));
+
+        let calls = Cell::new(0);
+        let result = QUICK.run(failing(&calls, 10, "not found"), |e| *e != "not found", std::future::pending()).await;
+        assert_eq!((result, calls.get()), (Err("not found"), 1));
+    }
+
+    #[tokio::test]
+    async fn cancellation_cuts_the_wait_short() {
+        let policy = RetryPolicy { initial_delay: Duration::from_secs(3600), ..QUICK };
+        let calls = Cell::new(0);
+        let started = std::time::Instant::now();
+        let result = policy.run(failing(&calls, 10, "timeout"), |_| true, async {}).await;
+        assert_eq!((result, calls.get()), (Err("timeout"), 1));
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+}
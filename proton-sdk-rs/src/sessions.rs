@@ -1,18 +1,32 @@
+mod store;
+mod two_factor;
+
 use std::{
-    ffi::c_void, fmt, fs::File, io::Write, sync::{Arc, Mutex}
+    ffi::c_void, fmt, sync::Arc
 };
 
-use log::{debug, error, info, trace, warn};
+use log::{debug, error, info, trace};
 use proton_sdk_sys::{
-    data::{AsyncCallback, BooleanCallback, ByteArray, Callback},
+    api::{LibloadingApi, SdkApi},
+    data::{AsyncCallback, BooleanCallback, ByteArray, Callback, CallbackGuard, CallbackState, ThreadSafeState},
     protobufs::{
-        AddressKeyRegistrationRequest, ProtonClientOptions, SessionBeginRequest, SessionId, SessionRenewRequest, SessionResumeRequest, ToByteArray
+        account::{AddressKeyRegistrationRequest, ProtonClientOptions, SessionBeginRequest, SessionId, SessionRenewRequest, SessionResumeRequest}, ProtoBuffer, SdkErrorKind, Sensitive, validation::Validate
     },
-    sessions::{self, SessionHandle},
+    secret::SecretBytes,
+    logger::LoggerProviderHandle,
+    sessions::SessionHandle,
+};
+use proton_sdk_sys::protobufs::account::StringResponse;
+use crate::{
+    cancellation::CancellationToken,
+    errors::SdkErrorDetails,
+    logging::{LoggerProvider, SdkLogger},
+    responses,
 };
-use proton_sdk_sys::protobufs::StringResponse;
-use crate::cancellation::CancellationToken;
-use proton_sdk_sys::protobufs::SessionInfo;
+use proton_sdk_sys::protobufs::account::SessionInfo;
+
+pub use self::store::{FileSessionStore, DEFAULT_SESSION_FILE};
+pub use self::two_factor::{proton_sdk_free, TwoFactorResponseBuffers};
 
 #[derive(Debug, thiserror::Error)]
 pub enum SessionError {
@@ -20,7 +34,7 @@ pub enum SessionError {
     SdkError(#[from] anyhow::Error),
 
     #[error("Session operation failed: {0}")]
-    OperationFailed(i32),
+    OperationFailed(SdkErrorDetails),
 
     #[error("Protobuf error: {0}")]
     ProtobufError(#[from] proton_sdk_sys::protobufs::ProtoError),
@@ -30,6 +44,25 @@ pub enum SessionError {
 
     #[error("Operation was cancelled")]
     Cancelled,
+
+    #[error("Invalid request: {0}")]
+    InvalidRequest(#[from] proton_sdk_sys::protobufs::validation::ValidationError),
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+impl SessionError {
+    /// Classifies the error, HTTP 401 and Proton auth codes count as authentication errors
+    pub fn kind(&self) -> SdkErrorKind {
+        match self {
+            SessionError::OperationFailed(details) => details.kind(),
+            SessionError::Cancelled => SdkErrorKind::Cancelled,
+            SessionError::InvalidRequest(_) => SdkErrorKind::InvalidRequest,
+            SessionError::ProtobufError(_) => SdkErrorKind::Serialization,
+            _ => SdkErrorKind::Unknown,
+        }
+    }
 }
 
 pub type RequestResponseCallback = Box<dyn Fn(&[u8]) + Send + Sync>;
@@ -46,16 +79,21 @@ pub struct SessionCallbacks {
     pub tokens_refreshed: Option<TokensRefreshedCallback>,
 }
 
-struct CallbackData {
-    request_response: Option<RequestResponseCallback>,
-    secret_requested: Option<SecretRequestedCallback>,
-    two_factor_requested: Option<TwoFactorRequestedCallbackRust>,
-    tokens_refreshed: Option<TokensRefreshedCallback>,
-    completion_sender: Arc<
-        std::sync::Mutex<Option<tokio::sync::oneshot::Sender<Result<SessionHandle, SessionError>>>>,
-    >,
+/// What a session's callbacks need, kept for as long as the session
+struct SessionCallbackData {
+    callbacks: SessionCallbacks,
+    two_factor_answers: TwoFactorResponseBuffers,
+}
+
+impl From<SessionCallbacks> for SessionCallbackData {
+    fn from(callbacks: SessionCallbacks) -> Self {
+        Self { callbacks, two_factor_answers: TwoFactorResponseBuffers::new() }
+    }
 }
 
+/// State of a session's callbacks, the result of `session_begin` besides them
+type SessionState = CallbackState<Result<SessionHandle, SessionError>, SessionCallbackData>;
+
 impl Default for SessionCallbacks {
     fn default() -> Self {
         Self {
@@ -72,8 +110,10 @@ impl Default for SessionCallbacks {
 
 pub struct Session {
     handle: SessionHandle,
-    _callback_data: Option<Box<CallbackData>>,
+    _callbacks: Option<CallbackGuard<Result<SessionHandle, SessionError>, SessionCallbackData>>,
     cancellation_token: CancellationToken,
+    logger_provider: Option<LoggerProviderHandle>,
+    api: Arc<dyn SdkApi>,
 }
 
 impl Session {
@@ -94,11 +134,10 @@ impl Session {
         }
 
         let key_data = ByteArray::from_slice(armored_key);
-        let result =
-            sessions::raw::session_register_armored_locked_user_key(self.handle, key_data)?;
+        let result = self.api.session_register_armored_locked_user_key(self.handle, key_data)?;
 
         if result != 0 {
-            return Err(SessionError::OperationFailed(result));
+            return Err(SessionError::OperationFailed(SdkErrorDetails::from_code(result)));
         }
 
         Ok(())
@@ -113,37 +152,24 @@ impl Session {
             return Err(SessionError::NullHandle);
         }
 
-        let proto_buf = request.to_proto_buffer()?;
-        let result =
-            sessions::raw::session_register_address_keys(self.handle, proto_buf.as_byte_array())?;
+        // the request holds the unlocked address keys
+        let proto_buf = ProtoBuffer::encode_sensitive(request)?;
+        let result = self.api.session_register_address_keys(self.handle, proto_buf.as_byte_array())?;
 
         if result != 0 {
-            return Err(SessionError::OperationFailed(result));
+            return Err(SessionError::OperationFailed(SdkErrorDetails::from_code(result)));
         }
 
         Ok(())
     }
 
     pub fn info(&self) -> anyhow::Result<SessionInfo> {
-        let session = sessions::raw::session_get_info(
+        let session = self.api.session_get_info(
             self.handle(), 
             self.cancellation_token().handle()
         ).map_err(|e| SessionError::SdkError(e))?;
         
-        #[cfg(debug_assertions)]
-        {
-            trace!("SessionId: {:?}", session.session_id);
-            trace!("Username: {}", session.username);
-            trace!("UserID: {:?}", session.user_id);
-            trace!("Access Token: {:?}", session.access_token);
-            trace!("Refresh Token: {:?}", session.refresh_token);
-            trace!("Scopes: ");
-            for scope in &session.scopes {
-                trace!("    {:?}", scope);
-            }
-            trace!("Is waiting for second factor code: {}", session.is_waiting_for_second_factor_code);
-            trace!("Password mode: {}", session.password_mode().as_str_name());
-        }
+        trace!("Session info: {:#?}", session.redacted());
 
         Ok(session)
     }
@@ -165,12 +191,11 @@ impl Session {
     /// # Returns
     /// Returns an [`anyhow::Result`]
     pub fn save_session(&self, path: Option<&str>) -> anyhow::Result<()> {
-        let path = path.unwrap_or("session_info.bin");
+        let path = path.unwrap_or(DEFAULT_SESSION_FILE);
         let info = self.info()?;
-        let info_bytes = info.to_bytes()?.to_vec();
-        let mut file = File::create("session_info.bin")
+        FileSessionStore::new(path)
+            .save(&info)
             .map_err(|e| anyhow::anyhow!("Failed to write to {:?} due to error: {}", path, e))?;
-        file.write_all(&info_bytes)?;
         Ok(())
     }
 
@@ -183,42 +208,45 @@ impl Session {
         debug!("Ending session synchronously...");
         debug!("Session handle: {:?}", self.handle);
 
-        unsafe {
-            match sessions::raw::session_free(self.handle) {
-                Ok(_t) => {
-                    debug!("Session freed successfully");
-                    Ok(())
-                }
-                Err(e) => {
-                    error!("Session free failed: {}", e);
-                    Err(SessionError::SdkError(e))
-                }
+        match self.api.session_free(self.handle) {
+            Ok(_t) => {
+                debug!("Session freed successfully");
+                Ok(())
+            }
+            Err(e) => {
+                error!("Session free failed: {}", e);
+                Err(SessionError::SdkError(e))
             }
         }
     }
 
+    /// Unlocks the account's keys with its data (mailbox) password
+    ///
+    /// The copies of the password made for the SDK are wiped once it returns.
     pub fn apply_data_password(
         &self,
-        password: &str,
+        password: impl Into<SecretBytes>,
     ) -> Result<(), SessionError> {
         if self.handle.is_null() {
             return Err(SessionError::NullHandle);
         }
 
-        let string_response = StringResponse {
-            value: password.to_string(),
+        let mut string_response = StringResponse {
+            value: password.into().into_inner(),
         };
-        let proto_buf = string_response.to_proto_buffer()?;
+        let proto_buf = ProtoBuffer::encode_sensitive(&string_response);
+        SecretBytes::wipe(&mut string_response.value);
+        let proto_buf = proto_buf?;
         let byte_array = proto_buf.as_byte_array();
 
-        let result = sessions::raw::session_apply_data_password(
+        let result = self.api.session_apply_data_password(
             self.handle,
             byte_array,
             self.cancellation_token().handle(),
         )?;
 
         if result != 0 {
-            return Err(SessionError::OperationFailed(result));
+            return Err(SessionError::OperationFailed(SdkErrorDetails::from_code(result)));
         }
 
         Ok(())
@@ -227,14 +255,29 @@ impl Session {
     pub fn cancellation_token(&self) -> &CancellationToken {
         &self.cancellation_token
     }
+
+    /// Returns the logger provider the session was created with, if any
+    ///
+    /// Drive clients created from this session log through the same provider.
+    pub fn logger_provider(&self) -> Option<LoggerProviderHandle> {
+        self.logger_provider
+    }
+
+    /// Returns the SDK calls the session makes, clients created from it make theirs the same way
+    pub fn api(&self) -> &Arc<dyn SdkApi> {
+        &self.api
+    }
 }
 
 impl Drop for Session {
     fn drop(&mut self) {
         if !self.handle.is_null() {
-            unsafe {
-                // todo: save the token information and write to a file before discarding session
-                let _ = sessions::raw::session_free(self.handle);
+            // todo: save the token information and write to a file before discarding session
+            if self.api.session_free(self.handle).is_ok() {
+                if let Some(callbacks) = &self._callbacks {
+                    // SAFETY: a freed session doesn't call back
+                    unsafe { callbacks.revoke() };
+                }
             }
         }
     }
@@ -243,14 +286,18 @@ impl Drop for Session {
 pub struct SessionBuilder {
     request: SessionBeginRequest,
     callbacks: SessionCallbacks,
+    api: Arc<dyn SdkApi>,
 }
 
 impl SessionBuilder {
     /// Creates a new Proton account session
-    pub fn new(username: String, password: String) -> Self {
+    ///
+    /// The password is wiped once [`begin`](Self::begin) encoded it for the SDK, and
+    /// the encoded request once the session began.
+    pub fn new(username: String, password: impl Into<SecretBytes>) -> Self {
         let request = SessionBeginRequest {
             username: username,
-            password: password,
+            password: password.into().into_inner(),
             two_factor_code: None,
             options: Some(ProtonClientOptions::default()),
         };
@@ -258,15 +305,32 @@ impl SessionBuilder {
         Self {
             request,
             callbacks: SessionCallbacks::default(),
+            api: LibloadingApi::shared(),
         }
     }
 
+    /// Makes the SDK calls through `api` instead of the loaded library
+    pub fn with_api(mut self, api: Arc<dyn SdkApi>) -> Self {
+        self.api = api;
+        self
+    }
+
     /// Adds options to client session
     pub fn with_options(mut self, options: ProtonClientOptions) -> Self {
         self.request.options = Some(options);
         self
     }
 
+    /// Routes the session's SDK logs to the given logger provider instead of the
+    /// globally installed [`SdkLogger`]
+    ///
+    /// The provider must outlive the session and every client created from it.
+    pub fn with_logger(mut self, logger: &LoggerProvider) -> Self {
+        let options = self.request.options.get_or_insert_with(ProtonClientOptions::default);
+        options.logger_provider_handle = Some(logger.handle().raw() as i64);
+        self
+    }
+
     /// Adds app version according to Proton Semantic Versioning (github)
     pub fn with_app_version(
         mut self,
@@ -329,7 +393,17 @@ impl SessionBuilder {
         self
     }
 
-    pub async fn begin(self) -> Result<Session, SessionError> {
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "session_begin",
+            skip_all,
+            fields(app_version = self.request.options.as_ref().map(|options| options.app_version.as_str()))
+        )
+    )]
+    pub async fn begin(mut self) -> Result<Session, SessionError> {
+        self.request.validate()?;
+
         let censor = |input: &String, censor: char| {
             let mut temp = String::new();
             for len in 0..input.len()-2 {
@@ -345,97 +419,77 @@ impl SessionBuilder {
             self.request.password.len()
         );
 
-        let proto_buf = self.request.to_proto_buffer()?;
+        let logger_provider = self.request.options.as_mut().and_then(attach_installed_logger);
 
-        let (tx, rx) = tokio::sync::oneshot::channel();
-        let tx = Arc::new(std::sync::Mutex::new(Some(tx)));
+        let proto_buf = self.request.to_sensitive_proto_buffer();
+        SecretBytes::wipe(&mut self.request.password);
+        if let Some(code) = self.request.two_factor_code.as_mut() {
+            SecretBytes::wipe(code);
+        }
+        let proto_buf = proto_buf?;
 
-        let callback_data = Box::new(CallbackData {
-            request_response: self.callbacks.request_response,
-            secret_requested: self.callbacks.secret_requested,
-            two_factor_requested: self.callbacks.two_factor_requested,
-            tokens_refreshed: self.callbacks.tokens_refreshed,
-            completion_sender: tx.clone(),
-        });
-        let callback_ptr = callback_data.as_ref() as *const CallbackData as *const c_void;
+        let cancellation_token =
+            CancellationToken::with_api(self.api.clone()).map_err(|e| SessionError::SdkError(e))?;
 
-        // creating c callbacks
-        let request_callback = Callback::new(callback_ptr, Some(request_response_c_callback));
-        let secret_callback = BooleanCallback::new(callback_ptr, Some(secret_requested_c_callback));
-        let two_factor_callback = proton_sdk_sys::data::TwoFactorRequestedCallback::new(
-            callback_ptr,
-            Some(two_factor_requested_c_callback),
-        );
-        let tokens_callback = Callback::new(callback_ptr, Some(tokens_refreshed_c_callback));
+        // the session's callbacks keep the state once it began, so only a failure gives it back
+        let guard = CallbackGuard::new(SessionCallbackData::from(self.callbacks))
+            .cancel_on_drop(self.api.clone(), cancellation_token.handle());
+        let callback_ptr = guard.state();
 
-        let cancellation_token = CancellationToken::new().map_err(|e| SessionError::SdkError(e))?;
+        // creating c callbacks, which the SDK calls from its own threads
+        let state = guard.thread_safe_state();
+        let request_callback = Callback::checked(state, request_response_c_callback);
+        let secret_callback = BooleanCallback::checked(state, secret_requested_c_callback);
+        let two_factor_callback =
+            proton_sdk_sys::data::TwoFactorRequestedCallback::checked(state, two_factor_requested_c_callback);
+        let tokens_callback = Callback::checked(state, tokens_refreshed_c_callback);
 
         // success callback
         extern "C" fn session_success_callback(state: *const c_void, response: ByteArray) {
-            if !state.is_null() {
-                unsafe {
-                    let data = &*(state as *const CallbackData);
-                    if let Ok(mut guard) = data.completion_sender.lock() {
-                        if let Some(sender) = guard.take() {
-                            debug!("Session success callback hit!");
-
-                            let response_slice = response.as_slice();
-                            trace!("Success response: {} bytes", response_slice.len());
-
-                            // Debug: Show response content
-                            if response_slice.len() <= 100 {
-                                trace!("Response hex: {:02x?}", response_slice);
-                                if let Ok(response_str) = std::str::from_utf8(response_slice) {
-                                    trace!("Response as string: {}", response_str);
-                                }
-                            }
-
-                            // Parse session handle
-                            let session_handle = unsafe { parse_session_handle(&response) }
-                                .unwrap_or_else(|e| {
-                                    warn!("Warning: {}, using default handle", e);
-                                    SessionHandle::from(1) // Non-zero to indicate success
-                                });
-
-                            debug!("Using session handle: {:?}", session_handle);
-                            let _ = sender.send(Ok(session_handle));
-                        }
+            let Some(state) = (unsafe { SessionState::from_raw(state) }) else {
+                error!("Callback state is null!");
+                return;
+            };
+            if !state.is_completed() {
+                debug!("Session success callback hit!");
+
+                // older builds answer with the session's tokens
+                trace!("Success response: {}", response.hex_preview(responses::MAX_DUMP, true));
+
+                match responses::session_handle(&response.view()) {
+                    Ok(session_handle) => {
+                        debug!("Using session handle: {:?}", session_handle);
+                        state.send(Ok(session_handle));
+                    }
+                    Err(e) => {
+                        error!("Unreadable session response: {}", e);
+                        state.send(Err(SessionError::SdkError(anyhow::anyhow!(e))));
                     }
                 }
-            } else {
-                error!("Callback state is null!");
             }
         }
 
         // failure callback
         extern "C" fn session_failure_callback(state: *const c_void, error_data: ByteArray) {
-            if !state.is_null() {
-                unsafe {
-                    let data = &*(state as *const CallbackData);
+            // no session began to call back, so the state is given back
+            unsafe {
+                SessionState::finish(state, |state| {
                     debug!("Session failure callback hit!");
 
-                    let (error_code, error_message) = parse_sdk_error(&error_data);
-                    error!(
-                        "Error details: code={}, message={}",
-                        error_code, error_message
-                    );
-
-                    match error_code {
-                        401 => error!("Authentication failed - check username/password"),
-                        403 => error!("Access forbidden - account may be suspended"),
-                        422 => error!("Invalid request - check your input data"),
-                        429 => error!("Rate limited - try again later"),
-                        1000..=1999 => error!("Client error - check your request format"),
-                        2000..=2999 => error!("Server error - Proton service may be down"),
-                        _ => error!("Check network connectivity and credentials"),
-                    }
+                    let details = SdkErrorDetails::decode(&error_data.view());
+                    error!("Error details: {}", details);
 
-                    if let Ok(mut guard) = data.completion_sender.lock() {
-                        if let Some(sender) = guard.take() {
-                            let _ = sender.send(Err(SessionError::OperationFailed(error_code)));
-                        }
+                    match details {
+                        SdkErrorDetails::Unauthorized { .. } => error!("Authentication failed - check username/password"),
+                        SdkErrorDetails::Forbidden { .. } => error!("Access forbidden - account may be suspended"),
+                        SdkErrorDetails::Unprocessable { .. } => error!("Invalid request - check your input data"),
+                        SdkErrorDetails::RateLimited { .. } => error!("Rate limited - try again later"),
+                        SdkErrorDetails::IncorrectPassword { .. } => error!("Incorrect password or second factor"),
+                        SdkErrorDetails::Other { .. } => error!("Check network connectivity and credentials"),
                     }
-                }
+
+                    state.send(Err(SessionError::OperationFailed(details)));
+                })
             }
         }
 
@@ -446,40 +500,54 @@ impl SessionBuilder {
             cancellation_token.handle().raw(),
         );
 
-        unsafe {
-            let result = sessions::raw::session_begin(
-                0,
+        let result = guard.call(|| {
+            self.api.session_begin(
                 proto_buf.as_byte_array(),
                 request_callback,
                 secret_callback,
                 two_factor_callback,
                 tokens_callback,
                 async_callback,
-            )?;
+            )
+        })?;
 
-            if result != 0 {
-                return Err(SessionError::OperationFailed(result));
-            }
+        if result != 0 {
+            return Err(SessionError::OperationFailed(SdkErrorDetails::from_code(result)));
         }
 
-        let session_handle = rx.await.map_err(|_| SessionError::Cancelled)??;
+        let session_handle = guard.completion().await?;
 
         Ok(Session {
             handle: session_handle,
-            _callback_data: Some(callback_data),
+            _callbacks: Some(guard),
             cancellation_token,
+            logger_provider,
+            api: self.api,
         })
     }
 
     // Resumes an existing session
     pub async fn resume_session(
-        mut request: SessionResumeRequest,
+        request: SessionResumeRequest,
         callbacks: SessionCallbacks,
         platform: SessionPlatform,
         app_name: &str,
         app_version: &str,
         // password: String,
     ) -> Result<Session, SessionError> {
+        Self::resume_session_with_api(LibloadingApi::shared(), request, callbacks, platform, app_name, app_version).await
+    }
+
+    /// Resumes an existing session, making the SDK calls through `api`
+    pub async fn resume_session_with_api(
+        api: Arc<dyn SdkApi>,
+        mut request: SessionResumeRequest,
+        callbacks: SessionCallbacks,
+        platform: SessionPlatform,
+        app_name: &str,
+        app_version: &str,
+    ) -> Result<Session, SessionError> {
+        request.validate()?;
         if let Some(ref mut options) = request.options {
             let version = format!("external-drive-{}_{}@{}", app_name, platform, app_version);
             options.app_version = version.to_string();
@@ -493,50 +561,45 @@ impl SessionBuilder {
         info!(
             "App version: external-drive-{}_{}@{}", app_name, platform, app_version
         );
-        
-        let proto_buf = request.to_proto_buffer()?;
 
-        let (tx, rx) = tokio::sync::oneshot::channel();
-        let tx = Arc::new(Mutex::new(Some(tx)));
+        let logger_provider = request.options.as_mut().and_then(attach_installed_logger);
 
-        let callback_data = Box::new(CallbackData {
-            request_response: callbacks.request_response,
-            secret_requested: callbacks.secret_requested,
-            two_factor_requested: callbacks.two_factor_requested,
-            tokens_refreshed: callbacks.tokens_refreshed,
-            completion_sender: tx,
-        });
+        let proto_buf = request.to_sensitive_proto_buffer()?;
 
-        let callback_ptr = callback_data.as_ref() as *const CallbackData as *const c_void;
+        // resuming answers right away, nothing is sent
+        let guard = CallbackGuard::<Result<SessionHandle, SessionError>, _>::new(SessionCallbackData::from(callbacks));
+        let state = guard.thread_safe_state();
 
-        let request_callback = Callback::new(callback_ptr, Some(request_response_c_callback));
-        let secret_callback = BooleanCallback::new(callback_ptr, Some(secret_requested_c_callback));
-        let tokens_callback = Callback::new(callback_ptr, Some(tokens_refreshed_c_callback));
+        let request_callback = Callback::checked(state, request_response_c_callback);
+        let secret_callback = BooleanCallback::checked(state, secret_requested_c_callback);
+        let tokens_callback = Callback::checked(state, tokens_refreshed_c_callback);
 
-        let cancellation_token = CancellationToken::new().map_err(|e| SessionError::SdkError(e))?;
+        let cancellation_token = CancellationToken::with_api(api.clone()).map_err(|e| SessionError::SdkError(e))?;
 
-        unsafe {
-            let (result, session_handle) = sessions::raw::session_resume(
-                proto_buf.as_byte_array(),
-                request_callback,
-                secret_callback,
-                tokens_callback,
-            )?;
+        let (result, session_handle) = api.session_resume(
+            proto_buf.as_byte_array(),
+            request_callback,
+            secret_callback,
+            tokens_callback,
+        )?;
 
-            if result != 0 {
-                return Err(SessionError::OperationFailed(result));
-            }
+        if result != 0 {
+            // SAFETY: no session was resumed to call back
+            unsafe { guard.revoke() };
+            return Err(SessionError::OperationFailed(SdkErrorDetails::from_code(result)));
+        }
 
-            let return_val = Session {
-                handle: session_handle,
-                _callback_data: Some(callback_data),
-                cancellation_token,
-            };
+        let return_val = Session {
+            handle: session_handle,
+            _callbacks: Some(guard),
+            cancellation_token,
+            logger_provider,
+            api,
+        };
 
-            // return_val.apply_data_password(password.as_str())?;
+        // return_val.apply_data_password(password.as_str())?;
 
-            Ok(return_val)
-        }
+        Ok(return_val)
     }
 
     /// Renew an existing session
@@ -549,230 +612,110 @@ impl SessionBuilder {
             return Err(SessionError::NullHandle);
         }
 
-        let proto_buf = request.to_proto_buffer()?;
+        let proto_buf = request.to_sensitive_proto_buffer()?;
 
-        let callback_data = if let Some(callback) = tokens_refreshed_callback {
-            Some(Box::new(CallbackData {
+        let guard = tokens_refreshed_callback.map(|callback| {
+            CallbackGuard::new(SessionCallbackData::from(SessionCallbacks {
                 request_response: None,
                 secret_requested: None,
                 two_factor_requested: None,
                 tokens_refreshed: Some(callback),
-                completion_sender: Arc::new(std::sync::Mutex::new(None)),
             }))
-        } else {
-            None
-        };
+        });
 
-        let callback_ptr = callback_data
-            .as_ref()
-            .map(|data| data.as_ref() as *const CallbackData as *const c_void)
-            .unwrap_or(std::ptr::null());
+        let state = guard.as_ref().map(CallbackGuard::thread_safe_state).unwrap_or_else(ThreadSafeState::none);
 
-        let tokens_callback = Callback::new(callback_ptr, Some(tokens_refreshed_c_callback));
+        let tokens_callback = Callback::checked(state, tokens_refreshed_c_callback);
 
         let cancellation_token = old_session.cancellation_token.clone();
 
-        unsafe {
-            let (result, new_session_handle) = sessions::raw::session_renew(
-                old_session.handle,
-                proto_buf.as_byte_array(),
-                tokens_callback,
-            )?;
+        let (result, new_session_handle) = old_session.api.session_renew(
+            old_session.handle,
+            proto_buf.as_byte_array(),
+            tokens_callback,
+        )?;
 
-            if result != 0 {
-                return Err(SessionError::OperationFailed(result));
+        if result != 0 {
+            if let Some(guard) = &guard {
+                // SAFETY: no session was renewed to call back
+                unsafe { guard.revoke() };
             }
-
-            Ok(Session {
-                handle: new_session_handle,
-                _callback_data: callback_data,
-                cancellation_token,
-            })
-        }
-    }
-}
-
-unsafe fn parse_session_handle(response: &ByteArray) -> Result<SessionHandle, String> {
-    let response_slice = response.as_slice();
-
-    if response_slice.is_empty() {
-        return Err("Empty response".to_string());
-    }
-
-    trace!("Response data: {} bytes", response_slice.len());
-
-    // Try to parse as protobuf IntResponse first
-    use proton_sdk_sys::protobufs::FromByteArray;
-    if let Ok(int_response) = proton_sdk_sys::protobufs::IntResponse::from_byte_array(response) {
-        trace!("Parsed as IntResponse: value = {}", int_response.value);
-        return Ok(SessionHandle::from(int_response.value as isize));
-    }
-
-    // Try to parse as protobuf SessionTokens
-    if let Ok(session_tokens) = proton_sdk_sys::protobufs::SessionTokens::from_byte_array(response)
-    {
-        trace!("Parsed as SessionTokens - using access token hash as handle");
-        let handle_value = session_tokens
-            .access_token
-            .as_bytes()
-            .iter()
-            .fold(0i64, |acc, &b| acc.wrapping_mul(31).wrapping_add(b as i64));
-        return Ok(SessionHandle::from(handle_value as isize));
-    }
-
-    // Try to interpret as raw bytes (lil indian)
-    if response_slice.len() >= 8 {
-        let handle_bytes = [
-            response_slice[0],
-            response_slice[1],
-            response_slice[2],
-            response_slice[3],
-            response_slice[4],
-            response_slice[5],
-            response_slice[6],
-            response_slice[7],
-        ];
-        let handle_value = i64::from_le_bytes(handle_bytes);
-        println!("Parsed as raw i64: {}", handle_value);
-        return Ok(SessionHandle::from(handle_value as isize));
-    }
-
-    // Try to interpret as raw bytes (big indian)
-    if response_slice.len() >= 8 {
-        let handle_bytes = [
-            response_slice[0],
-            response_slice[1],
-            response_slice[2],
-            response_slice[3],
-            response_slice[4],
-            response_slice[5],
-            response_slice[6],
-            response_slice[7],
-        ];
-        let handle_value = i64::from_be_bytes(handle_bytes);
-        trace!("Parsed as raw i64 (big-endian): {}", handle_value);
-        return Ok(SessionHandle::from(handle_value as isize));
-    }
-
-    // Try as string that might contain a number
-    if let Ok(response_str) = std::str::from_utf8(response_slice) {
-        if let Ok(handle_value) = response_str.trim().parse::<isize>() {
-            trace!("Parsed as string number: {}", handle_value);
-            return Ok(SessionHandle::from(handle_value));
+            return Err(SessionError::OperationFailed(SdkErrorDetails::from_code(result)));
         }
-    }
 
-    if response_slice.len() <= 50 {
-        trace!("Response hex dump: {:02x?}", response_slice);
-    } else {
-        trace!(
-            "Response hex dump (first 50 bytes): {:02x?}",
-            &response_slice[..50]
-        );
+        Ok(Session {
+            handle: new_session_handle,
+            _callbacks: guard,
+            cancellation_token,
+            logger_provider: old_session.logger_provider,
+            api: old_session.api.clone(),
+        })
     }
+}
 
-    Err(format!(
-        "Could not parse session handle from {} bytes",
-        response_slice.len()
-    ))
+/// Routes the native SDK logs of a session through the installed [`SdkLogger`],
+/// unless the options already name a logger provider
+///
+/// Returns the logger provider the session will use, if any
+fn attach_installed_logger(options: &mut ProtonClientOptions) -> Option<LoggerProviderHandle> {
+    if options.logger_provider_handle.is_none() {
+        options.logger_provider_handle =
+            SdkLogger::installed_handle().map(|handle| handle.raw() as i64);
+    }
+    options
+        .logger_provider_handle
+        .map(|handle| LoggerProviderHandle::from(handle as isize))
 }
 
 extern "C" fn request_response_c_callback(state: *const c_void, data: ByteArray) {
-    if !state.is_null() {
-        unsafe {
-            let callback_data = &*(state as *const CallbackData);
-            if let Some(ref callback) = callback_data.request_response {
-                let slice = data.as_slice();
-                callback(slice);
-            }
+    if let Some(state) = unsafe { SessionState::from_raw(state) } {
+        if let Some(ref callback) = state.data().callbacks.request_response {
+            callback(data.view().as_slice());
         }
     }
 }
 
 extern "C" fn secret_requested_c_callback(state: *const c_void, _data: ByteArray) -> bool {
-    if !state.is_null() {
-        unsafe {
-            let callback_data = &*(state as *const CallbackData);
-            if let Some(ref callback) = callback_data.secret_requested {
-                return callback();
-            }
+    if let Some(state) = unsafe { SessionState::from_raw(state) } {
+        if let Some(ref callback) = state.data().callbacks.secret_requested {
+            return callback();
         }
     }
     false
 }
 
 extern "C" fn tokens_refreshed_c_callback(state: *const c_void, data: ByteArray) {
-    if !state.is_null() {
-        unsafe {
-            let callback_data = &*(state as *const CallbackData);
-            if let Some(ref callback) = callback_data.tokens_refreshed {
-                let slice = data.as_slice();
-                callback(slice);
+    use proton_sdk_sys::protobufs::FromByteArray;
+
+    if let Some(state) = unsafe { SessionState::from_raw(state) } {
+        if let Some(ref callback) = state.data().callbacks.tokens_refreshed {
+            let slice = data.view().as_slice();
+            if let Ok(tokens) = proton_sdk_sys::protobufs::account::SessionTokens::from_bytes(slice) {
+                trace!("Tokens refreshed: {:?}", tokens.redacted());
             }
+            callback(slice);
         }
     }
 }
 
-#[no_mangle]
-pub extern "C" fn proton_sdk_free(ptr: *mut u8) {
-    if !ptr.is_null() {
-        unsafe { Box::from_raw(ptr); }
-    }
-}
-
 extern "C" fn two_factor_requested_c_callback(
     state: *const c_void,
     context: ByteArray,
     out_code: *mut ByteArray,
     data_pass: *mut ByteArray,
 ) -> bool {
-    if !state.is_null() {
-        unsafe {
-            let callback_data = &*(state as *const CallbackData);
-            if let Some(ref callback) = callback_data.two_factor_requested {
-                let input = context.as_slice();
-                let (code_opt, pass_opt) = callback(input);
-                let mut any_set = false;
-
-                if !out_code.is_null() {
-                    if let Some(code) = code_opt {
-                        if let Ok(proto_buf) = code.to_proto_buffer() {
-                            let bytes = proto_buf.as_byte_array();
-                            let vec = std::slice::from_raw_parts(bytes.pointer, bytes.length).to_vec();
-                            let boxed = vec.into_boxed_slice();
-                            let ptr = Box::into_raw(boxed) as *const u8;
-                            *out_code = ByteArray {
-                                pointer: ptr,
-                                length: bytes.length,
-                            };
-                            trace!("Allocated out_code at {:p} ({} bytes)", ptr, bytes.length);
-                            any_set = true;
-                        }
-                    }
-                }
-
-                if !data_pass.is_null() {
-                    if let Some(pass) = pass_opt {
-                        if let Ok(proto_buf) = pass.to_proto_buffer() {
-                            let bytes = proto_buf.as_byte_array();
-                            let vec = std::slice::from_raw_parts(bytes.pointer, bytes.length).to_vec();
-                            let boxed = vec.into_boxed_slice();
-                            let ptr = Box::into_raw(boxed) as *const u8;
-                            *data_pass = ByteArray {
-                                pointer: ptr,
-                                length: bytes.length,
-                            };
-                            trace!("Allocated data_pass at {:p} ({} bytes)", ptr, bytes.length);
-                            any_set = true;
-                        }
-                    }
-                }
-
-                return any_set;
-            }
-        }
-    }
-    false
+    let Some(state) = (unsafe { SessionState::from_raw(state) }) else {
+        return false;
+    };
+    let Some(ref callback) = state.data().callbacks.two_factor_requested else {
+        return false;
+    };
+    let (code, password) = callback(context.view().as_slice());
+    let answers = &state.data().two_factor_answers;
+    // both are answered even when the code isn't, the SDK frees them after reading them
+    let code_set = unsafe { answers.answer(out_code, code.as_ref()) };
+    let password_set = unsafe { answers.answer(data_pass, password.as_ref()) };
+    code_set || password_set
 }
 
 pub enum SessionPlatform {
@@ -797,41 +740,70 @@ impl fmt::Display for SessionPlatform {
     }
 }
 
-fn parse_sdk_error(error_data: &ByteArray) -> (i32, String) {
-    unsafe {
-        let error_slice = error_data.as_slice();
-
-        if error_slice.is_empty() {
-            return (-1, "Unknown error - no details provided".to_string());
-        }
-
-        // Try protobuf Error first
-        use proton_sdk_sys::protobufs::FromByteArray;
-        if let Ok(error_proto) = proton_sdk_sys::protobufs::Error::from_byte_array(error_data) {
-            return (error_proto.primary_code() as i32, error_proto.message);
-        }
-
-        // Try as UTF-8 string
-        if let Ok(error_str) = std::str::from_utf8(error_slice) {
-            // Check if it's JSON
-            if error_str.starts_with('{') {
-                return (-1, format!("JSON Error: {}", error_str));
-            }
-            return (-1, error_str.to_string());
-        }
-
-        // Last resort: hex dump
-        if error_slice.len() <= 50 {
-            return (-1, format!("Binary error data: {:02x?}", error_slice));
-        } else {
-            return (
-                -1,
-                format!(
-                    "Binary error data ({} bytes): {:02x?}...",
-                    error_slice.len(),
-                    &error_slice[..20]
-                ),
-            );
-        }
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use proton_sdk_sys::{
+        api::{MockApi, Reply},
+        prost::Message,
+        protobufs::account::{Error, IntResponse},
+    };
+
+    fn builder(mock: &Arc<MockApi>) -> SessionBuilder {
+        SessionBuilder::new("alice@proton.me".to_string(), "hunter2".to_string()).with_api(mock.clone())
+    }
+
+    /// A session begun against `mock`
+    pub(crate) async fn mock_session(mock: &Arc<MockApi>) -> Session {
+        builder(mock).begin().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn begin_reports_what_the_sdk_answered() {
+        let mock = Arc::new(MockApi::new());
+        let unauthorized = Error { primary_code: Some(401), message: "Incorrect login".to_string(), ..Default::default() };
+        mock.reply("session_begin", Reply::Failure(unauthorized.encode_to_vec()))
+            .reply("session_begin", Reply::Code(3))
+            .reply("session_begin", Reply::Success(IntResponse { value: 42 }.encode_to_vec()));
+
+        let error = builder(&mock).begin().await.err().unwrap();
+        assert!(matches!(&error, SessionError::OperationFailed(SdkErrorDetails::Unauthorized { message }) if message == "Incorrect login"));
+        assert_eq!(error.kind(), SdkErrorKind::Authentication);
+        let error = builder(&mock).begin().await.err().unwrap();
+        assert!(matches!(error, SessionError::OperationFailed(details) if details.code() == Some(3)));
+
+        let session = builder(&mock).begin().await.unwrap();
+        assert_eq!(session.handle(), SessionHandle::from(42));
+        let request = SessionBeginRequest::decode(mock.calls_to("session_begin")[2].request.as_slice()).unwrap();
+        assert_eq!((request.username.as_str(), request.password.as_str()), ("alice@proton.me", "hunter2"));
+    }
+
+    #[tokio::test]
+    async fn dropping_the_session_frees_it_and_its_token() {
+        let mock = Arc::new(MockApi::new());
+        let session = mock_session(&mock).await;
+        let (handle, token) = (session.handle().raw(), session.cancellation_token().handle().raw());
+        assert!(mock.calls_to("session_free").is_empty());
+
+        drop(session);
+        let freed = |name| mock.calls_to(name).iter().map(|call| call.handle).collect::<Vec<_>>();
+        assert_eq!(freed("session_free"), [handle]);
+        assert_eq!(freed("cancellation_token_source_free"), [token]);
+    }
+
+    #[tokio::test]
+    async fn key_registration_failures_are_errors() {
+        let mock = Arc::new(MockApi::new());
+        let session = mock_session(&mock).await;
+        mock.reply("session_register_address_keys", Reply::Code(22))
+            .reply("session_register_address_keys", Reply::Missing);
+
+        let request = AddressKeyRegistrationRequest::default();
+        let error = session.register_address_keys(&request).unwrap_err();
+        assert!(matches!(error, SessionError::OperationFailed(details) if details.code() == Some(22)));
+        let error = session.register_address_keys(&request).unwrap_err();
+        assert!(matches!(error, SessionError::SdkError(_)));
+        assert!(error.to_string().contains("session_register_address_keys"));
+        assert!(session.register_address_keys(&request).is_ok());
     }
 }
@@ -0,0 +1,182 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use log::debug;
+use proton_sdk_sys::protobufs::{
+    account::{SessionInfo, SessionTokens},
+    FromByteArray, ToByteArray,
+};
+
+use super::SessionError;
+
+/// Where sessions are saved when no path is given
+pub const DEFAULT_SESSION_FILE: &str = "session_info.bin";
+
+/// Keeps an encoded [`SessionInfo`] in a file so sessions can be resumed
+///
+/// The file holds the access and refresh tokens, so it is written with owner
+/// only permissions on Unix and replaced atomically.
+#[derive(Debug, Clone)]
+pub struct FileSessionStore {
+    path: PathBuf,
+}
+
+impl Default for FileSessionStore {
+    fn default() -> Self {
+        Self::new(DEFAULT_SESSION_FILE)
+    }
+}
+
+impl FileSessionStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reads the saved session, `None` when there is none
+    pub fn load(&self) -> Result<Option<SessionInfo>, SessionError> {
+        let bytes = match fs::read(&self.path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Some(SessionInfo::from_bytes_strict(&bytes)?))
+    }
+
+    /// Saves a session, replacing the previous one
+    pub fn save(&self, info: &SessionInfo) -> Result<(), SessionError> {
+        let bytes = info.to_bytes()?;
+        let temp_path = self.temp_path();
+
+        let mut file = owner_only_options().open(&temp_path)?;
+        file.write_all(&bytes)?;
+        file.sync_all()?;
+        drop(file);
+
+        fs::rename(&temp_path, &self.path)?;
+        debug!("Saved session to {}", self.path.display());
+        Ok(())
+    }
+
+    /// Swaps the tokens of the saved session for refreshed ones
+    ///
+    /// Returns `false` when there is no saved session to update.
+    pub fn update_tokens(&self, tokens: &SessionTokens) -> Result<bool, SessionError> {
+        let Some(mut info) = self.load()? else {
+            return Ok(false);
+        };
+
+        info.access_token = tokens.access_token.clone();
+        info.refresh_token = tokens.refresh_token.clone();
+        self.save(&info)?;
+        Ok(true)
+    }
+
+    /// Deletes the saved session, if any
+    pub fn clear(&self) -> Result<(), SessionError> {
+        match fs::remove_file(&self.path) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn temp_path(&self) -> PathBuf {
+        let mut name = self
+            .path
+            .file_name()
+            .map(|name| name.to_os_string())
+            .unwrap_or_default();
+        name.push(".tmp");
+        self.path.with_file_name(name)
+    }
+}
+
+#[cfg(unix)]
+fn owner_only_options() -> OpenOptions {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true).mode(0o600);
+    options
+}
+
+#[cfg(not(unix))]
+fn owner_only_options() -> OpenOptions {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    options
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proton_sdk_sys::protobufs::account::SessionId;
+
+    fn store(name: &str) -> FileSessionStore {
+        let dir = std::env::temp_dir().join(format!("proton-sdk-session-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let store = FileSessionStore::new(dir.join(name));
+        store.clear().unwrap();
+        store
+    }
+
+    fn info() -> SessionInfo {
+        SessionInfo {
+            session_id: Some(SessionId { value: "session".to_string() }),
+            username: "user".to_string(),
+            access_token: "access".to_string(),
+            refresh_token: "refresh".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn sessions_round_trip() {
+        let store = store("round-trip.bin");
+        assert_eq!(store.load().unwrap(), None);
+
+        store.save(&info()).unwrap();
+        assert_eq!(store.load().unwrap(), Some(info()));
+
+        store.clear().unwrap();
+        assert_eq!(store.load().unwrap(), None);
+        store.clear().unwrap();
+    }
+
+    #[test]
+    fn refreshed_tokens_replace_the_saved_ones() {
+        let store = store("refresh.bin");
+        let tokens = SessionTokens {
+            access_token: "new access".to_string(),
+            refresh_token: "new refresh".to_string(),
+        };
+        assert!(!store.update_tokens(&tokens).unwrap());
+
+        store.save(&info()).unwrap();
+        assert!(store.update_tokens(&tokens).unwrap());
+
+        let saved = store.load().unwrap().unwrap();
+        assert_eq!(saved.access_token, "new access");
+        assert_eq!(saved.refresh_token, "new refresh");
+        assert_eq!(saved.session_id, info().session_id);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn session_files_are_owner_only() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let store = store("permissions.bin");
+        store.save(&info()).unwrap();
+
+        let mode = fs::metadata(store.path()).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
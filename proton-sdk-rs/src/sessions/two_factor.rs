@@ -0,0 +1,162 @@
+use std::{
+    alloc::{self, Layout},
+    collections::BTreeSet,
+    mem,
+    sync::{Mutex, PoisonError},
+};
+
+use log::{trace, warn};
+use proton_sdk_sys::{
+    data::ByteArray,
+    prost::Message,
+    protobufs::account::StringResponse,
+};
+
+/// Buffers handed to the SDK and not freed yet, by address
+static ALLOCATED: Mutex<BTreeSet<usize>> = Mutex::new(BTreeSet::new());
+
+/// Bytes in front of every buffer, holding its length
+const HEADER: usize = mem::size_of::<usize>();
+
+fn layout(length: usize) -> Layout {
+    Layout::from_size_align(HEADER + length, mem::align_of::<usize>()).expect("buffer too large")
+}
+
+/// Copies `bytes` into a buffer [`proton_sdk_free`] can free, which records its own length
+fn allocate(bytes: &[u8]) -> *mut u8 {
+    let layout = layout(bytes.len());
+    unsafe {
+        let start = alloc::alloc(layout);
+        if start.is_null() {
+            alloc::handle_alloc_error(layout);
+        }
+        (start as *mut usize).write(bytes.len());
+        let pointer = start.add(HEADER);
+        pointer.copy_from_nonoverlapping(bytes.as_ptr(), bytes.len());
+        ALLOCATED.lock().unwrap_or_else(PoisonError::into_inner).insert(pointer as usize);
+        pointer
+    }
+}
+
+/// Zeroes and frees a buffer of [`allocate`], false when it isn't one or was freed already
+fn release(pointer: *mut u8) -> bool {
+    if !ALLOCATED.lock().unwrap_or_else(PoisonError::into_inner).remove(&(pointer as usize)) {
+        return false;
+    }
+    unsafe {
+        let start = pointer.sub(HEADER);
+        let length = (start as *const usize).read();
+        // the answers hold two-factor codes
+        #[cfg(feature = "zeroize")]
+        zeroize::Zeroize::zeroize(std::slice::from_raw_parts_mut(pointer, length));
+        #[cfg(not(feature = "zeroize"))]
+        pointer.write_bytes(0, length);
+        alloc::dealloc(start, layout(length));
+    }
+    true
+}
+
+/// Frees a buffer the bindings gave the SDK, like the answers to a two-factor request
+///
+/// Anything else, null included, is ignored, as is a buffer freed twice.
+#[no_mangle]
+pub extern "C" fn proton_sdk_free(ptr: *mut u8) {
+    if !ptr.is_null() && !release(ptr) {
+        warn!("Ignoring a request to free {:p}, which the bindings didn't allocate or already freed", ptr);
+    }
+}
+
+/// The answers given to a session's two-factor requests
+///
+/// The SDK frees each answer with [`proton_sdk_free`] once it read it. What it
+/// didn't free is freed along with this, so nothing leaks when it doesn't.
+#[derive(Debug, Default)]
+pub struct TwoFactorResponseBuffers {
+    handed_out: Mutex<Vec<usize>>,
+}
+
+impl TwoFactorResponseBuffers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Writes the encoded `response` to the out parameter `out`, returning whether it did
+    ///
+    /// # Safety
+    /// `out` has to be null or valid for writing a `ByteArray`.
+    pub unsafe fn answer(&self, out: *mut ByteArray, response: Option<&StringResponse>) -> bool {
+        let Some(response) = response else {
+            return false;
+        };
+        if out.is_null() {
+            return false;
+        }
+        let bytes = response.encode_to_vec();
+        let pointer = allocate(&bytes);
+        self.handed_out.lock().unwrap_or_else(PoisonError::into_inner).push(pointer as usize);
+        out.write(ByteArray { pointer, length: bytes.len() });
+        trace!("Answered the two-factor request with {} bytes at {:p}", bytes.len(), pointer);
+        true
+    }
+
+    /// Answers the SDK holds and hasn't freed yet
+    pub fn outstanding(&self) -> usize {
+        let allocated = ALLOCATED.lock().unwrap_or_else(PoisonError::into_inner);
+        let handed_out = self.handed_out.lock().unwrap_or_else(PoisonError::into_inner);
+        handed_out.iter().filter(|pointer| allocated.contains(pointer)).count()
+    }
+}
+
+impl Drop for TwoFactorResponseBuffers {
+    fn drop(&mut self) {
+        let handed_out = self.handed_out.get_mut().unwrap_or_else(PoisonError::into_inner);
+        for pointer in handed_out.drain(..) {
+            if release(pointer as *mut u8) {
+                trace!("Freed a two-factor answer the SDK didn't free");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(value: &str) -> StringResponse {
+        StringResponse { value: value.to_string() }
+    }
+
+    // a single test, as others allocating in parallel could be handed the addresses freed here
+    #[test]
+    fn answers_are_freed_by_the_sdk_or_with_the_buffers() {
+        let buffers = TwoFactorResponseBuffers::new();
+        let mut code = ByteArray::empty();
+        let mut password = ByteArray::empty();
+        unsafe {
+            assert!(buffers.answer(&mut code, Some(&response("123456"))));
+            assert!(buffers.answer(&mut password, Some(&response("hunter2"))));
+            // an empty answer encodes to nothing, but is a buffer to free all the same
+            let mut empty = ByteArray::empty();
+            assert!(buffers.answer(&mut empty, Some(&response(""))));
+            assert!(empty.is_empty() && !empty.pointer.is_null());
+            proton_sdk_free(empty.pointer as *mut u8);
+            assert!(!buffers.answer(std::ptr::null_mut(), Some(&response("123456"))));
+            assert!(!buffers.answer(&mut ByteArray::empty(), None));
+        }
+        assert_eq!(StringResponse::decode(code.view().as_slice()).unwrap(), response("123456"));
+        assert_eq!(buffers.outstanding(), 2);
+
+        proton_sdk_free(code.pointer as *mut u8);
+        assert_eq!(buffers.outstanding(), 1);
+        // a second free, or one of a buffer the bindings don't know, is ignored
+        proton_sdk_free(code.pointer as *mut u8);
+        proton_sdk_free(std::ptr::null_mut());
+        let foreign = [0u8; 8];
+        proton_sdk_free(foreign.as_ptr() as *mut u8);
+        assert_eq!(buffers.outstanding(), 1);
+
+        // the SDK kept the password
+        drop(buffers);
+        assert!(!ALLOCATED.lock().unwrap().contains(&(password.pointer as usize)));
+    }
+}
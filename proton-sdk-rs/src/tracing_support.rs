@@ -0,0 +1,18 @@
+//! Helpers for the span fields recorded when the `tracing` feature is enabled
+
+use proton_sdk_sys::protobufs::{account::OperationIdentifier, drive::NodeIdentity};
+
+/// Returns the link id of a node identity, or an empty string when it's missing
+pub(crate) fn node_id(identity: Option<&NodeIdentity>) -> &str {
+    identity
+        .and_then(|identity| identity.node_id.as_ref())
+        .map(|id| id.value.as_str())
+        .unwrap_or_default()
+}
+
+/// Returns the identifier of an operation, or an empty string when it's missing
+pub(crate) fn operation_id(operation: Option<&OperationIdentifier>) -> &str {
+    operation
+        .map(|operation| operation.identifier.as_str())
+        .unwrap_or_default()
+}
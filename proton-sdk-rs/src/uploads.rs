@@ -1,18 +1,25 @@
-use std::ffi::c_void;
+mod request;
+
+use std::{ffi::c_void, path::PathBuf, sync::Arc};
 use log::{debug, error};
-use tokio::sync::oneshot;
 use proton_sdk_sys::{
-    data::{AsyncCallback, AsyncCallbackWithProgress, ByteArray, Callback},
+    api::{LibloadingApi, SdkApi},
+    data::{AsyncCallback, AsyncCallbackWithProgress, ByteArray, Callback, CallbackGuard, CallbackState},
     drive::DriveClientHandle,
-    protobufs::{FileNode, FileUploadRequest, FileUploaderCreationRequest, IntResponse, Revision},
-    uploads::{raw, UploaderHandle},
+    protobufs::{drive::{FileNode, FileUploadRequest, FileUploaderCreationRequest, NodeIdentity, Revision}},
+    uploads::UploaderHandle,
     cancellation::CancellationTokenHandle,
     prost::Message,
-    protobufs::ToByteArray,
+    protobufs::{validation::Validate, ToByteArray},
 };
-use proton_sdk_sys::protobufs::{FromByteArray, ProgressUpdate};
+use crate::cancellation::CancellationToken;
 use crate::downloads::{DownloadError, Downloader, DownloaderBuilder};
 use crate::drive::DriveClient;
+use crate::errors::SdkErrorDetails;
+use crate::progress::{self, TransferProgress};
+use crate::responses;
+
+pub use self::request::FileUploadRequestBuilder;
 
 #[derive(Debug, thiserror::Error)]
 pub enum UploadError {
@@ -24,19 +31,34 @@ pub enum UploadError {
     Failure(i32),
     #[error("Callback channel closed")]
     CallbackClosed,
+    #[error("Uploader creation timed out")]
+    CreationTimeout,
     #[error("Uploader handle is null")]
     NullHandle,
+    #[error("Invalid request: {0}")]
+    InvalidRequest(#[from] proton_sdk_sys::protobufs::validation::ValidationError),
+    #[error("Unusable source file {}: {source}", path.display())]
+    Source { path: PathBuf, source: std::io::Error },
 }
 
-struct UploadState<F: Fn(f32) + Send + 'static> {
-    result_sender: oneshot::Sender<Result<FileNode, UploadError>>,
-    progress_callback: Option<F>,
+impl UploadError {
+    /// What the SDK reported, when it failed the call through a callback
+    pub fn details(&self) -> Option<&SdkErrorDetails> {
+        match self {
+            UploadError::Ffi(error) => error.downcast_ref(),
+            _ => None,
+        }
+    }
 }
 
+/// State of an upload's callbacks, the progress callback besides the result
+type UploadState<T, F> = CallbackState<Result<T, UploadError>, Option<F>>;
+
 pub struct Uploader {
     handle: UploaderHandle,
     _client: DriveClientHandle,
     _token: CancellationTokenHandle,
+    api: Arc<dyn SdkApi>,
 }
 
 impl Uploader {
@@ -45,133 +67,180 @@ impl Uploader {
         request: FileUploaderCreationRequest,
         token: CancellationTokenHandle,
     ) -> Result<Self, UploadError> {
+        Self::with_api(LibloadingApi::shared(), client, request, token).await
+    }
+
+    /// Creates an uploader for `client`, making the SDK calls through `api`
+    ///
+    /// Its uploads are cancelled with `token`. The creation has a token of its own,
+    /// cancelled when it times out or its future is dropped unfinished.
+    pub async fn with_api(
+        api: Arc<dyn SdkApi>,
+        client: DriveClientHandle,
+        request: FileUploaderCreationRequest,
+        token: CancellationTokenHandle,
+    ) -> Result<Self, UploadError> {
+        request.validate()?;
         let proto_buf = request.to_proto_buffer()?;
-        let (tx, rx) = oneshot::channel::<Result<UploaderHandle, UploadError>>();
-        let tx = Box::new(tx);
-        let tx_ptr = Box::into_raw(tx);
+        // dropped after the guard, which cancels it
+        let creation_token = CancellationToken::with_api(api.clone())?;
+        let guard = CallbackGuard::<Result<UploaderHandle, UploadError>>::new(())
+            .cancel_on_drop(api.clone(), creation_token.handle());
 
         extern "C" fn success_callback(state: *const c_void, response: ByteArray) {
-            if !state.is_null() {
-                unsafe {
-                    let tx_ptr = state as *mut oneshot::Sender<Result<UploaderHandle, UploadError>>;
-                    let tx = Box::from_raw(tx_ptr);
-                    let response = response.as_slice();
-                    let handle = match IntResponse::decode(response) {
-                        Ok(val) => UploaderHandle::from(val.value as isize),
+            unsafe {
+                CallbackState::<Result<UploaderHandle, UploadError>>::finish(state, |state| {
+                    let handle = match responses::created_handle(&response.view()) {
+                        Ok(val) => UploaderHandle::from(val),
                         Err(e) => {
-                            let _ = tx.send(Err(UploadError::Protobuf(e.into())));
+                            state.send(Err(UploadError::Protobuf(e)));
                             return;
                         }
                     };
                     debug!("Uploader created with handle: {:?}", handle);
-                    let _ = tx.send(Ok(handle));
-                }
+                    state.send(Ok(handle));
+                })
             }
         }
 
         extern "C" fn failure_callback(state: *const c_void, error_data: ByteArray) {
-            if !state.is_null() {
-                unsafe {
-                    let tx_ptr = state as *mut oneshot::Sender<Result<UploaderHandle, UploadError>>;
-                    let tx = Box::from_raw(tx_ptr);
-                    let error_msg = String::from_utf8_lossy(error_data.as_slice()).to_string();
-                    error!("Uploader creation failed: {}", error_msg);
-                    let _ = tx.send(Err(UploadError::Ffi(anyhow::anyhow!(error_msg))));
-                }
+            unsafe {
+                CallbackState::<Result<UploaderHandle, UploadError>>::finish(state, |state| {
+                    let details = SdkErrorDetails::decode(&error_data.view());
+                    error!("Uploader creation failed: {}", details);
+                    state.send(Err(UploadError::Ffi(details.into())));
+                })
             }
         }
 
         let async_callback = AsyncCallback::new(
-            tx_ptr as *const c_void,
+            guard.state(),
             Some(success_callback),
             Some(failure_callback),
-            0, // No cancellation token for now
+            creation_token.handle().raw(),
         );
 
-        let code = raw::uploader_create(client, proto_buf.as_byte_array(), async_callback)?;
+        let code = guard.call(|| api.uploader_create(client, proto_buf.as_byte_array(), async_callback))?;
         if code != 0 {
-            unsafe { let _ = Box::from_raw(tx_ptr); }
             return Err(UploadError::Failure(code));
         }
 
-        let handle = rx.await.map_err(|_| UploadError::CallbackClosed)??;
+        // dropping the guard on a timeout cancels the creation
+        let handle = tokio::time::timeout(std::time::Duration::from_secs(30), guard.completion())
+            .await
+            .unwrap_or(Err(UploadError::CreationTimeout))?;
         if handle.is_null() {
             return Err(UploadError::NullHandle);
         }
-        Ok(Uploader { handle, _client: client, _token: token })
+        Ok(Uploader { handle, _client: client, _token: token, api })
     }
 
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "upload",
+            skip_all,
+            fields(
+                operation_id = crate::tracing_support::operation_id(request.operation_id.as_ref()),
+                parent_id = crate::tracing_support::node_id(request.parent_folder_identity.as_ref()),
+                node_id = tracing::field::Empty,
+                bytes = tracing::field::Empty,
+            )
+        )
+    )]
     pub async fn upload_file_or_revision<F>(
         &self,
         request: FileUploadRequest,
         progress_callback: Option<F>,
     ) -> Result<FileNode, UploadError>
     where
-        F: Fn(f32) + Send + 'static,
+        F: Fn(TransferProgress) + Send + Sync + 'static,
     {
+        request.validate()?;
         let is_progress_callback = progress_callback.is_some();
 
         let proto_buf = request.to_proto_buffer()?;
-        let (tx, rx) = oneshot::channel::<Result<FileNode, UploadError>>();
-
-        let state = Box::new(UploadState {
-            result_sender: tx,
-            progress_callback,
-        });
-        let state_ptr = Box::into_raw(state);
+        // the uploader's token is shared by its other uploads, so a dropped upload doesn't cancel it
+        let guard = CallbackGuard::<Result<FileNode, UploadError>, _>::new(progress_callback);
 
-        extern "C" fn success_callback<F: Fn(f32) + Send + 'static>(
+        extern "C" fn success_callback<F: Fn(TransferProgress) + Send + Sync + 'static>(
             state: *const c_void,
             response: ByteArray,
         ) {
-            if !state.is_null() {
-                unsafe {
-                    let state_ptr = state as *mut UploadState<F>;
-                    let state = Box::from_raw(state_ptr);
-                    let response = response.as_slice();
+            unsafe {
+                UploadState::<FileNode, F>::finish(state, |state| {
+                    let response = response.view().as_slice();
                     let node = match FileNode::decode(response) {
                         Ok(val) => Ok(val),
                         Err(e) => Err(UploadError::Protobuf(e.into())),
                     };
-                    let _ = state.result_sender.send(node);
-                }
+                    state.send(node);
+                })
             }
         }
 
-        extern "C" fn failure_callback<F: Fn(f32) + Send + 'static>(
+        extern "C" fn failure_callback<F: Fn(TransferProgress) + Send + Sync + 'static>(
             state: *const c_void,
             error_data: ByteArray,
         ) {
-            if !state.is_null() {
-                unsafe {
-                    let state_ptr = state as *mut UploadState<F>;
-                    let state = Box::from_raw(state_ptr);
-                    let error_msg = String::from_utf8_lossy(error_data.as_slice()).to_string();
-                    let _ = state.result_sender.send(Err(UploadError::Ffi(anyhow::anyhow!(error_msg))));
-                }
+            unsafe {
+                UploadState::<FileNode, F>::finish(state, |state| {
+                    state.send(Err(UploadError::Ffi(SdkErrorDetails::decode(&error_data.view()).into())));
+                })
             }
         }
 
         let async_callback = AsyncCallback::new(
-            state_ptr as *const c_void,
+            guard.state(),
             Some(success_callback::<F>),
             Some(failure_callback::<F>),
             self._token.raw(),
         );
         let progress_cb = if is_progress_callback {
-            Callback::new(state_ptr as *const c_void, Some(progress_callback_fn::<F>))
+            Callback::checked(guard.thread_safe_state(), progress::report_progress::<Result<FileNode, UploadError>, F>)
         } else {
             Callback::empty()
         };
         let async_callback_with_progress = AsyncCallbackWithProgress::new(async_callback, progress_cb);
 
-        let code = raw::uploader_upload_file_or_revision(self.handle, proto_buf.as_byte_array(), async_callback_with_progress)?;
+        let code = guard.call(|| self.api.uploader_upload_file_or_revision(self.handle, proto_buf.as_byte_array(), async_callback_with_progress))?;
         if code != 0 {
-            unsafe { let _ = Box::from_raw(state_ptr); }
             return Err(UploadError::Failure(code));
         }
+        // present, as the request is valid
+        let operation_id = request.operation_id.as_ref().map_or("", |operation| operation.identifier.as_str());
+        debug!("Upload {} started", operation_id);
+
+        let node = guard
+            .completion()
+            .await
+            .inspect_err(|e| error!("Upload {} failed: {}", operation_id, e))?;
+        debug!("Upload {} finished", operation_id);
+
+        #[cfg(feature = "tracing")]
+        {
+            let span = tracing::Span::current();
+            span.record("node_id", crate::tracing_support::node_id(node.node_identity.as_ref()));
+            if let Some(size) = node.active_revision.as_ref().and_then(|revision| revision.size) {
+                span.record("bytes", size);
+            }
+        }
 
-        rx.await.map_err(|_| UploadError::CallbackClosed)?
+        Ok(node)
+    }
+
+    /// Uploads a local file into `parent`, see [`FileUploadRequestBuilder`] for the request
+    pub async fn upload_path<F>(
+        &self,
+        path: impl Into<PathBuf>,
+        parent: NodeIdentity,
+        progress_callback: Option<F>,
+    ) -> Result<FileNode, UploadError>
+    where
+        F: Fn(TransferProgress) + Send + Sync + 'static,
+    {
+        let request = FileUploadRequestBuilder::new(path, parent).build()?;
+        self.upload_file_or_revision(request, progress_callback).await
     }
 
     pub async fn upload_revision<F>(
@@ -180,100 +249,75 @@ impl Uploader {
         progress_callback: Option<F>,
     ) -> Result<Revision, UploadError>
     where
-        F: Fn(f32) + Send + 'static,
+        F: Fn(TransferProgress) + Send + Sync + 'static,
     {
+        request.validate()?;
         let is_progress_callback = progress_callback.is_some();
         let proto_buf = request.to_proto_buffer()?;
-        let (tx, rx) = oneshot::channel::<Result<Revision, UploadError>>();
-
-        struct UploadState<F: Fn(f32) + Send + 'static> {
-            result_sender: oneshot::Sender<Result<Revision, UploadError>>,
-            progress_callback: Option<F>,
-        }
-
-        let state = Box::new(UploadState {
-            result_sender: tx,
-            progress_callback,
-        });
-        let state_ptr = Box::into_raw(state);
+        // the uploader's token is shared by its other uploads, so a dropped upload doesn't cancel it
+        let guard = CallbackGuard::<Result<Revision, UploadError>, _>::new(progress_callback);
 
-        extern "C" fn success_callback<F: Fn(f32) + Send + 'static>(
+        extern "C" fn success_callback<F: Fn(TransferProgress) + Send + Sync + 'static>(
             state: *const c_void,
             response: ByteArray,
         ) {
-            if !state.is_null() {
-                unsafe {
-                    let state_ptr = state as *mut UploadState<F>;
-                    let state = Box::from_raw(state_ptr);
-                    let response = response.as_slice();
+            unsafe {
+                UploadState::<Revision, F>::finish(state, |state| {
+                    let response = response.view().as_slice();
                     let rev = match Revision::decode(response) {
                         Ok(val) => Ok(val),
                         Err(e) => Err(UploadError::Protobuf(e.into())),
                     };
-                    let _ = state.result_sender.send(rev);
-                }
+                    state.send(rev);
+                })
             }
         }
 
-        extern "C" fn failure_callback<F: Fn(f32) + Send + 'static>(
+        extern "C" fn failure_callback<F: Fn(TransferProgress) + Send + Sync + 'static>(
             state: *const c_void,
             error_data: ByteArray,
         ) {
-            if !state.is_null() {
-                unsafe {
-                    let state_ptr = state as *mut UploadState<F>;
-                    let state = Box::from_raw(state_ptr);
-                    let error_msg = String::from_utf8_lossy(error_data.as_slice()).to_string();
-                    let _ = state.result_sender.send(Err(UploadError::Ffi(anyhow::anyhow!(error_msg))));
-                }
+            unsafe {
+                UploadState::<Revision, F>::finish(state, |state| {
+                    state.send(Err(UploadError::Ffi(SdkErrorDetails::decode(&error_data.view()).into())));
+                })
             }
         }
 
         let async_callback = AsyncCallback::new(
-            state_ptr as *const c_void,
+            guard.state(),
             Some(success_callback::<F>),
             Some(failure_callback::<F>),
             self._token.raw(),
         );
         let progress_cb = if is_progress_callback {
-            Callback::new(state_ptr as *const c_void, Some(progress_callback_fn::<F>))
+            Callback::checked(guard.thread_safe_state(), progress::report_progress::<Result<Revision, UploadError>, F>)
         } else {
             Callback::empty()
         };
         let async_callback_with_progress = AsyncCallbackWithProgress::new(async_callback, progress_cb);
 
-        let code = raw::uploader_upload_revision(self.handle, proto_buf.as_byte_array(), async_callback_with_progress)?;
+        let code = guard.call(|| self.api.uploader_upload_revision(self.handle, proto_buf.as_byte_array(), async_callback_with_progress))?;
         if code != 0 {
-            unsafe { let _ = Box::from_raw(state_ptr); }
             return Err(UploadError::Failure(code));
         }
+        // present, as the request is valid
+        let operation_id = request.operation_id.as_ref().map_or("", |operation| operation.identifier.as_str());
+        debug!("Upload {} started", operation_id);
 
-        rx.await.map_err(|_| UploadError::CallbackClosed)?
-    }
-}
-
-extern "C" fn progress_callback_fn<F: Fn(f32) + Send + 'static>(
-    state: *const c_void,
-    progress_data: ByteArray,
-) {
-    if !state.is_null() {
-        unsafe {
-            let state_ptr = state as *const UploadState<F>;
-            let state = &*state_ptr;
-            let bytes = progress_data.as_slice();
-            let progress = ProgressUpdate::from_bytes(bytes).expect("No progress update data");
-            if let Some(ref callback) = state.progress_callback {
-                // completed out of total as percent
-                callback((progress.bytes_completed / progress.bytes_in_total) as f32);
-            }
-        }
+        let revision = guard
+            .completion()
+            .await
+            .inspect_err(|e| error!("Upload {} failed: {}", operation_id, e))?;
+        debug!("Upload {} finished", operation_id);
+        Ok(revision)
     }
 }
 
 impl Drop for Uploader {
     fn drop(&mut self) {
         if !self.handle.is_null() {
-            if let Err(e) = raw::uploader_free(self.handle) {
+            if let Err(e) = self.api.uploader_free(self.handle) {
                 error!("Failed to free uploader in Drop: {}", e);
             } else {
                 debug!("Uploader cleaned up automatically");
@@ -285,7 +329,8 @@ impl Drop for Uploader {
 pub struct UploaderBuilder {
     client: DriveClientHandle,
     request: FileUploaderCreationRequest,
-    token: CancellationTokenHandle
+    token: CancellationTokenHandle,
+    api: Arc<dyn SdkApi>,
 }
 
 impl UploaderBuilder {
@@ -293,7 +338,8 @@ impl UploaderBuilder {
         Self {
             client: client.handle(), 
             request: FileUploaderCreationRequest::default(), 
-            token: client.session().cancellation_token().handle() 
+            token: client.session().cancellation_token().handle(),
+            api: client.api().clone(),
         }
     }
     
@@ -304,6 +350,104 @@ impl UploaderBuilder {
     pub async fn build(
         self
     ) -> Result<Uploader, UploadError> {
-        Uploader::new(self.client, self.request, self.token).await
+        Uploader::with_api(self.api, self.client, self.request, self.token).await
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proton_sdk_sys::{
+        api::{MockApi, Reply},
+        protobufs::{account::OperationIdentifier, drive::{LinkId, NodeIdentity, ProgressUpdate, ShareMetadata}},
+    };
+
+    use crate::drive::tests::mock_client;
+
+    fn request() -> FileUploadRequest {
+        FileUploadRequest {
+            share_metadata: Some(ShareMetadata::default()),
+            parent_folder_identity: Some(NodeIdentity { node_id: Some(LinkId { value: "photos".to_string() }), ..Default::default() }),
+            name: "beach.jpg".to_string(),
+            source_file_path: "/tmp/beach.jpg".to_string(),
+            operation_id: Some(OperationIdentifier::upload()),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn creation_failures_are_reported() {
+        let mock = Arc::new(MockApi::new());
+        let client = mock_client(&mock).await;
+        mock.reply("uploader_create", Reply::Code(2))
+            .reply("uploader_create", Reply::Failure(b"Quota exceeded".to_vec()));
+
+        assert!(matches!(UploaderBuilder::new(&client).build().await, Err(UploadError::Failure(2))));
+        let error = UploaderBuilder::new(&client).build().await.err().unwrap();
+        assert_eq!(error.details().map(SdkErrorDetails::message), Some("Quota exceeded"));
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn abandoned_creations_cancel_a_token_of_their_own() {
+        let mock = Arc::new(MockApi::new());
+        let client = mock_client(&mock).await;
+        mock.reply("uploader_create", Reply::Pending);
+        let creation = UploaderBuilder::new(&client).build();
+        assert!(tokio::time::timeout(std::time::Duration::from_millis(10), creation).await.is_err());
+
+        let created = mock.calls_to("uploader_create");
+        let cancelled: Vec<_> = mock.calls_to("cancellation_token_source_cancel").iter().map(|call| call.handle).collect();
+        assert_eq!(created.len(), 1);
+        assert_eq!(cancelled.len(), 1);
+        assert_ne!(cancelled[0], client.session().cancellation_token().handle().raw());
+        assert!(mock.calls_to("cancellation_token_source_free").iter().any(|call| call.handle == cancelled[0]));
+    }
+
+    #[tokio::test]
+    async fn uploads_return_the_node_and_free_the_uploader() {
+        let mock = Arc::new(MockApi::new());
+        let client = mock_client(&mock).await;
+        let uploader = UploaderBuilder::new(&client).build().await.unwrap();
+        let node = FileNode { name: "beach.jpg".to_string(), ..Default::default() };
+        mock.progress("uploader_upload_file_or_revision", &[ProgressUpdate { bytes_completed: 512, bytes_in_total: 2048 }])
+            .reply("uploader_upload_file_or_revision", Reply::Success(node.encode_to_vec()))
+            .reply("uploader_upload_file_or_revision", Reply::Failure(b"Name taken".to_vec()))
+            .reply("uploader_upload_file_or_revision", Reply::Code(6));
+
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let progress = {
+            let seen = seen.clone();
+            move |progress: TransferProgress| seen.lock().unwrap().push(progress)
+        };
+        assert_eq!(uploader.upload_file_or_revision(request(), Some(progress)).await.unwrap(), node);
+        assert_eq!(*seen.lock().unwrap(), [TransferProgress { bytes_completed: 512, bytes_in_total: 2048 }]);
+        let error = uploader.upload_file_or_revision(request(), None::<fn(TransferProgress)>).await.unwrap_err();
+        assert_eq!(error.to_string(), "FFI error: Name taken");
+        assert!(matches!(uploader.upload_file_or_revision(request(), None::<fn(TransferProgress)>).await, Err(UploadError::Failure(6))));
+
+        let handle = mock.calls_to("uploader_upload_file_or_revision")[0].handle;
+        drop(uploader);
+        let freed: Vec<_> = mock.calls_to("uploader_free").iter().map(|call| call.handle).collect();
+        assert_eq!(freed, [handle]);
+    }
+
+    #[tokio::test]
+    async fn paths_are_uploaded_with_a_built_request() {
+        let mock = Arc::new(MockApi::new());
+        let client = mock_client(&mock).await;
+        let uploader = UploaderBuilder::new(&client).build().await.unwrap();
+        mock.reply("uploader_upload_file_or_revision", Reply::Success(FileNode::default().encode_to_vec()));
+
+        let path = std::env::temp_dir().join(format!("proton-sdk-upload-path-{}.txt", std::process::id()));
+        std::fs::write(&path, b"hello").unwrap();
+        let parent = request().parent_folder_identity.unwrap();
+        uploader.upload_path(&path, parent, None::<fn(TransferProgress)>).await.unwrap();
+
+        let sent = FileUploadRequest::decode(&mock.calls_to("uploader_upload_file_or_revision")[0].request[..]).unwrap();
+        assert_eq!(sent.name, path.file_name().unwrap().to_str().unwrap());
+        assert_eq!(sent.mime_type, "text/plain");
+        assert!(matches!(
+            uploader.upload_path(path.with_extension("missing"), NodeIdentity::default(), None::<fn(TransferProgress)>).await,
+            Err(UploadError::Source { .. })
+        ));
+    }
+}
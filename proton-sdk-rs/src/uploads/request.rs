@@ -0,0 +1,182 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use proton_sdk_sys::{
+    prost::bytes::Bytes,
+    protobufs::{
+        account::OperationIdentifier,
+        drive::{FileUploadRequest, NodeIdentity, ShareMetadata},
+    },
+};
+
+use super::UploadError;
+
+/// Builds the request uploading a local file into a folder
+///
+/// The name, mime type and modification date come from the file. Unless given
+/// ones, the operation is a new [`OperationIdentifier::upload`] and the share
+/// metadata only names the share of the parent folder.
+///
+/// ```no_run
+/// # use proton_sdk_rs::{uploads::FileUploadRequestBuilder, LinkId, NodeIdentity};
+/// let parent = NodeIdentity { node_id: Some(LinkId { value: "folder".to_string() }), ..Default::default() };
+/// let request = FileUploadRequestBuilder::new("photos/beach.jpg", parent).build()?;
+/// assert_eq!(request.mime_type, "image/jpeg");
+/// # Ok::<(), proton_sdk_rs::uploads::UploadError>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct FileUploadRequestBuilder {
+    path: PathBuf,
+    parent: NodeIdentity,
+    thumbnail: Option<Bytes>,
+    share_metadata: Option<ShareMetadata>,
+    operation_id: Option<OperationIdentifier>,
+}
+
+impl FileUploadRequestBuilder {
+    pub fn new(path: impl Into<PathBuf>, parent: NodeIdentity) -> Self {
+        Self { path: path.into(), parent, thumbnail: None, share_metadata: None, operation_id: None }
+    }
+
+    pub fn with_thumbnail(self, thumbnail: impl Into<Bytes>) -> Self {
+        Self { thumbnail: Some(thumbnail.into()), ..self }
+    }
+
+    /// Uses the full metadata of the share, with the membership address
+    pub fn with_share_metadata(self, share_metadata: ShareMetadata) -> Self {
+        Self { share_metadata: Some(share_metadata), ..self }
+    }
+
+    /// Uses an operation created by the caller, a revision upload for instance
+    pub fn with_operation_id(self, operation_id: OperationIdentifier) -> Self {
+        Self { operation_id: Some(operation_id), ..self }
+    }
+
+    /// Reads the file's metadata, failing when it isn't a readable file
+    pub fn build(self) -> Result<FileUploadRequest, UploadError> {
+        let source = |source: io::Error| UploadError::Source { path: self.path.clone(), source };
+        let name = file_name(&self.path).map_err(source)?;
+        let metadata = fs::metadata(&self.path).map_err(source)?;
+        if !metadata.is_file() {
+            return Err(source(io::Error::new(io::ErrorKind::InvalidInput, "not a file")));
+        }
+        let source_file_path = std::path::absolute(&self.path).map_err(source)?;
+        // files modified before 1970 are dated 0
+        let last_modification_date = metadata
+            .modified()
+            .map_err(source)?
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs() as i64);
+
+        let share_metadata = self.share_metadata.unwrap_or_else(|| ShareMetadata {
+            share_id: self.parent.share_id.clone(),
+            ..Default::default()
+        });
+        Ok(FileUploadRequest {
+            share_metadata: Some(share_metadata),
+            parent_folder_identity: Some(self.parent),
+            mime_type: mime_guess::from_path(&self.path).first_or_octet_stream().to_string(),
+            name,
+            source_file_path: source_file_path.to_string_lossy().into_owned(),
+            thumbnail: self.thumbnail,
+            last_modification_date,
+            operation_id: Some(self.operation_id.unwrap_or_else(OperationIdentifier::upload)),
+        })
+    }
+}
+
+fn file_name(path: &Path) -> io::Result<String> {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .map(str::to_string)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no usable file name"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, Utc};
+    use proton_sdk_sys::protobufs::{
+        account::OperationType,
+        drive::{LinkId, ShareId},
+        validation::Validate,
+    };
+    use std::{
+        fs::File,
+        time::{Duration, SystemTime},
+    };
+
+    fn parent() -> NodeIdentity {
+        NodeIdentity {
+            node_id: Some(LinkId { value: "folder".to_string() }),
+            share_id: Some(ShareId { value: "share".to_string() }),
+            ..Default::default()
+        }
+    }
+
+    fn temp_file(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("proton-sdk-upload-request-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        fs::write(&path, b"hello").unwrap();
+        let modified = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        File::options().write(true).open(&path).unwrap().set_modified(modified).unwrap();
+        path
+    }
+
+    #[test]
+    fn requests_are_filled_from_the_file() {
+        let path = temp_file("notes.txt");
+        let request = FileUploadRequestBuilder::new(&path, parent()).build().unwrap();
+
+        assert_eq!(request.name, "notes.txt");
+        assert_eq!(request.mime_type, "text/plain");
+        assert_eq!(request.last_modification_date, 1_700_000_000);
+        assert_eq!(Path::new(&request.source_file_path), path);
+        assert_eq!(request.parent_folder_identity, Some(parent()));
+        assert_eq!(request.share_metadata.as_ref().unwrap().share_id, parent().share_id);
+        assert_eq!(request.thumbnail, None);
+
+        let operation = request.operation_id.as_ref().unwrap();
+        assert_eq!(operation.r#type(), OperationType::FileUpload);
+        let timestamp = DateTime::parse_from_rfc3339(&operation.timestamp).unwrap();
+        assert!((Utc::now() - timestamp.with_timezone(&Utc)).num_seconds() < 5);
+        assert!(request.validate().is_ok());
+    }
+
+    #[test]
+    fn thumbnails_share_metadata_and_operations_can_be_given() {
+        let path = temp_file("beach.png");
+        let metadata = ShareMetadata {
+            share_id: Some(ShareId { value: "other".to_string() }),
+            membership_email_address: "user@proton.me".to_string(),
+            ..Default::default()
+        };
+        let operation = OperationIdentifier::revision_upload();
+        let request = FileUploadRequestBuilder::new(&path, parent())
+            .with_thumbnail(vec![0xff, 0xd8])
+            .with_share_metadata(metadata.clone())
+            .with_operation_id(operation.clone())
+            .build()
+            .unwrap();
+
+        assert_eq!(request.mime_type, "image/png");
+        assert_eq!(request.operation_id, Some(operation));
+        assert_eq!(request.thumbnail.as_deref(), Some(&[0xff, 0xd8][..]));
+        assert_eq!(request.share_metadata, Some(metadata));
+    }
+
+    #[test]
+    fn missing_files_and_folders_are_errors() {
+        let missing = std::env::temp_dir().join(format!("proton-sdk-missing-{:?}", SystemTime::now()));
+        let error = FileUploadRequestBuilder::new(&missing, parent()).build().unwrap_err();
+        assert!(matches!(error, UploadError::Source { path, .. } if path == missing));
+
+        let folder = temp_file("folder.txt").parent().unwrap().to_path_buf();
+        assert!(FileUploadRequestBuilder::new(&folder, parent()).build().is_err());
+        assert!(FileUploadRequestBuilder::new("/", parent()).build().is_err());
+    }
+}
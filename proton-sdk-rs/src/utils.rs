@@ -1,15 +1,15 @@
-use proton_sdk_sys::protobufs::{FileNode, FolderNode, NodeType};
+use proton_sdk_sys::protobufs::drive::{FileNode, FolderNode, NodeType};
 
+use crate::nodes::NodeTypeExt;
+
+#[deprecated(since = "0.1.0", note = "This clones the node, use `NodeTypeExt::as_folder` instead!")]
 pub fn node_is_folder(node: NodeType) -> (bool, Option<FolderNode>) {
-    match node.node_type {
-        Some(proton_sdk_sys::protobufs::node_type::NodeType::FolderNode(folder)) => (true, Some(folder)),
-        _ => (false, None),
-    }
+    let folder = node.as_folder().cloned();
+    (folder.is_some(), folder)
 }
 
+#[deprecated(since = "0.1.0", note = "This clones the node, use `NodeTypeExt::as_file` instead!")]
 pub fn node_is_file(node: NodeType) -> (bool, Option<FileNode>) {
-    match node.node_type {
-        Some(proton_sdk_sys::protobufs::node_type::NodeType::FileNode(file)) => (true, Some(file)),
-        _ => (false, None),
-    }
-}
\ No newline at end of file
+    let file = node.as_file().cloned();
+    (file.is_some(), file)
+}
@@ -0,0 +1,6 @@
+//! Misuses of the bindings the compiler has to reject, see tests/ui
+
+#[test]
+fn progress_callbacks_have_to_be_thread_safe() {
+    trybuild::TestCases::new().compile_fail("tests/ui/*.rs");
+}
@@ -0,0 +1,259 @@
+//! Runs the bindings end to end against the real Proton API, with the account of
+//! `PROTON_TEST_USERNAME` and `PROTON_TEST_PASSWORD`
+//!
+//! Needs the native SDK and the `live-tests` feature, see `docs/BUILDING.md`. Without the
+//! credentials the test passes without doing anything, so the feature is safe to enable in CI.
+
+use std::{
+    env, fs,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::{debug, error, info, warn};
+use proton_sdk_rs::{
+    downloads::DownloaderBuilder,
+    drive::{DriveClient, DriveClientBuilder},
+    nodes::{NodeIdentityExt, NodeTypeExt},
+    progress::TransferProgress,
+    sessions::{FileSessionStore, Session, SessionBuilder, SessionCallbacks, SessionPlatform},
+    uploads::UploaderBuilder,
+    ClientId, FileDownloadRequest, FileUploadRequest, FileUploaderCreationRequest, NodeIdentity, OperationIdentifier,
+    ProtonClientOptions, ProtonDriveClientCreateRequest, SessionInfo, SessionResumeRequest,
+};
+use proton_sdk_sys::protobufs::account::StringResponse;
+use totp_rs::{Algorithm, Secret, TOTP};
+
+/// Prefix of everything the test creates, remote files and local scratch directories alike
+const PREFIX: &str = "proton-sdk-rs-live";
+
+#[derive(Clone)]
+struct Credentials {
+    username: String,
+    password: String,
+    /// Base32 secret the 2FA codes are generated from, `PROTON_TEST_TOTP_SECRET`
+    totp_secret: Option<String>,
+    /// `PROTON_TEST_DATA_PASSWORD`, for accounts in two-password mode
+    data_password: Option<String>,
+}
+
+impl Credentials {
+    fn from_env() -> Option<Self> {
+        let var = |name| env::var(name).ok().filter(|value: &String| !value.is_empty());
+        Some(Self {
+            username: var("PROTON_TEST_USERNAME")?,
+            password: var("PROTON_TEST_PASSWORD")?,
+            totp_secret: var("PROTON_TEST_TOTP_SECRET"),
+            data_password: var("PROTON_TEST_DATA_PASSWORD"),
+        })
+    }
+
+    fn data_password(&self) -> &str {
+        self.data_password.as_deref().unwrap_or(&self.password)
+    }
+
+    /// Answers the SDK's 2FA request with the current TOTP code, if there is a secret
+    fn two_factor_answer(&self) -> (Option<StringResponse>, Option<StringResponse>) {
+        info!("The API asked for a second factor");
+        let code = self.totp_secret.as_ref().map(|secret| {
+            let bytes = Secret::Encoded(secret.clone()).to_bytes().expect("PROTON_TEST_TOTP_SECRET isn't base32");
+            let totp = TOTP::new(Algorithm::SHA1, 6, 1, 30, bytes).expect("PROTON_TEST_TOTP_SECRET is too short");
+            totp.generate_current().expect("the clock is before 1970")
+        });
+        if code.is_none() {
+            error!("The account has 2FA enabled, set PROTON_TEST_TOTP_SECRET");
+        }
+        (
+            code.map(|value| StringResponse { value }),
+            Some(StringResponse { value: self.data_password().to_string() }),
+        )
+    }
+}
+
+/// What a run leaves behind, cleaned up when it's dropped, whether the test passed or not
+struct LiveRun {
+    dir: PathBuf,
+    session: Option<Session>,
+    client: Option<DriveClient>,
+    /// Names of the files uploaded to the root folder
+    uploaded: Vec<String>,
+}
+
+impl LiveRun {
+    fn new() -> Self {
+        let dir = env::temp_dir().join(format!("{}-{}", PREFIX, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        Self { dir, session: None, client: None, uploaded: Vec::new() }
+    }
+
+    fn session(&self) -> Option<&Session> {
+        self.client.as_ref().map(DriveClient::session).or(self.session.as_ref())
+    }
+
+    /// Ends the session, failing the test if the API refuses
+    fn finish(mut self) {
+        let session = self.session().expect("no session to end");
+        session.end().expect("ending the session failed");
+        info!("Ended the session");
+        self.client = None;
+        self.session = None;
+    }
+}
+
+impl Drop for LiveRun {
+    fn drop(&mut self) {
+        for name in &self.uploaded {
+            // the bindings have no trash call yet
+            warn!("{} is left in the root folder of the test account, remove it by hand", name);
+        }
+        if let Some(session) = self.session() {
+            match session.end() {
+                Ok(()) => info!("Ended the session of the failed run"),
+                Err(e) => error!("Unable to end the session of the failed run: {}", e),
+            }
+        }
+        if let Err(e) = fs::remove_dir_all(&self.dir) {
+            warn!("Unable to remove {}: {}", self.dir.display(), e);
+        }
+    }
+}
+
+async fn begin(credentials: &Credentials) -> Session {
+    info!("Logging in as {}", credentials.username);
+    let answers = credentials.clone();
+    SessionBuilder::new(credentials.username.clone(), credentials.password.clone())
+        .with_app_version(SessionPlatform::Linux, PREFIX, env!("CARGO_PKG_VERSION"))
+        .with_request_response_callback(|data| debug!("HTTP: {}", String::from_utf8_lossy(data)))
+        .with_two_factor_requested_callback(move |_context| answers.two_factor_answer())
+        .begin()
+        .await
+        .unwrap_or_else(|e| panic!("logging in failed: {} ({:?})", e, e.kind()))
+}
+
+async fn resume(credentials: &Credentials, info: SessionInfo) -> Session {
+    info!("Resuming session {:?}", info.session_id);
+    let answers = credentials.clone();
+    let request = SessionResumeRequest {
+        session_id: info.session_id,
+        username: info.username,
+        user_id: info.user_id,
+        access_token: info.access_token,
+        refresh_token: info.refresh_token,
+        scopes: info.scopes,
+        is_waiting_for_second_factor_code: info.is_waiting_for_second_factor_code,
+        password_mode: info.password_mode,
+        options: Some(ProtonClientOptions::default()),
+    };
+    let callbacks = SessionCallbacks {
+        request_response: Some(Box::new(|data| debug!("HTTP: {}", String::from_utf8_lossy(data)))),
+        secret_requested: None,
+        two_factor_requested: Some(Box::new(move |_context| answers.two_factor_answer())),
+        tokens_refreshed: None,
+    };
+    let session = SessionBuilder::resume_session(request, callbacks, SessionPlatform::Linux, PREFIX, env!("CARGO_PKG_VERSION"))
+        .await
+        .unwrap_or_else(|e| panic!("resuming the saved session failed: {} ({:?})", e, e.kind()));
+    session.apply_data_password(credentials.data_password()).expect("applying the data password failed");
+    session
+}
+
+#[tokio::test]
+#[cfg_attr(
+    not(feature = "live-tests"),
+    ignore = "talks to the Proton API, build with the native SDK and --features live-tests"
+)]
+async fn a_file_makes_the_round_trip_through_the_drive() {
+    let _ = env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"))
+        .is_test(true)
+        .try_init();
+    let Some(credentials) = Credentials::from_env() else {
+        eprintln!("PROTON_TEST_USERNAME and PROTON_TEST_PASSWORD aren't set, skipping the live test");
+        return;
+    };
+    let mut run = LiveRun::new();
+
+    run.session = Some(begin(&credentials).await);
+    let session_file = run.dir.join("session_info.bin");
+    run.session().unwrap().save_session(Some(&session_file.to_string_lossy())).expect("saving the session failed");
+    let info = FileSessionStore::new(&session_file).load().unwrap().expect("the saved session is missing");
+    info!("Saved session {:?} with scopes {:?}", info.session_id, info.scopes);
+
+    // the first session is only freed locally, the resumed one goes on with its tokens
+    run.session = Some(resume(&credentials, info).await);
+    let client = DriveClientBuilder::new(run.session.take().unwrap())
+        .with_request(ProtonDriveClientCreateRequest { client_id: Some(ClientId { value: PREFIX.to_string() }) })
+        .build()
+        .expect("creating the drive client failed");
+    info!("Created drive client {:?}", client.handle());
+    run.client = Some(client);
+
+    let name = format!("{}-{}.txt", PREFIX, SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis());
+    // recorded before uploading, a failed upload can still leave a draft behind
+    run.uploaded.push(name.clone());
+    let client = run.client.as_ref().unwrap();
+
+    let volumes = client.get_volumes().await.expect("listing the volumes failed");
+    info!("Volumes: {:?}", volumes.iter().map(|volume| &volume.volume_id).collect::<Vec<_>>());
+    let volume = volumes.first().expect("the account has no volumes");
+    let shares = client.get_shares(volume).await.expect("listing the shares failed");
+    info!("Shares of the main volume: {:?}", shares.iter().map(|share| &share.share_id).collect::<Vec<_>>());
+    let share = shares.first().expect("the main volume has no shares");
+    let root = NodeIdentity {
+        node_id: share.root_node_id.clone(),
+        share_id: share.share_id.clone(),
+        volume_id: volume.volume_id.clone(),
+    };
+
+    // the bindings can't create folders yet, so the file goes to the root under a unique name
+    let content = format!("Uploaded by the live tests of proton-sdk-rs as {}\n", name).into_bytes();
+    let source = run.dir.join(&name);
+    fs::write(&source, &content).unwrap();
+    info!("Uploading {} ({} bytes)", name, content.len());
+    let uploader = UploaderBuilder::new(client)
+        .with_request(FileUploaderCreationRequest { file_size: content.len() as i64, number_of_samples: 0 })
+        .build()
+        .await
+        .expect("creating the uploader failed");
+    let request = FileUploadRequest {
+        share_metadata: Some(share.metadata()),
+        parent_folder_identity: Some(root.clone()),
+        name: name.clone(),
+        mime_type: "text/plain".to_string(),
+        source_file_path: source.to_string_lossy().into_owned(),
+        last_modification_date: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i64,
+        operation_id: Some(OperationIdentifier::upload()),
+        ..Default::default()
+    };
+    let uploaded = uploader
+        .upload_file_or_revision(request, Some(|progress: TransferProgress| debug!("Uploaded {:.0}%", progress.fraction() * 100.0)))
+        .await
+        .expect("uploading failed");
+    info!("Uploaded {:?}", uploaded.node_identity);
+
+    let listing = client.get_folder_children(root.clone()).await.expect("listing the root folder failed");
+    info!("The root folder has {} children", listing.len());
+    let node = listing
+        .iter()
+        .find(|node| node.as_file().is_some_and(|file| file.name == name))
+        .unwrap_or_else(|| panic!("{} isn't in the root folder after uploading it", name));
+    let identity = node.full_identity(&root).expect("the uploaded file has no identity");
+
+    let target = run.dir.join("downloaded.txt");
+    info!("Downloading {} to {}", name, target.display());
+    let downloader = DownloaderBuilder::new(client).build().await.expect("creating the downloader failed");
+    let request = FileDownloadRequest {
+        file_identity: Some(identity),
+        target_file_path: target.to_string_lossy().into_owned(),
+        operation_id: Some(OperationIdentifier::download()),
+        ..Default::default()
+    };
+    let downloaded = downloader
+        .download_file_simple(request)
+        .await
+        .expect("downloading failed");
+    assert_eq!(downloaded, content, "the downloaded bytes differ from the uploaded ones");
+    assert_eq!(fs::read(&target).unwrap(), content, "the downloaded file differs from the uploaded one");
+    info!("Downloaded {} bytes, matching the upload", downloaded.len());
+
+    run.finish();
+}
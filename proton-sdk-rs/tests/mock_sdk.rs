@@ -0,0 +1,205 @@
+//! Runs the bindings against the mock SDK library, loaded through libloading like the native one
+//!
+//! The accounts and files of `fixtures/mock_drive.json` pick the failure each test exercises.
+
+use std::{
+    env, fs,
+    path::PathBuf,
+    sync::{Arc, Mutex, Once},
+    time::Duration,
+};
+
+use mock_proton_sdk::{FIXTURE_ENV, GARBAGE_HANDLE};
+use proton_sdk_rs::{
+    downloads::{DownloadError, DownloaderBuilder},
+    drive::{DriveClient, DriveClientBuilder, DriveError},
+    errors::SdkErrorDetails,
+    nodes::{NodeIdentityExt, NodeTypeExt},
+    progress::TransferProgress,
+    sessions::{SessionBuilder, SessionError},
+    uploads::UploaderBuilder,
+};
+use proton_sdk_sys::{
+    protobufs::{
+        account::OperationIdentifier,
+        drive::{
+            FileDownloadRequest, FileUploadRequest, FileUploaderCreationRequest, LinkId, NodeIdentity, NodeType, ShareMetadata,
+        },
+        SdkErrorKind,
+    },
+    ProtonSDKLib, SdkLibError, LIB_PATH_ENV,
+};
+use tokio::time::timeout;
+
+const PATIENCE: Duration = Duration::from_millis(200);
+
+/// Points the bindings at the mock library built next to this test, before anything loads the SDK
+fn use_mock_sdk() {
+    static MOCK: Once = Once::new();
+    MOCK.call_once(|| {
+        let library = format!("{}mock_proton_sdk{}", env::consts::DLL_PREFIX, env::consts::DLL_SUFFIX);
+        let library = env::current_exe().unwrap().with_file_name(library);
+        assert!(library.exists(), "{} wasn't built", library.display());
+        env::set_var(LIB_PATH_ENV, library);
+        env::set_var(FIXTURE_ENV, concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/mock_drive.json"));
+    });
+}
+
+fn begin(username: &str, password: &str) -> SessionBuilder {
+    use_mock_sdk();
+    SessionBuilder::new(username.to_string(), password.to_string())
+}
+
+async fn client() -> DriveClient {
+    let session = begin("alice@proton.me", "hunter2").begin().await.unwrap();
+    DriveClientBuilder::new(session).build().unwrap()
+}
+
+/// A scratch directory of this test process
+fn scratch(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("mock-proton-sdk-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir.join(name)
+}
+
+/// The identity of the node called `name` in a listing
+fn named(nodes: &[NodeType], name: &str) -> NodeIdentity {
+    let node = nodes.iter().find(|node| {
+        let file_name = node.as_file().map(|file| &file.name);
+        file_name.or(node.as_folder().map(|folder| &folder.name)).is_some_and(|node_name| node_name == name)
+    });
+    node.unwrap_or_else(|| panic!("no {} in the listing", name)).identity().unwrap()
+}
+
+fn download_request(identity: NodeIdentity, target: &str) -> FileDownloadRequest {
+    FileDownloadRequest {
+        file_identity: Some(identity),
+        target_file_path: scratch(target).to_string_lossy().into_owned(),
+        operation_id: Some(OperationIdentifier::download()),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn login_list_download_and_upload() {
+    let client = client().await;
+    assert_eq!(client.session().info().unwrap().username, "alice@proton.me");
+
+    let volumes = client.get_volumes().await.unwrap();
+    let shares = client.get_shares(&volumes[0]).await.unwrap();
+    let share = &shares[0];
+    let root = NodeIdentity {
+        node_id: share.root_node_id.clone(),
+        share_id: share.share_id.clone(),
+        volume_id: share.volume_id.clone(),
+    };
+    let listing = client.get_folder_children(root).await.unwrap();
+    let photos = named(&listing, "Photos");
+    let documents = named(&listing, "Documents");
+
+    let photos = client.get_folder_children(photos).await.unwrap();
+    let beach = named(&photos, "beach.jpg");
+    let downloader = DownloaderBuilder::new(&client).build().await.unwrap();
+    let progress = Arc::new(Mutex::new(Vec::new()));
+    let seen = progress.clone();
+    let request = download_request(beach, "beach.jpg");
+    let target = request.target_file_path.clone();
+    let bytes = downloader
+        .download_file(request, Some(move |progress| seen.lock().unwrap().push(progress)))
+        .await
+        .unwrap();
+    assert_eq!(bytes, b"not really a jpeg");
+    assert_eq!(fs::read(&target).unwrap(), bytes);
+    let fractions: Vec<_> = progress.lock().unwrap().iter().map(TransferProgress::fraction).collect();
+    assert_eq!(fractions, [8.0 / 17.0, 1.0]);
+    assert_eq!(progress.lock().unwrap().last(), Some(&TransferProgress { bytes_completed: 17, bytes_in_total: 17 }));
+
+    let source = scratch("notes.txt");
+    fs::write(&source, "remember the milk").unwrap();
+    let uploader = UploaderBuilder::new(&client)
+        .with_request(FileUploaderCreationRequest { file_size: 17, number_of_samples: 0 })
+        .build()
+        .await
+        .unwrap();
+    let request = FileUploadRequest {
+        share_metadata: Some(ShareMetadata { share_id: share.share_id.clone(), ..Default::default() }),
+        parent_folder_identity: Some(documents.clone()),
+        name: "notes.txt".to_string(),
+        source_file_path: source.to_string_lossy().into_owned(),
+        operation_id: Some(OperationIdentifier::upload()),
+        ..Default::default()
+    };
+    let notes = uploader.upload_file_or_revision(request, None::<fn(TransferProgress)>).await.unwrap();
+    assert_eq!(notes.active_revision.unwrap().size, Some(17));
+
+    let listing = client.get_folder_children(documents).await.unwrap();
+    let uploaded = named(&listing, "notes.txt");
+    let bytes = downloader
+        .download_file_simple(download_request(uploaded, "notes.txt"))
+        .await
+        .unwrap();
+    assert_eq!(bytes, b"remember the milk");
+}
+
+#[test]
+fn the_mock_exports_every_required_symbol() {
+    use_mock_sdk();
+    let sdk = ProtonSDKLib::instance().unwrap();
+    sdk.verify_symbols().unwrap();
+    // resolved once when loaded, an optional export isn't among them
+    assert!(unsafe { sdk.symbol::<unsafe extern "C" fn() -> isize>("cancellation_token_source_create") }.is_ok());
+    let optional = unsafe { sdk.symbol::<unsafe extern "C" fn()>("node_move") };
+    assert!(matches!(optional, Err(SdkLibError::SymbolMissing("node_move"))));
+    // what the listings return is freed rather than leaked
+    assert!(sdk.has_symbol(proton_sdk_sys::data::BYTE_ARRAY_FREE_SYMBOL));
+}
+
+#[test]
+fn the_mock_reports_its_version() {
+    use_mock_sdk();
+    let version = ProtonSDKLib::instance().unwrap().version().unwrap();
+    assert_eq!((version.major, version.minor, version.raw.as_str()), (0, 1, "0.1.0-mock"));
+}
+
+#[tokio::test]
+async fn a_wrong_password_is_an_authentication_error() {
+    let error = begin("alice@proton.me", "hunter3").begin().await.err().unwrap();
+    assert!(matches!(error, SessionError::OperationFailed(SdkErrorDetails::IncorrectPassword { .. })));
+    assert_eq!(error.kind(), SdkErrorKind::Authentication);
+}
+
+#[tokio::test]
+async fn a_login_that_never_completes_can_be_abandoned() {
+    assert!(timeout(PATIENCE, begin("stuck@proton.me", "hunter2").begin()).await.is_err());
+}
+
+#[tokio::test]
+async fn a_second_callback_after_the_login_is_ignored() {
+    let session = begin("twice@proton.me", "hunter2").begin().await.unwrap();
+    // gives the failure callback time to arrive
+    tokio::time::sleep(PATIENCE).await;
+    assert_eq!(session.info().unwrap().username, "twice@proton.me");
+}
+
+#[tokio::test]
+async fn garbage_handles_are_rejected_by_later_calls() {
+    let session = begin("garbage@proton.me", "hunter2").begin().await.unwrap();
+    assert_eq!(session.handle().raw(), GARBAGE_HANDLE);
+    assert!(session.info().is_err());
+    assert!(matches!(DriveClientBuilder::new(session).build(), Err(DriveError::CreationFailed(1))));
+}
+
+#[tokio::test]
+async fn downloads_fail_or_hang_as_the_sdk_answers() {
+    let client = client().await;
+    let downloader = DownloaderBuilder::new(&client).build().await.unwrap();
+    let identity = |node_id: &str| NodeIdentity {
+        node_id: Some(LinkId { value: node_id.to_string() }),
+        ..Default::default()
+    };
+
+    let missing = downloader.download_file_simple(download_request(identity("nowhere"), "nowhere")).await;
+    assert!(matches!(missing, Err(DownloadError::DownloadFailed(details)) if details.kind() == SdkErrorKind::NotFound));
+    let stuck = downloader.download_file_simple(download_request(identity("stuck"), "stuck.jpg"));
+    assert!(timeout(PATIENCE, stuck).await.is_err());
+}
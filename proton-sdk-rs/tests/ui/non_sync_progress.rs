@@ -0,0 +1,12 @@
+// the SDK reports progress from its own threads while the download holds the callback
+use std::cell::Cell;
+
+use proton_sdk_rs::{downloads::Downloader, progress::TransferProgress, FileDownloadRequest};
+
+async fn download(downloader: &Downloader) {
+    let updates = Cell::new(0);
+    let progress = move |_progress: TransferProgress| updates.set(updates.get() + 1);
+    let _ = downloader.download_file(FileDownloadRequest::default(), Some(progress)).await;
+}
+
+fn main() {}
@@ -0,0 +1,77 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use proton_sdk_sys::protobufs::{
+    drive::{LinkId, NodeIdentity, ShareId, VolumeId},
+    ProtoBufferPool, ToByteArray,
+};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    hint::black_box,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Counts allocations so the pool's effect can be reported next to the timings
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+const ENCODES: usize = 10_000;
+
+fn identity() -> NodeIdentity {
+    NodeIdentity {
+        node_id: Some(LinkId { value: "a".repeat(88) }),
+        share_id: Some(ShareId { value: "b".repeat(88) }),
+        volume_id: Some(VolumeId { value: "c".repeat(88) }),
+    }
+}
+
+fn fresh_buffers(identity: &NodeIdentity) {
+    for _ in 0..ENCODES {
+        black_box(identity.to_proto_buffer().unwrap());
+    }
+}
+
+fn pooled_buffers(identity: &NodeIdentity) {
+    for _ in 0..ENCODES {
+        black_box(ProtoBufferPool::encode(identity).unwrap());
+    }
+}
+
+fn allocations(encode: fn(&NodeIdentity), identity: &NodeIdentity) -> usize {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    encode(identity);
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+fn encode(c: &mut Criterion) {
+    let identity = identity();
+    println!(
+        "allocations per {} encodes: to_proto_buffer {}, ProtoBufferPool {}",
+        ENCODES,
+        allocations(fresh_buffers, &identity),
+        allocations(pooled_buffers, &identity)
+    );
+
+    c.bench_function("10k NodeIdentity encodes with to_proto_buffer", |b| {
+        b.iter(|| fresh_buffers(&identity))
+    });
+    c.bench_function("10k NodeIdentity encodes with ProtoBufferPool", |b| {
+        b.iter(|| pooled_buffers(&identity))
+    });
+}
+
+criterion_group!(benches, encode);
+criterion_main!(benches);
@@ -0,0 +1,52 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use proton_sdk_sys::{
+    prost::{bytes::Bytes, Message},
+    protobufs::{
+        account::OperationIdentifier,
+        drive::{FileUploadRequest, LinkId, NodeIdentity, ShareMetadata},
+    },
+};
+use std::hint::black_box;
+
+const THUMBNAIL_SIZE: usize = 2 * 1024 * 1024;
+
+fn request() -> FileUploadRequest {
+    FileUploadRequest {
+        share_metadata: Some(ShareMetadata::default()),
+        parent_folder_identity: Some(NodeIdentity {
+            node_id: Some(LinkId { value: "parent".to_string() }),
+            ..Default::default()
+        }),
+        name: "photo.jpg".to_string(),
+        source_file_path: "/tmp/photo.jpg".to_string(),
+        thumbnail: Some(Bytes::from(vec![0x5a; THUMBNAIL_SIZE])),
+        operation_id: Some(OperationIdentifier::upload()),
+        ..Default::default()
+    }
+}
+
+/// Decoding from a slice copies the thumbnail, decoding from `Bytes` shares it
+fn decode(c: &mut Criterion) {
+    let encoded = Bytes::from(request().encode_to_vec());
+
+    c.bench_function("decode FileUploadRequest from slice", |b| {
+        b.iter(|| FileUploadRequest::decode(black_box(encoded.as_ref())).unwrap())
+    });
+    c.bench_function("decode FileUploadRequest from Bytes", |b| {
+        b.iter(|| FileUploadRequest::decode(black_box(encoded.clone())).unwrap())
+    });
+}
+
+fn encode_and_clone(c: &mut Criterion) {
+    let request = request();
+
+    c.bench_function("encode FileUploadRequest", |b| {
+        b.iter(|| black_box(&request).encode_to_vec())
+    });
+    c.bench_function("clone FileUploadRequest", |b| {
+        b.iter(|| black_box(&request).clone())
+    });
+}
+
+criterion_group!(benches, decode, encode_and_clone);
+criterion_main!(benches);
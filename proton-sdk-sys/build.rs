@@ -1,70 +1,491 @@
+#[path = "src/platform.rs"]
+mod platform;
+
 use anyhow::*;
+use sha2::{Digest, Sha256};
+use prost_types::field_descriptor_proto::{Label as FieldLabel, Type as FieldType};
+use prost_types::{DescriptorProto, FieldDescriptorProto, FileDescriptorProto, FileDescriptorSet};
 use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
     env,
     fs,
-    io::Result,
+    io::{self, Read, Write},
+    net::TcpStream,
     path::{Path, PathBuf},
+    time::Duration,
 };
 
+/// Variables the build reads, a change to any of them runs it again
+const WATCHED_ENV: &[&str] = &[
+    "PROTON_SDK_LIB_DIR",
+    "PROTON_SDK_DOWNLOAD_URL",
+    "PROTON_SDK_DOWNLOAD_SHA256",
+    "PROTON_SDK_SHA256_MANIFEST",
+    "CARGO_TARGET_DIR",
+    "CARGO_BUILD_TARGET_DIR",
+    "PROTOC",
+    "PROTOC_INCLUDE",
+];
+
 fn main() -> anyhow::Result<()> {
-    println!("cargo:warning=PROTON_SDK_LIB_DIR={:?}", std::env::var("PROTON_SDK_LIB_DIR"));
-    prost_build::Config::new().compile_protos(
-        &["protos/account.proto", "protos/drive.proto"],
-        &["protos/"],
-    )?;
+    // without any of these cargo runs the script again whenever a file of the package changes
+    for var in WATCHED_ENV {
+        println!("cargo:rerun-if-env-changed={}", var);
+    }
+    println!("cargo:rerun-if-changed=protos");
 
-    copy_dlls_to_exe_dir()?;
+    compile_protos()?;
+    copy_dlls_to_exe_dir()
+}
+
+/// What the generated code of a message or field gets beyond prost's defaults
+enum ProtoAttribute {
+    /// Put on every message and enum under the path, inert unless this crate's `feature` is on
+    Type { feature: &'static str, attribute: &'static str },
+    /// Put on every message under the path, inert unless `feature` is on
+    Message { feature: &'static str, attribute: &'static str },
+    /// `Eq` and `Hash` on the messages under the path that can have them but don't get them from
+    /// prost, inert unless `feature` is on
+    EqHash { feature: &'static str },
+    /// A password, token or unlocked key, marked in the docs and redacted from the message's `Debug`
+    Sensitive,
+    /// The message's `Debug` is implemented by hand in protobufs/redact.rs
+    HandWrittenDebug,
+}
 
-    println!("cargo:rerun-if-changed=protos/account.proto");
-    println!("cargo:rerun-if-changed=protos/drive.proto");
+use ProtoAttribute::*;
+
+/// Attributes of the generated code, by prost path: `.` for everything,
+/// `.package.Message` or `.package.Message.field`
+///
+/// The build fails when a path no longer names anything in protos/.
+const PROTO_ATTRIBUTES: &[(&str, ProtoAttribute)] = &[
+    (".", Type { feature: "serde", attribute: "derive(serde::Serialize, serde::Deserialize)" }),
+    // lets JSON omit fields the same way protobuf does
+    (".", Message { feature: "serde", attribute: "serde(default)" }),
+    (".", EqHash { feature: "extra-derives" }),
+    (".account.SessionInfo", HandWrittenDebug),
+    (".account.SessionInfo.access_token", Sensitive),
+    (".account.SessionInfo.refresh_token", Sensitive),
+    (".account.SessionTokens", HandWrittenDebug),
+    (".account.SessionTokens.access_token", Sensitive),
+    (".account.SessionTokens.refresh_token", Sensitive),
+    (".account.SessionBeginRequest.password", Sensitive),
+    (".account.SessionBeginRequest.two_factor_code", Sensitive),
+    (".account.SessionResumeRequest.access_token", Sensitive),
+    (".account.SessionResumeRequest.refresh_token", Sensitive),
+    (".account.SessionRenewRequest.access_token", Sensitive),
+    (".account.SessionRenewRequest.refresh_token", Sensitive),
+    (".account.ArmoredUserKey.passphrase", Sensitive),
+    (".account.AddressKeyWithData.raw_unlocked_data", Sensitive),
+    (".drive.ShareKeyRegistrationRequest.share_key_raw_unlocked_data", Sensitive),
+    (".drive.NodeKeysRegistrationRequest.node_key_raw_unlocked_data", Sensitive),
+    (".drive.NodeKeysRegistrationRequest.content_key_raw_unlocked_data", Sensitive),
+    (".drive.NodeKeysRegistrationRequest.hash_key_raw_unlocked_data", Sensitive),
+];
+
+const SENSITIVE_DOC: &str = "#[doc = \"Sensitive, `Debug` shows it redacted\"]";
+
+fn compile_protos() -> anyhow::Result<()> {
+    let mut config = prost_build::Config::new();
+    // shares buffers with the decoded input instead of copying thumbnails and keys
+    config.bytes(["."]);
+    let fds = config.load_fds(&["protos/account.proto", "protos/drive.proto"], &["protos/"])?;
+    let schema = Schema::new(&fds);
+
+    let mut redacted: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    let mut hand_written = BTreeSet::new();
+    for &(path, ref attribute) in PROTO_ATTRIBUTES {
+        if !schema.defines(path) {
+            bail!("build.rs sets attributes on {}, which protos/ doesn't define anymore", path);
+        }
+        match attribute {
+            Type { feature, attribute } => {
+                config.type_attribute(path, format!("#[cfg_attr(feature = \"{}\", {})]", feature, attribute));
+            }
+            Message { feature, attribute } => {
+                config.message_attribute(path, format!("#[cfg_attr(feature = \"{}\", {})]", feature, attribute));
+            }
+            EqHash { feature } => {
+                for (message, derives, oneofs) in schema.eq_hash_derives(path) {
+                    let attribute = format!("#[cfg_attr(feature = \"{}\", derive({}))]", feature, derives);
+                    config.message_attribute(message, &attribute);
+                    for oneof in oneofs {
+                        config.type_attribute(oneof, &attribute);
+                    }
+                }
+            }
+            Sensitive => {
+                let (message, field) = path.rsplit_once('.').unwrap();
+                schema.check_sensitive(message, field)?;
+                config.field_attribute(path, SENSITIVE_DOC);
+                redacted.entry(message).or_default().push(field);
+            }
+            HandWrittenDebug => {
+                hand_written.insert(path);
+            }
+        }
+    }
+
+    config.skip_debug(redacted.keys().chain(&hand_written));
+    let mut debug_impls = String::from("// Generated by build.rs for the messages with `Sensitive` fields in PROTO_ATTRIBUTES\n");
+    for (message, fields) in redacted.iter().filter(|(message, _)| !hand_written.contains(*message)) {
+        debug_impls.push_str(&schema.redacted_debug(message, fields)?);
+    }
+    for message in redacted.keys() {
+        debug_impls.push_str(&format!("\nimpl Sensitive for {} {{}}\n", schema.rust_path(message)));
+    }
+    fs::write(PathBuf::from(env::var("OUT_DIR")?).join("redacted_debug.rs"), debug_impls)?;
+
+    config.compile_fds(fds)?;
     Ok(())
 }
 
-fn get_platform_lib_name() -> &'static str {
-    #[cfg(target_os = "windows")]
-    {
-        "proton_drive_sdk.dll"
+/// A message of the protos and where it is declared
+struct MessageInfo<'a> {
+    package: &'a str,
+    descriptor: &'a DescriptorProto,
+    /// proto3 fields are `Option`s when declared `optional`, proto2 ones always
+    proto3: bool,
+}
+
+/// The messages and enums of the protos, by full name like `.account.SessionInfo`
+struct Schema<'a> {
+    messages: BTreeMap<String, MessageInfo<'a>>,
+    /// With the package they are in
+    enums: BTreeMap<String, &'a str>,
+}
+
+impl<'a> Schema<'a> {
+    fn new(fds: &'a FileDescriptorSet) -> Self {
+        let mut schema = Self { messages: BTreeMap::new(), enums: BTreeMap::new() };
+        for file in &fds.file {
+            let scope = format!(".{}", file.package());
+            for enumeration in &file.enum_type {
+                schema.enums.insert(format!("{}.{}", scope, enumeration.name()), file.package());
+            }
+            for message in &file.message_type {
+                schema.add_message(file, &scope, message);
+            }
+        }
+        schema
+    }
+
+    fn add_message(&mut self, file: &'a FileDescriptorProto, scope: &str, message: &'a DescriptorProto) {
+        let name = format!("{}.{}", scope, message.name());
+        for enumeration in &message.enum_type {
+            self.enums.insert(format!("{}.{}", name, enumeration.name()), file.package());
+        }
+        for nested in &message.nested_type {
+            self.add_message(file, &name, nested);
+        }
+        let info = MessageInfo { package: file.package(), descriptor: message, proto3: file.syntax() == "proto3" };
+        self.messages.insert(name, info);
+    }
+
+    /// Whether `path` names a message, an enum, a field or a oneof
+    fn defines(&self, path: &str) -> bool {
+        path == "."
+            || self.messages.contains_key(path)
+            || self.enums.contains_key(path)
+            || path.rsplit_once('.').is_some_and(|(message, field)| {
+                self.messages.get(message).is_some_and(|MessageInfo { descriptor: message, .. }| {
+                    message.field.iter().any(|f| f.name() == field)
+                        || message.oneof_decl.iter().any(|oneof| oneof.name() == field)
+                })
+            })
+    }
+
+    fn field(&self, message: &str, field: &str) -> &'a FieldDescriptorProto {
+        self.messages[message].descriptor.field.iter().find(|f| f.name() == field).unwrap()
+    }
+
+    /// The messages under `path` prost leaves without `Eq` and `Hash` that can have `Eq` or `Eq, Hash`,
+    /// with their oneofs that need the same
+    ///
+    /// prost derives both itself unless a message has float, repeated or
+    /// recursive message fields, which only floats, and maps for `Hash`, really
+    /// rule out. Messages with nested ones are left alone, prost would put the
+    /// attribute on those too.
+    fn eq_hash_derives(&self, path: &str) -> Vec<(&str, &'static str, Vec<String>)> {
+        let mut memo = HashMap::new();
+        self.messages
+            .keys()
+            .filter(|name| path == "." || *name == path || name.starts_with(&format!("{}.", path)))
+            .filter(|name| self.takes_extra_derives(name) && !self.prost_derives_eq(name))
+            .filter_map(|name| {
+                let derives = match self.derivable(name, &mut memo) {
+                    (true, true) => "Eq, Hash",
+                    (true, false) => "Eq",
+                    _ => return None,
+                };
+                Some((name.as_str(), derives, self.oneofs_without_eq(name)))
+            })
+            .collect()
+    }
+
+    /// Top-level messages without nested messages other than map entries
+    fn takes_extra_derives(&self, name: &str) -> bool {
+        self.messages.get(name).is_some_and(|message| {
+            name.matches('.').count() == message.package.matches('.').count() + 2
+                && message.descriptor.nested_type.iter().all(is_map_entry)
+        })
+    }
+
+    /// prost's own rule for deriving `Eq, Hash`
+    fn prost_derives_eq(&self, name: &str) -> bool {
+        self.messages
+            .get(name)
+            .is_some_and(|message| message.descriptor.field.iter().all(|field| self.prost_field_eq(name, field)))
     }
-    #[cfg(target_os = "linux")]
-    {
-        "libproton_drive_sdk.so"
+
+    fn prost_field_eq(&self, message: &str, field: &FieldDescriptorProto) -> bool {
+        match field.r#type() {
+            FieldType::Float | FieldType::Double | FieldType::Group => false,
+            FieldType::Message => {
+                field.label() != FieldLabel::Repeated
+                    && !self.reaches(field.type_name(), message, &mut BTreeSet::new())
+                    && self.prost_derives_eq(field.type_name())
+            }
+            _ => true,
+        }
     }
-    #[cfg(target_os = "macos")]
-    {
-        "libproton_drive_sdk.dylib"
+
+    /// Whether `to` is reachable from `from` through single message fields, as prost looks for recursion
+    fn reaches(&self, from: &str, to: &str, seen: &mut BTreeSet<String>) -> bool {
+        if from == to {
+            return true;
+        }
+        if !seen.insert(from.to_string()) {
+            return false;
+        }
+        self.messages.get(from).is_some_and(|message| {
+            message.descriptor.field.iter().any(|field| {
+                field.r#type() == FieldType::Message
+                    && field.label() != FieldLabel::Repeated
+                    && self.reaches(field.type_name(), to, seen)
+            })
+        })
+    }
+
+    /// Whether the message has, or can be given, `Eq` and `Hash`
+    fn derivable(&self, name: &str, memo: &mut HashMap<String, (bool, bool)>) -> (bool, bool) {
+        if self.prost_derives_eq(name) {
+            return (true, true);
+        }
+        if !self.takes_extra_derives(name) {
+            return (false, false);
+        }
+        if let Some(&derivable) = memo.get(name) {
+            return derivable;
+        }
+        // a recursive message doesn't rule itself out
+        memo.insert(name.to_string(), (true, true));
+        let (mut eq, mut hash) = (true, true);
+        for field in &self.messages[name].descriptor.field {
+            let (field_eq, field_hash) = self.field_derivable(field, memo);
+            eq &= field_eq;
+            hash &= field_hash;
+        }
+        memo.insert(name.to_string(), (eq, hash));
+        (eq, hash)
+    }
+
+    fn field_derivable(&self, field: &FieldDescriptorProto, memo: &mut HashMap<String, (bool, bool)>) -> (bool, bool) {
+        match field.r#type() {
+            FieldType::Float | FieldType::Double | FieldType::Group => (false, false),
+            FieldType::Message => match self.messages.get(field.type_name()) {
+                // maps are HashMaps, which have no Hash
+                Some(entry) if is_map_entry(entry.descriptor) => {
+                    (self.field_derivable(&entry.descriptor.field[1], memo).0, false)
+                }
+                _ => self.derivable(field.type_name(), memo),
+            },
+            _ => (true, true),
+        }
+    }
+
+    /// The oneofs of the message prost doesn't derive `Eq, Hash` on, it generates them as enums
+    fn oneofs_without_eq(&self, name: &str) -> Vec<String> {
+        let message = self.messages[name].descriptor;
+        message
+            .oneof_decl
+            .iter()
+            .enumerate()
+            .filter_map(|(i, oneof)| {
+                // proto3 `optional` fields sit in a synthetic oneof of their own
+                let fields: Vec<_> = message
+                    .field
+                    .iter()
+                    .filter(|f| f.oneof_index == Some(i as i32) && !f.proto3_optional())
+                    .collect();
+                let needs_derive = fields.iter().any(|field| !self.prost_field_eq(name, field));
+                needs_derive.then(|| format!("{}.{}", name, oneof.name()))
+            })
+            .collect()
+    }
+
+    fn check_sensitive(&self, message: &str, field: &str) -> anyhow::Result<()> {
+        let descriptor = self.field(message, field);
+        let redactable = matches!(descriptor.r#type(), FieldType::String | FieldType::Bytes)
+            && descriptor.label() != FieldLabel::Repeated
+            && (descriptor.oneof_index.is_none() || descriptor.proto3_optional());
+        if !redactable {
+            bail!("{}.{} is marked sensitive, but only single string and bytes fields can be redacted", message, field);
+        }
+        Ok(())
+    }
+
+    /// Rust path of a message or enum, relative to the `protobufs` module
+    fn rust_path(&self, name: &str) -> String {
+        let package = self.messages.get(name).map(|message| message.package).or_else(|| self.enums.get(name).copied()).unwrap();
+        let mut segments: Vec<String> = package.split('.').map(str::to_string).collect();
+        let nested: Vec<&str> = name[package.len() + 2..].split('.').collect();
+        let (last, scopes) = nested.split_last().unwrap();
+        segments.extend(scopes.iter().map(|scope| snake_case(scope)));
+        segments.push(last.to_string());
+        segments.join("::")
+    }
+
+    /// A `Debug` impl that prints the message as prost would, the `sensitive` fields redacted
+    fn redacted_debug(&self, name: &str, sensitive: &[&str]) -> anyhow::Result<String> {
+        let MessageInfo { descriptor: message, proto3, .. } = self.messages[name];
+        let mut fields = String::new();
+        let mut oneofs_done = BTreeSet::new();
+        for field in &message.field {
+            let ident = field.name();
+            if RUST_KEYWORDS.contains(&ident) {
+                bail!("{}.{} is a Rust keyword, write the Debug of {} by hand", name, ident, name);
+            }
+            let optional = field.proto3_optional() || !proto3 && field.label() == FieldLabel::Optional;
+            let value = if let Some(index) = field.oneof_index.filter(|_| !field.proto3_optional()) {
+                if !oneofs_done.insert(index) {
+                    continue;
+                }
+                let oneof = message.oneof_decl[index as usize].name();
+                fields.push_str(&format!("            .field(\"{}\", &self.{})\n", oneof, oneof));
+                continue;
+            } else if sensitive.contains(&ident) {
+                let kind = if field.r#type() == FieldType::String { "text" } else { "bytes" };
+                if optional {
+                    format!("&self.{}.as_deref().map(redact::Secret::{})", ident, kind)
+                } else {
+                    format!("&redact::Secret::{}(&self.{})", kind, ident)
+                }
+            } else if field.r#type() == FieldType::Enum && field.label() != FieldLabel::Repeated && !optional {
+                format!("&{}::try_from(self.{}).map_err(|_| self.{})", self.rust_path(field.type_name()), ident, ident)
+            } else {
+                format!("&self.{}", ident)
+            };
+            fields.push_str(&format!("            .field(\"{}\", {})\n", ident, value));
+        }
+        Ok(format!(
+            "\nimpl ::core::fmt::Debug for {} {{\n    fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {{\n        f.debug_struct(\"{}\")\n{}            .finish()\n    }}\n}}\n",
+            self.rust_path(name),
+            message.name(),
+            fields
+        ))
+    }
+}
+
+/// Maps are declared as nested entry messages, prost turns them into `HashMap` fields
+fn is_map_entry(message: &DescriptorProto) -> bool {
+    message.options.as_ref().is_some_and(|options| options.map_entry())
+}
+
+const RUST_KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern", "false", "fn",
+    "for", "gen", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return", "self",
+    "static", "struct", "super", "trait", "true", "try", "type", "unsafe", "use", "where", "while",
+];
+
+/// Module name prost gives the nested types of a message
+fn snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for (i, c) in name.char_indices() {
+        if c.is_uppercase() && i > 0 {
+            snake.push('_');
+        }
+        snake.extend(c.to_lowercase());
+    }
+    snake
+}
+
+/// File name of the SDK library on the build target, not the host
+fn target_lib_name() -> anyhow::Result<&'static str> {
+    let os = env::var("CARGO_CFG_TARGET_OS")?;
+    platform::lib_name(&os).ok_or_else(|| anyhow!("There is no native SDK build for {}", os))
+}
+
+/// Name the linker knows the library file `lib_name` by, without its `lib` prefix and extension
+fn link_name(lib_name: &str) -> &str {
+    let stem = lib_name.split('.').next().unwrap_or(lib_name);
+    stem.strip_prefix("lib").unwrap_or(stem)
+}
+
+/// Runtime id of the build target, which names its SDK build and its subdirectory in PROTON_SDK_LIB_DIR
+fn target_runtime_id() -> anyhow::Result<&'static str> {
+    let (os, arch) = (env::var("CARGO_CFG_TARGET_OS")?, env::var("CARGO_CFG_TARGET_ARCH")?);
+    platform::runtime_id(&os, &arch)
+        .ok_or_else(|| anyhow!("There is no native SDK build for {} on {}", arch, os))
+}
+
+/// Without the `require-native-lib` or `static-link` features a missing SDK only warns and skips the copy,
+/// so the crate builds for `cargo check`, docs and unit tests
+fn missing_native_lib(reason: String) -> anyhow::Result<()> {
+    if env::var_os("CARGO_FEATURE_REQUIRE_NATIVE_LIB").is_some() {
+        bail!("{}, the require-native-lib feature needs the native SDK libraries", reason);
     }
-    #[cfg(not(any(target_os = "windows", target_os = "linux", target_os = "macos")))]
-    {
-        compile_error!("Unsupported operating system");
+    if env::var_os("CARGO_FEATURE_STATIC_LINK").is_some() {
+        bail!("{}, the static-link feature links the native SDK at build time", reason);
     }
+    println!(
+        "cargo:warning={}, the native SDK isn't copied and {} has to be found at runtime",
+        reason,
+        target_lib_name()?
+    );
+    Ok(())
 }
 
 fn copy_dlls_to_exe_dir() -> anyhow::Result<()> {
-    let lib_dir = env::var("PROTON_SDK_LIB_DIR")
-        .map(PathBuf::from)
-        .unwrap_or_else(|_| panic!("Error: PROTON_SDK_LIB_DIR environment variable must be set to the directory containing the SDK libraries."));
+    let lib_dir = match env::var_os("PROTON_SDK_LIB_DIR") {
+        Some(dir) => {
+            let dir = PathBuf::from(dir);
+            // a library, runtime directory or checksum manifest added or replaced later
+            if dir.is_dir() {
+                println!("cargo:rerun-if-changed={}", dir.display());
+            }
+            dir
+        }
+        None => match requested_download()? {
+            Some(download) => download_sdk(&download)?,
+            None => {
+                return missing_native_lib(
+                    "PROTON_SDK_LIB_DIR isn't set to the directory containing the SDK libraries".to_string(),
+                )
+            }
+        },
+    };
 
     if !lib_dir.is_dir() {
-        panic!("PROTON_SDK_LIB_DIR does not point to a valid directory: {}", lib_dir.display());
+        return missing_native_lib(format!(
+            "PROTON_SDK_LIB_DIR does not point to a valid directory: {}",
+            lib_dir.display()
+        ));
     }
+    // a checkout holding the builds of several targets, one subdirectory per runtime id
+    let root = lib_dir;
+    let runtime_dir = root.join(target_runtime_id()?);
+    let lib_dir = if runtime_dir.is_dir() { runtime_dir } else { root.clone() };
+    let checksums = Checksums::find(&root, &lib_dir)?;
+    let lib_name = target_lib_name()?;
 
-    let profile = env::var("PROFILE").unwrap_or_else(|_| "debug".to_string());
-    let target_dir = env::var("CARGO_TARGET_DIR")
-        .map(PathBuf::from)
-        .unwrap_or_else(|_| {
-            let manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
-            Path::new(&manifest_dir).parent().unwrap().join("target")
-        });
-    let exe_dir = target_dir.join(&profile);
-
-    fs::create_dir_all(&exe_dir)?;
+    let artifact_dirs = artifact_dirs()?;
+    for dir in &artifact_dirs {
+        fs::create_dir_all(dir)?;
+    }
 
-    #[cfg(target_os = "windows")]
-    let exts = ["dll"];
-    #[cfg(target_os = "linux")]
-    let exts = ["so"];
-    #[cfg(target_os = "macos")]
-    let exts = ["dylib"];
+    let exts = [Path::new(lib_name).extension().unwrap().to_str().unwrap()];
 
     let mut found = false;
     for entry in fs::read_dir(&lib_dir)? {
@@ -73,11 +494,13 @@ fn copy_dlls_to_exe_dir() -> anyhow::Result<()> {
         if path.is_file() {
             if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
                 if exts.iter().any(|&wanted| ext.eq_ignore_ascii_case(wanted)) {
-                    let dest = exe_dir.join(path.file_name().unwrap());
-                    fs::copy(&path, &dest)?;
-                    println!("Copied {} to {}", path.display(), dest.display());
-
-                    println!("cargo:rerun-if-changed={}", path.display());
+                    if let Some(checksums) = &checksums {
+                        let sha256 = checksums.verify(&root, &path)?;
+                        if path.file_name().is_some_and(|name| name == lib_name) {
+                            println!("cargo:rustc-env=PROTON_SDK_LIB_SHA256={}", sha256);
+                        }
+                    }
+                    place_library(&path, &artifact_dirs)?;
                     found = true;
                 }
             }
@@ -85,12 +508,342 @@ fn copy_dlls_to_exe_dir() -> anyhow::Result<()> {
     }
 
     if !found {
-        panic!(
+        return missing_native_lib(format!(
             "No library files with extensions {:?} found in PROTON_SDK_LIB_DIR: {}",
             exts,
             lib_dir.display()
-        );
+        ));
+    }
+
+    if env::var_os("CARGO_FEATURE_STATIC_LINK").is_some() {
+        // the copies placed next to the binaries are what the executable finds at runtime
+        println!("cargo:rustc-link-search=native={}", lib_dir.display());
+        println!("cargo:rustc-link-lib=dylib={}", link_name(lib_name));
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Where cargo puts the binaries of this build: the profile directory, then `deps/` where test
+/// binaries run from and `examples/`
+///
+/// OUT_DIR is `<target dir>[/<triple>]/<profile>/build/<package>-<hash>/out`, which gives the real
+/// profile directory whatever the target dir, `--target` or profile. Without that layout the
+/// target dir comes from CARGO_TARGET_DIR, CARGO_BUILD_TARGET_DIR or the workspace.
+fn artifact_dirs() -> anyhow::Result<Vec<PathBuf>> {
+    let out_dir = PathBuf::from(env::var("OUT_DIR")?);
+    let in_build_dir = out_dir.parent().and_then(Path::parent).is_some_and(|dir| dir.ends_with("build"));
+    let profile_dir = match out_dir.ancestors().nth(3) {
+        Some(dir) if in_build_dir => dir.to_path_buf(),
+        _ => {
+            let mut dir = env::var_os("CARGO_TARGET_DIR")
+                .or_else(|| env::var_os("CARGO_BUILD_TARGET_DIR"))
+                .map(PathBuf::from)
+                .unwrap_or_else(|| Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).parent().unwrap().join("target"));
+            let target = env::var("TARGET")?;
+            if target != env::var("HOST")? {
+                dir.push(target);
+            }
+            dir.join(env::var("PROFILE").unwrap_or_else(|_| "debug".to_string()))
+        }
+    };
+    Ok(vec![profile_dir.clone(), profile_dir.join("deps"), profile_dir.join("examples")])
+}
+
+/// Copies the library into the profile directory, the first of `dirs`, and links the others to that copy
+///
+/// A copy with the size and modification time of the source is left alone.
+fn place_library(source: &Path, dirs: &[PathBuf]) -> anyhow::Result<()> {
+    let (profile_dir, others) = dirs.split_first().unwrap();
+    let name = source.file_name().unwrap();
+    let copy = profile_dir.join(name);
+    let source_meta = fs::metadata(source)?;
+    let up_to_date = fs::metadata(&copy).is_ok_and(|copy_meta| {
+        copy_meta.len() == source_meta.len() && copy_meta.modified().ok() == source_meta.modified().ok()
+    });
+    if up_to_date {
+        println!("{} is up to date", copy.display());
+    } else {
+        // the copy takes the time of the source for the next build to compare, then its permissions
+        // as a read-only copy couldn't be given the time
+        let mut to = fs::File::create(&copy)?;
+        io::copy(&mut fs::File::open(source)?, &mut to)?;
+        to.set_modified(source_meta.modified()?)?;
+        drop(to);
+        fs::set_permissions(&copy, source_meta.permissions())?;
+        println!("Copied {} to {}", source.display(), copy.display());
+    }
+    for dir in others {
+        link_library(&copy, &dir.join(name))?;
+    }
+    Ok(())
+}
+
+/// Links `dest` in a subdirectory of the profile directory to the copy there, relatively so the
+/// target directory can be moved
+#[cfg(unix)]
+fn link_library(copy: &Path, dest: &Path) -> anyhow::Result<()> {
+    let target = Path::new("..").join(copy.file_name().unwrap());
+    if fs::read_link(dest).is_ok_and(|existing| existing == target) {
+        return Ok(());
+    }
+    if dest.symlink_metadata().is_ok() {
+        fs::remove_file(dest)?;
+    }
+    std::os::unix::fs::symlink(&target, dest)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn link_library(copy: &Path, dest: &Path) -> anyhow::Result<()> {
+    fs::copy(copy, dest)?;
+    Ok(())
+}
+
+/// Checksums of the libraries in PROTON_SDK_LIB_DIR, in `sha256sum` format
+const CHECKSUM_MANIFEST: &str = "proton-sdk.sha256";
+
+/// The checksums the copied libraries must have, keyed by their path in PROTON_SDK_LIB_DIR
+struct Checksums {
+    manifest: PathBuf,
+    sums: HashMap<String, String>,
+}
+
+impl Checksums {
+    /// Reads `PROTON_SDK_SHA256_MANIFEST`, or the manifest next to the libraries or at the root of
+    /// PROTON_SDK_LIB_DIR, nothing when there is none and the libraries are copied unchecked
+    fn find(root: &Path, lib_dir: &Path) -> anyhow::Result<Option<Self>> {
+        let manifest = match env::var_os("PROTON_SDK_SHA256_MANIFEST") {
+            Some(path) => PathBuf::from(path),
+            None => match [lib_dir, root].iter().map(|dir| dir.join(CHECKSUM_MANIFEST)).find(|path| path.is_file()) {
+                Some(path) => path,
+                None => return Ok(None),
+            },
+        };
+        let text = fs::read_to_string(&manifest)
+            .with_context(|| format!("Couldn't read the checksums in {}", manifest.display()))?;
+        println!("cargo:rerun-if-changed={}", manifest.display());
+
+        let mut sums = HashMap::new();
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((sha256, name)) = line.split_once(char::is_whitespace) else {
+                bail!("`{}` in {} isn't `<sha256>  <file>`", line, manifest.display());
+            };
+            // `*` marks binary mode in sha256sum output
+            let name = name.trim_start().trim_start_matches('*').trim_start_matches("./").replace('\\', "/");
+            sums.insert(name, sha256.to_ascii_lowercase());
+        }
+        Ok(Some(Self { manifest, sums }))
+    }
+
+    /// Checks the library at `path` against its entry, by its path under `root` or its file name,
+    /// returning its sha256
+    fn verify(&self, root: &Path, path: &Path) -> anyhow::Result<String> {
+        let relative = path.strip_prefix(root).unwrap_or(path).to_string_lossy().replace('\\', "/");
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let Some(expected) = self.sums.get(&relative).or_else(|| self.sums.get(name.as_ref())) else {
+            bail!("{} has no checksum in {}", path.display(), self.manifest.display());
+        };
+        let actual = sha256_hex(&fs::read(path)?);
+        if &actual != expected {
+            bail!(
+                "{} has sha256 {}, but {} lists {}, the library is stale or corrupted",
+                path.display(),
+                actual,
+                self.manifest.display(),
+                expected
+            );
+        }
+        Ok(actual)
+    }
+}
+
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(60);
+const MAX_REDIRECTS: usize = 5;
+
+/// An SDK archive to fetch and the sha256 it must have
+struct Download {
+    runtime_id: &'static str,
+    url: String,
+    sha256: String,
+}
+
+/// What `PROTON_SDK_DOWNLOAD_URL` asks for, nothing without it
+fn requested_download() -> anyhow::Result<Option<Download>> {
+    let Some(url) = env::var_os("PROTON_SDK_DOWNLOAD_URL") else {
+        return Ok(None);
+    };
+    let url = url.into_string().map_err(|_| anyhow!("PROTON_SDK_DOWNLOAD_URL isn't valid UTF-8"))?;
+    let sha256 = env::var("PROTON_SDK_DOWNLOAD_SHA256")
+        .map_err(|_| anyhow!("PROTON_SDK_DOWNLOAD_URL needs PROTON_SDK_DOWNLOAD_SHA256 set to the sha256 of the archive"))?;
+    Ok(Some(Download { runtime_id: target_runtime_id()?, url, sha256: sha256.to_ascii_lowercase() }))
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Fetches and checks the archive into `OUT_DIR`, extracts its libraries and returns their directory
+///
+/// An archive left by an earlier build is reused when its checksum still matches.
+fn download_sdk(download: &Download) -> anyhow::Result<PathBuf> {
+    let dir = PathBuf::from(env::var("OUT_DIR")?).join("proton-sdk").join(download.runtime_id);
+    let lib_dir = dir.join("lib");
+    fs::create_dir_all(&lib_dir)?;
+
+    let name = download
+        .url
+        .rsplit('/')
+        .next()
+        .filter(|name| !name.is_empty())
+        .ok_or_else(|| anyhow!("{} doesn't end with a file name", download.url))?;
+    let archive = dir.join(name);
+
+    let bytes = match fs::read(&archive).ok().filter(|bytes| sha256_hex(bytes) == download.sha256) {
+        Some(bytes) => bytes,
+        None => {
+            println!("cargo:warning=Downloading the native SDK for {} from {}", download.runtime_id, download.url);
+            let bytes = fetch(&download.url).with_context(|| {
+                format!(
+                    "Downloading the native SDK from {} failed, download it by hand and set \
+                    PROTON_SDK_LIB_DIR to the directory containing it (see docs/BUILDING.md)",
+                    download.url
+                )
+            })?;
+            let actual = sha256_hex(&bytes);
+            if actual != download.sha256 {
+                bail!(
+                    "The native SDK downloaded from {} has sha256 {}, {} was expected",
+                    download.url,
+                    actual,
+                    download.sha256
+                );
+            }
+            fs::write(&archive, &bytes)?;
+            bytes
+        }
+    };
+
+    if extract_libraries(name, &bytes, &lib_dir)? == 0 {
+        bail!("{} has no {} in it", download.url, target_lib_name()?);
+    }
+    Ok(lib_dir)
+}
+
+/// Follows redirects, as release downloads go through one
+fn fetch(url: &str) -> anyhow::Result<Vec<u8>> {
+    let mut url = url::Url::parse(url)?;
+    for _ in 0..MAX_REDIRECTS {
+        if url.scheme() == "file" {
+            let path = url.to_file_path().map_err(|_| anyhow!("{} isn't a local path", url))?;
+            return Ok(fs::read(path)?);
+        }
+        let (status, location, body) = get(&url)?;
+        match (status, location) {
+            (200, _) => return Ok(body),
+            (301 | 302 | 303 | 307 | 308, Some(location)) => url = url.join(&location)?,
+            _ => bail!("{} answered HTTP {}", url, status),
+        }
+    }
+    bail!("{} redirects more than {} times", url, MAX_REDIRECTS)
+}
+
+/// A plain HTTP/1.0 GET, so the body is neither chunked nor kept alive,
+/// returning the status, the `Location` header and the body
+fn get(url: &url::Url) -> anyhow::Result<(u16, Option<String>, Vec<u8>)> {
+    let host = url.host_str().ok_or_else(|| anyhow!("{} has no host", url))?;
+    let port = url.port_or_known_default().ok_or_else(|| anyhow!("{} has no port", url))?;
+    let tcp = TcpStream::connect((host, port))?;
+    tcp.set_read_timeout(Some(DOWNLOAD_TIMEOUT))?;
+    tcp.set_write_timeout(Some(DOWNLOAD_TIMEOUT))?;
+
+    let request = format!(
+        "GET {}{} HTTP/1.0\r\nHost: {}\r\nUser-Agent: proton-sdk-sys-build\r\nConnection: close\r\n\r\n",
+        url.path(),
+        url.query().map(|query| format!("?{}", query)).unwrap_or_default(),
+        host
+    );
+    let mut response = Vec::new();
+    // servers often close TLS without a close_notify, a short body is caught by Content-Length below
+    let read = match url.scheme() {
+        "https" => {
+            let mut tls = native_tls::TlsConnector::new()?.connect(host, tcp)?;
+            tls.write_all(request.as_bytes())?;
+            tls.read_to_end(&mut response)
+        }
+        "http" => {
+            let mut tcp = tcp;
+            tcp.write_all(request.as_bytes())?;
+            tcp.read_to_end(&mut response)
+        }
+        scheme => bail!("{} URLs can't be downloaded", scheme),
+    };
+
+    let end = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or_else(|| match &read {
+            Err(e) => anyhow!("{} closed the connection: {}", host, e),
+            _ => anyhow!("{} sent no HTTP response", host),
+        })?;
+    let head = String::from_utf8_lossy(&response[..end]).into_owned();
+    let body = response.split_off(end + 4);
+
+    let mut lines = head.lines();
+    let status = lines
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| anyhow!("{} sent no HTTP status", host))?;
+    let header = |name: &str| {
+        lines.clone().find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            key.trim().eq_ignore_ascii_case(name).then(|| value.trim().to_string())
+        })
+    };
+    let length = header("content-length").and_then(|length| length.parse::<usize>().ok());
+    if length.is_some_and(|length| body.len() < length) || (length.is_none() && read.is_err()) {
+        bail!("{} closed the connection after {} bytes", host, body.len());
+    }
+    Ok((status, header("location"), body))
+}
+
+/// Writes the libraries of a `.zip`, `.tar.gz` or bare library download flat into `dest`, returning how many
+fn extract_libraries(name: &str, bytes: &[u8], dest: &Path) -> anyhow::Result<usize> {
+    let extension = Path::new(target_lib_name()?).extension().unwrap_or_default();
+    let is_library = |path: &Path| path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case(extension));
+    let mut count = 0;
+
+    if name.ends_with(".zip") {
+        let mut archive = zip::ZipArchive::new(io::Cursor::new(bytes))?;
+        for i in 0..archive.len() {
+            let mut entry = archive.by_index(i)?;
+            let Some(path) = entry.enclosed_name().filter(|path| entry.is_file() && is_library(path)) else {
+                continue;
+            };
+            let mut file = fs::File::create(dest.join(path.file_name().unwrap()))?;
+            io::copy(&mut entry, &mut file)?;
+            count += 1;
+        }
+    } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(bytes));
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let path = entry.path()?.into_owned();
+            if !entry.header().entry_type().is_file() || !is_library(&path) {
+                continue;
+            }
+            entry.unpack(dest.join(path.file_name().unwrap()))?;
+            count += 1;
+        }
+    } else if is_library(Path::new(name)) {
+        fs::write(dest.join(name), bytes)?;
+        count += 1;
+    } else {
+        bail!("{} isn't a .zip, .tar.gz or library download", name);
+    }
+    Ok(count)
+}
@@ -0,0 +1,376 @@
+#[cfg(any(test, feature = "test-support"))]
+pub mod mock;
+
+use std::sync::{Arc, OnceLock};
+
+use crate::{
+    cancellation::{self, CancellationTokenHandle},
+    data::{
+        AsyncCallback, AsyncCallbackWithProgress, BooleanCallback, ByteArray, Callback, OwnedByteArray,
+        TwoFactorRequestedCallback,
+    },
+    downloads::{self, DownloaderHandle},
+    drive::{self, DriveClientHandle},
+    nodes,
+    observability::{self, ObservabilityHandle},
+    protobufs::account::SessionInfo,
+    sessions::{self, SessionHandle},
+    uploads::{self, UploaderHandle},
+};
+
+#[cfg(any(test, feature = "test-support"))]
+pub use self::mock::{Call, MockApi, Reply};
+
+/// The native SDK calls the safe bindings make
+///
+/// [`LibloadingApi`] makes them against the loaded library, the `MockApi` of the
+/// `test-support` feature answers them without one. Each method takes the
+/// arguments of the `raw` function of the same name, which documents it.
+pub trait SdkApi: Send + Sync {
+    fn session_begin(
+        &self,
+        request: ByteArray,
+        request_response_callback: Callback,
+        secret_requested_callback: BooleanCallback,
+        two_factor_requested_callback: TwoFactorRequestedCallback,
+        tokens_refreshed_callback: Callback,
+        async_callback: AsyncCallback,
+    ) -> anyhow::Result<i32>;
+
+    fn session_resume(
+        &self,
+        request: ByteArray,
+        request_response_callback: Callback,
+        secret_requested_callback: BooleanCallback,
+        tokens_refreshed_callback: Callback,
+    ) -> anyhow::Result<(i32, SessionHandle)>;
+
+    fn session_renew(
+        &self,
+        old_session_handle: SessionHandle,
+        request: ByteArray,
+        tokens_refreshed_callback: Callback,
+    ) -> anyhow::Result<(i32, SessionHandle)>;
+
+    fn session_free(&self, session_handle: SessionHandle) -> anyhow::Result<()>;
+
+    fn session_register_armored_locked_user_key(
+        &self,
+        session_handle: SessionHandle,
+        armored_user_key: ByteArray,
+    ) -> anyhow::Result<i32>;
+
+    fn session_register_address_keys(&self, session_handle: SessionHandle, request: ByteArray) -> anyhow::Result<i32>;
+
+    fn session_get_info(
+        &self,
+        session_handle: SessionHandle,
+        cancellation_token: CancellationTokenHandle,
+    ) -> anyhow::Result<SessionInfo>;
+
+    fn session_apply_data_password(
+        &self,
+        session_handle: SessionHandle,
+        password: ByteArray,
+        cancellation_token: CancellationTokenHandle,
+    ) -> anyhow::Result<i32>;
+
+    fn cancellation_token_create(&self) -> anyhow::Result<isize>;
+
+    fn cancellation_token_cancel(&self, handle: isize) -> anyhow::Result<()>;
+
+    fn cancellation_token_free(&self, handle: isize) -> anyhow::Result<()>;
+
+    fn observability_service_start_new(&self, session_handle: SessionHandle) -> anyhow::Result<(i32, ObservabilityHandle)>;
+
+    fn observability_service_flush(&self, observability_handle: ObservabilityHandle, callback: AsyncCallback) -> anyhow::Result<i32>;
+
+    fn observability_service_free(&self, observability_handle: ObservabilityHandle) -> anyhow::Result<()>;
+
+    fn drive_client_create(
+        &self,
+        session_handle: SessionHandle,
+        observability_handle: ObservabilityHandle,
+        request: ByteArray,
+    ) -> anyhow::Result<(i32, DriveClientHandle)>;
+
+    fn drive_client_register_node_keys(&self, client_handle: DriveClientHandle, request: ByteArray) -> anyhow::Result<i32>;
+
+    fn drive_client_register_share_key(&self, client_handle: DriveClientHandle, request: ByteArray) -> anyhow::Result<i32>;
+
+    fn drive_client_free(&self, client_handle: DriveClientHandle) -> anyhow::Result<()>;
+
+    fn drive_client_get_volumes(
+        &self,
+        client_handle: DriveClientHandle,
+        cancellation_token: CancellationTokenHandle,
+    ) -> anyhow::Result<OwnedByteArray>;
+
+    fn drive_client_get_shares(
+        &self,
+        client_handle: DriveClientHandle,
+        volume_metadata: ByteArray,
+        cancellation_token: CancellationTokenHandle,
+    ) -> anyhow::Result<OwnedByteArray>;
+
+    fn drive_client_get_folder_children(
+        &self,
+        client_handle: DriveClientHandle,
+        node_identity: ByteArray,
+        cancellation_token: CancellationTokenHandle,
+    ) -> anyhow::Result<OwnedByteArray>;
+
+    fn node_decrypt_armored_name(
+        &self,
+        client_handle: DriveClientHandle,
+        request: ByteArray,
+        callback: AsyncCallback,
+    ) -> anyhow::Result<i32>;
+
+    fn downloader_create(&self, client_handle: DriveClientHandle, request: ByteArray, callback: AsyncCallback) -> anyhow::Result<i32>;
+
+    fn downloader_download_file(
+        &self,
+        downloader_handle: DownloaderHandle,
+        request: ByteArray,
+        callback: AsyncCallbackWithProgress,
+    ) -> anyhow::Result<i32>;
+
+    fn downloader_free(&self, downloader_handle: DownloaderHandle) -> anyhow::Result<()>;
+
+    fn uploader_create(&self, client_handle: DriveClientHandle, request: ByteArray, callback: AsyncCallback) -> anyhow::Result<i32>;
+
+    fn uploader_upload_file_or_revision(
+        &self,
+        uploader_handle: UploaderHandle,
+        request: ByteArray,
+        callback: AsyncCallbackWithProgress,
+    ) -> anyhow::Result<i32>;
+
+    fn uploader_upload_revision(
+        &self,
+        uploader_handle: UploaderHandle,
+        request: ByteArray,
+        callback: AsyncCallbackWithProgress,
+    ) -> anyhow::Result<i32>;
+
+    fn uploader_free(&self, uploader_handle: UploaderHandle) -> anyhow::Result<()>;
+}
+
+/// Calls the native SDK loaded by [`ProtonSDKLib`](crate::ProtonSDKLib)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LibloadingApi;
+
+impl LibloadingApi {
+    /// The instance the bindings use unless they are given another [`SdkApi`]
+    pub fn shared() -> Arc<dyn SdkApi> {
+        static SHARED: OnceLock<Arc<dyn SdkApi>> = OnceLock::new();
+        SHARED.get_or_init(|| Arc::new(LibloadingApi)).clone()
+    }
+}
+
+impl SdkApi for LibloadingApi {
+    fn session_begin(
+        &self,
+        request: ByteArray,
+        request_response_callback: Callback,
+        secret_requested_callback: BooleanCallback,
+        two_factor_requested_callback: TwoFactorRequestedCallback,
+        tokens_refreshed_callback: Callback,
+        async_callback: AsyncCallback,
+    ) -> anyhow::Result<i32> {
+        unsafe {
+            sessions::raw::session_begin(
+                0,
+                request,
+                request_response_callback,
+                secret_requested_callback,
+                two_factor_requested_callback,
+                tokens_refreshed_callback,
+                async_callback,
+            )
+        }
+    }
+
+    fn session_resume(
+        &self,
+        request: ByteArray,
+        request_response_callback: Callback,
+        secret_requested_callback: BooleanCallback,
+        tokens_refreshed_callback: Callback,
+    ) -> anyhow::Result<(i32, SessionHandle)> {
+        unsafe {
+            sessions::raw::session_resume(
+                request,
+                request_response_callback,
+                secret_requested_callback,
+                tokens_refreshed_callback,
+            )
+        }
+    }
+
+    fn session_renew(
+        &self,
+        old_session_handle: SessionHandle,
+        request: ByteArray,
+        tokens_refreshed_callback: Callback,
+    ) -> anyhow::Result<(i32, SessionHandle)> {
+        unsafe { sessions::raw::session_renew(old_session_handle, request, tokens_refreshed_callback) }
+    }
+
+    fn session_free(&self, session_handle: SessionHandle) -> anyhow::Result<()> {
+        unsafe { sessions::raw::session_free(session_handle) }
+    }
+
+    fn session_register_armored_locked_user_key(
+        &self,
+        session_handle: SessionHandle,
+        armored_user_key: ByteArray,
+    ) -> anyhow::Result<i32> {
+        sessions::raw::session_register_armored_locked_user_key(session_handle, armored_user_key)
+    }
+
+    fn session_register_address_keys(&self, session_handle: SessionHandle, request: ByteArray) -> anyhow::Result<i32> {
+        sessions::raw::session_register_address_keys(session_handle, request)
+    }
+
+    fn session_get_info(
+        &self,
+        session_handle: SessionHandle,
+        cancellation_token: CancellationTokenHandle,
+    ) -> anyhow::Result<SessionInfo> {
+        sessions::raw::session_get_info(session_handle, cancellation_token)
+    }
+
+    fn session_apply_data_password(
+        &self,
+        session_handle: SessionHandle,
+        password: ByteArray,
+        cancellation_token: CancellationTokenHandle,
+    ) -> anyhow::Result<i32> {
+        sessions::raw::session_apply_data_password(session_handle, password, cancellation_token)
+    }
+
+    fn cancellation_token_create(&self) -> anyhow::Result<isize> {
+        cancellation::raw::create()
+    }
+
+    fn cancellation_token_cancel(&self, handle: isize) -> anyhow::Result<()> {
+        cancellation::raw::cancel(handle)
+    }
+
+    fn cancellation_token_free(&self, handle: isize) -> anyhow::Result<()> {
+        cancellation::raw::free(handle)
+    }
+
+    fn observability_service_start_new(&self, session_handle: SessionHandle) -> anyhow::Result<(i32, ObservabilityHandle)> {
+        observability::raw::observability_service_start_new(session_handle)
+    }
+
+    fn observability_service_flush(&self, observability_handle: ObservabilityHandle, callback: AsyncCallback) -> anyhow::Result<i32> {
+        observability::raw::observability_service_flush(observability_handle, callback)
+    }
+
+    fn observability_service_free(&self, observability_handle: ObservabilityHandle) -> anyhow::Result<()> {
+        observability::raw::observability_service_free(observability_handle)
+    }
+
+    fn drive_client_create(
+        &self,
+        session_handle: SessionHandle,
+        observability_handle: ObservabilityHandle,
+        request: ByteArray,
+    ) -> anyhow::Result<(i32, DriveClientHandle)> {
+        drive::raw::drive_client_create(session_handle, observability_handle, request)
+    }
+
+    fn drive_client_register_node_keys(&self, client_handle: DriveClientHandle, request: ByteArray) -> anyhow::Result<i32> {
+        drive::raw::drive_client_register_node_keys(client_handle, request)
+    }
+
+    fn drive_client_register_share_key(&self, client_handle: DriveClientHandle, request: ByteArray) -> anyhow::Result<i32> {
+        drive::raw::drive_client_register_share_key(client_handle, request)
+    }
+
+    fn drive_client_free(&self, client_handle: DriveClientHandle) -> anyhow::Result<()> {
+        drive::raw::drive_client_free(client_handle)
+    }
+
+    fn drive_client_get_volumes(
+        &self,
+        client_handle: DriveClientHandle,
+        cancellation_token: CancellationTokenHandle,
+    ) -> anyhow::Result<OwnedByteArray> {
+        drive::raw::drive_client_get_volumes(client_handle, cancellation_token)
+    }
+
+    fn drive_client_get_shares(
+        &self,
+        client_handle: DriveClientHandle,
+        volume_metadata: ByteArray,
+        cancellation_token: CancellationTokenHandle,
+    ) -> anyhow::Result<OwnedByteArray> {
+        drive::raw::drive_client_get_shares(client_handle, volume_metadata, cancellation_token)
+    }
+
+    fn drive_client_get_folder_children(
+        &self,
+        client_handle: DriveClientHandle,
+        node_identity: ByteArray,
+        cancellation_token: CancellationTokenHandle,
+    ) -> anyhow::Result<OwnedByteArray> {
+        drive::raw::drive_client_get_folder_children(client_handle, node_identity, cancellation_token)
+    }
+
+    fn node_decrypt_armored_name(
+        &self,
+        client_handle: DriveClientHandle,
+        request: ByteArray,
+        callback: AsyncCallback,
+    ) -> anyhow::Result<i32> {
+        nodes::raw::node_decrypt_armored_name(client_handle, request, callback)
+    }
+
+    fn downloader_create(&self, client_handle: DriveClientHandle, request: ByteArray, callback: AsyncCallback) -> anyhow::Result<i32> {
+        downloads::raw::downloader_create(client_handle, request, callback)
+    }
+
+    fn downloader_download_file(
+        &self,
+        downloader_handle: DownloaderHandle,
+        request: ByteArray,
+        callback: AsyncCallbackWithProgress,
+    ) -> anyhow::Result<i32> {
+        downloads::raw::downloader_download_file(downloader_handle, request, callback)
+    }
+
+    fn downloader_free(&self, downloader_handle: DownloaderHandle) -> anyhow::Result<()> {
+        downloads::raw::downloader_free(downloader_handle)
+    }
+
+    fn uploader_create(&self, client_handle: DriveClientHandle, request: ByteArray, callback: AsyncCallback) -> anyhow::Result<i32> {
+        uploads::raw::uploader_create(client_handle, request, callback)
+    }
+
+    fn uploader_upload_file_or_revision(
+        &self,
+        uploader_handle: UploaderHandle,
+        request: ByteArray,
+        callback: AsyncCallbackWithProgress,
+    ) -> anyhow::Result<i32> {
+        uploads::raw::uploader_upload_file_or_revision(uploader_handle, request, callback)
+    }
+
+    fn uploader_upload_revision(
+        &self,
+        uploader_handle: UploaderHandle,
+        request: ByteArray,
+        callback: AsyncCallbackWithProgress,
+    ) -> anyhow::Result<i32> {
+        uploads::raw::uploader_upload_revision(uploader_handle, request, callback)
+    }
+
+    fn uploader_free(&self, uploader_handle: UploaderHandle) -> anyhow::Result<()> {
+        uploads::raw::uploader_free(uploader_handle)
+    }
+}
@@ -0,0 +1,451 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicIsize, Ordering},
+        Mutex,
+    },
+};
+
+use anyhow::anyhow;
+use prost::Message;
+
+use super::SdkApi;
+use crate::{
+    cancellation::CancellationTokenHandle,
+    data::{
+        AsyncCallback, AsyncCallbackWithProgress, BooleanCallback, ByteArray, Callback, OwnedByteArray,
+        TwoFactorRequestedCallback,
+    },
+    downloads::DownloaderHandle,
+    drive::DriveClientHandle,
+    observability::ObservabilityHandle,
+    protobufs::{account::{IntResponse, SessionInfo}, drive::ProgressUpdate},
+    sessions::SessionHandle,
+    uploads::UploaderHandle,
+    SdkLibError,
+};
+
+/// How [`MockApi`] answers a call
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reply {
+    /// Succeeds with these bytes, given to the success callback or returned by a call without one
+    Success(Vec<u8>),
+    /// Fails with these bytes, given to the failure callback or as the error of a call without one
+    Failure(Vec<u8>),
+    /// Returns this code instead of 0 and never calls back, as when the SDK rejects the call outright
+    Code(i32),
+    /// Fails like a library without the export
+    Missing,
+    /// Returns 0 and never calls back
+    Pending,
+}
+
+/// A call [`MockApi`] received
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Call {
+    pub name: &'static str,
+    /// The session, client, downloader, uploader or token it was made on, 0 for none
+    pub handle: isize,
+    pub request: Vec<u8>,
+}
+
+/// An [`SdkApi`] answering with programmed replies and recording every call
+///
+/// A call without a programmed reply succeeds, with a new handle when it
+/// creates something and empty bytes otherwise. Callbacks are called before
+/// the call returns, after the progress updates programmed for it.
+#[derive(Debug)]
+pub struct MockApi {
+    replies: Mutex<HashMap<&'static str, VecDeque<Reply>>>,
+    progress: Mutex<HashMap<&'static str, Vec<Vec<u8>>>>,
+    calls: Mutex<Vec<Call>>,
+    next_handle: AtomicIsize,
+}
+
+impl Default for MockApi {
+    fn default() -> Self {
+        Self {
+            replies: Mutex::default(),
+            progress: Mutex::default(),
+            calls: Mutex::default(),
+            next_handle: AtomicIsize::new(1),
+        }
+    }
+}
+
+impl MockApi {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answers the next call to `name` with `reply`, replies to the same call are used in order
+    pub fn reply(&self, name: &'static str, reply: Reply) -> &Self {
+        self.replies.lock().unwrap().entry(name).or_default().push_back(reply);
+        self
+    }
+
+    /// Sends `updates` to the progress callback of every call to `name` before it completes
+    pub fn progress(&self, name: &'static str, updates: &[ProgressUpdate]) -> &Self {
+        let updates = updates.iter().map(Message::encode_to_vec).collect();
+        self.progress.lock().unwrap().insert(name, updates);
+        self
+    }
+
+    /// Every call received so far, in order
+    pub fn calls(&self) -> Vec<Call> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    /// The calls to `name` received so far
+    pub fn calls_to(&self, name: &str) -> Vec<Call> {
+        self.calls().into_iter().filter(|call| call.name == name).collect()
+    }
+
+    fn new_handle(&self) -> isize {
+        self.next_handle.fetch_add(1, Ordering::Relaxed)
+    }
+
+    fn record(&self, name: &'static str, handle: isize, request: ByteArray) -> Option<Reply> {
+        let request = request.to_vec();
+        self.calls.lock().unwrap().push(Call { name, handle, request });
+        self.replies.lock().unwrap().get_mut(name).and_then(VecDeque::pop_front)
+    }
+
+    /// Answers a call without callback, with the bytes of a success or the code returned instead
+    fn answer(&self, name: &'static str, handle: isize, request: ByteArray) -> anyhow::Result<Result<Vec<u8>, i32>> {
+        match self.record(name, handle, request) {
+            None | Some(Reply::Pending) => Ok(Ok(Vec::new())),
+            Some(Reply::Success(bytes)) => Ok(Ok(bytes)),
+            Some(Reply::Code(code)) => Ok(Err(code)),
+            Some(Reply::Failure(bytes)) => Err(anyhow!("{} failed: {}", name, String::from_utf8_lossy(&bytes))),
+            Some(Reply::Missing) => Err(SdkLibError::SymbolMissing(name).into()),
+        }
+    }
+
+    fn code(&self, name: &'static str, handle: isize, request: ByteArray) -> anyhow::Result<i32> {
+        Ok(self.answer(name, handle, request)?.err().unwrap_or(0))
+    }
+
+    /// Answers a call returning the handle of what it created
+    fn create(&self, name: &'static str, handle: isize, request: ByteArray) -> anyhow::Result<(i32, isize)> {
+        Ok(match self.answer(name, handle, request)? {
+            Ok(_) => (0, self.new_handle()),
+            Err(code) => (code, 0),
+        })
+    }
+
+    fn bytes(&self, name: &'static str, handle: isize, request: ByteArray) -> anyhow::Result<OwnedByteArray> {
+        match self.answer(name, handle, request)? {
+            Ok(bytes) => Ok(OwnedByteArray::from(bytes)),
+            Err(code) => anyhow::bail!("{} failed with code {}", name, code),
+        }
+    }
+
+    fn free(&self, name: &'static str, handle: isize) -> anyhow::Result<()> {
+        self.answer(name, handle, ByteArray::empty()).map(|_| ())
+    }
+
+    /// Answers a call with callbacks, a default success responds with a new handle when `created`
+    fn call_back(
+        &self,
+        name: &'static str,
+        handle: isize,
+        request: ByteArray,
+        callback: &AsyncCallback,
+        progress: Option<&Callback>,
+        created: bool,
+    ) -> anyhow::Result<i32> {
+        let reply = self.record(name, handle, request).unwrap_or_else(|| {
+            let response = if created { IntResponse { value: self.new_handle() as i64 }.encode_to_vec() } else { Vec::new() };
+            Reply::Success(response)
+        });
+        let (bytes, on_done) = match reply {
+            Reply::Success(bytes) => (bytes, callback.on_success),
+            Reply::Failure(bytes) => (bytes, callback.on_failure),
+            Reply::Code(code) => return Ok(code),
+            Reply::Missing => return Err(SdkLibError::SymbolMissing(name).into()),
+            Reply::Pending => return Ok(0),
+        };
+
+        if let Some(Callback { state, callback: Some(on_progress) }) = progress {
+            let updates = self.progress.lock().unwrap().get(name).cloned().unwrap_or_default();
+            for update in updates {
+                on_progress(*state, ByteArray::from_slice(&update));
+            }
+        }
+        if let Some(on_done) = on_done {
+            on_done(callback.state, ByteArray::from_slice(&bytes));
+        }
+        Ok(0)
+    }
+}
+
+impl SdkApi for MockApi {
+    fn session_begin(
+        &self,
+        request: ByteArray,
+        _request_response_callback: Callback,
+        _secret_requested_callback: BooleanCallback,
+        _two_factor_requested_callback: TwoFactorRequestedCallback,
+        _tokens_refreshed_callback: Callback,
+        async_callback: AsyncCallback,
+    ) -> anyhow::Result<i32> {
+        self.call_back("session_begin", 0, request, &async_callback, None, true)
+    }
+
+    fn session_resume(
+        &self,
+        request: ByteArray,
+        _request_response_callback: Callback,
+        _secret_requested_callback: BooleanCallback,
+        _tokens_refreshed_callback: Callback,
+    ) -> anyhow::Result<(i32, SessionHandle)> {
+        let (code, handle) = self.create("session_resume", 0, request)?;
+        Ok((code, SessionHandle::from(handle)))
+    }
+
+    fn session_renew(
+        &self,
+        old_session_handle: SessionHandle,
+        request: ByteArray,
+        _tokens_refreshed_callback: Callback,
+    ) -> anyhow::Result<(i32, SessionHandle)> {
+        let (code, handle) = self.create("session_renew", old_session_handle.raw(), request)?;
+        Ok((code, SessionHandle::from(handle)))
+    }
+
+    fn session_free(&self, session_handle: SessionHandle) -> anyhow::Result<()> {
+        self.free("session_free", session_handle.raw())
+    }
+
+    fn session_register_armored_locked_user_key(
+        &self,
+        session_handle: SessionHandle,
+        armored_user_key: ByteArray,
+    ) -> anyhow::Result<i32> {
+        self.code("session_register_armored_locked_user_key", session_handle.raw(), armored_user_key)
+    }
+
+    fn session_register_address_keys(&self, session_handle: SessionHandle, request: ByteArray) -> anyhow::Result<i32> {
+        self.code("session_register_address_keys", session_handle.raw(), request)
+    }
+
+    fn session_get_info(
+        &self,
+        session_handle: SessionHandle,
+        _cancellation_token: CancellationTokenHandle,
+    ) -> anyhow::Result<SessionInfo> {
+        match self.answer("session_get_info", session_handle.raw(), ByteArray::empty())? {
+            Ok(bytes) => Ok(SessionInfo::decode(bytes.as_slice())?),
+            Err(code) => anyhow::bail!("session_get_info failed with code {}", code),
+        }
+    }
+
+    fn session_apply_data_password(
+        &self,
+        session_handle: SessionHandle,
+        password: ByteArray,
+        _cancellation_token: CancellationTokenHandle,
+    ) -> anyhow::Result<i32> {
+        self.code("session_apply_data_password", session_handle.raw(), password)
+    }
+
+    fn cancellation_token_create(&self) -> anyhow::Result<isize> {
+        match self.create("cancellation_token_source_create", 0, ByteArray::empty())? {
+            (_, 0) => anyhow::bail!("Failed to create cancellation token source"),
+            (_, handle) => Ok(handle),
+        }
+    }
+
+    fn cancellation_token_cancel(&self, handle: isize) -> anyhow::Result<()> {
+        self.free("cancellation_token_source_cancel", handle)
+    }
+
+    fn cancellation_token_free(&self, handle: isize) -> anyhow::Result<()> {
+        self.free("cancellation_token_source_free", handle)
+    }
+
+    fn observability_service_start_new(&self, session_handle: SessionHandle) -> anyhow::Result<(i32, ObservabilityHandle)> {
+        let (code, handle) = self.create("observability_service_start_new", session_handle.raw(), ByteArray::empty())?;
+        Ok((code, ObservabilityHandle::from(handle)))
+    }
+
+    fn observability_service_flush(&self, observability_handle: ObservabilityHandle, callback: AsyncCallback) -> anyhow::Result<i32> {
+        self.call_back("observability_service_flush", observability_handle.raw(), ByteArray::empty(), &callback, None, false)
+    }
+
+    fn observability_service_free(&self, observability_handle: ObservabilityHandle) -> anyhow::Result<()> {
+        self.free("observability_service_free", observability_handle.raw())
+    }
+
+    fn drive_client_create(
+        &self,
+        session_handle: SessionHandle,
+        _observability_handle: ObservabilityHandle,
+        request: ByteArray,
+    ) -> anyhow::Result<(i32, DriveClientHandle)> {
+        let (code, handle) = self.create("drive_client_create", session_handle.raw(), request)?;
+        Ok((code, DriveClientHandle::from(handle)))
+    }
+
+    fn drive_client_register_node_keys(&self, client_handle: DriveClientHandle, request: ByteArray) -> anyhow::Result<i32> {
+        self.code("drive_client_register_node_keys", client_handle.raw(), request)
+    }
+
+    fn drive_client_register_share_key(&self, client_handle: DriveClientHandle, request: ByteArray) -> anyhow::Result<i32> {
+        self.code("drive_client_register_share_key", client_handle.raw(), request)
+    }
+
+    fn drive_client_free(&self, client_handle: DriveClientHandle) -> anyhow::Result<()> {
+        self.free("drive_client_free", client_handle.raw())
+    }
+
+    fn drive_client_get_volumes(
+        &self,
+        client_handle: DriveClientHandle,
+        _cancellation_token: CancellationTokenHandle,
+    ) -> anyhow::Result<OwnedByteArray> {
+        self.bytes("drive_client_get_volumes", client_handle.raw(), ByteArray::empty())
+    }
+
+    fn drive_client_get_shares(
+        &self,
+        client_handle: DriveClientHandle,
+        volume_metadata: ByteArray,
+        _cancellation_token: CancellationTokenHandle,
+    ) -> anyhow::Result<OwnedByteArray> {
+        self.bytes("drive_client_get_shares", client_handle.raw(), volume_metadata)
+    }
+
+    fn drive_client_get_folder_children(
+        &self,
+        client_handle: DriveClientHandle,
+        node_identity: ByteArray,
+        _cancellation_token: CancellationTokenHandle,
+    ) -> anyhow::Result<OwnedByteArray> {
+        self.bytes("drive_client_get_folder_children", client_handle.raw(), node_identity)
+    }
+
+    fn node_decrypt_armored_name(
+        &self,
+        client_handle: DriveClientHandle,
+        request: ByteArray,
+        callback: AsyncCallback,
+    ) -> anyhow::Result<i32> {
+        self.call_back("node_decrypt_armored_name", client_handle.raw(), request, &callback, None, false)
+    }
+
+    fn downloader_create(&self, client_handle: DriveClientHandle, request: ByteArray, callback: AsyncCallback) -> anyhow::Result<i32> {
+        self.call_back("downloader_create", client_handle.raw(), request, &callback, None, true)
+    }
+
+    fn downloader_download_file(
+        &self,
+        downloader_handle: DownloaderHandle,
+        request: ByteArray,
+        callback: AsyncCallbackWithProgress,
+    ) -> anyhow::Result<i32> {
+        self.call_back(
+            "downloader_download_file",
+            downloader_handle.raw(),
+            request,
+            &callback.async_callback,
+            Some(&callback.progress_callback),
+            false,
+        )
+    }
+
+    fn downloader_free(&self, downloader_handle: DownloaderHandle) -> anyhow::Result<()> {
+        self.free("downloader_free", downloader_handle.raw())
+    }
+
+    fn uploader_create(&self, client_handle: DriveClientHandle, request: ByteArray, callback: AsyncCallback) -> anyhow::Result<i32> {
+        self.call_back("uploader_create", client_handle.raw(), request, &callback, None, true)
+    }
+
+    fn uploader_upload_file_or_revision(
+        &self,
+        uploader_handle: UploaderHandle,
+        request: ByteArray,
+        callback: AsyncCallbackWithProgress,
+    ) -> anyhow::Result<i32> {
+        self.call_back(
+            "uploader_upload_file_or_revision",
+            uploader_handle.raw(),
+            request,
+            &callback.async_callback,
+            Some(&callback.progress_callback),
+            false,
+        )
+    }
+
+    fn uploader_upload_revision(
+        &self,
+        uploader_handle: UploaderHandle,
+        request: ByteArray,
+        callback: AsyncCallbackWithProgress,
+    ) -> anyhow::Result<i32> {
+        self.call_back(
+            "uploader_upload_revision",
+            uploader_handle.raw(),
+            request,
+            &callback.async_callback,
+            Some(&callback.progress_callback),
+            false,
+        )
+    }
+
+    fn uploader_free(&self, uploader_handle: UploaderHandle) -> anyhow::Result<()> {
+        self.free("uploader_free", uploader_handle.raw())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::c_void;
+
+    extern "C" fn record_outcome(state: *const c_void, response: ByteArray) {
+        let outcome = unsafe { &mut *(state as *mut Vec<Vec<u8>>) };
+        outcome.push(response.to_vec());
+    }
+
+    #[test]
+    fn replies_are_used_in_order_then_calls_succeed() {
+        let mock = MockApi::new();
+        mock.reply("drive_client_create", Reply::Code(7))
+            .reply("drive_client_create", Reply::Missing);
+
+        let create = |session| mock.drive_client_create(SessionHandle::from(session), ObservabilityHandle::null(), ByteArray::empty());
+        assert_eq!(create(5).unwrap(), (7, DriveClientHandle::null()));
+        assert!(create(5).unwrap_err().downcast_ref::<SdkLibError>().is_some());
+        let (code, handle) = create(6).unwrap();
+        assert_eq!((code, handle.is_null()), (0, false));
+
+        let sessions: Vec<isize> = mock.calls_to("drive_client_create").iter().map(|call| call.handle).collect();
+        assert_eq!(sessions, [5, 5, 6]);
+    }
+
+    #[test]
+    fn callbacks_get_the_progress_then_the_reply() {
+        let mock = MockApi::new();
+        mock.reply("downloader_download_file", Reply::Failure(b"gone".to_vec()))
+            .progress("downloader_download_file", &[ProgressUpdate { bytes_completed: 1, bytes_in_total: 2 }]);
+
+        let mut outcome: Vec<Vec<u8>> = Vec::new();
+        let state = &mut outcome as *mut Vec<Vec<u8>> as *const c_void;
+        let callback = AsyncCallbackWithProgress::new(
+            AsyncCallback::new(state, None, Some(record_outcome), 0),
+            Callback::new(state, Some(record_outcome)),
+        );
+        let request = ByteArray::from_slice(b"request");
+        assert_eq!(mock.downloader_download_file(DownloaderHandle::from(3), request, callback).unwrap(), 0);
+
+        let progress = ProgressUpdate { bytes_completed: 1, bytes_in_total: 2 }.encode_to_vec();
+        assert_eq!(outcome, [progress, b"gone".to_vec()]);
+        assert_eq!(
+            mock.calls(),
+            [Call { name: "downloader_download_file", handle: 3, request: b"request".to_vec() }]
+        );
+    }
+}
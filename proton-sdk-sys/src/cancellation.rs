@@ -1,5 +1,3 @@
-use crate::{cancellation, ProtonSDKLib};
-
 /// Handle for a cancellation token source (raw type)
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct CancellationTokenHandle(pub isize);
@@ -33,15 +31,11 @@ impl From<isize> for CancellationTokenHandle {
 }
 
 pub mod raw {
-    use super::*;
-
     /// Creates a cancellation token source (raw FFI)
     pub fn create() -> anyhow::Result<isize> {
         unsafe {
-            let sdk = ProtonSDKLib::instance()?;
-
-            let create_fn: libloading::Symbol<unsafe extern "C" fn() -> isize> =
-                sdk.sdk_library.get(b"cancellation_token_source_create")?;
+            let create_fn: unsafe extern "C" fn() -> isize =
+                sdk_symbol!(cancellation_token_source_create);
 
             let handle = create_fn();
 
@@ -64,10 +58,8 @@ pub mod raw {
         }
 
         unsafe {
-            let sdk = ProtonSDKLib::instance()?;
-
-            let cancel_fn: libloading::Symbol<unsafe extern "C" fn(isize)> =
-                sdk.sdk_library.get(b"cancellation_token_source_cancel")?;
+            let cancel_fn: unsafe extern "C" fn(isize) =
+                sdk_symbol!(cancellation_token_source_cancel);
 
             cancel_fn(handle);
             Ok(())
@@ -83,10 +75,7 @@ pub mod raw {
         }
 
         unsafe {
-            let sdk = ProtonSDKLib::instance()?;
-
-            let free_fn: libloading::Symbol<unsafe extern "C" fn(isize)> =
-                sdk.sdk_library.get(b"cancellation_token_source_free")?;
+            let free_fn: unsafe extern "C" fn(isize) = sdk_symbol!(cancellation_token_source_free);
 
             free_fn(handle);
             Ok(())
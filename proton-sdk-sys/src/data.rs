@@ -1,4 +1,12 @@
-use std::os::raw::c_void;
+use std::{fmt, marker::PhantomData, ops::Deref, os::raw::c_void};
+
+use crate::protobufs::{drive::ProgressUpdate, FromByteArray, ProtoError};
+
+mod async_callback;
+mod callback_guard;
+
+pub use self::async_callback::{AsyncCallbackBuilder, AsyncCallbackGuard, Handlers};
+pub use self::callback_guard::{CallbackGuard, CallbackState};
 
 #[repr(C)]
 #[derive(Clone, Copy)]
@@ -36,6 +44,204 @@ impl ByteArray {
     pub fn is_empty(&self) -> bool {
         self.length == 0 || self.pointer.is_null()
     }
+
+    /// The bytes the array points to, borrowed for as long as the array is
+    pub fn view(&self) -> BorrowedBytes<'_> {
+        BorrowedBytes::from(self)
+    }
+
+    /// Copies the bytes the array points to
+    pub fn to_vec(&self) -> Vec<u8> {
+        self.view().to_vec()
+    }
+
+    /// Renders up to `max_len` bytes as hex for logs, like `[0a, 2a]`, with `...` after
+    /// when there are more
+    ///
+    /// `redact` leaves the bytes out, for responses known to hold tokens.
+    pub fn hex_preview(&self, max_len: usize, redact: bool) -> String {
+        self.view().hex_preview(max_len, redact)
+    }
+}
+
+/// Bytes shown by the `Debug` of a [`ByteArray`]
+const DEBUG_PREVIEW: usize = 32;
+
+impl fmt::Debug for ByteArray {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ByteArray")
+            .field("pointer", &self.pointer)
+            .field("length", &self.length)
+            .field("preview", &format_args!("{}", self.hex_preview(DEBUG_PREVIEW, false)))
+            .finish()
+    }
+}
+
+/// The bytes a [`ByteArray`] points to, which can't outlive the array
+///
+/// A `ByteArray` comes from [`ByteArray::from_slice`] or from the SDK, which passes callbacks
+/// arrays valid for the duration of the call, so its bytes can be read for as long as it's
+/// around. A null or empty array is an empty slice.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct BorrowedBytes<'a>(&'a [u8]);
+
+impl<'a> BorrowedBytes<'a> {
+    /// The bytes, for as long as the array they come from
+    pub fn as_slice(self) -> &'a [u8] {
+        self.0
+    }
+
+    /// See [`ByteArray::hex_preview`]
+    pub fn hex_preview(self, max_len: usize, redact: bool) -> String {
+        if redact {
+            format!("<{} bytes redacted>", self.0.len())
+        } else if self.0.len() <= max_len {
+            format!("{:02x?}", self.0)
+        } else {
+            format!("{:02x?}...", &self.0[..max_len])
+        }
+    }
+}
+
+impl<'a> From<&'a ByteArray> for BorrowedBytes<'a> {
+    fn from(array: &'a ByteArray) -> Self {
+        // the null and length checks are made once, here
+        Self(unsafe { array.as_slice() })
+    }
+}
+
+impl Deref for BorrowedBytes<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.0
+    }
+}
+
+impl AsRef<[u8]> for BorrowedBytes<'_> {
+    fn as_ref(&self) -> &[u8] {
+        self.0
+    }
+}
+
+impl fmt::Debug for BorrowedBytes<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.0, f)
+    }
+}
+
+/// Export freeing a buffer the SDK returned, see [`OwnedByteArray`]
+pub const BYTE_ARRAY_FREE_SYMBOL: &str = "byte_array_free";
+
+/// Who frees the memory of an [`OwnedByteArray`]
+enum Owner {
+    /// The SDK, with its free export, which older builds lack
+    Sdk(Option<unsafe extern "C" fn(ByteArray)>),
+    /// Rust, for buffers that never came from the SDK
+    Rust(Box<[u8]>),
+}
+
+/// A buffer the SDK returned, like the answer of `drive_client_get_volumes`, freed with
+/// the SDK's [`BYTE_ARRAY_FREE_SYMBOL`] export when it's dropped
+///
+/// SDK builds without the export can't free their buffers, which are then left allocated,
+/// as [`ProtonSDKLib::instance`](crate::ProtonSDKLib::instance) warns when it loads one.
+/// The export is resolved with the library, see [`ProtonSDKLib::byte_array_free`](crate::ProtonSDKLib::byte_array_free).
+pub struct OwnedByteArray {
+    array: ByteArray,
+    owner: Owner,
+}
+
+// the SDK frees its buffers from whatever thread, and nothing else refers to them
+unsafe impl Send for OwnedByteArray {}
+
+impl OwnedByteArray {
+    /// Takes ownership of a buffer an SDK allocated, freed with its `free` export, if it has one
+    ///
+    /// # Safety
+    /// `array` must come from the SDK `free` belongs to, which must not free it itself.
+    pub unsafe fn from_sdk(array: ByteArray, free: Option<unsafe extern "C" fn(ByteArray)>) -> Self {
+        let free = free.filter(|_| !array.pointer.is_null());
+        Self { array, owner: Owner::Sdk(free) }
+    }
+
+    /// The buffer as the SDK calls take it, valid while `self` is
+    pub fn as_byte_array(&self) -> ByteArray {
+        self.array
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        match &self.owner {
+            // the pointer stays valid until `self` frees it
+            Owner::Sdk(_) => self.array.view().0,
+            Owner::Rust(bytes) => bytes,
+        }
+    }
+
+    /// Copies the bytes out, freeing the buffer
+    pub fn into_vec(self) -> Vec<u8> {
+        self.as_slice().to_vec()
+    }
+
+    pub fn len(&self) -> usize {
+        self.as_slice().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.array.is_empty()
+    }
+}
+
+impl From<Vec<u8>> for OwnedByteArray {
+    fn from(bytes: Vec<u8>) -> Self {
+        let bytes = bytes.into_boxed_slice();
+        Self { array: ByteArray::from_slice(&bytes), owner: Owner::Rust(bytes) }
+    }
+}
+
+impl Drop for OwnedByteArray {
+    fn drop(&mut self) {
+        match self.owner {
+            Owner::Sdk(Some(free)) => unsafe { free(self.array) },
+            Owner::Sdk(None) | Owner::Rust(_) => {}
+        }
+    }
+}
+
+impl fmt::Debug for OwnedByteArray {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let owner = match self.owner {
+            Owner::Sdk(_) => "sdk",
+            Owner::Rust(_) => "rust",
+        };
+        f.debug_struct("OwnedByteArray").field("len", &self.len()).field("owner", &owner).finish()
+    }
+}
+
+/// The state of a callback, which the SDK may reach from any of its threads
+///
+/// It can only be made from state that is `Send + Sync`, which the raw callback
+/// structs can't check as they take any pointer. They are neither `Send` nor
+/// `Sync` themselves, as what their pointer points to may be neither.
+#[derive(Clone, Copy)]
+pub struct ThreadSafeState<'a> {
+    pointer: *const c_void,
+    _state: PhantomData<&'a (dyn Send + Sync)>,
+}
+
+impl<'a> ThreadSafeState<'a> {
+    pub fn new<T: Send + Sync + 'static>(state: &'a T) -> Self {
+        Self { pointer: state as *const T as *const c_void, _state: PhantomData }
+    }
+
+    /// No state, for callbacks that don't need one
+    pub fn none() -> Self {
+        Self { pointer: std::ptr::null(), _state: PhantomData }
+    }
+
+    pub fn as_ptr(self) -> *const c_void {
+        self.pointer
+    }
 }
 
 #[repr(C)]
@@ -75,8 +281,8 @@ impl AsyncCallback {
 
 #[repr(C)]
 pub struct Callback {
-    state: *const c_void,
-    callback: Option<extern "C" fn(*const c_void, ByteArray)>,
+    pub state: *const c_void,
+    pub callback: Option<extern "C" fn(*const c_void, ByteArray)>,
 }
 
 impl Callback {
@@ -88,6 +294,11 @@ impl Callback {
         Self { state, callback }
     }
 
+    /// Callback whose state is checked to be reachable from any thread
+    pub fn checked(state: ThreadSafeState<'_>, callback: extern "C" fn(*const c_void, ByteArray)) -> Self {
+        Self::new(state.as_ptr(), Some(callback))
+    }
+
     /// Empty instance of callback
     pub fn empty() -> Self {
         Self {
@@ -121,6 +332,14 @@ impl AsyncCallbackWithProgress {
     }
 }
 
+/// Decodes what the progress callback of an [`AsyncCallbackWithProgress`] receives
+///
+/// The SDK reports progress as a `ProgressUpdate`, leaving out the fields that are
+/// still 0, so an empty array is a transfer that hasn't started.
+pub fn parse_progress(progress: ByteArray) -> Result<ProgressUpdate, ProtoError> {
+    ProgressUpdate::from_bytes(&progress.view())
+}
+
 #[repr(C)]
 pub struct BooleanCallback {
     pub state: *const c_void,
@@ -136,6 +355,11 @@ impl BooleanCallback {
         Self { state, callback }
     }
 
+    /// BooleanCallback whose state is checked to be reachable from any thread
+    pub fn checked(state: ThreadSafeState<'_>, callback: extern "C" fn(*const c_void, ByteArray) -> bool) -> Self {
+        Self::new(state.as_ptr(), Some(callback))
+    }
+
     /// Create an empty BooleanCallback
     pub fn empty() -> Self {
         Self {
@@ -170,6 +394,14 @@ impl TwoFactorRequestedCallback {
         Self { state, callback }
     }
 
+    /// TwoFactorRequestedCallback whose state is checked to be reachable from any thread
+    pub fn checked(
+        state: ThreadSafeState<'_>,
+        callback: extern "C" fn(*const c_void, ByteArray, *mut ByteArray, *mut ByteArray) -> bool,
+    ) -> Self {
+        Self::new(state.as_ptr(), Some(callback))
+    }
+
     /// Create an empty TwoFactorRequestedCallback
     pub fn empty() -> Self {
         Self {
@@ -177,4 +409,88 @@ impl TwoFactorRequestedCallback {
             callback: None,
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static FREED: std::sync::Mutex<Vec<usize>> = std::sync::Mutex::new(Vec::new());
+
+    unsafe extern "C" fn record_free(array: ByteArray) {
+        FREED.lock().unwrap().push(array.pointer as usize);
+    }
+
+    #[test]
+    fn sdk_buffers_are_freed_once_when_dropped() {
+        let bytes = b"volumes".to_vec();
+        let array = ByteArray::from_slice(&bytes);
+        let owned = OwnedByteArray { array, owner: Owner::Sdk(Some(record_free)) };
+        assert_eq!(owned.as_slice(), b"volumes");
+        assert_eq!(owned.into_vec(), b"volumes");
+        let freed = FREED.lock().unwrap().iter().filter(|pointer| **pointer == bytes.as_ptr() as usize).count();
+        assert_eq!(freed, 1);
+    }
+
+    #[test]
+    fn views_of_null_arrays_are_empty() {
+        let bytes = b"tokens".to_vec();
+        let array = ByteArray::from_slice(&bytes);
+        assert_eq!(&*array.view(), b"tokens");
+        assert_eq!(array.to_vec(), bytes);
+        let dangling = ByteArray { pointer: std::ptr::null(), length: 16 };
+        assert!(dangling.view().is_empty());
+        assert_eq!(format!("{:02x?}", ByteArray::from_slice(&[0xab, 1]).view()), "[ab, 01]");
+    }
+
+    #[test]
+    fn previews_are_bounded() {
+        assert_eq!(ByteArray::empty().hex_preview(50, false), "[]");
+        assert_eq!(
+            format!("{:?}", ByteArray::empty()),
+            "ByteArray { pointer: 0x0, length: 0, preview: [] }"
+        );
+
+        let small = ByteArray::from_slice(&[0x0a, 0x2a]);
+        assert_eq!(small.hex_preview(50, false), "[0a, 2a]");
+        assert_eq!(small.hex_preview(50, true), "<2 bytes redacted>");
+        assert!(format!("{:?}", small).ends_with("length: 2, preview: [0a, 2a] }"));
+
+        let bytes = [0xffu8; 64];
+        let large = ByteArray::from_slice(&bytes);
+        let preview = large.hex_preview(50, false);
+        assert!(preview.starts_with("[ff, ff, ") && preview.ends_with("ff]..."));
+        assert_eq!(preview.matches("ff").count(), 50);
+        assert_eq!(large.hex_preview(50, true), "<64 bytes redacted>");
+        // past the pointer, which may hold ff too
+        let debug = format!("{:?}", large);
+        assert_eq!(debug[debug.find("preview").unwrap()..].matches("ff").count(), DEBUG_PREVIEW);
+    }
+
+    #[test]
+    fn progress_payloads_are_decoded() {
+        // as the SDK sent them during a 16 KiB download
+        let captured: [(&[u8], i64, i64); 4] = [
+            (&[], 0, 0),
+            (&[0x10, 0x80, 0x80, 0x01], 0, 16384),
+            (&[0x08, 0x80, 0x20, 0x10, 0x80, 0x80, 0x01], 4096, 16384),
+            (&[0x08, 0x80, 0x80, 0x01, 0x10, 0x80, 0x80, 0x01], 16384, 16384),
+        ];
+        for (payload, completed, total) in captured {
+            let update = parse_progress(ByteArray::from_slice(payload)).unwrap();
+            assert_eq!((update.bytes_completed, update.bytes_in_total), (completed, total));
+        }
+        // a little-endian f32, as progress was once assumed to be
+        assert!(parse_progress(ByteArray::from_slice(&0.25f32.to_le_bytes())).is_err());
+        assert!(parse_progress(ByteArray::from_slice(&[0x08])).is_err());
+    }
+
+    #[test]
+    fn rust_buffers_keep_their_bytes() {
+        let owned = OwnedByteArray::from(b"shares".to_vec());
+        assert_eq!(owned.len(), 6);
+        assert_eq!(owned.as_byte_array().to_vec(), b"shares");
+        assert!(OwnedByteArray::from(Vec::new()).is_empty());
+        assert!(unsafe { OwnedByteArray::from_sdk(ByteArray::empty(), None) }.is_empty());
+    }
+}
@@ -0,0 +1,209 @@
+//! [`AsyncCallback`]s calling closures, with the state they need owned by a [`CallbackGuard`]
+
+use std::{
+    ffi::c_void,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use super::{AsyncCallback, ByteArray, CallbackGuard, CallbackState};
+use crate::{api::SdkApi, cancellation::CancellationTokenHandle};
+
+type Handler<T> = Box<dyn FnOnce(ByteArray) -> T + Send>;
+
+/// The closures of an [`AsyncCallbackBuilder`], taken by whichever callback comes first
+pub struct Handlers<T> {
+    closures: Mutex<Option<Closures<T>>>,
+}
+
+struct Closures<T> {
+    on_success: Option<Handler<T>>,
+    on_failure: Option<Handler<T>>,
+}
+
+/// Owns the state of an [`AsyncCallbackBuilder`]'s callback, and its closures until one ran
+pub type AsyncCallbackGuard<T> = CallbackGuard<T, Handlers<T>>;
+
+type State<T> = CallbackState<T, Handlers<T>>;
+
+/// Runs the closure `pick` chooses, unless a callback came before
+fn complete<T>(state: &State<T>, data: ByteArray, pick: fn(Closures<T>) -> Option<Handler<T>>) {
+    let closures = state.data().closures.lock().unwrap_or_else(PoisonError::into_inner).take();
+    if let Some(handler) = closures.and_then(pick) {
+        state.send(handler(data));
+    }
+}
+
+extern "C" fn on_success<T>(state: *const c_void, response: ByteArray) {
+    unsafe { State::<T>::finish(state, |state| complete(state, response, |closures| closures.on_success)) }
+}
+
+extern "C" fn on_failure<T>(state: *const c_void, error: ByteArray) {
+    unsafe { State::<T>::finish(state, |state| complete(state, error, |closures| closures.on_failure)) }
+}
+
+/// Builds an [`AsyncCallback`] out of closures, instead of extern fns and a leaked state
+///
+/// Only the first callback the SDK makes runs its closure, and what the closure
+/// returns is the [`completion`](CallbackGuard::completion) of the guard.
+///
+/// ```
+/// use proton_sdk_sys::data::{AsyncCallbackBuilder, ByteArray};
+///
+/// let (callback, guard) = AsyncCallbackBuilder::new()
+///     .on_success(|response: ByteArray| Ok(response.to_vec()))
+///     .on_failure(|error: ByteArray| Err(String::from_utf8_lossy(&error.view()).into_owned()))
+///     .build();
+/// // as the SDK would call back
+/// (callback.on_success.unwrap())(callback.state, ByteArray::from_slice(b"done"));
+/// assert_eq!(guard.take_result(), Some(Ok(b"done".to_vec())));
+/// ```
+pub struct AsyncCallbackBuilder<T> {
+    on_success: Option<Handler<T>>,
+    on_failure: Option<Handler<T>>,
+    cancellation_token: CancellationTokenHandle,
+    cancel_through: Option<Arc<dyn SdkApi>>,
+}
+
+impl<T> Default for AsyncCallbackBuilder<T> {
+    fn default() -> Self {
+        Self {
+            on_success: None,
+            on_failure: None,
+            cancellation_token: CancellationTokenHandle::null(),
+            cancel_through: None,
+        }
+    }
+}
+
+impl<T: Send + 'static> AsyncCallbackBuilder<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Called with the response when the call succeeds
+    pub fn on_success(mut self, on_success: impl FnOnce(ByteArray) -> T + Send + 'static) -> Self {
+        self.on_success = Some(Box::new(on_success));
+        self
+    }
+
+    /// Called with the error, usually an `Error` protobuf, when the call fails
+    pub fn on_failure(mut self, on_failure: impl FnOnce(ByteArray) -> T + Send + 'static) -> Self {
+        self.on_failure = Some(Box::new(on_failure));
+        self
+    }
+
+    /// Cancels the call with this token, none by default
+    pub fn cancellation_token(mut self, cancellation_token: CancellationTokenHandle) -> Self {
+        self.cancellation_token = cancellation_token;
+        self
+    }
+
+    /// Cancels the token through `api` when the guard is dropped before the SDK called back
+    pub fn cancel_on_drop(mut self, api: Arc<dyn SdkApi>) -> Self {
+        self.cancel_through = Some(api);
+        self
+    }
+
+    /// The callback to give the SDK, with the guard of its state
+    ///
+    /// Make the call through [`CallbackGuard::call`], so that a refused call frees
+    /// the closures with the guard.
+    pub fn build(self) -> (AsyncCallback, AsyncCallbackGuard<T>) {
+        let on_success_callback = self.on_success.is_some().then_some(on_success::<T> as extern "C" fn(_, _));
+        let on_failure_callback = self.on_failure.is_some().then_some(on_failure::<T> as extern "C" fn(_, _));
+
+        let closures = Closures { on_success: self.on_success, on_failure: self.on_failure };
+        let handlers = Handlers { closures: Mutex::new(Some(closures)) };
+        let mut guard = CallbackGuard::new(handlers);
+        if let Some(api) = self.cancel_through.filter(|_| !self.cancellation_token.is_null()) {
+            guard = guard.cancel_on_drop(api, self.cancellation_token);
+        }
+        let callback = AsyncCallback::new(
+            guard.state(),
+            on_success_callback,
+            on_failure_callback,
+            self.cancellation_token.raw(),
+        );
+        (callback, guard)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::mock::MockApi;
+    use std::{
+        future::Future,
+        pin::pin,
+        task::{Context, Poll, Waker},
+    };
+
+    fn call(callback: Option<extern "C" fn(*const c_void, ByteArray)>, state: *const c_void, data: &[u8]) {
+        (callback.unwrap())(state, ByteArray::from_slice(data));
+    }
+
+    #[test]
+    fn only_the_first_callback_runs() {
+        let (callback, guard) = AsyncCallbackBuilder::new()
+            .on_success(|response| response.to_vec())
+            .on_failure(|_| b"failed".to_vec())
+            .cancellation_token(CancellationTokenHandle::from(7))
+            .build();
+        assert_eq!(callback.cancellation_token_source_handle, 7);
+        assert!(!guard.is_completed());
+
+        call(callback.on_failure, callback.state, b"error");
+        call(callback.on_success, callback.state, b"response");
+        assert!(guard.is_completed());
+        assert_eq!(guard.take_result(), Some(b"failed".to_vec()));
+        assert_eq!(guard.take_result(), None);
+    }
+
+    #[test]
+    fn closures_of_refused_calls_are_freed_with_the_guard() {
+        let captured = Arc::new(());
+        let (callback, guard) = AsyncCallbackBuilder::<()>::new()
+            .on_success({
+                let captured = captured.clone();
+                move |_| drop(captured)
+            })
+            .build();
+        assert!(callback.on_failure.is_none());
+        assert_eq!(guard.call(|| Ok(3)).unwrap(), 3);
+        assert_eq!(Arc::strong_count(&captured), 2);
+        drop(guard);
+        assert_eq!(Arc::strong_count(&captured), 1);
+    }
+
+    #[test]
+    fn timed_out_calls_are_cancelled_and_keep_their_closures() {
+        let api = Arc::new(MockApi::new());
+        let captured = Arc::new(());
+        let (callback, guard) = AsyncCallbackBuilder::new()
+            .on_success({
+                let captured = captured.clone();
+                move |response: ByteArray| drop((captured, response.length))
+            })
+            .cancellation_token(CancellationTokenHandle::from(7))
+            .cancel_on_drop(api.clone())
+            .build();
+        assert_eq!(guard.call(|| Ok(0)).unwrap(), 0);
+
+        drop(guard);
+        assert_eq!(api.calls_to("cancellation_token_source_cancel")[0].handle, 7);
+        assert_eq!(Arc::strong_count(&captured), 2);
+        // the SDK calling back late runs the closure, then frees the state
+        call(callback.on_success, callback.state, b"late");
+        assert_eq!(Arc::strong_count(&captured), 1);
+    }
+
+    #[test]
+    fn completions_resolve_once_called_back() {
+        let (callback, guard) = AsyncCallbackBuilder::new().on_success(|response| response.length).build();
+        let mut completion = pin!(guard.completion());
+        let mut cx = Context::from_waker(Waker::noop());
+        assert_eq!(completion.as_mut().poll(&mut cx), Poll::Pending);
+        call(callback.on_success, callback.state, b"four");
+        assert_eq!(completion.as_mut().poll(&mut cx), Poll::Ready(4));
+    }
+}
@@ -0,0 +1,320 @@
+//! State handed to the SDK as the `*const c_void` of its callbacks
+
+use std::{
+    ffi::c_void,
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard, PoisonError,
+    },
+    task::{Poll, Waker},
+};
+
+use super::ThreadSafeState;
+use crate::{api::SdkApi, cancellation::CancellationTokenHandle};
+
+/// What the callbacks of an SDK call see through their state pointer
+///
+/// Only the first value sent is kept, so an SDK calling back twice, or calling
+/// both the success and the failure callback, finds the result sent the second time.
+pub struct CallbackState<T, S = ()> {
+    outcome: Mutex<Outcome<T>>,
+    /// Whether the SDK's reference to the state was given up
+    released: AtomicBool,
+    data: S,
+}
+
+struct Outcome<T> {
+    value: Option<T>,
+    completed: bool,
+    waker: Option<Waker>,
+}
+
+impl<T, S> CallbackState<T, S> {
+    /// Reads back the state of a [`CallbackGuard`], `None` for a null pointer
+    ///
+    /// # Safety
+    /// `state` has to be null or come from [`CallbackGuard::state`] with the same `T`
+    /// and `S`, and the SDK's reference to it must not have been released.
+    pub unsafe fn from_raw<'a>(state: *const c_void) -> Option<&'a Self> {
+        (state as *const Self).as_ref()
+    }
+
+    /// Runs the final callback of a call with its state, then releases the SDK's reference
+    ///
+    /// Once released, the state is freed with the guard, or right away when the
+    /// guard was dropped already.
+    ///
+    /// # Safety
+    /// As for [`from_raw`](Self::from_raw), and the SDK must not call back with
+    /// `state` afterwards, unless the guard is still alive.
+    pub unsafe fn finish(state: *const c_void, complete: impl FnOnce(&Self)) {
+        let Some(this) = Self::from_raw(state) else {
+            return;
+        };
+        complete(this);
+        if !this.released.swap(true, Ordering::AcqRel) {
+            Arc::decrement_strong_count(state as *const Self);
+        }
+    }
+
+    fn outcome(&self) -> MutexGuard<'_, Outcome<T>> {
+        self.outcome.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Sends the result of the call, false when it was already sent
+    pub fn send(&self, value: T) -> bool {
+        let waker = {
+            let mut outcome = self.outcome();
+            if outcome.completed {
+                return false;
+            }
+            outcome.value = Some(value);
+            outcome.completed = true;
+            outcome.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+        true
+    }
+
+    /// Whether a callback already sent the result
+    pub fn is_completed(&self) -> bool {
+        self.outcome().completed
+    }
+
+    /// What the callbacks need besides the result, like a progress callback
+    pub fn data(&self) -> &S {
+        &self.data
+    }
+}
+
+/// Owns the state of an SDK call, keeping it alive for as long as the SDK may call back
+///
+/// The SDK gets [`state`](Self::state) as the state of the call's callbacks,
+/// which read it back with [`CallbackState::from_raw`]. Handing it out gives the
+/// SDK a reference of its own, which the final callback gives up through
+/// [`CallbackState::finish`]. Dropping the guard while the SDK still holds it, as
+/// on a timeout or when the awaiting future is dropped, cancels the call's token
+/// and leaves the state to the SDK instead of freeing it under its callbacks.
+///
+/// ```
+/// use proton_sdk_sys::data::{ByteArray, CallbackGuard, CallbackState};
+/// use std::ffi::c_void;
+///
+/// extern "C" fn on_success(state: *const c_void, response: ByteArray) {
+///     unsafe { CallbackState::<Vec<u8>>::finish(state, |state| { state.send(response.to_vec()); }) }
+/// }
+///
+/// let guard = CallbackGuard::<Vec<u8>>::new(());
+/// // as the SDK would call back
+/// on_success(guard.state(), ByteArray::from_slice(b"done"));
+/// assert_eq!(guard.take_result(), Some(b"done".to_vec()));
+/// ```
+pub struct CallbackGuard<T, S = ()> {
+    state: Arc<CallbackState<T, S>>,
+    /// Whether the SDK was given its reference
+    handed_out: AtomicBool,
+    cancellation: Option<(Arc<dyn SdkApi>, CancellationTokenHandle)>,
+}
+
+impl<T, S> CallbackGuard<T, S> {
+    /// State holding `data` besides the result
+    pub fn new(data: S) -> Self {
+        let state = CallbackState {
+            outcome: Mutex::new(Outcome { value: None, completed: false, waker: None }),
+            released: AtomicBool::new(false),
+            data,
+        };
+        Self { state: Arc::new(state), handed_out: AtomicBool::new(false), cancellation: None }
+    }
+
+    /// Cancels `token` through `api` when the guard is dropped before the SDK let go of the state
+    pub fn cancel_on_drop(mut self, api: Arc<dyn SdkApi>, token: CancellationTokenHandle) -> Self {
+        self.cancellation = Some((api, token));
+        self
+    }
+
+    /// The pointer to give the SDK as the state of the callbacks
+    pub fn state(&self) -> *const c_void {
+        if !self.handed_out.swap(true, Ordering::AcqRel) {
+            // the SDK's reference, given up by `CallbackState::finish` or `revoke`
+            std::mem::forget(self.state.clone());
+        }
+        Arc::as_ptr(&self.state) as *const c_void
+    }
+
+    /// Makes the SDK call the state was handed to, which returns the SDK's code
+    ///
+    /// A call that couldn't be made or that the SDK refused with a non-zero code
+    /// never calls back, so the state is taken back from the SDK.
+    pub fn call(&self, call: impl FnOnce() -> anyhow::Result<i32>) -> anyhow::Result<i32> {
+        let result = call();
+        if !matches!(result, Ok(0)) {
+            // SAFETY: refused calls don't call back
+            unsafe { self.revoke() };
+        }
+        result
+    }
+
+    /// Takes the state back from the SDK, which lets it be freed with the guard
+    ///
+    /// # Safety
+    /// The SDK must not call back anymore, as after freeing what the callbacks were given to.
+    pub unsafe fn revoke(&self) {
+        if self.handed_out.load(Ordering::Acquire) && !self.state.released.swap(true, Ordering::AcqRel) {
+            Arc::decrement_strong_count(Arc::as_ptr(&self.state));
+        }
+    }
+
+    /// Whether the SDK still holds the state
+    pub fn is_pending(&self) -> bool {
+        self.handed_out.load(Ordering::Acquire) && !self.state.released.load(Ordering::Acquire)
+    }
+
+    /// Whether a callback already sent the result
+    pub fn is_completed(&self) -> bool {
+        self.state.is_completed()
+    }
+
+    /// Takes the result a callback sent, `None` while none did or once taken
+    pub fn take_result(&self) -> Option<T> {
+        self.state.outcome().value.take()
+    }
+
+    /// Resolves to the result a callback sent
+    ///
+    /// It never resolves when the SDK doesn't call back, or when the result was
+    /// taken already, so wait for it with a timeout.
+    pub fn completion(&self) -> impl Future<Output = T> + '_ {
+        std::future::poll_fn(|cx| {
+            let mut outcome = self.state.outcome();
+            match outcome.value.take() {
+                Some(value) => Poll::Ready(value),
+                None => {
+                    outcome.waker = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        })
+    }
+}
+
+impl<T: Send + 'static, S: Send + Sync + 'static> CallbackGuard<T, S> {
+    /// The state as the checked constructors of the callbacks take it
+    pub fn thread_safe_state(&self) -> ThreadSafeState<'_> {
+        self.state();
+        ThreadSafeState::new(&*self.state)
+    }
+}
+
+impl<T, S> Drop for CallbackGuard<T, S> {
+    fn drop(&mut self) {
+        if !self.is_pending() {
+            return;
+        }
+        log::debug!("Dropping the state of a pending SDK call, the SDK frees it when calling back");
+        if let Some((api, token)) = &self.cancellation {
+            if let Err(e) = api.cancellation_token_cancel(token.raw()) {
+                log::warn!("Failed to cancel a pending SDK call: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{api::mock::MockApi, data::ByteArray};
+
+    extern "C" fn fake_callback(state: *const c_void, value: i32) -> bool {
+        unsafe { CallbackState::<i32>::from_raw(state) }.is_some_and(|state| state.send(value))
+    }
+
+    extern "C" fn fake_final_callback(state: *const c_void, response: ByteArray) {
+        unsafe { CallbackState::<usize, Arc<()>>::finish(state, |state| { state.send(response.length); }) }
+    }
+
+    #[test]
+    fn a_second_callback_finds_the_result_sent() {
+        let guard = CallbackGuard::new(());
+        assert!(fake_callback(guard.state(), 1));
+        assert!(!fake_callback(guard.state(), 2));
+        assert!(guard.is_completed());
+        assert_eq!(guard.take_result(), Some(1));
+        assert_eq!(guard.take_result(), None);
+        assert!(!fake_callback(std::ptr::null(), 3));
+    }
+
+    #[test]
+    fn the_state_is_freed_without_a_callback() {
+        let data = Arc::new(());
+        let guard = CallbackGuard::<i32, _>::new(data.clone());
+        assert_eq!(Arc::strong_count(&data), 2);
+        assert!(!guard.is_pending());
+        drop(guard);
+        assert_eq!(Arc::strong_count(&data), 1);
+    }
+
+    #[test]
+    fn refused_calls_give_the_state_back() {
+        let data = Arc::new(());
+        let api = Arc::new(MockApi::new());
+        let guard = CallbackGuard::<usize, _>::new(data.clone()).cancel_on_drop(api.clone(), CancellationTokenHandle::from(7));
+        guard.state();
+        assert!(guard.is_pending());
+
+        assert_eq!(guard.call(|| Ok(4)).unwrap(), 4);
+        assert!(!guard.is_pending());
+        drop(guard);
+        assert_eq!(Arc::strong_count(&data), 1);
+        assert!(api.calls().is_empty());
+    }
+
+    #[test]
+    fn callbacks_after_the_guard_was_dropped_free_the_state() {
+        let data = Arc::new(());
+        let api = Arc::new(MockApi::new());
+        let guard = CallbackGuard::<usize, _>::new(data.clone()).cancel_on_drop(api.clone(), CancellationTokenHandle::from(7));
+        let state = guard.state();
+        assert_eq!(guard.call(|| Ok(0)).unwrap(), 0);
+
+        // timed out, the SDK still holds the state
+        drop(guard);
+        assert_eq!(api.calls_to("cancellation_token_source_cancel").len(), 1);
+        assert_eq!(api.calls_to("cancellation_token_source_cancel")[0].handle, 7);
+        assert_eq!(Arc::strong_count(&data), 2);
+
+        fake_final_callback(state, ByteArray::from_slice(b"late"));
+        assert_eq!(Arc::strong_count(&data), 1);
+    }
+
+    #[test]
+    fn completed_calls_neither_cancel_nor_leak() {
+        let data = Arc::new(());
+        let api = Arc::new(MockApi::new());
+        let guard = CallbackGuard::<usize, _>::new(data.clone()).cancel_on_drop(api.clone(), CancellationTokenHandle::from(7));
+        fake_final_callback(guard.state(), ByteArray::from_slice(b"done"));
+        // a second final callback while the guard lives finds the result sent
+        fake_final_callback(guard.state(), ByteArray::from_slice(b"again"));
+
+        assert!(!guard.is_pending());
+        assert_eq!(guard.take_result(), Some(4));
+        drop(guard);
+        assert!(api.calls().is_empty());
+        assert_eq!(Arc::strong_count(&data), 1);
+    }
+
+    #[test]
+    fn completions_resolve_once_called_back() {
+        use std::{future::Future, pin::pin, task::Context};
+
+        let guard = CallbackGuard::<usize, Arc<()>>::new(Arc::new(()));
+        let mut completion = pin!(guard.completion());
+        let mut cx = Context::from_waker(Waker::noop());
+        assert_eq!(completion.as_mut().poll(&mut cx), Poll::Pending);
+        fake_final_callback(guard.state(), ByteArray::from_slice(b"four"));
+        assert_eq!(completion.as_mut().poll(&mut cx), Poll::Ready(4));
+    }
+}
@@ -29,7 +29,6 @@ pub mod raw {
     use crate::{
         data::{AsyncCallback, AsyncCallbackWithProgress, ByteArray},
         drive::DriveClientHandle,
-        ProtonSDKLib,
     };
 
     use super::*;
@@ -55,11 +54,8 @@ pub mod raw {
         callback: AsyncCallback,
     ) -> anyhow::Result<i32> {
         unsafe {
-            let sdk = ProtonSDKLib::instance()?;
-
-            let create_downloader_fn: libloading::Symbol<
-                unsafe extern "C" fn(isize, ByteArray, AsyncCallback) -> i32,
-            > = sdk.sdk_library.get(b"downloader_create")?;
+            let create_downloader_fn: unsafe extern "C" fn(isize, ByteArray, AsyncCallback) -> i32 =
+                sdk_symbol!(downloader_create);
 
             let result = create_downloader_fn(client_handle.raw(), request, callback);
 
@@ -89,11 +85,8 @@ pub mod raw {
         callback: AsyncCallbackWithProgress,
     ) -> anyhow::Result<i32> {
         unsafe {
-            let sdk = ProtonSDKLib::instance()?;
-
-            let download_file_fn: libloading::Symbol<
-                unsafe extern "C" fn(isize, ByteArray, AsyncCallbackWithProgress) -> i32,
-            > = sdk.sdk_library.get(b"downloader_download_file")?;
+            let download_file_fn: unsafe extern "C" fn(isize, ByteArray, AsyncCallbackWithProgress) -> i32 =
+                sdk_symbol!(downloader_download_file);
 
             let result = download_file_fn(downloader_handle.raw(), request, callback);
 
@@ -108,10 +101,7 @@ pub mod raw {
     /// * `downloader_handle` - Handle to the downloader to free
     pub fn downloader_free(downloader_handle: DownloaderHandle) -> anyhow::Result<()> {
         unsafe {
-            let sdk = ProtonSDKLib::instance()?;
-
-            let free_downloader_fn: libloading::Symbol<unsafe extern "C" fn(isize)> =
-                sdk.sdk_library.get(b"downloader_free")?;
+            let free_downloader_fn: unsafe extern "C" fn(isize) = sdk_symbol!(downloader_free);
 
             free_downloader_fn(downloader_handle.raw());
             Ok(())
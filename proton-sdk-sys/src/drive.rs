@@ -1,7 +1,6 @@
-use crate::data::ByteArray;
+use crate::data::{ByteArray, OwnedByteArray};
 use crate::observability::ObservabilityHandle;
 use crate::sessions::SessionHandle;
-use crate::ProtonSDKLib;
 
 /// Handle for a Drive client
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -32,7 +31,7 @@ impl From<isize> for DriveClientHandle {
 
 /// Raw FFI functions for Drive client management
 pub mod raw {
-    use crate::{cancellation::CancellationTokenHandle, data::AsyncCallback, protobufs::NodeIdentity};
+    use crate::{cancellation::CancellationTokenHandle, data::AsyncCallback, protobufs::drive::NodeIdentity};
 
     use super::*;
 
@@ -57,11 +56,8 @@ pub mod raw {
         request: ByteArray,
     ) -> anyhow::Result<(i32, DriveClientHandle)> {
         unsafe {
-            let sdk = ProtonSDKLib::instance()?;
-
-            let create_client_fn: libloading::Symbol<
-                unsafe extern "C" fn(isize, isize, ByteArray, *mut isize) -> i32,
-            > = sdk.sdk_library.get(b"drive_client_create")?;
+            let create_client_fn: unsafe extern "C" fn(isize, isize, ByteArray, *mut isize) -> i32 =
+                sdk_symbol!(drive_client_create);
 
             let mut client_handle: isize = 0;
             let result = create_client_fn(
@@ -92,11 +88,8 @@ pub mod raw {
         request: ByteArray,
     ) -> anyhow::Result<i32> {
         unsafe {
-            let sdk = ProtonSDKLib::instance()?;
-
-            let register_keys_fn: libloading::Symbol<
-                unsafe extern "C" fn(isize, ByteArray) -> i32,
-            > = sdk.sdk_library.get(b"drive_client_register_node_keys")?;
+            let register_keys_fn: unsafe extern "C" fn(isize, ByteArray) -> i32 =
+                sdk_symbol!(drive_client_register_node_keys);
 
             let result = register_keys_fn(client_handle.raw(), request);
 
@@ -121,10 +114,8 @@ pub mod raw {
         request: ByteArray,
     ) -> anyhow::Result<i32> {
         unsafe {
-            let sdk = ProtonSDKLib::instance()?;
-
-            let register_key_fn: libloading::Symbol<unsafe extern "C" fn(isize, ByteArray) -> i32> =
-                sdk.sdk_library.get(b"drive_client_register_share_key")?;
+            let register_key_fn: unsafe extern "C" fn(isize, ByteArray) -> i32 =
+                sdk_symbol!(drive_client_register_share_key);
 
             let result = register_key_fn(client_handle.raw(), request);
 
@@ -139,10 +130,7 @@ pub mod raw {
     /// * `client_handle` - Handle to the Drive client to free
     pub fn drive_client_free(client_handle: DriveClientHandle) -> anyhow::Result<()> {
         unsafe {
-            let sdk = ProtonSDKLib::instance()?;
-
-            let free_client_fn: libloading::Symbol<unsafe extern "C" fn(isize)> =
-                sdk.sdk_library.get(b"drive_client_free")?;
+            let free_client_fn: unsafe extern "C" fn(isize) = sdk_symbol!(drive_client_free);
 
             free_client_fn(client_handle.raw());
             Ok(())
@@ -157,19 +145,17 @@ pub mod raw {
     /// * `cancellation_token` - Handle to the cancellation token
     /// 
     /// # Returns
-    /// Returns a serialised VolumeResponse as a ByteArray
+    /// Returns a serialised VolumeResponse, freed by the SDK when it's dropped
     pub fn drive_client_get_volumes(
         client_handle: DriveClientHandle,
         cancellation_token: CancellationTokenHandle,
-    ) -> anyhow::Result<ByteArray> {
+    ) -> anyhow::Result<OwnedByteArray> {
         unsafe {
-            let sdk = ProtonSDKLib::instance()?;
-
-            let get_volumes_fn: libloading::Symbol<
-                unsafe extern "C" fn(isize, isize) -> ByteArray
-            > = sdk.sdk_library.get(b"drive_client_get_volumes")?;
+            let get_volumes_fn: unsafe extern "C" fn(isize, isize) -> ByteArray =
+                sdk_symbol!(drive_client_get_volumes);
 
-            Ok(get_volumes_fn(client_handle.raw(), cancellation_token.raw()))
+            let free = crate::ProtonSDKLib::instance()?.byte_array_free();
+            Ok(OwnedByteArray::from_sdk(get_volumes_fn(client_handle.raw(), cancellation_token.raw()), free))
         }
     }
 
@@ -182,14 +168,17 @@ pub mod raw {
         client_handle: DriveClientHandle,
         volume_metadata: ByteArray,
         cancellation_token: CancellationTokenHandle
-    ) -> anyhow::Result<ByteArray> {
+    ) -> anyhow::Result<OwnedByteArray> {
         unsafe {
-            let sdk = ProtonSDKLib::instance()?;
+            let get_shares_fn: unsafe extern "C" fn(isize, ByteArray, isize) -> ByteArray =
+                sdk_symbol!(drive_client_get_shares);
 
-            let get_shares_fn: libloading::Symbol<unsafe extern "C" fn(isize, ByteArray, isize) -> ByteArray>
-            = sdk.sdk_library.get(b"drive_client_get_shares")?;
-
-            Ok(get_shares_fn(client_handle.raw(), volume_metadata, cancellation_token.raw()))
+            let free = crate::ProtonSDKLib::instance()?.byte_array_free();
+            Ok(OwnedByteArray::from_sdk(get_shares_fn(
+                client_handle.raw(),
+                volume_metadata,
+                cancellation_token.raw(),
+            ), free))
         }
     }
 
@@ -197,19 +186,17 @@ pub mod raw {
         client_handle: DriveClientHandle,
         node_identity: ByteArray,
         cancellation_token: CancellationTokenHandle,
-    ) -> anyhow::Result<ByteArray> {
+    ) -> anyhow::Result<OwnedByteArray> {
         unsafe {
-            let sdk = ProtonSDKLib::instance()?;
-
-            let get_children_fn: libloading::Symbol<
-                unsafe extern "C" fn(isize, ByteArray, isize) -> ByteArray
-            > = sdk.sdk_library.get(b"drive_client_get_folder_children")?;
+            let get_children_fn: unsafe extern "C" fn(isize, ByteArray, isize) -> ByteArray =
+                sdk_symbol!(drive_client_get_folder_children);
 
-            Ok(get_children_fn(
+            let free = crate::ProtonSDKLib::instance()?.byte_array_free();
+            Ok(OwnedByteArray::from_sdk(get_children_fn(
                 client_handle.raw(),
                 node_identity,
                 cancellation_token.raw(),
-            ))
+            ), free))
         }
     }
 }
@@ -1,150 +1,604 @@
+/// Looks up the SDK export `name` with the type the `let` it's assigned to is annotated with,
+/// which must match the export's signature
+///
+/// The export comes from the library [`ProtonSDKLib`] loads, which resolves them all once, or
+/// with the `static-link` feature from the one linked at build time. Only usable in functions
+/// returning `anyhow::Result`.
+#[cfg(not(feature = "static-link"))]
+macro_rules! sdk_symbol {
+    ($name:ident) => {
+        $crate::ProtonSDKLib::instance()?.symbol(stringify!($name))?
+    };
+}
+
+#[cfg(feature = "static-link")]
+macro_rules! sdk_symbol {
+    ($name:ident) => {
+        $crate::linked::$name
+    };
+}
+
+pub mod api;
 pub mod cancellation;
 pub mod data;
 pub mod downloads;
 pub mod drive;
+#[cfg(feature = "static-link")]
+mod linked;
 pub mod logger;
 pub mod nodes;
 pub mod observability;
+pub mod platform;
 pub mod protobufs;
+pub mod secret;
 pub mod sessions;
 pub mod uploads;
+pub mod version;
 
 use libloading::Library;
 use log::{debug, error, warn};
 use std::{
-    path::PathBuf,
-    sync::{Mutex, Once},
+    ffi::{c_char, c_void, CStr},
+    fmt,
+    path::{Path, PathBuf},
+    ptr::NonNull,
+    sync::OnceLock,
 };
 
 pub use prost;
 
+use crate::{
+    data::{ByteArray, BYTE_ARRAY_FREE_SYMBOL},
+    version::{SdkVersion, VERSION_SYMBOL},
+};
+
 pub struct ProtonSDKLib {
     pub sdk_library: Library,
     pub location: PathBuf,
+    symbols: SdkSymbols,
+}
+
+/// The addresses of the [`REQUIRED_SYMBOLS`], in the same order, `None` for the missing ones,
+/// and the optional [`BYTE_ARRAY_FREE_SYMBOL`] every buffer the SDK returns is freed with
+struct SdkSymbols {
+    required: Vec<Option<NonNull<c_void>>>,
+    byte_array_free: Option<unsafe extern "C" fn(ByteArray)>,
+}
+
+// function addresses of a library that stays loaded for as long as the process
+unsafe impl Send for SdkSymbols {}
+unsafe impl Sync for SdkSymbols {}
+
+impl SdkSymbols {
+    fn resolve(library: &Library) -> Self {
+        let required = REQUIRED_SYMBOLS
+            .iter()
+            .map(|name| unsafe { library.get::<*mut c_void>(name.as_bytes()) }.ok().and_then(|symbol| NonNull::new(*symbol)))
+            .collect();
+        let byte_array_free =
+            unsafe { library.get::<unsafe extern "C" fn(ByteArray)>(BYTE_ARRAY_FREE_SYMBOL.as_bytes()) }.ok().map(|free| *free);
+        Self { required, byte_array_free }
+    }
+
+    fn get(&self, name: &str) -> Option<NonNull<c_void>> {
+        // the tests keep them sorted
+        REQUIRED_SYMBOLS.binary_search(&name).ok().and_then(|index| self.required[index])
+    }
+}
+
+/// Errors looking up exports of the loaded SDK
+#[derive(Debug, thiserror::Error)]
+pub enum SdkLibError {
+    #[error("SDK export `{0}` is missing, the loaded SDK predates it")]
+    SymbolMissing(&'static str),
+
+    #[error(
+        "The SDK library {} lacks exports the bindings call: {}. It predates the bindings or isn't the Proton Drive SDK",
+        .location.display(),
+        .missing.join(", ")
+    )]
+    SymbolsMissing { location: PathBuf, missing: Vec<&'static str> },
+}
+
+/// The platform has no native SDK build
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum PlatformError {
+    #[error("The native SDK isn't built for the {0} architecture")]
+    UnsupportedArch(&'static str),
+
+    #[error("The native SDK isn't built for {0}")]
+    UnsupportedOs(&'static str),
+}
+
+/// Why the SDK library couldn't be loaded, returned by every [`ProtonSDKLib::instance`] call once it failed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoadError {
+    /// No library was looked for, there is none for the platform
+    Unsupported(PlatformError),
+    /// None of the paths tried held a library that loads
+    Failed {
+        /// File name of the SDK library looked for
+        library: String,
+        /// Where the library was looked for and why it didn't load there
+        diagnostics: LoadDiagnostics,
+    },
+    /// The library loaded, but lacks some of the [`REQUIRED_SYMBOLS`]
+    SymbolsMissing { location: PathBuf, missing: Vec<&'static str> },
+}
+
+impl LoadError {
+    /// The paths tried in order, with why loading each failed
+    pub fn attempts(&self) -> &[(PathBuf, String)] {
+        match self {
+            LoadError::Unsupported(_) | LoadError::SymbolsMissing { .. } => &[],
+            LoadError::Failed { diagnostics, .. } => &diagnostics.attempts,
+        }
+    }
+
+    /// Where the library was looked for, `None` when there is none for the platform
+    pub fn diagnostics(&self) -> Option<&LoadDiagnostics> {
+        match self {
+            LoadError::Unsupported(_) | LoadError::SymbolsMissing { .. } => None,
+            LoadError::Failed { diagnostics, .. } => Some(diagnostics),
+        }
+    }
+}
+
+impl From<PlatformError> for LoadError {
+    fn from(error: PlatformError) -> Self {
+        LoadError::Unsupported(error)
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (library, diagnostics) = match self {
+            LoadError::Unsupported(error) => {
+                return write!(f, "{}, point PROTON_SDK_LIB_PATH at a build of your own", error)
+            }
+            LoadError::SymbolsMissing { location, missing } => {
+                let error = SdkLibError::SymbolsMissing { location: location.clone(), missing: missing.clone() };
+                return write!(f, "{}", error);
+            }
+            LoadError::Failed { library, diagnostics } => (library, diagnostics),
+        };
+        write!(
+            f,
+            "The native SDK library {} couldn't be loaded, set PROTON_SDK_LIB_DIR to the directory containing \
+            it and build again, put it next to the executable or point PROTON_SDK_LIB_PATH at it",
+            library
+        )?;
+        if !diagnostics.attempts.is_empty() {
+            write!(f, " (searched: {})", diagnostics.searched())?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoadError::Unsupported(error) => Some(error),
+            LoadError::Failed { .. } | LoadError::SymbolsMissing { .. } => None,
+        }
+    }
 }
 
-static INIT: Once = Once::new();
-static mut PROTON_SDK_INSTANCE: Option<ProtonSDKLib> = None;
+/// What the search for the SDK library went through, kept by [`LoadError::Failed`]
+///
+/// `Display` renders it as a table of the paths tried, for the user to find where the library
+/// should have been or why the one found doesn't load.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LoadDiagnostics {
+    /// Runtime id of the platform, like `linux-x64`, `None` when the SDK isn't built for it
+    pub runtime_id: Option<&'static str>,
+    /// The directory relative paths are resolved from, `None` when it can't be read
+    pub working_dir: Option<PathBuf>,
+    /// The paths tried in order, with why loading each failed
+    pub attempts: Vec<(PathBuf, String)>,
+}
+
+impl LoadDiagnostics {
+    fn new(runtime_id: Option<&'static str>) -> Self {
+        Self {
+            runtime_id,
+            working_dir: std::env::current_dir().ok(),
+            attempts: Vec::new(),
+        }
+    }
+
+    fn record(&mut self, path: PathBuf, error: &libloading::Error) {
+        warn!("Failed to load library from {}: {}", path.display(), error);
+        // on Windows the reason is only in the source, the error itself names the failed call
+        let error = match std::error::Error::source(error) {
+            Some(source) => format!("{}: {}", error, source),
+            None => error.to_string(),
+        };
+        self.attempts.push((path, error));
+    }
+
+    /// The paths tried with why each failed in short, like
+    /// `./libproton_drive_sdk.so (not found), ./libs/libproton_drive_sdk.so (wrong ELF class)`
+    pub fn searched(&self) -> String {
+        self.attempts
+            .iter()
+            .map(|(path, error)| format!("{} ({})", path.display(), Self::reason(path, error)))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Shortens what the system loader reported for `path` to what went wrong
+    pub fn reason(path: &Path, error: &str) -> String {
+        const KNOWN: &[(&str, &str)] = &[
+            ("wrong elf class", "wrong ELF class"),
+            ("incompatible architecture", "wrong architecture"),
+            ("not a valid win32 application", "wrong architecture"),
+            ("invalid elf header", "not a library"),
+            ("file too short", "not a library"),
+            ("not a mach-o file", "not a library"),
+        ];
+        let lower = error.to_lowercase();
+        if let Some((_, reason)) = KNOWN.iter().find(|(cause, _)| lower.contains(cause)) {
+            return reason.to_string();
+        }
+        if let Some((file, _)) = error.split_once(": cannot open shared object file") {
+            // a dependency the loader couldn't find is named instead of the library
+            if Path::new(file).file_name() != path.file_name() {
+                return format!("needs {}, which isn't found", file);
+            }
+            return "not found".to_string();
+        }
+        if lower.contains("no such file") || lower.contains("could not be found") || lower.contains("image not found") {
+            return "not found".to_string();
+        }
+        error.strip_prefix(&format!("{}: ", path.display())).unwrap_or(error).to_string()
+    }
+}
+
+impl fmt::Display for LoadDiagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.runtime_id, &self.working_dir) {
+            (Some(runtime_id), Some(dir)) => write!(f, "searched for the {} build from {}:", runtime_id, dir.display())?,
+            (Some(runtime_id), None) => write!(f, "searched for the {} build:", runtime_id)?,
+            (None, Some(dir)) => write!(f, "searched from {}:", dir.display())?,
+            (None, None) => f.write_str("searched:")?,
+        }
+        let width = self.attempts.iter().map(|(path, _)| path.display().to_string().len()).max().unwrap_or(0);
+        for (path, error) in &self.attempts {
+            let path_text = path.display().to_string();
+            write!(f, "\n  {:<width$}  {}", path_text, Self::reason(path, error), width = width)?;
+        }
+        Ok(())
+    }
+}
+
+/// Exports the bindings call, checked by [`ProtonSDKLib::verify_symbols`], sorted by name
+pub const REQUIRED_SYMBOLS: &[&str] = &[
+    "cancellation_token_source_cancel",
+    "cancellation_token_source_create",
+    "cancellation_token_source_free",
+    "downloader_create",
+    "downloader_download_file",
+    "downloader_free",
+    "drive_client_create",
+    "drive_client_free",
+    "drive_client_get_folder_children",
+    "drive_client_get_shares",
+    "drive_client_get_volumes",
+    "drive_client_register_node_keys",
+    "drive_client_register_share_key",
+    "logger_provider_create",
+    "logger_provider_free",
+    "node_decrypt_armored_name",
+    "observability_service_flush",
+    "observability_service_free",
+    "observability_service_start_new",
+    "session_apply_data_password",
+    "session_begin",
+    "session_end",
+    "session_free",
+    "session_get_info",
+    "session_register_address_keys",
+    "session_register_armored_locked_user_key",
+    "session_renew",
+    "session_resume",
+    "uploader_create",
+    "uploader_free",
+    "uploader_upload_file_or_revision",
+    "uploader_upload_revision",
+];
+
+/// Exports that only newer SDK builds provide, checked by [`ProtonSDKLib::check_symbols`]
+pub const OPTIONAL_SYMBOLS: &[&str] = &[
+    BYTE_ARRAY_FREE_SYMBOL,
+    "node_create_folder",
+    "node_move",
+    "node_rename",
+    "node_trash",
+    VERSION_SYMBOL,
+];
+
+/// Names the SDK library file to load instead of looking for the native one, see [`ProtonSDKLib::load_from`]
+pub const LIB_PATH_ENV: &str = "PROTON_SDK_LIB_PATH";
+
+/// Names the library file to look for instead of the platform's, see [`ProtonSDKLibBuilder::file_name`]
+pub const LIB_NAME_ENV: &str = "PROTON_SDK_LIB_NAME";
+
+/// How the SDK library is looked for, the first [`ProtonSDKLibBuilder::load`] or
+/// [`ProtonSDKLib::instance`] call of the process loads it
+#[derive(Debug, Clone, Default)]
+pub struct ProtonSDKLibBuilder {
+    file_name: Option<String>,
+}
+
+impl ProtonSDKLibBuilder {
+    /// Looks for a library file named `name` instead of the platform's, in the same places
+    ///
+    /// For packages that rename the library, like `libproton_drive_sdk.so.1.2.3` or a debug
+    /// `proton_drive_sdk_d.dll`. Takes precedence over [`LIB_NAME_ENV`], but not over a path
+    /// in [`LIB_PATH_ENV`], which is the only library tried when it's set.
+    pub fn file_name(mut self, name: impl Into<String>) -> Self {
+        self.file_name = Some(name.into());
+        self
+    }
+
+    /// Loads the library, or returns the one already loaded
+    pub fn load(self) -> Result<&'static ProtonSDKLib, LoadError> {
+        ProtonSDKLib::init_with(|| unsafe { ProtonSDKLib::load_internal(self.file_name.as_deref()) })
+    }
+}
+
+/// The library loaded by the first [`ProtonSDKLib::instance`] or [`ProtonSDKLib::load_from`] call, or why it wasn't
+static PROTON_SDK: OnceLock<Result<ProtonSDKLib, LoadError>> = OnceLock::new();
 
 impl ProtonSDKLib {
-    pub fn instance() -> anyhow::Result<&'static Self> {
-        unsafe {
-            INIT.call_once(|| match Self::load_internal() {
+    pub fn instance() -> Result<&'static Self, LoadError> {
+        Self::builder().load()
+    }
+
+    /// Sets up how the library is looked for before loading it
+    pub fn builder() -> ProtonSDKLibBuilder {
+        ProtonSDKLibBuilder::default()
+    }
+
+    /// Loads the SDK library at `path`, which every later [`ProtonSDKLib::instance`] call returns
+    ///
+    /// This takes precedence over [`LIB_PATH_ENV`] and the search for the native library, as long
+    /// as it comes first: once a library is loaded, asking for another one is an error.
+    pub fn load_from(path: impl AsRef<Path>) -> anyhow::Result<&'static Self> {
+        let path = path.as_ref();
+        if cfg!(feature = "static-link") {
+            anyhow::bail!(
+                "Can't load the SDK library {}, the bindings are built with the static-link feature",
+                path.display()
+            );
+        }
+        let instance = Self::init_with(|| unsafe { Self::load_path(path) })?;
+        if instance.location != path {
+            anyhow::bail!(
+                "Can't load the SDK library {}, {} is already loaded",
+                path.display(),
+                instance.location.display()
+            );
+        }
+        Ok(instance)
+    }
+
+    /// Loads the library with `load` on the first call, later calls return what it loaded or why it couldn't
+    fn init_with(load: impl FnOnce() -> Result<Self, LoadError>) -> Result<&'static Self, LoadError> {
+        PROTON_SDK
+            .get_or_init(|| match load() {
                 Ok(instance) => {
-                    PROTON_SDK_INSTANCE = Some(instance);
+                    if let Err(SdkLibError::SymbolsMissing { location, missing }) = instance.verify_symbols() {
+                        let e = LoadError::SymbolsMissing { location, missing };
+                        error!("{}", e);
+                        return Err(e);
+                    }
+                    let missing: Vec<_> =
+                        instance.check_symbols().into_iter().filter(|(_, found)| !found).map(|(name, _)| name).collect();
+                    if instance.byte_array_free().is_none() {
+                        warn!(
+                            "{} has no {} export, the buffers the SDK returns won't be freed",
+                            instance.location.display(),
+                            BYTE_ARRAY_FREE_SYMBOL
+                        );
+                    }
+                    if !missing.is_empty() {
+                        log::info!("{} lacks the optional exports {}", instance.location.display(), missing.join(", "));
+                    }
+                    if let Some(sha256) = option_env!("PROTON_SDK_LIB_SHA256") {
+                        log::info!(
+                            "Loaded {}, the SDK library verified at build time had sha256 {}",
+                            instance.location.display(),
+                            sha256
+                        );
+                    }
+                    Ok(instance)
                 }
                 Err(e) => {
                     error!("Failed to initialise ProtonSDKLib: {}", e);
-                    log::info!("Attempting fallback of checking PROTON_SDK_LIB_DIR env");
-                    check_and_move_env();
+                    Err(e)
                 }
-            });
+            })
+            .as_ref()
+            .map_err(Clone::clone)
+    }
 
-            // dude stfu i do not care about this error
-            #[warn(static_mut_refs)]
-            PROTON_SDK_INSTANCE
-                .as_ref()
-                .ok_or_else(|| anyhow::anyhow!("Failed to initialise ProtonSDKLib"))
-        }
+    /// Wraps a loaded library, resolving the [`REQUIRED_SYMBOLS`] it exports
+    fn new(sdk_library: Library, location: PathBuf) -> Self {
+        let symbols = SdkSymbols::resolve(&sdk_library);
+        Self { sdk_library, location, symbols }
     }
 
-    /// This function loads the library and returns an instance
-    /// of the ProtonSDKLib
-    unsafe fn load_internal() -> anyhow::Result<Self> {
-        let (lib, location) = Self::call_sdk_lib()?;
-        Ok(Self {
-            sdk_library: lib,
-            location,
-        })
+    /// Checks if the loaded SDK exports `name`
+    pub fn has_symbol(&self, name: &str) -> bool {
+        unsafe { self.sdk_library.get::<*const ()>(name.as_bytes()).is_ok() }
     }
 
-    unsafe fn call_sdk_lib() -> Result<(Library, PathBuf), libloading::Error> {
-        let (_runtime_id, lib_name) = Self::get_platform_info();
-        let library_path = PathBuf::from(lib_name);
+    /// The export `name` of the [`REQUIRED_SYMBOLS`], as resolved when the library was loaded
+    ///
+    /// # Safety
+    /// `T` must be the function pointer type matching the signature of the export.
+    pub unsafe fn symbol<T: Copy>(&self, name: &'static str) -> Result<T, SdkLibError> {
+        const { assert!(std::mem::size_of::<T>() == std::mem::size_of::<*const c_void>()) };
+        let address = self.symbols.get(name).ok_or(SdkLibError::SymbolMissing(name))?;
+        Ok(std::mem::transmute_copy(&address))
+    }
 
-        match Library::new(&library_path) {
-            Ok(lib) => {
-                debug!("Loaded SDK library from: {}", library_path.display());
-                Ok((lib, library_path))
-            }
-            Err(e) => {
-                warn!(
-                    "Failed to load library from {}: {}",
-                    library_path.display(),
-                    e
-                );
-
-                // Try fallback paths
-                for fallback_path in Self::get_fallback_paths() {
-                    match Library::new(&fallback_path) {
-                        Ok(lib) => {
-                            debug!(
-                                "Loaded SDK library from fallback: {}",
-                                fallback_path.display()
-                            );
-                            return Ok((lib, fallback_path));
-                        }
-                        Err(fallback_err) => {
-                            warn!(
-                                "Fallback failed for {}: {}",
-                                fallback_path.display(),
-                                fallback_err
-                            );
-                        }
-                    }
-                }
+    /// The [`BYTE_ARRAY_FREE_SYMBOL`] export, as resolved when the library was loaded, for the
+    /// [`OwnedByteArray`](crate::data::OwnedByteArray)s of the buffers it returns
+    pub fn byte_array_free(&self) -> Option<unsafe extern "C" fn(ByteArray)> {
+        self.symbols.byte_array_free
+    }
+
+    /// Checks the loaded SDK exports all the [`REQUIRED_SYMBOLS`], listing the missing ones
+    ///
+    /// Without this, a missing export only fails the call that needs it, possibly halfway through
+    /// a transfer. [`ProtonSDKLib::instance`] refuses a library missing any of them.
+    pub fn verify_symbols(&self) -> Result<(), SdkLibError> {
+        let missing: Vec<_> = REQUIRED_SYMBOLS.iter().copied().filter(|name| self.symbols.get(name).is_none()).collect();
+        if !missing.is_empty() {
+            return Err(SdkLibError::SymbolsMissing { location: self.location.clone(), missing });
+        }
+        Ok(())
+    }
 
-                Err(e)
+    /// Reports which of the [`OPTIONAL_SYMBOLS`] the loaded SDK exports, the missing ones are
+    /// logged when [`ProtonSDKLib::instance`] loads the library
+    pub fn check_symbols(&self) -> Vec<(&'static str, bool)> {
+        OPTIONAL_SYMBOLS
+            .iter()
+            .map(|name| (*name, self.has_symbol(name)))
+            .collect()
+    }
+
+    /// The version of the loaded SDK, `None` when it doesn't tell
+    ///
+    /// Read from the [`VERSION_SYMBOL`] export, or from the name of the file the library resolves
+    /// to, like `libproton_drive_sdk.so.1.4.0` on Linux.
+    pub fn version(&self) -> Option<SdkVersion> {
+        // the string is static in the library, which lives as long as `self`
+        unsafe {
+            if let Ok(get_version) = self.optional_symbol::<unsafe extern "C" fn() -> *const c_char>(VERSION_SYMBOL) {
+                let raw = get_version();
+                if !raw.is_null() {
+                    return SdkVersion::parse(&CStr::from_ptr(raw).to_string_lossy());
+                }
             }
         }
+        let resolved = std::fs::canonicalize(&self.location).ok()?;
+        SdkVersion::from_file_name(&resolved, Self::get_platform_info().ok()?.1)
+    }
+
+    /// Looks up an export that older SDK builds might not have
+    ///
+    /// # Safety
+    /// `T` must match the signature of the export.
+    pub unsafe fn optional_symbol<T>(
+        &self,
+        name: &'static str,
+    ) -> Result<libloading::Symbol<'_, T>, SdkLibError> {
+        self.sdk_library
+            .get(name.as_bytes())
+            .map_err(|_| SdkLibError::SymbolMissing(name))
     }
 
-    fn get_platform_info() -> (&'static str, &'static str) {
-        #[cfg(target_os = "windows")]
-        {
-            let runtime_id = match std::env::consts::ARCH {
-                "x86_64" => "win-x64",
-                "x86" => "win-x86",
-                "aarch64" => "win-arm64",
-                _ => panic!(
-                    "Unsupported Windows architecture: {}",
-                    std::env::consts::ARCH
-                ),
-            };
-            (runtime_id, "proton_drive_sdk.dll")
+    /// Opens the SDK the executable is linked with, to look its exports up like those of a loaded one
+    #[cfg(feature = "static-link")]
+    unsafe fn load_internal(file_name: Option<&str>) -> Result<Self, LoadError> {
+        let location = std::env::current_exe().unwrap_or_default();
+        // the runtime id only goes in the diagnostics of Windows, where the library is looked up by name
+        #[cfg_attr(not(windows), allow(unused_variables))]
+        let (runtime_id, lib_name) = Self::get_platform_info()?;
+        let lib_name = Self::file_name(file_name, std::env::var(LIB_NAME_ENV).ok(), lib_name);
+        #[cfg(unix)]
+        let sdk_library = libloading::os::unix::Library::this().into();
+        #[cfg(windows)]
+        let sdk_library = match libloading::os::windows::Library::open_already_loaded(&lib_name) {
+            Ok(library) => library.into(),
+            Err(e) => {
+                let mut diagnostics = LoadDiagnostics::new(Some(runtime_id));
+                diagnostics.record(PathBuf::from(&lib_name), &e);
+                return Err(LoadError::Failed { library: lib_name, diagnostics });
+            }
+        };
+        debug!("Using the SDK library {} linked with {}", lib_name, location.display());
+        Ok(Self::new(sdk_library, location))
+    }
+
+    /// This function loads the library and returns an instance
+    /// of the ProtonSDKLib
+    #[cfg(not(feature = "static-link"))]
+    unsafe fn load_internal(file_name: Option<&str>) -> Result<Self, LoadError> {
+        // an explicit library, like the mock SDK of the integration tests, is the only one tried
+        if let Some(library_path) = std::env::var_os(LIB_PATH_ENV).map(PathBuf::from) {
+            return Self::load_path(&library_path);
         }
 
-        #[cfg(target_os = "linux")]
-        {
-            let runtime_id = match std::env::consts::ARCH {
-                "x86_64" => "linux-x64",
-                "x86" => "linux-x86",
-                "aarch64" => "linux-arm64",
-                "arm" => "linux-arm",
-                _ => panic!("Unsupported Linux architecture: {}", std::env::consts::ARCH),
-            };
-            (runtime_id, "libproton_drive_sdk.so")
+        let (runtime_id, lib_name) = Self::get_platform_info()?;
+        Self::search(&Self::file_name(file_name, std::env::var(LIB_NAME_ENV).ok(), lib_name), runtime_id)
+    }
+
+    /// The library file to look for: the one asked for, the one of [`LIB_NAME_ENV`] or the platform's
+    fn file_name(requested: Option<&str>, from_env: Option<String>, platform: &str) -> String {
+        requested
+            .map(str::to_string)
+            .or(from_env.filter(|name| !name.is_empty()))
+            .unwrap_or_else(|| platform.to_string())
+    }
+
+    /// Loads the first of the places the library `lib_name` is looked for that holds one
+    #[cfg(not(feature = "static-link"))]
+    unsafe fn search(lib_name: &str, runtime_id: &'static str) -> Result<Self, LoadError> {
+        let mut diagnostics = LoadDiagnostics::new(Some(runtime_id));
+        for library_path in std::iter::once(PathBuf::from(lib_name)).chain(Self::get_fallback_paths(lib_name)) {
+            match Library::new(&library_path) {
+                Ok(sdk_library) => {
+                    debug!("Loaded SDK library from: {}", library_path.display());
+                    return Ok(Self::new(sdk_library, library_path));
+                }
+                Err(e) => diagnostics.record(library_path, &e),
+            }
         }
 
-        #[cfg(target_os = "macos")]
-        {
-            let runtime_id = match std::env::consts::ARCH {
-                "x86_64" => "osx-x64",
-                "aarch64" => "osx-arm64",
-                _ => panic!("Unsupported macOS architecture: {}", std::env::consts::ARCH),
-            };
-            (runtime_id, "libproton_drive_sdk.dylib")
+        Err(LoadError::Failed {
+            library: lib_name.to_string(),
+            diagnostics,
+        })
+    }
+
+    /// Loads the library at `path` and nothing else
+    unsafe fn load_path(path: &Path) -> Result<Self, LoadError> {
+        match Library::new(path) {
+            Ok(sdk_library) => {
+                debug!("Loaded SDK library from: {}", path.display());
+                Ok(Self::new(sdk_library, path.to_path_buf()))
+            }
+            Err(e) => {
+                // an explicit path may hold a build for a platform without an official one
+                let platform = Self::get_platform_info().ok();
+                let mut diagnostics = LoadDiagnostics::new(platform.map(|(runtime_id, _)| runtime_id));
+                diagnostics.record(path.to_path_buf(), &e);
+                Err(LoadError::Failed {
+                    library: platform.map_or("proton_drive_sdk", |(_, lib_name)| lib_name).to_string(),
+                    diagnostics,
+                })
+            }
         }
+    }
 
-        #[cfg(not(any(target_os = "windows", target_os = "linux", target_os = "macos")))]
-        {
-            panic!("Unsupported operating system: {}", std::env::consts::OS);
+    fn get_platform_info() -> Result<(&'static str, &'static str), PlatformError> {
+        let (os, arch) = target();
+        match (platform::runtime_id(os, arch), platform::lib_name(os)) {
+            (Some(runtime_id), Some(lib_name)) => Ok((runtime_id, lib_name)),
+            (None, Some(_)) => Err(PlatformError::UnsupportedArch(arch)),
+            _ => Err(PlatformError::UnsupportedOs(os)),
         }
     }
 
-    fn get_fallback_paths() -> Vec<PathBuf> {
+    #[cfg(not(feature = "static-link"))]
+    fn get_fallback_paths(lib_name: &str) -> Vec<PathBuf> {
         let mut paths = Vec::new();
-        let (_runtime_id, lib_name) = Self::get_platform_info();
 
         paths.push(PathBuf::from(format!("./{}", lib_name)));
         paths.push(PathBuf::from(format!("./libs/{}", lib_name)));
@@ -159,45 +613,145 @@ impl ProtonSDKLib {
     }
 }
 
-fn check_and_move_env() {
-    use std::{env, fs, path::PathBuf};
+#[cfg(test)]
+thread_local! {
+    /// The OS and architecture [`target`] reports on this thread instead of the real ones
+    static TARGET_OVERRIDE: std::cell::Cell<Option<(&'static str, &'static str)>> = const { std::cell::Cell::new(None) };
+}
+
+/// The OS and architecture to load the SDK for, as `std::env::consts` names them
+fn target() -> (&'static str, &'static str) {
+    #[cfg(test)]
+    if let Some(target) = TARGET_OVERRIDE.with(|target| target.get()) {
+        return target;
+    }
+    (std::env::consts::OS, std::env::consts::ARCH)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let (_runtime_id, lib_name) = ProtonSDKLib::get_platform_info();
+    /// The modules making the SDK calls, whose lookups must all be listed
+    const RAW_MODULES: &[&str] = &[
+        include_str!("cancellation.rs"),
+        include_str!("downloads.rs"),
+        include_str!("drive.rs"),
+        include_str!("logger.rs"),
+        include_str!("nodes.rs"),
+        include_str!("observability.rs"),
+        include_str!("sessions.rs"),
+        include_str!("uploads.rs"),
+    ];
+
+    /// Loads the SDK as if the process ran on `os` and `arch`
+    #[cfg(not(feature = "static-link"))]
+    fn load_on(os: &'static str, arch: &'static str) -> Result<ProtonSDKLib, LoadError> {
+        TARGET_OVERRIDE.with(|target| target.set(Some((os, arch))));
+        let loaded = unsafe { ProtonSDKLib::load_internal(None) };
+        TARGET_OVERRIDE.with(|target| target.set(None));
+        loaded
+    }
 
-    let lib_dir = match env::var("PROTON_SDK_LIB_DIR") {
-        Ok(val) => PathBuf::from(val),
-        Err(_) => {
-            warn!("PROTON_SDK_LIB_DIR is not set.");
+    #[test]
+    #[cfg(not(feature = "static-link"))]
+    fn unsupported_platforms_are_errors() {
+        if std::env::var_os(LIB_PATH_ENV).is_some() {
             return;
         }
-    };
+        let error = load_on("linux", "riscv64").err().unwrap();
+        assert_eq!(error, LoadError::Unsupported(PlatformError::UnsupportedArch("riscv64")));
+        assert!(error.to_string().starts_with("The native SDK isn't built for the riscv64 architecture"));
+        let error = load_on("freebsd", "x86_64").err().unwrap();
+        assert_eq!(error, LoadError::Unsupported(PlatformError::UnsupportedOs("freebsd")));
+    }
+
+    #[test]
+    #[cfg(not(feature = "static-link"))]
+    fn every_path_searched_is_in_the_diagnostics() {
+        let error = unsafe { ProtonSDKLib::search("libno_such_sdk.so", "linux-x64") }.err().unwrap();
+        let diagnostics = error.diagnostics().unwrap();
+        assert_eq!(diagnostics.runtime_id, Some("linux-x64"));
+        assert_eq!(diagnostics.working_dir, std::env::current_dir().ok());
+
+        let expected: Vec<_> = std::iter::once(PathBuf::from("libno_such_sdk.so"))
+            .chain(ProtonSDKLib::get_fallback_paths("libno_such_sdk.so"))
+            .collect();
+        let tried: Vec<_> = error.attempts().iter().map(|(path, _)| path.clone()).collect();
+        assert_eq!(tried, expected);
+        for (path, reason) in error.attempts() {
+            assert_eq!(LoadDiagnostics::reason(path, reason), "not found", "{}", reason);
+        }
 
-    let lib_path = lib_dir.join(lib_name);
-    if !lib_path.exists() {
-        warn!(
-            "Library {} not found in PROTON_SDK_LIB_DIR: {}",
-            lib_name,
-            lib_dir.display()
+        assert!(error.to_string().contains("(searched: libno_such_sdk.so (not found), ./libno_such_sdk.so (not found), "));
+        let table = diagnostics.to_string();
+        assert!(table.starts_with("searched for the linux-x64 build from "), "{}", table);
+        assert_eq!(table.lines().count(), expected.len() + 1);
+        // the reasons line up in a column
+        let columns: Vec<_> = table.lines().skip(1).map(|line| line.find("not found")).collect();
+        assert!(columns.iter().all(|column| column.is_some() && *column == columns[0]), "{}", table);
+    }
+
+    #[test]
+    fn requested_file_names_win_over_the_environment_and_the_platform() {
+        let platform = "libproton_drive_sdk.so";
+        let from_env = || Some("libproton_drive_sdk.so.1.2.3".to_string());
+        assert_eq!(ProtonSDKLib::file_name(Some("libsdk_d.so"), from_env(), platform), "libsdk_d.so");
+        assert_eq!(ProtonSDKLib::file_name(None, from_env(), platform), "libproton_drive_sdk.so.1.2.3");
+        assert_eq!(ProtonSDKLib::file_name(None, Some(String::new()), platform), platform);
+        assert_eq!(ProtonSDKLib::file_name(None, None, platform), platform);
+    }
+
+    #[test]
+    #[cfg(not(feature = "static-link"))]
+    fn renamed_libraries_are_looked_for_in_the_usual_places() {
+        let error = unsafe { ProtonSDKLib::search("proton_drive_sdk_d.dll", "win-x64") }.err().unwrap();
+        assert!(matches!(&error, LoadError::Failed { library, .. } if library == "proton_drive_sdk_d.dll"));
+        let tried: Vec<_> = error.attempts().iter().map(|(path, _)| path.to_string_lossy().into_owned()).collect();
+        assert!(tried.contains(&"./libs/proton_drive_sdk_d.dll".to_string()), "{:?}", tried);
+        assert!(tried.iter().all(|path| path.ends_with("proton_drive_sdk_d.dll")), "{:?}", tried);
+    }
+
+    #[test]
+    fn loader_errors_are_shortened() {
+        let path = Path::new("./libs/libproton_drive_sdk.so");
+        let reason = |error| LoadDiagnostics::reason(path, error);
+        assert_eq!(reason("./libs/libproton_drive_sdk.so: wrong ELF class: ELFCLASS32"), "wrong ELF class");
+        assert_eq!(
+            reason("./libs/libproton_drive_sdk.so: cannot open shared object file: No such file or directory"),
+            "not found"
+        );
+        assert_eq!(
+            reason("libicuuc.so.70: cannot open shared object file: No such file or directory"),
+            "needs libicuuc.so.70, which isn't found"
+        );
+        assert_eq!(
+            reason("LoadLibraryExW failed: %1 is not a valid Win32 application. (os error 193)"),
+            "wrong architecture"
         );
-        return;
+        assert_eq!(reason("./libs/libproton_drive_sdk.so: undefined symbol: GC_Init"), "undefined symbol: GC_Init");
     }
 
-    let dest_path = PathBuf::from(lib_name);
-    match fs::copy(&lib_path, &dest_path) {
-        Ok(_) => {
-            debug!(
-                "Copied SDK library from {} to {}",
-                lib_path.display(),
-                dest_path.display()
-            );
-        }
-        Err(e) => {
-            warn!(
-                "Failed to copy SDK library from {} to {}: {}",
-                lib_path.display(),
-                dest_path.display(),
-                e
-            );
-        }
+    #[test]
+    fn every_export_looked_up_is_required() {
+        let mut looked_up: Vec<_> = RAW_MODULES
+            .iter()
+            .flat_map(|source| source.split("sdk_symbol!(").skip(1))
+            .map(|rest| &rest[..rest.find(')').unwrap()])
+            .collect();
+        looked_up.sort();
+        looked_up.dedup();
+        assert_eq!(looked_up, REQUIRED_SYMBOLS);
+    }
+
+    #[test]
+    fn every_required_export_is_declared_for_linking() {
+        let linked = include_str!("linked.rs");
+        let declared: Vec<_> = linked
+            .split("pub fn ")
+            .skip(1)
+            .map(|rest| &rest[..rest.find('(').unwrap()])
+            .collect();
+        assert_eq!(declared, REQUIRED_SYMBOLS);
     }
 }
@@ -0,0 +1,53 @@
+//! Declarations of the SDK exports for the `static-link` feature, which links the native
+//! SDK at build time instead of loading it with libloading
+//!
+//! build.rs passes the library and PROTON_SDK_LIB_DIR to the linker. The signatures must match
+//! the ones the raw modules annotate their `sdk_symbol!` lookups with, which the compiler
+//! checks when the feature is on.
+
+use crate::data::{
+    AsyncCallback, AsyncCallbackWithProgress, BooleanCallback, ByteArray, Callback, TwoFactorRequestedCallback,
+};
+
+extern "C" {
+    pub fn cancellation_token_source_cancel(_: isize);
+    pub fn cancellation_token_source_create() -> isize;
+    pub fn cancellation_token_source_free(_: isize);
+    pub fn downloader_create(_: isize, _: ByteArray, _: AsyncCallback) -> i32;
+    pub fn downloader_download_file(_: isize, _: ByteArray, _: AsyncCallbackWithProgress) -> i32;
+    pub fn downloader_free(_: isize);
+    pub fn drive_client_create(_: isize, _: isize, _: ByteArray, _: *mut isize) -> i32;
+    pub fn drive_client_free(_: isize);
+    pub fn drive_client_get_folder_children(_: isize, _: ByteArray, _: isize) -> ByteArray;
+    pub fn drive_client_get_shares(_: isize, _: ByteArray, _: isize) -> ByteArray;
+    pub fn drive_client_get_volumes(_: isize, _: isize) -> ByteArray;
+    pub fn drive_client_register_node_keys(_: isize, _: ByteArray) -> i32;
+    pub fn drive_client_register_share_key(_: isize, _: ByteArray) -> i32;
+    pub fn logger_provider_create(_: Callback, _: *mut isize) -> i32;
+    pub fn logger_provider_free(_: isize);
+    pub fn node_decrypt_armored_name(_: isize, _: ByteArray, _: AsyncCallback) -> i32;
+    pub fn observability_service_flush(_: isize, _: AsyncCallback) -> i32;
+    pub fn observability_service_free(_: isize);
+    pub fn observability_service_start_new(_: isize, _: *mut isize) -> i32;
+    pub fn session_apply_data_password(_: isize, _: ByteArray, _: isize) -> i32;
+    pub fn session_begin(
+        _: isize,
+        _: ByteArray,
+        _: Callback,
+        _: BooleanCallback,
+        _: TwoFactorRequestedCallback,
+        _: Callback,
+        _: AsyncCallback,
+    ) -> i32;
+    pub fn session_end(_: isize, _: AsyncCallback) -> i32;
+    pub fn session_free(_: isize);
+    pub fn session_get_info(_: isize, _: isize, _: *mut ByteArray) -> i32;
+    pub fn session_register_address_keys(_: isize, _: ByteArray) -> i32;
+    pub fn session_register_armored_locked_user_key(_: isize, _: ByteArray) -> i32;
+    pub fn session_renew(_: isize, _: ByteArray, _: Callback, _: *mut isize) -> i32;
+    pub fn session_resume(_: ByteArray, _: Callback, _: BooleanCallback, _: Callback, _: *mut isize) -> i32;
+    pub fn uploader_create(_: isize, _: ByteArray, _: AsyncCallback) -> i32;
+    pub fn uploader_free(_: isize);
+    pub fn uploader_upload_file_or_revision(_: isize, _: ByteArray, _: AsyncCallbackWithProgress) -> i32;
+    pub fn uploader_upload_revision(_: isize, _: ByteArray, _: AsyncCallbackWithProgress) -> i32;
+}
@@ -1,43 +1,69 @@
+/// Handle for a logger provider
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct LoggerProviderHandle(pub isize);
 
 impl LoggerProviderHandle {
+    /// Creates a null/invalid handle
     pub fn null() -> Self {
         Self(0)
     }
 
+    /// Checks if the handle is null/invalid
     pub fn is_null(&self) -> bool {
         self.0 == 0
     }
 
+    /// Gets the raw isize value for FFI
     pub fn raw(&self) -> isize {
         self.0
     }
 }
 
+impl From<isize> for LoggerProviderHandle {
+    fn from(handle: isize) -> Self {
+        Self(handle)
+    }
+}
+
 pub mod raw {
-    use crate::{data::Callback, logger::LoggerProviderHandle, observability, ProtonSDKLib};
+    use crate::{data::Callback, logger::LoggerProviderHandle};
 
     // int logger_provider_create(
     //     Callback log_callback,
     //     intptr_t* logger_provider_handle
     // );
+    /// Creates a logger provider that reports SDK log events to a callback
+    ///
+    /// # Parameters
+    /// * `log_callback` - Callback receiving each log event as a `LogEvent` ByteArray
+    ///
+    /// # Returns
+    /// (Result code, Logger provider handle) - code 0 = success
     pub fn logger_provider_create(
         log_callback: Callback,
     ) -> anyhow::Result<(i32, LoggerProviderHandle)> {
         unsafe {
-            let sdk = ProtonSDKLib::instance()?;
-
-            let logger_create: libloading::Symbol<
-                unsafe extern "C" fn(Callback, *mut isize) -> i32,
-            > = sdk.sdk_library.get(b"logger_provider_create")?;
+            let logger_create: unsafe extern "C" fn(Callback, *mut isize) -> i32 =
+                sdk_symbol!(logger_provider_create);
 
             let mut logger_provider_handle: isize = 0;
             let result = logger_create(log_callback, &mut logger_provider_handle);
 
-            Ok((
-                result,
-                LoggerProviderHandle::from(LoggerProviderHandle(logger_provider_handle)),
-            ))
+            Ok((result, LoggerProviderHandle::from(logger_provider_handle)))
+        }
+    }
+
+    // void logger_provider_free(intptr_t logger_provider_handle);
+    /// Frees a logger provider
+    ///
+    /// # Parameters
+    /// * `logger_provider_handle` - Handle to the logger provider to free
+    pub fn logger_provider_free(logger_provider_handle: LoggerProviderHandle) -> anyhow::Result<()> {
+        unsafe {
+            let logger_free: unsafe extern "C" fn(isize) = sdk_symbol!(logger_provider_free);
+
+            logger_free(logger_provider_handle.raw());
+            Ok(())
         }
     }
 }
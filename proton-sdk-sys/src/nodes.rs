@@ -27,15 +27,122 @@ pub mod raw {
         callback: AsyncCallback,
     ) -> anyhow::Result<i32> {
         unsafe {
-            let sdk = ProtonSDKLib::instance()?;
-
-            let decrypt_name_fn: libloading::Symbol<
-                unsafe extern "C" fn(isize, ByteArray, AsyncCallback) -> i32,
-            > = sdk.sdk_library.get(b"node_decrypt_armored_name")?;
+            let decrypt_name_fn: unsafe extern "C" fn(isize, ByteArray, AsyncCallback) -> i32 =
+                sdk_symbol!(node_decrypt_armored_name);
 
             let result = decrypt_name_fn(client_handle.raw(), request, callback);
 
             Ok(result)
         }
     }
+
+    // The exports below follow the shape of node_decrypt_armored_name: a client
+    // handle, a protobuf request and an AsyncCallback receiving the resulting
+    // node (or an Error protobuf). Older SDK builds don't have them, in which case
+    // the calls fail with `SdkLibError::SymbolMissing` instead of panicking.
+
+    // int node_create_folder(
+    //     intptr_t client_handle,
+    //     ByteArray pointer, // FolderCreationRequest
+    //     AsyncCallback callback
+    // );
+    /// Creates a folder
+    ///
+    /// # Parameters
+    /// * `client_handle` - Handle to the Drive client
+    /// * `request` - Parent folder identity and name as ByteArray
+    /// * `callback` - Async callback receiving the created FolderNode
+    ///
+    /// # Returns
+    /// Result code (0 = success, non-zero = error)
+    pub fn node_create_folder(
+        client_handle: DriveClientHandle,
+        request: ByteArray,
+        callback: AsyncCallback,
+    ) -> anyhow::Result<i32> {
+        call_node_export("node_create_folder", client_handle, request, callback)
+    }
+
+    // int node_move(
+    //     intptr_t client_handle,
+    //     ByteArray pointer, // NodeMoveRequest
+    //     AsyncCallback callback
+    // );
+    /// Moves a node to another folder
+    ///
+    /// # Parameters
+    /// * `client_handle` - Handle to the Drive client
+    /// * `request` - Node identity and new parent folder identity as ByteArray
+    /// * `callback` - Async callback for completion
+    ///
+    /// # Returns
+    /// Result code (0 = success, non-zero = error)
+    pub fn node_move(
+        client_handle: DriveClientHandle,
+        request: ByteArray,
+        callback: AsyncCallback,
+    ) -> anyhow::Result<i32> {
+        call_node_export("node_move", client_handle, request, callback)
+    }
+
+    // int node_rename(
+    //     intptr_t client_handle,
+    //     ByteArray pointer, // NodeRenameRequest
+    //     AsyncCallback callback
+    // );
+    /// Renames a node
+    ///
+    /// # Parameters
+    /// * `client_handle` - Handle to the Drive client
+    /// * `request` - Node identity and new name as ByteArray
+    /// * `callback` - Async callback for completion
+    ///
+    /// # Returns
+    /// Result code (0 = success, non-zero = error)
+    pub fn node_rename(
+        client_handle: DriveClientHandle,
+        request: ByteArray,
+        callback: AsyncCallback,
+    ) -> anyhow::Result<i32> {
+        call_node_export("node_rename", client_handle, request, callback)
+    }
+
+    // int node_trash(
+    //     intptr_t client_handle,
+    //     ByteArray pointer, // NodeTrashRequest
+    //     AsyncCallback callback
+    // );
+    /// Moves nodes to the trash
+    ///
+    /// # Parameters
+    /// * `client_handle` - Handle to the Drive client
+    /// * `request` - Identities of the nodes to trash as ByteArray
+    /// * `callback` - Async callback for completion
+    ///
+    /// # Returns
+    /// Result code (0 = success, non-zero = error)
+    pub fn node_trash(
+        client_handle: DriveClientHandle,
+        request: ByteArray,
+        callback: AsyncCallback,
+    ) -> anyhow::Result<i32> {
+        call_node_export("node_trash", client_handle, request, callback)
+    }
+
+    fn call_node_export(
+        name: &'static str,
+        client_handle: DriveClientHandle,
+        request: ByteArray,
+        callback: AsyncCallback,
+    ) -> anyhow::Result<i32> {
+        unsafe {
+            let sdk = ProtonSDKLib::instance()?;
+
+            let node_fn: libloading::Symbol<
+                unsafe extern "C" fn(isize, ByteArray, AsyncCallback) -> i32,
+            > = sdk.optional_symbol(name)?;
+
+            Ok(node_fn(client_handle.raw(), request, callback))
+        }
+    }
 }
@@ -30,7 +30,6 @@ pub mod raw {
         data::{AsyncCallback, ByteArray},
         observability::ObservabilityHandle,
         sessions::SessionHandle,
-        ProtonSDKLib,
     };
 
     // int observability_service_start_new(
@@ -48,11 +47,8 @@ pub mod raw {
         session_handle: SessionHandle,
     ) -> anyhow::Result<(i32, ObservabilityHandle)> {
         unsafe {
-            let sdk = ProtonSDKLib::instance()?;
-
-            let observability_start_fn: libloading::Symbol<
-                unsafe extern "C" fn(isize, *mut isize) -> i32,
-            > = sdk.sdk_library.get(b"observability_service_start_new")?;
+            let observability_start_fn: unsafe extern "C" fn(isize, *mut isize) -> i32 =
+                sdk_symbol!(observability_service_start_new);
 
             let mut observability_handle: isize = 0;
             let result = observability_start_fn(session_handle.raw(), &mut observability_handle);
@@ -82,10 +78,8 @@ pub mod raw {
         callback: AsyncCallback,
     ) -> anyhow::Result<i32> {
         unsafe {
-            let sdk = ProtonSDKLib::instance()?;
-
-            let flush_fn: libloading::Symbol<unsafe extern "C" fn(isize, AsyncCallback) -> i32> =
-                sdk.sdk_library.get(b"observability_service_flush")?;
+            let flush_fn: unsafe extern "C" fn(isize, AsyncCallback) -> i32 =
+                sdk_symbol!(observability_service_flush);
 
             let result = flush_fn(observability_handle.raw(), callback);
 
@@ -102,10 +96,7 @@ pub mod raw {
         observability_handle: ObservabilityHandle,
     ) -> anyhow::Result<()> {
         unsafe {
-            let sdk = ProtonSDKLib::instance()?;
-
-            let free_fn: libloading::Symbol<unsafe extern "C" fn(isize)> =
-                sdk.sdk_library.get(b"observability_service_free")?;
+            let free_fn: unsafe extern "C" fn(isize) = sdk_symbol!(observability_service_free);
 
             free_fn(observability_handle.raw());
             Ok(())
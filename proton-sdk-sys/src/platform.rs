@@ -0,0 +1,31 @@
+//! Names of the native SDK builds per platform
+//!
+//! build.rs includes this file too, so it must not depend on anything else of the crate.
+//! The OS and architecture are named as `std::env::consts` and the `target_os` and
+//! `target_arch` cfgs name them.
+
+/// .NET runtime id of the SDK build for `os` and `arch`, like `linux-x64`
+pub fn runtime_id(os: &str, arch: &str) -> Option<&'static str> {
+    Some(match (os, arch) {
+        ("windows", "x86_64") => "win-x64",
+        ("windows", "x86") => "win-x86",
+        ("windows", "aarch64") => "win-arm64",
+        ("linux", "x86_64") => "linux-x64",
+        ("linux", "x86") => "linux-x86",
+        ("linux", "aarch64") => "linux-arm64",
+        ("linux", "arm") => "linux-arm",
+        ("macos", "x86_64") => "osx-x64",
+        ("macos", "aarch64") => "osx-arm64",
+        _ => return None,
+    })
+}
+
+/// File name of the SDK library on `os`
+pub fn lib_name(os: &str) -> Option<&'static str> {
+    match os {
+        "windows" => Some("proton_drive_sdk.dll"),
+        "linux" => Some("libproton_drive_sdk.so"),
+        "macos" => Some("libproton_drive_sdk.dylib"),
+        _ => None,
+    }
+}
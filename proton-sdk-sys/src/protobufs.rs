@@ -1,8 +1,41 @@
 use crate::data::ByteArray;
 use prost::Message;
 
-// Include the generated protobuf code
-include!(concat!(env!("OUT_DIR"), "/_.rs"));
+/// Generated messages of the `account` package: sessions, keys and shared responses
+pub mod account {
+    include!(concat!(env!("OUT_DIR"), "/account.rs"));
+}
+
+/// Generated messages of the `drive` package: clients, nodes and transfers
+pub mod drive {
+    include!(concat!(env!("OUT_DIR"), "/drive.rs"));
+}
+
+/// Flat view of every generated message, as before the split into packages
+#[deprecated(note = "import from `protobufs::account` or `protobufs::drive` instead")]
+pub mod flat {
+    pub use super::account::*;
+    pub use super::drive::*;
+}
+
+// `Debug` and `Sensitive` of the messages with sensitive fields, see PROTO_ATTRIBUTES in build.rs
+include!(concat!(env!("OUT_DIR"), "/redacted_debug.rs"));
+
+mod convert;
+mod enums;
+mod error;
+mod identity;
+mod operation;
+mod pool;
+mod progress;
+mod redact;
+
+pub use enums::ParseEnumError;
+pub use error::{primary_code_name, SdkErrorKind};
+pub use identity::{CompactIdentityError, NodeIdentityBuilder};
+pub use pool::{PooledBuffer, ProtoBufferPool};
+pub use progress::human_bytes;
+pub use redact::{RedactedSessionInfo, RedactedSessionTokens};
 
 #[derive(Debug, thiserror::Error)]
 pub enum ProtoError {
@@ -14,6 +47,9 @@ pub enum ProtoError {
 
     #[error("ByteArray contains invalid data")]
     InvalidData,
+
+    #[error("Only {known} of {total} bytes belong to fields of the expected message")]
+    UnknownFields { known: usize, total: usize },
 }
 
 pub struct ProtoBuffer {
@@ -21,6 +57,8 @@ pub struct ProtoBuffer {
     _buffer: Vec<u8>,
     /// The ByteArray pointing to the buffer data
     byte_array: ByteArray,
+    /// Zeroed when dropped, with the `zeroize` feature
+    sensitive: bool,
 }
 
 impl ProtoBuffer {
@@ -28,19 +66,21 @@ impl ProtoBuffer {
     ///
     /// # Example
     /// ```rust
-    /// use crate::protobufs::SessionBeginRequest;
+    /// use proton_sdk_sys::protobufs::{account::SessionBeginRequest, ProtoBuffer};
     ///
     /// let request = SessionBeginRequest {
     ///     username: "user@example.com".to_string(),
     ///     password: "password".to_string(),
-    ///     options: None,
+    ///     ..Default::default()
     /// };
     ///
-    /// let proto_buf = ProtoBuffer::encode(&request)?;
-    /// let result = raw::session_begin(0, proto_buf.as_byte_array(), callbacks...)?;
+    /// let proto_buf = ProtoBuffer::encode(&request).unwrap();
+    /// // hand proto_buf.as_byte_array() to the SDK, e.g. raw::session_begin
+    /// assert!(!proto_buf.is_empty());
     /// ```
     pub fn encode<T: Message>(message: &T) -> Result<Self, ProtoError> {
-        let mut buffer = Vec::new();
+        // sized up front, growing would leave copies of sensitive messages behind unzeroed
+        let mut buffer = Vec::with_capacity(message.encoded_len());
         message.encode(&mut buffer)?;
 
         let byte_array = ByteArray::from_slice(&buffer);
@@ -48,9 +88,24 @@ impl ProtoBuffer {
         Ok(Self {
             _buffer: buffer,
             byte_array,
+            sensitive: false,
         })
     }
 
+    /// Encodes a message holding a password, token or key, see [`Sensitive`]
+    ///
+    /// With the `zeroize` feature the encoded bytes are zeroed when the buffer is dropped.
+    pub fn encode_sensitive<T: Message>(message: &T) -> Result<Self, ProtoError> {
+        let mut buffer = Self::encode(message)?;
+        buffer.sensitive = true;
+        Ok(buffer)
+    }
+
+    /// Checks if the buffer is zeroed when dropped, with the `zeroize` feature
+    pub fn is_sensitive(&self) -> bool {
+        self.sensitive
+    }
+
     /// Gets the ByteArray for FFI calls
     pub fn as_byte_array(&self) -> ByteArray {
         self.byte_array
@@ -72,6 +127,24 @@ impl ProtoBuffer {
     }
 }
 
+impl Drop for ProtoBuffer {
+    fn drop(&mut self) {
+        #[cfg(feature = "zeroize")]
+        if self.sensitive {
+            zeroize::Zeroize::zeroize(&mut self._buffer);
+        }
+    }
+}
+
+/// A message with a password, token or unlocked key, the ones with `Sensitive`
+/// fields in build.rs
+pub trait Sensitive: Message + Sized {
+    /// Encodes the message into a buffer zeroed when dropped, with the `zeroize` feature
+    fn to_sensitive_proto_buffer(&self) -> Result<ProtoBuffer, ProtoError> {
+        ProtoBuffer::encode_sensitive(self)
+    }
+}
+
 /// Helper trait for encoding protobuf messages to ByteArray
 pub trait ToByteArray {
     /// Encodes the message and returns a ProtoBuffer that manages the lifetime
@@ -88,6 +161,18 @@ pub trait FromByteArray: Sized {
 
     /// Decodes a message from raw bytes
     fn from_bytes(data: &[u8]) -> Result<Self, ProtoError>;
+
+    /// Decodes a message from a ByteArray, see [`FromByteArray::from_bytes_strict`]
+    fn from_byte_array_strict(data: &ByteArray) -> Result<Self, ProtoError>;
+
+    /// Decodes a message from raw bytes, failing when any of the buffer isn't
+    /// made of the message's fields
+    ///
+    /// Protobuf decoding skips unknown fields, so a buffer holding a different
+    /// message (or trailing data) often decodes "successfully" into a mostly
+    /// empty message. This rejects buffers the decoded message doesn't re-encode
+    /// to the same length as, which a field it doesn't know always changes.
+    fn from_bytes_strict(data: &[u8]) -> Result<Self, ProtoError>;
 }
 
 /// Implement ToByteArray for all protobuf messages
@@ -97,7 +182,7 @@ impl<T: Message> ToByteArray for T {
     }
 
     fn to_bytes(&self) -> Result<Vec<u8>, ProtoError> {
-        let mut buffer = Vec::new();
+        let mut buffer = Vec::with_capacity(self.encoded_len());
         self.encode(&mut buffer)?;
         Ok(buffer)
     }
@@ -106,25 +191,41 @@ impl<T: Message> ToByteArray for T {
 /// Implement FromByteArray for all protobuf messages
 impl<T: Message + Default> FromByteArray for T {
     fn from_byte_array(data: &ByteArray) -> Result<Self, ProtoError> {
-        unsafe {
-            let slice = data.as_slice();
-            Ok(T::decode(slice)?)
-        }
+        Ok(T::decode(&*data.view())?)
     }
 
     fn from_bytes(data: &[u8]) -> Result<Self, ProtoError> {
         Ok(T::decode(data)?)
     }
+
+    fn from_byte_array_strict(data: &ByteArray) -> Result<Self, ProtoError> {
+        T::from_bytes_strict(&data.view())
+    }
+
+    fn from_bytes_strict(data: &[u8]) -> Result<Self, ProtoError> {
+        let message = T::decode(data)?;
+        // re-encoding only writes known fields, so this measures what was understood
+        let known = message.encoded_len();
+        if known != data.len() {
+            return Err(ProtoError::UnknownFields {
+                known,
+                total: data.len(),
+            });
+        }
+        Ok(message)
+    }
 }
 
 /// Convenience functions for common protobuf operations
 pub mod helpers {
+    use std::marker::PhantomData;
+
     use super::*;
 
     /// Encodes a protobuf message and returns (buffer, ByteArray) tuple
     /// The buffer must be kept alive while using the ByteArray
     pub fn encode_message<T: Message>(message: &T) -> Result<(Vec<u8>, ByteArray), ProtoError> {
-        let mut buffer = Vec::new();
+        let mut buffer = Vec::with_capacity(message.encoded_len());
         message.encode(&mut buffer)?;
         let byte_array = ByteArray::from_slice(&buffer);
         Ok((buffer, byte_array))
@@ -144,6 +245,92 @@ pub mod helpers {
     pub fn empty_byte_array() -> ByteArray {
         ByteArray::from_slice(&[])
     }
+
+    /// How much of a buffer a decoded message accounted for
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum DecodeConfidence {
+        /// No known field was set, the buffer may have held another message or nothing
+        Empty,
+        /// At least one known field was decoded
+        WithFields,
+    }
+
+    /// Decodes raw bytes as `T`, reporting whether any of its fields were present
+    ///
+    /// Useful when probing which message a buffer holds, as every buffer of
+    /// unknown fields decodes into an empty message.
+    pub fn try_decode_as<T: Message + Default>(
+        data: &[u8],
+    ) -> Result<(T, DecodeConfidence), ProtoError> {
+        let message = T::decode(data)?;
+        let confidence = if message.encoded_len() == 0 {
+            DecodeConfidence::Empty
+        } else {
+            DecodeConfidence::WithFields
+        };
+        Ok((message, confidence))
+    }
+
+    /// Decodes a buffer holding a sequence of length-delimited messages
+    ///
+    /// An empty buffer decodes into no messages.
+    pub fn decode_delimited<T: Message + Default>(data: &[u8]) -> Result<Vec<T>, ProtoError> {
+        iter_delimited(data).collect()
+    }
+
+    /// Lazily decodes a buffer holding a sequence of length-delimited messages
+    ///
+    /// The iterator stops after the first error.
+    pub fn iter_delimited<T: Message + Default>(data: &[u8]) -> DelimitedMessages<'_, T> {
+        DelimitedMessages {
+            remaining: data,
+            failed: false,
+            _message: PhantomData,
+        }
+    }
+
+    /// Decodes a buffer holding either one plain message or length-delimited messages
+    ///
+    /// A plain message is tried first, and only accepted if every byte belongs
+    /// to one of its fields, as a delimited buffer can otherwise decode into a
+    /// bogus message. An empty buffer decodes into no messages.
+    pub fn decode_delimited_or_single<T: Message + Default>(
+        data: &[u8],
+    ) -> Result<Vec<T>, ProtoError> {
+        if data.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        if let Ok(message) = T::decode(data) {
+            if message.encoded_len() == data.len() {
+                return Ok(vec![message]);
+            }
+        }
+        decode_delimited(data)
+    }
+
+    /// Iterator returned by [`iter_delimited`]
+    pub struct DelimitedMessages<'a, T> {
+        remaining: &'a [u8],
+        failed: bool,
+        _message: PhantomData<T>,
+    }
+
+    impl<T: Message + Default> Iterator for DelimitedMessages<'_, T> {
+        type Item = Result<T, ProtoError>;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            if self.failed || self.remaining.is_empty() {
+                return None;
+            }
+
+            let message = T::decode_length_delimited(&mut self.remaining);
+            if message.is_err() {
+                self.failed = true;
+            }
+            Some(message.map_err(ProtoError::from))
+        }
+    }
 }
 
 pub mod callbacks {
@@ -159,7 +346,7 @@ pub mod callbacks {
     /// use proton_sdk_sys::data::ByteArray;
     /// use std::ffi::c_void;
     /// use proton_sdk_sys::protobufs::callbacks::handle_protobuf_response;
-    /// use proton_sdk_sys::protobufs::SessionTokens;
+    /// use proton_sdk_sys::protobufs::account::SessionTokens;
     ///
     /// extern "C" fn session_success_callback(_state: *const c_void, response: ByteArray) {
     ///     handle_protobuf_response(&response, |tokens: SessionTokens| {
@@ -179,8 +366,8 @@ pub mod callbacks {
     }
 
     /// Helper for handling protobuf errors in failure callbacks
-    pub fn handle_protobuf_error(data: &ByteArray) -> Option<super::Error> {
-        super::Error::from_byte_array(data).ok()
+    pub fn handle_protobuf_error(data: &ByteArray) -> Option<super::account::Error> {
+        super::account::Error::from_byte_array(data).ok()
     }
 
     /// Generic callback wrapper that decodes protobuf and calls user function
@@ -213,27 +400,249 @@ pub mod callbacks {
 }
 
 /// Validation helpers for protobuf messages
-pub mod validation {
+pub mod validation;
+
+#[cfg(test)]
+mod tests {
+    use super::account::{IntResponse, SessionTokens};
+    use super::drive::{Share, ShareId};
+    use super::helpers::{
+        decode_delimited, decode_delimited_or_single, iter_delimited, try_decode_as,
+        DecodeConfidence,
+    };
     use super::*;
 
-    /// Validates that required fields are present
-    pub trait Validate {
-        type Error;
-        fn validate(&self) -> Result<(), Self::Error>;
-    }
-
-    // Example validation for SessionBeginRequest
-    // impl Validate for SessionBeginRequest {
-    //     type Error = &'static str;
-
-    //     fn validate(&self) -> Result<(), Self::Error> {
-    //         if self.username.is_empty() {
-    //             return Err("Username is required");
-    //         }
-    //         if self.password.is_empty() {
-    //             return Err("Password is required");
-    //         }
-    //         Ok(())
-    //     }
-    // }
+    #[test]
+    fn strict_decoding_rejects_other_messages() {
+        let tokens = SessionTokens {
+            access_token: "access".to_string(),
+            refresh_token: "refresh".to_string(),
+        }
+        .encode_to_vec();
+
+        // field 1 has the wrong wire type, and field 2 is unknown to IntResponse
+        assert!(IntResponse::from_bytes_strict(&tokens).is_err());
+
+        let refresh_only = SessionTokens {
+            refresh_token: "refresh".to_string(),
+            ..Default::default()
+        }
+        .encode_to_vec();
+        assert!(IntResponse::from_bytes(&refresh_only).is_ok());
+        assert!(matches!(
+            IntResponse::from_bytes_strict(&refresh_only),
+            Err(ProtoError::UnknownFields { known: 0, .. })
+        ));
+
+        // a single unknown field is enough, however small next to the known ones
+        let mut extended = IntResponse { value: i64::MAX }.encode_to_vec();
+        extended.extend_from_slice(&[0x10, 0x01]);
+        assert!(matches!(
+            IntResponse::from_bytes_strict(&extended),
+            Err(ProtoError::UnknownFields { known: 10, total: 12 })
+        ));
+    }
+
+    #[test]
+    fn strict_decoding_accepts_matching_messages() {
+        let response = IntResponse { value: 42 }.encode_to_vec();
+        assert_eq!(IntResponse::from_bytes_strict(&response).unwrap().value, 42);
+        assert_eq!(IntResponse::from_bytes_strict(&[]).unwrap().value, 0);
+    }
+
+    #[test]
+    fn messages_with_secrets_encode_into_sensitive_buffers() {
+        let tokens = SessionTokens {
+            access_token: "access".to_string(),
+            refresh_token: "refresh".to_string(),
+        };
+        let buffer = tokens.to_sensitive_proto_buffer().unwrap();
+        assert!(buffer.is_sensitive());
+        assert_eq!(SessionTokens::decode(buffer.as_byte_array().view().as_slice()).unwrap(), tokens);
+        assert!(!tokens.to_proto_buffer().unwrap().is_sensitive());
+    }
+
+    #[test]
+    fn try_decode_as_reports_confidence() {
+        let response = IntResponse { value: 7 }.encode_to_vec();
+        let (_, confidence) = try_decode_as::<IntResponse>(&response).unwrap();
+        assert_eq!(confidence, DecodeConfidence::WithFields);
+
+        let unknown = SessionTokens {
+            refresh_token: "refresh".to_string(),
+            ..Default::default()
+        }
+        .encode_to_vec();
+        let (_, confidence) = try_decode_as::<IntResponse>(&unknown).unwrap();
+        assert_eq!(confidence, DecodeConfidence::Empty);
+    }
+
+    fn share(id: &str) -> Share {
+        Share {
+            share_id: Some(ShareId { value: id.to_string() }),
+            membership_email_address: format!("{}@example.com", id),
+            ..Default::default()
+        }
+    }
+
+    fn delimited(shares: &[Share]) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        for share in shares {
+            share.encode_length_delimited(&mut buffer).unwrap();
+        }
+        buffer
+    }
+
+    #[test]
+    fn delimited_buffers_decode_every_message() {
+        assert!(decode_delimited::<Share>(&[]).unwrap().is_empty());
+
+        let single = vec![share("first")];
+        assert_eq!(decode_delimited::<Share>(&delimited(&single)).unwrap(), single);
+
+        let many = vec![share("first"), share("second"), share("third")];
+        assert_eq!(decode_delimited::<Share>(&delimited(&many)).unwrap(), many);
+    }
+
+    #[test]
+    fn delimited_iteration_stops_at_truncated_messages() {
+        let buffer = delimited(&[share("first"), share("second")]);
+        let mut messages = iter_delimited::<Share>(&buffer[..buffer.len() - 1]);
+
+        assert_eq!(messages.next().unwrap().unwrap(), share("first"));
+        assert!(messages.next().unwrap().is_err());
+        assert!(messages.next().is_none());
+    }
+
+    #[test]
+    fn plain_messages_are_tried_before_delimited_ones() {
+        let plain = share("only").encode_to_vec();
+        assert_eq!(decode_delimited_or_single::<Share>(&plain).unwrap(), vec![share("only")]);
+
+        let many = vec![share("first"), share("second")];
+        assert_eq!(decode_delimited_or_single::<Share>(&delimited(&many)).unwrap(), many);
+        assert!(decode_delimited_or_single::<Share>(&[]).unwrap().is_empty());
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod serde_tests {
+    use super::account::SessionInfo;
+    use super::drive::{
+        node_type, FileNode, FolderNode, LinkId, NodeIdentity, NodeState, NodeType, Revision,
+        Share, ShareId, VolumeId, VolumeMetadata, VolumeState,
+    };
+
+    fn round_trip<T>(message: &T) -> T
+    where
+        T: serde::Serialize + serde::de::DeserializeOwned,
+    {
+        let json = serde_json::to_string(message).unwrap();
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[test]
+    fn messages_round_trip_through_json() {
+        let file = FileNode {
+            node_identity: Some(NodeIdentity {
+                node_id: Some(LinkId { value: "node".to_string() }),
+                share_id: Some(ShareId { value: "share".to_string() }),
+                volume_id: None,
+            }),
+            name: "notes.txt".to_string(),
+            name_hash_digest: vec![1, 2, 3].into(),
+            state: NodeState::Active as i32,
+            active_revision: Some(Revision {
+                size: Some(42),
+                samples_sha256_digests: vec![vec![0xab; 32].into()],
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert_eq!(round_trip(&file), file);
+
+        let share = Share {
+            share_id: Some(ShareId { value: "share".to_string() }),
+            membership_email_address: "user@example.com".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(round_trip(&share), share);
+
+        let session = SessionInfo {
+            username: "user".to_string(),
+            scopes: vec!["full".to_string(), "drive".to_string()],
+            is_waiting_for_second_factor_code: true,
+            ..Default::default()
+        };
+        assert_eq!(round_trip(&session), session);
+    }
+
+    #[test]
+    fn identities_and_volumes_round_trip() {
+        let identity = NodeIdentity {
+            node_id: Some(LinkId { value: "node".to_string() }),
+            share_id: None,
+            volume_id: Some(VolumeId { value: "volume".to_string() }),
+        };
+        assert_eq!(round_trip(&identity), identity);
+        assert_eq!(round_trip(&NodeIdentity::default()), NodeIdentity::default());
+
+        // a file whose optional fields were all left out
+        let bare = FileNode { name: "empty".to_string(), ..Default::default() };
+        assert!(bare.node_identity.is_none() && bare.active_revision.is_none());
+        assert_eq!(round_trip(&bare), bare);
+
+        let volume = VolumeMetadata {
+            volume_id: Some(VolumeId { value: "volume".to_string() }),
+            state: VolumeState::Active as i32,
+            max_space: 5 << 30,
+            root_share_id: Some(ShareId { value: "root".to_string() }),
+        };
+        assert_eq!(round_trip(&volume), volume);
+    }
+
+    #[test]
+    fn oneofs_and_missing_fields_deserialize() {
+        let node = NodeType {
+            node_type: Some(node_type::NodeType::FolderNode(FolderNode {
+                name: "Photos".to_string(),
+                ..Default::default()
+            })),
+        };
+        assert_eq!(round_trip(&node), node);
+
+        let share: Share = serde_json::from_str(r#"{"membership_email_address":"a@b.c"}"#).unwrap();
+        assert_eq!(share.membership_email_address, "a@b.c");
+        assert!(share.share_id.is_none());
+    }
+}
+
+#[cfg(all(test, feature = "extra-derives"))]
+mod extra_derive_tests {
+    use std::collections::HashSet;
+
+    use super::account::Error;
+    use super::drive::{node_type, FolderNode, NodeType, NodeTypeList};
+
+    /// prost leaves out `Eq` and `Hash` on repeated and recursive message fields
+    #[test]
+    fn repeated_and_recursive_messages_can_be_hashed() {
+        let list = |name: &str| NodeTypeList {
+            nodes: vec![NodeType {
+                node_type: Some(node_type::NodeType::FolderNode(FolderNode {
+                    name: name.to_string(),
+                    ..Default::default()
+                })),
+            }],
+        };
+        let lists: HashSet<_> = [list("a"), list("b"), list("a")].into();
+        assert_eq!(lists.len(), 2);
+
+        let error = Error {
+            message: "outer".to_string(),
+            inner_error: Some(Box::new(Error { message: "inner".to_string(), ..Default::default() })),
+            ..Default::default()
+        };
+        assert!(HashSet::from([error.clone()]).contains(&error));
+    }
 }
@@ -0,0 +1,96 @@
+use super::drive::{FileNode, Revision, RevisionMetadata, Share, ShareMetadata};
+
+impl From<&Share> for ShareMetadata {
+    fn from(share: &Share) -> Self {
+        ShareMetadata {
+            share_id: share.share_id.clone(),
+            membership_address_id: share.membership_address_id.clone(),
+            membership_email_address: share.membership_email_address.clone(),
+        }
+    }
+}
+
+impl Share {
+    /// Returns the metadata uploads need to reference this share
+    pub fn metadata(&self) -> ShareMetadata {
+        self.into()
+    }
+}
+
+impl From<&Revision> for RevisionMetadata {
+    fn from(revision: &Revision) -> Self {
+        RevisionMetadata {
+            revision_id: revision.revision_id.clone(),
+            state: revision.state,
+            manifest_signature: revision.manifest_signature.clone(),
+            signature_email_address: revision.signature_email_address.clone(),
+            samples_sha256_digests: revision.samples_sha256_digests.clone(),
+        }
+    }
+}
+
+impl FileNode {
+    /// Returns the metadata downloads need to fetch the file's active revision
+    pub fn active_revision_metadata(&self) -> Option<RevisionMetadata> {
+        self.active_revision.as_ref().map(RevisionMetadata::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protobufs::account::AddressId;
+    use crate::protobufs::drive::{LinkId, RevisionId, RevisionState, ShareId, VolumeId};
+
+    #[test]
+    fn share_metadata_copies_the_membership() {
+        let share = Share {
+            share_id: Some(ShareId { value: "share".to_string() }),
+            membership_address_id: Some(AddressId { value: "address".to_string() }),
+            membership_email_address: "user@example.com".to_string(),
+            volume_id: Some(VolumeId { value: "volume".to_string() }),
+            root_node_id: Some(LinkId { value: "root".to_string() }),
+        };
+
+        assert_eq!(
+            share.metadata(),
+            ShareMetadata {
+                share_id: share.share_id.clone(),
+                membership_address_id: share.membership_address_id.clone(),
+                membership_email_address: share.membership_email_address.clone(),
+            }
+        );
+    }
+
+    #[test]
+    fn revision_metadata_keeps_every_shared_field() {
+        let revision = Revision {
+            revision_id: Some(RevisionId { value: "revision".to_string() }),
+            state: RevisionState::Active.into(),
+            manifest_signature: Some(vec![1, 2, 3].into()),
+            signature_email_address: Some("user@example.com".to_string()),
+            samples_sha256_digests: vec![vec![0xaa; 32].into(), vec![0xbb; 32].into()],
+            volume_id: Some(VolumeId { value: "volume".to_string() }),
+            file_id: Some(LinkId { value: "file".to_string() }),
+            size: Some(42),
+            quota_consumption: 64,
+            creation_time: 1_700_000_000,
+        };
+        let file = FileNode {
+            active_revision: Some(revision.clone()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            file.active_revision_metadata(),
+            Some(RevisionMetadata {
+                revision_id: revision.revision_id.clone(),
+                state: revision.state,
+                manifest_signature: revision.manifest_signature.clone(),
+                signature_email_address: revision.signature_email_address.clone(),
+                samples_sha256_digests: revision.samples_sha256_digests.clone(),
+            })
+        );
+        assert_eq!(FileNode::default().active_revision_metadata(), None);
+    }
+}
@@ -0,0 +1,190 @@
+use std::{fmt, str::FromStr};
+
+use super::{account::OperationType, drive::VolumeEventType};
+
+/// A string that doesn't name a variant of a protobuf enum
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Unknown {type_name} '{value}'")]
+pub struct ParseEnumError {
+    pub type_name: &'static str,
+    pub value: String,
+}
+
+/// Parses `s` against the short names of `variants`, then their protobuf names,
+/// ignoring case and treating `-` as `_`
+fn parse_variant<T: Copy>(
+    s: &str,
+    type_name: &'static str,
+    variants: &[(&str, T)],
+    from_str_name: fn(&str) -> Option<T>,
+) -> Result<T, ParseEnumError> {
+    let normalized = s.trim().replace('-', "_");
+    variants
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(&normalized))
+        .map(|(_, variant)| *variant)
+        .or_else(|| from_str_name(&normalized.to_ascii_uppercase()))
+        .ok_or_else(|| ParseEnumError {
+            type_name,
+            value: s.to_string(),
+        })
+}
+
+impl OperationType {
+    /// Returns the short lowercase name, as used by [`Display`](fmt::Display)
+    pub fn name(&self) -> &'static str {
+        match self {
+            OperationType::Invalid => "invalid",
+            OperationType::Download => "download",
+            OperationType::FileUpload => "file_upload",
+            OperationType::RevisionUpload => "revision_upload",
+        }
+    }
+}
+
+impl fmt::Display for OperationType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for OperationType {
+    type Err = ParseEnumError;
+
+    /// Accepts the short names, `upload` for a file upload and the protobuf names
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_variant(
+            s,
+            "operation type",
+            &[
+                ("invalid", OperationType::Invalid),
+                ("download", OperationType::Download),
+                ("upload", OperationType::FileUpload),
+                ("file_upload", OperationType::FileUpload),
+                ("revision_upload", OperationType::RevisionUpload),
+            ],
+            OperationType::from_str_name,
+        )
+    }
+}
+
+impl VolumeEventType {
+    /// Returns the short lowercase name, as used by [`Display`](fmt::Display)
+    pub fn name(&self) -> &'static str {
+        match self {
+            VolumeEventType::Delete => "delete",
+            VolumeEventType::Create => "create",
+            VolumeEventType::Update => "update",
+            VolumeEventType::UpdateMetadata => "update_metadata",
+        }
+    }
+
+    /// Checks if the event removed a node from the share view, which needs the
+    /// local copy to be deleted
+    ///
+    /// Moves out of the share view are reported as deletions too, while renames
+    /// and moves inside it are metadata updates.
+    pub fn is_destructive(&self) -> bool {
+        match self {
+            VolumeEventType::Delete => true,
+            VolumeEventType::Create | VolumeEventType::Update | VolumeEventType::UpdateMetadata => {
+                false
+            }
+        }
+    }
+}
+
+impl fmt::Display for VolumeEventType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for VolumeEventType {
+    type Err = ParseEnumError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_variant(
+            s,
+            "volume event type",
+            &[
+                ("delete", VolumeEventType::Delete),
+                ("create", VolumeEventType::Create),
+                ("update", VolumeEventType::Update),
+                ("update_metadata", VolumeEventType::UpdateMetadata),
+            ],
+            VolumeEventType::from_str_name,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn operation_types_parse_case_insensitively() {
+        let cases = [
+            ("download", Some(OperationType::Download)),
+            ("Upload", Some(OperationType::FileUpload)),
+            ("FILE_UPLOAD", Some(OperationType::FileUpload)),
+            ("revision-upload", Some(OperationType::RevisionUpload)),
+            ("operation_type_download", Some(OperationType::Download)),
+            ("OPERATION_TYPE_REVISION_UPLOAD", Some(OperationType::RevisionUpload)),
+            ("", None),
+            ("sideload", None),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(input.parse::<OperationType>().ok(), expected, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn volume_event_types_parse_case_insensitively() {
+        let cases = [
+            ("delete", Some(VolumeEventType::Delete)),
+            (" Create ", Some(VolumeEventType::Create)),
+            ("update-metadata", Some(VolumeEventType::UpdateMetadata)),
+            ("VOLUME_EVENT_TYPE_UPDATE", Some(VolumeEventType::Update)),
+            ("moved", None),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(input.parse::<VolumeEventType>().ok(), expected, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn display_round_trips_through_from_str() {
+        for value in [0, 1, 2, 3] {
+            let operation = OperationType::try_from(value).unwrap();
+            assert_eq!(operation.to_string().parse::<OperationType>().unwrap(), operation);
+
+            let event = VolumeEventType::try_from(value).unwrap();
+            assert_eq!(event.to_string().parse::<VolumeEventType>().unwrap(), event);
+        }
+    }
+
+    #[test]
+    fn unknown_discriminants_are_errors() {
+        for value in [-1, 4, i32::MAX] {
+            assert!(OperationType::try_from(value).is_err());
+            assert!(VolumeEventType::try_from(value).is_err());
+        }
+    }
+
+    #[test]
+    fn only_deletions_are_destructive() {
+        let cases = [
+            (VolumeEventType::Delete, true),
+            (VolumeEventType::Create, false),
+            (VolumeEventType::Update, false),
+            (VolumeEventType::UpdateMetadata, false),
+        ];
+
+        for (event, destructive) in cases {
+            assert_eq!(event.is_destructive(), destructive, "{}", event);
+        }
+    }
+}
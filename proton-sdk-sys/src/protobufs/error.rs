@@ -0,0 +1,153 @@
+use std::fmt;
+
+use super::account::{Error, ErrorDomain};
+
+/// Coarse classification of SDK errors, shared by the error types of the safe wrappers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SdkErrorKind {
+    Cancelled,
+    Authentication,
+    PermissionDenied,
+    NotFound,
+    AlreadyExists,
+    InvalidRequest,
+    Network,
+    Cryptography,
+    DataIntegrity,
+    Serialization,
+    /// Any other error reported by the Proton API
+    Api,
+    Unknown,
+}
+
+impl SdkErrorKind {
+    /// Classifies an error by its domain, then its primary code
+    pub fn classify(domain: ErrorDomain, primary_code: Option<i64>) -> Self {
+        match domain {
+            ErrorDomain::SuccessfulCancellation => return SdkErrorKind::Cancelled,
+            ErrorDomain::Network | ErrorDomain::Transport => return SdkErrorKind::Network,
+            ErrorDomain::Cryptography => return SdkErrorKind::Cryptography,
+            ErrorDomain::DataIntegrity => return SdkErrorKind::DataIntegrity,
+            ErrorDomain::Serialization => return SdkErrorKind::Serialization,
+            ErrorDomain::Api | ErrorDomain::Undefined => {}
+        }
+
+        match primary_code {
+            Some(2000 | 2001 | 2061 | 5003) => SdkErrorKind::InvalidRequest,
+            Some(2011) => SdkErrorKind::PermissionDenied,
+            Some(2500) => SdkErrorKind::AlreadyExists,
+            Some(2501) => SdkErrorKind::NotFound,
+            Some(8002 | 9001 | 10013) => SdkErrorKind::Authentication,
+            Some(_) if domain == ErrorDomain::Api => SdkErrorKind::Api,
+            _ => SdkErrorKind::Unknown,
+        }
+    }
+}
+
+impl fmt::Display for SdkErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+/// Returns the symbolic name of a known Proton API primary code
+pub fn primary_code_name(code: i64) -> Option<&'static str> {
+    Some(match code {
+        2000 => "InvalidRequirements",
+        2001 => "InvalidValue",
+        2011 => "InsufficientScope",
+        2061 => "InvalidId",
+        2500 => "AlreadyExists",
+        2501 => "DoesNotExist",
+        5003 => "AppVersionBad",
+        8002 => "IncorrectPassword",
+        9001 => "HumanVerificationRequired",
+        10013 => "InvalidRefreshToken",
+        _ => return None,
+    })
+}
+
+impl Error {
+    /// Classifies the error, see [`SdkErrorKind::classify`]
+    pub fn kind(&self) -> SdkErrorKind {
+        let domain = ErrorDomain::try_from(self.domain).unwrap_or(ErrorDomain::Undefined);
+        SdkErrorKind::classify(domain, self.primary_code)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.primary_code {
+            Some(code) => match primary_code_name(code) {
+                Some(name) => write!(f, "Proton error {} ({})", code, name)?,
+                None => write!(f, "Proton error {}", code)?,
+            },
+            None if !self.r#type.is_empty() => write!(f, "Proton error ({})", self.r#type)?,
+            None => f.write_str("Proton error")?,
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.inner_error
+            .as_deref()
+            .map(|inner| inner as &(dyn std::error::Error + 'static))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_codes_are_named() {
+        let error = Error {
+            message: "Access token does not have sufficient scope".to_string(),
+            domain: ErrorDomain::Api.into(),
+            primary_code: Some(2011),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            error.to_string(),
+            "Proton error 2011 (InsufficientScope): Access token does not have sufficient scope"
+        );
+        assert_eq!(error.kind(), SdkErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    fn unknown_codes_render_the_number() {
+        let error = Error {
+            message: "Something".to_string(),
+            domain: ErrorDomain::Api.into(),
+            primary_code: Some(123456),
+            ..Default::default()
+        };
+
+        assert_eq!(error.to_string(), "Proton error 123456: Something");
+        assert_eq!(error.kind(), SdkErrorKind::Api);
+
+        let untyped = Error {
+            r#type: "IOException".to_string(),
+            message: "Disk full".to_string(),
+            domain: 42,
+            ..Default::default()
+        };
+        assert_eq!(untyped.to_string(), "Proton error (IOException): Disk full");
+        assert_eq!(untyped.kind(), SdkErrorKind::Unknown);
+    }
+
+    #[test]
+    fn domains_take_precedence_over_codes() {
+        assert_eq!(
+            SdkErrorKind::classify(ErrorDomain::Transport, Some(2501)),
+            SdkErrorKind::Network
+        );
+        assert_eq!(
+            SdkErrorKind::classify(ErrorDomain::SuccessfulCancellation, None),
+            SdkErrorKind::Cancelled
+        );
+    }
+}
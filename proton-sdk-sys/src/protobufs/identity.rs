@@ -0,0 +1,250 @@
+use std::{fmt, str::FromStr};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+
+use super::drive::{LinkId, NodeIdentity, ShareId, VolumeId};
+
+/// Marks a missing id in the compact form
+const MISSING: &str = "-";
+const SEPARATOR: char = ':';
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum CompactIdentityError {
+    #[error("Expected volume:share:node, found {0} component(s)")]
+    WrongComponentCount(usize),
+
+    #[error("The {0} id isn't valid URL-safe base64")]
+    InvalidBase64(&'static str),
+
+    #[error("The {0} id isn't valid UTF-8")]
+    InvalidUtf8(&'static str),
+}
+
+/// Builds a [`NodeIdentity`] from plain string ids
+#[derive(Debug, Clone, Default)]
+pub struct NodeIdentityBuilder {
+    identity: NodeIdentity,
+}
+
+impl NodeIdentityBuilder {
+    pub fn node_id(mut self, id: &str) -> Self {
+        self.identity.node_id = Some(LinkId { value: id.to_string() });
+        self
+    }
+
+    pub fn share_id(mut self, id: &str) -> Self {
+        self.identity.share_id = Some(ShareId { value: id.to_string() });
+        self
+    }
+
+    pub fn volume_id(mut self, id: &str) -> Self {
+        self.identity.volume_id = Some(VolumeId { value: id.to_string() });
+        self
+    }
+
+    pub fn build(self) -> NodeIdentity {
+        self.identity
+    }
+}
+
+impl NodeIdentity {
+    /// Starts building an identity
+    pub fn builder() -> NodeIdentityBuilder {
+        NodeIdentityBuilder::default()
+    }
+
+    /// Fills the ids missing (or empty) in `self` from `fallback`
+    ///
+    /// Ids already present are kept, even when `fallback` has different ones.
+    pub fn merge(mut self, fallback: &NodeIdentity) -> NodeIdentity {
+        if !has_value(self.node_id.as_ref().map(|id| id.value.as_str())) {
+            self.node_id = fallback.node_id.clone();
+        }
+        if !has_value(self.share_id.as_ref().map(|id| id.value.as_str())) {
+            self.share_id = fallback.share_id.clone();
+        }
+        if !has_value(self.volume_id.as_ref().map(|id| id.value.as_str())) {
+            self.volume_id = fallback.volume_id.clone();
+        }
+        self
+    }
+
+    /// Returns a copy with the ids missing (or empty) taken from `parent`, see [`NodeIdentity::merge`]
+    ///
+    /// Unlike a share or volume id, a node id taken from the parent names the parent,
+    /// so check the node id first when completing the identity of a child.
+    pub fn with_fallback(&self, parent: &NodeIdentity) -> NodeIdentity {
+        self.clone().merge(parent)
+    }
+
+    /// Checks if the node, share and volume ids are all present and non-empty
+    pub fn is_complete(&self) -> bool {
+        has_value(self.node_id.as_ref().map(|id| id.value.as_str()))
+            && has_value(self.share_id.as_ref().map(|id| id.value.as_str()))
+            && has_value(self.volume_id.as_ref().map(|id| id.value.as_str()))
+    }
+
+    /// Serializes the identity as `volume:share:node`
+    ///
+    /// Each id is URL-safe base64 without padding, and missing (or empty) ids are
+    /// written as `-`, so the result can be used as a command line argument or
+    /// URL fragment as-is.
+    pub fn to_compact_string(&self) -> String {
+        format!(
+            "{}{SEPARATOR}{}{SEPARATOR}{}",
+            encode_component(self.volume_id.as_ref().map(|id| id.value.as_str())),
+            encode_component(self.share_id.as_ref().map(|id| id.value.as_str())),
+            encode_component(self.node_id.as_ref().map(|id| id.value.as_str())),
+        )
+    }
+
+    /// Parses an identity written by [`NodeIdentity::to_compact_string`]
+    pub fn parse_compact(s: &str) -> Result<Self, CompactIdentityError> {
+        let components: Vec<&str> = s.trim().split(SEPARATOR).collect();
+        let [volume, share, node] = components[..] else {
+            return Err(CompactIdentityError::WrongComponentCount(components.len()));
+        };
+
+        Ok(Self {
+            volume_id: decode_component(volume, "volume")?.map(|value| VolumeId { value }),
+            share_id: decode_component(share, "share")?.map(|value| ShareId { value }),
+            node_id: decode_component(node, "node")?.map(|value| LinkId { value }),
+        })
+    }
+}
+
+fn has_value(value: Option<&str>) -> bool {
+    value.is_some_and(|value| !value.is_empty())
+}
+
+fn encode_component(value: Option<&str>) -> String {
+    match value {
+        Some(value) if !value.is_empty() => URL_SAFE_NO_PAD.encode(value),
+        _ => MISSING.to_string(),
+    }
+}
+
+fn decode_component(
+    component: &str,
+    name: &'static str,
+) -> Result<Option<String>, CompactIdentityError> {
+    if component == MISSING || component.is_empty() {
+        return Ok(None);
+    }
+
+    let bytes = URL_SAFE_NO_PAD
+        .decode(component)
+        .map_err(|_| CompactIdentityError::InvalidBase64(name))?;
+    String::from_utf8(bytes)
+        .map(Some)
+        .map_err(|_| CompactIdentityError::InvalidUtf8(name))
+}
+
+impl fmt::Display for NodeIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_compact_string())
+    }
+}
+
+impl FromStr for NodeIdentity {
+    type Err = CompactIdentityError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse_compact(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn identity(volume: Option<String>, share: Option<String>, node: Option<String>) -> NodeIdentity {
+        NodeIdentity {
+            volume_id: volume.map(|value| VolumeId { value }),
+            share_id: share.map(|value| ShareId { value }),
+            node_id: node.map(|value| LinkId { value }),
+        }
+    }
+
+    #[test]
+    fn missing_ids_use_a_marker() {
+        let partial = identity(None, Some("share==".to_string()), Some("a/b+c".to_string()));
+        let compact = partial.to_compact_string();
+
+        assert!(compact.starts_with("-:"));
+        assert!(!compact.contains(['/', '+', '=']));
+        assert_eq!(compact.parse::<NodeIdentity>().unwrap(), partial);
+    }
+
+    #[test]
+    fn malformed_strings_are_rejected() {
+        assert_eq!(
+            NodeIdentity::parse_compact("abc:def"),
+            Err(CompactIdentityError::WrongComponentCount(2))
+        );
+        assert_eq!(
+            NodeIdentity::parse_compact("-:-:***"),
+            Err(CompactIdentityError::InvalidBase64("node"))
+        );
+    }
+
+    #[test]
+    fn builder_and_merge_fill_missing_ids() {
+        let child = NodeIdentity::builder().node_id("child").build();
+        let parent = NodeIdentity::builder()
+            .node_id("parent")
+            .share_id("share")
+            .volume_id("volume")
+            .build();
+        assert!(!child.is_complete());
+        assert!(parent.is_complete());
+
+        let merged = child.merge(&parent);
+        assert!(merged.is_complete());
+        assert_eq!(merged, identity(Some("volume".into()), Some("share".into()), Some("child".into())));
+
+        let empty_share = NodeIdentity::builder().node_id("child").share_id("").build();
+        assert_eq!(empty_share.merge(&parent).share_id, parent.share_id);
+    }
+
+    #[test]
+    fn every_combination_of_missing_ids_is_completed_from_the_parent() {
+        let parent = identity(Some("volume".into()), Some("share".into()), Some("parent".into()));
+        for missing in 0..8u8 {
+            let id = |bit: u8, value: &str| (missing & bit == 0).then(|| value.to_string());
+            let child = identity(id(1, "own volume"), id(2, "own share"), id(4, "child"));
+            assert_eq!(child.is_complete(), missing == 0, "missing {:03b}", missing);
+
+            let completed = child.with_fallback(&parent);
+            assert!(completed.is_complete(), "missing {:03b}", missing);
+            let expected = |bit: u8, own: &str, inherited: &str| {
+                Some(if missing & bit == 0 { own } else { inherited }.to_string())
+            };
+            assert_eq!(
+                completed,
+                identity(expected(1, "own volume", "volume"), expected(2, "own share", "share"), expected(4, "child", "parent")),
+                "missing {:03b}",
+                missing
+            );
+        }
+
+        let empty = NodeIdentity::builder().node_id("").share_id("").volume_id("").build();
+        assert!(!empty.is_complete());
+        assert_eq!(empty.with_fallback(&parent), parent);
+        assert!(!NodeIdentity::default().with_fallback(&NodeIdentity::default()).is_complete());
+    }
+
+    proptest! {
+        #[test]
+        fn compact_strings_round_trip(
+            volume in proptest::option::of("[^\\x00]{1,40}"),
+            share in proptest::option::of("[^\\x00]{1,40}"),
+            node in proptest::option::of("[^\\x00]{1,40}"),
+        ) {
+            let original = identity(volume, share, node);
+            let parsed: NodeIdentity = original.to_string().parse().unwrap();
+            prop_assert_eq!(parsed, original);
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use chrono::{SecondsFormat, Utc};
+use uuid::Uuid;
+
+use super::account::{OperationIdentifier, OperationType};
+
+impl OperationIdentifier {
+    /// Creates an identifier for a new operation, with a random v4 uuid and the
+    /// current time as an RFC 3339 UTC timestamp
+    pub fn new(operation_type: OperationType) -> Self {
+        Self {
+            r#type: operation_type.into(),
+            identifier: Uuid::new_v4().to_string(),
+            timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+        }
+    }
+
+    /// Creates an identifier for a file download
+    pub fn download() -> Self {
+        Self::new(OperationType::Download)
+    }
+
+    /// Creates an identifier for a new file upload
+    pub fn upload() -> Self {
+        Self::new(OperationType::FileUpload)
+    }
+
+    /// Creates an identifier for a revision upload to an existing file
+    pub fn revision_upload() -> Self {
+        Self::new(OperationType::RevisionUpload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::DateTime;
+    use prost::Message;
+
+    #[test]
+    fn constructors_set_the_operation_type() {
+        assert_eq!(OperationIdentifier::download().r#type(), OperationType::Download);
+        assert_eq!(OperationIdentifier::upload().r#type(), OperationType::FileUpload);
+        assert_eq!(
+            OperationIdentifier::revision_upload().r#type(),
+            OperationType::RevisionUpload
+        );
+    }
+
+    #[test]
+    fn timestamps_are_rfc3339_utc() {
+        let operation = OperationIdentifier::download();
+        let timestamp = DateTime::parse_from_rfc3339(&operation.timestamp).unwrap();
+
+        assert_eq!(timestamp.offset().local_minus_utc(), 0);
+        assert!(operation.timestamp.ends_with('Z'));
+        assert!((Utc::now() - timestamp.with_timezone(&Utc)).num_seconds() < 5);
+    }
+
+    #[test]
+    fn types_survive_encoding() {
+        for operation_type in [OperationType::Download, OperationType::FileUpload, OperationType::RevisionUpload] {
+            let operation = OperationIdentifier::new(operation_type);
+            let decoded = OperationIdentifier::decode(operation.encode_to_vec().as_slice()).unwrap();
+
+            assert_eq!(decoded.r#type(), operation_type);
+            assert_eq!(decoded, operation);
+            assert!(DateTime::parse_from_rfc3339(&decoded.timestamp).is_ok());
+        }
+    }
+
+    #[test]
+    fn identifiers_are_unique_v4_uuids() {
+        let first = Uuid::parse_str(&OperationIdentifier::upload().identifier).unwrap();
+        let second = Uuid::parse_str(&OperationIdentifier::upload().identifier).unwrap();
+
+        assert_eq!(first.get_version_num(), 4);
+        assert_ne!(first, second);
+    }
+}
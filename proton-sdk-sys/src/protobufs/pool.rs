@@ -0,0 +1,166 @@
+use std::{
+    cell::RefCell,
+    ops::{Deref, DerefMut},
+};
+
+use bytes::BytesMut;
+use prost::Message;
+
+use super::{ProtoBuffer, ProtoError};
+use crate::data::ByteArray;
+
+/// Buffers kept per thread, enough for the few encodes in flight on a thread
+const MAX_POOLED_BUFFERS: usize = 8;
+/// Buffers grown past this are dropped instead of pooled, so one huge message
+/// doesn't pin its memory for the lifetime of the thread
+const MAX_POOLED_CAPACITY: usize = 64 * 1024;
+
+thread_local! {
+    static POOL: RefCell<Vec<BytesMut>> = const { RefCell::new(Vec::new()) };
+}
+
+impl ProtoBuffer {
+    /// Encodes a protobuf message into `buffer`, replacing its contents
+    ///
+    /// Reuses the buffer's allocation when it is large enough.
+    pub fn encode_into<T: Message>(message: &T, buffer: &mut BytesMut) -> Result<(), ProtoError> {
+        buffer.clear();
+        buffer.reserve(message.encoded_len());
+        message.encode(buffer)?;
+        Ok(())
+    }
+}
+
+/// A thread-local pool of encode buffers for hot paths
+///
+/// # Example
+/// ```rust
+/// use proton_sdk_sys::protobufs::{
+///     drive::{LinkId, NodeIdentity},
+///     ProtoBufferPool,
+/// };
+///
+/// let identity = NodeIdentity {
+///     node_id: Some(LinkId { value: "node".to_string() }),
+///     ..Default::default()
+/// };
+///
+/// let buffer = ProtoBufferPool::encode(&identity).unwrap();
+/// assert!(!buffer.is_empty());
+/// // the buffer goes back to the pool when dropped
+/// ```
+pub struct ProtoBufferPool;
+
+impl ProtoBufferPool {
+    /// Takes an empty buffer from the current thread's pool, or allocates one
+    pub fn get() -> PooledBuffer {
+        let buffer = POOL
+            .with(|pool| pool.borrow_mut().pop())
+            .unwrap_or_default();
+        PooledBuffer { buffer }
+    }
+
+    /// Encodes a message into a pooled buffer
+    pub fn encode<T: Message>(message: &T) -> Result<PooledBuffer, ProtoError> {
+        let mut buffer = Self::get();
+        ProtoBuffer::encode_into(message, &mut buffer)?;
+        Ok(buffer)
+    }
+
+    /// Returns the number of idle buffers in the current thread's pool
+    pub fn idle() -> usize {
+        POOL.with(|pool| pool.borrow().len())
+    }
+}
+
+/// An encode buffer that returns to the pool of the thread dropping it
+pub struct PooledBuffer {
+    buffer: BytesMut,
+}
+
+impl PooledBuffer {
+    /// Gets the ByteArray for FFI calls, valid while the buffer is alive and unchanged
+    pub fn as_byte_array(&self) -> ByteArray {
+        ByteArray::from_slice(&self.buffer)
+    }
+
+    /// Gets the raw bytes
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buffer
+    }
+}
+
+impl Deref for PooledBuffer {
+    type Target = BytesMut;
+
+    fn deref(&self) -> &Self::Target {
+        &self.buffer
+    }
+}
+
+impl DerefMut for PooledBuffer {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.buffer
+    }
+}
+
+impl Drop for PooledBuffer {
+    fn drop(&mut self) {
+        let mut buffer = std::mem::take(&mut self.buffer);
+        if buffer.capacity() > MAX_POOLED_CAPACITY {
+            return;
+        }
+        buffer.clear();
+
+        // the pool is gone when a buffer is dropped during thread teardown
+        let _ = POOL.try_with(|pool| {
+            let mut pool = pool.borrow_mut();
+            if pool.len() < MAX_POOLED_BUFFERS {
+                pool.push(buffer);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protobufs::drive::{LinkId, NodeIdentity};
+
+    fn identity(id: &str) -> NodeIdentity {
+        NodeIdentity {
+            node_id: Some(LinkId { value: id.to_string() }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn encode_into_replaces_the_contents() {
+        let mut buffer = BytesMut::from(&b"stale data"[..]);
+        ProtoBuffer::encode_into(&identity("node"), &mut buffer).unwrap();
+
+        assert_eq!(&buffer[..], identity("node").encode_to_vec().as_slice());
+    }
+
+    #[test]
+    fn buffers_are_reused_on_the_same_thread() {
+        let first = ProtoBufferPool::encode(&identity("first")).unwrap();
+        let pointer = first.as_ptr();
+        drop(first);
+        assert!(ProtoBufferPool::idle() >= 1);
+
+        let second = ProtoBufferPool::encode(&identity("second")).unwrap();
+        assert_eq!(second.as_ptr(), pointer);
+        assert_eq!(second.as_bytes(), identity("second").encode_to_vec().as_slice());
+    }
+
+    #[test]
+    fn oversized_buffers_are_not_pooled() {
+        let idle = ProtoBufferPool::idle();
+        let mut buffer = ProtoBufferPool::get();
+        buffer.reserve(MAX_POOLED_CAPACITY * 2);
+        drop(buffer);
+
+        assert!(ProtoBufferPool::idle() <= idle);
+    }
+}
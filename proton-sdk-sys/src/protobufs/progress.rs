@@ -0,0 +1,90 @@
+use super::drive::ProgressUpdate;
+
+const UNITS: [&str; 7] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+
+impl ProgressUpdate {
+    /// Returns the completed share of the transfer, between 0 and 1
+    ///
+    /// 0 while the total isn't known yet, and clamped to 1 if the SDK reports
+    /// more bytes than the total.
+    pub fn fraction(&self) -> f64 {
+        if self.bytes_in_total <= 0 {
+            return 0.0;
+        }
+        (self.bytes_completed.max(0) as f64 / self.bytes_in_total as f64).min(1.0)
+    }
+
+    /// Checks if every byte of a known total has been transferred
+    pub fn is_complete(&self) -> bool {
+        self.bytes_in_total > 0 && self.bytes_completed >= self.bytes_in_total
+    }
+
+    /// Formats the progress as `123.4 MiB / 2.0 GiB (6%)`
+    pub fn human(&self) -> String {
+        format!(
+            "{} / {} ({}%)",
+            human_bytes(self.bytes_completed),
+            human_bytes(self.bytes_in_total),
+            (self.fraction() * 100.0).floor() as u32
+        )
+    }
+}
+
+/// Formats a byte count with a binary unit, as `123.4 MiB`
+pub fn human_bytes(bytes: i64) -> String {
+    let bytes = bytes.max(0);
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn progress(bytes_completed: i64, bytes_in_total: i64) -> ProgressUpdate {
+        ProgressUpdate {
+            bytes_completed,
+            bytes_in_total,
+        }
+    }
+
+    #[test]
+    fn zero_totals_report_no_progress() {
+        assert_eq!(progress(0, 0).fraction(), 0.0);
+        assert_eq!(progress(10, 0).fraction(), 0.0);
+        assert!(!progress(0, 0).is_complete());
+        assert_eq!(progress(0, 0).human(), "0 B / 0 B (0%)");
+    }
+
+    #[test]
+    fn overshooting_totals_are_clamped() {
+        let update = progress(150, 100);
+        assert_eq!(update.fraction(), 1.0);
+        assert!(update.is_complete());
+        assert_eq!(update.human(), "150 B / 100 B (100%)");
+    }
+
+    #[test]
+    fn partial_progress_is_formatted_in_binary_units() {
+        let update = progress(129_394_278, 2 * 1024 * 1024 * 1024);
+        assert!(!update.is_complete());
+        assert_eq!(update.human(), "123.4 MiB / 2.0 GiB (6%)");
+    }
+
+    #[test]
+    fn huge_values_do_not_overflow() {
+        let update = progress(i64::MAX / 2, i64::MAX);
+        assert!((update.fraction() - 0.5).abs() < 1e-9);
+        assert_eq!(update.human(), "4.0 EiB / 8.0 EiB (50%)");
+        assert!(progress(i64::MAX, i64::MAX).is_complete());
+    }
+}
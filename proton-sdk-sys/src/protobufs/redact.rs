@@ -0,0 +1,199 @@
+//! Redacted representations of the messages carrying session tokens
+//!
+//! `SessionInfo` and `SessionTokens` don't get prost's derived `Debug`, which
+//! would print the tokens, their `Debug` goes through the adapters below. The
+//! other messages with `Sensitive` fields in build.rs get a generated `Debug`
+//! printing those fields as a [`Secret`].
+
+use std::fmt;
+
+use sha2::{Digest, Sha256};
+
+use super::account::{PasswordMode, SessionInfo, SessionTokens};
+
+/// Shows a secret as its length and the start of its SHA-256, enough to tell
+/// tokens apart in logs
+pub(super) struct Secret<'a> {
+    secret: &'a [u8],
+    unit: &'static str,
+}
+
+impl<'a> Secret<'a> {
+    pub(super) fn text(text: &'a str) -> Self {
+        Self { secret: text.as_bytes(), unit: "chars" }
+    }
+
+    pub(super) fn bytes(bytes: &'a [u8]) -> Self {
+        Self { secret: bytes, unit: "bytes" }
+    }
+}
+
+impl fmt::Debug for Secret<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.secret.is_empty() {
+            return f.write_str("<empty>");
+        }
+        let digest = Sha256::digest(self.secret);
+        write!(
+            f,
+            "<redacted, {} {}, sha256:{:02x}{:02x}{:02x}{:02x}>",
+            self.secret.len(),
+            self.unit,
+            digest[0],
+            digest[1],
+            digest[2],
+            digest[3]
+        )
+    }
+}
+
+/// A `SessionInfo` that is safe to log
+#[derive(Clone, Copy)]
+pub struct RedactedSessionInfo<'a>(&'a SessionInfo);
+
+impl fmt::Debug for RedactedSessionInfo<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let info = self.0;
+        f.debug_struct("SessionInfo")
+            .field("session_id", &info.session_id.as_ref().map(|id| &id.value))
+            .field("username", &info.username)
+            .field("user_id", &info.user_id.as_ref().map(|id| &id.value))
+            .field("access_token", &Secret::text(&info.access_token))
+            .field("refresh_token", &Secret::text(&info.refresh_token))
+            .field("scopes", &info.scopes)
+            .field(
+                "is_waiting_for_second_factor_code",
+                &info.is_waiting_for_second_factor_code,
+            )
+            .field(
+                "password_mode",
+                &PasswordMode::try_from(info.password_mode).map_err(|_| info.password_mode),
+            )
+            .finish()
+    }
+}
+
+impl fmt::Display for RedactedSessionInfo<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "session of {} ({} scopes, access token {:?})",
+            self.0.username,
+            self.0.scopes.len(),
+            Secret::text(&self.0.access_token)
+        )
+    }
+}
+
+/// A `SessionTokens` that is safe to log
+#[derive(Clone, Copy)]
+pub struct RedactedSessionTokens<'a>(&'a SessionTokens);
+
+impl fmt::Debug for RedactedSessionTokens<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SessionTokens")
+            .field("access_token", &Secret::text(&self.0.access_token))
+            .field("refresh_token", &Secret::text(&self.0.refresh_token))
+            .finish()
+    }
+}
+
+impl SessionInfo {
+    /// Returns a view of the session info that hides the tokens when formatted
+    pub fn redacted(&self) -> RedactedSessionInfo<'_> {
+        RedactedSessionInfo(self)
+    }
+}
+
+impl SessionTokens {
+    /// Returns a view of the tokens that hides them when formatted
+    pub fn redacted(&self) -> RedactedSessionTokens<'_> {
+        RedactedSessionTokens(self)
+    }
+}
+
+impl fmt::Debug for SessionInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.redacted(), f)
+    }
+}
+
+impl fmt::Debug for SessionTokens {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.redacted(), f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::account::{SessionBeginRequest, SessionResumeRequest};
+    use super::super::drive::NodeKeysRegistrationRequest;
+    use super::*;
+
+    const ACCESS_TOKEN: &str = "access-token-that-must-not-leak";
+    const REFRESH_TOKEN: &str = "refresh-token-that-must-not-leak";
+
+    #[test]
+    fn debug_output_hides_tokens() {
+        let info = SessionInfo {
+            username: "user".to_string(),
+            access_token: ACCESS_TOKEN.to_string(),
+            refresh_token: REFRESH_TOKEN.to_string(),
+            scopes: vec!["full".to_string()],
+            ..Default::default()
+        };
+
+        for output in [
+            format!("{:?}", info),
+            format!("{:#?}", info.redacted()),
+            info.redacted().to_string(),
+        ] {
+            assert!(!output.contains(ACCESS_TOKEN), "{}", output);
+            assert!(!output.contains(REFRESH_TOKEN), "{}", output);
+        }
+        assert!(format!("{:?}", info).contains(&format!("{} chars", ACCESS_TOKEN.len())));
+
+        let tokens = SessionTokens {
+            access_token: ACCESS_TOKEN.to_string(),
+            refresh_token: String::new(),
+        };
+        let output = format!("{:?}", tokens);
+        assert!(!output.contains(ACCESS_TOKEN));
+        assert!(output.contains("<empty>"));
+    }
+
+    #[test]
+    fn generated_debug_redacts_sensitive_fields() {
+        let request = SessionBeginRequest {
+            username: "user".to_string(),
+            password: "password-that-must-not-leak".to_string(),
+            two_factor_code: Some("123456".to_string()),
+            options: None,
+        };
+        let output = format!("{:?}", request);
+        assert!(!output.contains("password-that-must-not-leak"), "{}", output);
+        assert!(!output.contains("123456"), "{}", output);
+        assert!(output.contains("username: \"user\""), "{}", output);
+        assert!(output.contains("two_factor_code: Some(<redacted, 6 chars"), "{}", output);
+
+        let keys = NodeKeysRegistrationRequest {
+            node_key_raw_unlocked_data: vec![7; 32].into(),
+            hash_key_raw_unlocked_data: None,
+            ..Default::default()
+        };
+        let output = format!("{:?}", keys);
+        assert!(output.contains("node_key_raw_unlocked_data: <redacted, 32 bytes"), "{}", output);
+        assert!(output.contains("hash_key_raw_unlocked_data: None"), "{}", output);
+
+        let resume = SessionResumeRequest { password_mode: PasswordMode::Dual as i32, ..Default::default() };
+        assert!(format!("{:?}", resume).contains("password_mode: Ok(Dual)"));
+    }
+
+    #[test]
+    fn fingerprints_tell_tokens_apart() {
+        let first = format!("{:?}", Secret::text(ACCESS_TOKEN));
+        let second = format!("{:?}", Secret::text(REFRESH_TOKEN));
+        assert_ne!(first, second);
+        assert_eq!(first, format!("{:?}", Secret::text(ACCESS_TOKEN)));
+    }
+}
@@ -0,0 +1,333 @@
+use std::{fmt, path::Path};
+
+use super::account::{SessionBeginRequest, SessionResumeRequest};
+use super::drive::{
+    FileDownloadRequest, FileUploadRequest, FileUploaderCreationRequest, NodeIdentity,
+    ProtonDriveClientCreateRequest,
+};
+
+/// Validates that required fields are present
+pub trait Validate {
+    type Error;
+    fn validate(&self) -> Result<(), Self::Error>;
+}
+
+/// A request field the native SDK would reject
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldViolation {
+    pub field: &'static str,
+    pub reason: &'static str,
+}
+
+impl fmt::Display for FieldViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "`{}`: {}", self.field, self.reason)
+    }
+}
+
+/// Every field of a request the native SDK would reject, in the order of the message
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub struct ValidationError {
+    pub violations: Vec<FieldViolation>,
+}
+
+impl ValidationError {
+    pub fn new(field: &'static str, reason: &'static str) -> Self {
+        Self { violations: vec![FieldViolation { field, reason }] }
+    }
+
+    /// The names of the rejected fields
+    pub fn fields(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.violations.iter().map(|violation| violation.field)
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let plural = if self.violations.len() == 1 { "" } else { "s" };
+        write!(f, "Invalid request field{} ", plural)?;
+        for (i, violation) in self.violations.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{}", violation)?;
+        }
+        Ok(())
+    }
+}
+
+/// Collects the violations of a request, so all of them are reported at once
+#[derive(Default)]
+struct Violations(Vec<FieldViolation>);
+
+impl Violations {
+    fn add(&mut self, field: &'static str, reason: &'static str) {
+        self.0.push(FieldViolation { field, reason });
+    }
+
+    fn non_empty(&mut self, field: &'static str, value: &str) -> bool {
+        let valid = !value.trim().is_empty();
+        if !valid {
+            self.add(field, "must not be empty");
+        }
+        valid
+    }
+
+    fn present<T>(&mut self, field: &'static str, value: Option<&T>) {
+        if value.is_none() {
+            self.add(field, "is missing");
+        }
+    }
+
+    fn non_negative(&mut self, field: &'static str, value: i64) {
+        if value < 0 {
+            self.add(field, "must not be negative");
+        }
+    }
+
+    fn absolute(&mut self, field: &'static str, path: &str) {
+        if self.non_empty(field, path) && !Path::new(path).is_absolute() {
+            self.add(field, "must be an absolute path");
+        }
+    }
+
+    /// Checks that an identity names a node, the share and volume ids are optional
+    fn node_identity(&mut self, field: &'static str, identity: Option<&NodeIdentity>) {
+        match identity {
+            None => self.add(field, "is missing"),
+            Some(identity) => match &identity.node_id {
+                Some(node_id) if !node_id.value.is_empty() => {}
+                _ => self.add(field, "has no node id"),
+            },
+        }
+    }
+
+    fn finish(self) -> Result<(), ValidationError> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(ValidationError { violations: self.0 })
+        }
+    }
+}
+
+impl Validate for SessionBeginRequest {
+    type Error = ValidationError;
+
+    fn validate(&self) -> Result<(), Self::Error> {
+        let mut violations = Violations::default();
+        violations.non_empty("username", &self.username);
+        violations.non_empty("password", &self.password);
+        if let Some(code) = &self.two_factor_code {
+            violations.non_empty("two_factor_code", code);
+        }
+        violations.finish()
+    }
+}
+
+impl Validate for SessionResumeRequest {
+    type Error = ValidationError;
+
+    fn validate(&self) -> Result<(), Self::Error> {
+        let mut violations = Violations::default();
+        match &self.session_id {
+            Some(session_id) => {
+                violations.non_empty("session_id", &session_id.value);
+            }
+            None => violations.add("session_id", "is missing"),
+        }
+        violations.non_empty("username", &self.username);
+        violations.present("user_id", self.user_id.as_ref());
+        violations.non_empty("access_token", &self.access_token);
+        violations.non_empty("refresh_token", &self.refresh_token);
+        violations.finish()
+    }
+}
+
+impl Validate for FileUploaderCreationRequest {
+    type Error = ValidationError;
+
+    fn validate(&self) -> Result<(), Self::Error> {
+        let mut violations = Violations::default();
+        violations.non_negative("file_size", self.file_size);
+        violations.non_negative("number_of_samples", self.number_of_samples.into());
+        violations.finish()
+    }
+}
+
+impl Validate for FileUploadRequest {
+    type Error = ValidationError;
+
+    fn validate(&self) -> Result<(), Self::Error> {
+        let mut violations = Violations::default();
+        violations.present("share_metadata", self.share_metadata.as_ref());
+        violations.node_identity("parent_folder_identity", self.parent_folder_identity.as_ref());
+        if violations.non_empty("name", &self.name) && self.name.contains('/') {
+            violations.add("name", "must not contain '/'");
+        }
+        violations.non_empty("source_file_path", &self.source_file_path);
+        violations.non_negative("last_modification_date", self.last_modification_date);
+        violations.present("operation_id", self.operation_id.as_ref());
+        violations.finish()
+    }
+}
+
+impl Validate for FileDownloadRequest {
+    type Error = ValidationError;
+
+    fn validate(&self) -> Result<(), Self::Error> {
+        let mut violations = Violations::default();
+        violations.node_identity("file_identity", self.file_identity.as_ref());
+        violations.absolute("target_file_path", &self.target_file_path);
+        violations.present("operation_id", self.operation_id.as_ref());
+        violations.finish()
+    }
+}
+
+impl Validate for ProtonDriveClientCreateRequest {
+    type Error = ValidationError;
+
+    /// The client id is optional, but an empty one is rejected
+    fn validate(&self) -> Result<(), Self::Error> {
+        let mut violations = Violations::default();
+        if let Some(client_id) = &self.client_id {
+            violations.non_empty("client_id", &client_id.value);
+        }
+        violations.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protobufs::account::{OperationIdentifier, SessionId, UserId};
+    use crate::protobufs::drive::{ClientId, LinkId, ShareMetadata};
+
+    fn fields_of<T: Validate<Error = ValidationError>>(request: &T) -> Vec<&'static str> {
+        request.validate().err().map(|e| e.fields().collect()).unwrap_or_default()
+    }
+
+    /// A change to a valid request, and the fields it breaks
+    type Case<T> = (&'static str, fn(&mut T), &'static [&'static str]);
+
+    /// Checks every case of a table
+    fn check<T>(valid: T, cases: &[Case<T>])
+    where
+        T: Validate<Error = ValidationError> + Clone,
+    {
+        assert_eq!(fields_of(&valid), Vec::<&str>::new());
+        for (case, change, fields) in cases {
+            let mut request = valid.clone();
+            change(&mut request);
+            assert_eq!(fields_of(&request), *fields, "{}", case);
+        }
+    }
+
+    fn identity() -> Option<NodeIdentity> {
+        Some(NodeIdentity {
+            node_id: Some(LinkId { value: "node".to_string() }),
+            ..Default::default()
+        })
+    }
+
+    fn absolute() -> String {
+        if cfg!(windows) { "C:\\file.bin" } else { "/tmp/file.bin" }.to_string()
+    }
+
+    #[test]
+    fn session_requests_need_credentials() {
+        let begin = SessionBeginRequest {
+            username: "user".to_string(),
+            password: "hunter2".to_string(),
+            ..Default::default()
+        };
+        check(begin, &[
+            ("blank password", |r| r.password = " ".to_string(), &["password"]),
+            ("empty two-factor code", |r| r.two_factor_code = Some(String::new()), &["two_factor_code"]),
+            ("no credentials", |r| *r = SessionBeginRequest::default(), &["username", "password"]),
+        ]);
+
+        let resume = SessionResumeRequest {
+            session_id: Some(SessionId { value: "session".to_string() }),
+            username: "user".to_string(),
+            user_id: Some(UserId { value: "user-id".to_string() }),
+            access_token: "access".to_string(),
+            refresh_token: "refresh".to_string(),
+            ..Default::default()
+        };
+        check(resume, &[
+            ("empty session id", |r| r.session_id.as_mut().unwrap().value.clear(), &["session_id"]),
+            ("no tokens", |r| {
+                r.access_token.clear();
+                r.refresh_token.clear();
+            }, &["access_token", "refresh_token"]),
+            ("nothing", |r| *r = SessionResumeRequest::default(), &[
+                "session_id", "username", "user_id", "access_token", "refresh_token",
+            ]),
+        ]);
+    }
+
+    #[test]
+    fn transfer_requests_name_the_offending_field() {
+        let download = FileDownloadRequest {
+            file_identity: identity(),
+            target_file_path: absolute(),
+            operation_id: Some(OperationIdentifier::default()),
+            ..Default::default()
+        };
+        check(download, &[
+            ("relative target", |r| r.target_file_path = "file.bin".to_string(), &["target_file_path"]),
+            ("identity without a node", |r| r.file_identity = Some(NodeIdentity::default()), &["file_identity"]),
+            ("nothing", |r| *r = FileDownloadRequest::default(), &[
+                "file_identity", "target_file_path", "operation_id",
+            ]),
+        ]);
+
+        let upload = FileUploadRequest {
+            share_metadata: Some(ShareMetadata::default()),
+            parent_folder_identity: identity(),
+            name: "notes.txt".to_string(),
+            source_file_path: absolute(),
+            operation_id: Some(OperationIdentifier::default()),
+            ..Default::default()
+        };
+        check(upload, &[
+            ("name with a slash", |r| r.name = "a/b".to_string(), &["name"]),
+            ("no parent folder", |r| r.parent_folder_identity = None, &["parent_folder_identity"]),
+            ("negative date", |r| r.last_modification_date = -1, &["last_modification_date"]),
+            ("nothing", |r| *r = FileUploadRequest::default(), &[
+                "share_metadata", "parent_folder_identity", "name", "source_file_path", "operation_id",
+            ]),
+        ]);
+
+        let uploader = FileUploaderCreationRequest { file_size: 42, number_of_samples: 0 };
+        check(uploader, &[
+            ("negative size", |r| r.file_size = -1, &["file_size"]),
+            ("negative sizes", |r| {
+                r.file_size = -1;
+                r.number_of_samples = -1;
+            }, &["file_size", "number_of_samples"]),
+        ]);
+    }
+
+    #[test]
+    fn client_id_is_optional_but_not_empty() {
+        check(ProtonDriveClientCreateRequest::default(), &[
+            ("empty client id", |r| r.client_id = Some(ClientId::default()), &["client_id"]),
+            ("client id", |r| r.client_id = Some(ClientId { value: "client".to_string() }), &[]),
+        ]);
+    }
+
+    #[test]
+    fn every_violation_is_listed() {
+        let error = SessionBeginRequest::default().validate().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid request fields `username`: must not be empty, `password`: must not be empty"
+        );
+        assert_eq!(
+            ValidationError::new("name", "must not contain '/'").to_string(),
+            "Invalid request field `name`: must not contain '/'"
+        );
+    }
+}
@@ -0,0 +1,86 @@
+//! Passwords and other secrets handed to the SDK
+
+use std::{fmt, mem};
+
+/// A password or token, zeroed when dropped with the `zeroize` feature
+///
+/// `Debug` only shows the length, so a secret can't end up in a log by accident.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct SecretBytes(String);
+
+impl SecretBytes {
+    pub fn new(secret: String) -> Self {
+        Self(secret)
+    }
+
+    /// The secret itself, to be copied as little as possible
+    pub fn expose(&self) -> &[u8] {
+        self.0.as_bytes()
+    }
+
+    /// The secret itself as text, to be copied as little as possible
+    pub fn expose_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Wipes a copy of a secret left in `value`, like the password field of a request
+    /// the SDK was given, leaving it empty
+    pub fn wipe(value: &mut String) {
+        drop(Self(mem::take(value)));
+    }
+
+    /// Moves the secret into a field of a request, see [`wipe`](Self::wipe) for
+    /// getting rid of it afterwards
+    pub fn into_inner(mut self) -> String {
+        mem::take(&mut self.0)
+    }
+}
+
+impl From<String> for SecretBytes {
+    fn from(secret: String) -> Self {
+        Self(secret)
+    }
+}
+
+impl From<&str> for SecretBytes {
+    fn from(secret: &str) -> Self {
+        Self(secret.to_string())
+    }
+}
+
+impl fmt::Debug for SecretBytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "SecretBytes(<{} bytes redacted>)", self.0.len())
+    }
+}
+
+impl Drop for SecretBytes {
+    fn drop(&mut self) {
+        #[cfg(feature = "zeroize")]
+        zeroize::Zeroize::zeroize(&mut self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secrets_are_redacted_and_wiped() {
+        let secret = SecretBytes::from("hunter2");
+        assert_eq!(format!("{:?}", secret), "SecretBytes(<7 bytes redacted>)");
+        assert_eq!((secret.expose(), secret.expose_str()), (&b"hunter2"[..], "hunter2"));
+
+        let mut field = secret.into_inner();
+        SecretBytes::wipe(&mut field);
+        assert!(field.is_empty());
+    }
+}
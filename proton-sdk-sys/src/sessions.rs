@@ -25,7 +25,7 @@ impl From<isize> for SessionHandle {
 }
 
 pub mod raw {
-    use crate::{cancellation::{self, CancellationTokenHandle}, data::*, protobufs::{FromByteArray, SessionInfo}, ProtonSDKLib};
+    use crate::{cancellation::{self, CancellationTokenHandle}, data::*, protobufs::{account::SessionInfo, FromByteArray}};
 
     use super::*;
 
@@ -58,19 +58,15 @@ pub mod raw {
         tokens_refreshed_callback: Callback,
         async_callback: AsyncCallback,
     ) -> anyhow::Result<i32> {
-        let sdk = ProtonSDKLib::instance()?;
-
-        let session_begin_fn: libloading::Symbol<
-            unsafe extern "C" fn(
-                isize,
-                ByteArray,
-                Callback,
-                BooleanCallback,
-                TwoFactorRequestedCallback,
-                Callback,
-                AsyncCallback,
-            ) -> i32,
-        > = sdk.sdk_library.get(b"session_begin")?;
+        let session_begin_fn: unsafe extern "C" fn(
+            isize,
+            ByteArray,
+            Callback,
+            BooleanCallback,
+            TwoFactorRequestedCallback,
+            Callback,
+            AsyncCallback,
+        ) -> i32 = sdk_symbol!(session_begin);
 
         let result = session_begin_fn(
             unused_handle,
@@ -109,17 +105,13 @@ pub mod raw {
         tokens_refreshed_callback: Callback,
     ) -> anyhow::Result<(i32, SessionHandle)> {
         unsafe {
-            let sdk = ProtonSDKLib::instance()?;
-
-            let session_resume_fn: libloading::Symbol<
-                unsafe extern "C" fn(
-                    ByteArray,
-                    Callback,
-                    BooleanCallback,
-                    Callback,
-                    *mut isize,
-                ) -> i32,
-            > = sdk.sdk_library.get(b"session_resume")?;
+            let session_resume_fn: unsafe extern "C" fn(
+                ByteArray,
+                Callback,
+                BooleanCallback,
+                Callback,
+                *mut isize,
+            ) -> i32 = sdk_symbol!(session_resume);
 
             let mut session_handle: isize = 0;
             let result = session_resume_fn(
@@ -155,11 +147,8 @@ pub mod raw {
         tokens_refreshed_callback: Callback,
     ) -> anyhow::Result<(i32, SessionHandle)> {
         unsafe {
-            let sdk = ProtonSDKLib::instance()?;
-
-            let session_renew_fn: libloading::Symbol<
-                unsafe extern "C" fn(isize, ByteArray, Callback, *mut isize) -> i32,
-            > = sdk.sdk_library.get(b"session_renew")?;
+            let session_renew_fn: unsafe extern "C" fn(isize, ByteArray, Callback, *mut isize) -> i32 =
+                sdk_symbol!(session_renew);
 
             let mut new_session_handle: isize = 0;
             let result = session_renew_fn(
@@ -190,11 +179,8 @@ pub mod raw {
         async_callback: AsyncCallback,
     ) -> anyhow::Result<i32> {
         unsafe {
-            let sdk = ProtonSDKLib::instance()?;
-
-            let session_end_fn: libloading::Symbol<
-                unsafe extern "C" fn(isize, AsyncCallback) -> i32,
-            > = sdk.sdk_library.get(b"session_end")?;
+            let session_end_fn: unsafe extern "C" fn(isize, AsyncCallback) -> i32 =
+                sdk_symbol!(session_end);
 
             let result = session_end_fn(session_handle.raw(), async_callback);
 
@@ -209,10 +195,7 @@ pub mod raw {
     /// * `session_handle` - Handle to the session to free
     pub unsafe fn session_free(session_handle: SessionHandle) -> anyhow::Result<()> {
         unsafe {
-            let sdk = ProtonSDKLib::instance()?;
-
-            let session_free_fn: libloading::Symbol<unsafe extern "C" fn(isize)> =
-                sdk.sdk_library.get(b"session_free")?;
+            let session_free_fn: unsafe extern "C" fn(isize) = sdk_symbol!(session_free);
 
             session_free_fn(session_handle.raw());
             Ok(())
@@ -236,11 +219,8 @@ pub mod raw {
         armored_user_key: ByteArray,
     ) -> anyhow::Result<i32> {
         unsafe {
-            let sdk = ProtonSDKLib::instance()?;
-
-            let register_key_fn: libloading::Symbol<unsafe extern "C" fn(isize, ByteArray) -> i32> =
-                sdk.sdk_library
-                    .get(b"session_register_armored_locked_user_key")?;
+            let register_key_fn: unsafe extern "C" fn(isize, ByteArray) -> i32 =
+                sdk_symbol!(session_register_armored_locked_user_key);
 
             let result = register_key_fn(session_handle.raw(), armored_user_key);
 
@@ -265,11 +245,8 @@ pub mod raw {
         request: ByteArray,
     ) -> anyhow::Result<i32> {
         unsafe {
-            let sdk = ProtonSDKLib::instance()?;
-
-            let register_keys_fn: libloading::Symbol<
-                unsafe extern "C" fn(isize, ByteArray) -> i32,
-            > = sdk.sdk_library.get(b"session_register_address_keys")?;
+            let register_keys_fn: unsafe extern "C" fn(isize, ByteArray) -> i32 =
+                sdk_symbol!(session_register_address_keys);
 
             let result = register_keys_fn(session_handle.raw(), request);
 
@@ -285,12 +262,10 @@ pub mod raw {
     /// 
     /// # Returns
     /// The `SessionInfo` protobuf
-    pub fn session_get_info(session_handle: SessionHandle, cancellation_token: CancellationTokenHandle) -> anyhow::Result<crate::protobufs::SessionInfo> {
+    pub fn session_get_info(session_handle: SessionHandle, cancellation_token: CancellationTokenHandle) -> anyhow::Result<crate::protobufs::account::SessionInfo> {
         unsafe {
-            let sdk = ProtonSDKLib::instance()?;
-            let session_get_info_fn: libloading::Symbol<
-                unsafe extern "C" fn(isize, isize, *mut ByteArray) -> i32,
-            > = sdk.sdk_library.get(b"session_get_info")?;
+            let session_get_info_fn: unsafe extern "C" fn(isize, isize, *mut ByteArray) -> i32 =
+                sdk_symbol!(session_get_info);
 
             let mut out_bytes = ByteArray::empty();
             let result = session_get_info_fn(session_handle.raw(), cancellation_token.raw(), &mut out_bytes as *mut _);
@@ -298,7 +273,8 @@ pub mod raw {
                 anyhow::bail!("session_get_info failed with code {}", result);
             }
 
-            let info = SessionInfo::from_byte_array(&out_bytes)?;
+            let out_bytes = OwnedByteArray::from_sdk(out_bytes, crate::ProtonSDKLib::instance()?.byte_array_free());
+            let info = SessionInfo::from_bytes(out_bytes.as_slice())?;
 
             Ok(info)
         }
@@ -310,11 +286,8 @@ pub mod raw {
         cancellation_token: CancellationTokenHandle
     ) -> anyhow::Result<i32> {
         unsafe {
-            let sdk = ProtonSDKLib::instance()?;
-
-            let apply_data_password_fn: libloading::Symbol<
-                unsafe extern "C" fn(isize, ByteArray, isize) -> i32
-            > = sdk.sdk_library.get(b"session_apply_data_password")?;
+            let apply_data_password_fn: unsafe extern "C" fn(isize, ByteArray, isize) -> i32 =
+                sdk_symbol!(session_apply_data_password);
 
             let result = apply_data_password_fn(
                 session_handle.raw(),
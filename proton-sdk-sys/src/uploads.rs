@@ -52,7 +52,6 @@ pub mod raw {
         data::{AsyncCallback, AsyncCallbackWithProgress, ByteArray, Callback},
         drive::DriveClientHandle,
         uploads::{self, UploaderHandle},
-        ProtonSDKLib,
     };
 
     /// Creates a new uploader
@@ -71,11 +70,8 @@ pub mod raw {
         callback: AsyncCallback,
     ) -> anyhow::Result<i32> {
         unsafe {
-            let sdk = ProtonSDKLib::instance()?;
-
-            let create_uploader_fn: libloading::Symbol<
-                unsafe extern "C" fn(isize, ByteArray, AsyncCallback) -> i32,
-            > = sdk.sdk_library.get(b"uploader_create")?;
+            let create_uploader_fn: unsafe extern "C" fn(isize, ByteArray, AsyncCallback) -> i32 =
+                sdk_symbol!(uploader_create);
 
             let result = create_uploader_fn(client_handle.raw(), request, callback);
 
@@ -99,11 +95,8 @@ pub mod raw {
         callback: AsyncCallbackWithProgress,
     ) -> anyhow::Result<i32> {
         unsafe {
-            let sdk = ProtonSDKLib::instance()?;
-
-            let upload_file_fn: libloading::Symbol<
-                unsafe extern "C" fn(isize, ByteArray, AsyncCallbackWithProgress) -> i32,
-            > = sdk.sdk_library.get(b"uploader_upload_file_or_revision")?;
+            let upload_file_fn: unsafe extern "C" fn(isize, ByteArray, AsyncCallbackWithProgress) -> i32 =
+                sdk_symbol!(uploader_upload_file_or_revision);
 
             let result = upload_file_fn(uploader_handle.raw(), request, callback);
 
@@ -127,11 +120,8 @@ pub mod raw {
         callback: AsyncCallbackWithProgress,
     ) -> anyhow::Result<i32> {
         unsafe {
-            let sdk = ProtonSDKLib::instance()?;
-
-            let upload_revision_fn: libloading::Symbol<
-                unsafe extern "C" fn(isize, ByteArray, AsyncCallbackWithProgress) -> i32,
-            > = sdk.sdk_library.get(b"uploader_upload_revision")?;
+            let upload_revision_fn: unsafe extern "C" fn(isize, ByteArray, AsyncCallbackWithProgress) -> i32 =
+                sdk_symbol!(uploader_upload_revision);
 
             let result = upload_revision_fn(uploader_handle.raw(), request, callback);
 
@@ -145,10 +135,7 @@ pub mod raw {
     /// * `uploader_handle` - Handle to the uploader to free
     pub fn uploader_free(uploader_handle: UploaderHandle) -> anyhow::Result<()> {
         unsafe {
-            let sdk = ProtonSDKLib::instance()?;
-
-            let free_uploader_fn: libloading::Symbol<unsafe extern "C" fn(isize)> =
-                sdk.sdk_library.get(b"uploader_free")?;
+            let free_uploader_fn: unsafe extern "C" fn(isize) = sdk_symbol!(uploader_free);
 
             free_uploader_fn(uploader_handle.raw());
             Ok(())
@@ -0,0 +1,71 @@
+//! Version of the loaded native SDK, see [`ProtonSDKLib::version`](crate::ProtonSDKLib::version)
+
+use std::{fmt, path::Path};
+
+/// Export returning the SDK version as a NUL-terminated string the library owns
+pub const VERSION_SYMBOL: &str = "sdk_get_version";
+
+/// A version of the native SDK, like `1.4.0` or `v1.4.0-beta.2`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SdkVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+    /// The version as the SDK reported it
+    pub raw: String,
+}
+
+impl SdkVersion {
+    /// Reads a version made of up to three numbers separated by dots, missing ones are 0
+    ///
+    /// Anything after the digits of a number, like the `-beta.2` of `1.4.0-beta.2`, is ignored.
+    pub fn parse(raw: &str) -> Option<Self> {
+        let raw = raw.trim();
+        let mut numbers = raw.strip_prefix('v').unwrap_or(raw).splitn(3, '.').map(|part| {
+            let digits = part.find(|c: char| !c.is_ascii_digit()).unwrap_or(part.len());
+            part[..digits].parse::<u32>().ok()
+        });
+        Some(Self {
+            major: numbers.next()??,
+            minor: numbers.next().flatten().unwrap_or(0),
+            patch: numbers.next().flatten().unwrap_or(0),
+            raw: raw.to_string(),
+        })
+    }
+
+    /// Reads the version a library file is named with, like `libproton_drive_sdk.so.1.4.0`
+    pub fn from_file_name(path: &Path, lib_name: &str) -> Option<Self> {
+        let file_name = path.file_name()?.to_str()?;
+        Self::parse(file_name.strip_prefix(lib_name)?.strip_prefix('.')?)
+    }
+}
+
+impl fmt::Display for SdkVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.raw)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions_parse_with_or_without_their_extras() {
+        let version = SdkVersion::parse(" v1.4.0-beta.2\n").unwrap();
+        assert_eq!((version.major, version.minor, version.patch), (1, 4, 0));
+        assert_eq!(version.to_string(), "v1.4.0-beta.2");
+        let version = SdkVersion::parse("2").unwrap();
+        assert_eq!((version.major, version.minor, version.patch), (2, 0, 0));
+        assert!(SdkVersion::parse("unknown").is_none());
+        assert!(SdkVersion::parse("").is_none());
+    }
+
+    #[test]
+    fn versions_are_read_from_versioned_file_names() {
+        let lib_name = "libproton_drive_sdk.so";
+        let version = SdkVersion::from_file_name(Path::new("/opt/proton/libproton_drive_sdk.so.1.4.2"), lib_name);
+        assert_eq!(version.map(|version| version.patch), Some(2));
+        assert!(SdkVersion::from_file_name(Path::new("/opt/proton/libproton_drive_sdk.so"), lib_name).is_none());
+    }
+}
@@ -0,0 +1,53 @@
+//! Loads the SDK from an explicit path, in a test binary of its own since the library is loaded once per process
+
+use proton_sdk_sys::{LoadDiagnostics, LoadError, ProtonSDKLib};
+
+#[test]
+#[cfg(not(feature = "static-link"))]
+fn a_library_that_fails_to_load_is_named_by_every_later_call() {
+    let path = std::env::temp_dir().join("no-such-dir").join("libproton_drive_sdk.so");
+    let error = ProtonSDKLib::load_from(&path).err().unwrap().to_string();
+    assert!(error.contains(&path.display().to_string()), "{}", error);
+
+    // the search for the native library isn't tried after the explicit path failed
+    let later = ProtonSDKLib::instance().err().unwrap();
+    assert_eq!(later.attempts().len(), 1);
+    assert_eq!(later.attempts()[0].0, path);
+    assert_eq!(later.to_string(), error);
+}
+
+#[test]
+#[cfg(feature = "static-link")]
+fn the_linked_library_is_the_only_one() {
+    let path = std::env::temp_dir().join("libproton_drive_sdk.so");
+    let error = ProtonSDKLib::load_from(&path).err().unwrap().to_string();
+    assert!(error.contains("static-link"), "{}", error);
+    // a call keeps the linker from dropping the library no export of which is used
+    let handle = proton_sdk_sys::cancellation::raw::create().unwrap();
+    proton_sdk_sys::cancellation::raw::free(handle).unwrap();
+    ProtonSDKLib::instance().unwrap().verify_symbols().unwrap();
+}
+
+#[test]
+fn load_errors_list_every_path_tried() {
+    let error = LoadError::Failed {
+        library: "libproton_drive_sdk.so".to_string(),
+        diagnostics: LoadDiagnostics {
+            runtime_id: Some("linux-x64"),
+            working_dir: Some("/home/user".into()),
+            attempts: vec![
+                ("libproton_drive_sdk.so".into(), "not found".to_string()),
+                ("./libs/libproton_drive_sdk.so".into(), "wrong ELF class".to_string()),
+            ],
+        },
+    };
+    assert!(error.to_string().ends_with(
+        "(searched: libproton_drive_sdk.so (not found), ./libs/libproton_drive_sdk.so (wrong ELF class))"
+    ));
+    assert_eq!(
+        error.diagnostics().unwrap().to_string(),
+        "searched for the linux-x64 build from /home/user:\n  \
+        libproton_drive_sdk.so         not found\n  \
+        ./libs/libproton_drive_sdk.so  wrong ELF class"
+    );
+}
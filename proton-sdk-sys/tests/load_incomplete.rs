@@ -0,0 +1,14 @@
+//! Loads a library that isn't the SDK, in a test binary of its own since the library is loaded once per process
+
+use proton_sdk_sys::{LoadError, ProtonSDKLib};
+
+#[test]
+#[cfg(all(target_os = "linux", not(feature = "static-link")))]
+fn a_library_lacking_exports_is_refused_by_every_later_call() {
+    let error = ProtonSDKLib::load_from("libc.so.6").err().unwrap().to_string();
+    assert!(error.contains("lacks exports the bindings call"), "{}", error);
+
+    let later = ProtonSDKLib::instance().err().unwrap();
+    assert!(matches!(&later, LoadError::SymbolsMissing { missing, .. } if missing.contains(&"session_begin")));
+    assert_eq!(later.to_string(), error);
+}
@@ -0,0 +1,13 @@
+//! Loads the native SDK the build script copied, from where `cargo test` runs test binaries
+
+use proton_sdk_sys::ProtonSDKLib;
+
+#[test]
+#[cfg_attr(
+    not(feature = "require-native-lib"),
+    ignore = "needs the native SDK, build with PROTON_SDK_LIB_DIR and --features require-native-lib"
+)]
+fn the_copied_sdk_loads_in_test_binaries() {
+    let sdk = ProtonSDKLib::instance().unwrap();
+    assert!(sdk.has_symbol("session_begin"));
+}
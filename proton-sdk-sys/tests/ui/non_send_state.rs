@@ -0,0 +1,14 @@
+// the SDK calls back from its own threads, so a callback's state can't hold a lock guard
+use std::{ffi::c_void, sync::Mutex};
+
+use proton_sdk_sys::data::{ByteArray, Callback, ThreadSafeState};
+
+static RESPONSES: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+
+extern "C" fn on_response(_state: *const c_void, _response: ByteArray) {}
+
+fn main() {
+    let responses = RESPONSES.lock().unwrap();
+    let state = move |_response: &[u8]| responses.len();
+    let _callback = Callback::checked(ThreadSafeState::new(&state), on_response);
+}